@@ -21,6 +21,9 @@ pub const MAP_SYNC: u32 = 0x80000;
 pub const MAP_FIXED_NOREPLACE: u32 = 0x100000;
 pub const MAP_FILE: u32 = 0;
 
+pub const MFD_CLOEXEC: u32 = 0x0001;
+pub const MFD_ALLOW_SEALING: u32 = 0x0002;
+
 pub const PROT_NONE: u32 = 0;
 pub const PROT_READ: u32 = 1;
 pub const PROT_WRITE: u32 = 2;
@@ -55,7 +58,186 @@ pub const AT_HWCAP3: u32 = 29;
 pub const AT_HWCAP4: u32 = 30;
 pub const AT_EXECFN: u32 = 31;
 
+// fcntl.h
+pub const O_ACCMODE: u32 = 0o3;
+pub const O_RDONLY: u32 = 0o0;
+pub const O_WRONLY: u32 = 0o1;
+pub const O_RDWR: u32 = 0o2;
+pub const O_CREAT: u32 = 0o100;
+pub const O_EXCL: u32 = 0o200;
+pub const O_NOCTTY: u32 = 0o400;
+pub const O_TRUNC: u32 = 0o1000;
+pub const O_APPEND: u32 = 0o2000;
+pub const O_NONBLOCK: u32 = 0o4000;
+pub const O_DIRECTORY: u32 = 0o200000;
+pub const O_NOFOLLOW: u32 = 0o400000;
+pub const O_CLOEXEC: u32 = 0o2000000;
+pub const O_PATH: u32 = 0o10000000;
+pub const O_TMPFILE: u32 = 0o20000000 | O_DIRECTORY;
+
+pub const AT_FDCWD: i32 = -100;
+pub const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+pub const AT_REMOVEDIR: u32 = 0x200;
+pub const AT_EMPTY_PATH: u32 = 0x1000;
+
+pub const RENAME_NOREPLACE: u32 = 1;
+pub const RENAME_EXCHANGE: u32 = 2;
+pub const RENAME_WHITEOUT: u32 = 4;
+
+pub const SEEK_SET: u32 = 0;
+pub const SEEK_CUR: u32 = 1;
+pub const SEEK_END: u32 = 2;
+
+// sys/eventfd.h
+pub const EFD_SEMAPHORE: u32 = 1;
+pub const EFD_CLOEXEC: u32 = O_CLOEXEC;
+pub const EFD_NONBLOCK: u32 = O_NONBLOCK;
+
+// sys/timerfd.h
+pub const TFD_TIMER_ABSTIME: u32 = 1;
+pub const TFD_TIMER_CANCEL_ON_SET: u32 = 2;
+pub const TFD_CLOEXEC: u32 = O_CLOEXEC;
+pub const TFD_NONBLOCK: u32 = O_NONBLOCK;
+
+// poll.h
+pub const POLLIN: u16 = 0x001;
+pub const POLLPRI: u16 = 0x002;
+pub const POLLOUT: u16 = 0x004;
+pub const POLLERR: u16 = 0x008;
+pub const POLLHUP: u16 = 0x010;
+pub const POLLNVAL: u16 = 0x020;
+pub const POLLRDHUP: u16 = 0x2000;
+
+// sys/epoll.h
+pub const EPOLL_CLOEXEC: u32 = O_CLOEXEC;
+pub const EPOLL_CTL_ADD: u32 = 1;
+pub const EPOLL_CTL_DEL: u32 = 2;
+pub const EPOLL_CTL_MOD: u32 = 3;
+pub const EPOLLIN: u32 = POLLIN as u32;
+pub const EPOLLOUT: u32 = POLLOUT as u32;
+pub const EPOLLERR: u32 = POLLERR as u32;
+pub const EPOLLHUP: u32 = POLLHUP as u32;
+pub const EPOLLRDHUP: u32 = POLLRDHUP as u32;
+pub const EPOLLEXCLUSIVE: u32 = 1 << 28;
+pub const EPOLLWAKEUP: u32 = 1 << 29;
+pub const EPOLLONESHOT: u32 = 1 << 30;
+pub const EPOLLET: u32 = 1 << 31;
+
+// sys/socket.h
+pub const AF_UNSPEC: u32 = 0;
+pub const AF_UNIX: u32 = 1;
+pub const AF_INET: u32 = 2;
+pub const AF_INET6: u32 = 10;
+
+pub const SOCK_STREAM: u32 = 1;
+pub const SOCK_DGRAM: u32 = 2;
+pub const SOCK_SEQPACKET: u32 = 5;
+pub const SOCK_TYPE_MASK: u32 = 0xf;
+pub const SOCK_NONBLOCK: u32 = O_NONBLOCK;
+pub const SOCK_CLOEXEC: u32 = O_CLOEXEC;
+
+pub const SOL_SOCKET: u32 = 1;
+pub const SO_TYPE: u32 = 3;
+pub const SO_ERROR: u32 = 4;
+pub const SO_SNDBUF: u32 = 7;
+pub const SO_RCVBUF: u32 = 8;
+pub const SO_ACCEPTCONN: u32 = 30;
+pub const SO_PROTOCOL: u32 = 38;
+pub const SO_DOMAIN: u32 = 39;
+
+pub const MSG_PEEK: u32 = 0x2;
+pub const MSG_TRUNC: u32 = 0x20;
+pub const MSG_DONTWAIT: u32 = 0x40;
+pub const MSG_WAITALL: u32 = 0x100;
+pub const MSG_NOSIGNAL: u32 = 0x4000;
+pub const MSG_CMSG_CLOEXEC: u32 = 0x4000_0000;
+
+pub const SHUT_RD: u32 = 0;
+pub const SHUT_WR: u32 = 1;
+pub const SHUT_RDWR: u32 = 2;
+
+pub const SOMAXCONN: u32 = 4096;
+
+// ioctl
+pub const TCGETS: u32 = 0x5401;
+pub const TIOCGWINSZ: u32 = 0x5413;
+
+// sys/stat.h
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFSOCK: u32 = 0o140000;
+
+pub const STATX_BASIC_STATS: u32 = 0x7ff;
+
+// dirent.h
+pub const DT_UNKNOWN: u8 = 0;
+pub const DT_FIFO: u8 = 1;
+pub const DT_CHR: u8 = 2;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+pub const DT_SOCK: u8 = 12;
+
+// limits.h
+pub const PATH_MAX: u32 = 4096;
+pub const IOV_MAX: u32 = 1024;
+pub const SSIZE_MAX: u32 = i32::MAX as u32;
+
+// time.h
+pub const CLOCK_REALTIME: u32 = 0;
+pub const CLOCK_MONOTONIC: u32 = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: u32 = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: u32 = 3;
+pub const CLOCK_MONOTONIC_RAW: u32 = 4;
+pub const CLOCK_REALTIME_COARSE: u32 = 5;
+pub const CLOCK_MONOTONIC_COARSE: u32 = 6;
+pub const CLOCK_BOOTTIME: u32 = 7;
+pub const TIMER_ABSTIME: u32 = 1;
+
+// sys/resource.h
+pub const RUSAGE_SELF: i32 = 0;
+pub const RUSAGE_CHILDREN: i32 = -1;
+pub const RUSAGE_THREAD: i32 = 1;
+
+// sched.h
+pub const SCHED_OTHER: u32 = 0;
+pub const SCHED_FIFO: u32 = 1;
+pub const SCHED_RR: u32 = 2;
+pub const SCHED_BATCH: u32 = 3;
+pub const SCHED_IDLE: u32 = 5;
+pub const SCHED_RESET_ON_FORK: u32 = 0x4000_0000;
+
+// sys/prctl.h
+pub const PR_SET_PDEATHSIG: u32 = 1;
+pub const PR_GET_PDEATHSIG: u32 = 2;
+pub const PR_GET_DUMPABLE: u32 = 3;
+pub const PR_SET_DUMPABLE: u32 = 4;
+pub const PR_SET_NAME: u32 = 15;
+pub const PR_GET_NAME: u32 = 16;
+pub const PR_SET_NO_NEW_PRIVS: u32 = 38;
+pub const PR_GET_NO_NEW_PRIVS: u32 = 39;
+
+// asm/hwprobe.h
+pub const RISCV_HWPROBE_KEY_MVENDORID: i64 = 0;
+pub const RISCV_HWPROBE_KEY_MARCHID: i64 = 1;
+pub const RISCV_HWPROBE_KEY_MIMPID: i64 = 2;
+pub const RISCV_HWPROBE_KEY_BASE_BEHAVIOR: i64 = 3;
+pub const RISCV_HWPROBE_BASE_BEHAVIOR_IMA: u64 = 1 << 0;
+pub const RISCV_HWPROBE_KEY_IMA_EXT_0: i64 = 4;
+pub const RISCV_HWPROBE_IMA_FD: u64 = 1 << 0;
+pub const RISCV_HWPROBE_IMA_C: u64 = 1 << 1;
+pub const RISCV_HWPROBE_KEY_CPUPERF_0: i64 = 5;
+pub const RISCV_HWPROBE_MISALIGNED_FAST: u64 = 3;
+pub const RISCV_HWPROBE_KEY_ZICBOZ_BLOCK_SIZE: i64 = 6;
+pub const RISCV_HWPROBE_WHICH_CPUS: u32 = 1 << 0;
+
 // rlimit
+pub const RLIMIT_STACK: u32 = 3;
+pub const RLIMIT_NOFILE: u32 = 7;
 pub const RLIM_INFINITY: u32 = -1i32 as u32;
 
 // futex
@@ -115,5 +297,247 @@ pub const EMLINK: i32 = 31;
 pub const EPIPE: i32 = 32;
 pub const EDOM: i32 = 33;
 pub const ERANGE: i32 = 34;
-pub const EWOULDBLOCK: i32 = EAGAIN;
+pub const EDEADLK: i32 = 35;
+pub const ENAMETOOLONG: i32 = 36;
+pub const ENOLCK: i32 = 37;
 pub const ENOSYS: i32 = 38;
+pub const ENOTEMPTY: i32 = 39;
+pub const ELOOP: i32 = 40;
+pub const ENOMSG: i32 = 42;
+pub const EIDRM: i32 = 43;
+pub const ECHRNG: i32 = 44;
+pub const EL2NSYNC: i32 = 45;
+pub const EL3HLT: i32 = 46;
+pub const EL3RST: i32 = 47;
+pub const ELNRNG: i32 = 48;
+pub const EUNATCH: i32 = 49;
+pub const ENOCSI: i32 = 50;
+pub const EL2HLT: i32 = 51;
+pub const EBADE: i32 = 52;
+pub const EBADR: i32 = 53;
+pub const EXFULL: i32 = 54;
+pub const ENOANO: i32 = 55;
+pub const EBADRQC: i32 = 56;
+pub const EBADSLT: i32 = 57;
+pub const EBFONT: i32 = 59;
+pub const ENOSTR: i32 = 60;
+pub const ENODATA: i32 = 61;
+pub const ETIME: i32 = 62;
+pub const ENOSR: i32 = 63;
+pub const ENONET: i32 = 64;
+pub const ENOPKG: i32 = 65;
+pub const EREMOTE: i32 = 66;
+pub const ENOLINK: i32 = 67;
+pub const EADV: i32 = 68;
+pub const ESRMNT: i32 = 69;
+pub const ECOMM: i32 = 70;
+pub const EPROTO: i32 = 71;
+pub const EMULTIHOP: i32 = 72;
+pub const EDOTDOT: i32 = 73;
+pub const EBADMSG: i32 = 74;
+pub const EOVERFLOW: i32 = 75;
+pub const ENOTUNIQ: i32 = 76;
+pub const EBADFD: i32 = 77;
+pub const EREMCHG: i32 = 78;
+pub const ELIBACC: i32 = 79;
+pub const ELIBBAD: i32 = 80;
+pub const ELIBSCN: i32 = 81;
+pub const ELIBMAX: i32 = 82;
+pub const ELIBEXEC: i32 = 83;
+pub const EILSEQ: i32 = 84;
+pub const ERESTART: i32 = 85;
+pub const ESTRPIPE: i32 = 86;
+pub const EUSERS: i32 = 87;
+pub const ENOTSOCK: i32 = 88;
+pub const EDESTADDRREQ: i32 = 89;
+pub const EMSGSIZE: i32 = 90;
+pub const EPROTOTYPE: i32 = 91;
+pub const ENOPROTOOPT: i32 = 92;
+pub const EPROTONOSUPPORT: i32 = 93;
+pub const ESOCKTNOSUPPORT: i32 = 94;
+pub const EOPNOTSUPP: i32 = 95;
+pub const EPFNOSUPPORT: i32 = 96;
+pub const EAFNOSUPPORT: i32 = 97;
+pub const EADDRINUSE: i32 = 98;
+pub const EADDRNOTAVAIL: i32 = 99;
+pub const ENETDOWN: i32 = 100;
+pub const ENETUNREACH: i32 = 101;
+pub const ENETRESET: i32 = 102;
+pub const ECONNABORTED: i32 = 103;
+pub const ECONNRESET: i32 = 104;
+pub const ENOBUFS: i32 = 105;
+pub const EISCONN: i32 = 106;
+pub const ENOTCONN: i32 = 107;
+pub const ESHUTDOWN: i32 = 108;
+pub const ETOOMANYREFS: i32 = 109;
+pub const ETIMEDOUT: i32 = 110;
+pub const ECONNREFUSED: i32 = 111;
+pub const EHOSTDOWN: i32 = 112;
+pub const EHOSTUNREACH: i32 = 113;
+pub const EALREADY: i32 = 114;
+pub const EINPROGRESS: i32 = 115;
+pub const ESTALE: i32 = 116;
+pub const EUCLEAN: i32 = 117;
+pub const ENOTNAM: i32 = 118;
+pub const ENAVAIL: i32 = 119;
+pub const EISNAM: i32 = 120;
+pub const EREMOTEIO: i32 = 121;
+pub const EDQUOT: i32 = 122;
+pub const ENOMEDIUM: i32 = 123;
+pub const EMEDIUMTYPE: i32 = 124;
+pub const ECANCELED: i32 = 125;
+pub const ENOKEY: i32 = 126;
+pub const EKEYEXPIRED: i32 = 127;
+pub const EKEYREVOKED: i32 = 128;
+pub const EKEYREJECTED: i32 = 129;
+pub const EOWNERDEAD: i32 = 130;
+pub const ENOTRECOVERABLE: i32 = 131;
+pub const ERFKILL: i32 = 132;
+pub const EHWPOISON: i32 = 133;
+pub const EWOULDBLOCK: i32 = EAGAIN;
+pub const EDEADLOCK: i32 = EDEADLK;
+pub const ENOTSUP: i32 = EOPNOTSUPP;
+
+/// Every errno value with its name, in numeric order. Aliases like
+/// `EWOULDBLOCK` are left out.
+pub const ERRNO_TABLE: [(i32, &str); 131] = [
+    (EPERM, "EPERM"),
+    (ENOENT, "ENOENT"),
+    (ESRCH, "ESRCH"),
+    (EINTR, "EINTR"),
+    (EIO, "EIO"),
+    (ENXIO, "ENXIO"),
+    (E2BIG, "E2BIG"),
+    (ENOEXEC, "ENOEXEC"),
+    (EBADF, "EBADF"),
+    (ECHILD, "ECHILD"),
+    (EAGAIN, "EAGAIN"),
+    (ENOMEM, "ENOMEM"),
+    (EACCES, "EACCES"),
+    (EFAULT, "EFAULT"),
+    (ENOTBLK, "ENOTBLK"),
+    (EBUSY, "EBUSY"),
+    (EEXIST, "EEXIST"),
+    (EXDEV, "EXDEV"),
+    (ENODEV, "ENODEV"),
+    (ENOTDIR, "ENOTDIR"),
+    (EISDIR, "EISDIR"),
+    (EINVAL, "EINVAL"),
+    (ENFILE, "ENFILE"),
+    (EMFILE, "EMFILE"),
+    (ENOTTY, "ENOTTY"),
+    (ETXTBSY, "ETXTBSY"),
+    (EFBIG, "EFBIG"),
+    (ENOSPC, "ENOSPC"),
+    (ESPIPE, "ESPIPE"),
+    (EROFS, "EROFS"),
+    (EMLINK, "EMLINK"),
+    (EPIPE, "EPIPE"),
+    (EDOM, "EDOM"),
+    (ERANGE, "ERANGE"),
+    (EDEADLK, "EDEADLK"),
+    (ENAMETOOLONG, "ENAMETOOLONG"),
+    (ENOLCK, "ENOLCK"),
+    (ENOSYS, "ENOSYS"),
+    (ENOTEMPTY, "ENOTEMPTY"),
+    (ELOOP, "ELOOP"),
+    (ENOMSG, "ENOMSG"),
+    (EIDRM, "EIDRM"),
+    (ECHRNG, "ECHRNG"),
+    (EL2NSYNC, "EL2NSYNC"),
+    (EL3HLT, "EL3HLT"),
+    (EL3RST, "EL3RST"),
+    (ELNRNG, "ELNRNG"),
+    (EUNATCH, "EUNATCH"),
+    (ENOCSI, "ENOCSI"),
+    (EL2HLT, "EL2HLT"),
+    (EBADE, "EBADE"),
+    (EBADR, "EBADR"),
+    (EXFULL, "EXFULL"),
+    (ENOANO, "ENOANO"),
+    (EBADRQC, "EBADRQC"),
+    (EBADSLT, "EBADSLT"),
+    (EBFONT, "EBFONT"),
+    (ENOSTR, "ENOSTR"),
+    (ENODATA, "ENODATA"),
+    (ETIME, "ETIME"),
+    (ENOSR, "ENOSR"),
+    (ENONET, "ENONET"),
+    (ENOPKG, "ENOPKG"),
+    (EREMOTE, "EREMOTE"),
+    (ENOLINK, "ENOLINK"),
+    (EADV, "EADV"),
+    (ESRMNT, "ESRMNT"),
+    (ECOMM, "ECOMM"),
+    (EPROTO, "EPROTO"),
+    (EMULTIHOP, "EMULTIHOP"),
+    (EDOTDOT, "EDOTDOT"),
+    (EBADMSG, "EBADMSG"),
+    (EOVERFLOW, "EOVERFLOW"),
+    (ENOTUNIQ, "ENOTUNIQ"),
+    (EBADFD, "EBADFD"),
+    (EREMCHG, "EREMCHG"),
+    (ELIBACC, "ELIBACC"),
+    (ELIBBAD, "ELIBBAD"),
+    (ELIBSCN, "ELIBSCN"),
+    (ELIBMAX, "ELIBMAX"),
+    (ELIBEXEC, "ELIBEXEC"),
+    (EILSEQ, "EILSEQ"),
+    (ERESTART, "ERESTART"),
+    (ESTRPIPE, "ESTRPIPE"),
+    (EUSERS, "EUSERS"),
+    (ENOTSOCK, "ENOTSOCK"),
+    (EDESTADDRREQ, "EDESTADDRREQ"),
+    (EMSGSIZE, "EMSGSIZE"),
+    (EPROTOTYPE, "EPROTOTYPE"),
+    (ENOPROTOOPT, "ENOPROTOOPT"),
+    (EPROTONOSUPPORT, "EPROTONOSUPPORT"),
+    (ESOCKTNOSUPPORT, "ESOCKTNOSUPPORT"),
+    (EOPNOTSUPP, "EOPNOTSUPP"),
+    (EPFNOSUPPORT, "EPFNOSUPPORT"),
+    (EAFNOSUPPORT, "EAFNOSUPPORT"),
+    (EADDRINUSE, "EADDRINUSE"),
+    (EADDRNOTAVAIL, "EADDRNOTAVAIL"),
+    (ENETDOWN, "ENETDOWN"),
+    (ENETUNREACH, "ENETUNREACH"),
+    (ENETRESET, "ENETRESET"),
+    (ECONNABORTED, "ECONNABORTED"),
+    (ECONNRESET, "ECONNRESET"),
+    (ENOBUFS, "ENOBUFS"),
+    (EISCONN, "EISCONN"),
+    (ENOTCONN, "ENOTCONN"),
+    (ESHUTDOWN, "ESHUTDOWN"),
+    (ETOOMANYREFS, "ETOOMANYREFS"),
+    (ETIMEDOUT, "ETIMEDOUT"),
+    (ECONNREFUSED, "ECONNREFUSED"),
+    (EHOSTDOWN, "EHOSTDOWN"),
+    (EHOSTUNREACH, "EHOSTUNREACH"),
+    (EALREADY, "EALREADY"),
+    (EINPROGRESS, "EINPROGRESS"),
+    (ESTALE, "ESTALE"),
+    (EUCLEAN, "EUCLEAN"),
+    (ENOTNAM, "ENOTNAM"),
+    (ENAVAIL, "ENAVAIL"),
+    (EISNAM, "EISNAM"),
+    (EREMOTEIO, "EREMOTEIO"),
+    (EDQUOT, "EDQUOT"),
+    (ENOMEDIUM, "ENOMEDIUM"),
+    (EMEDIUMTYPE, "EMEDIUMTYPE"),
+    (ECANCELED, "ECANCELED"),
+    (ENOKEY, "ENOKEY"),
+    (EKEYEXPIRED, "EKEYEXPIRED"),
+    (EKEYREVOKED, "EKEYREVOKED"),
+    (EKEYREJECTED, "EKEYREJECTED"),
+    (EOWNERDEAD, "EOWNERDEAD"),
+    (ENOTRECOVERABLE, "ENOTRECOVERABLE"),
+    (ERFKILL, "ERFKILL"),
+    (EHWPOISON, "EHWPOISON"),
+];
+
+/// The name of `errno`, e.g. `"ENOENT"`.
+pub fn errno_name(errno: i32) -> Option<&'static str> {
+    ERRNO_TABLE
+        .iter()
+        .find(|&&(e, _)| e == errno)
+        .map(|&(_, name)| name)
+}
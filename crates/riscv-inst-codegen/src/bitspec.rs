@@ -150,6 +150,12 @@ pub fn serialize_bitspecs(src: TokenStream, out_ty: TokenStream, bitspecs: &str)
             }
             quote! { unsafe { Reg::from_u5((#accessor) as u8 + 8) } }
         }
+        "CFReg" => {
+            if hsb.unwrap_or_default() > 2 {
+                panic!("CFReg can only be accessed with 3 bits");
+            }
+            quote! { unsafe { FReg::from_u5((#accessor) as u8 + 8) } }
+        }
         _ => accessor,
     }
 }
@@ -57,6 +57,15 @@ impl RvExt {
         }
     }
 
+    /// Whether an ISA with this extension has `other`'s instructions: D
+    /// builds on F, and Q on D.
+    pub fn implies(&self, other: RvExt) -> bool {
+        match (self, other) {
+            (RvExt::F(this), RvExt::F(other)) => *this as u8 >= other as u8,
+            _ => *self == other,
+        }
+    }
+
     pub const fn from_char(c: char) -> Option<Self> {
         match c {
             'm' => Some(RvExt::M),
@@ -93,10 +102,15 @@ impl Isa {
         if ext_base != self.base {
             return false;
         }
+        // Z extensions aren't part of the ISA's name, so are decoded in any
+        // ISA with their base, and gated at run time
+        if rest.starts_with('z') {
+            return true;
+        }
 
         rest.chars()
             .filter_map(RvExt::from_char)
-            .all(|ext| self.exts.contains(&ext))
+            .all(|ext| self.exts.iter().any(|has| has.implies(ext)))
     }
 
     pub fn contains_op(&self, op: Opcode) -> bool {
@@ -33,3 +33,38 @@ pub fn generate_lookup_table(compressed: &[&mut Opcode]) -> TokenStream {
 
     quote! { [ #(#table),* ] }
 }
+
+/// Discriminant of a [`generate_major_table`] slot that several opcodes share.
+pub const SHARED_SLOT: u8 = u8::MAX;
+
+/// A 256 entry table of `(discriminant, mask, value)` indexed by the major
+/// opcode (bits 6..2) and funct3 (bits 14..12) of a 32-bit instruction.
+///
+/// A slot with a single opcode holds its encoding, which the instruction must
+/// match in full. An empty slot never matches. Slots shared by several opcodes
+/// have the discriminant [`SHARED_SLOT`], and are left to the match tree.
+pub fn generate_major_table(uncompressed: &[&mut Opcode]) -> TokenStream {
+    assert!(
+        uncompressed
+            .iter()
+            .all(|o| o.discriminant.unwrap() != SHARED_SLOT),
+        "Too many opcodes for the major opcode table"
+    );
+
+    let slots = (0..256u32).map(|slot| {
+        let slot_mask = 0b111 << 12 | 0b11111 << 2;
+        let slot_value = (slot & 0b111) << 12 | (slot >> 3) << 2;
+        let mut candidates = uncompressed
+            .iter()
+            .map(|o| (o.discriminant.unwrap(), o.mask_match()))
+            .filter(|(_, (mask, value))| (value ^ slot_value) & mask & slot_mask == 0);
+
+        match (candidates.next(), candidates.next()) {
+            (None, _) => quote! { (0, 0, 1) },
+            (Some((d, (mask, value))), None) => quote! { (#d, #mask, #value) },
+            (Some(_), Some(_)) => quote! { (#SHARED_SLOT, 0, 0) },
+        }
+    });
+
+    quote! { [ #(#slots),* ] }
+}
@@ -22,6 +22,10 @@ pub fn mask(width: usize, shift: usize) -> LitInt {
 
 const RISCV_OPERANDS: &str = include_str!("../riscv-meta/operands");
 const RISCV_OPCODES: &str = include_str!("../riscv-meta/opcodes");
+const CUSTOM_OPCODES: &str = include_str!("../riscv-meta/custom");
+
+/// Major opcodes (bits 6..2) reserved for custom extensions: custom-0 to custom-3.
+const CUSTOM_MAJOR_OPCODES: [u32; 4] = [0x02, 0x0A, 0x16, 0x1E];
 
 const PKG_DIR: &str = env!("CARGO_MANIFEST_DIR");
 
@@ -49,9 +53,9 @@ const ISAS_TO_GEN: &[Isa] = &[
     // isa!(RV32, M),
     // isa!(RV32, M, C),
     // isa!(RV32, M, A, C),
-    isa!(RV32, M, A, S, C),
+    // isa!(RV32, M, A, S, C),
     // isa!(RV32, M, A, S, F, C),
-    // isa!(RV32, M, A, S, D, C),
+    isa!(RV32, M, A, S, D, C),
     // isa!(RV32, M, A, S, Q, C),
     // isa!(RV64,),
     // isa!(RV64, M),
@@ -99,7 +103,7 @@ fn main() {
 
         let gen = syn::parse_quote! {
             #![allow(unused_parens, clippy::unnecessary_cast, clippy::write_literal, clippy::identity_op)]
-            pub(crate) use crate::{Reg, FReg};
+            pub(crate) use crate::{Extension, Reg, FReg};
 
             #(#mods)*
         };
@@ -136,10 +140,11 @@ fn preprocess(spec: &str) -> Vec<Vec<String>> {
 
 fn codegen() -> Vec<(String, String, Option<String>)> {
     let operands = preprocess(RISCV_OPERANDS);
-    let opcodes = preprocess(RISCV_OPCODES)
+    let mut opcodes = preprocess(RISCV_OPCODES)
         .into_iter()
         .map(Opcode::parse)
         .collect::<Vec<_>>();
+    opcodes.extend(custom_opcodes(&opcodes));
 
     let accessors: HashMap<String, (Ident, TokenStream)> = operands
         .iter()
@@ -161,7 +166,7 @@ fn codegen() -> Vec<(String, String, Option<String>)> {
 
             let gen = syn::parse_quote! {
                 #[allow(unused_imports)]
-                use super::{Reg, FReg};
+                use super::{Extension, Reg, FReg};
 
                 #isa_enum
             };
@@ -175,11 +180,61 @@ fn codegen() -> Vec<(String, String, Option<String>)> {
         .collect()
 }
 
+/// Parse the custom instruction spec, checking each instruction fits in the
+/// custom opcode space and doesn't clash with anything already defined.
+fn custom_opcodes(standard: &[Opcode]) -> Vec<Opcode> {
+    let mut custom: Vec<Opcode> = vec![];
+    for opcode in preprocess(CUSTOM_OPCODES).into_iter().map(Opcode::parse) {
+        assert!(
+            opcode.is_custom(),
+            "Custom instruction {} must only be in rv32x or rv64x, not {:?}",
+            opcode.name,
+            opcode.isas
+        );
+        let major = opcode
+            .encodings
+            .iter()
+            .find(|enc| enc.eq_range(2, 6))
+            .map(|enc| enc.value);
+        let quadrant = opcode
+            .encodings
+            .iter()
+            .find(|enc| enc.eq_range(0, 1))
+            .map(|enc| enc.value);
+        assert!(
+            quadrant == Some(3) && major.is_some_and(|m| CUSTOM_MAJOR_OPCODES.contains(&m)),
+            "Custom instruction {} must have 1..0=3 and 6..2 set to a custom major opcode {:x?}",
+            opcode.name,
+            CUSTOM_MAJOR_OPCODES
+        );
+
+        let (mask, value) = opcode.mask_match();
+        for other in standard.iter().chain(&custom) {
+            assert_ne!(
+                opcode.name, other.name,
+                "Custom instruction {} is already defined",
+                opcode.name
+            );
+            let (other_mask, other_value) = other.mask_match();
+            assert!(
+                other.is_c() || (value ^ other_value) & mask & other_mask != 0,
+                "Custom instruction {} overlaps {}",
+                opcode.name,
+                other.name
+            );
+        }
+        custom.push(opcode);
+    }
+
+    custom
+}
+
 fn generate_operand_accessor_fn(operand: &[String]) -> TokenStream {
     let fn_ident = Ident::new(&operand[3], proc_macro2::Span::call_site());
     let operand_type = match operand[2].as_str() {
         "arg" => quote!(u32),    // Argument
         "creg" => quote!(Reg),   // Compressed Register (= Reg+8)
+        "cfreg" => quote!(FReg), // Compressed FP Register (= FReg+8)
         "ireg" => quote!(Reg),   // Integer Register
         "freg" => quote!(FReg),  // FP Register
         "offset" => quote!(i32), // Signed Offset
@@ -190,6 +245,8 @@ fn generate_operand_accessor_fn(operand: &[String]) -> TokenStream {
     // Signal to bitspec to +8 this register
     let spec_ty = if operand[2] == "creg" {
         quote!(CReg)
+    } else if operand[2] == "cfreg" {
+        quote!(CFReg)
     } else {
         operand_type.clone()
     };
@@ -243,6 +300,20 @@ fn generate_isa_enum(
 
     let variants = opcodes.iter().map(Opcode::as_variant).collect::<Vec<_>>();
     let names = opcodes.iter().map(Opcode::name_ident).collect::<Vec<_>>();
+    let exts = opcodes
+        .iter()
+        .map(Opcode::extension_ident)
+        .collect::<Vec<_>>();
+    let mnemonics = opcodes.iter().map(|o| o.name.as_str()).collect::<Vec<_>>();
+    let ids = opcodes
+        .iter()
+        .enumerate()
+        .map(|(i, o)| {
+            assert_eq!(o.discriminant, Some(i as u8), "discriminants must be dense");
+            LitInt::new(&format!("{i}"), proc_macro2::Span::call_site())
+        })
+        .collect::<Vec<_>>();
+    let count = opcodes.len();
 
     let opcode_structs = opcodes
         .iter()
@@ -277,6 +348,34 @@ fn generate_isa_enum(
 
         impl #isa_ident {
             #decode_fn
+
+            /// Number of opcodes in this ISA; every [`Self::id`] is below it.
+            pub const COUNT: usize = #count;
+
+            /// Mnemonic of each opcode, indexed by [`Self::id`].
+            pub const MNEMONICS: [&'static str; #count] = [#(#mnemonics),*];
+
+            /// Extension of each opcode, indexed by [`Self::id`].
+            pub const EXTENSIONS: [Extension; #count] = [#(Extension::#exts),*];
+
+            /// A dense index identifying the opcode, regardless of operands.
+            #[inline(always)]
+            pub const fn id(&self) -> u8 {
+                match self {
+                    #( #isa_ident::#names(_) => #ids ),*
+                }
+            }
+
+            #[inline(always)]
+            pub const fn mnemonic(&self) -> &'static str {
+                Self::MNEMONICS[self.id() as usize]
+            }
+
+            /// The extension this instruction belongs to.
+            #[inline(always)]
+            pub const fn extension(&self) -> Extension {
+                Self::EXTENSIONS[self.id() as usize]
+            }
         }
 
         #(#opcode_structs)*
@@ -6,7 +6,7 @@ use syn::{Ident, LitInt};
 
 use crate::{
     isa::Isa,
-    lookup_tables::generate_lookup_table,
+    lookup_tables::{generate_lookup_table, generate_major_table, SHARED_SLOT},
     opcode::{BitEnc, Opcode},
 };
 
@@ -41,6 +41,7 @@ pub fn generate_opcode_parser(
     let c_decode = if table.is_some() {
         let include_path = format!("./{isa}_lookup_table.rs");
         quote! {
+            #[allow(clippy::large_const_arrays)]
             const C_LOOKUP: [u8; 0xFFFF] = include!(#include_path);
 
             #[allow(clippy::missing_transmute_annotations)]
@@ -52,13 +53,16 @@ pub fn generate_opcode_parser(
 
     let tree = build_decision_tree(&uncompressed);
     let return_ty = isa.ident();
+    // Bits 1..0 are known to be 0b11 once past the compressed check
     let parser = build_match(
         Ident::new("inst", proc_macro2::Span::call_site()),
         isa,
         &tree,
         &uncompressed,
         None,
+        &[(0, 1)],
     );
+    let major_table = generate_major_table(&uncompressed);
 
     let decode_fn = quote! {
         #[inline(always)]
@@ -66,9 +70,24 @@ pub fn generate_opcode_parser(
             if inst & 0b11 != 0b11 {
                 #c_decode
             } else {
-                #parser
+                // Most opcode/funct3 pairs are a single instruction, so look
+                // those up, and only walk the match tree for the rest
+                const MAJOR_LOOKUP: [(u8, u32, u32); 256] = #major_table;
+
+                let (op, mask, value) = MAJOR_LOOKUP[(((inst >> 2) & 0b11111) << 3 | (inst >> 12) & 0b111) as usize];
+                if op != #SHARED_SLOT {
+                    (inst & mask == value).then(|| unsafe { core::mem::transmute::<u8, #return_ty>(op) })
+                } else {
+                    Self::parse_shared(inst)
+                }
             }
         }
+
+        /// Decode a 32-bit instruction whose opcode and funct3 don't identify it.
+        #[inline(never)]
+        fn parse_shared(inst: u32) -> Option<#return_ty> {
+            #parser
+        }
     };
 
     (table, decode_fn)
@@ -182,58 +201,43 @@ fn build_match(
     cur: &DecisionNode,
     opcodes: &[&mut Opcode],
     pattern: Option<LitInt>,
+    checked: &[(usize, usize)],
 ) -> TokenStream {
     match cur {
         DecisionNode::Leaf(vec) => {
             // There should have been at least one branch before this leaf
             let pattern = pattern.unwrap();
-            if vec.len() == 1 {
-                // Only one instruction, just return that match arm
-                let opcode = &opcodes[vec[0]];
-                let ident = opcode.full_instance(&isa);
-                quote! {
-                   #pattern => Some(#ident)
-                }
-            } else {
-                // The instructions are equal, but some have more restrictive encoding
-                // e.g. c.addi16sp = c.lui with  11..7=2
-                let mut opcodes = vec
+
+            // Several instructions can share a leaf when one is a more
+            // restrictive encoding of another, e.g. c.addi16sp = c.lui with
+            // 11..7=2, so try the most restrictive first
+            let mut opcodes = vec.iter().map(|&idx| &opcodes[idx]).collect::<Vec<_>>();
+            opcodes.sort_by_key(|op| std::cmp::Reverse(op.encodings.len()));
+
+            let arms = opcodes.into_iter().map(|op| {
+                let ident = op.full_instance(&isa);
+                // Fields the path to this leaf hasn't matched on still have to be checked
+                let unchecked = op
+                    .encodings
                     .iter()
-                    .map(|&idx| (&opcodes[idx], opcodes[idx].encodings.clone()))
+                    .filter(|enc| !checked.iter().any(|&(start, end)| enc.eq_range(start, end)))
+                    .map(|enc| {
+                        let extract = enc.codegen_extract(src.clone());
+                        let value =
+                            LitInt::new(&format!("{}", enc.value), proc_macro2::Span::call_site());
+                        quote! { #extract == #value }
+                    })
                     .collect::<Vec<_>>();
-                opcodes.sort_by_key(|(_, encodings)| encodings.len());
 
-                // The first opcode has all the common encodings
-                let common = &opcodes[0].1;
-
-                let mut arms = vec![];
-                // Iterate backwards, from most to least restrictive
-                for (op, encs) in opcodes.iter().rev() {
-                    let unique = encs
-                        .iter()
-                        .filter(|enc| !common.contains(enc))
-                        .collect::<Vec<_>>();
-
-                    let ident = op.full_instance(&isa);
-
-                    if unique.is_empty() {
-                        arms.push(quote! { #pattern => Some(#ident) });
-                    } else {
-                        let if_expr = unique.into_iter().map(|enc| {
-                            let extract = enc.codegen_extract(src.clone());
-                            let value = LitInt::new(
-                                &format!("{}", enc.value),
-                                proc_macro2::Span::call_site(),
-                            );
-                            quote! { #extract == #value }
-                        });
-                        arms.push(quote! { #pattern if #(#if_expr)&&* => Some(#ident) });
-                    }
+                if unchecked.is_empty() {
+                    quote! { #pattern => Some(#ident) }
+                } else {
+                    quote! { #pattern if #(#unchecked)&&* => Some(#ident) }
                 }
+            });
 
-                quote! {
-                    #(#arms),*
-                }
+            quote! {
+                #(#arms),*
             }
         }
         DecisionNode::Branch {
@@ -246,6 +250,7 @@ fn build_match(
                 value: 0,
             };
             let match_expr = enc.codegen_extract(src.clone());
+            let checked = [checked, &[(*start, *end)]].concat();
 
             // TODO: The manual "unreachable!()" + None branches seem like they should
             // outperform, but it seems worse!
@@ -256,7 +261,14 @@ fn build_match(
                     let pattern = LitInt::new(&format!("{:#0width$b}", i), Span::call_site());
 
                     if let Some((_, child)) = children.iter().find(|(pat, _)| *pat == i) {
-                        Some(build_match(src.clone(), isa, child, opcodes, Some(pattern)))
+                        Some(build_match(
+                            src.clone(),
+                            isa,
+                            child,
+                            opcodes,
+                            Some(pattern),
+                            &checked,
+                        ))
                     } else if width > EXHAUSTIVE_MATCH_THRESHOLD {
                         // Don't expand for wide bit ranges, just return
                         None
@@ -87,13 +87,13 @@ impl Opcode {
 
         let operands = args[1..]
             .iter()
-            .filter(|arg| arg.chars().next().map_or(false, char::is_alphabetic))
+            .filter(|arg| arg.chars().next().is_some_and(char::is_alphabetic))
             .cloned()
             .collect();
 
         let encodings = args[1..]
             .iter()
-            .filter(|arg| arg.chars().next().map_or(false, char::is_numeric))
+            .filter(|arg| arg.chars().next().is_some_and(char::is_numeric))
             .filter_map(BitEnc::parse)
             .collect();
 
@@ -177,6 +177,44 @@ impl Opcode {
         }
     }
 
+    /// The `riscv_inst::Extension` variant this opcode belongs to.
+    pub fn extension_ident(&self) -> Ident {
+        let ext = if self.name.starts_with("csrr") {
+            "Zicsr"
+        } else {
+            let tag = self.isas[0]
+                .trim_start_matches("rv32")
+                .trim_start_matches("rv64");
+            if let Some(z) = tag.strip_prefix('z') {
+                return Ident::new(&format!("Z{z}"), Span::call_site());
+            } else if tag.contains('c') {
+                "C"
+            } else if tag.starts_with('x') {
+                "Xcustom"
+            } else {
+                match tag.chars().next() {
+                    Some('i') => "I",
+                    Some('m') => "M",
+                    Some('a') => "A",
+                    Some('f') => "F",
+                    Some('d') => "D",
+                    Some('q') => "Q",
+                    Some('s') => "S",
+                    _ => panic!("Unknown extension for {}: {}", self.name, self.isas[0]),
+                }
+            }
+        };
+
+        Ident::new(ext, Span::call_site())
+    }
+
+    /// Whether this is a user-defined instruction from the custom spec.
+    pub fn is_custom(&self) -> bool {
+        self.isas
+            .iter()
+            .all(|isa| matches!(isa.as_str(), "rv32x" | "rv64x"))
+    }
+
     pub fn is_c(&self) -> bool {
         self.isas.iter().any(|isa| isa.contains("c"))
     }
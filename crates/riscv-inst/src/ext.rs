@@ -0,0 +1,182 @@
+use std::{fmt::Display, str::FromStr};
+
+/// The ISA extension an instruction belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Extension {
+    I = 0,
+    M = 1,
+    A = 2,
+    F = 3,
+    D = 4,
+    Q = 5,
+    C = 6,
+    /// Privileged instructions (xRET, WFI, SFENCE)
+    S = 7,
+    /// Control and status register instructions
+    Zicsr = 8,
+    /// Instructions declared in the custom opcode spec, executed by a host
+    /// handler
+    Xcustom = 9,
+    /// Scalar AES encryption
+    Zkne = 10,
+    /// Scalar AES decryption
+    Zknd = 11,
+    /// SHA-256 and SHA-512 sigma and sum functions
+    Zknh = 12,
+    /// Waiting on a reservation set, for spin loops
+    Zawrs = 13,
+}
+
+impl Extension {
+    pub const ALL: [Extension; 14] = [
+        Extension::I,
+        Extension::M,
+        Extension::A,
+        Extension::F,
+        Extension::D,
+        Extension::Q,
+        Extension::C,
+        Extension::S,
+        Extension::Zicsr,
+        Extension::Xcustom,
+        Extension::Zkne,
+        Extension::Zknd,
+        Extension::Zknh,
+        Extension::Zawrs,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Extension::I => "i",
+            Extension::M => "m",
+            Extension::A => "a",
+            Extension::F => "f",
+            Extension::D => "d",
+            Extension::Q => "q",
+            Extension::C => "c",
+            Extension::S => "s",
+            Extension::Zicsr => "zicsr",
+            Extension::Xcustom => "xcustom",
+            Extension::Zkne => "zkne",
+            Extension::Zknd => "zknd",
+            Extension::Zknh => "zknh",
+            Extension::Zawrs => "zawrs",
+        }
+    }
+}
+
+/// A set of [`Extension`]s.
+///
+/// Parses from ISA strings such as `rv32imac` or `rv32im_zicsr_xcustom`. `g`
+/// expands to `imafd_zicsr`; otherwise `zicsr`, `xcustom`, `zawrs` and the
+/// scalar crypto extensions `zkne`, `zknd` and `zknh` must be named
+/// explicitly. `zihintpause` is accepted, since hints are always legal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extensions(u16);
+
+impl Extensions {
+    pub const NONE: Self = Self(0);
+    pub const RV32I: Self = Self::NONE.with(Extension::I);
+    pub const RV32IM: Self = Self::RV32I.with(Extension::M);
+    pub const RV32IMAC: Self = Self::RV32IM.with(Extension::A).with(Extension::C);
+    /// Everything the rv32 interpreter implements, but for the scalar crypto
+    /// and Zawrs extensions, which guests are seldom built for.
+    pub const RV32IMASC: Self = Self::RV32IMAC.with(Extension::S).with(Extension::Zicsr);
+
+    #[inline(always)]
+    pub const fn contains(self, ext: Extension) -> bool {
+        self.0 & (1 << ext as u8) != 0
+    }
+
+    pub const fn with(self, ext: Extension) -> Self {
+        Self(self.0 | (1 << ext as u8))
+    }
+
+    pub const fn without(self, ext: Extension) -> Self {
+        Self(self.0 & !(1 << ext as u8))
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Extension> {
+        Extension::ALL
+            .into_iter()
+            .filter(move |&ext| self.contains(ext))
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self::RV32IMASC
+    }
+}
+
+impl Display for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rv32")?;
+        for ext in self.iter() {
+            match ext {
+                Extension::Zicsr
+                | Extension::Xcustom
+                | Extension::Zkne
+                | Extension::Zknd
+                | Extension::Zknh
+                | Extension::Zawrs => write!(f, "_{}", ext.name())?,
+                _ => write!(f, "{}", ext.name())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Extensions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let rest = lower
+            .strip_prefix("rv32")
+            .ok_or_else(|| format!("ISA string {s:?} must start with rv32"))?;
+
+        let mut parts = rest.split('_');
+        let mut exts = Self::NONE;
+        for c in parts.next().unwrap_or_default().chars() {
+            exts = match c {
+                'i' => exts.with(Extension::I),
+                'm' => exts.with(Extension::M),
+                'a' => exts.with(Extension::A),
+                'f' => exts.with(Extension::F),
+                'd' => exts.with(Extension::D),
+                'q' => exts.with(Extension::Q),
+                'c' => exts.with(Extension::C),
+                's' => exts.with(Extension::S),
+                'g' => exts
+                    .with(Extension::I)
+                    .with(Extension::M)
+                    .with(Extension::A)
+                    .with(Extension::F)
+                    .with(Extension::D)
+                    .with(Extension::Zicsr),
+                _ => return Err(format!("unknown extension {c:?} in {s:?}")),
+            };
+        }
+        for z in parts {
+            exts = match z {
+                "zicsr" => exts.with(Extension::Zicsr),
+                "xcustom" => exts.with(Extension::Xcustom),
+                "zkne" => exts.with(Extension::Zkne),
+                "zknd" => exts.with(Extension::Zknd),
+                "zknh" => exts.with(Extension::Zknh),
+                "zawrs" => exts.with(Extension::Zawrs),
+                // Part of I in the interpreter
+                "zifencei" | "zihintpause" => exts,
+                _ => return Err(format!("unknown extension {z:?} in {s:?}")),
+            };
+        }
+
+        if !exts.contains(Extension::I) {
+            return Err(format!("ISA string {s:?} is missing the base I extension"));
+        }
+
+        Ok(exts)
+    }
+}
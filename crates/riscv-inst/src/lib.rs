@@ -1,6 +1,8 @@
 pub mod codegen;
+mod ext;
 mod reg;
 
+pub use ext::*;
 pub use reg::*;
 
 // #[cfg(test)]
@@ -0,0 +1,741 @@
+//! Cross-checks the generated decoder against a slow reference decoder written
+//! directly from the RISC-V specification, independently of riscv-meta.
+
+use std::collections::BTreeMap;
+
+use proptest::prelude::*;
+use riscv_inst::{codegen::rv32imasfdc::Rv32IMASFDC, FReg, Reg};
+
+/// Operand values by accessor name.
+type Fields = BTreeMap<&'static str, i64>;
+type Decoded = (&'static str, Fields);
+
+trait Field {
+    fn value(self) -> i64;
+}
+
+impl Field for Reg {
+    fn value(self) -> i64 {
+        self as i64
+    }
+}
+
+impl Field for FReg {
+    fn value(self) -> i64 {
+        self as i64
+    }
+}
+
+impl Field for i32 {
+    fn value(self) -> i64 {
+        self as i64
+    }
+}
+
+impl Field for u32 {
+    fn value(self) -> i64 {
+        self as i64
+    }
+}
+
+macro_rules! fields {
+    ($op:expr, $inst:expr, { $($variant:ident => [$($field:ident),*],)* }) => {
+        match $op {
+            $(Rv32IMASFDC::$variant(op) => {
+                let _ = op;
+                Fields::from([$((stringify!($field), op.$field($inst).value())),*])
+            })*
+        }
+    };
+}
+
+/// Decode `inst` with riscv-inst, reading every operand the reference knows about.
+fn decode(inst: u32) -> Option<Decoded> {
+    let op = Rv32IMASFDC::parse(inst)?;
+    let fields = fields!(op, inst, {
+        Lui => [rd, imm],
+        Auipc => [rd, imm],
+        Jal => [rd, imm],
+        Jalr => [rd, rs1, imm],
+        Beq => [rs1, rs2, imm],
+        Bne => [rs1, rs2, imm],
+        Blt => [rs1, rs2, imm],
+        Bge => [rs1, rs2, imm],
+        Bltu => [rs1, rs2, imm],
+        Bgeu => [rs1, rs2, imm],
+        Lb => [rd, rs1, imm],
+        Lh => [rd, rs1, imm],
+        Lw => [rd, rs1, imm],
+        Lbu => [rd, rs1, imm],
+        Lhu => [rd, rs1, imm],
+        Sb => [rs1, rs2, imm],
+        Sh => [rs1, rs2, imm],
+        Sw => [rs1, rs2, imm],
+        Addi => [rd, rs1, imm],
+        Slti => [rd, rs1, imm],
+        Sltiu => [rd, rs1, imm],
+        Xori => [rd, rs1, imm],
+        Ori => [rd, rs1, imm],
+        Andi => [rd, rs1, imm],
+        Slli => [rd, rs1, shamt],
+        Srli => [rd, rs1, shamt],
+        Srai => [rd, rs1, shamt],
+        Add => [rd, rs1, rs2],
+        Sub => [rd, rs1, rs2],
+        Sll => [rd, rs1, rs2],
+        Slt => [rd, rs1, rs2],
+        Sltu => [rd, rs1, rs2],
+        Xor => [rd, rs1, rs2],
+        Srl => [rd, rs1, rs2],
+        Sra => [rd, rs1, rs2],
+        Or => [rd, rs1, rs2],
+        And => [rd, rs1, rs2],
+        Fence => [pred, succ],
+        FenceI => [],
+        Ecall => [],
+        Ebreak => [],
+        Mul => [rd, rs1, rs2],
+        Mulh => [rd, rs1, rs2],
+        Mulhsu => [rd, rs1, rs2],
+        Mulhu => [rd, rs1, rs2],
+        Div => [rd, rs1, rs2],
+        Divu => [rd, rs1, rs2],
+        Rem => [rd, rs1, rs2],
+        Remu => [rd, rs1, rs2],
+        LrW => [rd, rs1, aq, rl],
+        ScW => [rd, rs1, rs2, aq, rl],
+        AmoswapW => [rd, rs1, rs2, aq, rl],
+        AmoaddW => [rd, rs1, rs2, aq, rl],
+        AmoxorW => [rd, rs1, rs2, aq, rl],
+        AmoorW => [rd, rs1, rs2, aq, rl],
+        AmoandW => [rd, rs1, rs2, aq, rl],
+        AmominW => [rd, rs1, rs2, aq, rl],
+        AmomaxW => [rd, rs1, rs2, aq, rl],
+        AmominuW => [rd, rs1, rs2, aq, rl],
+        AmomaxuW => [rd, rs1, rs2, aq, rl],
+        Uret => [],
+        Sret => [],
+        Hret => [],
+        Mret => [],
+        Dret => [],
+        SfenceVm => [rs1],
+        SfenceVma => [rs1, rs2],
+        Wfi => [],
+        Csrrw => [rd, rs1, csr12],
+        Csrrs => [rd, rs1, csr12],
+        Csrrc => [rd, rs1, csr12],
+        Csrrwi => [rd, imm, csr12],
+        Csrrsi => [rd, imm, csr12],
+        Csrrci => [rd, imm, csr12],
+        Aes32esi => [rd, rs1, rs2, bs],
+        Aes32esmi => [rd, rs1, rs2, bs],
+        Aes32dsi => [rd, rs1, rs2, bs],
+        Aes32dsmi => [rd, rs1, rs2, bs],
+        Sha256sig0 => [rd, rs1],
+        Sha256sig1 => [rd, rs1],
+        Sha256sum0 => [rd, rs1],
+        Sha256sum1 => [rd, rs1],
+        Sha512sig0h => [rd, rs1, rs2],
+        Sha512sig0l => [rd, rs1, rs2],
+        Sha512sig1h => [rd, rs1, rs2],
+        Sha512sig1l => [rd, rs1, rs2],
+        Sha512sum0r => [rd, rs1, rs2],
+        Sha512sum1r => [rd, rs1, rs2],
+        WrsNto => [],
+        WrsSto => [],
+        Flw => [frd, rs1, imm],
+        Fsw => [rs1, frs2, imm],
+        FmaddS => [frd, frs1, frs2, frs3, rm],
+        FmsubS => [frd, frs1, frs2, frs3, rm],
+        FnmsubS => [frd, frs1, frs2, frs3, rm],
+        FnmaddS => [frd, frs1, frs2, frs3, rm],
+        FaddS => [frd, frs1, frs2, rm],
+        FsubS => [frd, frs1, frs2, rm],
+        FmulS => [frd, frs1, frs2, rm],
+        FdivS => [frd, frs1, frs2, rm],
+        FsgnjS => [frd, frs1, frs2],
+        FsgnjnS => [frd, frs1, frs2],
+        FsgnjxS => [frd, frs1, frs2],
+        FminS => [frd, frs1, frs2],
+        FmaxS => [frd, frs1, frs2],
+        FsqrtS => [frd, frs1, rm],
+        FleS => [rd, frs1, frs2],
+        FltS => [rd, frs1, frs2],
+        FeqS => [rd, frs1, frs2],
+        FcvtWS => [rd, frs1, rm],
+        FcvtWuS => [rd, frs1, rm],
+        FcvtSW => [frd, rs1, rm],
+        FcvtSWu => [frd, rs1, rm],
+        FmvXS => [rd, frs1],
+        FclassS => [rd, frs1],
+        FmvSX => [frd, rs1],
+        Fld => [frd, rs1, imm],
+        Fsd => [rs1, frs2, imm],
+        FmaddD => [frd, frs1, frs2, frs3, rm],
+        FmsubD => [frd, frs1, frs2, frs3, rm],
+        FnmsubD => [frd, frs1, frs2, frs3, rm],
+        FnmaddD => [frd, frs1, frs2, frs3, rm],
+        FaddD => [frd, frs1, frs2, rm],
+        FsubD => [frd, frs1, frs2, rm],
+        FmulD => [frd, frs1, frs2, rm],
+        FdivD => [frd, frs1, frs2, rm],
+        FsgnjD => [frd, frs1, frs2],
+        FsgnjnD => [frd, frs1, frs2],
+        FsgnjxD => [frd, frs1, frs2],
+        FminD => [frd, frs1, frs2],
+        FmaxD => [frd, frs1, frs2],
+        FcvtSD => [frd, frs1, rm],
+        FcvtDS => [frd, frs1, rm],
+        FsqrtD => [frd, frs1, rm],
+        FleD => [rd, frs1, frs2],
+        FltD => [rd, frs1, frs2],
+        FeqD => [rd, frs1, frs2],
+        FcvtWD => [rd, frs1, rm],
+        FcvtWuD => [rd, frs1, rm],
+        FcvtDW => [frd, rs1, rm],
+        FcvtDWu => [frd, rs1, rm],
+        FclassD => [rd, frs1],
+        Unimp => [],
+        CAddi4spn => [rd, imm],
+        CLw => [rd, rs1, imm],
+        CSw => [rs1, rs2, imm],
+        CNop => [],
+        CAddi => [rs1rd, imm],
+        CJal => [imm],
+        CLi => [rs1rd, imm],
+        CAddi16sp => [rs1rd, imm],
+        CLui => [rd, imm],
+        CSrli => [rs1rd, shamt],
+        CSrai => [rs1rd, shamt],
+        CAndi => [rs1rd, imm],
+        CSub => [rs1rd, rs2],
+        CXor => [rs1rd, rs2],
+        COr => [rs1rd, rs2],
+        CAnd => [rs1rd, rs2],
+        CJ => [imm],
+        CBeqz => [rs1, imm],
+        CBnez => [rs1, imm],
+        CSlli => [rs1rd, shamt],
+        CLwsp => [rd, imm],
+        CJr => [rs1],
+        CMv => [rd, rs2],
+        CEbreak => [],
+        CJalr => [rs1],
+        CAdd => [rs1rd, rs2],
+        CSwsp => [rs2, imm],
+        CFld => [frd, rs1, imm],
+        CFlw => [frd, rs1, imm],
+        CFsd => [rs1, frs2, imm],
+        CFsw => [rs1, frs2, imm],
+        CFldsp => [frd, imm],
+        CFlwsp => [frd, imm],
+        CFsdsp => [frs2, imm],
+        CFswsp => [frs2, imm],
+        CUnimp => [],
+    });
+
+    Some((op.mnemonic(), fields))
+}
+
+fn bits(inst: u32, hi: u32, lo: u32) -> u32 {
+    (inst >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+fn bit(inst: u32, n: u32) -> u32 {
+    (inst >> n) & 1
+}
+
+/// Sign extend the low `width` bits of `value`.
+fn sext(value: u32, width: u32) -> i64 {
+    let shift = 64 - width;
+    ((value as i64) << shift) >> shift
+}
+
+fn decoded<const N: usize>(mnemonic: &'static str, fields: [(&'static str, i64); N]) -> Decoded {
+    (mnemonic, Fields::from(fields))
+}
+
+/// Decode a 32-bit instruction from the base, M, A, F, D, Zicsr and privileged
+/// specs.
+fn reference32(inst: u32) -> Option<Decoded> {
+    let rd = bits(inst, 11, 7) as i64;
+    let rs1 = bits(inst, 19, 15) as i64;
+    let rs2 = bits(inst, 24, 20) as i64;
+    let funct3 = bits(inst, 14, 12);
+    let funct7 = bits(inst, 31, 25);
+
+    let i_imm = sext(bits(inst, 31, 20), 12);
+    let s_imm = sext(bits(inst, 31, 25) << 5 | bits(inst, 11, 7), 12);
+    let b_imm = sext(
+        bit(inst, 31) << 12 | bit(inst, 7) << 11 | bits(inst, 30, 25) << 5 | bits(inst, 11, 8) << 1,
+        13,
+    );
+    let u_imm = sext(inst & 0xFFFF_F000, 32);
+    let j_imm = sext(
+        bit(inst, 31) << 20
+            | bits(inst, 19, 12) << 12
+            | bit(inst, 20) << 11
+            | bits(inst, 30, 21) << 1,
+        21,
+    );
+
+    let rm = funct3 as i64;
+    let rs3 = bits(inst, 31, 27) as i64;
+    // The F or D format, in funct7's low bits
+    let fp_fmt = |m: [&'static str; 2]| match bits(inst, 26, 25) {
+        0 => Some(m[0]),
+        1 => Some(m[1]),
+        _ => None,
+    };
+
+    let r_type = |m| decoded(m, [("rd", rd), ("rs1", rs1), ("rs2", rs2)]);
+    let i_type = |m| decoded(m, [("rd", rd), ("rs1", rs1), ("imm", i_imm)]);
+    let shift = |m| decoded(m, [("rd", rd), ("rs1", rs1), ("shamt", rs2)]);
+
+    let op = match inst & 0x7F {
+        0x37 => decoded("lui", [("rd", rd), ("imm", u_imm)]),
+        0x17 => decoded("auipc", [("rd", rd), ("imm", u_imm)]),
+        0x6F => decoded("jal", [("rd", rd), ("imm", j_imm)]),
+        0x67 if funct3 == 0 => i_type("jalr"),
+        0x63 => {
+            let m = match funct3 {
+                0 => "beq",
+                1 => "bne",
+                4 => "blt",
+                5 => "bge",
+                6 => "bltu",
+                7 => "bgeu",
+                _ => return None,
+            };
+            decoded(m, [("rs1", rs1), ("rs2", rs2), ("imm", b_imm)])
+        }
+        0x03 => i_type(match funct3 {
+            0 => "lb",
+            1 => "lh",
+            2 => "lw",
+            4 => "lbu",
+            5 => "lhu",
+            _ => return None,
+        }),
+        0x23 => {
+            let m = match funct3 {
+                0 => "sb",
+                1 => "sh",
+                2 => "sw",
+                _ => return None,
+            };
+            decoded(m, [("rs1", rs1), ("rs2", rs2), ("imm", s_imm)])
+        }
+        0x13 => match (funct3, funct7) {
+            (0, _) => i_type("addi"),
+            (2, _) => i_type("slti"),
+            (3, _) => i_type("sltiu"),
+            (4, _) => i_type("xori"),
+            (6, _) => i_type("ori"),
+            (7, _) => i_type("andi"),
+            // shamt[5] must be zero on RV32
+            (1, 0x00) => shift("slli"),
+            (5, 0x00) => shift("srli"),
+            (5, 0x20) => shift("srai"),
+            // Zknh
+            (1, 0x08) => decoded(
+                match rs2 {
+                    0 => "sha256sum0",
+                    1 => "sha256sum1",
+                    2 => "sha256sig0",
+                    3 => "sha256sig1",
+                    _ => return None,
+                },
+                [("rd", rd), ("rs1", rs1)],
+            ),
+            _ => return None,
+        },
+        // Zkne and Zknd, with a byte select in funct7's top bits
+        0x33 if funct3 == 0 && matches!(funct7 & 0x1F, 0x11 | 0x13 | 0x15 | 0x17) => {
+            let m = match funct7 & 0x1F {
+                0x11 => "aes32esi",
+                0x13 => "aes32esmi",
+                0x15 => "aes32dsi",
+                _ => "aes32dsmi",
+            };
+            let bs = (funct7 >> 5) as i64;
+            decoded(m, [("rd", rd), ("rs1", rs1), ("rs2", rs2), ("bs", bs)])
+        }
+        0x33 => r_type(match (funct7, funct3) {
+            (0x00, 0) => "add",
+            (0x20, 0) => "sub",
+            (0x00, 1) => "sll",
+            (0x00, 2) => "slt",
+            (0x00, 3) => "sltu",
+            (0x00, 4) => "xor",
+            (0x00, 5) => "srl",
+            (0x20, 5) => "sra",
+            (0x00, 6) => "or",
+            (0x00, 7) => "and",
+            (0x01, 0) => "mul",
+            (0x01, 1) => "mulh",
+            (0x01, 2) => "mulhsu",
+            (0x01, 3) => "mulhu",
+            (0x01, 4) => "div",
+            (0x01, 5) => "divu",
+            (0x01, 6) => "rem",
+            (0x01, 7) => "remu",
+            // Zknh
+            (0x2E, 0) => "sha512sig0h",
+            (0x2A, 0) => "sha512sig0l",
+            (0x2F, 0) => "sha512sig1h",
+            (0x2B, 0) => "sha512sig1l",
+            (0x28, 0) => "sha512sum0r",
+            (0x29, 0) => "sha512sum1r",
+            _ => return None,
+        }),
+        // Unused fence fields are reserved, and must be ignored
+        0x0F => match funct3 {
+            0 => decoded(
+                "fence",
+                [
+                    ("pred", bits(inst, 27, 24) as i64),
+                    ("succ", bits(inst, 23, 20) as i64),
+                ],
+            ),
+            1 => decoded("fence.i", []),
+            _ => return None,
+        },
+        0x73 => {
+            let csr = bits(inst, 31, 20) as i64;
+            match funct3 {
+                0 => match inst {
+                    0x0000_0073 => decoded("ecall", []),
+                    0x0010_0073 => decoded("ebreak", []),
+                    0x0020_0073 => decoded("uret", []),
+                    0x1020_0073 => decoded("sret", []),
+                    0x2020_0073 => decoded("hret", []),
+                    0x3020_0073 => decoded("mret", []),
+                    0x7B20_0073 => decoded("dret", []),
+                    0x1050_0073 => decoded("wfi", []),
+                    0x00D0_0073 => decoded("wrs.nto", []),
+                    0x01D0_0073 => decoded("wrs.sto", []),
+                    _ if rd == 0 && funct7 == 0x08 && rs2 == 4 => {
+                        decoded("sfence.vm", [("rs1", rs1)])
+                    }
+                    _ if rd == 0 && funct7 == 0x09 => {
+                        decoded("sfence.vma", [("rs1", rs1), ("rs2", rs2)])
+                    }
+                    _ => return None,
+                },
+                // csrrw x0, cycle, x0
+                1 if inst == 0xC000_1073 => decoded("unimp", []),
+                1 => decoded("csrrw", [("rd", rd), ("rs1", rs1), ("csr12", csr)]),
+                2 => decoded("csrrs", [("rd", rd), ("rs1", rs1), ("csr12", csr)]),
+                3 => decoded("csrrc", [("rd", rd), ("rs1", rs1), ("csr12", csr)]),
+                5 => decoded("csrrwi", [("rd", rd), ("imm", rs1), ("csr12", csr)]),
+                6 => decoded("csrrsi", [("rd", rd), ("imm", rs1), ("csr12", csr)]),
+                7 => decoded("csrrci", [("rd", rd), ("imm", rs1), ("csr12", csr)]),
+                _ => return None,
+            }
+        }
+        0x07 | 0x27 => {
+            let m = match (inst & 0x7F, funct3) {
+                (0x07, 2) => "flw",
+                (0x07, 3) => "fld",
+                (0x27, 2) => "fsw",
+                (0x27, 3) => "fsd",
+                _ => return None,
+            };
+            if inst & 0x7F == 0x07 {
+                decoded(m, [("frd", rd), ("rs1", rs1), ("imm", i_imm)])
+            } else {
+                decoded(m, [("rs1", rs1), ("frs2", rs2), ("imm", s_imm)])
+            }
+        }
+        0x43 | 0x47 | 0x4B | 0x4F => {
+            let m = match inst & 0x7F {
+                0x43 => fp_fmt(["fmadd.s", "fmadd.d"]),
+                0x47 => fp_fmt(["fmsub.s", "fmsub.d"]),
+                0x4B => fp_fmt(["fnmsub.s", "fnmsub.d"]),
+                _ => fp_fmt(["fnmadd.s", "fnmadd.d"]),
+            }?;
+            decoded(
+                m,
+                [
+                    ("frd", rd),
+                    ("frs1", rs1),
+                    ("frs2", rs2),
+                    ("frs3", rs3),
+                    ("rm", rm),
+                ],
+            )
+        }
+        0x53 => {
+            let fp_r = |m| decoded(m, [("frd", rd), ("frs1", rs1), ("frs2", rs2)]);
+            let fp_rm = |m| decoded(m, [("frd", rd), ("frs1", rs1), ("frs2", rs2), ("rm", rm)]);
+            let fp_unary = |m| decoded(m, [("frd", rd), ("frs1", rs1), ("rm", rm)]);
+            match (bits(inst, 31, 27), funct3, rs2) {
+                (0x00, ..) => fp_rm(fp_fmt(["fadd.s", "fadd.d"])?),
+                (0x01, ..) => fp_rm(fp_fmt(["fsub.s", "fsub.d"])?),
+                (0x02, ..) => fp_rm(fp_fmt(["fmul.s", "fmul.d"])?),
+                (0x03, ..) => fp_rm(fp_fmt(["fdiv.s", "fdiv.d"])?),
+                (0x0B, _, 0) => fp_unary(fp_fmt(["fsqrt.s", "fsqrt.d"])?),
+                (0x04, 0, _) => fp_r(fp_fmt(["fsgnj.s", "fsgnj.d"])?),
+                (0x04, 1, _) => fp_r(fp_fmt(["fsgnjn.s", "fsgnjn.d"])?),
+                (0x04, 2, _) => fp_r(fp_fmt(["fsgnjx.s", "fsgnjx.d"])?),
+                (0x05, 0, _) => fp_r(fp_fmt(["fmin.s", "fmin.d"])?),
+                (0x05, 1, _) => fp_r(fp_fmt(["fmax.s", "fmax.d"])?),
+                (0x08, _, 1) if funct7 & 3 == 0 => fp_unary("fcvt.s.d"),
+                (0x08, _, 0) if funct7 & 3 == 1 => fp_unary("fcvt.d.s"),
+                (0x14, 0..=2, _) => decoded(
+                    fp_fmt(match funct3 {
+                        0 => ["fle.s", "fle.d"],
+                        1 => ["flt.s", "flt.d"],
+                        _ => ["feq.s", "feq.d"],
+                    })?,
+                    [("rd", rd), ("frs1", rs1), ("frs2", rs2)],
+                ),
+                (0x18, _, 0..=1) => decoded(
+                    fp_fmt(match rs2 {
+                        0 => ["fcvt.w.s", "fcvt.w.d"],
+                        _ => ["fcvt.wu.s", "fcvt.wu.d"],
+                    })?,
+                    [("rd", rd), ("frs1", rs1), ("rm", rm)],
+                ),
+                (0x1A, _, 0..=1) => decoded(
+                    fp_fmt(match rs2 {
+                        0 => ["fcvt.s.w", "fcvt.d.w"],
+                        _ => ["fcvt.s.wu", "fcvt.d.wu"],
+                    })?,
+                    [("frd", rd), ("rs1", rs1), ("rm", rm)],
+                ),
+                // The D moves are RV64 only
+                (0x1C, 0, 0) if funct7 & 3 == 0 => decoded("fmv.x.s", [("rd", rd), ("frs1", rs1)]),
+                (0x1C, 1, 0) => decoded(
+                    fp_fmt(["fclass.s", "fclass.d"])?,
+                    [("rd", rd), ("frs1", rs1)],
+                ),
+                (0x1E, 0, 0) if funct7 & 3 == 0 => decoded("fmv.s.x", [("frd", rd), ("rs1", rs1)]),
+                _ => return None,
+            }
+        }
+        0x2F if funct3 == 2 => {
+            let aq = bit(inst, 26) as i64;
+            let rl = bit(inst, 25) as i64;
+            let m = match bits(inst, 31, 27) {
+                0x02 if rs2 == 0 => {
+                    return Some(decoded(
+                        "lr.w",
+                        [("rd", rd), ("rs1", rs1), ("aq", aq), ("rl", rl)],
+                    ))
+                }
+                0x03 => "sc.w",
+                0x01 => "amoswap.w",
+                0x00 => "amoadd.w",
+                0x04 => "amoxor.w",
+                0x08 => "amoor.w",
+                0x0C => "amoand.w",
+                0x10 => "amomin.w",
+                0x14 => "amomax.w",
+                0x18 => "amominu.w",
+                0x1C => "amomaxu.w",
+                _ => return None,
+            };
+            decoded(
+                m,
+                [
+                    ("rd", rd),
+                    ("rs1", rs1),
+                    ("rs2", rs2),
+                    ("aq", aq),
+                    ("rl", rl),
+                ],
+            )
+        }
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+/// Decode a 16-bit instruction from the RV32C spec.
+///
+/// The decoder reports unknown compressed encodings as `c.unimp`, so this does
+/// too. riscv-meta can't say an operand must be nonzero, so the encodings
+/// reserved for a zero immediate or `x0` (e.g. `c.lwsp x0`) decode like any
+/// other operand value.
+fn reference16(inst: u32) -> Decoded {
+    let illegal = decoded("c.unimp", []);
+    let reg = bits(inst, 11, 7) as i64;
+    let rs2 = bits(inst, 6, 2) as i64;
+    // The 3-bit registers, x8-x15
+    let creg = |hi, lo| 8 + bits(inst, hi, lo) as i64;
+    let ci_imm = sext(bit(inst, 12) << 5 | bits(inst, 6, 2), 6);
+    let cl_imm = (bits(inst, 12, 10) << 3 | bit(inst, 6) << 2 | bit(inst, 5) << 6) as i64;
+    let cld_imm = (bits(inst, 12, 10) << 3 | bits(inst, 6, 5) << 6) as i64;
+    let lwsp_imm = (bit(inst, 12) << 5 | bits(inst, 6, 4) << 2 | bits(inst, 3, 2) << 6) as i64;
+    let ldsp_imm = (bit(inst, 12) << 5 | bits(inst, 6, 5) << 3 | bits(inst, 4, 2) << 6) as i64;
+    let swsp_imm = (bits(inst, 12, 9) << 2 | bits(inst, 8, 7) << 6) as i64;
+    let sdsp_imm = (bits(inst, 12, 10) << 3 | bits(inst, 9, 7) << 6) as i64;
+    let cj_imm = sext(
+        bit(inst, 12) << 11
+            | bit(inst, 11) << 4
+            | bits(inst, 10, 9) << 8
+            | bit(inst, 8) << 10
+            | bit(inst, 7) << 6
+            | bit(inst, 6) << 7
+            | bits(inst, 5, 3) << 1
+            | bit(inst, 2) << 5,
+        12,
+    );
+    let cb_imm = sext(
+        bit(inst, 12) << 8
+            | bits(inst, 11, 10) << 3
+            | bits(inst, 6, 5) << 6
+            | bits(inst, 4, 3) << 1
+            | bit(inst, 2) << 5,
+        9,
+    );
+
+    match (inst & 0b11, bits(inst, 15, 13)) {
+        (0, 0) => {
+            let imm = bits(inst, 12, 11) << 4
+                | bits(inst, 10, 7) << 6
+                | bit(inst, 6) << 2
+                | bit(inst, 5) << 3;
+            if inst == 0 {
+                return illegal;
+            }
+            decoded("c.addi4spn", [("rd", creg(4, 2)), ("imm", imm as i64)])
+        }
+        (0, 2) => decoded(
+            "c.lw",
+            [("rd", creg(4, 2)), ("rs1", creg(9, 7)), ("imm", cl_imm)],
+        ),
+        (0, 6) => decoded(
+            "c.sw",
+            [("rs1", creg(9, 7)), ("rs2", creg(4, 2)), ("imm", cl_imm)],
+        ),
+        (0, 1) => decoded(
+            "c.fld",
+            [("frd", creg(4, 2)), ("rs1", creg(9, 7)), ("imm", cld_imm)],
+        ),
+        (0, 3) => decoded(
+            "c.flw",
+            [("frd", creg(4, 2)), ("rs1", creg(9, 7)), ("imm", cl_imm)],
+        ),
+        (0, 5) => decoded(
+            "c.fsd",
+            [("rs1", creg(9, 7)), ("frs2", creg(4, 2)), ("imm", cld_imm)],
+        ),
+        (0, 7) => decoded(
+            "c.fsw",
+            [("rs1", creg(9, 7)), ("frs2", creg(4, 2)), ("imm", cl_imm)],
+        ),
+        (1, 0) if inst == 0x0001 => decoded("c.nop", []),
+        (1, 0) => decoded("c.addi", [("rs1rd", reg), ("imm", ci_imm)]),
+        (1, 1) => decoded("c.jal", [("imm", cj_imm)]),
+        (1, 2) => decoded("c.li", [("rs1rd", reg), ("imm", ci_imm)]),
+        (1, 3) if reg == 2 => {
+            let imm = sext(
+                bit(inst, 12) << 9
+                    | bit(inst, 6) << 4
+                    | bit(inst, 5) << 6
+                    | bits(inst, 4, 3) << 7
+                    | bit(inst, 2) << 5,
+                10,
+            );
+            decoded("c.addi16sp", [("rs1rd", reg), ("imm", imm)])
+        }
+        (1, 3) => {
+            let imm = sext(bit(inst, 12) << 17 | bits(inst, 6, 2) << 12, 18);
+            decoded("c.lui", [("rd", reg), ("imm", imm)])
+        }
+        (1, 4) => {
+            let rs1rd = creg(9, 7);
+            match (bits(inst, 11, 10), bit(inst, 12)) {
+                // shamt[5] must be zero on RV32
+                (0, 0) => decoded("c.srli", [("rs1rd", rs1rd), ("shamt", rs2)]),
+                (1, 0) => decoded("c.srai", [("rs1rd", rs1rd), ("shamt", rs2)]),
+                (2, _) => decoded("c.andi", [("rs1rd", rs1rd), ("imm", ci_imm)]),
+                (3, 0) => {
+                    let m = match bits(inst, 6, 5) {
+                        0 => "c.sub",
+                        1 => "c.xor",
+                        2 => "c.or",
+                        _ => "c.and",
+                    };
+                    decoded(m, [("rs1rd", rs1rd), ("rs2", creg(4, 2))])
+                }
+                _ => illegal,
+            }
+        }
+        (1, 5) => decoded("c.j", [("imm", cj_imm)]),
+        (1, 6) => decoded("c.beqz", [("rs1", creg(9, 7)), ("imm", cb_imm)]),
+        (1, 7) => decoded("c.bnez", [("rs1", creg(9, 7)), ("imm", cb_imm)]),
+        (2, 0) if bit(inst, 12) == 0 => decoded("c.slli", [("rs1rd", reg), ("shamt", rs2)]),
+        (2, 2) => decoded("c.lwsp", [("rd", reg), ("imm", lwsp_imm)]),
+        (2, 1) => decoded("c.fldsp", [("frd", reg), ("imm", ldsp_imm)]),
+        (2, 3) => decoded("c.flwsp", [("frd", reg), ("imm", lwsp_imm)]),
+        (2, 4) => match (bit(inst, 12), reg, rs2) {
+            (0, _, 0) => decoded("c.jr", [("rs1", reg)]),
+            (0, _, _) => decoded("c.mv", [("rd", reg), ("rs2", rs2)]),
+            (_, 0, 0) => decoded("c.ebreak", []),
+            (_, _, 0) => decoded("c.jalr", [("rs1", reg)]),
+            (_, _, _) => decoded("c.add", [("rs1rd", reg), ("rs2", rs2)]),
+        },
+        (2, 6) => decoded("c.swsp", [("rs2", rs2), ("imm", swsp_imm)]),
+        (2, 5) => decoded("c.fsdsp", [("frs2", rs2), ("imm", sdsp_imm)]),
+        (2, 7) => decoded("c.fswsp", [("frs2", rs2), ("imm", swsp_imm)]),
+        _ => illegal,
+    }
+}
+
+/// Major opcodes (bits 6..0) of every 32-bit instruction the decoder knows.
+const MAJOR_OPCODES: [u32; 18] = [
+    0x37, 0x17, 0x6F, 0x67, 0x63, 0x03, 0x23, 0x13, 0x33, 0x0F, 0x73, 0x07, 0x27, 0x43, 0x47, 0x4B,
+    0x4F, 0x53,
+];
+
+fn check32(inst: u32) -> Result<(), TestCaseError> {
+    prop_assert_eq!(
+        decode(inst),
+        reference32(inst),
+        "instruction {:#010x}",
+        inst
+    );
+    Ok(())
+}
+
+#[test]
+fn compressed_matches_reference() {
+    for inst in 0..=u16::MAX as u32 {
+        if inst & 0b11 == 0b11 {
+            continue;
+        }
+        assert_eq!(
+            decode(inst),
+            Some(reference16(inst)),
+            "instruction {inst:#06x}"
+        );
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(20_000))]
+
+    #[test]
+    fn known_opcodes_match_reference(
+        major in prop::sample::select(&MAJOR_OPCODES[..]),
+        rest in any::<u32>(),
+    ) {
+        check32(rest & !0x7F | major)?;
+    }
+
+    #[test]
+    fn atomics_match_reference(funct5 in 0u32..32, rest in any::<u32>()) {
+        // funct3 = 2 is the only valid width on RV32
+        check32(funct5 << 27 | rest & 0x07FF_8F80 | 0x2 << 12 | 0x2F)?;
+    }
+
+    #[test]
+    fn random_words_match_reference(inst in any::<u32>().prop_map(|i| i | 0b11)) {
+        check32(inst)?;
+    }
+}
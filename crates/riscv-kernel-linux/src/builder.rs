@@ -0,0 +1,226 @@
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use crate::{
+    vfs::{CapturedStdio, Stdio},
+    ClockMode, CpuTopology, Entropy, FetchService, FsQuota, HostCache, HostTable, Identity,
+    KvService, LinuxError, MemoryTimeline, MockLinux, SystemInfo, UnknownSyscallPolicy,
+    VirtualClock,
+};
+
+/// Where the guest's standard streams go.
+#[derive(Debug, Clone, Default)]
+pub enum StdioMode {
+    /// Reads are EOF and writes are dropped
+    #[default]
+    Null,
+    /// Forwarded to the host's stdio
+    Host,
+    /// Kept in memory, see [`CapturedStdio::shared`]
+    Captured(Rc<RefCell<CapturedStdio>>),
+}
+
+#[derive(Debug)]
+enum MountSpec {
+    Host {
+        guest_path: String,
+        host_dir: PathBuf,
+        read_only: bool,
+    },
+    Archive(Vec<u8>),
+}
+
+/// Configures a [`MockLinux`], from [`MockLinux::builder`]:
+///
+/// ```no_run
+/// # use riscv_kernel_linux::{ClockMode, MockLinux, StdioMode};
+/// let kernel = MockLinux::builder()
+///     .stdio(StdioMode::Host)
+///     .clock_mode(ClockMode::Deterministic)
+///     .mount_host("/data", "./data", true)
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// Defaults match [`MockLinux::new`] without stdio passthrough.
+#[derive(Debug, Default)]
+pub struct MockLinuxBuilder {
+    strace: bool,
+    memory_timeline: bool,
+    stdio: StdioMode,
+    mounts: Vec<MountSpec>,
+    host_cache: Option<HostCache>,
+    entropy: Entropy,
+    clock: VirtualClock,
+    topology: CpuTopology,
+    system: SystemInfo,
+    fs_quota: FsQuota,
+    unknown_syscalls: UnknownSyscallPolicy,
+    identity: Identity,
+    hosts: HostTable,
+    fetch: Option<FetchService>,
+    kv: Option<KvService>,
+    gas_limit: Option<u64>,
+}
+
+impl MockLinuxBuilder {
+    /// Print each syscall and its result to stderr, like strace.
+    pub fn strace(mut self, strace: bool) -> Self {
+        self.strace = strace;
+        self
+    }
+
+    /// Record a timeline of the guest's `brk`, `mmap` and `munmap` calls,
+    /// see [`MockLinux::memory_timeline`].
+    pub fn memory_timeline(mut self, memory_timeline: bool) -> Self {
+        self.memory_timeline = memory_timeline;
+        self
+    }
+
+    pub fn stdio(mut self, stdio: StdioMode) -> Self {
+        self.stdio = stdio;
+        self
+    }
+
+    /// Expose the host directory `host_dir` to the guest at `guest_path`.
+    pub fn mount_host(
+        mut self,
+        guest_path: impl Into<String>,
+        host_dir: impl Into<PathBuf>,
+        read_only: bool,
+    ) -> Self {
+        self.mounts.push(MountSpec::Host {
+            guest_path: guest_path.into(),
+            host_dir: host_dir.into(),
+            read_only,
+        });
+        self
+    }
+
+    /// Cache what host mounts find on the host, see [`HostCache`]. Only
+    /// for host directories that don't change while the guest runs.
+    pub fn host_cache(mut self, cache: HostCache) -> Self {
+        self.host_cache = Some(cache);
+        self
+    }
+
+    /// Use a tar or newc cpio archive as the root filesystem, see
+    /// [`MockLinux::mount_archive`]. Mounts apply in the order given, so
+    /// this should come before any host mounts.
+    pub fn mount_archive(mut self, archive: impl Into<Vec<u8>>) -> Self {
+        self.mounts.push(MountSpec::Archive(archive.into()));
+        self
+    }
+
+    pub fn entropy(mut self, entropy: Entropy) -> Self {
+        self.entropy = entropy;
+        self
+    }
+
+    pub fn clock(mut self, clock: VirtualClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Use the default clock in `mode`.
+    pub fn clock_mode(self, mode: ClockMode) -> Self {
+        self.clock(VirtualClock::new(mode))
+    }
+
+    pub fn topology(mut self, topology: CpuTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// What `uname` and `sysinfo` report.
+    pub fn system(mut self, system: SystemInfo) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// Limit what the guest stores in files, see [`FsQuota`].
+    pub fn fs_quota(mut self, quota: FsQuota) -> Self {
+        self.fs_quota = quota;
+        self
+    }
+
+    /// What to do about syscalls that aren't implemented.
+    pub fn unknown_syscalls(mut self, policy: UnknownSyscallPolicy) -> Self {
+        self.unknown_syscalls = policy;
+        self
+    }
+
+    /// Who the guest runs as, see [`Identity`].
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Host names the guest can resolve, see [`HostTable`].
+    pub fn hosts(mut self, hosts: HostTable) -> Self {
+        self.hosts = hosts;
+        self
+    }
+
+    /// Answer the guest's `fetch` hypercall, see [`FetchService`].
+    pub fn fetch(mut self, fetch: FetchService) -> Self {
+        self.fetch = Some(fetch);
+        self
+    }
+
+    /// Answer the guest's key-value hypercalls, see [`KvService`].
+    pub fn kv(mut self, kv: KvService) -> Self {
+        self.kv = Some(kv);
+        self
+    }
+
+    /// Fail hypercalls with `EDQUOT` once they'd charge more than `gas`.
+    pub fn gas_limit(mut self, gas: u64) -> Self {
+        self.gas_limit = Some(gas);
+        self
+    }
+
+    /// Build the kernel, failing if a mount can't be set up.
+    pub fn build(self) -> Result<MockLinux, LinuxError> {
+        let mut kernel = MockLinux::new(false);
+        kernel.strace = self.strace;
+        kernel.memory_timeline = self.memory_timeline.then(MemoryTimeline::new);
+        kernel.entropy = self.entropy;
+        kernel.clock = self.clock;
+        kernel.topology = self.topology;
+        kernel.system = self.system;
+        kernel.fs_quota = self.fs_quota;
+        kernel.host_cache = self.host_cache;
+        kernel.unknown_syscalls = self.unknown_syscalls;
+        kernel.hosts = self.hosts;
+        kernel.fetch = self.fetch;
+        kernel.kv = self.kv;
+        kernel.gas_limit = self.gas_limit;
+        kernel.set_identity(self.identity);
+        match self.stdio {
+            StdioMode::Null => {}
+            StdioMode::Host => kernel.files.set_stdio(|fd| Stdio::new(fd, true)),
+            StdioMode::Captured(captured) => kernel
+                .files
+                .set_stdio(|fd| Stdio::captured(fd, captured.clone())),
+        }
+
+        for mount in self.mounts {
+            match mount {
+                MountSpec::Host {
+                    guest_path,
+                    host_dir,
+                    read_only,
+                } => kernel
+                    .mount_host(&guest_path, &host_dir, read_only)
+                    .map_err(|source| LinuxError::Mount {
+                        guest_path,
+                        host_dir,
+                        source,
+                    })?,
+                MountSpec::Archive(archive) => kernel.mount_archive(&archive)?,
+            }
+        }
+
+        Ok(kernel)
+    }
+}
@@ -0,0 +1,105 @@
+use riscv_vm::{hart::Hart32, memory::Memory};
+
+use crate::{errno::KernelResult, poll::read_timespec, MockLinux, Timespec64};
+
+/// Resolution of the coarse clocks, a jiffy at `HZ=250`.
+const COARSE_RES_NS: u64 = 4_000_000;
+
+impl MockLinux {
+    /// The current time on `clockid`, or `None` if there's no such clock.
+    fn clock_ns(&self, hart: &Hart32, clockid: u32) -> Option<u64> {
+        let inst_count = hart.inst_count;
+        Some(match clockid {
+            libc_riscv32::CLOCK_REALTIME | libc_riscv32::CLOCK_REALTIME_COARSE => {
+                self.clock.realtime_ns(inst_count)
+            }
+            libc_riscv32::CLOCK_MONOTONIC
+            | libc_riscv32::CLOCK_MONOTONIC_RAW
+            | libc_riscv32::CLOCK_MONOTONIC_COARSE
+            | libc_riscv32::CLOCK_BOOTTIME => self.clock.monotonic_ns(inst_count),
+            libc_riscv32::CLOCK_PROCESS_CPUTIME_ID | libc_riscv32::CLOCK_THREAD_CPUTIME_ID => {
+                self.clock.cpu_ns(inst_count)
+            }
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn clock_gettime64(
+        &mut self,
+        hart: &Hart32,
+        mem: &mut Memory,
+        clockid: u32,
+        tp: u32,
+    ) -> KernelResult {
+        let ns = self.clock_ns(hart, clockid).ok_or(libc_riscv32::EINVAL)?;
+
+        mem.write(tp, Timespec64::from_ns(ns))
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn clock_getres_time64(
+        &mut self,
+        hart: &Hart32,
+        mem: &mut Memory,
+        clockid: u32,
+        res: u32,
+    ) -> KernelResult {
+        self.clock_ns(hart, clockid).ok_or(libc_riscv32::EINVAL)?;
+        let ns = match clockid {
+            libc_riscv32::CLOCK_REALTIME_COARSE | libc_riscv32::CLOCK_MONOTONIC_COARSE => {
+                COARSE_RES_NS
+            }
+            _ => 1,
+        };
+        if res != 0 {
+            mem.write(res, Timespec64::from_ns(ns))
+                .map_err(|_| libc_riscv32::EFAULT)?;
+        }
+
+        Ok(0)
+    }
+
+    pub(crate) fn clock_settime64(&mut self, mem: &Memory, clockid: u32, tp: u32) -> KernelResult {
+        if clockid != libc_riscv32::CLOCK_REALTIME {
+            return Err(libc_riscv32::EINVAL);
+        }
+        read_timespec(mem, tp)?;
+
+        // The guest runs unprivileged
+        Err(libc_riscv32::EPERM)
+    }
+
+    pub(crate) fn clock_nanosleep_time64(
+        &mut self,
+        mem: &mut Memory,
+        clockid: u32,
+        flags: u32,
+        request: u32,
+        _remain: u32,
+    ) -> KernelResult {
+        let now = self.now.get();
+        let Some(clock_now) = now.get(clockid) else {
+            return Err(match clockid {
+                // Timers don't run on the raw and coarse clocks
+                libc_riscv32::CLOCK_MONOTONIC_RAW
+                | libc_riscv32::CLOCK_REALTIME_COARSE
+                | libc_riscv32::CLOCK_MONOTONIC_COARSE => libc_riscv32::EOPNOTSUPP,
+                _ => libc_riscv32::EINVAL,
+            });
+        };
+        let request = read_timespec(mem, request)?;
+
+        // Sleeps can't be interrupted, so `remain` is never written
+        let deadline = if flags & libc_riscv32::TIMER_ABSTIME != 0 {
+            now.monotonic_ns
+                .saturating_add(request.saturating_sub(clock_now))
+        } else {
+            now.monotonic_ns.saturating_add(request)
+        };
+        self.sleep_until(deadline);
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,100 @@
+use goblin::elf::Elf;
+use riscv_vm::{hart::Hart32, memory::Memory};
+
+use crate::{LinuxError, MockLinux};
+
+/// `PATH` in [`GuestEnv::preset`], musl's default search path.
+pub const DEFAULT_PATH: &str = "/usr/local/bin:/bin:/usr/bin";
+
+/// A guest's `argv` and environment, for [`MockLinux::load_elf_with_env`].
+///
+/// Many programs misbehave with an empty environment, say looking up the
+/// locale or terminal, so [`GuestEnv::preset`] fills in what a login shell
+/// would. Variables keep the order they were first set in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GuestEnv {
+    args: Vec<String>,
+    vars: Vec<(String, String)>,
+}
+
+impl GuestEnv {
+    /// `argv[0]` set to `argv0`, and no environment.
+    pub fn new(argv0: impl Into<String>) -> Self {
+        Self {
+            args: vec![argv0.into()],
+            vars: vec![],
+        }
+    }
+
+    /// `argv[0]` set to `argv0`, with `PATH`, `LANG` and `TERM` set the way
+    /// musl programs expect.
+    pub fn preset(argv0: impl Into<String>) -> Self {
+        Self::new(argv0)
+            .var("PATH", DEFAULT_PATH)
+            .var("LANG", "C.UTF-8")
+            .var("TERM", "xterm")
+    }
+
+    /// Append `arg` to `argv`.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Append `args` to `argv`.
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the variable `name`, replacing its value if it's already set.
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let (name, value) = (name.into(), value.into());
+        match self.vars.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.vars.push((name, value)),
+        }
+        self
+    }
+
+    /// Unset the variable `name`.
+    pub fn remove_var(mut self, name: &str) -> Self {
+        self.vars.retain(|(n, _)| n != name);
+        self
+    }
+
+    /// The value of the variable `name`, if it's set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let (_, value) = self.vars.iter().find(|(n, _)| n == name)?;
+        Some(value)
+    }
+
+    pub fn argv(&self) -> &[String] {
+        &self.args
+    }
+
+    /// The environment as `NAME=value` strings, as they go on the stack.
+    pub fn envp(&self) -> Vec<String> {
+        self.vars
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect()
+    }
+}
+
+impl MockLinux {
+    /// Like [`MockLinux::load_static_elf`], with `argv` and the environment
+    /// from `env`.
+    pub fn load_elf_with_env<'a>(
+        &mut self,
+        hart: &mut Hart32,
+        mem: &mut Memory,
+        bytes: &'a [u8],
+        env: &GuestEnv,
+    ) -> Result<Elf<'a>, LinuxError> {
+        let args: Vec<&str> = env.args.iter().map(String::as_str).collect();
+        let envp = env.envp();
+        let envp: Vec<&str> = envp.iter().map(String::as_str).collect();
+        self.load_static_elf(hart, mem, bytes, &args, &envp)
+    }
+}
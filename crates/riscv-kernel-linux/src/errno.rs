@@ -0,0 +1,92 @@
+use std::io::ErrorKind;
+
+use libc_riscv32 as guest;
+
+/// The result of a syscall or a kernel operation on its behalf: a value, or
+/// a positive guest errno, which the guest sees negated in `a0`.
+pub type KernelResult<T = u32> = Result<T, i32>;
+
+/// The value a syscall leaves in `a0`.
+pub(crate) fn return_value(ret: KernelResult) -> u32 {
+    ret.unwrap_or_else(|errno| -errno as u32)
+}
+
+/// Convert a host I/O error to a guest errno.
+///
+/// Host errno values are translated by name, as they don't match the generic
+/// ones used by rv32 on every host. Errors that didn't come from the OS are
+/// mapped by their kind, and anything else is `EIO`.
+pub(crate) fn host_errno(e: std::io::Error) -> i32 {
+    e.raw_os_error()
+        .and_then(guest_errno)
+        .unwrap_or_else(|| kind_errno(e.kind()))
+}
+
+macro_rules! translate_host_errno {
+    ($raw:expr; $($name:ident),* $(,)?) => {
+        match $raw {
+            $(libc::$name => Some(guest::$name),)*
+            _ => None,
+        }
+    };
+}
+
+/// Errno values that exist on every host we run on, Linux and macOS included.
+/// Aliases like `EWOULDBLOCK` are left out since they collide on some hosts.
+fn guest_errno(raw: i32) -> Option<i32> {
+    translate_host_errno!(raw;
+        EPERM, ENOENT, ESRCH, EINTR, EIO, ENXIO, E2BIG, ENOEXEC, EBADF, ECHILD,
+        EAGAIN, ENOMEM, EACCES, EFAULT, ENOTBLK, EBUSY, EEXIST, EXDEV, ENODEV,
+        ENOTDIR, EISDIR, EINVAL, ENFILE, EMFILE, ENOTTY, ETXTBSY, EFBIG, ENOSPC,
+        ESPIPE, EROFS, EMLINK, EPIPE, EDOM, ERANGE, EDEADLK, ENAMETOOLONG, ENOLCK,
+        ENOSYS, ENOTEMPTY, ELOOP, ENOMSG, EIDRM, ENODATA, ENOLINK, EPROTO,
+        EMULTIHOP, EBADMSG, EOVERFLOW, EILSEQ, EUSERS, ENOTSOCK, EDESTADDRREQ,
+        EMSGSIZE, EPROTOTYPE, ENOPROTOOPT, EPROTONOSUPPORT, ESOCKTNOSUPPORT,
+        EOPNOTSUPP, EPFNOSUPPORT, EAFNOSUPPORT, EADDRINUSE, EADDRNOTAVAIL,
+        ENETDOWN, ENETUNREACH, ENETRESET, ECONNABORTED, ECONNRESET, ENOBUFS,
+        EISCONN, ENOTCONN, ESHUTDOWN, ETOOMANYREFS, ETIMEDOUT, ECONNREFUSED,
+        EHOSTDOWN, EHOSTUNREACH, EALREADY, EINPROGRESS, ESTALE, EDQUOT,
+        ECANCELED, EOWNERDEAD, ENOTRECOVERABLE,
+    )
+}
+
+fn kind_errno(kind: ErrorKind) -> i32 {
+    match kind {
+        ErrorKind::NotFound => guest::ENOENT,
+        ErrorKind::PermissionDenied => guest::EACCES,
+        ErrorKind::AlreadyExists => guest::EEXIST,
+        ErrorKind::WouldBlock => guest::EAGAIN,
+        ErrorKind::Interrupted => guest::EINTR,
+        ErrorKind::InvalidInput | ErrorKind::InvalidData => guest::EINVAL,
+        ErrorKind::TimedOut => guest::ETIMEDOUT,
+        ErrorKind::OutOfMemory => guest::ENOMEM,
+        ErrorKind::Unsupported => guest::EOPNOTSUPP,
+        ErrorKind::BrokenPipe => guest::EPIPE,
+        ErrorKind::NotADirectory => guest::ENOTDIR,
+        ErrorKind::IsADirectory => guest::EISDIR,
+        ErrorKind::DirectoryNotEmpty => guest::ENOTEMPTY,
+        ErrorKind::ReadOnlyFilesystem => guest::EROFS,
+        ErrorKind::StorageFull => guest::ENOSPC,
+        ErrorKind::QuotaExceeded => guest::EDQUOT,
+        ErrorKind::FileTooLarge => guest::EFBIG,
+        ErrorKind::NotSeekable => guest::ESPIPE,
+        ErrorKind::ResourceBusy => guest::EBUSY,
+        ErrorKind::ExecutableFileBusy => guest::ETXTBSY,
+        ErrorKind::Deadlock => guest::EDEADLK,
+        ErrorKind::CrossesDevices => guest::EXDEV,
+        ErrorKind::TooManyLinks => guest::EMLINK,
+        ErrorKind::InvalidFilename => guest::ENAMETOOLONG,
+        ErrorKind::ArgumentListTooLong => guest::E2BIG,
+        ErrorKind::StaleNetworkFileHandle => guest::ESTALE,
+        ErrorKind::ConnectionRefused => guest::ECONNREFUSED,
+        ErrorKind::ConnectionReset => guest::ECONNRESET,
+        ErrorKind::ConnectionAborted => guest::ECONNABORTED,
+        ErrorKind::NotConnected => guest::ENOTCONN,
+        ErrorKind::AddrInUse => guest::EADDRINUSE,
+        ErrorKind::AddrNotAvailable => guest::EADDRNOTAVAIL,
+        ErrorKind::HostUnreachable => guest::EHOSTUNREACH,
+        ErrorKind::NetworkUnreachable => guest::ENETUNREACH,
+        ErrorKind::NetworkDown => guest::ENETDOWN,
+        _ => guest::EIO,
+    }
+}
@@ -0,0 +1,113 @@
+use riscv_vm::{
+    bpred::PredictorStats,
+    hart::Hart32,
+    memory::{Memory, MMAP_BASE},
+};
+
+use crate::{errno::KernelResult, vfs::FdTable, FsUsage, MockLinux};
+
+/// Resource usage of the guest process, like a `struct rusage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RunStats {
+    pub inst_count: u64,
+    pub syscalls: u64,
+    /// CPU time, from retired instructions
+    pub cpu_ns: u64,
+    /// Time charged for syscalls
+    pub system_ns: u64,
+    /// Monotonic time since start, including sleeps and syscall costs
+    pub elapsed_ns: u64,
+    /// Bytes between the initial and current program break
+    pub heap_bytes: u32,
+    /// Bytes allocated below [`MMAP_BASE`] by `mmap`
+    pub mmap_bytes: u32,
+    /// Peak guest memory backed by host pages
+    pub max_rss_bytes: u64,
+    pub open_files: usize,
+    /// Files read, written and created
+    pub fs: FsUsage,
+    /// Conditional branch prediction, if the hart has a branch predictor
+    pub branch_prediction: Option<PredictorStats>,
+}
+
+/// How the guest process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessExit {
+    /// The status passed to `exit`/`exit_group`
+    pub code: u32,
+    /// Usage as of the exit, before teardown
+    pub stats: RunStats,
+}
+
+type ExitHook = Box<dyn FnMut(&ProcessExit)>;
+
+/// Callbacks registered with [`MockLinux::on_exit`].
+#[derive(Default)]
+pub(crate) struct ExitHooks(Vec<ExitHook>);
+
+impl std::fmt::Debug for ExitHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExitHooks({})", self.0.len())
+    }
+}
+
+impl MockLinux {
+    /// Run `hook` when the guest exits, after its files are closed and its
+    /// shared mappings detached.
+    pub fn on_exit(&mut self, hook: impl FnMut(&ProcessExit) + 'static) {
+        self.exit_hooks.0.push(Box::new(hook));
+    }
+
+    /// The exit status, once the guest has exited.
+    pub fn exit_status(&self) -> Option<&ProcessExit> {
+        self.exit.as_ref()
+    }
+
+    pub fn stats(&mut self, hart: &Hart32, mem: &Memory) -> RunStats {
+        let (_, max_rss_bytes) = self.sample_rss(mem);
+        RunStats {
+            inst_count: hart.inst_count,
+            syscalls: self.usage.syscalls,
+            cpu_ns: self.clock.cpu_ns(hart.inst_count),
+            system_ns: self.clock.syscall_ns(),
+            elapsed_ns: self.clock.monotonic_ns(hart.inst_count),
+            heap_bytes: mem.brk.saturating_sub(self.brk_start),
+            mmap_bytes: MMAP_BASE.saturating_sub(mem.mmap_top),
+            max_rss_bytes,
+            open_files: self.files.len(),
+            fs: self.fs_usage,
+            branch_prediction: hart.branch_predictor.as_ref().map(|p| p.stats),
+        }
+    }
+
+    /// `exit_group`, or `exit` of the only thread: tear down the process and
+    /// notify the exit hooks.
+    pub(crate) fn exit_process(&mut self, hart: &Hart32, mem: &mut Memory, code: u32) {
+        let exit = ProcessExit {
+            code,
+            stats: self.stats(hart, mem),
+        };
+        tracing::debug!("exit: {exit:?}");
+
+        // Closing flushes and releases host files
+        self.files = FdTable::default();
+        if let Err(e) = self.unmap_shared(mem, 0, u32::MAX) {
+            tracing::warn!("exit: failed to detach shared mappings: {e}");
+        }
+
+        self.exit = Some(exit);
+        for hook in &mut self.exit_hooks.0 {
+            hook(&exit);
+        }
+    }
+
+    pub(crate) fn exit_group(
+        &mut self,
+        hart: &Hart32,
+        mem: &mut Memory,
+        status: i32,
+    ) -> KernelResult {
+        self.exit_process(hart, mem, status as u32);
+        Ok(0)
+    }
+}
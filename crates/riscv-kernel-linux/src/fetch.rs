@@ -0,0 +1,129 @@
+use std::{fmt, io};
+
+use riscv_vm::memory::Memory;
+
+use crate::{
+    errno::{host_errno, KernelResult},
+    MockLinux,
+};
+
+/// What a [`FetchService`] lets the guest fetch, and what it costs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchPolicy {
+    /// URL prefixes the guest may fetch, e.g. `https://api.example.com/v1/`.
+    /// End them with `/`, or `https://example.com` allows
+    /// `https://example.com.evil.org` too. Other URLs fail with `EACCES`
+    pub allow: Vec<String>,
+    /// Longest request, beyond which calls fail with `E2BIG`
+    pub max_request_bytes: u32,
+    /// Longest response, beyond which calls fail with `EFBIG`
+    pub max_response_bytes: u32,
+    /// Gas charged for each call
+    pub gas_per_call: u64,
+    /// Gas charged for each byte of URL, request and response
+    pub gas_per_byte: u64,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            allow: vec![],
+            max_request_bytes: 64 << 10,
+            max_response_bytes: 1 << 20,
+            gas_per_call: 10_000,
+            gas_per_byte: 1,
+        }
+    }
+}
+
+impl FetchPolicy {
+    fn allows(&self, url: &str) -> bool {
+        self.allow.iter().any(|prefix| url.starts_with(prefix))
+    }
+}
+
+type Fetcher = Box<dyn FnMut(&str, &[u8]) -> io::Result<Vec<u8>>>;
+
+/// The host side of the guest's `fetch` hypercall, so plugins can get data
+/// from elsewhere without a network stack.
+///
+/// The guest passes a URL and request bytes, and gets back the response:
+///
+/// ```text
+/// a7 = HYPERCALL_FETCH
+/// a0, a1 = url, url_len
+/// a2, a3 = request, request_len
+/// a4, a5 = response, response_cap
+/// ```
+///
+/// The call returns the response's length, of which at most
+/// `response_cap` bytes are copied, or a negated errno. The fetcher, from
+/// the embedder, decides what requests mean, e.g. HTTP bodies to POST, and
+/// its I/O errors reach the guest as errnos.
+pub struct FetchService {
+    pub policy: FetchPolicy,
+    fetcher: Fetcher,
+}
+
+impl FetchService {
+    /// Answer allowed calls with `fetcher(url, request)`.
+    pub fn new(
+        policy: FetchPolicy,
+        fetcher: impl FnMut(&str, &[u8]) -> io::Result<Vec<u8>> + 'static,
+    ) -> Self {
+        Self {
+            policy,
+            fetcher: Box::new(fetcher),
+        }
+    }
+}
+
+impl fmt::Debug for FetchService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FetchService")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MockLinux {
+    pub(crate) fn fetch(&mut self, mem: &mut Memory, args: [u32; 6]) -> KernelResult {
+        let [url, url_len, request, request_len, response, response_cap] = args;
+        let service = self.fetch.as_ref().ok_or(libc_riscv32::ENOSYS)?;
+        let policy = service.policy.clone();
+
+        let url = mem
+            .slice::<u8>(url, url_len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        let url = std::str::from_utf8(url).map_err(|_| libc_riscv32::EINVAL)?;
+        if !policy.allows(url) {
+            tracing::debug!("fetch: {url:?} isn't allowed");
+            return Err(libc_riscv32::EACCES);
+        }
+        if request_len > policy.max_request_bytes {
+            return Err(libc_riscv32::E2BIG);
+        }
+        let request = mem
+            .slice::<u8>(request, request_len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        let sent = (url.len() + request.len()) as u64;
+        self.charge_gas(
+            policy
+                .gas_per_call
+                .saturating_add(policy.gas_per_byte.saturating_mul(sent)),
+        )?;
+        let fetcher = &mut self.fetch.as_mut().unwrap().fetcher;
+        let body = fetcher(url, request).map_err(host_errno)?;
+        if body.len() > policy.max_response_bytes as usize {
+            return Err(libc_riscv32::EFBIG);
+        }
+        self.charge_gas(policy.gas_per_byte.saturating_mul(body.len() as u64))?;
+
+        let n = body.len().min(response_cap as usize);
+        mem.copy_to(response, &body[..n])
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(body.len() as u32)
+    }
+}
@@ -0,0 +1,479 @@
+use std::io::SeekFrom;
+
+use riscv_vm::{marshal::GuestValue, memory::Memory};
+
+use crate::{
+    errno::KernelResult,
+    impls::read_iovecs,
+    vfs::{normalize, FileKind, FileLike, FileStat, OpenFile},
+    MockLinux,
+};
+
+#[repr(C)]
+#[derive(GuestValue, Default)]
+#[guest(size = 16)]
+struct StatxTimestamp {
+    tv_sec: i64,
+    tv_nsec: u32,
+    __reserved: i32,
+}
+
+#[repr(C)]
+#[derive(GuestValue, Default)]
+#[guest(size = 256)]
+struct Statx {
+    stx_mask: u32,
+    stx_blksize: u32,
+    stx_attributes: u64,
+    stx_nlink: u32,
+    stx_uid: u32,
+    stx_gid: u32,
+    stx_mode: u16,
+    __spare0: u16,
+    stx_ino: u64,
+    stx_size: u64,
+    stx_blocks: u64,
+    stx_attributes_mask: u64,
+    stx_atime: StatxTimestamp,
+    stx_btime: StatxTimestamp,
+    stx_ctime: StatxTimestamp,
+    stx_mtime: StatxTimestamp,
+    stx_rdev_major: u32,
+    stx_rdev_minor: u32,
+    stx_dev_major: u32,
+    stx_dev_minor: u32,
+    __spare2: [u64; 14],
+}
+
+impl From<FileStat> for Statx {
+    fn from(stat: FileStat) -> Self {
+        let mtime = || StatxTimestamp {
+            tv_sec: (stat.mtime_ns / 1_000_000_000) as i64,
+            tv_nsec: (stat.mtime_ns % 1_000_000_000) as u32,
+            __reserved: 0,
+        };
+        Statx {
+            stx_mask: libc_riscv32::STATX_BASIC_STATS,
+            stx_blksize: 4096,
+            stx_nlink: stat.nlink,
+            stx_mode: stat.mode() as u16,
+            stx_ino: stat.ino,
+            stx_size: stat.size,
+            stx_blocks: stat.size.div_ceil(512),
+            stx_atime: mtime(),
+            stx_ctime: mtime(),
+            stx_mtime: mtime(),
+            ..Default::default()
+        }
+    }
+}
+
+impl MockLinux {
+    /// Give the guest `file` at its lowest free descriptor, as if it had
+    /// opened `path` with `flags`, which say whether it can be read and
+    /// written. Fails with `EMFILE` if there's no free descriptor.
+    pub fn install_file(
+        &mut self,
+        file: impl FileLike,
+        path: impl Into<String>,
+        flags: u32,
+    ) -> KernelResult<i32> {
+        self.files.insert(OpenFile {
+            file: Box::new(file),
+            path: path.into(),
+            flags,
+            dir_pos: 0,
+        })
+    }
+
+    /// Like [`MockLinux::install_file`], but at `fd`, closing the file
+    /// there, e.g. to replace stdin.
+    pub fn install_file_at(
+        &mut self,
+        fd: i32,
+        file: impl FileLike,
+        path: impl Into<String>,
+        flags: u32,
+    ) {
+        let file = OpenFile {
+            file: Box::new(file),
+            path: path.into(),
+            flags,
+            dir_pos: 0,
+        };
+        self.files.insert_at(fd, file);
+    }
+
+    /// The file at `fd`, if it's open and a `T`, e.g. to see what the
+    /// guest did with one from [`MockLinux::install_file`].
+    pub fn file_as<T: FileLike>(&mut self, fd: i32) -> Option<&mut T> {
+        self.files.get_as(fd).ok()
+    }
+
+    /// Resolve `pathname` relative to `dirfd` into an absolute guest path.
+    fn resolve_at(&mut self, mem: &Memory, dirfd: i32, pathname: u32) -> KernelResult<String> {
+        let path = mem
+            .read_string(pathname, libc_riscv32::PATH_MAX)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        self.resolve_path_at(dirfd, &path)
+    }
+
+    pub(crate) fn resolve_path_at(&mut self, dirfd: i32, path: &str) -> KernelResult<String> {
+        if path.starts_with('/') {
+            return Ok(normalize("/", path));
+        }
+        let base = match dirfd {
+            libc_riscv32::AT_FDCWD => self.cwd.clone(),
+            fd => self.files.get(fd)?.path.clone(),
+        };
+
+        Ok(normalize(&base, path))
+    }
+
+    pub(crate) fn openat(
+        &mut self,
+        mem: &Memory,
+        dirfd: i32,
+        pathname: u32,
+        flags: u32,
+        mode: u32,
+    ) -> KernelResult {
+        let path = self.resolve_at(mem, dirfd, pathname)?;
+        tracing::debug!("openat: {path:?} flags={flags:#o} mode={mode:#o}");
+        if is_proc(&path) {
+            self.refresh_proc(mem);
+        }
+
+        let creates = flags & libc_riscv32::O_TMPFILE == libc_riscv32::O_TMPFILE
+            || (flags & libc_riscv32::O_CREAT != 0 && self.vfs.stat(&path).is_err());
+        if creates {
+            self.check_file_quota()?;
+        }
+        let file = self.vfs.open(&path, flags, mode & !self.creds.umask)?;
+        if creates {
+            self.fs_usage.files_created += 1;
+        }
+        let fd = self.files.insert(OpenFile {
+            file,
+            path,
+            flags,
+            dir_pos: 0,
+        })?;
+
+        Ok(fd as u32)
+    }
+
+    pub(crate) fn close(&mut self, fd: i32) -> KernelResult {
+        self.files.remove(fd)?;
+        Ok(0)
+    }
+
+    pub(crate) fn read(&mut self, mem: &mut Memory, fd: i32, buf: u32, count: u32) -> KernelResult {
+        let slice = mem
+            .slice_mut::<u8>(buf, count)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        loop {
+            let file = self.files.get(fd)?;
+            if file.flags & libc_riscv32::O_ACCMODE == libc_riscv32::O_WRONLY {
+                return Err(libc_riscv32::EBADF);
+            }
+            let nonblocking = file.flags & libc_riscv32::O_NONBLOCK != 0;
+            match file.file.read(slice) {
+                // Nothing else runs, so only a timer can make the read succeed
+                Err(libc_riscv32::EAGAIN) if !nonblocking => {
+                    let wake_at = file.file.wake_at().ok_or(libc_riscv32::EAGAIN)?;
+                    self.sleep_until(wake_at);
+                }
+                Ok(n) => {
+                    if self.is_regular_file(fd) {
+                        self.fs_usage.bytes_read += n as u64;
+                    }
+                    return Ok(n as u32);
+                }
+                Err(errno) => return Err(errno),
+            }
+        }
+    }
+
+    pub(crate) fn readv(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        iov: u32,
+        iovcnt: i32,
+    ) -> KernelResult {
+        let iovs = read_iovecs(mem, iov, iovcnt)?;
+
+        let mut total = 0u32;
+        for iov in iovs {
+            let count = self.read(mem, fd, iov.ptr, iov.len)?;
+            total = total.checked_add(count).ok_or(libc_riscv32::EINVAL)?;
+            // A short read means there's nothing more for now
+            if count < iov.len {
+                break;
+            }
+        }
+
+        Ok(total)
+    }
+
+    pub(crate) fn pread64(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        buf: u32,
+        count: u32,
+        offset_low: u32,
+        offset_high: u32,
+    ) -> KernelResult {
+        self.at_offset(fd, offset_low, offset_high, |this| {
+            this.read(mem, fd, buf, count)
+        })
+    }
+
+    pub(crate) fn pwrite64(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        buf: u32,
+        count: u32,
+        offset_low: u32,
+        offset_high: u32,
+    ) -> KernelResult {
+        self.at_offset(fd, offset_low, offset_high, |this| {
+            this.write(mem, fd, buf, count)
+        })
+    }
+
+    pub(crate) fn preadv(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        iov: u32,
+        iovcnt: i32,
+        offset_low: u32,
+        offset_high: u32,
+    ) -> KernelResult {
+        self.at_offset(fd, offset_low, offset_high, |this| {
+            this.readv(mem, fd, iov, iovcnt)
+        })
+    }
+
+    pub(crate) fn pwritev(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        iov: u32,
+        iovcnt: i32,
+        offset_low: u32,
+        offset_high: u32,
+    ) -> KernelResult {
+        self.at_offset(fd, offset_low, offset_high, |this| {
+            this.writev(mem, fd, iov, iovcnt)
+        })
+    }
+
+    /// Run `op` on `fd` at an offset, split across two registers on rv32,
+    /// leaving the file position where it was.
+    fn at_offset(
+        &mut self,
+        fd: i32,
+        offset_low: u32,
+        offset_high: u32,
+        op: impl FnOnce(&mut Self) -> KernelResult,
+    ) -> KernelResult {
+        let offset = ((offset_high as u64) << 32 | offset_low as u64) as i64;
+        if offset < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let file = self.files.get(fd)?;
+        if file.file.stat()?.kind == FileKind::Dir {
+            return Err(libc_riscv32::EISDIR);
+        }
+        // Pipes and terminals can't seek, which is the `ESPIPE` we want
+        let saved = file.file.seek(SeekFrom::Current(0))?;
+        file.file.seek(SeekFrom::Start(offset as u64))?;
+
+        let ret = op(self);
+        self.files.get(fd)?.file.seek(SeekFrom::Start(saved))?;
+
+        ret
+    }
+
+    /// Fill `buf` from `fd` starting at `offset`, short only at end of file,
+    /// leaving the file position where it was.
+    pub(crate) fn read_at(&mut self, fd: i32, buf: &mut [u8], offset: u64) -> KernelResult<usize> {
+        let file = &mut self.files.get(fd)?.file;
+        let saved = file.seek(SeekFrom::Current(0))?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut filled = 0;
+        let ret = loop {
+            match file.read(&mut buf[filled..]) {
+                Ok(0) => break Ok(filled),
+                Ok(n) => filled += n,
+                Err(errno) => break Err(errno),
+            }
+            if filled == buf.len() {
+                break Ok(filled);
+            }
+        };
+        file.seek(SeekFrom::Start(saved))?;
+
+        ret
+    }
+
+    /// `_llseek`, which is what syscall 62 (`lseek` elsewhere) means on rv32.
+    pub(crate) fn llseek(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        offset_high: u32,
+        offset_low: u32,
+        result: u32,
+        whence: u32,
+    ) -> KernelResult {
+        let offset = ((offset_high as u64) << 32 | offset_low as u64) as i64;
+        let pos = match whence {
+            libc_riscv32::SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+            libc_riscv32::SEEK_CUR => SeekFrom::Current(offset),
+            libc_riscv32::SEEK_END => SeekFrom::End(offset),
+            _ => return Err(libc_riscv32::EINVAL),
+        };
+        let file = self.files.get(fd)?;
+        if file.file.stat()?.kind == FileKind::Dir {
+            // Only rewinding is supported for directories
+            if pos != SeekFrom::Start(0) {
+                return Err(libc_riscv32::EINVAL);
+            }
+            file.dir_pos = 0;
+            mem.store::<u64>(result, 0);
+            return Ok(0);
+        }
+
+        let new = file.file.seek(pos)?;
+        mem.store::<u64>(result, new);
+
+        Ok(0)
+    }
+
+    pub(crate) fn getdents64(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        dirp: u32,
+        count: u32,
+    ) -> KernelResult {
+        let file = self.files.get(fd)?;
+        let entries = file.file.read_dir()?;
+
+        // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen; u8 d_type; char d_name[]; }
+        let mut out = vec![];
+        for (i, entry) in entries.iter().enumerate().skip(file.dir_pos) {
+            let start = out.len();
+            let reclen = (19 + entry.name.len() + 1).next_multiple_of(8);
+            if start + reclen > count as usize {
+                break;
+            }
+            out.extend_from_slice(&entry.ino.to_le_bytes());
+            out.extend_from_slice(&(i as i64 + 1).to_le_bytes());
+            out.extend_from_slice(&(reclen as u16).to_le_bytes());
+            out.push(entry.kind.dirent_type());
+            out.extend_from_slice(entry.name.as_bytes());
+            // NUL terminator and padding
+            out.resize(start + reclen, 0);
+            file.dir_pos = i + 1;
+        }
+        if out.is_empty() && file.dir_pos < entries.len() {
+            // Buffer too small for the next entry
+            return Err(libc_riscv32::EINVAL);
+        }
+
+        mem.copy_to(dirp, &out).map_err(|_| libc_riscv32::EFAULT)?;
+        Ok(out.len() as u32)
+    }
+
+    pub(crate) fn statx(
+        &mut self,
+        mem: &mut Memory,
+        dirfd: i32,
+        pathname: u32,
+        flags: u32,
+        _mask: u32,
+        statxbuf: u32,
+    ) -> KernelResult {
+        let path = mem
+            .read_string(pathname, libc_riscv32::PATH_MAX)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        let stat = if path.is_empty() && flags & libc_riscv32::AT_EMPTY_PATH != 0 {
+            self.files.get(dirfd)?.file.stat()?
+        } else {
+            let path = self.resolve_path_at(dirfd, &path)?;
+            if is_proc(&path) {
+                self.refresh_proc(mem);
+            }
+            self.vfs.stat(&path)?
+        };
+
+        mem.write(statxbuf, Statx::from(stat))
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn mkdirat(
+        &mut self,
+        mem: &Memory,
+        dirfd: i32,
+        pathname: u32,
+        mode: u32,
+    ) -> KernelResult {
+        let path = self.resolve_at(mem, dirfd, pathname)?;
+        tracing::debug!("mkdirat: {path:?} mode={mode:#o}");
+        self.check_file_quota()?;
+        self.vfs.mkdir(&path, mode & !self.creds.umask)?;
+        self.fs_usage.files_created += 1;
+
+        Ok(0)
+    }
+
+    pub(crate) fn unlinkat(
+        &mut self,
+        mem: &Memory,
+        dirfd: i32,
+        pathname: u32,
+        flags: u32,
+    ) -> KernelResult {
+        if flags & !libc_riscv32::AT_REMOVEDIR != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let path = self.resolve_at(mem, dirfd, pathname)?;
+        tracing::debug!("unlinkat: {path:?} flags={flags:#x}");
+        self.vfs
+            .unlink(&path, flags & libc_riscv32::AT_REMOVEDIR != 0)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn renameat2(
+        &mut self,
+        mem: &Memory,
+        olddirfd: i32,
+        oldpath: u32,
+        newdirfd: i32,
+        newpath: u32,
+        flags: u32,
+    ) -> KernelResult {
+        let from = self.resolve_at(mem, olddirfd, oldpath)?;
+        let to = self.resolve_at(mem, newdirfd, newpath)?;
+        tracing::debug!("renameat2: {from:?} -> {to:?} flags={flags:#x}");
+        self.vfs.rename(&from, &to, flags)?;
+
+        Ok(0)
+    }
+}
+
+fn is_proc(path: &str) -> bool {
+    path == "/proc" || path.starts_with("/proc/")
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use riscv_vm::{
+    error::MachineError,
+    machine::{Machine, MachineConfig},
+    quota::Quotas,
+};
+
+use crate::{elf_symbols, CapturedStdio, ClockMode, LinuxError, MockLinux, StdioMode};
+
+/// Instructions a [`GuestTest`] may run before it fails, so a guest that
+/// hangs fails its test instead of hanging it.
+pub const DEFAULT_MAX_INSTRUCTIONS: u64 = 1 << 32;
+
+/// Runs a static guest ELF to completion under [`MockLinux`] with in-memory
+/// stdio, for end-to-end tests of guest programs, the kernel and the hart:
+///
+/// ```no_run
+/// # use riscv_kernel_linux::harness::GuestTest;
+/// # let elf = &[];
+/// GuestTest::new(elf)
+///     .args(["echo", "hi"])
+///     .stdin("input")
+///     .run()
+///     .unwrap()
+///     .assert_exit(0)
+///     .assert_stdout("hi\n");
+/// ```
+///
+/// Guest clocks run on virtual time, so runs are reproducible.
+pub struct GuestTest<'a> {
+    elf: &'a [u8],
+    args: Vec<String>,
+    env: Vec<String>,
+    stdin: Vec<u8>,
+    config: MachineConfig,
+}
+
+impl<'a> GuestTest<'a> {
+    /// Run `elf` with `argv[0]` set to `guest` and no environment.
+    pub fn new(elf: &'a [u8]) -> Self {
+        Self {
+            elf,
+            args: vec!["guest".to_string()],
+            env: vec![],
+            stdin: vec![],
+            config: MachineConfig {
+                quotas: Quotas {
+                    instructions: Some(DEFAULT_MAX_INSTRUCTIONS),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The guest's `argv`, including `argv[0]`.
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The guest's environment, as `NAME=value` strings.
+    pub fn env<S: Into<String>>(mut self, env: impl IntoIterator<Item = S>) -> Self {
+        self.env = env.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn stdin(mut self, stdin: impl Into<Vec<u8>>) -> Self {
+        self.stdin = stdin.into();
+        self
+    }
+
+    /// Run on a machine built from `config`, instead of the default one
+    /// with [`DEFAULT_MAX_INSTRUCTIONS`].
+    pub fn config(mut self, config: MachineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Load and run the guest until it exits. Guests that fail to load
+    /// report it as a kernel error.
+    pub fn run(self) -> Result<GuestOutput, MachineError<LinuxError>> {
+        let captured = CapturedStdio::shared(self.stdin);
+        let kernel = MockLinux::builder()
+            .clock_mode(ClockMode::Deterministic)
+            .stdio(StdioMode::Captured(captured.clone()))
+            .build()
+            .map_err(MachineError::Kernel)?;
+        let mut machine = Machine::try_with_config(kernel, self.config)?;
+
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let env: Vec<&str> = self.env.iter().map(String::as_str).collect();
+        let elf = machine
+            .kernel
+            .load_static_elf(&mut machine.hart, &mut machine.mem, self.elf, &args, &env)
+            .map_err(MachineError::Kernel)?;
+        machine.symbols = elf_symbols(&elf);
+        machine.run()?;
+
+        let captured = captured.take();
+        Ok(GuestOutput {
+            exit_code: machine.kernel.exit_code(),
+            stdout: captured.stdout,
+            stderr: captured.stderr,
+            instructions: machine.hart.inst_count,
+        })
+    }
+}
+
+/// What a [`GuestTest`] run did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestOutput {
+    /// Exit status, if the guest exited rather than halting another way
+    pub exit_code: Option<u32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Instructions retired
+    pub instructions: u64,
+}
+
+impl GuestOutput {
+    pub fn stdout_str(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    pub fn stderr_str(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+
+    /// Panic, showing the guest's output, unless it exited with `code`.
+    #[track_caller]
+    pub fn assert_exit(&self, code: u32) -> &Self {
+        assert_eq!(
+            self.exit_code,
+            Some(code),
+            "guest exit code differs\n{self}"
+        );
+        self
+    }
+
+    /// Panic unless the guest wrote exactly `expected` to stdout.
+    #[track_caller]
+    pub fn assert_stdout(&self, expected: impl AsRef<[u8]>) -> &Self {
+        let expected = String::from_utf8_lossy(expected.as_ref());
+        assert_eq!(self.stdout_str(), expected, "guest stdout differs\n{self}");
+        self
+    }
+
+    /// Panic unless the guest wrote exactly `expected` to stderr.
+    #[track_caller]
+    pub fn assert_stderr(&self, expected: impl AsRef<[u8]>) -> &Self {
+        let expected = String::from_utf8_lossy(expected.as_ref());
+        assert_eq!(self.stderr_str(), expected, "guest stderr differs\n{self}");
+        self
+    }
+}
+
+impl fmt::Display for GuestOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "exit code {:?} after {} instructions",
+            self.exit_code, self.instructions
+        )?;
+        writeln!(f, "--- stdout ---\n{}", self.stdout_str())?;
+        write!(f, "--- stderr ---\n{}", self.stderr_str())
+    }
+}
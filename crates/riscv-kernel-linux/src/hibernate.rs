@@ -0,0 +1,254 @@
+use std::{any::Any, collections::BTreeMap};
+
+use crate::{
+    vfs::{EventFd, FdTable, FileLike, MemFile, Node, OpenFile, Stdio},
+    MockLinux,
+};
+
+/// Version of the state [`MockLinux::save`] writes.
+const STATE_VERSION: u32 = 1;
+
+// How each open file is brought back
+const STDIO: u8 = 0;
+const MEM_FILE: u8 = 1;
+const EVENT_FD: u8 = 2;
+const REOPEN: u8 = 3;
+
+/// Writes the little-endian fields of a saved kernel state.
+#[derive(Default)]
+pub(crate) struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    /// `bytes`, after their length as a `u32`.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend(bytes);
+    }
+
+    pub fn str(&mut self, s: &str) {
+        self.bytes(s.as_bytes());
+    }
+}
+
+/// Reads back what a [`StateWriter`] wrote.
+pub(crate) struct StateReader<'a>(&'a [u8]);
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < len {
+            return Err("truncated kernel state".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| "bad string in kernel state".to_string())
+    }
+}
+
+impl MockLinux {
+    /// The guest's side of the kernel, for [`Kernel::save_state`]. `None`
+    /// if something the guest holds can't be saved.
+    ///
+    /// [`Kernel::save_state`]: riscv_vm::machine::Kernel::save_state
+    pub(crate) fn save(&self) -> Option<Vec<u8>> {
+        // An exited guest has nothing to resume, and shared memory lives on
+        // the host, outside the image
+        if self.exit.is_some() || !self.shared_regions.is_empty() || !self.shm.is_empty() {
+            return None;
+        }
+
+        let mut w = StateWriter::default();
+        w.u32(STATE_VERSION);
+        w.u32(self.brk_start);
+        w.str(&self.cwd);
+        self.clock.save(&mut w);
+        self.entropy.save(&mut w);
+        for count in [
+            self.usage.syscalls,
+            self.usage.bytes_read,
+            self.usage.bytes_written,
+            self.fs_usage.bytes_read,
+            self.fs_usage.bytes_written,
+            self.fs_usage.files_created,
+            self.peak_rss_pages,
+            self.gas_used,
+        ] {
+            w.u64(count);
+        }
+        w.bytes(&self.stderr_tail.iter().copied().collect::<Vec<_>>());
+        self.attrs.save(&mut w);
+        self.creds.save(&mut w);
+        let mounted = self.vfs.save(&mut w);
+
+        // Files open on nodes no longer in a filesystem, e.g. unlinked, keep
+        // them alive, so they're saved with the files
+        let mut files = StateWriter::default();
+        let mut orphans = BTreeMap::new();
+        files.u32(self.files.len() as u32);
+        for (fd, open) in self.files.iter() {
+            files.u32(fd as u32);
+            files.str(&open.path);
+            files.u32(open.flags);
+            files.u64(open.dir_pos as u64);
+            let file: &dyn Any = open.file.as_ref();
+            if let Some(stdio) = file.downcast_ref::<Stdio>() {
+                files.u8(STDIO);
+                files.u32(stdio.stream() as u32);
+            } else if let Some(file) = file.downcast_ref::<MemFile>() {
+                files.u8(MEM_FILE);
+                file.save(&mut files);
+                if !mounted.contains(&file.node().ino) {
+                    orphans.insert(file.node().ino, file.node());
+                }
+            } else if let Some(event) = file.downcast_ref::<EventFd>() {
+                files.u8(EVENT_FD);
+                event.save(&mut files);
+            } else if let Some(pos) = open.file.reopen_at() {
+                files.u8(REOPEN);
+                files.u64(pos);
+            } else {
+                tracing::debug!("save_state: fd {fd} ({}) can't be saved", open.path);
+                return None;
+            }
+        }
+        w.u32(orphans.len() as u32);
+        for node in orphans.values() {
+            node.save(&mut w);
+        }
+        w.0.extend(files.0);
+
+        Some(w.0)
+    }
+
+    /// Restore what [`MockLinux::save`] saved, for
+    /// [`Kernel::restore_state`].
+    ///
+    /// [`Kernel::restore_state`]: riscv_vm::machine::Kernel::restore_state
+    pub(crate) fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        let mut r = StateReader(state);
+        let version = r.u32()?;
+        if version != STATE_VERSION {
+            return Err(format!("unknown kernel state version {version}"));
+        }
+        self.brk_start = r.u32()?;
+        self.cwd = r.string()?;
+        self.clock.restore(&mut r)?;
+        self.entropy.restore(&mut r)?;
+        for count in [
+            &mut self.usage.syscalls,
+            &mut self.usage.bytes_read,
+            &mut self.usage.bytes_written,
+            &mut self.fs_usage.bytes_read,
+            &mut self.fs_usage.bytes_written,
+            &mut self.fs_usage.files_created,
+            &mut self.peak_rss_pages,
+            &mut self.gas_used,
+        ] {
+            *count = r.u64()?;
+        }
+        self.stderr_tail = r.bytes()?.iter().copied().collect();
+        self.attrs.restore(&mut r)?;
+        self.creds.restore(&mut r)?;
+
+        let mut nodes = self.vfs.restore(&mut r)?;
+        for _ in 0..r.u32()? {
+            let node = Node::restore(&mut r)?;
+            nodes.insert(node.ino, node);
+        }
+
+        // Stdio stays as the embedder set it up on this side
+        let mut stdio = BTreeMap::new();
+        for (_, open) in self.files.iter() {
+            let file: &dyn Any = open.file.as_ref();
+            if let Some(file) = file.downcast_ref::<Stdio>() {
+                stdio.entry(file.stream()).or_insert_with(|| file.clone());
+            }
+        }
+        let mut files = FdTable::default();
+        for _ in 0..r.u32()? {
+            let fd = r.u32()? as i32;
+            let path = r.string()?;
+            let flags = r.u32()?;
+            let dir_pos = r.u64()? as usize;
+            let file: Box<dyn FileLike> = match r.u8()? {
+                STDIO => {
+                    let stream = r.u32()? as i32;
+                    let file = stdio
+                        .get(&stream)
+                        .ok_or_else(|| format!("fd {fd}: no stdio stream {stream}"))?;
+                    Box::new(file.clone())
+                }
+                MEM_FILE => Box::new(MemFile::restore(&mut r, &nodes)?),
+                EVENT_FD => Box::new(EventFd::restore(&mut r)?),
+                REOPEN => {
+                    let pos = r.u64()?;
+                    let reopen = flags
+                        & !(libc_riscv32::O_CREAT | libc_riscv32::O_EXCL | libc_riscv32::O_TRUNC);
+                    let mut file = self.vfs.open(&path, reopen, 0).map_err(|errno| {
+                        let name = libc_riscv32::errno_name(errno).unwrap_or("unknown error");
+                        format!("fd {fd}: reopening {path}: {name}")
+                    })?;
+                    if pos != 0 {
+                        file.seek(std::io::SeekFrom::Start(pos))
+                            .map_err(|_| format!("fd {fd}: seeking {path} to {pos}"))?;
+                    }
+                    file
+                }
+                kind => return Err(format!("fd {fd}: unknown kind of file {kind}")),
+            };
+            files.insert_at(
+                fd,
+                OpenFile {
+                    file,
+                    path,
+                    flags,
+                    dir_pos,
+                },
+            );
+        }
+        if !r.0.is_empty() {
+            return Err("trailing data after the kernel state".to_string());
+        }
+        self.files = files;
+        self.unix_names.clear();
+
+        Ok(())
+    }
+}
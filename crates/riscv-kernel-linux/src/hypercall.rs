@@ -0,0 +1,72 @@
+use riscv_vm::{hart::Hart32, machine::StepResult, memory::Memory};
+
+use crate::{errno, errno::KernelResult, table, MockLinux};
+
+/// The first of the VM's own calls, which the guest makes with `ecall` like
+/// a syscall, with a number in `a7` above any Linux syscall's.
+pub const HYPERCALL_BASE: u32 = 0x1_0000;
+
+/// `fetch(url, url_len, request, request_len, response, response_cap)`, see
+/// [`FetchService`](crate::FetchService).
+pub const HYPERCALL_FETCH: u32 = HYPERCALL_BASE;
+
+/// `kv_get(key, key_len, value, value_cap)`, see [`KvService`](crate::KvService).
+pub const HYPERCALL_KV_GET: u32 = HYPERCALL_BASE + 1;
+
+/// `kv_put(key, key_len, value, value_len)`
+pub const HYPERCALL_KV_PUT: u32 = HYPERCALL_BASE + 2;
+
+/// `kv_delete(key, key_len)`
+pub const HYPERCALL_KV_DELETE: u32 = HYPERCALL_BASE + 3;
+
+/// `kv_next(key, key_len, next_key, next_key_cap)`
+pub const HYPERCALL_KV_NEXT: u32 = HYPERCALL_BASE + 4;
+
+impl MockLinux {
+    /// Gas charged by hypercalls since the guest was loaded or reset.
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// Charge `gas`, or fail with `EDQUOT`, charging nothing, if that would
+    /// go over [`MockLinux::gas_limit`].
+    pub(crate) fn charge_gas(&mut self, gas: u64) -> KernelResult<()> {
+        let used = self.gas_used.saturating_add(gas);
+        if self.gas_limit.is_some_and(|limit| used > limit) {
+            return Err(libc_riscv32::EDQUOT);
+        }
+        self.gas_used = used;
+
+        Ok(())
+    }
+
+    /// Run hypercall `nr`, or return `None` if there's no such call.
+    pub(crate) fn hypercall(
+        &mut self,
+        hart: &mut Hart32,
+        mem: &mut Memory,
+        nr: usize,
+    ) -> Option<StepResult> {
+        let (name, argc, ret) = match u32::try_from(nr).ok()? {
+            HYPERCALL_FETCH => ("fetch", 6, self.fetch(mem, hart.args())),
+            HYPERCALL_KV_GET => ("kv_get", 4, self.kv_get(mem, hart.args())),
+            HYPERCALL_KV_PUT => ("kv_put", 4, self.kv_put(mem, hart.args())),
+            HYPERCALL_KV_DELETE => ("kv_delete", 2, self.kv_delete(mem, hart.args())),
+            HYPERCALL_KV_NEXT => ("kv_next", 4, self.kv_next(mem, hart.args())),
+            _ => return None,
+        };
+        let args: [u32; 6] = hart.args();
+        self.usage.syscalls += 1;
+
+        let args: Vec<String> = args[..argc].iter().map(|arg| format!("{arg:#x}")).collect();
+        let trace = format!("{name}({}) = {}", args.join(", "), table::strace_ret(&ret));
+        if self.strace {
+            eprintln!("{trace}");
+        } else {
+            tracing::debug!("{trace}");
+        }
+        hart.set_return(errno::return_value(ret));
+
+        Some(StepResult::Ok)
+    }
+}
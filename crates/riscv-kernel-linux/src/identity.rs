@@ -0,0 +1,358 @@
+use riscv_vm::memory::Memory;
+
+use crate::{
+    errno::KernelResult,
+    hibernate::{StateReader, StateWriter},
+    vfs::MemFs,
+    MockLinux,
+};
+
+/// `NGROUPS_MAX`, the most supplementary groups a process can have.
+const NGROUPS_MAX: u32 = 65536;
+
+/// `(uid_t)-1`, leaving an id unchanged.
+const UNCHANGED: u32 = u32::MAX;
+
+/// Who the guest runs as.
+///
+/// Programs look themselves up at startup, by `getuid` and then in
+/// `/etc/passwd`, so the kernel reports the same user in both. The default
+/// is root, as in a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub uid: u32,
+    pub gid: u32,
+    /// Login name, in `/etc/passwd`
+    pub user: String,
+    /// Name of the primary group, in `/etc/group`
+    pub group: String,
+    pub home: String,
+    pub shell: String,
+    /// File mode bits cleared from new files, see `umask(2)`
+    pub umask: u32,
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Self {
+            uid: 0,
+            gid: 0,
+            user: "root".to_string(),
+            group: "root".to_string(),
+            home: "/root".to_string(),
+            shell: "/bin/sh".to_string(),
+            umask: 0o022,
+        }
+    }
+}
+
+impl Identity {
+    /// An unprivileged user `name`, in a group of the same name, with
+    /// `/home/<name>` as its home.
+    pub fn user(name: impl Into<String>, uid: u32, gid: u32) -> Self {
+        let name = name.into();
+        Self {
+            uid,
+            gid,
+            home: format!("/home/{name}"),
+            group: name.clone(),
+            user: name,
+            ..Self::default()
+        }
+    }
+
+    /// The contents of `/etc/passwd`.
+    fn passwd(&self) -> String {
+        let mut passwd = String::new();
+        if self.uid != 0 {
+            passwd += "root:x:0:0:root:/root:/bin/sh\n";
+        }
+        passwd += &format!(
+            "{user}:x:{}:{}:{user}:{}:{}\n",
+            self.uid,
+            self.gid,
+            self.home,
+            self.shell,
+            user = self.user
+        );
+        passwd + "nobody:x:65534:65534:nobody:/:/sbin/nologin\n"
+    }
+
+    /// The contents of `/etc/group`.
+    fn group(&self) -> String {
+        let mut group = String::new();
+        if self.gid != 0 {
+            group += "root:x:0:\n";
+        }
+        group += &format!("{}:x:{}:{}\n", self.group, self.gid, self.user);
+        group + "nobody:x:65534:\n"
+    }
+
+    /// The guest's default `/etc`, describing this user.
+    pub(crate) fn etc(&self) -> MemFs {
+        let mut etc = MemFs::new();
+        etc.add_file("passwd", 0o644, self.passwd().into_bytes());
+        etc.add_file("group", 0o644, self.group().into_bytes());
+        etc
+    }
+}
+
+/// A real, effective and saved user or group id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ids {
+    real: u32,
+    effective: u32,
+    saved: u32,
+}
+
+impl Ids {
+    fn new(id: u32) -> Self {
+        Self {
+            real: id,
+            effective: id,
+            saved: id,
+        }
+    }
+
+    fn contains(&self, id: u32) -> bool {
+        [self.real, self.effective, self.saved].contains(&id)
+    }
+
+    /// `setuid`: root sets all three, others only the effective id, to
+    /// their real or saved one.
+    fn set(&mut self, privileged: bool, id: u32) -> KernelResult {
+        if privileged {
+            *self = Self::new(id);
+        } else if id == self.real || id == self.saved {
+            self.effective = id;
+        } else {
+            return Err(libc_riscv32::EPERM);
+        }
+
+        Ok(0)
+    }
+
+    /// `setreuid`, which also saves the new effective id when the real one
+    /// is set or the effective one changes away from it.
+    fn set_re(&mut self, privileged: bool, real: u32, effective: u32) -> KernelResult {
+        let mut ids = *self;
+        if real != UNCHANGED {
+            if !privileged && real != self.real && real != self.effective {
+                return Err(libc_riscv32::EPERM);
+            }
+            ids.real = real;
+        }
+        if effective != UNCHANGED {
+            if !privileged && !self.contains(effective) {
+                return Err(libc_riscv32::EPERM);
+            }
+            ids.effective = effective;
+        }
+        if real != UNCHANGED || (effective != UNCHANGED && effective != self.real) {
+            ids.saved = ids.effective;
+        }
+        *self = ids;
+
+        Ok(0)
+    }
+
+    /// `setresuid`: unprivileged callers can only pick among their ids.
+    fn set_res(&mut self, privileged: bool, real: u32, effective: u32, saved: u32) -> KernelResult {
+        let ids = [real, effective, saved];
+        if !privileged && ids.iter().any(|&id| id != UNCHANGED && !self.contains(id)) {
+            return Err(libc_riscv32::EPERM);
+        }
+        for (current, id) in [&mut self.real, &mut self.effective, &mut self.saved]
+            .into_iter()
+            .zip(ids)
+        {
+            if id != UNCHANGED {
+                *current = id;
+            }
+        }
+
+        Ok(0)
+    }
+
+    /// Write the ids to the guest pointers `real`, `effective` and `saved`.
+    fn write(&self, mem: &mut Memory, real: u32, effective: u32, saved: u32) -> KernelResult {
+        for (addr, id) in [
+            (real, self.real),
+            (effective, self.effective),
+            (saved, self.saved),
+        ] {
+            mem.copy_to(addr, &[id]).map_err(|_| libc_riscv32::EFAULT)?;
+        }
+
+        Ok(0)
+    }
+}
+
+/// The process' credentials, starting as [`MockLinux::identity`] says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Credentials {
+    uid: Ids,
+    gid: Ids,
+    /// Supplementary groups
+    groups: Vec<u32>,
+    pub umask: u32,
+}
+
+impl Credentials {
+    pub fn new(identity: &Identity) -> Self {
+        Self {
+            uid: Ids::new(identity.uid),
+            gid: Ids::new(identity.gid),
+            groups: vec![],
+            umask: identity.umask & 0o777,
+        }
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        for ids in [self.uid, self.gid] {
+            w.u32(ids.real);
+            w.u32(ids.effective);
+            w.u32(ids.saved);
+        }
+        w.u32(self.groups.len() as u32);
+        for &group in &self.groups {
+            w.u32(group);
+        }
+        w.u32(self.umask);
+    }
+
+    pub fn restore(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for ids in [&mut self.uid, &mut self.gid] {
+            ids.real = r.u32()?;
+            ids.effective = r.u32()?;
+            ids.saved = r.u32()?;
+        }
+        self.groups = (0..r.u32()?).map(|_| r.u32()).collect::<Result<_, _>>()?;
+        self.umask = r.u32()?;
+
+        Ok(())
+    }
+
+    /// Whether the process has `CAP_SETUID` and `CAP_SETGID`, which only
+    /// root does.
+    fn privileged(&self) -> bool {
+        self.uid.effective == 0
+    }
+}
+
+impl MockLinux {
+    /// Who the guest runs as.
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    /// Run the guest as `identity`, describing it in `/etc`. Call before
+    /// mounting a root filesystem, whose `/etc`, if it has one, replaces it.
+    pub(crate) fn set_identity(&mut self, identity: Identity) {
+        self.creds = Credentials::new(&identity);
+        self.identity = identity;
+        self.mount_etc();
+    }
+
+    pub(crate) fn getuid(&mut self) -> KernelResult {
+        Ok(self.creds.uid.real)
+    }
+
+    pub(crate) fn geteuid(&mut self) -> KernelResult {
+        Ok(self.creds.uid.effective)
+    }
+
+    pub(crate) fn getgid(&mut self) -> KernelResult {
+        Ok(self.creds.gid.real)
+    }
+
+    pub(crate) fn getegid(&mut self) -> KernelResult {
+        Ok(self.creds.gid.effective)
+    }
+
+    pub(crate) fn setuid(&mut self, uid: u32) -> KernelResult {
+        let privileged = self.creds.privileged();
+        self.creds.uid.set(privileged, uid)
+    }
+
+    pub(crate) fn setgid(&mut self, gid: u32) -> KernelResult {
+        let privileged = self.creds.privileged();
+        self.creds.gid.set(privileged, gid)
+    }
+
+    pub(crate) fn setreuid(&mut self, ruid: u32, euid: u32) -> KernelResult {
+        let privileged = self.creds.privileged();
+        self.creds.uid.set_re(privileged, ruid, euid)
+    }
+
+    pub(crate) fn setregid(&mut self, rgid: u32, egid: u32) -> KernelResult {
+        let privileged = self.creds.privileged();
+        self.creds.gid.set_re(privileged, rgid, egid)
+    }
+
+    pub(crate) fn setresuid(&mut self, ruid: u32, euid: u32, suid: u32) -> KernelResult {
+        let privileged = self.creds.privileged();
+        self.creds.uid.set_res(privileged, ruid, euid, suid)
+    }
+
+    pub(crate) fn setresgid(&mut self, rgid: u32, egid: u32, sgid: u32) -> KernelResult {
+        let privileged = self.creds.privileged();
+        self.creds.gid.set_res(privileged, rgid, egid, sgid)
+    }
+
+    pub(crate) fn getresuid(
+        &mut self,
+        mem: &mut Memory,
+        ruid: u32,
+        euid: u32,
+        suid: u32,
+    ) -> KernelResult {
+        self.creds.uid.write(mem, ruid, euid, suid)
+    }
+
+    pub(crate) fn getresgid(
+        &mut self,
+        mem: &mut Memory,
+        rgid: u32,
+        egid: u32,
+        sgid: u32,
+    ) -> KernelResult {
+        self.creds.gid.write(mem, rgid, egid, sgid)
+    }
+
+    pub(crate) fn getgroups(&mut self, mem: &mut Memory, size: u32, list: u32) -> KernelResult {
+        let groups = &self.creds.groups;
+        if size == 0 {
+            return Ok(groups.len() as u32);
+        }
+        if (size as usize) < groups.len() {
+            return Err(libc_riscv32::EINVAL);
+        }
+        mem.copy_to(list, groups)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(groups.len() as u32)
+    }
+
+    pub(crate) fn setgroups(&mut self, mem: &mut Memory, size: u32, list: u32) -> KernelResult {
+        if !self.creds.privileged() {
+            return Err(libc_riscv32::EPERM);
+        }
+        if size > NGROUPS_MAX {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let groups = mem
+            .slice::<u32>(list, size)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        self.creds.groups = groups.to_vec();
+
+        Ok(0)
+    }
+
+    pub(crate) fn umask(&mut self, mask: u32) -> KernelResult {
+        let old = self.creds.umask;
+        self.creds.umask = mask & 0o777;
+
+        Ok(old)
+    }
+}
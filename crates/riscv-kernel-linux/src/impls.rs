@@ -1,71 +1,109 @@
-use std::ffi::{CStr, CString};
-
-use riscv_vm::memory::Memory;
-
-use crate::MockLinux;
-
-impl MockLinux {
-    pub(crate) fn ioctl(&mut self, _fd: i32, _request: u32) -> Result<u32, i32> {
-        // just return 0 for now
-        Ok(0)
+use std::ffi::CString;
+
+use riscv_vm::{
+    marshal::{GuestSlice, GuestValue},
+    memory::Memory,
+    protect::PagePerms,
+    shm::SharedMemory,
+};
+
+use crate::{
+    errno::{host_errno, KernelResult},
+    poll::read_timespec,
+    vfs::FdTable,
+    MockLinux, SharedRegion, PAGE_SIZE,
+};
+
+/// Bytes of stderr kept by [`MockLinux::record_stderr`].
+const STDERR_TAIL: usize = 4096;
+
+/// Read an `iovec` array of `iovcnt` entries, which like Linux must be at
+/// most [`IOV_MAX`](libc_riscv32::IOV_MAX), so the guest can't make the
+/// host allocate much for it.
+pub(crate) fn read_iovecs(mem: &Memory, iov: u32, iovcnt: i32) -> KernelResult<Vec<GuestSlice>> {
+    let iovcnt = u32::try_from(iovcnt).map_err(|_| libc_riscv32::EINVAL)?;
+    if iovcnt > libc_riscv32::IOV_MAX {
+        return Err(libc_riscv32::EINVAL);
     }
+    mem.read_guest_slices(iov, iovcnt)
+        .map_err(|_| libc_riscv32::EFAULT)
+}
 
-    pub(crate) fn write(
+impl MockLinux {
+    pub(crate) fn ioctl(
         &mut self,
-        mem: &Memory,
+        mem: &mut Memory,
         fd: i32,
-        buf: u32,
-        count: u32,
-    ) -> Result<u32, i32> {
+        request: u32,
+        arg: u32,
+    ) -> KernelResult {
+        let file = self.files.get(fd)?;
+        match request {
+            libc_riscv32::TCGETS | libc_riscv32::TIOCGWINSZ if !file.file.is_tty() => {
+                Err(libc_riscv32::ENOTTY)
+            }
+            libc_riscv32::TIOCGWINSZ => {
+                // struct winsize { rows, cols, xpixel, ypixel }
+                mem.write(arg, [24u16, 80, 0, 0])
+                    .map_err(|_| libc_riscv32::EFAULT)?;
+                Ok(0)
+            }
+            // just return 0 for now
+            _ => Ok(0),
+        }
+    }
+
+    pub(crate) fn write(&mut self, mem: &Memory, fd: i32, buf: u32, count: u32) -> KernelResult {
         let slice = mem.slice(buf, count).map_err(|_| {
             tracing::warn!("write: buffer read would overflow guest memory");
             libc_riscv32::EFAULT
         })?;
 
-        match fd {
-            1 => {
-                if self.passthrough_stdio {
-                    print!("{}", String::from_utf8_lossy(slice)); // stdout
-                }
-                Ok(count)
-            }
-            2 => {
-                if self.passthrough_stdio {
-                    eprint!("{}", String::from_utf8_lossy(slice)); // stderr
-                }
-                Ok(count)
-            }
-            _ => {
-                tracing::warn!("write: fd {fd} not supported");
-                Err(libc_riscv32::EBADF)
-            }
+        let regular = self.is_regular_file(fd);
+        let slice = if regular {
+            &slice[..self.writable_bytes(slice.len())?]
+        } else {
+            slice
+        };
+        let file = self.files.get(fd)?;
+        if file.flags & libc_riscv32::O_ACCMODE == libc_riscv32::O_RDONLY {
+            return Err(libc_riscv32::EBADF);
         }
-    }
 
-    pub(crate) fn writev(
-        &mut self,
-        mem: &Memory,
-        fd: i32,
-        iov: u32,
-        iovcnt: i32,
-    ) -> Result<u32, i32> {
-        #[repr(C)]
-        struct Iovec {
-            iov_base: u32,
-            iov_len: u32,
+        let n = file.file.write(slice)?;
+        if regular {
+            self.fs_usage.bytes_written += n as u64;
         }
-        if iovcnt < 0 {
-            return Err(libc_riscv32::EINVAL);
+        if fd == 2 {
+            self.record_stderr(&slice[..n]);
         }
 
-        let iovs = mem
-            .slice::<Iovec>(iov, iovcnt as u32)
-            .map_err(|_| libc_riscv32::EFAULT)?;
+        Ok(n as u32)
+    }
 
-        let total = iovs.iter().try_fold(0u32, |total, iov| {
-            let count = self.write(mem, fd, iov.iov_base, iov.iov_len)?;
-            total.checked_add(count).ok_or(libc_riscv32::EINVAL)
-        })?;
+    /// Keep the last [`STDERR_TAIL`] bytes written to stderr, for panic messages.
+    fn record_stderr(&mut self, bytes: &[u8]) {
+        self.stderr_tail.extend(bytes);
+        let excess = self.stderr_tail.len().saturating_sub(STDERR_TAIL);
+        self.stderr_tail.drain(..excess);
+    }
+
+    pub(crate) fn writev(&mut self, mem: &Memory, fd: i32, iov: u32, iovcnt: i32) -> KernelResult {
+        let iovs = read_iovecs(mem, iov, iovcnt)?;
+
+        let mut total = 0u32;
+        for iov in iovs {
+            // Like the kernel, report what was written before a short write
+            // or an error, such as running out of quota
+            let count = match self.write(mem, fd, iov.ptr, iov.len) {
+                Err(_) if total > 0 => break,
+                count => count?,
+            };
+            total = total.checked_add(count).ok_or(libc_riscv32::EINVAL)?;
+            if count < iov.len {
+                break;
+            }
+        }
 
         Ok(total)
     }
@@ -77,15 +115,12 @@ impl MockLinux {
         pathname: u32,
         buf: u32,
         bufsiz: usize,
-    ) -> Result<u32, i32> {
+    ) -> KernelResult {
         let pathname = mem
-            .bytes_null_terminated(pathname, None)
-            .map_err(|_| libc_riscv32::EINVAL)?;
-        let pathname = CStr::from_bytes_with_nul(pathname)
-            .map(|s| s.to_str().unwrap_or(""))
-            .map_err(|_| libc_riscv32::EINVAL)?;
+            .read_string(pathname, libc_riscv32::PATH_MAX)
+            .map_err(|_| libc_riscv32::EFAULT)?;
 
-        match pathname {
+        match pathname.as_str() {
             "/proc/self/exe" => {
                 let fake_path = "/tmp/main".as_bytes();
 
@@ -104,18 +139,18 @@ impl MockLinux {
         }
     }
 
-    pub(crate) fn gettid(&mut self) -> Result<u32, i32> {
+    pub(crate) fn gettid(&mut self) -> KernelResult {
         Ok(0x1)
     }
 
-    pub(crate) fn set_tid_address(&mut self, mem: &mut Memory, tidptr: u32) -> Result<u32, i32> {
+    pub(crate) fn set_tid_address(&mut self, mem: &mut Memory, tidptr: u32) -> KernelResult {
         let tid = self.gettid()?;
         mem.store(tidptr, tid);
 
         Ok(tid)
     }
 
-    pub(crate) fn getpid(&mut self) -> Result<u32, i32> {
+    pub(crate) fn getpid(&mut self) -> KernelResult {
         Ok(0x1)
     }
 
@@ -129,7 +164,7 @@ impl MockLinux {
         _utime: u32,
         _uaddr2: u32,
         val3: u32,
-    ) -> Result<u32, i32> {
+    ) -> KernelResult {
         let futex_word = mem.load::<u32>(uaddr);
         tracing::trace!(
         "futex: uaddr={:x}({futex_word:x}) op={op} val={val} utime={:x} uaddr2={:x} val3={val3:x}",
@@ -183,11 +218,11 @@ impl MockLinux {
         _mem: &mut Memory,
         _head: u32,
         _len: u32,
-    ) -> Result<u32, i32> {
+    ) -> KernelResult {
         Ok(0)
     }
 
-    pub fn tgkill(&mut self, _tgid: i32, _pid: i32, _sig: i32) -> Result<u32, i32> {
+    pub fn tgkill(&mut self, _tgid: i32, _pid: i32, _sig: i32) -> KernelResult {
         Ok(0)
     }
 
@@ -198,7 +233,7 @@ impl MockLinux {
         _act: u32,
         _oldact: u32,
         _sigsetsize: u32,
-    ) -> Result<u32, i32> {
+    ) -> KernelResult {
         // stubbed out -- this is called w/ SIGPIPE during rust runtime startup
         Ok(0)
     }
@@ -210,7 +245,7 @@ impl MockLinux {
         _set: u32,
         oldset: u32,
         sigsetsize: u32,
-    ) -> Result<u32, i32> {
+    ) -> KernelResult {
         if oldset != 0 {
             mem.memset(oldset, 0, sigsetsize)
                 .map_err(|_| libc_riscv32::EFAULT)?;
@@ -219,7 +254,31 @@ impl MockLinux {
         Ok(0)
     }
 
-    pub(crate) fn brk(&mut self, mem: &mut Memory, addr: u32) -> Result<u32, i32> {
+    pub(crate) fn rt_sigtimedwait_time64(
+        &mut self,
+        mem: &Memory,
+        _set: u32,
+        _info: u32,
+        timeout: u32,
+        sigsetsize: u32,
+    ) -> KernelResult {
+        if sigsetsize != 8 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        // No signal is ever pending, so this only waits out the timeout
+        match timeout {
+            0 => tracing::warn!("rt_sigtimedwait: no signal can arrive, returning early"),
+            tsp => {
+                let ns = read_timespec(mem, tsp)?;
+                let deadline = self.now.get().monotonic_ns.saturating_add(ns);
+                self.sleep_until(deadline);
+            }
+        }
+
+        Err(libc_riscv32::EAGAIN)
+    }
+
+    pub(crate) fn brk(&mut self, mem: &mut Memory, addr: u32) -> KernelResult {
         // TODO: Move brk / mmap_top to be managed by Kernel struct.
         // TODO: OOM detection/handling
         let old_brk = mem.brk;
@@ -244,6 +303,14 @@ impl MockLinux {
             tracing::warn!("brk: failed to zero memory");
             libc_riscv32::ENOMEM
         })?;
+        // Pages wholly given back are poisoned, and pages taken mapped again
+        if new_brk < old_brk {
+            let released = new_brk.next_multiple_of(PAGE_SIZE);
+            mem.poison_range(released, old_brk.saturating_sub(released))
+        } else {
+            mem.protect(base, size, PagePerms::DATA)
+        }
+        .map_err(|_| libc_riscv32::ENOMEM)?;
 
         // brk returns the new program break on success
         Ok(new_brk)
@@ -256,20 +323,30 @@ impl MockLinux {
         mem: &mut Memory,
         addr: u32,
         len: u32,
-        _prot: u32,
-        _flags: u32,
+        prot: u32,
+        flags: u32,
         fd: i32,
-        _offset: u32,
-    ) -> Result<u32, i32> {
+        pgoff: u32,
+    ) -> KernelResult {
         tracing::trace!(
-            "mmap: addr={addr:#x} len={len:#x} prot={_prot:#x} flags={_flags:#x} fd={fd} offset={_offset:#x}"
+            "mmap: addr={addr:#x} len={len:#x} prot={prot:#x} flags={flags:#x} fd={fd} pgoff={pgoff:#x}"
         );
 
         let size = (len + 0xFFF) & !0xFFF;
-        if fd != -1 || size == 0 {
-            tracing::warn!("mmap: invalid fd or size");
+        if size == 0 {
+            tracing::warn!("mmap: invalid size");
             return Err(libc_riscv32::MAP_FAILED);
         }
+        // Files backed by memfd or shm objects can be shared, any other
+        // readable file only mapped privately
+        let backing = if flags & libc_riscv32::MAP_ANONYMOUS != 0 {
+            Backing::Anonymous
+        } else {
+            match self.files.get(fd)?.file.shared_memory() {
+                Some(shm) => Backing::Shm(shm.clone()),
+                None => Backing::File,
+            }
+        };
 
         // Align up to page size
         let map_addr = if addr != 0 {
@@ -285,16 +362,60 @@ impl MockLinux {
             return Err(libc_riscv32::MAP_FAILED);
         }
 
+        let shared = flags & libc_riscv32::MAP_TYPE == libc_riscv32::MAP_SHARED;
+        let offset = pgoff as u64 * PAGE_SIZE as u64;
+        match backing {
+            Backing::Shm(shm) if shared => {
+                let region = SharedRegion {
+                    shm,
+                    offset,
+                    len: size,
+                };
+                self.map_shared(mem, map_addr, region)?;
+            }
+            Backing::Anonymous if shared => {
+                let shm = SharedMemory::with_len("anon_shared", size as u64).map_err(host_errno)?;
+                let region = SharedRegion {
+                    shm,
+                    offset: 0,
+                    len: size,
+                };
+                self.map_shared(mem, map_addr, region)?;
+            }
+            Backing::File if shared => {
+                tracing::warn!("mmap: fd {fd} can't be mapped shared");
+                return Err(libc_riscv32::ENODEV);
+            }
+            backing => {
+                self.unmap_shared(mem, map_addr, size)?;
+                // Zero out the region
+                mem.memset(map_addr, 0, size).map_err(|_| {
+                    tracing::warn!("mmap: failed to zero memory");
+                    libc_riscv32::ENOMEM
+                })?;
+                // Private file mappings get a copy of the contents, and
+                // zeroes past the end of the file
+                let buf = mem
+                    .slice_mut::<u8>(map_addr, size)
+                    .map_err(|_| libc_riscv32::ENOMEM)?;
+                match backing {
+                    Backing::Anonymous => {}
+                    Backing::Shm(shm) => {
+                        shm.read_at(buf, offset).map_err(host_errno)?;
+                    }
+                    Backing::File => {
+                        self.read_at(fd, buf, offset)?;
+                    }
+                }
+            }
+        }
+
+        mem.protect(map_addr, size, prot_perms(prot))
+            .map_err(|_| libc_riscv32::EACCES)?;
         if addr == 0 {
             mem.mmap_top = map_addr;
         }
 
-        // Zero out the region
-        mem.memset(map_addr, 0, size).map_err(|_| {
-            tracing::warn!("mmap: failed to zero memory");
-            libc_riscv32::ENOMEM
-        })?;
-
         tracing::debug!("mmap: returning region at {map_addr:#x} of size {size:#x}");
 
         Ok(map_addr)
@@ -302,11 +423,17 @@ impl MockLinux {
 
     pub(crate) fn mprotect(
         &mut self,
-        _mem: &Memory,
-        _addr: u32,
-        _len: u32,
-        _prot: u32,
-    ) -> Result<u32, i32> {
+        mem: &mut Memory,
+        addr: u32,
+        len: u32,
+        prot: u32,
+    ) -> KernelResult {
+        if !addr.is_multiple_of(PAGE_SIZE) {
+            return Err(libc_riscv32::EINVAL);
+        }
+        mem.protect(addr, len, prot_perms(prot))
+            .map_err(|_| libc_riscv32::ENOMEM)?;
+
         Ok(0)
     }
 
@@ -315,104 +442,51 @@ impl MockLinux {
         mem: &mut Memory,
         resource: u32,
         rlim_ptr: u32,
-    ) -> Result<u32, i32> {
+    ) -> KernelResult {
         #[repr(C)]
+        #[derive(GuestValue)]
+        #[guest(size = 8)]
         struct RLimit {
             rlim_cur: u32,
             rlim_max: u32,
         }
 
         let rlim = match resource {
-            // RLIMIT_STACK = 3, 8MB of stack
-            3 => RLimit {
+            // 8MB of stack
+            libc_riscv32::RLIMIT_STACK => RLimit {
                 rlim_cur: 0x800000,
                 rlim_max: 0x800000,
             },
+            libc_riscv32::RLIMIT_NOFILE => RLimit {
+                rlim_cur: FdTable::LIMIT as u32,
+                rlim_max: FdTable::LIMIT as u32,
+            },
             // For other resources, return "unlimited"
             _ => RLimit {
                 rlim_cur: libc_riscv32::RLIM_INFINITY,
                 rlim_max: libc_riscv32::RLIM_INFINITY,
             },
         };
-        mem.copy_to(rlim_ptr, &[rlim])
+        mem.write(rlim_ptr, rlim)
             .map_err(|_| libc_riscv32::EFAULT)?;
 
         Ok(0)
     }
+}
 
-    pub(crate) fn riscv_hwprobe(
-        &mut self,
-        _mem: &Memory,
-        _pairs: u32,
-        _pair_count: u32,
-        _cpusetsize: u32,
-        _cpus: u32,
-        _flags: u32,
-    ) -> Result<u32, i32> {
-        Err(libc_riscv32::ENOSYS)
-    }
-
-    pub(crate) fn getrandom(
-        &mut self,
-        mem: &mut Memory,
-        buf: u32,
-        len: u32,
-        _flags: u32,
-    ) -> Result<u32, i32> {
-        // TODO: Stubbed to zero buffer
-        mem.memset(buf, 0, len).map_err(|_| libc_riscv32::EFAULT)?;
-        // let slice = mem
-        //     .slice::<u8>(buf, len)
-        //     .map_err(|_| libc_riscv32::EFAULT)?;
-
-        // let mut rng = rand::thread_rng();
-        // for byte in slice {
-        //     *byte = rng.gen();
-        // }
-
-        Ok(len)
-    }
-
-    pub(crate) fn statx(
-        &mut self,
-        _mem: &Memory,
-        _dirfd: i32,
-        _pathname: u32,
-        _flags: u32,
-        _mask: u32,
-        _statxbuf: u32,
-    ) -> Result<u32, i32> {
-        // stubbed out
-        Ok(0)
+/// What pages mapped with `prot` may be used for. Reads are always allowed.
+pub(crate) fn prot_perms(prot: u32) -> PagePerms {
+    PagePerms {
+        write: prot & libc_riscv32::PROT_WRITE != 0,
+        exec: prot & libc_riscv32::PROT_EXEC != 0,
     }
+}
 
-    pub(crate) fn ppoll_time64(
-        &mut self,
-        mem: &Memory,
-        fds: u32,
-        nfds: u32,
-        _tsp: u32,
-        _sigmask: u32,
-        _sigsetsize: u32,
-    ) -> Result<u32, i32> {
-        #[repr(C)]
-        struct PollFd {
-            fd: i32,
-            events: i16,
-            revents: i16,
-        }
-        // This method is called during CRT init for stdin/out/err
-        let fds = mem
-            .slice::<PollFd>(fds, nfds)
-            .map_err(|_| libc_riscv32::EFAULT)?;
-        for fd in fds {
-            if fd.fd > 2 {
-                // We don't support any other file descriptors
-                return Err(libc_riscv32::ENOSYS);
-            }
-        }
-
-        // We can just return 0, as stdin/out/err are always ready
-        Ok(0)
-    }
+/// What an `mmap` maps.
+enum Backing {
+    Anonymous,
+    /// A memfd or shm object, which can be mapped shared
+    Shm(SharedMemory),
+    /// Any other file, which can only be mapped privately
+    File,
 }
@@ -0,0 +1,220 @@
+use std::{collections::BTreeMap, fmt, io, ops::Bound};
+
+use riscv_vm::memory::Memory;
+
+use crate::{
+    errno::{host_errno, KernelResult},
+    MockLinux,
+};
+
+/// Storage behind the guest's key-value hypercalls, with byte string keys
+/// kept in order.
+///
+/// Errors reach the guest as errnos. A `BTreeMap` is a store kept in memory.
+pub trait KvStore {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()>;
+
+    /// Remove `key`, returning whether it was there.
+    fn delete(&mut self, key: &[u8]) -> io::Result<bool>;
+
+    /// The first key after `key`, or the first key of all for `None`.
+    fn next_key(&self, key: Option<&[u8]>) -> io::Result<Option<Vec<u8>>>;
+}
+
+impl KvStore for BTreeMap<Vec<u8>, Vec<u8>> {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(BTreeMap::get(self, key).cloned())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> io::Result<bool> {
+        Ok(self.remove(key).is_some())
+    }
+
+    fn next_key(&self, key: Option<&[u8]>) -> io::Result<Option<Vec<u8>>> {
+        let start = key.map_or(Bound::Unbounded, Bound::Excluded);
+        let mut keys = self.range::<[u8], _>((start, Bound::Unbounded));
+        Ok(keys.next().map(|(key, _)| key.clone()))
+    }
+}
+
+/// What a [`KvService`] lets the guest store, and what it costs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KvPolicy {
+    /// Longest key, beyond which calls fail with `E2BIG`
+    pub max_key_bytes: u32,
+    /// Longest value, beyond which puts fail with `EFBIG`
+    pub max_value_bytes: u32,
+    /// Gas charged for each get and iteration step
+    pub gas_per_read: u64,
+    /// Gas charged for each put and delete
+    pub gas_per_write: u64,
+    /// Gas charged for each byte of key and value passed either way
+    pub gas_per_byte: u64,
+}
+
+impl Default for KvPolicy {
+    fn default() -> Self {
+        Self {
+            max_key_bytes: 256,
+            max_value_bytes: 64 << 10,
+            gas_per_read: 200,
+            gas_per_write: 5_000,
+            gas_per_byte: 1,
+        }
+    }
+}
+
+/// The host side of the guest's key-value hypercalls, for contract-style
+/// guests whose only state is what they store.
+///
+/// Each call takes a key in `a0, a1` = `key, key_len`:
+///
+/// ```text
+/// HYPERCALL_KV_GET     a2, a3 = value, value_cap
+/// HYPERCALL_KV_PUT     a2, a3 = value, value_len
+/// HYPERCALL_KV_DELETE
+/// HYPERCALL_KV_NEXT    a2, a3 = next_key, next_key_cap
+/// ```
+///
+/// Gets return the value's length and `KV_NEXT` the next key's, of which at
+/// most the cap is copied, or `-ENOENT` if there's none. To iterate, start
+/// `KV_NEXT` from a null key, which comes before even the empty key. Puts
+/// and deletes return 0, with deletes failing with `ENOENT` too.
+pub struct KvService {
+    pub policy: KvPolicy,
+    store: Box<dyn KvStore>,
+}
+
+impl KvService {
+    pub fn new(policy: KvPolicy, store: impl KvStore + 'static) -> Self {
+        Self {
+            policy,
+            store: Box::new(store),
+        }
+    }
+
+    pub fn store(&self) -> &dyn KvStore {
+        &*self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut dyn KvStore {
+        &mut *self.store
+    }
+}
+
+impl fmt::Debug for KvService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KvService")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The key at `addr`, with `None` for a null `addr`.
+fn read_key(mem: &Memory, policy: &KvPolicy, addr: u32, len: u32) -> KernelResult<Option<Vec<u8>>> {
+    if addr == 0 {
+        return Ok(None);
+    }
+    if len > policy.max_key_bytes {
+        return Err(libc_riscv32::E2BIG);
+    }
+    let key = mem
+        .slice::<u8>(addr, len)
+        .map_err(|_| libc_riscv32::EFAULT)?;
+    Ok(Some(key.to_vec()))
+}
+
+impl MockLinux {
+    /// The store behind the key-value hypercalls, if there is one.
+    pub fn kv(&mut self) -> Option<&mut KvService> {
+        self.kv.as_mut()
+    }
+
+    pub(crate) fn kv_get(&mut self, mem: &mut Memory, args: [u32; 4]) -> KernelResult {
+        let [key, key_len, value, value_cap] = args;
+        let policy = self.kv.as_ref().ok_or(libc_riscv32::ENOSYS)?.policy;
+        let key = read_key(mem, &policy, key, key_len)?.ok_or(libc_riscv32::EFAULT)?;
+
+        self.charge_gas(
+            policy
+                .gas_per_read
+                .saturating_add(policy.gas_per_byte.saturating_mul(key.len() as u64)),
+        )?;
+        let store = self.kv.as_ref().unwrap().store();
+        let found = store.get(&key).map_err(host_errno)?;
+        let found = found.ok_or(libc_riscv32::ENOENT)?;
+        self.charge_gas(policy.gas_per_byte.saturating_mul(found.len() as u64))?;
+
+        let n = found.len().min(value_cap as usize);
+        mem.copy_to(value, &found[..n])
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(found.len() as u32)
+    }
+
+    pub(crate) fn kv_put(&mut self, mem: &mut Memory, args: [u32; 4]) -> KernelResult {
+        let [key, key_len, value, value_len] = args;
+        let policy = self.kv.as_ref().ok_or(libc_riscv32::ENOSYS)?.policy;
+        let key = read_key(mem, &policy, key, key_len)?.ok_or(libc_riscv32::EFAULT)?;
+        if value_len > policy.max_value_bytes {
+            return Err(libc_riscv32::EFBIG);
+        }
+        let value = mem
+            .slice::<u8>(value, value_len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        let bytes = (key.len() + value.len()) as u64;
+        self.charge_gas(
+            policy
+                .gas_per_write
+                .saturating_add(policy.gas_per_byte.saturating_mul(bytes)),
+        )?;
+        let store = self.kv.as_mut().unwrap().store_mut();
+        store.put(&key, value).map_err(host_errno)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn kv_delete(&mut self, mem: &mut Memory, args: [u32; 2]) -> KernelResult {
+        let [key, key_len] = args;
+        let policy = self.kv.as_ref().ok_or(libc_riscv32::ENOSYS)?.policy;
+        let key = read_key(mem, &policy, key, key_len)?.ok_or(libc_riscv32::EFAULT)?;
+
+        self.charge_gas(
+            policy
+                .gas_per_write
+                .saturating_add(policy.gas_per_byte.saturating_mul(key.len() as u64)),
+        )?;
+        let store = self.kv.as_mut().unwrap().store_mut();
+        if !store.delete(&key).map_err(host_errno)? {
+            return Err(libc_riscv32::ENOENT);
+        }
+
+        Ok(0)
+    }
+
+    pub(crate) fn kv_next(&mut self, mem: &mut Memory, args: [u32; 4]) -> KernelResult {
+        let [key, key_len, next, next_cap] = args;
+        let policy = self.kv.as_ref().ok_or(libc_riscv32::ENOSYS)?.policy;
+        let key = read_key(mem, &policy, key, key_len)?;
+
+        self.charge_gas(policy.gas_per_read)?;
+        let store = self.kv.as_ref().unwrap().store();
+        let found = store.next_key(key.as_deref()).map_err(host_errno)?;
+        let found = found.ok_or(libc_riscv32::ENOENT)?;
+        self.charge_gas(policy.gas_per_byte.saturating_mul(found.len() as u64))?;
+
+        let n = found.len().min(next_cap as usize);
+        mem.copy_to(next, &found[..n])
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(found.len() as u32)
+    }
+}
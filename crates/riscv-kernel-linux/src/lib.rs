@@ -1,28 +1,248 @@
+mod builder;
+mod clock;
+mod env;
+mod errno;
+mod exit;
+mod fetch;
+mod fs;
+pub mod harness;
+mod hibernate;
+mod hypercall;
+mod identity;
 mod impls;
+mod kv;
+mod library;
+mod net;
+mod poll;
+mod prctl;
+mod random;
+mod resolver;
+mod sched;
+mod shm;
+mod storage;
+mod system;
+mod table;
+mod time;
+mod timeline;
+mod usage;
+mod vfs;
 
-use std::ffi::CString;
+pub use builder::{MockLinuxBuilder, StdioMode};
+pub use env::{GuestEnv, DEFAULT_PATH};
+pub use errno::KernelResult;
+pub use exit::{ProcessExit, RunStats};
+pub use fetch::{FetchPolicy, FetchService};
+pub use hypercall::{
+    HYPERCALL_BASE, HYPERCALL_FETCH, HYPERCALL_KV_DELETE, HYPERCALL_KV_GET, HYPERCALL_KV_NEXT,
+    HYPERCALL_KV_PUT,
+};
+pub use identity::Identity;
+pub use kv::{KvPolicy, KvService, KvStore};
+pub use random::Entropy;
+pub use resolver::HostTable;
+pub use sched::CpuTopology;
+pub use shm::SharedRegion;
+pub use storage::{FsQuota, FsUsage};
+pub use system::SystemInfo;
+pub use time::{ClockMode, ClockReading, SyscallCosts, Timespec64, VirtualClock};
+pub use timeline::{MemoryTimeline, RegionChange, RegionEvent};
+pub use vfs::{
+    ArchiveError, CapturedStdio, DirEntry, FileKind, FileLike, FileStat, HostCache, HostCacheStats,
+    HostMount,
+};
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, VecDeque},
+    rc::Rc,
+};
 
-use goblin::elf::{program_header::PT_LOAD, Elf};
+use goblin::elf::{
+    program_header::{PT_LOAD, PT_PHDR},
+    Elf,
+};
 
+use exit::ExitHooks;
+use identity::Credentials;
+use prctl::ProcessAttrs;
 use riscv_vm::{
-    error::MachineError,
+    error::{MachineError, MemoryError},
     hart::Hart32,
-    machine::{Kernel, StepResult},
+    machine::{Kernel, LoadedLibrary, StepResult},
     memory::Memory,
+    quota::KernelUsage,
     riscv_inst::Reg,
+    symbols::SymbolTable,
 };
 use syscalls::riscv32::Sysno;
 use thiserror::Error;
+use vfs::{Binding, FdTable, MemFs, Mount, ProcFs, ShmFs, Stdio, UnixAddr, Vfs};
 
 const PAGE_SIZE: u32 = 4096;
 
 #[derive(Error, Debug)]
-pub enum LinuxError {}
+pub enum LinuxError {
+    #[error("Unimplemented syscall {nr} at address {pc:#08x}")]
+    UnknownSyscall { nr: usize, pc: u32 },
+    #[error("Failed to parse ELF: {0}")]
+    InvalidElf(#[from] goblin::error::Error),
+    #[error("ELF segment at {vaddr:#08x} ({len:#x} bytes) lies outside the file or guest memory")]
+    InvalidSegment { vaddr: u32, len: u32 },
+    #[error("Argument or environment variable {0:?} contains a null byte")]
+    NullInArgument(String),
+    #[error("Failed to set up the initial stack: {0}")]
+    Stack(#[from] MemoryError),
+    #[error("Failed to mount {} at {guest_path}: {source}", host_dir.display())]
+    Mount {
+        guest_path: String,
+        host_dir: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to unpack the root filesystem: {0}")]
+    Archive(#[from] ArchiveError),
+    #[error("Not a position-independent rv32 shared object")]
+    NotALibrary,
+    #[error("No room below the mmap region for a {size:#x} byte library")]
+    NoSpaceForLibrary { size: u32 },
+    #[error("Undefined symbol {0:?} in library")]
+    UnresolvedSymbol(String),
+    #[error("Unsupported relocation type {kind} at {offset:#x} in library")]
+    UnsupportedRelocation { kind: u32, offset: u32 },
+    #[error("Failed to map the library: {0}")]
+    MapLibrary(MemoryError),
+    #[error("Failed to open interpreter {path:?}: {}", libc_riscv32::errno_name(*errno).unwrap_or("unknown error"))]
+    Interpreter { path: String, errno: i32 },
+    #[error("Can't protect the ELF segment at {vaddr:#08x}: {source}")]
+    ProtectSegment { vaddr: u32, source: MemoryError },
+}
+
+impl LinuxError {
+    /// Stable number for this kind of error, in `900..1000` alongside
+    /// [`MachineError::code`]'s kernel errors.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::UnknownSyscall { .. } => 901,
+            Self::InvalidElf(_) => 902,
+            Self::InvalidSegment { .. } => 903,
+            Self::NullInArgument(_) => 904,
+            Self::Stack(_) => 905,
+            Self::Mount { .. } => 906,
+            Self::Archive(_) => 907,
+            Self::NotALibrary => 908,
+            Self::NoSpaceForLibrary { .. } => 909,
+            Self::UnresolvedSymbol(_) => 910,
+            Self::UnsupportedRelocation { .. } => 911,
+            Self::MapLibrary(_) => 912,
+            Self::Interpreter { .. } => 913,
+            Self::ProtectSegment { .. } => 914,
+        }
+    }
+}
+
+/// What to do when the guest makes a syscall [`MockLinux`] doesn't implement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownSyscallPolicy {
+    /// Fail with `ENOSYS`, like a kernel that lacks the syscall
+    #[default]
+    Enosys,
+    /// Fail with `ENOSYS` and log a warning
+    Log,
+    /// Stop the machine with [`LinuxError::UnknownSyscall`]
+    Abort,
+}
+
+impl std::fmt::Display for UnknownSyscallPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Enosys => "enosys",
+            Self::Log => "log",
+            Self::Abort => "abort",
+        })
+    }
+}
+
+impl std::str::FromStr for UnknownSyscallPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enosys" => Ok(Self::Enosys),
+            "log" => Ok(Self::Log),
+            "abort" => Ok(Self::Abort),
+            _ => Err(format!(
+                "unknown syscall policy {s:?}, expected enosys, log or abort"
+            )),
+        }
+    }
+}
+
+/// Collect the function symbols of a loaded ELF.
+pub fn elf_symbols(elf: &Elf) -> SymbolTable {
+    let mut symbols = SymbolTable::new();
+    for sym in elf.syms.iter().filter(|sym| sym.is_function()) {
+        if let Some(name) = elf.strtab.get_at(sym.st_name) {
+            symbols.insert(name, sym.st_value as u32, sym.st_size as u32);
+        }
+    }
 
-#[derive(Default, Debug)]
+    symbols
+}
+
+#[derive(Debug)]
 pub struct MockLinux {
-    exit_code: Option<u32>,
-    passthrough_stdio: bool,
+    exit: Option<ProcessExit>,
+    exit_hooks: ExitHooks,
+    usage: KernelUsage,
+    /// Program break right after loading
+    brk_start: u32,
+    pub clock: VirtualClock,
+    pub topology: CpuTopology,
+    pub system: SystemInfo,
+    pub fs_quota: FsQuota,
+    pub unknown_syscalls: UnknownSyscallPolicy,
+    /// Print each syscall and its result to stderr, like strace
+    pub strace: bool,
+    /// Record the guest's heap and mapping changes, if set
+    pub memory_timeline: Option<MemoryTimeline>,
+    pub entropy: Entropy,
+    /// The end of the guest's stderr output
+    stderr_tail: VecDeque<u8>,
+    /// Clocks at the start of the current syscall, shared with timer fds
+    now: Rc<Cell<ClockReading>>,
+    vfs: Vfs,
+    files: FdTable,
+    /// Absolute guest path of the working directory
+    cwd: String,
+    /// Objects in `/dev/shm`, shared with the mount in `vfs`
+    shm: ShmFs,
+    /// `MAP_SHARED` mappings by start address
+    shared_regions: BTreeMap<u32, SharedRegion>,
+    /// Backs `/proc`, refreshed when the guest looks there
+    proc: ProcFs,
+    /// Shared by host mounts, if they cache
+    host_cache: Option<HostCache>,
+    peak_rss_pages: u64,
+    attrs: ProcessAttrs,
+    identity: Identity,
+    creds: Credentials,
+    fs_usage: FsUsage,
+    /// Where the guest's UNIX sockets are bound
+    unix_names: HashMap<UnixAddr, Binding>,
+    /// Names listed in `/etc/hosts`
+    hosts: HostTable,
+    /// Answers the `fetch` hypercall, if set
+    fetch: Option<FetchService>,
+    /// Answers the key-value hypercalls, if set
+    kv: Option<KvService>,
+    /// Fail hypercalls with `EDQUOT` rather than charge more gas than this
+    pub gas_limit: Option<u64>,
+    gas_used: u64,
+}
+
+impl Default for MockLinux {
+    fn default() -> Self {
+        Self::new(false)
+    }
 }
 
 impl Kernel for MockLinux {
@@ -33,101 +253,288 @@ impl Kernel for MockLinux {
         hart: &mut Hart32,
         mem: &mut Memory,
     ) -> Result<StepResult, MachineError<Self::Error>> {
-        macro_rules! reg {
-            ($reg: ident) => {
-                hart.get_reg(Reg::$reg) as _
-            };
-        }
-
-        let call: usize = reg!(A7);
-        let parsed = Sysno::new(call);
-        if parsed.is_none() {
-            tracing::error!("SYSCALL({call}) unknown");
-            hart.set_reg(Reg::A0, -libc_riscv32::ENOSYS as u32);
-            return Ok(StepResult::Ok);
-        }
-        tracing::debug!("SYSCALL({call}) -> {parsed:?}");
-
-        let call = parsed.unwrap();
-        let ret = match call {
-            Sysno::ioctl => self.ioctl(reg!(A0), reg!(A1)),
-            Sysno::write => self.write(mem, reg!(A0), reg!(A1), reg!(A2)),
-            Sysno::writev => self.writev(mem, reg!(A0), reg!(A1), reg!(A2)),
-            Sysno::readlinkat => self.readlinkat(mem, reg!(A0), reg!(A1), reg!(A2), reg!(A3)),
-            Sysno::exit | Sysno::exit_group => {
-                self.exit_code = Some(reg!(A0));
-                return Ok(StepResult::Halt);
-            }
-            Sysno::set_tid_address => self.set_tid_address(mem, reg!(A0)),
-            Sysno::futex => self.futex(
-                mem,
-                reg!(A0),
-                reg!(A1),
-                reg!(A2),
-                reg!(A3),
-                reg!(A4),
-                reg!(A5),
-            ),
-            Sysno::set_robust_list => self.set_robust_list(mem, reg!(A0), reg!(A1)),
-            Sysno::tgkill => self.tgkill(reg!(A0), reg!(A1), reg!(A2)),
-            Sysno::rt_sigaction => self.rt_sigaction(mem, reg!(A0), reg!(A1), reg!(A2), reg!(A3)),
-            Sysno::rt_sigprocmask => {
-                self.rt_sigprocmask(mem, reg!(A0), reg!(A1), reg!(A2), reg!(A3))
+        let nr = hart.get_reg(Reg::A7) as usize;
+        let Some(sysno) = Sysno::new(nr) else {
+            if let Some(step) = self.hypercall(hart, mem, nr) {
+                return Ok(step);
             }
-            Sysno::getpid => self.getpid(),
-            Sysno::gettid => self.gettid(),
-            Sysno::brk => self.brk(mem, reg!(A0)),
-            Sysno::mmap => self.mmap(
-                mem,
-                reg!(A0),
-                reg!(A1),
-                reg!(A2),
-                reg!(A3),
-                reg!(A4),
-                reg!(A5),
-            ),
-            Sysno::mprotect => self.mprotect(mem, reg!(A0), reg!(A1), reg!(A2)),
-            Sysno::riscv_hwprobe => {
-                self.riscv_hwprobe(mem, reg!(A0), reg!(A1), reg!(A2), reg!(A3), reg!(A4))
+            return self.unknown_syscall(hart, nr);
+        };
+        let args = hart.args();
+
+        // Events logged while handling the syscall belong to it
+        let _span = tracing::debug_span!(
+            "syscall",
+            name = sysno.name(),
+            pc = format_args!("{:#x}", hart.pc)
+        )
+        .entered();
+        self.usage.syscalls += 1;
+        self.clock.charge_syscall(sysno);
+        self.now.set(self.clock.read(hart.inst_count));
+        if matches!(sysno, Sysno::mmap | Sysno::mprotect) {
+            // Writable code under W^X stops the guest where it asked for
+            // it, rather than failing a call it may not check
+            mem.check_protect(args[0], args[1], impls::prot_perms(args[2]))?;
+        }
+        let brk = mem.brk;
+        let Some(ret) = self.dispatch(sysno, hart, mem, args) else {
+            return self.unknown_syscall(hart, nr);
+        };
+        if let Some(timeline) = &mut self.memory_timeline {
+            match (sysno, ret) {
+                (Sysno::brk, Ok(_)) if mem.brk != brk => timeline.record(
+                    hart,
+                    RegionChange::Brk,
+                    self.brk_start,
+                    mem.brk - self.brk_start,
+                ),
+                (Sysno::mmap, Ok(addr)) => {
+                    let size = args[1].next_multiple_of(PAGE_SIZE);
+                    timeline.record(hart, RegionChange::Mmap, addr, size)
+                }
+                (Sysno::munmap, Ok(_)) => {
+                    let size = args[1].next_multiple_of(PAGE_SIZE);
+                    timeline.record(hart, RegionChange::Munmap, args[0], size)
+                }
+                _ => {}
             }
-            Sysno::getrlimit => self.getrlimit(mem, reg!(A0), reg!(A1)),
-            Sysno::getrandom => self.getrandom(mem, reg!(A0), reg!(A1), reg!(A2)),
-            Sysno::statx => self.statx(mem, reg!(A0), reg!(A1), reg!(A2), reg!(A3), reg!(A4)),
-            Sysno::ppoll_time64 => {
-                self.ppoll_time64(mem, reg!(A0), reg!(A1), reg!(A2), reg!(A3), reg!(A4))
+        }
+        match (sysno, ret) {
+            (Sysno::read | Sysno::readv | Sysno::pread64 | Sysno::preadv, Ok(n)) => {
+                self.usage.bytes_read += n as u64
             }
-            Sysno::futex_time64 => self.futex(
-                mem,
-                reg!(A0),
-                reg!(A1),
-                reg!(A2),
-                reg!(A3),
-                reg!(A4),
-                reg!(A5),
-            ),
-            _ => {
-                tracing::error!("SYSCALL({call}) unimplemented");
-                Err(libc_riscv32::ENOSYS)
+            (Sysno::write | Sysno::writev | Sysno::pwrite64 | Sysno::pwritev, Ok(n)) => {
+                self.usage.bytes_written += n as u64
             }
+            _ => {}
         }
-        .unwrap_or_else(|e| -e as u32);
 
-        hart.set_reg(Reg::A0, ret as u32);
+        let exited = self.exit.is_some();
+        let trace = || {
+            let ret = if exited {
+                "?".to_string()
+            } else {
+                table::strace_ret(&ret)
+            };
+            format!("{} = {ret}", table::strace_call(sysno, args))
+        };
+        if self.strace {
+            eprintln!("{}", trace());
+        } else {
+            tracing::debug!("{}", trace());
+        }
+        if exited {
+            return Ok(StepResult::Halt);
+        }
+        hart.set_return(errno::return_value(ret));
 
         Ok(StepResult::Ok)
     }
+
+    fn stderr_tail(&self) -> Option<String> {
+        let (a, b) = self.stderr_tail.as_slices();
+        Some(String::from_utf8_lossy(&[a, b].concat()).into_owned())
+    }
+
+    fn usage(&self) -> KernelUsage {
+        self.usage
+    }
+
+    fn load_library(
+        &mut self,
+        mem: &mut Memory,
+        symbols: &SymbolTable,
+        elf: &[u8],
+    ) -> Option<Result<LoadedLibrary, LinuxError>> {
+        Some(MockLinux::load_library(self, mem, symbols, elf))
+    }
+
+    /// Saves the fd table, the memory filesystems such as an unpacked root
+    /// archive, the clocks, the entropy stream, credentials, process
+    /// attributes and usage. Host mounts aren't saved, as their files are
+    /// the host's, and are reopened by path on restore. The guest's signal
+    /// handlers aren't either, as signals are never delivered.
+    ///
+    /// Returns `None` once the guest has exited, and while it has a
+    /// socket, timerfd, epoll instance, memfd, shared memory object or
+    /// shared mapping open, or a file the embedder installed, which can't
+    /// be saved.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.save()
+    }
+
+    /// Restores onto a kernel configured like the saved one, with the same
+    /// mounts. Stdio is left as this kernel has it.
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), String> {
+        self.restore(state)
+    }
+
+    /// Files the guest created or changed stay as they are; everything else
+    /// the guest did, like opening files or mapping shared memory, is undone.
+    fn reset(&mut self, mem: &mut Memory) -> bool {
+        let _ = self.unmap_shared(mem, 0, u32::MAX);
+        self.exit = None;
+        self.usage = KernelUsage::default();
+        if let Some(timeline) = &mut self.memory_timeline {
+            timeline.clear();
+        }
+        self.clock.reset();
+        self.stderr_tail.clear();
+        self.now.set(ClockReading::default());
+        self.files.close_all_but_stdio();
+        self.cwd = "/".to_string();
+        self.peak_rss_pages = 0;
+        self.creds = Credentials::new(&self.identity);
+        self.fs_usage = FsUsage::default();
+        self.unix_names.clear();
+        self.gas_used = 0;
+
+        true
+    }
 }
 
 impl MockLinux {
+    /// Handle syscall `nr`, which isn't implemented, according to `self.unknown_syscalls`.
+    fn unknown_syscall(
+        &mut self,
+        hart: &mut Hart32,
+        nr: usize,
+    ) -> Result<StepResult, MachineError<LinuxError>> {
+        let name = Sysno::new(nr).map_or("unknown", |sysno| sysno.name());
+        match self.unknown_syscalls {
+            UnknownSyscallPolicy::Enosys => tracing::debug!("SYSCALL({nr}) {name} unimplemented"),
+            UnknownSyscallPolicy::Log => tracing::warn!("SYSCALL({nr}) {name} unimplemented"),
+            UnknownSyscallPolicy::Abort => {
+                return Err(MachineError::Kernel(LinuxError::UnknownSyscall {
+                    nr,
+                    pc: hart.pc,
+                }))
+            }
+        }
+        hart.set_return(-libc_riscv32::ENOSYS as u32);
+
+        Ok(StepResult::Ok)
+    }
+
+    /// Configure a kernel beyond the defaults of [`MockLinux::new`].
+    pub fn builder() -> MockLinuxBuilder {
+        MockLinuxBuilder::default()
+    }
+
+    /// A kernel with the default configuration, forwarding stdio to the
+    /// host if `passthrough_stdio` is set. See [`MockLinux::builder`] for
+    /// the other options.
     pub fn new(passthrough_stdio: bool) -> Self {
+        let shm = ShmFs::default();
+        let proc = ProcFs::default();
+        let identity = Identity::default();
+        let mut vfs = Vfs::default();
+        vfs.mount("/dev/shm", Box::new(shm.clone()));
+        vfs.mount("/proc", Box::new(proc.clone()));
+
+        let mut kernel = Self {
+            exit: None,
+            exit_hooks: ExitHooks::default(),
+            usage: KernelUsage::default(),
+            brk_start: 0,
+            clock: VirtualClock::default(),
+            topology: CpuTopology::default(),
+            system: SystemInfo::default(),
+            fs_quota: FsQuota::default(),
+            unknown_syscalls: UnknownSyscallPolicy::default(),
+            strace: false,
+            memory_timeline: None,
+            entropy: Entropy::default(),
+            stderr_tail: VecDeque::new(),
+            now: Rc::default(),
+            vfs,
+            files: FdTable::with_stdio(passthrough_stdio),
+            cwd: "/".to_string(),
+            shm,
+            shared_regions: BTreeMap::new(),
+            proc,
+            host_cache: None,
+            peak_rss_pages: 0,
+            attrs: ProcessAttrs::default(),
+            creds: Credentials::new(&identity),
+            identity,
+            fs_usage: FsUsage::default(),
+            unix_names: HashMap::new(),
+            hosts: HostTable::default(),
+            fetch: None,
+            kv: None,
+            gas_limit: None,
+            gas_used: 0,
+        };
+        kernel.mount_etc();
+        kernel
+    }
+
+    /// Like [`MockLinux::new`], but guest clocks run on virtual time.
+    pub fn deterministic(passthrough_stdio: bool) -> Self {
         Self {
-            exit_code: None,
-            passthrough_stdio,
+            clock: VirtualClock::new(ClockMode::Deterministic),
+            ..Self::new(passthrough_stdio)
         }
     }
 
+    /// Give the guest `stdin` and keep what it writes to stdout and stderr,
+    /// instead of using the host's stdio. Call before the guest runs, as it
+    /// replaces fds 0-2.
+    pub fn capture_stdio(&mut self, stdin: impl Into<Vec<u8>>) -> Rc<RefCell<CapturedStdio>> {
+        let captured = CapturedStdio::shared(stdin);
+        self.files
+            .set_stdio(|fd| Stdio::captured(fd, captured.clone()));
+        captured
+    }
+
     pub fn exit_code(&self) -> Option<u32> {
-        self.exit_code
+        self.exit.map(|exit| exit.code)
+    }
+
+    /// Expose the host directory `host_dir` to the guest at `guest_path`,
+    /// through [`MockLinux::host_cache`] if there is one.
+    pub fn mount_host(
+        &mut self,
+        guest_path: &str,
+        host_dir: impl AsRef<std::path::Path>,
+        read_only: bool,
+    ) -> std::io::Result<()> {
+        let mut mount = HostMount::new(host_dir, read_only)?;
+        if let Some(cache) = &self.host_cache {
+            mount = mount.with_cache(cache.clone());
+        }
+        self.mount_fs(guest_path, Box::new(mount));
+
+        Ok(())
+    }
+
+    /// The cache host mounts share, see [`MockLinuxBuilder::host_cache`].
+    pub fn host_cache(&self) -> Option<&HostCache> {
+        self.host_cache.as_ref()
+    }
+
+    /// Use a tar or newc cpio archive as the guest's root filesystem, like an initramfs.
+    ///
+    /// The archive is unpacked into memory; guest writes don't affect `archive`.
+    pub fn mount_archive(&mut self, archive: &[u8]) -> Result<(), ArchiveError> {
+        let mut fs = MemFs::new();
+        vfs::unpack(&mut fs, archive)?;
+        self.mount_fs("/", Box::new(fs));
+
+        Ok(())
+    }
+
+    /// Mount `fs` at `at`. A root filesystem with an `/etc` of its own
+    /// replaces the one describing [`MockLinux::identity`].
+    fn mount_fs(&mut self, at: &str, mut fs: Box<dyn Mount>) {
+        if vfs::normalize("/", at) == "/"
+            && fs.stat("etc").is_ok_and(|stat| stat.kind == FileKind::Dir)
+        {
+            self.vfs.unmount("/etc");
+        }
+        self.vfs.mount(at, fs);
     }
 
     pub fn load_static_elf<'a>(
@@ -137,29 +544,50 @@ impl MockLinux {
         bytes: &'a [u8],
         args: &[&str],
         env: &[&str],
-    ) -> Elf<'a> {
-        let elf = Elf::parse(bytes).expect("Failed to parse ELF");
+    ) -> Result<Elf<'a>, LinuxError> {
+        let elf = Elf::parse(bytes)?;
         // Load main program segments
         let mut brk = 0;
         for ph in &elf.program_headers {
             if ph.p_type == PT_LOAD {
                 let vaddr = ph.p_vaddr as u32;
-                for i in 0..ph.p_filesz as usize {
-                    mem.store::<u8>(vaddr + i as u32, bytes[ph.p_offset as usize + i]);
-                }
+                let invalid = || LinuxError::InvalidSegment {
+                    vaddr,
+                    len: ph.p_memsz as u32,
+                };
+                let end = vaddr
+                    .checked_add(ph.p_memsz as u32)
+                    .filter(|_| ph.p_filesz <= ph.p_memsz)
+                    .ok_or_else(invalid)?;
+                let data = bytes
+                    .get(ph.p_offset as usize..)
+                    .and_then(|rest| rest.get(..ph.p_filesz as usize))
+                    .ok_or_else(invalid)?;
+                mem.slice_mut::<u8>(vaddr, data.len() as u32)
+                    .map_err(|_| invalid())?
+                    .copy_from_slice(data);
+                library::protect_segment(mem, vaddr, ph)?;
                 // BSS already zero since fresh mmap
-                if vaddr + ph.p_memsz as u32 > brk {
-                    brk = vaddr + ph.p_memsz as u32;
-                }
+                let end = end.checked_next_multiple_of(0x1000).ok_or_else(invalid)?;
+                brk = brk.max(end);
             }
         }
 
-        // PC
-        hart.pc = elf.entry as u32;
-
-        // Align and set brk
-        brk = (brk + 0xfff) & !0xfff;
+        // brk starts on the page after the last segment
         mem.brk = brk;
+        self.brk_start = brk;
+
+        // PC, which is the interpreter's entry for dynamically linked programs
+        let (interp_base, entry) = match elf.interpreter {
+            Some(path) => self.load_interpreter(mem, path)?,
+            None => (0, elf.entry as u32),
+        };
+        hart.pc = entry;
+        self.topology.isa = hart.extensions;
+        if let Some(arg0) = args.first() {
+            self.attrs
+                .set_comm(arg0.rsplit('/').next().unwrap().as_bytes());
+        }
         // Global pointer is at __DATA_BEGIN__
         // TODO: Do we actually need to set this? Or does libc initialize it on its own?
         let data_begin = elf
@@ -175,30 +603,36 @@ impl MockLinux {
         let mut stack_init: Vec<u32> = vec![];
 
         // Arguments
+        // Strings are written top-down from the last one, so their pointers
+        // are collected and pushed in order afterwards.
         stack_init.push(args.len() as u32); // argc
+        let mut argv = vec![];
         for &arg in args.iter().rev() {
-            let bytes = CString::new(arg).expect("argument contains null byte");
-            let bytes = bytes.to_bytes_with_nul();
+            if arg.contains('\0') {
+                return Err(LinuxError::NullInArgument(arg.to_string()));
+            }
 
-            sp = (sp - bytes.len() as u32) & !(4 - 1); // align to 4 bytes
-            mem.copy_to(sp, bytes)
-                .expect("Failed to copy argument to stack");
+            sp = (sp - arg.len() as u32 - 1) & !(4 - 1); // align to 4 bytes
+            mem.write_cstr(sp, arg.as_bytes())?;
 
-            stack_init.push(sp); // pointer to arg
+            argv.push(sp); // pointer to arg
         }
+        stack_init.extend(argv.iter().rev());
         stack_init.push(0); // argv NULL terminator
 
         // Environment
+        let mut envp = vec![];
         for &e in env.iter().rev() {
-            let bytes = CString::new(e).expect("environment variable contains null byte");
-            let bytes = bytes.to_bytes_with_nul();
+            if e.contains('\0') {
+                return Err(LinuxError::NullInArgument(e.to_string()));
+            }
 
-            sp = (sp - bytes.len() as u32) & !(4 - 1); // align to 4 bytes
-            mem.copy_to(sp, bytes)
-                .expect("Failed to copy environment variable to stack");
+            sp = (sp - e.len() as u32 - 1) & !(4 - 1); // align to 4 bytes
+            mem.write_cstr(sp, e.as_bytes())?;
 
-            stack_init.push(sp);
+            envp.push(sp);
         }
+        stack_init.extend(envp.iter().rev());
         stack_init.push(0); // envp NULL terminator
 
         // ELF Auxillary Vector
@@ -208,24 +642,44 @@ impl MockLinux {
                 stack_init.push($val);
             };
         }
+        // The dynamic linker finds the program through its headers
+        let phdr = elf
+            .program_headers
+            .iter()
+            .find(|ph| ph.p_type == PT_PHDR)
+            .map(|ph| ph.p_vaddr)
+            .or_else(|| {
+                elf.program_headers
+                    .iter()
+                    .find(|ph| ph.p_type == PT_LOAD && ph.p_offset == 0)
+                    .map(|ph| ph.p_vaddr + elf.header.e_phoff)
+            })
+            .unwrap_or(0) as u32;
         // TODO: Fill in the rest of the aux vector
         set_AT!(libc_riscv32::AT_PAGESZ, PAGE_SIZE);
         set_AT!(libc_riscv32::AT_CLKTCK, 100);
-        set_AT!(libc_riscv32::AT_BASE, 0); // TODO
-        set_AT!(libc_riscv32::AT_ENTRY, hart.pc);
+        set_AT!(libc_riscv32::AT_PHDR, phdr);
+        set_AT!(libc_riscv32::AT_PHENT, elf.header.e_phentsize as u32);
+        set_AT!(libc_riscv32::AT_PHNUM, elf.header.e_phnum as u32);
+        set_AT!(libc_riscv32::AT_BASE, interp_base);
+        set_AT!(libc_riscv32::AT_ENTRY, elf.entry as u32);
+        set_AT!(libc_riscv32::AT_UID, self.getuid().unwrap());
+        set_AT!(libc_riscv32::AT_EUID, self.geteuid().unwrap());
+        set_AT!(libc_riscv32::AT_GID, self.getgid().unwrap());
+        set_AT!(libc_riscv32::AT_EGID, self.getegid().unwrap());
+        set_AT!(libc_riscv32::AT_SECURE, 0);
         set_AT!(libc_riscv32::AT_NULL, 0);
 
         // Set up stack
         sp -= (stack_init.len() * 4) as u32;
 
-        mem.copy_to(sp, &stack_init)
-            .expect("Failed to copy stack data vector");
+        mem.copy_to(sp, &stack_init)?;
 
         hart.set_reg(Reg::Sp, sp);
 
         tracing::debug!("Stack at {:#x}, GP at {:#x}", sp, data_begin);
-        tracing::debug!("Loaded ELF. Start at {:08x}, brk={:08x}", elf.entry, brk);
+        tracing::debug!("Loaded ELF. Start at {:08x}, brk={:08x}", entry, brk);
 
-        elf
+        Ok(elf)
     }
 }
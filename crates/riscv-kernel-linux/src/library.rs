@@ -0,0 +1,229 @@
+use goblin::elf::{
+    dynamic::{DT_INIT, DT_INIT_ARRAY, DT_INIT_ARRAYSZ},
+    header::{EM_RISCV, ET_DYN},
+    program_header::{ProgramHeader, PF_W, PF_X, PT_LOAD},
+    reloc::{R_RISCV_32, R_RISCV_JUMP_SLOT, R_RISCV_NONE, R_RISCV_RELATIVE},
+    section_header::SHN_UNDEF,
+    sym::{STB_WEAK, STT_FUNC, STT_OBJECT},
+    Elf,
+};
+use riscv_vm::{machine::LoadedLibrary, memory::Memory, protect::PagePerms, symbols::SymbolTable};
+
+use crate::{LinuxError, MockLinux, PAGE_SIZE};
+
+impl MockLinux {
+    /// Map the shared object `bytes` below the current mmap region and apply
+    /// its dynamic relocations, resolving imports against its own symbols
+    /// first, then `symbols`. Undefined weak imports resolve to 0.
+    ///
+    /// Only what a manual `dlopen` needs is supported: no `DT_NEEDED`
+    /// dependencies, TLS or symbol versioning.
+    pub fn load_library(
+        &mut self,
+        mem: &mut Memory,
+        symbols: &SymbolTable,
+        bytes: &[u8],
+    ) -> Result<LoadedLibrary, LinuxError> {
+        let elf = Elf::parse(bytes)?;
+        if elf.header.e_type != ET_DYN || elf.header.e_machine != EM_RISCV || elf.is_64 {
+            return Err(LinuxError::NotALibrary);
+        }
+        if !elf.libraries.is_empty() {
+            tracing::warn!("load_library: ignoring dependencies {:?}", elf.libraries);
+        }
+
+        let (map_addr, base, size) = map_image(mem, &elf, bytes)?;
+
+        let resolve = |index: usize| -> Result<u32, LinuxError> {
+            let sym = elf.dynsyms.get(index).ok_or(LinuxError::NotALibrary)?;
+            if sym.st_shndx != SHN_UNDEF as usize {
+                return Ok(base.wrapping_add(sym.st_value as u32));
+            }
+            let name = elf.dynstrtab.get_at(sym.st_name).unwrap_or_default();
+            match symbols.lookup(name) {
+                Some(addr) => Ok(addr),
+                None if sym.st_bind() == STB_WEAK => Ok(0),
+                None => Err(LinuxError::UnresolvedSymbol(name.to_string())),
+            }
+        };
+        let relocs = elf
+            .dynrelas
+            .iter()
+            .chain(elf.dynrels.iter())
+            .chain(elf.pltrelocs.iter());
+        for reloc in relocs {
+            let addr = base.wrapping_add(reloc.r_offset as u32);
+            // REL relocations keep their addend in place
+            let addend = match reloc.r_addend {
+                Some(addend) => addend as u32,
+                None => mem.read::<u32>(addr).map_err(LinuxError::MapLibrary)?,
+            };
+            let value = match reloc.r_type {
+                R_RISCV_NONE => continue,
+                R_RISCV_RELATIVE => base.wrapping_add(addend),
+                R_RISCV_32 => resolve(reloc.r_sym)?.wrapping_add(addend),
+                R_RISCV_JUMP_SLOT => resolve(reloc.r_sym)?,
+                kind => {
+                    return Err(LinuxError::UnsupportedRelocation {
+                        kind,
+                        offset: reloc.r_offset as u32,
+                    })
+                }
+            };
+            mem.write(addr, value).map_err(LinuxError::MapLibrary)?;
+        }
+
+        let mut library_symbols = SymbolTable::new();
+        for sym in elf.dynsyms.iter() {
+            let defined = sym.st_shndx != SHN_UNDEF as usize;
+            if defined && matches!(sym.st_type(), STT_FUNC | STT_OBJECT) {
+                if let Some(name) = elf.dynstrtab.get_at(sym.st_name) {
+                    let addr = base.wrapping_add(sym.st_value as u32);
+                    library_symbols.insert(name, addr, sym.st_size as u32);
+                }
+            }
+        }
+
+        let mut constructors = vec![];
+        let mut init_array = None;
+        let mut init_array_size = 0;
+        for d in elf.dynamic.iter().flat_map(|dynamic| &dynamic.dyns) {
+            match d.d_tag {
+                DT_INIT => constructors.push(base.wrapping_add(d.d_val as u32)),
+                DT_INIT_ARRAY => init_array = Some(base.wrapping_add(d.d_val as u32)),
+                DT_INIT_ARRAYSZ => init_array_size = d.d_val as u32,
+                _ => {}
+            }
+        }
+        if let Some(init_array) = init_array {
+            let entries = mem
+                .read_vec::<u32>(init_array, init_array_size / 4)
+                .map_err(LinuxError::MapLibrary)?;
+            constructors.extend(entries);
+        }
+
+        mem.mmap_top = map_addr;
+        tracing::debug!("load_library: mapped {size:#x} bytes at {map_addr:#x}");
+
+        Ok(LoadedLibrary {
+            base,
+            size,
+            symbols: library_symbols,
+            constructors,
+        })
+    }
+}
+
+impl MockLinux {
+    /// Map the program interpreter at `path`, from the guest's filesystem,
+    /// for a dynamically linked program. It relocates itself, so this only
+    /// maps it; returns its load bias and entry point.
+    pub(crate) fn load_interpreter(
+        &mut self,
+        mem: &mut Memory,
+        path: &str,
+    ) -> Result<(u32, u32), LinuxError> {
+        let failed = |errno| LinuxError::Interpreter {
+            path: path.to_string(),
+            errno,
+        };
+        let mut file = self
+            .vfs
+            .open(path, libc_riscv32::O_RDONLY, 0)
+            .map_err(failed)?;
+        let mut bytes = vec![];
+        let mut buf = [0; 4096];
+        loop {
+            match file.read(&mut buf).map_err(failed)? {
+                0 => break,
+                n => bytes.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        let elf = Elf::parse(&bytes)?;
+        if elf.header.e_type != ET_DYN || elf.header.e_machine != EM_RISCV || elf.is_64 {
+            return Err(LinuxError::NotALibrary);
+        }
+        let (map_addr, base, size) = map_image(mem, &elf, &bytes)?;
+        mem.mmap_top = map_addr;
+        tracing::debug!("load_interpreter: mapped {path} ({size:#x} bytes) at {map_addr:#x}");
+
+        Ok((base, base.wrapping_add(elf.entry as u32)))
+    }
+}
+
+/// Map the `PT_LOAD` segments of the position-independent `elf` below the
+/// current mmap region, zeroing the rest of the span, without relocating
+/// anything. Returns where the span starts, the load bias and its size;
+/// moving `mmap_top` down is up to the caller.
+pub(crate) fn map_image(
+    mem: &mut Memory,
+    elf: &Elf,
+    bytes: &[u8],
+) -> Result<(u32, u32, u32), LinuxError> {
+    let segments: Vec<_> = elf
+        .program_headers
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .collect();
+    let start = segments
+        .iter()
+        .map(|ph| ph.p_vaddr as u32 & !(PAGE_SIZE - 1))
+        .min()
+        .ok_or(LinuxError::NotALibrary)?;
+    let end = segments
+        .iter()
+        .map(|ph| (ph.p_vaddr + ph.p_memsz) as u32)
+        .max()
+        .unwrap();
+    let size = end
+        .checked_sub(start)
+        .and_then(|size| size.checked_next_multiple_of(PAGE_SIZE))
+        .ok_or(LinuxError::InvalidSegment {
+            vaddr: start,
+            len: end.wrapping_sub(start),
+        })?;
+
+    let map_addr = mem.mmap_top.saturating_sub(size) & !(PAGE_SIZE - 1);
+    if map_addr < mem.brk {
+        return Err(LinuxError::NoSpaceForLibrary { size });
+    }
+    mem.memset(map_addr, 0, size)
+        .map_err(LinuxError::MapLibrary)?;
+    // The library's addresses are relative to `base`
+    let base = map_addr.wrapping_sub(start);
+
+    for ph in segments {
+        let vaddr = base.wrapping_add(ph.p_vaddr as u32);
+        let invalid = || LinuxError::InvalidSegment {
+            vaddr: ph.p_vaddr as u32,
+            len: ph.p_memsz as u32,
+        };
+        if ph.p_filesz > ph.p_memsz {
+            return Err(invalid());
+        }
+        let data = bytes
+            .get(ph.p_offset as usize..)
+            .and_then(|rest| rest.get(..ph.p_filesz as usize))
+            .ok_or_else(invalid)?;
+        mem.copy_to(vaddr, data).map_err(|_| invalid())?;
+        protect_segment(mem, vaddr, ph)?;
+    }
+
+    Ok((map_addr, base, size))
+}
+
+/// Give the pages of the segment `ph`, loaded at `vaddr`, the permissions
+/// its flags ask for.
+pub(crate) fn protect_segment(
+    mem: &mut Memory,
+    vaddr: u32,
+    ph: &ProgramHeader,
+) -> Result<(), LinuxError> {
+    let perms = PagePerms {
+        write: ph.p_flags & PF_W != 0,
+        exec: ph.p_flags & PF_X != 0,
+    };
+    mem.protect(vaddr, ph.p_memsz as u32, perms)
+        .map_err(|source| LinuxError::ProtectSegment { vaddr, source })
+}
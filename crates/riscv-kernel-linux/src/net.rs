@@ -0,0 +1,513 @@
+use riscv_vm::{
+    marshal::{GuestSlice, GuestValue},
+    memory::Memory,
+};
+
+use crate::{
+    errno::KernelResult,
+    impls::read_iovecs,
+    vfs::{Binding, OpenFile, SocketKind, UnixAddr, UnixSocket, QUEUE_CAPACITY},
+    MockLinux,
+};
+
+/// Bytes in `struct sockaddr_un`: the family, then 108 of `sun_path`.
+const SOCKADDR_UN_LEN: u32 = 110;
+
+/// `struct msghdr` on rv32.
+#[repr(C)]
+#[derive(GuestValue, Debug, Clone, Copy, Default)]
+#[guest(size = 28)]
+struct Msghdr {
+    name: u32,
+    namelen: u32,
+    iov: u32,
+    iovlen: u32,
+    control: u32,
+    controllen: u32,
+    flags: u32,
+}
+
+/// Bytes in `struct mmsghdr`: a `msghdr`, then the length received.
+const MMSGHDR_LEN: u32 = 32;
+
+/// Write `addr` as a `sockaddr_un` to `buf`, cut to `len` bytes, returning
+/// its full length.
+fn write_sockaddr(mem: &mut Memory, addr: &UnixAddr, buf: u32, len: u32) -> KernelResult<u32> {
+    let family = (libc_riscv32::AF_UNIX as u16).to_ne_bytes();
+    let bytes = [&family[..], &addr.sun_path()].concat();
+    if (len as i32) < 0 {
+        return Err(libc_riscv32::EINVAL);
+    }
+    let n = bytes.len().min(len as usize);
+    mem.copy_to(buf, &bytes[..n])
+        .map_err(|_| libc_riscv32::EFAULT)?;
+
+    Ok(bytes.len() as u32)
+}
+
+/// Write `addr` to `buf`, if any, for a call that takes its length at
+/// `addrlen` and is told the full length there.
+fn write_addr_out(mem: &mut Memory, addr: &UnixAddr, buf: u32, addrlen: u32) -> KernelResult<()> {
+    if buf == 0 {
+        return Ok(());
+    }
+    let len = mem.read::<u32>(addrlen).map_err(|_| libc_riscv32::EFAULT)?;
+    let full = write_sockaddr(mem, addr, buf, len)?;
+    mem.write(addrlen, full).map_err(|_| libc_riscv32::EFAULT)
+}
+
+/// The iovecs a `msghdr` points at, and the bytes they hold in all, which
+/// like Linux must fit in an `ssize_t`.
+fn read_msg_iovecs(mem: &Memory, msg: &Msghdr) -> KernelResult<(Vec<GuestSlice>, usize)> {
+    let iovs = read_iovecs(mem, msg.iov, msg.iovlen as i32)?;
+    let total = iovs
+        .iter()
+        .try_fold(0u32, |total, iov| total.checked_add(iov.len))
+        .filter(|&total| total <= libc_riscv32::SSIZE_MAX)
+        .ok_or(libc_riscv32::EINVAL)?;
+
+    Ok((iovs, total as usize))
+}
+
+impl MockLinux {
+    /// The socket at `fd`. Other files are `ENOTSOCK`.
+    fn unix_socket(&mut self, fd: i32) -> KernelResult<&mut UnixSocket> {
+        self.files.get(fd)?;
+        self.files.get_as(fd).map_err(|_| libc_riscv32::ENOTSOCK)
+    }
+
+    fn insert_socket(&mut self, socket: UnixSocket, flags: u32) -> KernelResult<i32> {
+        self.files.insert(OpenFile {
+            file: Box::new(socket),
+            path: "socket:[unix]".to_string(),
+            flags: libc_riscv32::O_RDWR | (flags & libc_riscv32::O_NONBLOCK),
+            dir_pos: 0,
+        })
+    }
+
+    /// Read the `sockaddr_un` of `addrlen` bytes at `addr`, resolving a path
+    /// against the working directory.
+    fn read_sockaddr(&mut self, mem: &Memory, addr: u32, addrlen: u32) -> KernelResult<UnixAddr> {
+        if !(2..=SOCKADDR_UN_LEN).contains(&addrlen) {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let bytes = mem
+            .slice::<u8>(addr, addrlen)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        if u16::from_ne_bytes([bytes[0], bytes[1]]) as u32 != libc_riscv32::AF_UNIX {
+            return Err(libc_riscv32::EINVAL);
+        }
+
+        match &bytes[2..] {
+            [] => Ok(UnixAddr::Unnamed),
+            [0, name @ ..] => Ok(UnixAddr::Abstract(name.to_vec())),
+            path => {
+                let len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+                let path = String::from_utf8_lossy(&path[..len]);
+                let path = self.resolve_path_at(libc_riscv32::AT_FDCWD, &path)?;
+                Ok(UnixAddr::Path(path))
+            }
+        }
+    }
+
+    /// What's bound at the `sockaddr_un` at `addr`, and its address.
+    fn lookup_sockaddr(
+        &mut self,
+        mem: &Memory,
+        addr: u32,
+        addrlen: u32,
+    ) -> KernelResult<(Binding, UnixAddr)> {
+        let addr = self.read_sockaddr(mem, addr, addrlen)?;
+        match self.unix_names.get(&addr) {
+            Some(binding) => Ok((binding.clone(), addr)),
+            None if matches!(addr, UnixAddr::Path(_)) => Err(libc_riscv32::ENOENT),
+            None => Err(libc_riscv32::ECONNREFUSED),
+        }
+    }
+
+    pub(crate) fn socket(&mut self, domain: u32, ty: u32, protocol: u32) -> KernelResult {
+        let (kind, flags) = socket_type(domain, ty, protocol)?;
+        let fd = self.insert_socket(UnixSocket::new(kind), flags)?;
+
+        Ok(fd as u32)
+    }
+
+    pub(crate) fn socketpair(
+        &mut self,
+        mem: &mut Memory,
+        domain: u32,
+        ty: u32,
+        protocol: u32,
+        sv: u32,
+    ) -> KernelResult {
+        let (kind, flags) = socket_type(domain, ty, protocol)?;
+        let (a, b) = UnixSocket::pair(kind);
+        let a = self.insert_socket(a, flags)?;
+        let b = match self.insert_socket(b, flags) {
+            Ok(b) => b,
+            Err(errno) => {
+                let _ = self.files.remove(a);
+                return Err(errno);
+            }
+        };
+        if mem.write(sv, [a as u32, b as u32]).is_err() {
+            let _ = self.files.remove(a);
+            let _ = self.files.remove(b);
+            return Err(libc_riscv32::EFAULT);
+        }
+
+        Ok(0)
+    }
+
+    pub(crate) fn bind(&mut self, mem: &Memory, fd: i32, addr: u32, addrlen: u32) -> KernelResult {
+        if self.unix_socket(fd)?.addr != UnixAddr::Unnamed {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let mut addr = self.read_sockaddr(mem, addr, addrlen)?;
+        if addr == UnixAddr::Unnamed {
+            // Autobind to an unused abstract name, like Linux
+            addr = (0..)
+                .map(|n: u32| UnixAddr::Abstract(format!("{n:05x}").into_bytes()))
+                .find(|addr| !self.unix_names.get(addr).is_some_and(Binding::in_use))
+                .unwrap();
+        }
+        if self.unix_names.get(&addr).is_some_and(Binding::in_use) {
+            return Err(libc_riscv32::EADDRINUSE);
+        }
+
+        let binding = self.unix_socket(fd)?.bind(addr.clone());
+        self.unix_names.insert(addr, binding);
+
+        Ok(0)
+    }
+
+    pub(crate) fn listen(&mut self, fd: i32, backlog: i32) -> KernelResult {
+        let limit = match backlog {
+            ..0 => libc_riscv32::SOMAXCONN,
+            n => (n as u32).min(libc_riscv32::SOMAXCONN),
+        };
+        self.unix_socket(fd)?.listen(limit as usize)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn connect(
+        &mut self,
+        mem: &Memory,
+        fd: i32,
+        addr: u32,
+        addrlen: u32,
+    ) -> KernelResult {
+        self.unix_socket(fd)?;
+        let (binding, addr) = self.lookup_sockaddr(mem, addr, addrlen)?;
+        self.unix_socket(fd)?.connect(&binding, addr)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn accept(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        addr: u32,
+        addrlen: u32,
+    ) -> KernelResult {
+        self.accept4(mem, fd, addr, addrlen, 0)
+    }
+
+    pub(crate) fn accept4(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        addr: u32,
+        addrlen: u32,
+        flags: u32,
+    ) -> KernelResult {
+        if flags & !(libc_riscv32::SOCK_NONBLOCK | libc_riscv32::SOCK_CLOEXEC) != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let socket = self.unix_socket(fd)?.accept()?;
+        let peer = socket.peer_addr().cloned().unwrap_or(UnixAddr::Unnamed);
+        let conn = self.insert_socket(socket, flags)?;
+        write_addr_out(mem, &peer, addr, addrlen)?;
+
+        Ok(conn as u32)
+    }
+
+    pub(crate) fn getsockname(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        addr: u32,
+        addrlen: u32,
+    ) -> KernelResult {
+        let name = self.unix_socket(fd)?.addr.clone();
+        write_addr_out(mem, &name, addr, addrlen)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn getpeername(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        addr: u32,
+        addrlen: u32,
+    ) -> KernelResult {
+        let socket = self.unix_socket(fd)?;
+        let peer = socket.peer_addr().ok_or(libc_riscv32::ENOTCONN)?.clone();
+        write_addr_out(mem, &peer, addr, addrlen)?;
+
+        Ok(0)
+    }
+
+    /// Send `data` from the socket at `fd`, to `to` or its peer.
+    fn send(&mut self, fd: i32, data: &[u8], flags: u32, to: Option<Binding>) -> KernelResult {
+        // Nothing blocks, and there are no signals to suppress
+        let known = libc_riscv32::MSG_DONTWAIT | libc_riscv32::MSG_NOSIGNAL;
+        if flags & !known != 0 {
+            return Err(libc_riscv32::EOPNOTSUPP);
+        }
+        let n = self.unix_socket(fd)?.send(data, to.as_ref())?;
+
+        Ok(n as u32)
+    }
+
+    /// Receive into `buf` from the socket at `fd`, returning how much was
+    /// received, the message's length and who sent it.
+    fn recv(
+        &mut self,
+        fd: i32,
+        buf: &mut [u8],
+        flags: u32,
+    ) -> KernelResult<(usize, usize, UnixAddr)> {
+        let known = libc_riscv32::MSG_PEEK
+            | libc_riscv32::MSG_TRUNC
+            | libc_riscv32::MSG_DONTWAIT
+            | libc_riscv32::MSG_WAITALL
+            | libc_riscv32::MSG_CMSG_CLOEXEC;
+        if flags & !known != 0 {
+            return Err(libc_riscv32::EOPNOTSUPP);
+        }
+        let peek = flags & libc_riscv32::MSG_PEEK != 0;
+
+        self.unix_socket(fd)?.recv(buf, peek)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn sendto(
+        &mut self,
+        mem: &Memory,
+        fd: i32,
+        buf: u32,
+        len: u32,
+        flags: u32,
+        dest_addr: u32,
+        addrlen: u32,
+    ) -> KernelResult {
+        let data = mem
+            .slice::<u8>(buf, len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        self.unix_socket(fd)?;
+        let to = match dest_addr {
+            0 => None,
+            addr => Some(self.lookup_sockaddr(mem, addr, addrlen)?.0),
+        };
+
+        self.send(fd, data, flags, to)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn recvfrom(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        buf: u32,
+        len: u32,
+        flags: u32,
+        src_addr: u32,
+        addrlen: u32,
+    ) -> KernelResult {
+        let buf = mem
+            .slice_mut::<u8>(buf, len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        let (n, full, from) = self.recv(fd, buf, flags)?;
+        write_addr_out(mem, &from, src_addr, addrlen)?;
+
+        // `MSG_TRUNC` asks for the length of a datagram cut short
+        match flags & libc_riscv32::MSG_TRUNC {
+            0 => Ok(n as u32),
+            _ => Ok(full as u32),
+        }
+    }
+
+    pub(crate) fn sendmsg(&mut self, mem: &Memory, fd: i32, msg: u32, flags: u32) -> KernelResult {
+        let msg = mem.read::<Msghdr>(msg).map_err(|_| libc_riscv32::EFAULT)?;
+        self.unix_socket(fd)?;
+        if msg.controllen != 0 {
+            // Passing descriptors or credentials isn't supported
+            tracing::warn!("sendmsg: ancillary data unsupported");
+            return Err(libc_riscv32::EOPNOTSUPP);
+        }
+        let to = match msg.name {
+            0 => None,
+            name => Some(self.lookup_sockaddr(mem, name, msg.namelen)?.0),
+        };
+
+        // No socket queues more than `QUEUE_CAPACITY`, so a byte past that is
+        // enough to tell a datagram it's too long
+        let (iovs, total) = read_msg_iovecs(mem, &msg)?;
+        let mut data = Vec::with_capacity(total.min(QUEUE_CAPACITY + 1));
+        for iov in iovs {
+            let slice = mem
+                .slice::<u8>(iov.ptr, iov.len)
+                .map_err(|_| libc_riscv32::EFAULT)?;
+            let room = QUEUE_CAPACITY + 1 - data.len();
+            data.extend_from_slice(&slice[..slice.len().min(room)]);
+        }
+
+        self.send(fd, &data, flags, to)
+    }
+
+    pub(crate) fn recvmsg(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        msg: u32,
+        flags: u32,
+    ) -> KernelResult {
+        let mut header = mem.read::<Msghdr>(msg).map_err(|_| libc_riscv32::EFAULT)?;
+        let (iovs, total) = read_msg_iovecs(mem, &header)?;
+
+        // Only as much as there is to receive
+        let mut buf = vec![0; total.min(self.unix_socket(fd)?.pending())];
+        let (n, full, from) = self.recv(fd, &mut buf, flags)?;
+        let mut received = &buf[..n];
+        for iov in iovs {
+            let chunk = received.len().min(iov.len as usize);
+            mem.copy_to(iov.ptr, &received[..chunk])
+                .map_err(|_| libc_riscv32::EFAULT)?;
+            received = &received[chunk..];
+        }
+
+        if header.name != 0 {
+            header.namelen = write_sockaddr(mem, &from, header.name, header.namelen)?;
+        }
+        header.controllen = 0;
+        header.flags = if full > n { libc_riscv32::MSG_TRUNC } else { 0 };
+        mem.write(msg, header).map_err(|_| libc_riscv32::EFAULT)?;
+
+        match flags & libc_riscv32::MSG_TRUNC {
+            0 => Ok(n as u32),
+            _ => Ok(full as u32),
+        }
+    }
+
+    /// Receive up to `vlen` messages, stopping early once none are waiting.
+    pub(crate) fn recvmmsg_time64(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        msgvec: u32,
+        vlen: u32,
+        flags: u32,
+        _timeout: u32,
+    ) -> KernelResult {
+        let mut received = 0;
+        while received < vlen {
+            let msg = msgvec.wrapping_add(received * MMSGHDR_LEN);
+            let len = match self.recvmsg(mem, fd, msg, flags) {
+                Err(_) if received > 0 => break,
+                len => len?,
+            };
+            mem.write(msg + MMSGHDR_LEN - 4, len)
+                .map_err(|_| libc_riscv32::EFAULT)?;
+            received += 1;
+        }
+
+        Ok(received)
+    }
+
+    pub(crate) fn shutdown(&mut self, fd: i32, how: u32) -> KernelResult {
+        let (read, write) = match how {
+            libc_riscv32::SHUT_RD => (true, false),
+            libc_riscv32::SHUT_WR => (false, true),
+            libc_riscv32::SHUT_RDWR => (true, true),
+            _ => return Err(libc_riscv32::EINVAL),
+        };
+        self.unix_socket(fd)?.shutdown(read, write)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn getsockopt(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        level: u32,
+        optname: u32,
+        optval: u32,
+        optlen: u32,
+    ) -> KernelResult {
+        let socket = self.unix_socket(fd)?;
+        if level != libc_riscv32::SOL_SOCKET {
+            return Err(libc_riscv32::ENOPROTOOPT);
+        }
+        let value = match optname {
+            libc_riscv32::SO_TYPE => socket.kind.sock_type(),
+            libc_riscv32::SO_DOMAIN => libc_riscv32::AF_UNIX,
+            libc_riscv32::SO_PROTOCOL | libc_riscv32::SO_ERROR => 0,
+            libc_riscv32::SO_ACCEPTCONN => socket.is_listening() as u32,
+            libc_riscv32::SO_SNDBUF | libc_riscv32::SO_RCVBUF => QUEUE_CAPACITY as u32,
+            _ => return Err(libc_riscv32::ENOPROTOOPT),
+        };
+
+        let len = mem.read::<u32>(optlen).map_err(|_| libc_riscv32::EFAULT)?;
+        if (len as i32) < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let len = len.min(4);
+        mem.copy_to(optval, &value.to_ne_bytes()[..len as usize])
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        mem.write(optlen, len).map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+
+    /// Socket options are accepted, but change nothing.
+    pub(crate) fn setsockopt(
+        &mut self,
+        fd: i32,
+        level: u32,
+        _optname: u32,
+        _optval: u32,
+        _optlen: u32,
+    ) -> KernelResult {
+        self.unix_socket(fd)?;
+        if level != libc_riscv32::SOL_SOCKET {
+            return Err(libc_riscv32::ENOPROTOOPT);
+        }
+
+        Ok(0)
+    }
+}
+
+/// The kind of socket and `SOCK_*` flags asked for by `socket` or
+/// `socketpair`.
+fn socket_type(domain: u32, ty: u32, protocol: u32) -> KernelResult<(SocketKind, u32)> {
+    // There's no network, only sockets within the VM
+    if domain != libc_riscv32::AF_UNIX {
+        return Err(libc_riscv32::EAFNOSUPPORT);
+    }
+    let flags = ty & !libc_riscv32::SOCK_TYPE_MASK;
+    if flags & !(libc_riscv32::SOCK_NONBLOCK | libc_riscv32::SOCK_CLOEXEC) != 0 {
+        return Err(libc_riscv32::EINVAL);
+    }
+    let kind = SocketKind::from_type(ty & libc_riscv32::SOCK_TYPE_MASK)
+        .ok_or(libc_riscv32::ESOCKTNOSUPPORT)?;
+    if protocol != 0 {
+        return Err(libc_riscv32::EPROTONOSUPPORT);
+    }
+
+    Ok((kind, flags))
+}
@@ -0,0 +1,410 @@
+use riscv_vm::{marshal::GuestValue, memory::Memory};
+
+use crate::{
+    errno::KernelResult,
+    vfs::{Epoll, EpollEvent, EventFd, FdTable, OpenFile, TimerFd},
+    MockLinux, Timespec64,
+};
+
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: u16,
+    revents: u16,
+}
+
+/// `struct itimerspec` with 64-bit times.
+#[repr(C)]
+#[derive(GuestValue, Debug, Clone, Copy, Default)]
+#[guest(size = 32)]
+struct Itimerspec64 {
+    it_interval: Timespec64,
+    it_value: Timespec64,
+}
+
+impl MockLinux {
+    /// Let guest time pass until the monotonic clock reaches `deadline_ns`.
+    pub(crate) fn sleep_until(&mut self, deadline_ns: u64) {
+        let now = self.now.get();
+        if deadline_ns > now.monotonic_ns {
+            self.clock.advance(deadline_ns - now.monotonic_ns);
+        }
+        self.now.set(self.clock.read(now.inst_count));
+    }
+
+    /// Run `check` until it finds ready fds or `timeout_ns` passes (forever if `None`).
+    ///
+    /// `check` returns the number of ready fds and the earliest
+    /// [`wake_at`](crate::vfs::FileLike::wake_at) among the rest. The guest is
+    /// single-threaded, so waiting means sleeping until that wakeup.
+    fn wait_ready(
+        &mut self,
+        timeout_ns: Option<u64>,
+        mut check: impl FnMut(&mut Self) -> (u32, Option<u64>),
+    ) -> u32 {
+        let limit = timeout_ns.map(|ns| self.now.get().monotonic_ns.saturating_add(ns));
+        loop {
+            let (ready, wake_at) = check(self);
+            if ready > 0 {
+                return ready;
+            }
+            let next = match (wake_at, limit) {
+                (Some(a), Some(b)) => a.min(b),
+                (next, None) | (None, next) => match next {
+                    Some(next) => next,
+                    None => {
+                        tracing::warn!("poll: no fd can become ready, returning early");
+                        return 0;
+                    }
+                },
+            };
+            if next <= self.now.get().monotonic_ns {
+                return 0;
+            }
+            self.sleep_until(next);
+        }
+    }
+
+    pub(crate) fn ppoll_time64(
+        &mut self,
+        mem: &mut Memory,
+        fds: u32,
+        nfds: u32,
+        tsp: u32,
+        _sigmask: u32,
+        _sigsetsize: u32,
+    ) -> KernelResult {
+        let timeout = match tsp {
+            0 => None,
+            tsp => Some(read_timespec(mem, tsp)?),
+        };
+        // This method is called during CRT init for stdin/out/err, with no events
+        let fds = mem
+            .slice_mut::<PollFd>(fds, nfds)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(self.wait_ready(timeout, |kernel| {
+            let mut ready = 0;
+            let mut wake_at = None;
+            for pollfd in fds.iter_mut() {
+                pollfd.revents = if pollfd.fd < 0 {
+                    0
+                } else if let Ok(file) = kernel.files.get(pollfd.fd) {
+                    wake_at = min_some(wake_at, file.file.wake_at());
+                    let always = libc_riscv32::POLLERR | libc_riscv32::POLLHUP;
+                    file.file.poll() & (pollfd.events | always)
+                } else {
+                    libc_riscv32::POLLNVAL
+                };
+                if pollfd.revents != 0 {
+                    ready += 1;
+                }
+            }
+            (ready, wake_at)
+        }))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn pselect6_time64(
+        &mut self,
+        mem: &mut Memory,
+        nfds: i32,
+        readfds: u32,
+        writefds: u32,
+        exceptfds: u32,
+        timeout: u32,
+        _sig: u32,
+    ) -> KernelResult {
+        if !(0..=FdTable::LIMIT).contains(&nfds) {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let nfds = nfds as u32;
+        // `fd_set`s are arrays of 32-bit longs
+        let words = nfds.div_ceil(32);
+        let addrs = [readfds, writefds, exceptfds];
+        let mut wanted = vec![];
+        for addr in addrs {
+            wanted.push(match addr {
+                0 => vec![0; words as usize],
+                addr => mem
+                    .read_vec::<u32>(addr, words)
+                    .map_err(|_| libc_riscv32::EFAULT)?,
+            });
+        }
+        let timeout = match timeout {
+            0 => None,
+            tsp => Some(read_timespec(mem, tsp)?),
+        };
+        let fds: Vec<(i32, [bool; 3])> = (0..nfds)
+            .filter_map(|fd| {
+                let (word, bit) = ((fd / 32) as usize, 1 << (fd % 32));
+                let sets = [0, 1, 2].map(|set| wanted[set][word] & bit != 0);
+                sets.contains(&true).then_some((fd as i32, sets))
+            })
+            .collect();
+        if fds.iter().any(|&(fd, _)| !self.files.contains(fd)) {
+            return Err(libc_riscv32::EBADF);
+        }
+
+        let mut found = vec![vec![0u32; words as usize]; 3];
+        let ready = self.wait_ready(timeout, |kernel| {
+            let mut ready = 0;
+            let mut wake_at = None;
+            found.iter_mut().for_each(|set| set.fill(0));
+            for &(fd, sets) in &fds {
+                let file = kernel.files.get(fd).unwrap();
+                wake_at = min_some(wake_at, file.file.wake_at());
+                let events = file.file.poll();
+                let hits = [
+                    events & (libc_riscv32::POLLIN | libc_riscv32::POLLHUP | libc_riscv32::POLLERR),
+                    events & (libc_riscv32::POLLOUT | libc_riscv32::POLLERR),
+                    events & libc_riscv32::POLLPRI,
+                ];
+                for set in 0..3 {
+                    if sets[set] && hits[set] != 0 {
+                        found[set][fd as usize / 32] |= 1 << (fd % 32);
+                        ready += 1;
+                    }
+                }
+            }
+            (ready, wake_at)
+        });
+
+        for (addr, set) in addrs.into_iter().zip(&found) {
+            if addr != 0 {
+                mem.copy_to(addr, set).map_err(|_| libc_riscv32::EFAULT)?;
+            }
+        }
+        Ok(ready)
+    }
+
+    pub(crate) fn eventfd2(&mut self, initval: u32, flags: u32) -> KernelResult {
+        let known =
+            libc_riscv32::EFD_SEMAPHORE | libc_riscv32::EFD_CLOEXEC | libc_riscv32::EFD_NONBLOCK;
+        if flags & !known != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let semaphore = flags & libc_riscv32::EFD_SEMAPHORE != 0;
+        let fd = self.files.insert(OpenFile {
+            file: Box::new(EventFd::new(initval, semaphore)),
+            path: "anon_inode:[eventfd]".to_string(),
+            flags: libc_riscv32::O_RDWR | (flags & libc_riscv32::O_NONBLOCK),
+            dir_pos: 0,
+        })?;
+
+        Ok(fd as u32)
+    }
+
+    pub(crate) fn timerfd_create(&mut self, clockid: u32, flags: u32) -> KernelResult {
+        if flags & !(libc_riscv32::TFD_CLOEXEC | libc_riscv32::TFD_NONBLOCK) != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let timer = TimerFd::new(clockid, self.now.clone()).ok_or(libc_riscv32::EINVAL)?;
+        let fd = self.files.insert(OpenFile {
+            file: Box::new(timer),
+            path: "anon_inode:[timerfd]".to_string(),
+            flags: libc_riscv32::O_RDONLY | (flags & libc_riscv32::O_NONBLOCK),
+            dir_pos: 0,
+        })?;
+
+        Ok(fd as u32)
+    }
+
+    pub(crate) fn timerfd_settime64(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        flags: u32,
+        new_value: u32,
+        old_value: u32,
+    ) -> KernelResult {
+        let known = libc_riscv32::TFD_TIMER_ABSTIME | libc_riscv32::TFD_TIMER_CANCEL_ON_SET;
+        if flags & !known != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let new = mem
+            .read::<Itimerspec64>(new_value)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        let value = new.it_value.to_ns().ok_or(libc_riscv32::EINVAL)?;
+        let interval = new.it_interval.to_ns().ok_or(libc_riscv32::EINVAL)?;
+
+        let timer = self.files.get_as::<TimerFd>(fd)?;
+        let absolute = flags & libc_riscv32::TFD_TIMER_ABSTIME != 0;
+        let old = timer.set(value, interval, absolute);
+        if old_value != 0 {
+            write_itimerspec(mem, old_value, old)?;
+        }
+
+        Ok(0)
+    }
+
+    pub(crate) fn timerfd_gettime64(
+        &mut self,
+        mem: &mut Memory,
+        fd: i32,
+        curr_value: u32,
+    ) -> KernelResult {
+        let current = self.files.get_as::<TimerFd>(fd)?.get();
+        write_itimerspec(mem, curr_value, current)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn epoll_create1(&mut self, flags: u32) -> KernelResult {
+        if flags & !libc_riscv32::EPOLL_CLOEXEC != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let fd = self.files.insert(OpenFile {
+            file: Box::new(Epoll::default()),
+            path: "anon_inode:[eventpoll]".to_string(),
+            flags: libc_riscv32::O_RDWR,
+            dir_pos: 0,
+        })?;
+
+        Ok(fd as u32)
+    }
+
+    pub(crate) fn epoll_ctl(
+        &mut self,
+        mem: &Memory,
+        epfd: i32,
+        op: u32,
+        fd: i32,
+        event: u32,
+    ) -> KernelResult {
+        if !self.files.contains(fd) {
+            return Err(libc_riscv32::EBADF);
+        }
+        if fd == epfd {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let event = match op {
+            libc_riscv32::EPOLL_CTL_DEL => EpollEvent::default(),
+            _ => mem.read(event).map_err(|_| libc_riscv32::EFAULT)?,
+        };
+
+        let interest = &mut self.files.get_as::<Epoll>(epfd)?.interest;
+        match (op, interest.contains_key(&fd)) {
+            (libc_riscv32::EPOLL_CTL_ADD, true) => return Err(libc_riscv32::EEXIST),
+            (libc_riscv32::EPOLL_CTL_ADD, false) => {
+                interest.insert(fd, event);
+            }
+            (libc_riscv32::EPOLL_CTL_MOD | libc_riscv32::EPOLL_CTL_DEL, false) => {
+                return Err(libc_riscv32::ENOENT)
+            }
+            (libc_riscv32::EPOLL_CTL_MOD, true) => {
+                interest.insert(fd, event);
+            }
+            (libc_riscv32::EPOLL_CTL_DEL, true) => {
+                interest.remove(&fd);
+            }
+            _ => return Err(libc_riscv32::EINVAL),
+        }
+
+        Ok(0)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn epoll_pwait(
+        &mut self,
+        mem: &mut Memory,
+        epfd: i32,
+        events: u32,
+        maxevents: i32,
+        timeout_ms: i32,
+        _sigmask: u32,
+        _sigsetsize: u32,
+    ) -> KernelResult {
+        let timeout = u64::try_from(timeout_ms).ok().map(|ms| ms * 1_000_000);
+        self.epoll_wait(mem, epfd, events, maxevents, timeout)
+    }
+
+    pub(crate) fn epoll_pwait2(
+        &mut self,
+        mem: &mut Memory,
+        epfd: i32,
+        events: u32,
+        maxevents: i32,
+        timeout: u32,
+        _sigmask: u32,
+    ) -> KernelResult {
+        let timeout = match timeout {
+            0 => None,
+            tsp => Some(read_timespec(mem, tsp)?),
+        };
+        self.epoll_wait(mem, epfd, events, maxevents, timeout)
+    }
+
+    fn epoll_wait(
+        &mut self,
+        mem: &mut Memory,
+        epfd: i32,
+        events: u32,
+        maxevents: i32,
+        timeout_ns: Option<u64>,
+    ) -> KernelResult {
+        if maxevents <= 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        self.files.get_as::<Epoll>(epfd)?;
+
+        let mut out = vec![];
+        self.wait_ready(timeout_ns, |kernel| {
+            out.clear();
+            let interest = kernel.files.get_as::<Epoll>(epfd).unwrap().interest.clone();
+            let mut wake_at = None;
+            for (&fd, event) in &interest {
+                // Closed fds leave the interest list
+                let Ok(file) = kernel.files.get(fd) else {
+                    continue;
+                };
+                wake_at = min_some(wake_at, file.file.wake_at());
+                let always = libc_riscv32::EPOLLERR | libc_riscv32::EPOLLHUP;
+                let ready = file.file.poll() as u32 & (event.events | always);
+                if ready == 0 || out.len() == maxevents as usize {
+                    continue;
+                }
+                out.push(EpollEvent {
+                    events: ready,
+                    data: event.data,
+                });
+                if event.events & libc_riscv32::EPOLLONESHOT != 0 {
+                    // Disabled until the next EPOLL_CTL_MOD
+                    let epoll = kernel.files.get_as::<Epoll>(epfd).unwrap();
+                    epoll.interest.get_mut(&fd).unwrap().events &= libc_riscv32::EPOLLONESHOT;
+                }
+            }
+            (out.len() as u32, wake_at)
+        });
+
+        mem.write_slice(events, &out)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        Ok(out.len() as u32)
+    }
+}
+
+fn min_some(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+pub(crate) fn read_timespec(mem: &Memory, addr: u32) -> KernelResult<u64> {
+    mem.read::<Timespec64>(addr)
+        .map_err(|_| libc_riscv32::EFAULT)?
+        .to_ns()
+        .ok_or(libc_riscv32::EINVAL)
+}
+
+fn write_itimerspec(
+    mem: &mut Memory,
+    addr: u32,
+    (value, interval): (u64, u64),
+) -> KernelResult<()> {
+    let spec = Itimerspec64 {
+        it_interval: Timespec64::from_ns(interval),
+        it_value: Timespec64::from_ns(value),
+    };
+    mem.write(addr, spec).map_err(|_| libc_riscv32::EFAULT)
+}
@@ -0,0 +1,124 @@
+use riscv_vm::memory::Memory;
+
+use crate::{
+    errno::KernelResult,
+    hibernate::{StateReader, StateWriter},
+    MockLinux,
+};
+
+/// `TASK_COMM_LEN`, including the NUL.
+const COMM_LEN: usize = 16;
+
+/// Highest signal number, `_NSIG`.
+const NSIG: u32 = 64;
+
+/// Process attributes set through `prctl`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProcessAttrs {
+    /// Process name, without the NUL; from `argv[0]` or `PR_SET_NAME`
+    pub comm: Vec<u8>,
+    /// Once set, can't be cleared
+    pub no_new_privs: bool,
+    pub dumpable: bool,
+    /// Signal sent when the parent exits, which never happens
+    pub pdeathsig: u32,
+}
+
+impl Default for ProcessAttrs {
+    fn default() -> Self {
+        Self {
+            comm: vec![],
+            no_new_privs: false,
+            dumpable: true,
+            pdeathsig: 0,
+        }
+    }
+}
+
+impl ProcessAttrs {
+    /// Set `comm` like the kernel, truncating to 15 bytes.
+    pub fn set_comm(&mut self, name: &[u8]) {
+        let len = name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(name.len())
+            .min(COMM_LEN - 1);
+        self.comm = name[..len].to_vec();
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.bytes(&self.comm);
+        w.u8(self.no_new_privs as u8);
+        w.u8(self.dumpable as u8);
+        w.u32(self.pdeathsig);
+    }
+
+    pub fn restore(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.comm = r.bytes()?.to_vec();
+        self.no_new_privs = r.bool()?;
+        self.dumpable = r.bool()?;
+        self.pdeathsig = r.u32()?;
+
+        Ok(())
+    }
+}
+
+impl MockLinux {
+    pub(crate) fn prctl(
+        &mut self,
+        mem: &mut Memory,
+        option: u32,
+        arg2: u32,
+        arg3: u32,
+        arg4: u32,
+        arg5: u32,
+    ) -> KernelResult {
+        let attrs = &mut self.attrs;
+        match option {
+            libc_riscv32::PR_SET_PDEATHSIG => {
+                if arg2 > NSIG {
+                    return Err(libc_riscv32::EINVAL);
+                }
+                attrs.pdeathsig = arg2;
+            }
+            libc_riscv32::PR_GET_PDEATHSIG => {
+                mem.write(arg2, attrs.pdeathsig)
+                    .map_err(|_| libc_riscv32::EFAULT)?;
+            }
+            libc_riscv32::PR_GET_DUMPABLE => return Ok(attrs.dumpable as u32),
+            libc_riscv32::PR_SET_DUMPABLE => match arg2 {
+                0 | 1 => attrs.dumpable = arg2 == 1,
+                _ => return Err(libc_riscv32::EINVAL),
+            },
+            libc_riscv32::PR_SET_NAME => {
+                let name = mem
+                    .slice::<u8>(arg2, COMM_LEN as u32)
+                    .map_err(|_| libc_riscv32::EFAULT)?;
+                attrs.set_comm(name);
+            }
+            libc_riscv32::PR_GET_NAME => {
+                let mut name = [0u8; COMM_LEN];
+                name[..attrs.comm.len()].copy_from_slice(&attrs.comm);
+                mem.copy_to(arg2, &name).map_err(|_| libc_riscv32::EFAULT)?;
+            }
+            libc_riscv32::PR_SET_NO_NEW_PRIVS => {
+                if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(libc_riscv32::EINVAL);
+                }
+                attrs.no_new_privs = true;
+            }
+            libc_riscv32::PR_GET_NO_NEW_PRIVS => {
+                if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(libc_riscv32::EINVAL);
+                }
+                return Ok(attrs.no_new_privs as u32);
+            }
+            _ => {
+                tracing::warn!("prctl: unsupported option {option}");
+                return Err(libc_riscv32::EINVAL);
+            }
+        }
+
+        Ok(0)
+    }
+}
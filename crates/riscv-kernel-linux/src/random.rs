@@ -0,0 +1,92 @@
+use std::{fs::File, io::Read};
+
+use riscv_vm::memory::Memory;
+
+use crate::{
+    errno::{host_errno, KernelResult},
+    hibernate::{StateReader, StateWriter},
+    MockLinux,
+};
+
+/// Where `getrandom` gets its bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Entropy {
+    /// All zeros, so runs are reproducible
+    #[default]
+    Zero,
+    /// A fixed pseudo-random stream, reproducible but not constant
+    Seeded(u64),
+    /// The host's `/dev/urandom`
+    Host,
+}
+
+impl Entropy {
+    fn fill(&mut self, buf: &mut [u8]) -> KernelResult<()> {
+        match self {
+            Self::Zero => buf.fill(0),
+            Self::Seeded(state) => {
+                for chunk in buf.chunks_mut(8) {
+                    let bytes = splitmix64(state).to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+            }
+            Self::Host => File::open("/dev/urandom")
+                .and_then(|mut urandom| urandom.read_exact(buf))
+                .map_err(host_errno)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Entropy {
+    pub(crate) fn save(&self, w: &mut StateWriter) {
+        match *self {
+            Self::Zero => w.u8(0),
+            Self::Seeded(state) => {
+                w.u8(1);
+                w.u64(state);
+            }
+            Self::Host => w.u8(2),
+        }
+    }
+
+    /// The entropy source [`Entropy::save`] saved, carrying on from where
+    /// a seeded stream was.
+    pub(crate) fn restore(&mut self, r: &mut StateReader) -> Result<(), String> {
+        *self = match r.u8()? {
+            0 => Self::Zero,
+            1 => Self::Seeded(r.u64()?),
+            2 => Self::Host,
+            source => return Err(format!("unknown entropy source {source}")),
+        };
+
+        Ok(())
+    }
+}
+
+/// The next output of SplitMix64, advancing `state`.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl MockLinux {
+    pub(crate) fn getrandom(
+        &mut self,
+        mem: &mut Memory,
+        buf: u32,
+        len: u32,
+        _flags: u32,
+    ) -> KernelResult {
+        let slice = mem
+            .slice_mut::<u8>(buf, len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        self.entropy.fill(slice)?;
+
+        Ok(len)
+    }
+}
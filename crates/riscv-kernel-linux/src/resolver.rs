@@ -0,0 +1,83 @@
+use std::net::IpAddr;
+
+use crate::MockLinux;
+
+/// Host names the guest can resolve, for hermetic tests.
+///
+/// There's no network, so nothing answers DNS queries. Instead, the names
+/// are listed in `/etc/hosts`, which `getaddrinfo` and `gethostbyname` in
+/// musl and glibc read before asking a DNS server. Other names fail to
+/// resolve at once, since the guest can't open a socket to send a query.
+/// `localhost` and the guest's host name always resolve to loopback.
+///
+/// A root filesystem with an `/etc` of its own replaces this one, and
+/// with it the table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostTable {
+    entries: Vec<(String, IpAddr)>,
+}
+
+impl HostTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `name` to `addr`, as well as any addresses it already has.
+    pub fn insert(&mut self, name: impl Into<String>, addr: IpAddr) {
+        self.entries.push((name.into(), addr));
+    }
+
+    /// The addresses `name` resolves to, in the order they were inserted.
+    pub fn lookup(&self, name: &str) -> Vec<IpAddr> {
+        self.entries
+            .iter()
+            .filter(|(entry, _)| entry == name)
+            .map(|&(_, addr)| addr)
+            .collect()
+    }
+
+    /// The contents of `/etc/hosts`, for a guest called `hostname`.
+    fn hosts(&self, hostname: &str) -> String {
+        let mut hosts = format!(
+            "127.0.0.1\tlocalhost\n::1\tlocalhost ip6-localhost ip6-loopback\n127.0.1.1\t{hostname}\n"
+        );
+        for (name, addr) in &self.entries {
+            hosts += &format!("{addr}\t{name}\n");
+        }
+        hosts
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, IpAddr)> for HostTable {
+    fn from_iter<I: IntoIterator<Item = (S, IpAddr)>>(iter: I) -> Self {
+        let mut table = Self::new();
+        for (name, addr) in iter {
+            table.insert(name, addr);
+        }
+        table
+    }
+}
+
+impl MockLinux {
+    /// Mount the guest's default `/etc`, describing its user, host name
+    /// and the names it can resolve.
+    pub(crate) fn mount_etc(&mut self) {
+        let hostname = &self.system.nodename;
+        let mut etc = self.identity.etc();
+        etc.add_file("hostname", 0o644, format!("{hostname}\n").into_bytes());
+        etc.add_file("hosts", 0o644, self.hosts.hosts(hostname).into_bytes());
+        // With no servers listed, libc tries loopback, where it can't open
+        // a socket, so unknown names fail at once
+        let resolv = "# Names resolve only through /etc/hosts\n";
+        etc.add_file("resolv.conf", 0o644, resolv.as_bytes().to_vec());
+        // glibc asks DNS first without this
+        let nsswitch = "passwd: files\ngroup: files\nhosts: files\n";
+        etc.add_file("nsswitch.conf", 0o644, nsswitch.as_bytes().to_vec());
+        self.vfs.mount("/etc", Box::new(etc));
+    }
+
+    /// The names the guest can resolve.
+    pub fn hosts(&self) -> &HostTable {
+        &self.hosts
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt::Write;
+
+use riscv_vm::{
+    memory::Memory,
+    riscv_inst::{Extension, Extensions},
+};
+
+use crate::{errno::KernelResult, MockLinux, Timespec64};
+
+/// The CPUs the guest is told it runs on.
+///
+/// The guest only ever runs on one hart, but runtimes size their thread pools
+/// from `sched_getaffinity`, so the count is reported consistently there, in
+/// `riscv_hwprobe` and in `/proc/cpuinfo`. All CPUs are identical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTopology {
+    /// Number of online CPUs, at least 1
+    pub cpus: u32,
+    /// ISA of every CPU, set from the hart when the ELF is loaded
+    pub isa: Extensions,
+}
+
+impl Default for CpuTopology {
+    fn default() -> Self {
+        Self {
+            cpus: 1,
+            isa: Extensions::default(),
+        }
+    }
+}
+
+impl CpuTopology {
+    /// Bytes in the kernel's `cpumask`, which is a whole number of `long`s.
+    fn mask_size(&self) -> u32 {
+        self.cpus.max(1).div_ceil(32) * 4
+    }
+
+    /// The affinity mask with every CPU set.
+    fn online_mask(&self) -> Vec<u8> {
+        let mut mask = vec![0; self.mask_size() as usize];
+        for cpu in 0..self.cpus.max(1) as usize {
+            mask[cpu / 8] |= 1 << (cpu % 8);
+        }
+        mask
+    }
+
+    /// The value of a `riscv_hwprobe` key, or `None` if the key is unknown.
+    fn hwprobe(&self, key: i64) -> Option<u64> {
+        let has = |ext| self.isa.contains(ext);
+        Some(match key {
+            // Not a real implementation, so no vendor IDs
+            libc_riscv32::RISCV_HWPROBE_KEY_MVENDORID
+            | libc_riscv32::RISCV_HWPROBE_KEY_MARCHID
+            | libc_riscv32::RISCV_HWPROBE_KEY_MIMPID => 0,
+            libc_riscv32::RISCV_HWPROBE_KEY_BASE_BEHAVIOR => {
+                if has(Extension::I) && has(Extension::M) && has(Extension::A) {
+                    libc_riscv32::RISCV_HWPROBE_BASE_BEHAVIOR_IMA
+                } else {
+                    0
+                }
+            }
+            libc_riscv32::RISCV_HWPROBE_KEY_IMA_EXT_0 => {
+                let mut exts = 0;
+                if has(Extension::F) && has(Extension::D) {
+                    exts |= libc_riscv32::RISCV_HWPROBE_IMA_FD;
+                }
+                if has(Extension::C) {
+                    exts |= libc_riscv32::RISCV_HWPROBE_IMA_C;
+                }
+                exts
+            }
+            // Guest memory accesses are host unaligned loads and stores
+            libc_riscv32::RISCV_HWPROBE_KEY_CPUPERF_0 => {
+                libc_riscv32::RISCV_HWPROBE_MISALIGNED_FAST
+            }
+            libc_riscv32::RISCV_HWPROBE_KEY_ZICBOZ_BLOCK_SIZE => 0,
+            _ => return None,
+        })
+    }
+
+    /// The contents of `/proc/cpuinfo`.
+    pub(crate) fn cpuinfo(&self) -> String {
+        // Like the kernel, list single-letter extensions then the Z extensions
+        let isa = self.isa.without(Extension::S).to_string() + "_zifencei";
+        let mut out = String::new();
+        for cpu in 0..self.cpus.max(1) {
+            let _ = writeln!(out, "processor\t: {cpu}");
+            let _ = writeln!(out, "hart\t\t: {cpu}");
+            let _ = writeln!(out, "isa\t\t: {isa}");
+            let _ = writeln!(out, "mmu\t\t: sv32");
+            let _ = writeln!(out, "mvendorid\t: 0x0");
+            let _ = writeln!(out, "marchid\t\t: 0x0");
+            let _ = writeln!(out, "mimpid\t\t: 0x0");
+            let _ = writeln!(out);
+        }
+        out
+    }
+}
+
+/// `struct riscv_hwprobe`.
+#[repr(C)]
+struct HwprobePair {
+    key: i64,
+    value: u64,
+}
+
+impl MockLinux {
+    /// Whether `pid` names the guest process (or its only thread).
+    fn is_self(&mut self, pid: u32) -> bool {
+        pid == 0 || pid == self.getpid().unwrap()
+    }
+
+    pub(crate) fn sched_yield(&mut self) -> KernelResult {
+        // Nothing else to run
+        Ok(0)
+    }
+
+    pub(crate) fn sched_getaffinity(
+        &mut self,
+        mem: &mut Memory,
+        pid: u32,
+        len: u32,
+        mask: u32,
+    ) -> KernelResult {
+        if !self.is_self(pid) {
+            return Err(libc_riscv32::ESRCH);
+        }
+        if len < self.topology.cpus.div_ceil(8) || !len.is_multiple_of(4) {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let online = self.topology.online_mask();
+        let size = online.len().min(len as usize);
+        mem.copy_to(mask, &online[..size])
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(size as u32)
+    }
+
+    pub(crate) fn sched_setaffinity(
+        &mut self,
+        mem: &Memory,
+        pid: u32,
+        len: u32,
+        mask: u32,
+    ) -> KernelResult {
+        if !self.is_self(pid) {
+            return Err(libc_riscv32::ESRCH);
+        }
+        let mask = mem
+            .slice::<u8>(mask, len)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        let online = self.topology.online_mask();
+        // Any online CPU will do, since they all run on the one hart
+        if !mask.iter().zip(&online).any(|(a, b)| a & b != 0) {
+            return Err(libc_riscv32::EINVAL);
+        }
+
+        Ok(0)
+    }
+
+    pub(crate) fn getcpu(
+        &mut self,
+        mem: &mut Memory,
+        cpu: u32,
+        node: u32,
+        _tcache: u32,
+    ) -> KernelResult {
+        for ptr in [cpu, node] {
+            if ptr != 0 {
+                mem.write(ptr, 0u32).map_err(|_| libc_riscv32::EFAULT)?;
+            }
+        }
+
+        Ok(0)
+    }
+
+    pub(crate) fn sched_getscheduler(&mut self, pid: u32) -> KernelResult {
+        if !self.is_self(pid) {
+            return Err(libc_riscv32::ESRCH);
+        }
+        Ok(libc_riscv32::SCHED_OTHER)
+    }
+
+    pub(crate) fn sched_setscheduler(
+        &mut self,
+        mem: &Memory,
+        pid: u32,
+        policy: u32,
+        param: u32,
+    ) -> KernelResult {
+        if !self.is_self(pid) {
+            return Err(libc_riscv32::ESRCH);
+        }
+        let priority = mem.read::<i32>(param).map_err(|_| libc_riscv32::EFAULT)?;
+        match policy & !libc_riscv32::SCHED_RESET_ON_FORK {
+            libc_riscv32::SCHED_OTHER | libc_riscv32::SCHED_BATCH | libc_riscv32::SCHED_IDLE
+                if priority == 0 =>
+            {
+                Ok(0)
+            }
+            // The guest runs unprivileged
+            libc_riscv32::SCHED_FIFO | libc_riscv32::SCHED_RR => Err(libc_riscv32::EPERM),
+            _ => Err(libc_riscv32::EINVAL),
+        }
+    }
+
+    pub(crate) fn sched_getparam(
+        &mut self,
+        mem: &mut Memory,
+        pid: u32,
+        param: u32,
+    ) -> KernelResult {
+        if !self.is_self(pid) {
+            return Err(libc_riscv32::ESRCH);
+        }
+        mem.write(param, 0i32).map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn sched_get_priority_max(&mut self, policy: u32) -> KernelResult {
+        match policy {
+            libc_riscv32::SCHED_FIFO | libc_riscv32::SCHED_RR => Ok(99),
+            libc_riscv32::SCHED_OTHER | libc_riscv32::SCHED_BATCH | libc_riscv32::SCHED_IDLE => {
+                Ok(0)
+            }
+            _ => Err(libc_riscv32::EINVAL),
+        }
+    }
+
+    pub(crate) fn sched_get_priority_min(&mut self, policy: u32) -> KernelResult {
+        match policy {
+            libc_riscv32::SCHED_FIFO | libc_riscv32::SCHED_RR => Ok(1),
+            libc_riscv32::SCHED_OTHER | libc_riscv32::SCHED_BATCH | libc_riscv32::SCHED_IDLE => {
+                Ok(0)
+            }
+            _ => Err(libc_riscv32::EINVAL),
+        }
+    }
+
+    pub(crate) fn sched_rr_get_interval_time64(
+        &mut self,
+        mem: &mut Memory,
+        pid: u32,
+        interval: u32,
+    ) -> KernelResult {
+        if !self.is_self(pid) {
+            return Err(libc_riscv32::ESRCH);
+        }
+        // Only SCHED_RR has a fixed timeslice
+        mem.write(interval, Timespec64::from_ns(0))
+            .map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn riscv_hwprobe(
+        &mut self,
+        mem: &mut Memory,
+        pairs: u32,
+        pair_count: u32,
+        cpusetsize: u32,
+        cpus: u32,
+        flags: u32,
+    ) -> KernelResult {
+        if flags & !libc_riscv32::RISCV_HWPROBE_WHICH_CPUS != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let online = self.topology.online_mask();
+        let mut cpuset = match (cpusetsize, cpus) {
+            (0, 0) => None,
+            (_, 0) => return Err(libc_riscv32::EFAULT),
+            _ => Some(
+                mem.slice::<u8>(cpus, cpusetsize)
+                    .map_err(|_| libc_riscv32::EFAULT)?
+                    .to_vec(),
+            ),
+        };
+        if let Some(cpuset) = &mut cpuset {
+            for (byte, online) in cpuset
+                .iter_mut()
+                .zip(online.iter().chain([0].iter().cycle()))
+            {
+                *byte &= online;
+            }
+        }
+
+        let pairs = mem
+            .slice_mut::<HwprobePair>(pairs, pair_count)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        if flags & libc_riscv32::RISCV_HWPROBE_WHICH_CPUS != 0 {
+            // Narrow the set to CPUs matching every pair. All CPUs are the
+            // same, so that's all of them or none.
+            let mut cpuset = cpuset.ok_or(libc_riscv32::EINVAL)?;
+            let matches = pairs
+                .iter()
+                .all(|pair| self.topology.hwprobe(pair.key) == Some(pair.value));
+            if !matches {
+                cpuset.fill(0);
+            }
+            mem.copy_to(cpus, &cpuset)
+                .map_err(|_| libc_riscv32::EFAULT)?;
+        } else {
+            if cpuset.is_some_and(|cpuset| cpuset.iter().all(|&byte| byte == 0)) {
+                return Err(libc_riscv32::EINVAL);
+            }
+            for pair in pairs {
+                match self.topology.hwprobe(pair.key) {
+                    Some(value) => pair.value = value,
+                    None => {
+                        pair.key = -1;
+                        pair.value = 0;
+                    }
+                }
+            }
+        }
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,151 @@
+use riscv_vm::{memory::Memory, shm::SharedMemory};
+
+use crate::{
+    errno::{host_errno, KernelResult},
+    vfs::{OpenFile, ShmFile},
+    MockLinux, PAGE_SIZE,
+};
+
+/// A `MAP_SHARED` region of guest memory.
+#[derive(Debug, Clone)]
+pub struct SharedRegion {
+    pub shm: SharedMemory,
+    /// Byte offset of the region's start into `shm`
+    pub offset: u64,
+    pub len: u32,
+}
+
+impl MockLinux {
+    /// The shared memory object behind guest descriptor `fd`, e.g. from `memfd_create`.
+    pub fn shared_memory(&mut self, fd: i32) -> Option<SharedMemory> {
+        self.files.get(fd).ok()?.file.shared_memory().cloned()
+    }
+
+    /// The POSIX shared memory object `/dev/shm/<name>`.
+    pub fn shm_object(&self, name: &str) -> Option<SharedMemory> {
+        self.shm.get(name)
+    }
+
+    /// The shared mapping containing guest address `addr`, and where it starts.
+    pub fn shared_region(&self, addr: u32) -> Option<(u32, &SharedRegion)> {
+        let (&start, region) = self.shared_regions.range(..=addr).next_back()?;
+        (addr - start < region.len).then_some((start, region))
+    }
+
+    pub(crate) fn map_shared(
+        &mut self,
+        mem: &mut Memory,
+        addr: u32,
+        region: SharedRegion,
+    ) -> KernelResult<()> {
+        // Mapping past the last page of the object would fault on the host
+        let end = region.offset + region.len as u64;
+        let obj_len = region.shm.len().map_err(host_errno)?;
+        if end > obj_len.next_multiple_of(PAGE_SIZE as u64) {
+            tracing::warn!("mmap: {end:#x} is past the end of {:?}", region.shm.name());
+            return Err(libc_riscv32::ENXIO);
+        }
+
+        self.unmap_shared(mem, addr, region.len)?;
+        mem.map_shared(addr, &region.shm, region.offset, region.len)
+            .map_err(|e| {
+                tracing::warn!("mmap: {e}");
+                libc_riscv32::ENOMEM
+            })?;
+        self.shared_regions.insert(addr, region);
+
+        Ok(())
+    }
+
+    /// Detach shared mappings overlapping `[addr, addr + len)`, leaving zeroed
+    /// private pages in their place. Parts outside the range stay mapped.
+    pub(crate) fn unmap_shared(
+        &mut self,
+        mem: &mut Memory,
+        addr: u32,
+        len: u32,
+    ) -> KernelResult<()> {
+        let end = addr as u64 + len as u64;
+        let overlapping: Vec<u32> = self
+            .shared_regions
+            .iter()
+            .filter(|(&start, region)| {
+                (start as u64) < end && start as u64 + region.len as u64 > addr as u64
+            })
+            .map(|(&start, _)| start)
+            .collect();
+
+        for start in overlapping {
+            let region = self.shared_regions.remove(&start).unwrap();
+            let region_end = start as u64 + region.len as u64;
+            if start < addr {
+                let head = SharedRegion {
+                    len: addr - start,
+                    ..region.clone()
+                };
+                self.shared_regions.insert(start, head);
+            }
+            if region_end > end {
+                let tail = SharedRegion {
+                    offset: region.offset + (end - start as u64),
+                    len: (region_end - end) as u32,
+                    ..region.clone()
+                };
+                self.shared_regions.insert(end as u32, tail);
+            }
+
+            let cut_start = start.max(addr);
+            let cut_len = (region_end.min(end) - cut_start as u64) as u32;
+            mem.unmap_shared(cut_start, cut_len)
+                .map_err(|_| libc_riscv32::ENOMEM)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn memfd_create(&mut self, mem: &Memory, name: u32, flags: u32) -> KernelResult {
+        let name = mem
+            .read_string(name, 250)
+            .map_err(|_| libc_riscv32::EFAULT)?;
+        if flags & !(libc_riscv32::MFD_CLOEXEC | libc_riscv32::MFD_ALLOW_SEALING) != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+
+        let shm = SharedMemory::new(&name).map_err(host_errno)?;
+        let fd = self.files.insert(OpenFile {
+            file: Box::new(ShmFile::new(shm, 0)),
+            path: format!("/memfd:{name}"),
+            flags: libc_riscv32::O_RDWR,
+            dir_pos: 0,
+        })?;
+        tracing::debug!("memfd_create: {name:?} -> {fd}");
+
+        Ok(fd as u32)
+    }
+
+    pub(crate) fn ftruncate(&mut self, fd: i32, len_low: u32, len_high: u32) -> KernelResult {
+        let len = ((len_high as u64) << 32 | len_low as u64) as i64;
+        if len < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let file = self.files.get(fd)?;
+        if file.flags & libc_riscv32::O_ACCMODE == libc_riscv32::O_RDONLY {
+            return Err(libc_riscv32::EINVAL);
+        }
+        file.file.truncate(len as u64)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn munmap(&mut self, mem: &mut Memory, addr: u32, len: u32) -> KernelResult {
+        if !addr.is_multiple_of(PAGE_SIZE) || len == 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        let len = len.next_multiple_of(PAGE_SIZE);
+        self.unmap_shared(mem, addr, len)?;
+        mem.poison_range(addr, len)
+            .map_err(|_| libc_riscv32::EINVAL)?;
+
+        Ok(0)
+    }
+}
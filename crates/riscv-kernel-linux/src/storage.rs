@@ -0,0 +1,74 @@
+use crate::{errno::KernelResult, vfs::FileKind, MockLinux};
+
+/// Limits on what the guest may store in its filesystems, so one guest
+/// can't fill a disk or memory shared with others.
+///
+/// Going over a limit fails the syscall that would, as a full disk or
+/// quota would on Linux; the guest keeps running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FsQuota {
+    /// Bytes the guest may write to files. The write that reaches the
+    /// limit is cut short
+    pub bytes_written: Option<u64>,
+    /// Files and directories the guest may create
+    pub files: Option<u64>,
+    /// Fail with `ENOSPC`, as on a full disk, rather than `EDQUOT`
+    pub disk_full: bool,
+}
+
+/// What the guest did with files this run, see [`MockLinux::fs_usage`].
+///
+/// Only regular files count: not consoles, pipes or event fds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FsUsage {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Files and directories created
+    pub files_created: u64,
+}
+
+impl FsQuota {
+    fn errno(&self) -> i32 {
+        if self.disk_full {
+            libc_riscv32::ENOSPC
+        } else {
+            libc_riscv32::EDQUOT
+        }
+    }
+}
+
+impl MockLinux {
+    /// What the guest did with files since it was loaded or reset.
+    pub fn fs_usage(&self) -> FsUsage {
+        self.fs_usage
+    }
+
+    /// Whether `fd` is a regular file, whose reads and writes are counted.
+    pub(crate) fn is_regular_file(&mut self, fd: i32) -> bool {
+        self.files
+            .get(fd)
+            .and_then(|file| file.file.stat())
+            .is_ok_and(|stat| stat.kind == FileKind::File)
+    }
+
+    /// How many of `len` bytes may be written to a file.
+    pub(crate) fn writable_bytes(&self, len: usize) -> KernelResult<usize> {
+        let Some(limit) = self.fs_quota.bytes_written else {
+            return Ok(len);
+        };
+        let left = limit.saturating_sub(self.fs_usage.bytes_written);
+        if left == 0 && len > 0 {
+            return Err(self.fs_quota.errno());
+        }
+
+        Ok(len.min(left.try_into().unwrap_or(usize::MAX)))
+    }
+
+    /// Fail if the guest can't create another file or directory.
+    pub(crate) fn check_file_quota(&self) -> KernelResult<()> {
+        match self.fs_quota.files {
+            Some(limit) if self.fs_usage.files_created >= limit => Err(self.fs_quota.errno()),
+            _ => Ok(()),
+        }
+    }
+}
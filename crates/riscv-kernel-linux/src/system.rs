@@ -0,0 +1,127 @@
+use riscv_vm::{marshal::GuestValue, memory::Memory};
+
+use crate::{errno::KernelResult, MockLinux, PAGE_SIZE};
+
+/// Length of each `struct utsname` field, including the NUL.
+const UTS_LEN: usize = 65;
+
+/// The machine the guest is told it runs on, by `uname` and `sysinfo`.
+///
+/// Guests branch on the kernel release to pick syscalls, and size caches
+/// from total RAM, so these are fixed rather than taken from the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemInfo {
+    /// Host name, also from `gethostname`
+    pub nodename: String,
+    /// Kernel release, e.g. `6.1.0`
+    pub release: String,
+    /// Kernel build, e.g. `#1 SMP`
+    pub version: String,
+    pub domainname: String,
+    /// RAM reported by `sysinfo`, in bytes. Free RAM is what the guest
+    /// hasn't touched of it.
+    pub total_ram: u64,
+}
+
+impl Default for SystemInfo {
+    fn default() -> Self {
+        Self {
+            nodename: "derisc".to_string(),
+            release: "6.1.0".to_string(),
+            version: "#1 SMP".to_string(),
+            domainname: "(none)".to_string(),
+            // The whole address space
+            total_ram: 1 << 32,
+        }
+    }
+}
+
+/// `struct new_utsname`.
+#[repr(C)]
+#[derive(GuestValue)]
+#[guest(size = 390)]
+struct Utsname {
+    sysname: [u8; UTS_LEN],
+    nodename: [u8; UTS_LEN],
+    release: [u8; UTS_LEN],
+    version: [u8; UTS_LEN],
+    machine: [u8; UTS_LEN],
+    domainname: [u8; UTS_LEN],
+}
+
+/// `s` as a NUL-terminated `utsname` field, truncated to fit.
+fn uts_field(s: &str) -> [u8; UTS_LEN] {
+    let mut field = [0; UTS_LEN];
+    let len = s.len().min(UTS_LEN - 1);
+    field[..len].copy_from_slice(&s.as_bytes()[..len]);
+    field
+}
+
+/// `struct sysinfo` on rv32, with 32-bit `long`s.
+#[repr(C)]
+#[derive(GuestValue, Debug, Default)]
+#[guest(size = 64)]
+struct Sysinfo32 {
+    uptime: i32,
+    /// 1, 5 and 15 minute load averages, in 1/65536ths
+    loads: [u32; 3],
+    totalram: u32,
+    freeram: u32,
+    sharedram: u32,
+    bufferram: u32,
+    totalswap: u32,
+    freeswap: u32,
+    procs: u16,
+    pad: u16,
+    totalhigh: u32,
+    freehigh: u32,
+    mem_unit: u32,
+    _f: [u8; 8],
+}
+
+impl MockLinux {
+    pub(crate) fn uname(&mut self, mem: &mut Memory, buf: u32) -> KernelResult {
+        let system = &self.system;
+        let uts = Utsname {
+            sysname: uts_field("Linux"),
+            nodename: uts_field(&system.nodename),
+            release: uts_field(&system.release),
+            version: uts_field(&system.version),
+            machine: uts_field("riscv32"),
+            domainname: uts_field(&system.domainname),
+        };
+        mem.write(buf, uts).map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+
+    pub(crate) fn sysinfo(&mut self, mem: &mut Memory, info: u32) -> KernelResult {
+        let (rss_bytes, _) = self.sample_rss(mem);
+        let total = self.system.total_ram;
+        // Like the kernel, count in pages if bytes don't fit in a long
+        let unit = if total > u32::MAX as u64 {
+            PAGE_SIZE
+        } else {
+            1
+        };
+        let units = |bytes: u64| (bytes / unit as u64).min(u32::MAX as u64) as u32;
+        let shared_bytes: u64 = self
+            .shared_regions
+            .values()
+            .map(|region| region.len as u64)
+            .sum();
+        let uptime = self.now.get().monotonic_ns / 1_000_000_000;
+        let sysinfo = Sysinfo32 {
+            uptime: uptime.min(i32::MAX as u64) as i32,
+            totalram: units(total),
+            freeram: units(total.saturating_sub(rss_bytes)),
+            sharedram: units(shared_bytes),
+            procs: 1,
+            mem_unit: unit,
+            ..Default::default()
+        };
+        mem.write(info, sysinfo).map_err(|_| libc_riscv32::EFAULT)?;
+
+        Ok(0)
+    }
+}
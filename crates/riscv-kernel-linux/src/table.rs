@@ -0,0 +1,214 @@
+use riscv_vm::{hart::Hart32, memory::Memory};
+use syscalls::riscv32::Sysno;
+
+use crate::{errno::KernelResult, MockLinux};
+
+/// A syscall argument, decoded from its register.
+pub(crate) trait SyscallArg: Sized {
+    fn from_reg(reg: u32) -> Self;
+
+    /// Format the argument for a trace.
+    fn strace(&self) -> String;
+}
+
+impl SyscallArg for u32 {
+    fn from_reg(reg: u32) -> Self {
+        reg
+    }
+
+    /// Addresses, flags and masks are clearer in hex.
+    fn strace(&self) -> String {
+        format!("{self:#x}")
+    }
+}
+
+impl SyscallArg for i32 {
+    fn from_reg(reg: u32) -> Self {
+        reg as i32
+    }
+
+    fn strace(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl SyscallArg for usize {
+    fn from_reg(reg: u32) -> Self {
+        reg as usize
+    }
+
+    fn strace(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Format a syscall's return value like strace, e.g. `3` or `-1 ENOENT`.
+pub(crate) fn strace_ret(ret: &KernelResult) -> String {
+    match ret {
+        Ok(value) => (*value as i32).to_string(),
+        Err(errno) => match libc_riscv32::errno_name(*errno) {
+            Some(name) => format!("-1 {name}"),
+            None => format!("-1 errno {errno}"),
+        },
+    }
+}
+
+/// Generate [`MockLinux::dispatch`] and [`strace_call`] from a list of
+/// `sysno => handler[context](arg: type, ...)` entries.
+///
+/// Arguments are taken from `a0` onward and decoded with [`SyscallArg`]. The
+/// context, `hart` and/or `mem`, is passed to the handler before them, using
+/// the names given in the leading `|hart, mem|`.
+macro_rules! syscall_table {
+    (
+        |$hart:ident, $mem:ident|
+        $(
+            $($sysno:ident)|+ => $handler:ident $([$($ctx:ident),*])? ($($arg:ident: $ty:ty),* $(,)?);
+        )*
+    ) => {
+        impl MockLinux {
+            /// Run the handler for `sysno` with `args` from `a0`-`a5`, or return
+            /// `None` if it isn't implemented.
+            #[allow(unused_mut, unused_variables)]
+            pub(crate) fn dispatch(
+                &mut self,
+                sysno: Sysno,
+                $hart: &mut Hart32,
+                $mem: &mut Memory,
+                args: [u32; 6],
+            ) -> Option<KernelResult> {
+                let mut regs = args.into_iter();
+                Some(match sysno {
+                    $(
+                        $(Sysno::$sysno)|+ => {
+                            $(let $arg = <$ty as SyscallArg>::from_reg(regs.next().unwrap());)*
+                            self.$handler($($($ctx,)*)? $($arg),*)
+                        }
+                    )*
+                    _ => return None,
+                })
+            }
+        }
+
+        /// Format a call like strace, e.g. `close(fd=3)`.
+        #[allow(unused_mut)]
+        pub(crate) fn strace_call(sysno: Sysno, args: [u32; 6]) -> String {
+            let mut regs = args.into_iter();
+            let args: Vec<String> = match sysno {
+                $(
+                    $(Sysno::$sysno)|+ => vec![$(
+                        format!(
+                            "{}={}",
+                            stringify!($arg).trim_start_matches('_'),
+                            <$ty as SyscallArg>::from_reg(regs.next().unwrap()).strace(),
+                        )
+                    ),*],
+                )*
+                _ => args.iter().map(|arg| format!("{arg:#x}")).collect(),
+            };
+            format!("{}({})", sysno.name(), args.join(", "))
+        }
+    };
+}
+
+syscall_table! {
+    |hart, mem|
+    ioctl => ioctl[mem](fd: i32, request: u32, arg: u32);
+    openat => openat[mem](dirfd: i32, pathname: u32, flags: u32, mode: u32);
+    close => close(fd: i32);
+    read => read[mem](fd: i32, buf: u32, count: u32);
+    readv => readv[mem](fd: i32, iov: u32, iovcnt: i32);
+    pread64 => pread64[mem](fd: i32, buf: u32, count: u32, offset_low: u32, offset_high: u32);
+    preadv => preadv[mem](fd: i32, iov: u32, iovcnt: i32, offset_low: u32, offset_high: u32);
+    lseek => llseek[mem](fd: i32, offset_high: u32, offset_low: u32, result: u32, whence: u32);
+    getdents64 => getdents64[mem](fd: i32, dirp: u32, count: u32);
+    mkdirat => mkdirat[mem](dirfd: i32, pathname: u32, mode: u32);
+    unlinkat => unlinkat[mem](dirfd: i32, pathname: u32, flags: u32);
+    renameat2 => renameat2[mem](olddirfd: i32, oldpath: u32, newdirfd: i32, newpath: u32, flags: u32);
+    write => write[mem](fd: i32, buf: u32, count: u32);
+    writev => writev[mem](fd: i32, iov: u32, iovcnt: i32);
+    pwrite64 => pwrite64[mem](fd: i32, buf: u32, count: u32, offset_low: u32, offset_high: u32);
+    pwritev => pwritev[mem](fd: i32, iov: u32, iovcnt: i32, offset_low: u32, offset_high: u32);
+    readlinkat => readlinkat[mem](dirfd: i32, pathname: u32, buf: u32, bufsiz: usize);
+    // The guest only ever has one thread, so `exit` ends the process too
+    exit | exit_group => exit_group[hart, mem](status: i32);
+    set_tid_address => set_tid_address[mem](tidptr: u32);
+    futex | futex_time64 => futex[mem](uaddr: u32, op: u32, val: u32, utime: u32, uaddr2: u32, val3: u32);
+    set_robust_list => set_robust_list[mem](head: u32, len: u32);
+    tgkill => tgkill(tgid: i32, pid: i32, sig: i32);
+    rt_sigaction => rt_sigaction[mem](sig: u32, act: u32, oldact: u32, sigsetsize: u32);
+    rt_sigprocmask => rt_sigprocmask[mem](how: u32, set: u32, oldset: u32, sigsetsize: u32);
+    getpid => getpid();
+    gettid => gettid();
+    getuid => getuid();
+    geteuid => geteuid();
+    getgid => getgid();
+    getegid => getegid();
+    setuid => setuid(uid: u32);
+    setgid => setgid(gid: u32);
+    setreuid => setreuid(ruid: u32, euid: u32);
+    setregid => setregid(rgid: u32, egid: u32);
+    setresuid => setresuid(ruid: u32, euid: u32, suid: u32);
+    setresgid => setresgid(rgid: u32, egid: u32, sgid: u32);
+    getresuid => getresuid[mem](ruid: u32, euid: u32, suid: u32);
+    getresgid => getresgid[mem](rgid: u32, egid: u32, sgid: u32);
+    getgroups => getgroups[mem](size: u32, list: u32);
+    setgroups => setgroups[mem](size: u32, list: u32);
+    umask => umask(mask: u32);
+    uname => uname[mem](buf: u32);
+    sysinfo => sysinfo[mem](info: u32);
+    brk => brk[mem](addr: u32);
+    mmap => mmap[mem](addr: u32, len: u32, prot: u32, flags: u32, fd: i32, pgoff: u32);
+    munmap => munmap[mem](addr: u32, len: u32);
+    memfd_create => memfd_create[mem](name: u32, flags: u32);
+    ftruncate => ftruncate(fd: i32, len_low: u32, len_high: u32);
+    mprotect => mprotect[mem](addr: u32, len: u32, prot: u32);
+    riscv_hwprobe => riscv_hwprobe[mem](pairs: u32, pair_count: u32, cpusetsize: u32, cpus: u32, flags: u32);
+    prctl => prctl[mem](option: u32, arg2: u32, arg3: u32, arg4: u32, arg5: u32);
+    sched_yield => sched_yield();
+    sched_getaffinity => sched_getaffinity[mem](pid: u32, len: u32, mask: u32);
+    sched_setaffinity => sched_setaffinity[mem](pid: u32, len: u32, mask: u32);
+    getcpu => getcpu[mem](cpu: u32, node: u32, tcache: u32);
+    sched_getscheduler => sched_getscheduler(pid: u32);
+    sched_setscheduler => sched_setscheduler[mem](pid: u32, policy: u32, param: u32);
+    sched_getparam => sched_getparam[mem](pid: u32, param: u32);
+    sched_get_priority_max => sched_get_priority_max(policy: u32);
+    sched_get_priority_min => sched_get_priority_min(policy: u32);
+    getrusage => getrusage[hart, mem](who: i32, usage: u32);
+    getrlimit => getrlimit[mem](resource: u32, rlim: u32);
+    clock_gettime64 => clock_gettime64[hart, mem](clockid: u32, tp: u32);
+    clock_getres_time64 => clock_getres_time64[hart, mem](clockid: u32, res: u32);
+    clock_settime64 => clock_settime64[mem](clockid: u32, tp: u32);
+    clock_nanosleep_time64 => clock_nanosleep_time64[mem](clockid: u32, flags: u32, request: u32, remain: u32);
+    sched_rr_get_interval_time64 => sched_rr_get_interval_time64[mem](pid: u32, interval: u32);
+    rt_sigtimedwait_time64 => rt_sigtimedwait_time64[mem](set: u32, info: u32, timeout: u32, sigsetsize: u32);
+    socket => socket(domain: u32, ty: u32, protocol: u32);
+    socketpair => socketpair[mem](domain: u32, ty: u32, protocol: u32, sv: u32);
+    bind => bind[mem](fd: i32, addr: u32, addrlen: u32);
+    listen => listen(fd: i32, backlog: i32);
+    accept => accept[mem](fd: i32, addr: u32, addrlen: u32);
+    accept4 => accept4[mem](fd: i32, addr: u32, addrlen: u32, flags: u32);
+    connect => connect[mem](fd: i32, addr: u32, addrlen: u32);
+    getsockname => getsockname[mem](fd: i32, addr: u32, addrlen: u32);
+    getpeername => getpeername[mem](fd: i32, addr: u32, addrlen: u32);
+    sendto => sendto[mem](fd: i32, buf: u32, len: u32, flags: u32, dest_addr: u32, addrlen: u32);
+    recvfrom => recvfrom[mem](fd: i32, buf: u32, len: u32, flags: u32, src_addr: u32, addrlen: u32);
+    sendmsg => sendmsg[mem](fd: i32, msg: u32, flags: u32);
+    recvmsg => recvmsg[mem](fd: i32, msg: u32, flags: u32);
+    recvmmsg_time64 => recvmmsg_time64[mem](fd: i32, msgvec: u32, vlen: u32, flags: u32, timeout: u32);
+    shutdown => shutdown(fd: i32, how: u32);
+    getsockopt => getsockopt[mem](fd: i32, level: u32, optname: u32, optval: u32, optlen: u32);
+    setsockopt => setsockopt(fd: i32, level: u32, optname: u32, optval: u32, optlen: u32);
+    getrandom => getrandom[mem](buf: u32, len: u32, flags: u32);
+    statx => statx[mem](dirfd: i32, pathname: u32, flags: u32, mask: u32, statxbuf: u32);
+    eventfd2 => eventfd2(initval: u32, flags: u32);
+    timerfd_create => timerfd_create(clockid: u32, flags: u32);
+    timerfd_settime64 => timerfd_settime64[mem](fd: i32, flags: u32, new_value: u32, old_value: u32);
+    timerfd_gettime64 => timerfd_gettime64[mem](fd: i32, curr_value: u32);
+    epoll_create1 => epoll_create1(flags: u32);
+    epoll_ctl => epoll_ctl[mem](epfd: i32, op: u32, fd: i32, event: u32);
+    epoll_pwait => epoll_pwait[mem](epfd: i32, events: u32, maxevents: i32, timeout: i32, sigmask: u32, sigsetsize: u32);
+    epoll_pwait2 => epoll_pwait2[mem](epfd: i32, events: u32, maxevents: i32, timeout: u32, sigmask: u32);
+    pselect6_time64 => pselect6_time64[mem](nfds: i32, readfds: u32, writefds: u32, exceptfds: u32, timeout: u32, sig: u32);
+    ppoll_time64 => ppoll_time64[mem](fds: u32, nfds: u32, tsp: u32, sigmask: u32, sigsetsize: u32);
+}
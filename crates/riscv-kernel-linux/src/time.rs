@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use riscv_vm::marshal::GuestValue;
+use syscalls::riscv32::Sysno;
+
+use crate::hibernate::{StateReader, StateWriter};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Where guest clocks read their time from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockMode {
+    /// Host wall and monotonic clocks
+    #[default]
+    Host,
+    /// Virtual time derived from retired instructions and syscall costs,
+    /// so runs are reproducible.
+    Deterministic,
+}
+
+/// Virtual time charged for each syscall, in nanoseconds.
+#[derive(Debug, Clone)]
+pub struct SyscallCosts {
+    pub default: u64,
+    pub overrides: HashMap<Sysno, u64>,
+}
+
+impl SyscallCosts {
+    /// Charge nothing for any syscall.
+    pub fn zero() -> Self {
+        Self {
+            default: 0,
+            overrides: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, call: Sysno, ns: u64) -> &mut Self {
+        self.overrides.insert(call, ns);
+        self
+    }
+
+    pub fn cost(&self, call: Sysno) -> u64 {
+        self.overrides.get(&call).copied().unwrap_or(self.default)
+    }
+}
+
+impl Default for SyscallCosts {
+    /// Rough costs of the fast paths on a modern Linux host.
+    fn default() -> Self {
+        let mut costs = Self {
+            default: 200,
+            overrides: HashMap::new(),
+        };
+        costs
+            .set(Sysno::read, 1_000)
+            .set(Sysno::write, 1_000)
+            .set(Sysno::writev, 1_200)
+            .set(Sysno::futex, 500)
+            .set(Sysno::futex_time64, 500)
+            .set(Sysno::mmap, 2_000)
+            .set(Sysno::brk, 1_000)
+            .set(Sysno::clock_gettime64, 50);
+
+        costs
+    }
+}
+
+/// The kernel's view of time.
+///
+/// In [`ClockMode::Deterministic`], time is `inst_count * ns_per_inst` plus the
+/// syscall costs and sleeps charged so far, starting from `epoch_ns` for the
+/// realtime clock and 0 for the monotonic one.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    pub mode: ClockMode,
+    pub ns_per_inst: u64,
+    /// Realtime clock value at virtual time 0, in ns since the Unix epoch
+    pub epoch_ns: u64,
+    pub costs: SyscallCosts,
+    /// Time charged outside of instruction execution
+    charged_ns: u64,
+    /// Part of `charged_ns` from syscall costs
+    syscall_ns: u64,
+    started: Instant,
+}
+
+impl VirtualClock {
+    pub fn new(mode: ClockMode) -> Self {
+        Self {
+            mode,
+            ns_per_inst: 1,
+            // 2024-01-01T00:00:00Z
+            epoch_ns: 1_704_067_200 * NANOS_PER_SEC,
+            costs: SyscallCosts::default(),
+            charged_ns: 0,
+            syscall_ns: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Advance virtual time by `ns`, e.g. for a sleep or a syscall's cost.
+    pub fn charge(&mut self, ns: u64) {
+        self.charged_ns = self.charged_ns.saturating_add(ns);
+    }
+
+    /// Save the time that has passed, but not the configuration.
+    pub(crate) fn save(&self, w: &mut StateWriter) {
+        w.u64(self.charged_ns);
+        w.u64(self.syscall_ns);
+        w.u64(self.started.elapsed().as_nanos() as u64);
+    }
+
+    /// Carry on from the time [`VirtualClock::save`] saved, so host clocks
+    /// don't go back either.
+    pub(crate) fn restore(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.charged_ns = r.u64()?;
+        self.syscall_ns = r.u64()?;
+        let elapsed = Duration::from_nanos(r.u64()?);
+        self.started = Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or_else(Instant::now);
+
+        Ok(())
+    }
+
+    /// Start over at virtual time 0, keeping the configuration.
+    pub fn reset(&mut self) {
+        self.charged_ns = 0;
+        self.syscall_ns = 0;
+        self.started = Instant::now();
+    }
+
+    pub fn charge_syscall(&mut self, call: Sysno) {
+        let cost = self.costs.cost(call);
+        self.syscall_ns = self.syscall_ns.saturating_add(cost);
+        self.charge(cost);
+    }
+
+    /// Time charged by syscall costs so far, i.e. the guest's system time.
+    pub fn syscall_ns(&self) -> u64 {
+        self.syscall_ns
+    }
+
+    /// Time charged by syscalls and sleeps so far.
+    pub fn charged(&self) -> u64 {
+        self.charged_ns
+    }
+
+    /// Nanoseconds since the machine started.
+    pub fn monotonic_ns(&self, inst_count: u64) -> u64 {
+        match self.mode {
+            ClockMode::Host => self.started.elapsed().as_nanos() as u64,
+            ClockMode::Deterministic => inst_count
+                .saturating_mul(self.ns_per_inst)
+                .saturating_add(self.charged_ns),
+        }
+    }
+
+    /// Nanoseconds since the Unix epoch.
+    pub fn realtime_ns(&self, inst_count: u64) -> u64 {
+        match self.mode {
+            ClockMode::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+            ClockMode::Deterministic => self.epoch_ns + self.monotonic_ns(inst_count),
+        }
+    }
+
+    /// CPU time used by the guest, which excludes sleeps.
+    pub fn cpu_ns(&self, inst_count: u64) -> u64 {
+        inst_count.saturating_mul(self.ns_per_inst)
+    }
+
+    /// Let `ns` of guest time pass without executing instructions.
+    ///
+    /// Deterministic clocks are charged; host clocks actually sleep.
+    pub fn advance(&mut self, ns: u64) {
+        match self.mode {
+            ClockMode::Host => std::thread::sleep(Duration::from_nanos(ns)),
+            ClockMode::Deterministic => self.charge(ns),
+        }
+    }
+
+    pub fn read(&self, inst_count: u64) -> ClockReading {
+        ClockReading {
+            inst_count,
+            monotonic_ns: self.monotonic_ns(inst_count),
+            realtime_ns: self.realtime_ns(inst_count),
+        }
+    }
+}
+
+/// The guest clocks at some instant, e.g. the start of the current syscall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockReading {
+    pub inst_count: u64,
+    pub monotonic_ns: u64,
+    pub realtime_ns: u64,
+}
+
+impl ClockReading {
+    /// The value of clock `clockid`, or `None` for clocks timers can't use.
+    pub fn get(&self, clockid: u32) -> Option<u64> {
+        match clockid {
+            libc_riscv32::CLOCK_REALTIME => Some(self.realtime_ns),
+            libc_riscv32::CLOCK_MONOTONIC | libc_riscv32::CLOCK_BOOTTIME => Some(self.monotonic_ns),
+            _ => None,
+        }
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new(ClockMode::default())
+    }
+}
+
+/// A 64-bit `struct timespec`, as used by the `*_time64` syscalls on rv32.
+#[repr(C)]
+#[derive(GuestValue, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[guest(size = 16)]
+pub struct Timespec64 {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+impl Timespec64 {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NANOS_PER_SEC) as i64,
+            tv_nsec: (ns % NANOS_PER_SEC) as i64,
+        }
+    }
+
+    /// `None` if the timespec is negative or `tv_nsec` is out of range.
+    ///
+    /// rv32 userspace only writes the low half of `tv_nsec` (a `long`), so like the
+    /// kernel, the padding in the upper half is ignored.
+    pub fn to_ns(self) -> Option<u64> {
+        let nsec = self.tv_nsec as i32;
+        if self.tv_sec < 0 || !(0..NANOS_PER_SEC as i32).contains(&nsec) {
+            return None;
+        }
+        (self.tv_sec as u64)
+            .checked_mul(NANOS_PER_SEC)?
+            .checked_add(nsec as u64)
+    }
+}
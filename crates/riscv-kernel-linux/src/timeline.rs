@@ -0,0 +1,109 @@
+use std::io::Write;
+
+use riscv_vm::{hart::Hart32, riscv_inst::Reg, symbols::SymbolTable};
+
+/// How the guest changed its memory layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionChange {
+    /// The heap is now `addr..addr + size`
+    Brk,
+    /// `addr..addr + size` was mapped
+    Mmap,
+    /// `addr..addr + size` was unmapped
+    Munmap,
+}
+
+impl RegionChange {
+    pub const fn name(self) -> &'static str {
+        match self {
+            RegionChange::Brk => "brk",
+            RegionChange::Mmap => "mmap",
+            RegionChange::Munmap => "munmap",
+        }
+    }
+}
+
+/// A successful `brk`, `mmap` or `munmap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionEvent {
+    pub change: RegionChange,
+    /// Instructions retired before the syscall
+    pub inst_count: u64,
+    pub addr: u32,
+    /// Bytes, in whole pages except for the heap
+    pub size: u32,
+    /// The `ecall`
+    pub pc: u32,
+    /// Return address at the `ecall`, in the caller of the syscall wrapper
+    pub caller: u32,
+}
+
+/// Every change the guest made to its heap and mappings, in order, from
+/// [`MockLinux::memory_timeline`](crate::MockLinux::memory_timeline).
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTimeline {
+    events: Vec<RegionEvent>,
+}
+
+impl MemoryTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[RegionEvent] {
+        &self.events
+    }
+
+    pub(crate) fn record(&mut self, hart: &Hart32, change: RegionChange, addr: u32, size: u32) {
+        self.events.push(RegionEvent {
+            change,
+            inst_count: hart.inst_count,
+            addr,
+            size,
+            pc: hart.pc,
+            caller: hart.get_reg(Reg::Ra),
+        });
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Write the timeline as a JSON array of events, naming the caller of
+    /// each with `symbols` when it can.
+    pub fn write_json(&self, w: &mut impl Write, symbols: &SymbolTable) -> std::io::Result<()> {
+        writeln!(w, "[")?;
+        for (i, event) in self.events.iter().enumerate() {
+            write!(
+                w,
+                r#"  {{"kind": "{}", "inst_count": {}, "addr": {}, "size": {}, "pc": {}, "caller": {}"#,
+                event.change.name(),
+                event.inst_count,
+                event.addr,
+                event.size,
+                event.pc,
+                event.caller
+            )?;
+            if let Some((name, offset)) = symbols.symbolize(event.caller) {
+                write!(w, r#", "caller_symbol": "{}+{offset:#x}""#, escape(name))?;
+            }
+            let comma = if i + 1 < self.events.len() { "," } else { "" };
+            writeln!(w, "}}{comma}")?;
+        }
+        writeln!(w, "]")
+    }
+}
+
+/// Escape `s` for a JSON string.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,92 @@
+use riscv_vm::{hart::Hart32, marshal::GuestValue, memory::Memory};
+
+use crate::{errno::KernelResult, MockLinux, PAGE_SIZE};
+
+/// The kernel's `struct rusage` on rv32, with 32-bit `long`s and old-style timevals.
+#[repr(C)]
+#[derive(GuestValue, Debug, Default)]
+#[guest(size = 72)]
+struct Rusage32 {
+    ru_utime: [i32; 2],
+    ru_stime: [i32; 2],
+    ru_maxrss: i32,
+    ru_ixrss: i32,
+    ru_idrss: i32,
+    ru_isrss: i32,
+    ru_minflt: i32,
+    ru_majflt: i32,
+    ru_nswap: i32,
+    ru_inblock: i32,
+    ru_oublock: i32,
+    ru_msgsnd: i32,
+    ru_msgrcv: i32,
+    ru_nsignals: i32,
+    ru_nvcsw: i32,
+    ru_nivcsw: i32,
+}
+
+/// `struct timeval` from nanoseconds, saturating the 32-bit seconds.
+fn timeval(ns: u64) -> [i32; 2] {
+    let secs = (ns / 1_000_000_000).min(i32::MAX as u64) as i32;
+    [secs, (ns % 1_000_000_000 / 1000) as i32]
+}
+
+impl MockLinux {
+    /// Current and peak resident guest memory, in bytes. If the host can't
+    /// say which pages are resident, the peak stands in for the current.
+    pub(crate) fn sample_rss(&mut self, mem: &Memory) -> (u64, u64) {
+        let pages = mem
+            .resident_pages()
+            .map_or(self.peak_rss_pages, |pages| pages as u64);
+        self.peak_rss_pages = self.peak_rss_pages.max(pages);
+
+        (
+            pages * PAGE_SIZE as u64,
+            self.peak_rss_pages * PAGE_SIZE as u64,
+        )
+    }
+
+    /// Update what `/proc/self/status` and `/proc/cpuinfo` report.
+    pub(crate) fn refresh_proc(&mut self, mem: &Memory) {
+        let (rss_bytes, peak_rss_bytes) = self.sample_rss(mem);
+        let data_bytes = (mem.brk.saturating_sub(self.brk_start) as u64)
+            + riscv_vm::memory::MMAP_BASE.saturating_sub(mem.mmap_top) as u64;
+        let pid = self.getpid().unwrap();
+
+        let mut status = self.proc.status.borrow_mut();
+        status.name = String::from_utf8_lossy(&self.attrs.comm).into_owned();
+        status.no_new_privs = self.attrs.no_new_privs;
+        status.pid = pid;
+        status.rss_bytes = rss_bytes;
+        status.peak_rss_bytes = peak_rss_bytes;
+        status.data_bytes = data_bytes;
+        status.threads = 1;
+        *self.proc.cpuinfo.borrow_mut() = self.topology.cpuinfo();
+    }
+
+    pub(crate) fn getrusage(
+        &mut self,
+        hart: &Hart32,
+        mem: &mut Memory,
+        who: i32,
+        usage: u32,
+    ) -> KernelResult {
+        let rusage = match who {
+            // There are no children, and the only thread is the process
+            libc_riscv32::RUSAGE_CHILDREN => Rusage32::default(),
+            libc_riscv32::RUSAGE_SELF | libc_riscv32::RUSAGE_THREAD => {
+                let (_, peak_rss_bytes) = self.sample_rss(mem);
+                Rusage32 {
+                    ru_utime: timeval(self.clock.cpu_ns(hart.inst_count)),
+                    ru_stime: timeval(self.clock.syscall_ns()),
+                    ru_maxrss: (peak_rss_bytes / 1024) as i32,
+                    ..Default::default()
+                }
+            }
+            _ => return Err(libc_riscv32::EINVAL),
+        };
+
+        mem.write(usage, rusage).map_err(|_| libc_riscv32::EFAULT)?;
+        Ok(0)
+    }
+}
@@ -0,0 +1,159 @@
+use thiserror::Error;
+
+use super::memfs::MemFs;
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Unrecognized archive format (expected tar or newc cpio)")]
+    UnknownFormat,
+    #[error("Archive truncated at offset {0}")]
+    Truncated(usize),
+    #[error("Invalid {format} header at offset {offset}")]
+    InvalidHeader { format: &'static str, offset: usize },
+}
+
+/// Unpack a tar (ustar/GNU/pax) or newc cpio archive into `fs`.
+pub(crate) fn unpack(fs: &mut MemFs, bytes: &[u8]) -> Result<(), ArchiveError> {
+    if bytes.starts_with(b"070701") || bytes.starts_with(b"070702") {
+        unpack_cpio(fs, bytes)
+    } else if bytes.get(257..262) == Some(b"ustar") {
+        unpack_tar(fs, bytes)
+    } else {
+        Err(ArchiveError::UnknownFormat)
+    }
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let s = std::str::from_utf8(field).ok()?;
+    let s = s.trim_matches(|c: char| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(s, 8).ok()
+}
+
+fn c_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn unpack_tar(fs: &mut MemFs, bytes: &[u8]) -> Result<(), ArchiveError> {
+    let mut offset = 0;
+    // Name overrides from GNU `L` or pax `x` headers, applying to the next entry
+    let mut long_name: Option<String> = None;
+    let mut long_link: Option<String> = None;
+
+    while offset + 512 <= bytes.len() {
+        let header = &bytes[offset..offset + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let invalid = ArchiveError::InvalidHeader {
+            format: "tar",
+            offset,
+        };
+
+        let size = parse_octal(&header[124..136]).ok_or(invalid)? as usize;
+        let mode = parse_octal(&header[100..108]).unwrap_or(0o644) as u32;
+        let typeflag = header[156];
+        let data_start = offset + 512;
+        let data = bytes
+            .get(data_start..data_start + size)
+            .ok_or(ArchiveError::Truncated(data_start))?;
+        offset = data_start + size.next_multiple_of(512);
+
+        let mut name = c_str(&header[0..100]);
+        let prefix = c_str(&header[345..500]);
+        if !prefix.is_empty() {
+            name = format!("{prefix}/{name}");
+        }
+        let name = long_name.take().unwrap_or(name);
+        let link = long_link.take().unwrap_or_else(|| c_str(&header[157..257]));
+
+        match typeflag {
+            b'L' => long_name = Some(c_str(data)),
+            b'K' => long_link = Some(c_str(data)),
+            b'x' => {
+                for record in pax_records(data) {
+                    match record {
+                        ("path", value) => long_name = Some(value.to_string()),
+                        ("linkpath", value) => long_link = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+            }
+            b'0' | 0 | b'7' => fs.add_file(&name, mode, data.to_vec()),
+            b'5' => fs.add_dir(&name, mode),
+            b'2' => fs.add_symlink(&name, &link),
+            // Hard links are copied
+            b'1' => {
+                if let Ok(contents) = fs.contents(&link) {
+                    fs.add_file(&name, mode, contents);
+                }
+            }
+            // Devices, fifos and global pax headers
+            _ => tracing::debug!("tar: skipping {name:?} of type {:?}", typeflag as char),
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse pax extended header records of the form `"<len> <key>=<value>\n"`.
+fn pax_records(data: &[u8]) -> impl Iterator<Item = (&str, &str)> {
+    let text = std::str::from_utf8(data).unwrap_or_default();
+    text.split('\n').filter_map(|record| {
+        let (_, kv) = record.split_once(' ')?;
+        kv.split_once('=')
+    })
+}
+
+fn unpack_cpio(fs: &mut MemFs, bytes: &[u8]) -> Result<(), ArchiveError> {
+    const HEADER_LEN: usize = 110;
+    let mut offset = 0;
+
+    while offset + HEADER_LEN <= bytes.len() {
+        let header = &bytes[offset..offset + HEADER_LEN];
+        let invalid = || ArchiveError::InvalidHeader {
+            format: "cpio",
+            offset,
+        };
+        if !header.starts_with(b"07070") {
+            return Err(invalid());
+        }
+        // Fields are 8 hex digits each, after the 6-byte magic
+        let field = |i: usize| {
+            std::str::from_utf8(&header[6 + i * 8..14 + i * 8])
+                .ok()
+                .and_then(|s| u32::from_str_radix(s, 16).ok())
+                .ok_or_else(invalid)
+        };
+        let mode = field(1)?;
+        let size = field(6)? as usize;
+        let name_len = field(11)? as usize;
+
+        let name_start = offset + HEADER_LEN;
+        let name = bytes
+            .get(name_start..name_start + name_len)
+            .ok_or(ArchiveError::Truncated(name_start))?;
+        let name = c_str(name);
+        let data_start = (name_start + name_len).next_multiple_of(4);
+        let data = bytes
+            .get(data_start..data_start + size)
+            .ok_or(ArchiveError::Truncated(data_start))?;
+        offset = (data_start + size).next_multiple_of(4);
+
+        if name == "TRAILER!!!" {
+            break;
+        }
+        let perm = mode & 0o7777;
+        match mode & libc_riscv32::S_IFMT {
+            libc_riscv32::S_IFREG => fs.add_file(&name, perm, data.to_vec()),
+            libc_riscv32::S_IFDIR if name != "." => fs.add_dir(&name, perm),
+            libc_riscv32::S_IFLNK => fs.add_symlink(&name, &String::from_utf8_lossy(data)),
+            _ => tracing::debug!("cpio: skipping {name:?} with mode {mode:#o}"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::{FileLike, FileStat};
+use crate::errno::{host_errno, KernelResult};
+
+/// How well a [`HostCache`] is doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostCacheStats {
+    /// Path lookups, from `stat` and opens for reading, answered from the
+    /// cache
+    pub stat_hits: u64,
+    pub stat_misses: u64,
+    /// Files opened for reading whose contents were cached
+    pub read_hits: u64,
+    pub read_misses: u64,
+    /// Times the cache was emptied because the guest changed a host mount
+    pub invalidations: u64,
+}
+
+/// Caches what host mounts find on the host, so guests that look at many
+/// files, like compilers searching include paths, don't cost a few host
+/// syscalls for each of theirs.
+///
+/// Path lookups are cached, including those that fail, along with the
+/// contents of files opened for reading, up to a total size. Anything the
+/// guest changes through a mount empties the cache; changes made on the
+/// host behind the guest's back aren't seen until
+/// [`HostCache::invalidate`].
+///
+/// Clones share the same cache, which can serve several mounts.
+#[derive(Debug, Clone)]
+pub struct HostCache {
+    inner: Rc<RefCell<CacheState>>,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    /// Most bytes of file contents kept
+    capacity: u64,
+    /// Where host paths lead and what's there, or why they can't be
+    /// reached, by the path before canonicalization
+    lookups: HashMap<PathBuf, KernelResult<(PathBuf, FileStat)>>,
+    /// File contents by canonical host path
+    contents: HashMap<PathBuf, Rc<[u8]>>,
+    /// Keys of `contents`, oldest first, for eviction
+    order: VecDeque<PathBuf>,
+    cached_bytes: u64,
+    stats: HostCacheStats,
+}
+
+impl HostCache {
+    /// A cache holding at most `capacity` bytes of file contents.
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(CacheState {
+                capacity,
+                lookups: HashMap::new(),
+                contents: HashMap::new(),
+                order: VecDeque::new(),
+                cached_bytes: 0,
+                stats: HostCacheStats::default(),
+            })),
+        }
+    }
+
+    pub fn stats(&self) -> HostCacheStats {
+        self.inner.borrow().stats
+    }
+
+    /// Bytes of file contents cached.
+    pub fn cached_bytes(&self) -> u64 {
+        self.inner.borrow().cached_bytes
+    }
+
+    /// Forget everything, e.g. after changing files on the host.
+    pub fn invalidate(&self) {
+        let mut state = self.inner.borrow_mut();
+        if state.lookups.is_empty() && state.contents.is_empty() {
+            return;
+        }
+        state.lookups.clear();
+        state.contents.clear();
+        state.order.clear();
+        state.cached_bytes = 0;
+        state.stats.invalidations += 1;
+    }
+
+    /// The cached lookup of `host`, or `lookup`'s, which is then cached.
+    pub(crate) fn lookup(
+        &self,
+        host: PathBuf,
+        lookup: impl FnOnce() -> KernelResult<(PathBuf, FileStat)>,
+    ) -> KernelResult<(PathBuf, FileStat)> {
+        if let Some(found) = self.inner.borrow_mut().hit_lookup(&host) {
+            return found;
+        }
+        let found = lookup();
+        let mut state = self.inner.borrow_mut();
+        state.stats.stat_misses += 1;
+        state.lookups.insert(host, found.clone());
+
+        found
+    }
+
+    /// The contents of the file at the canonical path `host`, read and
+    /// cached if `stat` says it fits, or `None` if it doesn't.
+    pub(crate) fn contents(&self, host: &Path, stat: &FileStat) -> KernelResult<Option<Rc<[u8]>>> {
+        let mut state = self.inner.borrow_mut();
+        if let Some(data) = state.contents.get(host).cloned() {
+            state.stats.read_hits += 1;
+            return Ok(Some(data));
+        }
+        state.stats.read_misses += 1;
+        if stat.size > state.capacity {
+            return Ok(None);
+        }
+
+        let data: Rc<[u8]> = std::fs::read(host).map_err(host_errno)?.into();
+        let size = data.len() as u64;
+        while state.cached_bytes + size > state.capacity {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = state.contents.remove(&oldest) {
+                state.cached_bytes -= evicted.len() as u64;
+            }
+        }
+        state.cached_bytes += size;
+        state.order.push_back(host.to_path_buf());
+        state.contents.insert(host.to_path_buf(), data.clone());
+
+        Ok(Some(data))
+    }
+}
+
+impl CacheState {
+    fn hit_lookup(&mut self, host: &Path) -> Option<KernelResult<(PathBuf, FileStat)>> {
+        let found = self.lookups.get(host)?.clone();
+        self.stats.stat_hits += 1;
+        Some(found)
+    }
+}
+
+/// A host file opened for reading from its cached contents.
+#[derive(Debug)]
+pub(crate) struct CachedFile {
+    data: Rc<[u8]>,
+    stat: FileStat,
+    pos: u64,
+}
+
+impl CachedFile {
+    pub fn new(data: Rc<[u8]>, stat: FileStat) -> Self {
+        Self { data, stat, pos: 0 }
+    }
+}
+
+impl FileLike for CachedFile {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        let start = (self.pos as usize).min(self.data.len());
+        let n = buf.len().min(self.data.len() - start);
+        buf[..n].copy_from_slice(&self.data[start..start + n]);
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> KernelResult<u64> {
+        let new = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::Current(off) => self.pos as i64 + off,
+            SeekFrom::End(off) => self.data.len() as i64 + off,
+        };
+        if new < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        self.pos = new as u64;
+
+        Ok(self.pos)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(self.stat)
+    }
+
+    fn reopen_at(&self) -> Option<u64> {
+        Some(self.pos)
+    }
+}
@@ -0,0 +1,243 @@
+use std::{cell::Cell, collections::BTreeMap, rc::Rc};
+
+use riscv_vm::marshal::GuestValue;
+
+use super::{FileKind, FileLike, FileStat};
+use crate::{
+    errno::KernelResult,
+    hibernate::{StateReader, StateWriter},
+    time::ClockReading,
+};
+
+/// The `stat` of an anonymous inode, which event, timer and epoll fds live on.
+fn anon_stat() -> FileStat {
+    FileStat {
+        kind: FileKind::File,
+        perm: 0o600,
+        size: 0,
+        ino: 0,
+        nlink: 1,
+        mtime_ns: 0,
+    }
+}
+
+/// Reads and writes of eventfds and timerfds are a single `u64`.
+fn read_u64(buf: &[u8]) -> KernelResult<u64> {
+    let bytes = buf.get(..8).ok_or(libc_riscv32::EINVAL)?;
+    Ok(u64::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+fn write_u64(buf: &mut [u8], value: u64) -> KernelResult<usize> {
+    let bytes = buf.get_mut(..8).ok_or(libc_riscv32::EINVAL)?;
+    bytes.copy_from_slice(&value.to_ne_bytes());
+    Ok(8)
+}
+
+#[derive(Debug)]
+pub(crate) struct EventFd {
+    count: u64,
+    semaphore: bool,
+}
+
+impl EventFd {
+    pub fn new(initval: u32, semaphore: bool) -> Self {
+        Self {
+            count: initval as u64,
+            semaphore,
+        }
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u64(self.count);
+        w.u8(self.semaphore as u8);
+    }
+
+    pub fn restore(r: &mut StateReader) -> Result<Self, String> {
+        Ok(Self {
+            count: r.u64()?,
+            semaphore: r.bool()?,
+        })
+    }
+}
+
+impl FileLike for EventFd {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        if buf.len() < 8 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        if self.count == 0 {
+            return Err(libc_riscv32::EAGAIN);
+        }
+        let value = if self.semaphore { 1 } else { self.count };
+        self.count -= value;
+
+        write_u64(buf, value)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        let value = read_u64(buf)?;
+        if value == u64::MAX {
+            return Err(libc_riscv32::EINVAL);
+        }
+        // The counter saturates at u64::MAX - 1
+        match self.count.checked_add(value) {
+            Some(count) if count < u64::MAX => self.count = count,
+            _ => return Err(libc_riscv32::EAGAIN),
+        }
+
+        Ok(8)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(anon_stat())
+    }
+
+    fn poll(&mut self) -> u16 {
+        let mut events = 0;
+        if self.count > 0 {
+            events |= libc_riscv32::POLLIN;
+        }
+        if self.count < u64::MAX - 1 {
+            events |= libc_riscv32::POLLOUT;
+        }
+        events
+    }
+}
+
+/// A timer on one of the guest clocks, read at the start of each syscall.
+#[derive(Debug)]
+pub(crate) struct TimerFd {
+    clockid: u32,
+    now: Rc<Cell<ClockReading>>,
+    /// Next expiration on `clockid`, if armed
+    deadline: Option<u64>,
+    interval_ns: u64,
+    /// Expirations since the last read
+    expirations: u64,
+}
+
+impl TimerFd {
+    /// `None` if timers can't run on `clockid`.
+    pub fn new(clockid: u32, now: Rc<Cell<ClockReading>>) -> Option<Self> {
+        now.get().get(clockid)?;
+        Some(Self {
+            clockid,
+            now,
+            deadline: None,
+            interval_ns: 0,
+            expirations: 0,
+        })
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.now.get().get(self.clockid).unwrap()
+    }
+
+    /// Count expirations up to now.
+    fn update(&mut self) {
+        let now = self.now_ns();
+        let Some(deadline) = self.deadline.filter(|&deadline| deadline <= now) else {
+            return;
+        };
+        match (now - deadline).checked_div(self.interval_ns) {
+            Some(periods) => {
+                let n = periods + 1;
+                self.expirations += n;
+                self.deadline = Some(deadline + n * self.interval_ns);
+            }
+            // One-shot
+            None => {
+                self.expirations += 1;
+                self.deadline = None;
+            }
+        }
+    }
+
+    /// Time until the next expiration and the interval, or zeroes if disarmed.
+    pub fn get(&mut self) -> (u64, u64) {
+        self.update();
+        let remaining = self
+            .deadline
+            .map_or(0, |deadline| deadline.saturating_sub(self.now_ns()));
+        (remaining, self.interval_ns)
+    }
+
+    /// Arm the timer to expire in `value_ns`, or at `value_ns` if `absolute`,
+    /// then every `interval_ns`. A zero `value_ns` disarms it.
+    ///
+    /// Returns the previous setting, as [`TimerFd::get`] would.
+    pub fn set(&mut self, value_ns: u64, interval_ns: u64, absolute: bool) -> (u64, u64) {
+        let old = self.get();
+        self.expirations = 0;
+        self.interval_ns = interval_ns;
+        self.deadline = match value_ns {
+            0 => None,
+            ns if absolute => Some(ns),
+            ns => Some(self.now_ns().saturating_add(ns)),
+        };
+
+        old
+    }
+}
+
+impl FileLike for TimerFd {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        if buf.len() < 8 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        self.update();
+        if self.expirations == 0 {
+            return Err(libc_riscv32::EAGAIN);
+        }
+
+        write_u64(buf, std::mem::take(&mut self.expirations))
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(anon_stat())
+    }
+
+    fn poll(&mut self) -> u16 {
+        self.update();
+        if self.expirations > 0 {
+            libc_riscv32::POLLIN
+        } else {
+            0
+        }
+    }
+
+    fn wake_at(&self) -> Option<u64> {
+        let deadline = self.deadline?;
+        let now = self.now.get();
+        Some(now.monotonic_ns + deadline.saturating_sub(self.now_ns()))
+    }
+}
+
+/// `struct epoll_event`, which isn't packed on rv32.
+#[repr(C)]
+#[derive(GuestValue, Debug, Clone, Copy, Default)]
+#[guest(size = 16)]
+pub(crate) struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+/// An epoll instance's interest list.
+///
+/// Edge-triggered registrations are treated as level-triggered, which only
+/// adds spurious wakeups.
+#[derive(Debug, Default)]
+pub(crate) struct Epoll {
+    pub interest: BTreeMap<i32, EpollEvent>,
+}
+
+impl FileLike for Epoll {
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(anon_stat())
+    }
+
+    /// Nested epoll instances are never ready.
+    fn poll(&mut self) -> u16 {
+        0
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    fs::{DirBuilder, File, Metadata, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{
+    cache::{CachedFile, HostCache},
+    DirEntry, FileKind, FileLike, FileStat, Mount,
+};
+use crate::errno::{host_errno, KernelResult};
+
+/// A host directory exposed to the guest.
+///
+/// Guest paths can't leave `root`: `..` is resolved before reaching the mount, and
+/// every host path is canonicalized and checked to still be under `root`, so
+/// symlinks pointing outside of it fail with `EACCES`.
+#[derive(Debug)]
+pub struct HostMount {
+    root: PathBuf,
+    read_only: bool,
+    cache: Option<HostCache>,
+}
+
+impl HostMount {
+    pub fn new(root: impl AsRef<Path>, read_only: bool) -> std::io::Result<Self> {
+        let root = root.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotADirectory));
+        }
+
+        Ok(Self {
+            root,
+            read_only,
+            cache: None,
+        })
+    }
+
+    /// Look paths and files up in `cache` before going to the host.
+    pub fn with_cache(mut self, cache: HostCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Translate a mount-relative guest path, refusing anything that escapes `root`.
+    ///
+    /// Paths that don't exist yet are checked through their parent directory.
+    fn translate(&self, path: &str) -> KernelResult<PathBuf> {
+        let host = self.root.join(path);
+        let canonical = match host.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // A dangling symlink would be followed on creation
+                if host.symlink_metadata().is_ok() {
+                    return Err(libc_riscv32::EACCES);
+                }
+                let (parent, name) = match path.rsplit_once('/') {
+                    Some((parent, name)) => (self.root.join(parent), name),
+                    None => (self.root.clone(), path),
+                };
+                parent.canonicalize().map_err(host_errno)?.join(name)
+            }
+            Err(e) => return Err(host_errno(e)),
+        };
+
+        if !canonical.starts_with(&self.root) {
+            tracing::warn!("host mount: {path:?} escapes {}", self.root.display());
+            return Err(libc_riscv32::EACCES);
+        }
+
+        Ok(canonical)
+    }
+
+    /// Like [`Self::translate`], but without following a symlink in the last component.
+    fn translate_entry(&self, path: &str) -> KernelResult<PathBuf> {
+        match path.rsplit_once('/') {
+            Some((parent, name)) => Ok(self.translate(parent)?.join(name)),
+            None => Ok(self.root.join(path)),
+        }
+    }
+
+    /// The host path `path` leads to and what's there.
+    fn lookup(&self, path: &str) -> KernelResult<(PathBuf, FileStat)> {
+        let lookup = || {
+            let host = self.translate(path)?;
+            let stat = metadata_stat(&host.metadata().map_err(host_errno)?);
+            Ok((host, stat))
+        };
+        match &self.cache {
+            Some(cache) => cache.lookup(self.root.join(path), lookup),
+            None => lookup(),
+        }
+    }
+
+    /// Fail on a read-only mount, and otherwise forget what's cached, as
+    /// the guest is about to change something.
+    fn check_writable(&self) -> KernelResult<()> {
+        if self.read_only {
+            return Err(libc_riscv32::EROFS);
+        }
+        if let Some(cache) = &self.cache {
+            cache.invalidate();
+        }
+        Ok(())
+    }
+
+    /// Open a file for reading through the cache.
+    fn open_cached(
+        &self,
+        cache: &HostCache,
+        path: &str,
+        flags: u32,
+    ) -> KernelResult<Box<dyn FileLike>> {
+        let (host, stat) = self.lookup(path)?;
+        if stat.kind == FileKind::Dir {
+            return Ok(Box::new(HostDir { path: host, stat }));
+        }
+        if flags & libc_riscv32::O_DIRECTORY != 0 {
+            return Err(libc_riscv32::ENOTDIR);
+        }
+        if let Some(data) = cache.contents(&host, &stat)? {
+            return Ok(Box::new(CachedFile::new(data, stat)));
+        }
+
+        let file = File::open(&host).map_err(host_errno)?;
+        Ok(Box::new(HostFile { file, cache: None }))
+    }
+}
+
+impl Mount for HostMount {
+    fn open(&mut self, path: &str, flags: u32, mode: u32) -> KernelResult<Box<dyn FileLike>> {
+        let access = flags & libc_riscv32::O_ACCMODE;
+        if access != libc_riscv32::O_RDONLY
+            || flags & (libc_riscv32::O_CREAT | libc_riscv32::O_TRUNC) != 0
+        {
+            self.check_writable()?;
+        } else if let Some(cache) = &self.cache {
+            return self.open_cached(cache, path, flags);
+        }
+
+        let host = self.translate(path)?;
+        if host.is_dir() {
+            if access != libc_riscv32::O_RDONLY {
+                return Err(libc_riscv32::EISDIR);
+            }
+            let stat = metadata_stat(&host.metadata().map_err(host_errno)?);
+            return Ok(Box::new(HostDir { path: host, stat }));
+        }
+        if flags & libc_riscv32::O_DIRECTORY != 0 {
+            return Err(libc_riscv32::ENOTDIR);
+        }
+
+        let file = OpenOptions::new()
+            .read(access != libc_riscv32::O_WRONLY)
+            .write(access != libc_riscv32::O_RDONLY)
+            .append(flags & libc_riscv32::O_APPEND != 0)
+            .truncate(flags & libc_riscv32::O_TRUNC != 0)
+            .create(flags & libc_riscv32::O_CREAT != 0)
+            .create_new(flags & libc_riscv32::O_CREAT != 0 && flags & libc_riscv32::O_EXCL != 0)
+            .mode(mode & 0o7777)
+            .open(&host)
+            .map_err(host_errno)?;
+        let cache = self.cache.clone();
+
+        Ok(Box::new(HostFile { file, cache }))
+    }
+
+    fn stat(&mut self, path: &str) -> KernelResult<FileStat> {
+        Ok(self.lookup(path)?.1)
+    }
+
+    /// Creates and immediately removes a uniquely named file, since the host's
+    /// `O_TMPFILE` value is architecture-specific.
+    fn open_tmpfile(
+        &mut self,
+        dir: &str,
+        flags: u32,
+        mode: u32,
+    ) -> KernelResult<Box<dyn FileLike>> {
+        static NEXT_TMPFILE: AtomicU64 = AtomicU64::new(0);
+
+        self.check_writable()?;
+        let dir = self.translate(dir)?;
+        if !dir.is_dir() {
+            return Err(libc_riscv32::ENOTDIR);
+        }
+
+        loop {
+            let n = NEXT_TMPFILE.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!(".riscuit-tmpfile-{}-{n}", std::process::id()));
+            let file = OpenOptions::new()
+                .read(flags & libc_riscv32::O_ACCMODE == libc_riscv32::O_RDWR)
+                .write(true)
+                .append(flags & libc_riscv32::O_APPEND != 0)
+                .create_new(true)
+                .mode(mode & 0o7777)
+                .open(&path);
+            match file {
+                Ok(file) => {
+                    std::fs::remove_file(&path).map_err(host_errno)?;
+                    let cache = self.cache.clone();
+                    return Ok(Box::new(HostFile { file, cache }));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(host_errno(e)),
+            }
+        }
+    }
+
+    fn mkdir(&mut self, path: &str, mode: u32) -> KernelResult<()> {
+        self.check_writable()?;
+        let host = self.translate_entry(path)?;
+        DirBuilder::new()
+            .mode(mode & 0o7777)
+            .create(host)
+            .map_err(host_errno)
+    }
+
+    fn unlink(&mut self, path: &str, dir: bool) -> KernelResult<()> {
+        self.check_writable()?;
+        let host = self.translate_entry(path)?;
+        if dir {
+            std::fs::remove_dir(host).map_err(host_errno)
+        } else {
+            std::fs::remove_file(host).map_err(host_errno)
+        }
+    }
+
+    fn rename(&mut self, from: &str, to: &str, flags: u32) -> KernelResult<()> {
+        self.check_writable()?;
+        let from = self.translate_entry(from)?;
+        let to = self.translate_entry(to)?;
+        match flags {
+            0 => {}
+            libc_riscv32::RENAME_NOREPLACE => {
+                if to.symlink_metadata().is_ok() {
+                    return Err(libc_riscv32::EEXIST);
+                }
+            }
+            // Exchanging needs renameat2 itself, which std doesn't expose
+            _ => return Err(libc_riscv32::EINVAL),
+        }
+
+        std::fs::rename(from, to).map_err(host_errno)
+    }
+}
+
+fn metadata_stat(meta: &Metadata) -> FileStat {
+    let ft = meta.file_type();
+    let kind = if ft.is_dir() {
+        FileKind::Dir
+    } else if ft.is_symlink() {
+        FileKind::Symlink
+    } else {
+        FileKind::File
+    };
+
+    FileStat {
+        kind,
+        perm: meta.mode() & 0o7777,
+        size: meta.len(),
+        ino: meta.ino(),
+        nlink: meta.nlink() as u32,
+        mtime_ns: (meta.mtime() as u64)
+            .saturating_mul(1_000_000_000)
+            .saturating_add(meta.mtime_nsec() as u64),
+    }
+}
+
+#[derive(Debug)]
+struct HostFile {
+    file: File,
+    /// Emptied when the file changes
+    cache: Option<HostCache>,
+}
+
+impl HostFile {
+    fn invalidate(&self) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate();
+        }
+    }
+}
+
+impl FileLike for HostFile {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        self.file.read(buf).map_err(host_errno)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        self.invalidate();
+        self.file.write(buf).map_err(host_errno)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> KernelResult<u64> {
+        self.file.seek(pos).map_err(host_errno)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(metadata_stat(&self.file.metadata().map_err(host_errno)?))
+    }
+
+    fn truncate(&mut self, len: u64) -> KernelResult<()> {
+        self.invalidate();
+        self.file.set_len(len).map_err(host_errno)
+    }
+
+    fn reopen_at(&self) -> Option<u64> {
+        (&self.file).stream_position().ok()
+    }
+}
+
+#[derive(Debug)]
+struct HostDir {
+    path: PathBuf,
+    stat: FileStat,
+}
+
+impl FileLike for HostDir {
+    fn read(&mut self, _buf: &mut [u8]) -> KernelResult<usize> {
+        Err(libc_riscv32::EISDIR)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(self.stat)
+    }
+
+    fn read_dir(&mut self) -> KernelResult<Vec<DirEntry>> {
+        let mut entries = std::fs::read_dir(&self.path)
+            .map_err(host_errno)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                Some(DirEntry {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    kind: metadata_stat(&meta).kind,
+                    ino: meta.ino(),
+                })
+            })
+            .collect::<Vec<_>>();
+        // Keep getdents64 offsets stable between calls
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(entries)
+    }
+
+    fn reopen_at(&self) -> Option<u64> {
+        Some(0)
+    }
+}
@@ -0,0 +1,536 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::SeekFrom,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{normalize, DirEntry, FileKind, FileLike, FileStat, Mount};
+use crate::{
+    errno::KernelResult,
+    hibernate::{StateReader, StateWriter},
+};
+
+/// Symlinks followed before giving up with `ELOOP`
+const MAX_SYMLINKS: usize = 8;
+
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    kind: FileKind,
+    perm: u32,
+    pub ino: u64,
+    mtime_ns: u64,
+    /// File contents, or the target of a symlink. Shared with open handles.
+    data: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Node {
+    fn new(kind: FileKind, perm: u32, data: Vec<u8>) -> Self {
+        Self {
+            kind,
+            perm,
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            mtime_ns: 0,
+            data: Rc::new(RefCell::new(data)),
+        }
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u64(self.ino);
+        w.u8(FileKind::ALL
+            .iter()
+            .position(|&kind| kind == self.kind)
+            .unwrap() as u8);
+        w.u32(self.perm);
+        w.u64(self.mtime_ns);
+        w.bytes(&self.data.borrow());
+    }
+
+    /// A node as [`Node::save`] saved it, keeping its inode number.
+    pub fn restore(r: &mut StateReader) -> Result<Self, String> {
+        let ino = r.u64()?;
+        let kind = *FileKind::ALL
+            .get(r.u8()? as usize)
+            .ok_or("unknown kind of file in kernel state")?;
+        let node = Self {
+            kind,
+            perm: r.u32()?,
+            ino,
+            mtime_ns: r.u64()?,
+            data: Rc::new(RefCell::new(r.bytes()?.to_vec())),
+        };
+        // Nodes made from now on mustn't reuse it
+        NEXT_INO.fetch_max(ino + 1, Ordering::Relaxed);
+
+        Ok(node)
+    }
+
+    fn stat(&self) -> FileStat {
+        FileStat {
+            kind: self.kind,
+            perm: self.perm,
+            size: self.data.borrow().len() as u64,
+            ino: self.ino,
+            nlink: 1,
+            mtime_ns: self.mtime_ns,
+        }
+    }
+}
+
+/// An in-memory filesystem, e.g. the contents of an initramfs archive.
+///
+/// Nodes are keyed by their mount-relative path; the root is `""`.
+#[derive(Debug)]
+pub(crate) struct MemFs {
+    nodes: BTreeMap<String, Node>,
+}
+
+impl Default for MemFs {
+    fn default() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(String::new(), Node::new(FileKind::Dir, 0o755, vec![]));
+
+        Self { nodes }
+    }
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a directory, along with any missing parents.
+    pub fn add_dir(&mut self, path: &str, perm: u32) {
+        let path = clean(path);
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.add_dir(parent, 0o755);
+        }
+        self.nodes
+            .entry(path)
+            .or_insert_with(|| Node::new(FileKind::Dir, perm, vec![]));
+    }
+
+    /// Create or replace a regular file, creating missing parent directories.
+    pub fn add_file(&mut self, path: &str, perm: u32, data: Vec<u8>) {
+        self.add_node(path, Node::new(FileKind::File, perm, data));
+    }
+
+    pub fn add_symlink(&mut self, path: &str, target: &str) {
+        let node = Node::new(FileKind::Symlink, 0o777, target.as_bytes().to_vec());
+        self.add_node(path, node);
+    }
+
+    /// Contents of the file at `path`, following symlinks.
+    pub fn contents(&self, path: &str) -> KernelResult<Vec<u8>> {
+        match self.lookup(&clean(path))?.1 {
+            node if node.kind == FileKind::File => Ok(node.data.borrow().clone()),
+            _ => Err(libc_riscv32::EISDIR),
+        }
+    }
+
+    /// Save every node, returning their inode numbers.
+    pub fn save(&self, w: &mut StateWriter) -> Vec<u64> {
+        w.u32(self.nodes.len() as u32);
+        for (path, node) in &self.nodes {
+            w.str(path);
+            node.save(w);
+        }
+
+        self.nodes.values().map(|node| node.ino).collect()
+    }
+
+    /// Replace every node with those [`MemFs::save`] saved, adding them to
+    /// `nodes` by inode number.
+    pub fn restore(
+        &mut self,
+        r: &mut StateReader,
+        nodes: &mut BTreeMap<u64, Node>,
+    ) -> Result<(), String> {
+        self.nodes.clear();
+        for _ in 0..r.u32()? {
+            let path = r.string()?;
+            let node = Node::restore(r)?;
+            nodes.insert(node.ino, node.clone());
+            self.nodes.insert(path, node);
+        }
+
+        Ok(())
+    }
+
+    fn add_node(&mut self, path: &str, node: Node) {
+        let path = clean(path);
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.add_dir(parent, 0o755);
+        }
+        self.nodes.insert(path, node);
+    }
+
+    /// Resolve symlinks in every component of `path`.
+    fn lookup(&self, path: &str) -> KernelResult<(String, &Node)> {
+        let mut resolved = String::new();
+        let mut hops = 0;
+        let mut rest = path.to_string();
+        loop {
+            let (head, tail) = match rest.split_once('/') {
+                Some((head, tail)) => (head.to_string(), tail.to_string()),
+                None => (rest.clone(), String::new()),
+            };
+            if head.is_empty() {
+                let node = self.nodes.get(&resolved).ok_or(libc_riscv32::ENOENT)?;
+                return Ok((resolved, node));
+            }
+
+            let candidate = join(&resolved, &head);
+            let node = self.nodes.get(&candidate).ok_or(libc_riscv32::ENOENT)?;
+            match node.kind {
+                FileKind::Symlink => {
+                    hops += 1;
+                    if hops > MAX_SYMLINKS {
+                        return Err(libc_riscv32::ELOOP);
+                    }
+                    // Absolute targets are relative to the mount root
+                    let target = String::from_utf8_lossy(&node.data.borrow()).into_owned();
+                    let target = normalize(&format!("/{resolved}"), &target);
+                    rest = clean(&format!("{target}/{tail}"));
+                    resolved = String::new();
+                }
+                FileKind::Dir => {
+                    resolved = candidate;
+                    rest = tail;
+                }
+                _ if tail.is_empty() => return Ok((candidate, node)),
+                _ => return Err(libc_riscv32::ENOTDIR),
+            }
+        }
+    }
+
+    /// Where a new node at `path` would live, once symlinks in its parent are resolved.
+    fn resolve_new(&self, path: &str) -> KernelResult<String> {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        match self.lookup(parent)? {
+            (parent, node) if node.kind == FileKind::Dir => Ok(join(&parent, name)),
+            _ => Err(libc_riscv32::ENOTDIR),
+        }
+    }
+
+    fn is_empty_dir(&self, path: &str) -> bool {
+        let prefix = format!("{path}/");
+        !self
+            .nodes
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(p, _)| p.starts_with(&prefix))
+    }
+
+    /// Remove `path` and everything under it, keyed by their path relative to `path`.
+    fn take_subtree(&mut self, path: &str) -> Vec<(String, Node)> {
+        let prefix = format!("{path}/");
+        let keys: Vec<String> = self
+            .nodes
+            .range(path.to_string()..)
+            .map(|(p, _)| p)
+            .take_while(|p| p.starts_with(path))
+            .filter(|p| *p == path || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+        keys.into_iter()
+            .map(|key| {
+                let node = self.nodes.remove(&key).unwrap();
+                (key[path.len()..].to_string(), node)
+            })
+            .collect()
+    }
+
+    fn put_subtree(&mut self, path: &str, subtree: Vec<(String, Node)>) {
+        for (rel, node) in subtree {
+            self.nodes.insert(format!("{path}{rel}"), node);
+        }
+    }
+}
+
+impl Mount for MemFs {
+    fn open(&mut self, path: &str, flags: u32, mode: u32) -> KernelResult<Box<dyn FileLike>> {
+        let access = flags & libc_riscv32::O_ACCMODE;
+        let node = match self.lookup(path) {
+            Ok(_) if flags & libc_riscv32::O_CREAT != 0 && flags & libc_riscv32::O_EXCL != 0 => {
+                return Err(libc_riscv32::EEXIST)
+            }
+            Ok((_, node)) => node.clone(),
+            Err(libc_riscv32::ENOENT) if flags & libc_riscv32::O_CREAT != 0 => {
+                let path = self.resolve_new(path)?;
+                let node = Node::new(FileKind::File, mode & 0o7777, vec![]);
+                self.nodes.insert(path, node.clone());
+                node
+            }
+            Err(e) => return Err(e),
+        };
+
+        match node.kind {
+            FileKind::Dir if access != libc_riscv32::O_RDONLY => Err(libc_riscv32::EISDIR),
+            FileKind::Dir => {
+                let dir = self.lookup(path)?.0;
+                let prefix = if dir.is_empty() {
+                    String::new()
+                } else {
+                    format!("{dir}/")
+                };
+                let entries = self
+                    .nodes
+                    .range(prefix.clone()..)
+                    .skip_while(|(p, _)| p.is_empty())
+                    .take_while(|(p, _)| p.starts_with(&prefix))
+                    .filter(|(p, _)| !p[prefix.len()..].contains('/'))
+                    .map(|(p, n)| DirEntry {
+                        name: p[prefix.len()..].to_string(),
+                        kind: n.kind,
+                        ino: n.ino,
+                    })
+                    .collect();
+                Ok(Box::new(MemDir {
+                    stat: node.stat(),
+                    entries,
+                }))
+            }
+            _ if flags & libc_riscv32::O_DIRECTORY != 0 => Err(libc_riscv32::ENOTDIR),
+            _ => {
+                if flags & libc_riscv32::O_TRUNC != 0 && access != libc_riscv32::O_RDONLY {
+                    node.data.borrow_mut().clear();
+                }
+                Ok(Box::new(MemFile {
+                    node,
+                    pos: 0,
+                    append: flags & libc_riscv32::O_APPEND != 0,
+                }))
+            }
+        }
+    }
+
+    fn stat(&mut self, path: &str) -> KernelResult<FileStat> {
+        Ok(self.lookup(path)?.1.stat())
+    }
+
+    fn open_tmpfile(
+        &mut self,
+        dir: &str,
+        flags: u32,
+        mode: u32,
+    ) -> KernelResult<Box<dyn FileLike>> {
+        if self.lookup(dir)?.1.kind != FileKind::Dir {
+            return Err(libc_riscv32::ENOTDIR);
+        }
+
+        Ok(Box::new(MemFile {
+            node: Node::new(FileKind::File, mode & 0o7777, vec![]),
+            pos: 0,
+            append: flags & libc_riscv32::O_APPEND != 0,
+        }))
+    }
+
+    fn mkdir(&mut self, path: &str, mode: u32) -> KernelResult<()> {
+        let path = self.resolve_new(path)?;
+        if self.nodes.contains_key(&path) {
+            return Err(libc_riscv32::EEXIST);
+        }
+        self.nodes
+            .insert(path, Node::new(FileKind::Dir, mode & 0o7777, vec![]));
+
+        Ok(())
+    }
+
+    fn unlink(&mut self, path: &str, dir: bool) -> KernelResult<()> {
+        let path = self.resolve_new(path)?;
+        let node = self.nodes.get(&path).ok_or(libc_riscv32::ENOENT)?;
+        match (node.kind, dir) {
+            (FileKind::Dir, false) => return Err(libc_riscv32::EISDIR),
+            (FileKind::Dir, true) if !self.is_empty_dir(&path) => {
+                return Err(libc_riscv32::ENOTEMPTY)
+            }
+            (FileKind::Dir, true) => {}
+            (_, true) => return Err(libc_riscv32::ENOTDIR),
+            (_, false) => {}
+        }
+        // Open handles keep their own reference to the contents
+        self.nodes.remove(&path);
+
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &str, to: &str, flags: u32) -> KernelResult<()> {
+        let from = self.resolve_new(from)?;
+        let to = self.resolve_new(to)?;
+        let src = self.nodes.get(&from).ok_or(libc_riscv32::ENOENT)?.kind;
+        let dst = self.nodes.get(&to).map(|node| node.kind);
+
+        if flags & libc_riscv32::RENAME_EXCHANGE != 0 {
+            if flags & libc_riscv32::RENAME_NOREPLACE != 0 {
+                return Err(libc_riscv32::EINVAL);
+            }
+            dst.ok_or(libc_riscv32::ENOENT)?;
+            if from.starts_with(&format!("{to}/")) || to.starts_with(&format!("{from}/")) {
+                return Err(libc_riscv32::EINVAL);
+            }
+            let from_tree = self.take_subtree(&from);
+            let to_tree = self.take_subtree(&to);
+            self.put_subtree(&to, from_tree);
+            self.put_subtree(&from, to_tree);
+            return Ok(());
+        }
+        if flags & !libc_riscv32::RENAME_NOREPLACE != 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+
+        if from == to {
+            return Ok(());
+        }
+        if to.starts_with(&format!("{from}/")) {
+            // A directory can't become its own descendant
+            return Err(libc_riscv32::EINVAL);
+        }
+        match (src, dst) {
+            (_, None) => {}
+            (_, Some(_)) if flags & libc_riscv32::RENAME_NOREPLACE != 0 => {
+                return Err(libc_riscv32::EEXIST)
+            }
+            (FileKind::Dir, Some(FileKind::Dir)) if !self.is_empty_dir(&to) => {
+                return Err(libc_riscv32::ENOTEMPTY)
+            }
+            (FileKind::Dir, Some(FileKind::Dir)) => {}
+            (FileKind::Dir, Some(_)) => return Err(libc_riscv32::ENOTDIR),
+            (_, Some(FileKind::Dir)) => return Err(libc_riscv32::EISDIR),
+            (_, Some(_)) => {}
+        }
+
+        self.nodes.remove(&to);
+        let tree = self.take_subtree(&from);
+        self.put_subtree(&to, tree);
+
+        Ok(())
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+/// Normalize to a mount-relative path without leading or trailing slashes.
+fn clean(path: &str) -> String {
+    normalize("/", path)[1..].to_string()
+}
+
+#[derive(Debug)]
+pub(crate) struct MemFile {
+    node: Node,
+    pos: u64,
+    append: bool,
+}
+
+impl MemFile {
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// Save the file, but not its node, which is saved with its filesystem
+    /// or on its own.
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u64(self.node.ino);
+        w.u64(self.pos);
+        w.u8(self.append as u8);
+    }
+
+    /// The file [`MemFile::save`] saved, open on its node in `nodes`.
+    pub fn restore(r: &mut StateReader, nodes: &BTreeMap<u64, Node>) -> Result<Self, String> {
+        let ino = r.u64()?;
+        let node = nodes
+            .get(&ino)
+            .ok_or_else(|| format!("no inode {ino} in kernel state"))?;
+        Ok(Self {
+            node: node.clone(),
+            pos: r.u64()?,
+            append: r.bool()?,
+        })
+    }
+}
+
+impl FileLike for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        let data = self.node.data.borrow();
+        let start = (self.pos as usize).min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        let mut data = self.node.data.borrow_mut();
+        if self.append {
+            self.pos = data.len() as u64;
+        }
+        let start = self.pos as usize;
+        let end = start + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        self.pos = end as u64;
+
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> KernelResult<u64> {
+        let len = self.node.data.borrow().len() as i64;
+        let new = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::Current(off) => self.pos as i64 + off,
+            SeekFrom::End(off) => len + off,
+        };
+        if new < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        self.pos = new as u64;
+
+        Ok(self.pos)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(self.node.stat())
+    }
+
+    fn truncate(&mut self, len: u64) -> KernelResult<()> {
+        self.node.data.borrow_mut().resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct MemDir {
+    stat: FileStat,
+    entries: Vec<DirEntry>,
+}
+
+impl FileLike for MemDir {
+    fn read(&mut self, _buf: &mut [u8]) -> KernelResult<usize> {
+        Err(libc_riscv32::EISDIR)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(self.stat)
+    }
+
+    fn read_dir(&mut self) -> KernelResult<Vec<DirEntry>> {
+        Ok(self.entries.clone())
+    }
+
+    fn reopen_at(&self) -> Option<u64> {
+        Some(0)
+    }
+}
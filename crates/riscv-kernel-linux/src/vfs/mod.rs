@@ -0,0 +1,425 @@
+mod archive;
+mod cache;
+mod event;
+mod host;
+mod memfs;
+mod proc;
+mod shm;
+mod socket;
+mod stdio;
+
+use std::{any::Any, collections::BTreeMap, fmt::Debug, io::SeekFrom};
+
+use riscv_vm::shm::SharedMemory;
+
+use crate::{
+    errno::KernelResult,
+    hibernate::{StateReader, StateWriter},
+};
+
+pub(crate) use archive::unpack;
+pub use archive::ArchiveError;
+pub use cache::{HostCache, HostCacheStats};
+pub(crate) use event::{Epoll, EpollEvent, EventFd, TimerFd};
+pub use host::HostMount;
+pub(crate) use memfs::{MemFile, MemFs, Node};
+pub(crate) use proc::ProcFs;
+pub(crate) use shm::{ShmFile, ShmFs};
+pub(crate) use socket::{Binding, SocketKind, UnixAddr, UnixSocket, QUEUE_CAPACITY};
+pub use stdio::CapturedStdio;
+pub(crate) use stdio::Stdio;
+
+/// The type of a file, as reported by `stat` and `getdents`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    CharDevice,
+    Fifo,
+    Socket,
+}
+
+impl FileKind {
+    pub(crate) const ALL: [FileKind; 6] = [
+        FileKind::File,
+        FileKind::Dir,
+        FileKind::Symlink,
+        FileKind::CharDevice,
+        FileKind::Fifo,
+        FileKind::Socket,
+    ];
+
+    /// The `S_IFMT` bits for this kind.
+    pub const fn mode_bits(self) -> u32 {
+        match self {
+            FileKind::File => libc_riscv32::S_IFREG,
+            FileKind::Dir => libc_riscv32::S_IFDIR,
+            FileKind::Symlink => libc_riscv32::S_IFLNK,
+            FileKind::CharDevice => libc_riscv32::S_IFCHR,
+            FileKind::Fifo => libc_riscv32::S_IFIFO,
+            FileKind::Socket => libc_riscv32::S_IFSOCK,
+        }
+    }
+
+    /// The `d_type` for this kind.
+    pub const fn dirent_type(self) -> u8 {
+        match self {
+            FileKind::File => libc_riscv32::DT_REG,
+            FileKind::Dir => libc_riscv32::DT_DIR,
+            FileKind::Symlink => libc_riscv32::DT_LNK,
+            FileKind::CharDevice => libc_riscv32::DT_CHR,
+            FileKind::Fifo => libc_riscv32::DT_FIFO,
+            FileKind::Socket => libc_riscv32::DT_SOCK,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub kind: FileKind,
+    /// Permission bits
+    pub perm: u32,
+    pub size: u64,
+    pub ino: u64,
+    pub nlink: u32,
+    /// Modification time, in ns since the Unix epoch
+    pub mtime_ns: u64,
+}
+
+impl FileStat {
+    pub const fn mode(&self) -> u32 {
+        self.kind.mode_bits() | (self.perm & 0o7777)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: FileKind,
+    pub ino: u64,
+}
+
+/// An open file description.
+///
+/// Operations return a positive errno on failure. Besides the kernel's own
+/// files, embedders can give the guest descriptors of their own, such as a
+/// device or a channel to the host, with [`MockLinux::install_file`]; the
+/// guest uses them through `read`, `write`, `poll` and the like.
+///
+/// [`MockLinux::install_file`]: crate::MockLinux::install_file
+pub trait FileLike: Debug + Any {
+    fn read(&mut self, _buf: &mut [u8]) -> KernelResult<usize> {
+        Err(libc_riscv32::EINVAL)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> KernelResult<usize> {
+        Err(libc_riscv32::EINVAL)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> KernelResult<u64> {
+        Err(libc_riscv32::ESPIPE)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat>;
+
+    /// All entries of a directory, excluding `.` and `..`.
+    fn read_dir(&mut self) -> KernelResult<Vec<DirEntry>> {
+        Err(libc_riscv32::ENOTDIR)
+    }
+
+    fn is_tty(&self) -> bool {
+        false
+    }
+
+    fn truncate(&mut self, _len: u64) -> KernelResult<()> {
+        Err(libc_riscv32::EINVAL)
+    }
+
+    /// The object backing this file, if it can be mapped `MAP_SHARED`.
+    fn shared_memory(&self) -> Option<&SharedMemory> {
+        None
+    }
+
+    /// Current readiness, as `POLL*` bits. Reads that would block should
+    /// fail with `EAGAIN`.
+    fn poll(&mut self) -> u16 {
+        libc_riscv32::POLLIN | libc_riscv32::POLLOUT
+    }
+
+    /// Monotonic time at which readiness may change without guest action.
+    ///
+    /// Blocking operations sleep until then, since nothing else can wake them.
+    fn wake_at(&self) -> Option<u64> {
+        None
+    }
+
+    /// The position to seek back to after opening the file's path again,
+    /// for a kernel restored from [`Kernel::save_state`], or `None` if it
+    /// can't be opened again that way. The kernel can't be saved while a
+    /// file that returns `None` is open.
+    ///
+    /// [`Kernel::save_state`]: riscv_vm::machine::Kernel::save_state
+    fn reopen_at(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A filesystem attached to the [`Vfs`] at some guest path.
+///
+/// Paths passed to a mount are relative to its root, normalized, and never
+/// contain `.` or `..` components. The mount root is the empty path.
+pub(crate) trait Mount: Debug + Any {
+    fn open(&mut self, path: &str, flags: u32, mode: u32) -> KernelResult<Box<dyn FileLike>>;
+
+    fn stat(&mut self, path: &str) -> KernelResult<FileStat>;
+
+    /// Create an unnamed regular file in the directory `dir`, for `O_TMPFILE`.
+    fn open_tmpfile(&mut self, dir: &str, flags: u32, mode: u32)
+        -> KernelResult<Box<dyn FileLike>>;
+
+    fn mkdir(&mut self, path: &str, mode: u32) -> KernelResult<()>;
+
+    /// Remove the entry at `path` without following a final symlink.
+    ///
+    /// With `dir`, only empty directories are removed, like `rmdir`.
+    fn unlink(&mut self, path: &str, dir: bool) -> KernelResult<()>;
+
+    /// Move `from` to `to` within this mount. `flags` are `RENAME_*`.
+    fn rename(&mut self, from: &str, to: &str, flags: u32) -> KernelResult<()>;
+}
+
+/// Join `path` onto the absolute directory `cwd`, resolving `.` and `..` lexically.
+pub(crate) fn normalize(cwd: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = vec![];
+    let base = if path.starts_with('/') { "" } else { cwd };
+    for part in base.split('/').chain(path.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    format!("/{}", parts.join("/"))
+}
+
+/// The guest's mount table.
+#[derive(Debug, Default)]
+pub(crate) struct Vfs {
+    /// Mount point (normalized, absolute) -> filesystem
+    mounts: BTreeMap<String, Box<dyn Mount>>,
+}
+
+impl Vfs {
+    pub fn mount(&mut self, at: &str, fs: Box<dyn Mount>) {
+        self.mounts.insert(normalize("/", at), fs);
+    }
+
+    pub fn unmount(&mut self, at: &str) {
+        self.mounts.remove(&normalize("/", at));
+    }
+
+    /// Find the innermost mount containing the absolute, normalized `path`.
+    pub fn resolve<'a>(&mut self, path: &'a str) -> KernelResult<(&mut dyn Mount, &'a str)> {
+        let (prefix, rel) = self.locate(path)?;
+        Ok((self.mounts.get_mut(&prefix).unwrap().as_mut(), rel))
+    }
+
+    /// The mount point and mount-relative path of `path`.
+    fn locate<'a>(&self, path: &'a str) -> KernelResult<(String, &'a str)> {
+        let (prefix, rel) = self
+            .mounts
+            .keys()
+            .filter_map(|prefix| {
+                let rel = match prefix.as_str() {
+                    "/" => path.strip_prefix('/')?,
+                    prefix => match path.strip_prefix(prefix)? {
+                        "" => "",
+                        rest => rest.strip_prefix('/')?,
+                    },
+                };
+                Some((prefix, rel))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .ok_or(libc_riscv32::ENOENT)?;
+
+        Ok((prefix.clone(), rel))
+    }
+
+    pub fn open(&mut self, path: &str, flags: u32, mode: u32) -> KernelResult<Box<dyn FileLike>> {
+        let (mount, rel) = self.resolve(path)?;
+        if flags & libc_riscv32::O_TMPFILE == libc_riscv32::O_TMPFILE {
+            if flags & libc_riscv32::O_ACCMODE == libc_riscv32::O_RDONLY {
+                return Err(libc_riscv32::EINVAL);
+            }
+            return mount.open_tmpfile(rel, flags, mode);
+        }
+        mount.open(rel, flags, mode)
+    }
+
+    pub fn stat(&mut self, path: &str) -> KernelResult<FileStat> {
+        let (mount, rel) = self.resolve(path)?;
+        mount.stat(rel)
+    }
+
+    pub fn mkdir(&mut self, path: &str, mode: u32) -> KernelResult<()> {
+        match self.resolve(path)? {
+            (_, "") => Err(libc_riscv32::EEXIST),
+            (mount, rel) => mount.mkdir(rel, mode),
+        }
+    }
+
+    pub fn unlink(&mut self, path: &str, dir: bool) -> KernelResult<()> {
+        match self.resolve(path)? {
+            (_, "") => Err(libc_riscv32::EBUSY),
+            (mount, rel) => mount.unlink(rel, dir),
+        }
+    }
+
+    /// Save the contents of the memory filesystems, returning the inode
+    /// numbers saved. Other mounts are the host's, or rebuilt from the
+    /// kernel's state.
+    pub fn save(&self, w: &mut StateWriter) -> Vec<u64> {
+        let memfs: Vec<_> = self
+            .mounts
+            .iter()
+            .filter_map(|(at, mount)| {
+                Some((at, (mount.as_ref() as &dyn Any).downcast_ref::<MemFs>()?))
+            })
+            .collect();
+        let mut inos = vec![];
+        w.u32(memfs.len() as u32);
+        for (at, fs) in memfs {
+            w.str(at);
+            inos.extend(fs.save(w));
+        }
+
+        inos
+    }
+
+    /// Restore the memory filesystems [`Vfs::save`] saved onto the ones
+    /// mounted in the same places, returning their nodes by inode number.
+    pub fn restore(&mut self, r: &mut StateReader) -> Result<BTreeMap<u64, Node>, String> {
+        let mut nodes = BTreeMap::new();
+        for _ in 0..r.u32()? {
+            let at = r.string()?;
+            let fs = self
+                .mounts
+                .get_mut(&at)
+                .and_then(|mount| (mount.as_mut() as &mut dyn Any).downcast_mut::<MemFs>())
+                .ok_or_else(|| format!("no memory filesystem mounted at {at}"))?;
+            fs.restore(r, &mut nodes)?;
+        }
+
+        Ok(nodes)
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str, flags: u32) -> KernelResult<()> {
+        let (from_mount, from) = self.locate(from)?;
+        let (to_mount, to) = self.locate(to)?;
+        if from_mount != to_mount {
+            return Err(libc_riscv32::EXDEV);
+        }
+        if from.is_empty() || to.is_empty() {
+            return Err(libc_riscv32::EBUSY);
+        }
+
+        let mount = self.mounts.get_mut(&from_mount).unwrap();
+        mount.rename(from, to, flags)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct OpenFile {
+    pub file: Box<dyn FileLike>,
+    /// Absolute guest path the file was opened at
+    pub path: String,
+    pub flags: u32,
+    /// Next `getdents64` entry for directories
+    pub dir_pos: usize,
+}
+
+/// The process' file descriptor table.
+#[derive(Debug, Default)]
+pub(crate) struct FdTable {
+    files: BTreeMap<i32, OpenFile>,
+}
+
+impl FdTable {
+    /// Descriptors stay below this, the usual `RLIMIT_NOFILE`.
+    pub const LIMIT: i32 = 1024;
+
+    /// Set up fds 0-2, forwarding to the host's stdio if `passthrough`.
+    pub fn with_stdio(passthrough: bool) -> Self {
+        let mut table = Self::default();
+        table.set_stdio(|fd| Stdio::new(fd, passthrough));
+        table
+    }
+
+    /// Replace fds 0-2 with `stdio(fd)`.
+    pub fn set_stdio(&mut self, stdio: impl Fn(i32) -> Stdio) {
+        for fd in 0..3 {
+            let file = OpenFile {
+                file: Box::new(stdio(fd)),
+                path: format!("/dev/fd/{fd}"),
+                flags: if fd == 0 {
+                    libc_riscv32::O_RDONLY
+                } else {
+                    libc_riscv32::O_WRONLY
+                },
+                dir_pos: 0,
+            };
+            self.files.insert(fd, file);
+        }
+    }
+
+    /// Install `file` at the lowest free descriptor, or fail with `EMFILE`
+    /// if there's none.
+    pub fn insert(&mut self, file: OpenFile) -> KernelResult<i32> {
+        let fd = (0..Self::LIMIT)
+            .find(|fd| !self.files.contains_key(fd))
+            .ok_or(libc_riscv32::EMFILE)?;
+        self.files.insert(fd, file);
+
+        Ok(fd)
+    }
+
+    pub fn get(&mut self, fd: i32) -> KernelResult<&mut OpenFile> {
+        self.files.get_mut(&fd).ok_or(libc_riscv32::EBADF)
+    }
+
+    /// The file at `fd`, if it's a `T`. Other files are `EINVAL`.
+    pub fn get_as<T: FileLike>(&mut self, fd: i32) -> KernelResult<&mut T> {
+        let file: &mut dyn Any = self.get(fd)?.file.as_mut();
+        file.downcast_mut().ok_or(libc_riscv32::EINVAL)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &OpenFile)> {
+        self.files.iter().map(|(&fd, file)| (fd, file))
+    }
+
+    pub fn contains(&self, fd: i32) -> bool {
+        self.files.contains_key(&fd)
+    }
+
+    /// Close every descriptor but 0-2.
+    pub fn close_all_but_stdio(&mut self) {
+        self.files.retain(|&fd, _| fd < 3);
+    }
+
+    pub fn remove(&mut self, fd: i32) -> KernelResult<OpenFile> {
+        self.files.remove(&fd).ok_or(libc_riscv32::EBADF)
+    }
+
+    /// Install `file` at `fd`, returning the file it replaces.
+    pub fn insert_at(&mut self, fd: i32, file: OpenFile) -> Option<OpenFile> {
+        self.files.insert(fd, file)
+    }
+}
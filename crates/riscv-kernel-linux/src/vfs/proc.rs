@@ -0,0 +1,190 @@
+use std::{cell::RefCell, fmt::Write, io::SeekFrom, rc::Rc};
+
+use super::{DirEntry, FileKind, FileLike, FileStat, Mount};
+use crate::errno::KernelResult;
+
+/// Process details rendered into `/proc/<pid>/status`.
+///
+/// The kernel refreshes this before each access under `/proc`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProcStatus {
+    pub name: String,
+    pub pid: u32,
+    pub rss_bytes: u64,
+    pub peak_rss_bytes: u64,
+    /// Heap and anonymous mappings
+    pub data_bytes: u64,
+    pub threads: u32,
+    pub no_new_privs: bool,
+}
+
+impl ProcStatus {
+    fn render(&self) -> String {
+        let kb = |bytes: u64| format!("{:>8} kB", bytes / 1024);
+        let mut out = String::new();
+        let _ = writeln!(out, "Name:\t{}", self.name);
+        let _ = writeln!(out, "State:\tR (running)");
+        let _ = writeln!(out, "Tgid:\t{}", self.pid);
+        let _ = writeln!(out, "Pid:\t{}", self.pid);
+        let _ = writeln!(out, "PPid:\t0");
+        let _ = writeln!(out, "VmHWM:\t{}", kb(self.peak_rss_bytes));
+        let _ = writeln!(out, "VmRSS:\t{}", kb(self.rss_bytes));
+        let _ = writeln!(out, "VmData:\t{}", kb(self.data_bytes));
+        let _ = writeln!(out, "Threads:\t{}", self.threads);
+        let _ = writeln!(out, "NoNewPrivs:\t{}", self.no_new_privs as u8);
+        out
+    }
+}
+
+/// A minimal procfs: `/proc/cpuinfo`, and `/proc/self` and `/proc/<pid>`,
+/// each containing `status`.
+///
+/// Clones share the same contents.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProcFs {
+    pub status: Rc<RefCell<ProcStatus>>,
+    pub cpuinfo: Rc<RefCell<String>>,
+}
+
+impl ProcFs {
+    /// The process directory `path` names, if any.
+    fn is_process_dir(&self, path: &str) -> bool {
+        path == "self" || path.parse() == Ok(self.status.borrow().pid)
+    }
+
+    fn lookup(&self, path: &str) -> KernelResult<FileKind> {
+        match path.split_once('/') {
+            None if path.is_empty() || self.is_process_dir(path) => Ok(FileKind::Dir),
+            None if path == "cpuinfo" => Ok(FileKind::File),
+            Some((dir, "status")) if self.is_process_dir(dir) => Ok(FileKind::File),
+            _ => Err(libc_riscv32::ENOENT),
+        }
+    }
+}
+
+impl Mount for ProcFs {
+    fn open(&mut self, path: &str, flags: u32, _mode: u32) -> KernelResult<Box<dyn FileLike>> {
+        if flags & libc_riscv32::O_ACCMODE != libc_riscv32::O_RDONLY
+            || flags & libc_riscv32::O_CREAT != 0
+        {
+            return Err(libc_riscv32::EACCES);
+        }
+
+        let entry = |name: &str, kind| DirEntry {
+            name: name.to_string(),
+            kind,
+            ino: 0,
+        };
+        let pid = self.status.borrow().pid;
+        let contents = match (self.lookup(path)?, path) {
+            (FileKind::Dir, "") => ProcContents::Dir(vec![
+                entry(&pid.to_string(), FileKind::Dir),
+                entry("cpuinfo", FileKind::File),
+                entry("self", FileKind::Dir),
+            ]),
+            (FileKind::Dir, _) => ProcContents::Dir(vec![entry("status", FileKind::File)]),
+            _ if flags & libc_riscv32::O_DIRECTORY != 0 => return Err(libc_riscv32::ENOTDIR),
+            (_, "cpuinfo") => ProcContents::File(self.cpuinfo.borrow().clone().into_bytes()),
+            _ => ProcContents::File(self.status.borrow().render().into_bytes()),
+        };
+
+        Ok(Box::new(ProcFile { contents, pos: 0 }))
+    }
+
+    fn stat(&mut self, path: &str) -> KernelResult<FileStat> {
+        Ok(proc_stat(self.lookup(path)?))
+    }
+
+    fn open_tmpfile(
+        &mut self,
+        _dir: &str,
+        _flags: u32,
+        _mode: u32,
+    ) -> KernelResult<Box<dyn FileLike>> {
+        Err(libc_riscv32::EOPNOTSUPP)
+    }
+
+    fn mkdir(&mut self, _path: &str, _mode: u32) -> KernelResult<()> {
+        Err(libc_riscv32::EACCES)
+    }
+
+    fn unlink(&mut self, _path: &str, _dir: bool) -> KernelResult<()> {
+        Err(libc_riscv32::EACCES)
+    }
+
+    fn rename(&mut self, _from: &str, _to: &str, _flags: u32) -> KernelResult<()> {
+        Err(libc_riscv32::EACCES)
+    }
+}
+
+fn proc_stat(kind: FileKind) -> FileStat {
+    FileStat {
+        kind,
+        perm: if kind == FileKind::Dir { 0o555 } else { 0o444 },
+        // Like Linux, generated files report no size
+        size: 0,
+        ino: 0,
+        nlink: 1,
+        mtime_ns: 0,
+    }
+}
+
+#[derive(Debug)]
+enum ProcContents {
+    Dir(Vec<DirEntry>),
+    /// Rendered when opened
+    File(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct ProcFile {
+    contents: ProcContents,
+    pos: usize,
+}
+
+impl FileLike for ProcFile {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        let ProcContents::File(data) = &self.contents else {
+            return Err(libc_riscv32::EISDIR);
+        };
+        let start = self.pos.min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> KernelResult<u64> {
+        let new = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::Current(off) => self.pos as i64 + off,
+            // Sizes are unknown, as on Linux
+            SeekFrom::End(_) => return Err(libc_riscv32::EINVAL),
+        };
+        if new < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        self.pos = new as usize;
+
+        Ok(self.pos as u64)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(proc_stat(match self.contents {
+            ProcContents::Dir(_) => FileKind::Dir,
+            ProcContents::File(_) => FileKind::File,
+        }))
+    }
+
+    fn read_dir(&mut self) -> KernelResult<Vec<DirEntry>> {
+        match &self.contents {
+            ProcContents::Dir(entries) => Ok(entries.clone()),
+            ProcContents::File(_) => Err(libc_riscv32::ENOTDIR),
+        }
+    }
+
+    fn reopen_at(&self) -> Option<u64> {
+        Some(self.pos as u64)
+    }
+}
@@ -0,0 +1,221 @@
+use std::{cell::RefCell, collections::BTreeMap, io::SeekFrom, rc::Rc};
+
+use riscv_vm::shm::SharedMemory;
+
+use super::{DirEntry, FileKind, FileLike, FileStat, Mount};
+use crate::errno::{host_errno, KernelResult};
+
+/// An open `memfd` or POSIX shared memory object.
+#[derive(Debug)]
+pub(crate) struct ShmFile {
+    shm: SharedMemory,
+    pos: u64,
+    append: bool,
+}
+
+impl ShmFile {
+    pub fn new(shm: SharedMemory, flags: u32) -> Self {
+        Self {
+            shm,
+            pos: 0,
+            append: flags & libc_riscv32::O_APPEND != 0,
+        }
+    }
+}
+
+impl FileLike for ShmFile {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        let n = self.shm.read_at(buf, self.pos).map_err(host_errno)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        if self.append {
+            self.pos = self.shm.len().map_err(host_errno)?;
+        }
+        let n = self.shm.write_at(buf, self.pos).map_err(host_errno)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> KernelResult<u64> {
+        let new = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::Current(off) => self.pos as i64 + off,
+            SeekFrom::End(off) => self.shm.len().map_err(host_errno)? as i64 + off,
+        };
+        if new < 0 {
+            return Err(libc_riscv32::EINVAL);
+        }
+        self.pos = new as u64;
+
+        Ok(self.pos)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(FileStat {
+            kind: FileKind::File,
+            perm: 0o600,
+            size: self.shm.len().map_err(host_errno)?,
+            ino: 0,
+            nlink: 1,
+            mtime_ns: 0,
+        })
+    }
+
+    fn truncate(&mut self, len: u64) -> KernelResult<()> {
+        self.shm.set_len(len).map_err(host_errno)
+    }
+
+    fn shared_memory(&self) -> Option<&SharedMemory> {
+        Some(&self.shm)
+    }
+}
+
+/// The flat namespace of POSIX shared memory objects, mounted at `/dev/shm`.
+///
+/// Clones share the same objects, so the embedder can look them up by name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ShmFs {
+    objects: Rc<RefCell<BTreeMap<String, SharedMemory>>>,
+}
+
+impl ShmFs {
+    pub fn get(&self, name: &str) -> Option<SharedMemory> {
+        self.objects.borrow().get(name).cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.borrow().is_empty()
+    }
+}
+
+impl Mount for ShmFs {
+    fn open(&mut self, path: &str, flags: u32, _mode: u32) -> KernelResult<Box<dyn FileLike>> {
+        if path.is_empty() {
+            let entries = self
+                .objects
+                .borrow()
+                .keys()
+                .map(|name| DirEntry {
+                    name: name.clone(),
+                    kind: FileKind::File,
+                    ino: 0,
+                })
+                .collect();
+            return Ok(Box::new(ShmDir { entries }));
+        }
+        if path.contains('/') {
+            return Err(libc_riscv32::ENOENT);
+        }
+        if flags & libc_riscv32::O_DIRECTORY != 0 {
+            return Err(libc_riscv32::ENOTDIR);
+        }
+
+        let mut objects = self.objects.borrow_mut();
+        let shm = match objects.get(path) {
+            Some(_) if flags & libc_riscv32::O_CREAT != 0 && flags & libc_riscv32::O_EXCL != 0 => {
+                return Err(libc_riscv32::EEXIST)
+            }
+            Some(shm) => shm.clone(),
+            None if flags & libc_riscv32::O_CREAT != 0 => {
+                let shm = SharedMemory::new(path).map_err(host_errno)?;
+                objects.insert(path.to_string(), shm.clone());
+                shm
+            }
+            None => return Err(libc_riscv32::ENOENT),
+        };
+        if flags & libc_riscv32::O_TRUNC != 0 {
+            shm.set_len(0).map_err(host_errno)?;
+        }
+
+        Ok(Box::new(ShmFile::new(shm, flags)))
+    }
+
+    fn stat(&mut self, path: &str) -> KernelResult<FileStat> {
+        if path.is_empty() {
+            return ShmDir { entries: vec![] }.stat();
+        }
+        let shm = self.get(path).ok_or(libc_riscv32::ENOENT)?;
+        ShmFile::new(shm, 0).stat()
+    }
+
+    fn open_tmpfile(
+        &mut self,
+        dir: &str,
+        flags: u32,
+        _mode: u32,
+    ) -> KernelResult<Box<dyn FileLike>> {
+        if !dir.is_empty() {
+            return Err(libc_riscv32::ENOENT);
+        }
+        let shm = SharedMemory::new("tmpfile").map_err(host_errno)?;
+        Ok(Box::new(ShmFile::new(shm, flags)))
+    }
+
+    fn mkdir(&mut self, _path: &str, _mode: u32) -> KernelResult<()> {
+        Err(libc_riscv32::EPERM)
+    }
+
+    fn unlink(&mut self, path: &str, dir: bool) -> KernelResult<()> {
+        if dir {
+            return Err(libc_riscv32::ENOTDIR);
+        }
+        // Open descriptors and mappings keep the object alive
+        self.objects
+            .borrow_mut()
+            .remove(path)
+            .map(|_| ())
+            .ok_or(libc_riscv32::ENOENT)
+    }
+
+    fn rename(&mut self, from: &str, to: &str, flags: u32) -> KernelResult<()> {
+        let mut objects = self.objects.borrow_mut();
+        if !objects.contains_key(from) {
+            return Err(libc_riscv32::ENOENT);
+        }
+        match flags {
+            0 => {}
+            libc_riscv32::RENAME_NOREPLACE if objects.contains_key(to) => {
+                return Err(libc_riscv32::EEXIST)
+            }
+            libc_riscv32::RENAME_NOREPLACE => {}
+            _ => return Err(libc_riscv32::EINVAL),
+        }
+        if to.contains('/') {
+            return Err(libc_riscv32::ENOENT);
+        }
+
+        let shm = objects.remove(from).unwrap();
+        objects.insert(to.to_string(), shm);
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ShmDir {
+    entries: Vec<DirEntry>,
+}
+
+impl FileLike for ShmDir {
+    fn read(&mut self, _buf: &mut [u8]) -> KernelResult<usize> {
+        Err(libc_riscv32::EISDIR)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(FileStat {
+            kind: FileKind::Dir,
+            perm: 0o1777,
+            size: 0,
+            ino: 0,
+            nlink: 2,
+            mtime_ns: 0,
+        })
+    }
+
+    fn read_dir(&mut self) -> KernelResult<Vec<DirEntry>> {
+        Ok(self.entries.clone())
+    }
+}
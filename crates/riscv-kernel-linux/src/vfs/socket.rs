@@ -0,0 +1,482 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+
+use super::{FileKind, FileLike, FileStat};
+use crate::errno::KernelResult;
+
+/// Bytes that can be queued for a socket, Linux's default `SO_RCVBUF`.
+pub(crate) const QUEUE_CAPACITY: usize = 212_992;
+
+/// The address of a UNIX domain socket.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum UnixAddr {
+    Unnamed,
+    /// An absolute guest path. Nothing is created there: sockets are only
+    /// found by their name
+    Path(String),
+    /// A name in the abstract namespace, without its leading NUL
+    Abstract(Vec<u8>),
+}
+
+impl UnixAddr {
+    /// `sun_path` as the guest sees it.
+    pub fn sun_path(&self) -> Vec<u8> {
+        match self {
+            UnixAddr::Unnamed => vec![],
+            UnixAddr::Path(path) => [path.as_bytes(), b"\0"].concat(),
+            UnixAddr::Abstract(name) => [b"\0", name.as_slice()].concat(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SocketKind {
+    Stream,
+    Datagram,
+    SeqPacket,
+}
+
+impl SocketKind {
+    /// The kind for a `SOCK_*` type.
+    pub fn from_type(ty: u32) -> Option<Self> {
+        match ty {
+            libc_riscv32::SOCK_STREAM => Some(Self::Stream),
+            libc_riscv32::SOCK_DGRAM => Some(Self::Datagram),
+            libc_riscv32::SOCK_SEQPACKET => Some(Self::SeqPacket),
+            _ => None,
+        }
+    }
+
+    pub fn sock_type(self) -> u32 {
+        match self {
+            Self::Stream => libc_riscv32::SOCK_STREAM,
+            Self::Datagram => libc_riscv32::SOCK_DGRAM,
+            Self::SeqPacket => libc_riscv32::SOCK_SEQPACKET,
+        }
+    }
+
+    /// Whether sockets of this kind listen for and accept connections.
+    fn connection_oriented(self) -> bool {
+        self != Self::Datagram
+    }
+}
+
+/// A message queued for a socket, with its sender's address.
+#[derive(Debug)]
+struct Message {
+    data: Vec<u8>,
+    from: UnixAddr,
+}
+
+/// What's been sent to one socket and not yet received.
+///
+/// Stream sockets read across messages; the others receive one at a time.
+#[derive(Debug, Default)]
+pub(crate) struct Queue {
+    messages: VecDeque<Message>,
+    bytes: usize,
+    /// The connected peer won't send any more
+    closed: bool,
+    /// Nothing will receive from it any more
+    abandoned: bool,
+}
+
+impl Queue {
+    fn space(&self) -> usize {
+        QUEUE_CAPACITY - self.bytes
+    }
+
+    /// Drop the first `n` bytes of a stream.
+    fn consume(&mut self, mut n: usize) {
+        self.bytes -= n;
+        while n > 0 {
+            let front = self.messages.front_mut().unwrap();
+            if front.data.len() > n {
+                front.data.drain(..n);
+                return;
+            }
+            n -= front.data.len();
+            self.messages.pop_front();
+        }
+    }
+}
+
+/// Connections to a bound stream or seqpacket socket waiting to be
+/// accepted.
+#[derive(Debug)]
+pub(crate) struct Backlog {
+    kind: SocketKind,
+    /// Most connections kept waiting, once listening
+    limit: Option<usize>,
+    pending: VecDeque<UnixSocket>,
+}
+
+/// What a bound address leads to, in [`MockLinux`]'s namespace of UNIX
+/// sockets.
+///
+/// [`MockLinux`]: crate::MockLinux
+#[derive(Debug, Clone)]
+pub(crate) enum Binding {
+    Listener(Weak<RefCell<Backlog>>),
+    Datagram(Weak<RefCell<Queue>>),
+}
+
+impl Binding {
+    /// Whether the socket bound there is still open.
+    pub fn in_use(&self) -> bool {
+        match self {
+            Binding::Listener(backlog) => backlog.strong_count() > 0,
+            Binding::Datagram(queue) => queue
+                .upgrade()
+                .is_some_and(|queue| !queue.borrow().abandoned),
+        }
+    }
+}
+
+/// A UNIX domain socket, connected only to others in the same VM.
+///
+/// The guest is the only process, so nothing ever waits on the other end:
+/// operations that would block fail with `EAGAIN`.
+#[derive(Debug)]
+pub(crate) struct UnixSocket {
+    pub kind: SocketKind,
+    pub addr: UnixAddr,
+    /// What others send this socket
+    rx: Rc<RefCell<Queue>>,
+    /// Where sends go unless told otherwise, and its address
+    peer: Option<(Rc<RefCell<Queue>>, UnixAddr)>,
+    /// Connections waiting, once a connection-oriented socket is bound
+    backlog: Option<Rc<RefCell<Backlog>>>,
+    shut_read: bool,
+    shut_write: bool,
+}
+
+impl UnixSocket {
+    pub fn new(kind: SocketKind) -> Self {
+        Self {
+            kind,
+            addr: UnixAddr::Unnamed,
+            rx: Rc::default(),
+            peer: None,
+            backlog: None,
+            shut_read: false,
+            shut_write: false,
+        }
+    }
+
+    /// Two sockets connected to each other, as from `socketpair`.
+    pub fn pair(kind: SocketKind) -> (Self, Self) {
+        let (mut a, mut b) = (Self::new(kind), Self::new(kind));
+        a.peer = Some((b.rx.clone(), UnixAddr::Unnamed));
+        b.peer = Some((a.rx.clone(), UnixAddr::Unnamed));
+        (a, b)
+    }
+
+    pub fn peer_addr(&self) -> Option<&UnixAddr> {
+        self.peer.as_ref().map(|(_, addr)| addr)
+    }
+
+    /// Bytes sent to this socket and not yet received, which is at most
+    /// [`QUEUE_CAPACITY`].
+    pub fn pending(&self) -> usize {
+        self.rx.borrow().bytes
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.backlog
+            .as_ref()
+            .is_some_and(|backlog| backlog.borrow().limit.is_some())
+    }
+
+    /// Name the socket `addr`, returning what others find there.
+    pub fn bind(&mut self, addr: UnixAddr) -> Binding {
+        self.addr = addr;
+        if self.kind.connection_oriented() {
+            let backlog = self.backlog.insert(Rc::new(RefCell::new(Backlog {
+                kind: self.kind,
+                limit: None,
+                pending: VecDeque::new(),
+            })));
+            Binding::Listener(Rc::downgrade(backlog))
+        } else {
+            Binding::Datagram(Rc::downgrade(&self.rx))
+        }
+    }
+
+    /// Accept connections, keeping up to `limit` more than accepted. An
+    /// unbound socket listens where nobody can connect.
+    pub fn listen(&mut self, limit: usize) -> KernelResult<()> {
+        if !self.kind.connection_oriented() {
+            return Err(libc_riscv32::EOPNOTSUPP);
+        }
+        if self.peer.is_some() {
+            return Err(libc_riscv32::EINVAL);
+        }
+        if self.backlog.is_none() {
+            self.bind(UnixAddr::Unnamed);
+        }
+        self.backlog.as_ref().unwrap().borrow_mut().limit = Some(limit);
+
+        Ok(())
+    }
+
+    /// Connect to the socket bound at `addr`, found through `binding`.
+    ///
+    /// Connections complete at once, waiting for the listener to accept
+    /// them. Datagram sockets only set where they send.
+    pub fn connect(&mut self, binding: &Binding, addr: UnixAddr) -> KernelResult<()> {
+        match binding {
+            Binding::Listener(backlog) if self.kind.connection_oriented() => {
+                if self.peer.is_some() {
+                    return Err(libc_riscv32::EISCONN);
+                }
+                if self.is_listening() {
+                    return Err(libc_riscv32::EINVAL);
+                }
+                let backlog = backlog.upgrade().ok_or(libc_riscv32::ECONNREFUSED)?;
+                let mut backlog = backlog.borrow_mut();
+                if backlog.kind != self.kind {
+                    return Err(libc_riscv32::EPROTOTYPE);
+                }
+                match backlog.limit {
+                    None => return Err(libc_riscv32::ECONNREFUSED),
+                    Some(limit) if backlog.pending.len() > limit => {
+                        return Err(libc_riscv32::EAGAIN)
+                    }
+                    Some(_) => {}
+                }
+
+                let mut server = Self::new(self.kind);
+                server.addr = addr.clone();
+                server.peer = Some((self.rx.clone(), self.addr.clone()));
+                self.peer = Some((server.rx.clone(), addr));
+                backlog.pending.push_back(server);
+            }
+            Binding::Datagram(queue) if self.kind == SocketKind::Datagram => {
+                let queue = queue.upgrade().ok_or(libc_riscv32::ECONNREFUSED)?;
+                if queue.borrow().abandoned {
+                    return Err(libc_riscv32::ECONNREFUSED);
+                }
+                self.peer = Some((queue, addr));
+            }
+            _ => return Err(libc_riscv32::EPROTOTYPE),
+        }
+
+        Ok(())
+    }
+
+    /// The next connection waiting to be accepted.
+    pub fn accept(&mut self) -> KernelResult<UnixSocket> {
+        let backlog = self
+            .backlog
+            .as_ref()
+            .filter(|_| self.is_listening())
+            .ok_or(libc_riscv32::EINVAL)?;
+        let next = backlog.borrow_mut().pending.pop_front();
+
+        next.ok_or(libc_riscv32::EAGAIN)
+    }
+
+    /// Send `data` to the socket bound at `to`, or the connected peer.
+    ///
+    /// Returns how much was sent, which on a stream may be less than all of
+    /// it.
+    pub fn send(&mut self, data: &[u8], to: Option<&Binding>) -> KernelResult<usize> {
+        let connection_oriented = self.kind.connection_oriented();
+        if self.shut_write {
+            return Err(libc_riscv32::EPIPE);
+        }
+        let queue = match (to, &self.peer) {
+            (Some(_), Some(_)) if connection_oriented => return Err(libc_riscv32::EISCONN),
+            (Some(_), None) if connection_oriented => return Err(libc_riscv32::EOPNOTSUPP),
+            (Some(Binding::Datagram(queue)), _) => {
+                queue.upgrade().ok_or(libc_riscv32::ECONNREFUSED)?
+            }
+            (Some(Binding::Listener(_)), _) => return Err(libc_riscv32::EPROTOTYPE),
+            (None, Some((queue, _))) => queue.clone(),
+            (None, None) if connection_oriented => return Err(libc_riscv32::ENOTCONN),
+            (None, None) => return Err(libc_riscv32::EDESTADDRREQ),
+        };
+
+        let mut queue = queue.borrow_mut();
+        if queue.abandoned {
+            return Err(if connection_oriented {
+                libc_riscv32::EPIPE
+            } else {
+                libc_riscv32::ECONNREFUSED
+            });
+        }
+        let len = if self.kind == SocketKind::Stream {
+            data.len().min(queue.space())
+        } else if data.len() > QUEUE_CAPACITY {
+            return Err(libc_riscv32::EMSGSIZE);
+        } else if data.len() > queue.space() {
+            0
+        } else {
+            data.len()
+        };
+        if len == 0 && !data.is_empty() {
+            return Err(libc_riscv32::EAGAIN);
+        }
+        if len > 0 || self.kind != SocketKind::Stream {
+            queue.bytes += len;
+            queue.messages.push_back(Message {
+                data: data[..len].to_vec(),
+                from: self.addr.clone(),
+            });
+        }
+
+        Ok(len)
+    }
+
+    /// Receive into `buf`, leaving what's received queued if `peek`.
+    ///
+    /// Returns how much was received, the length of the message, which is
+    /// more if it didn't fit, and who sent it.
+    pub fn recv(&mut self, buf: &mut [u8], peek: bool) -> KernelResult<(usize, usize, UnixAddr)> {
+        if self.is_listening() {
+            return Err(libc_riscv32::EINVAL);
+        }
+        if self.kind.connection_oriented() && self.peer.is_none() {
+            return Err(libc_riscv32::ENOTCONN);
+        }
+
+        let mut rx = self.rx.borrow_mut();
+        let Some(first) = rx.messages.front() else {
+            // End of file once the peer is gone or reading was shut down
+            if self.shut_read || (self.kind.connection_oriented() && rx.closed) {
+                return Ok((0, 0, UnixAddr::Unnamed));
+            }
+            return Err(libc_riscv32::EAGAIN);
+        };
+        let from = first.from.clone();
+
+        if self.kind != SocketKind::Stream {
+            let len = first.data.len();
+            let n = buf.len().min(len);
+            buf[..n].copy_from_slice(&first.data[..n]);
+            if !peek {
+                rx.messages.pop_front();
+                rx.bytes -= len;
+            }
+            return Ok((n, len, from));
+        }
+
+        let mut n = 0;
+        for message in &rx.messages {
+            let chunk = message.data.len().min(buf.len() - n);
+            buf[n..n + chunk].copy_from_slice(&message.data[..chunk]);
+            n += chunk;
+            if n == buf.len() {
+                break;
+            }
+        }
+        if !peek {
+            rx.consume(n);
+        }
+
+        Ok((n, n, from))
+    }
+
+    /// Stop reading and/or writing, telling the peer.
+    pub fn shutdown(&mut self, read: bool, write: bool) -> KernelResult<()> {
+        let connection_oriented = self.kind.connection_oriented();
+        if connection_oriented && self.peer.is_none() {
+            return Err(libc_riscv32::ENOTCONN);
+        }
+        if read {
+            self.shut_read = true;
+            if connection_oriented {
+                self.rx.borrow_mut().abandoned = true;
+            }
+        }
+        if write {
+            self.shut_write = true;
+            if let Some((tx, _)) = self.peer.as_ref().filter(|_| connection_oriented) {
+                tx.borrow_mut().closed = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FileLike for UnixSocket {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        self.recv(buf, false).map(|(n, _, _)| n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        self.send(buf, None)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(FileStat {
+            kind: FileKind::Socket,
+            perm: 0o777,
+            size: 0,
+            ino: 0,
+            nlink: 1,
+            mtime_ns: 0,
+        })
+    }
+
+    fn poll(&mut self) -> u16 {
+        if let Some(backlog) = self.backlog.as_ref().filter(|_| self.is_listening()) {
+            return match backlog.borrow().pending.is_empty() {
+                true => 0,
+                false => libc_riscv32::POLLIN,
+            };
+        }
+
+        let connection_oriented = self.kind.connection_oriented();
+        let rx = self.rx.borrow();
+        let mut events = 0;
+        if !rx.messages.is_empty() {
+            events |= libc_riscv32::POLLIN;
+        }
+        let read_done = self.shut_read || (connection_oriented && rx.closed);
+        if read_done {
+            events |= libc_riscv32::POLLIN | libc_riscv32::POLLRDHUP;
+        }
+        let write_done = match &self.peer {
+            Some((tx, _)) => {
+                let tx = tx.borrow();
+                if !self.shut_write && (tx.abandoned || tx.space() > 0) {
+                    events |= libc_riscv32::POLLOUT;
+                }
+                self.shut_write || tx.abandoned
+            }
+            None if connection_oriented => true,
+            None => {
+                events |= libc_riscv32::POLLOUT;
+                false
+            }
+        };
+        if connection_oriented && (read_done || self.peer.is_none()) && write_done {
+            events |= libc_riscv32::POLLHUP;
+        }
+
+        events
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        self.rx.borrow_mut().abandoned = true;
+        if let Some((tx, _)) = &self.peer {
+            if self.kind.connection_oriented() {
+                tx.borrow_mut().closed = true;
+            }
+        }
+        // Refuse the connections nobody will accept
+        if let Some(backlog) = &self.backlog {
+            let mut backlog = backlog.borrow_mut();
+            backlog.limit = None;
+            backlog.pending.clear();
+        }
+    }
+}
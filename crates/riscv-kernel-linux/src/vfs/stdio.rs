@@ -0,0 +1,117 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use super::{FileKind, FileLike, FileStat};
+use crate::errno::{host_errno, KernelResult};
+
+/// The guest's standard streams kept in memory, from
+/// [`MockLinux::capture_stdio`](crate::MockLinux::capture_stdio).
+#[derive(Debug, Default)]
+pub struct CapturedStdio {
+    /// Left for the guest to read, EOF once empty
+    pub stdin: VecDeque<u8>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl CapturedStdio {
+    /// Streams with `stdin` left to read, to share with the kernel.
+    pub fn shared(stdin: impl Into<Vec<u8>>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            stdin: stdin.into().into(),
+            ..Default::default()
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Backing {
+    /// Reads are EOF and writes are dropped
+    Null,
+    /// Forwarded to the host's stdio
+    Host,
+    Captured(Rc<RefCell<CapturedStdio>>),
+}
+
+/// The guest's standard streams, optionally forwarded to the host's.
+#[derive(Debug, Clone)]
+pub(crate) struct Stdio {
+    fd: i32,
+    backing: Backing,
+}
+
+impl Stdio {
+    pub fn new(fd: i32, passthrough: bool) -> Self {
+        let backing = if passthrough {
+            Backing::Host
+        } else {
+            Backing::Null
+        };
+        Self { fd, backing }
+    }
+
+    pub fn captured(fd: i32, captured: Rc<RefCell<CapturedStdio>>) -> Self {
+        Self {
+            fd,
+            backing: Backing::Captured(captured),
+        }
+    }
+
+    /// Which stream this is: 0 for stdin, 1 for stdout, 2 for stderr.
+    pub fn stream(&self) -> i32 {
+        self.fd
+    }
+}
+
+impl FileLike for Stdio {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        if self.fd != 0 {
+            return Err(libc_riscv32::EBADF);
+        }
+        match &self.backing {
+            Backing::Host => std::io::stdin().read(buf).map_err(host_errno),
+            // EOF
+            Backing::Null => Ok(0),
+            Backing::Captured(captured) => {
+                let stdin = &mut captured.borrow_mut().stdin;
+                let n = buf.len().min(stdin.len());
+                for (dst, src) in buf.iter_mut().zip(stdin.drain(..n)) {
+                    *dst = src;
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        match (&self.backing, self.fd) {
+            (_, 0) => return Err(libc_riscv32::EBADF),
+            (Backing::Null, _) => {}
+            (Backing::Host, 1) => std::io::stdout().write_all(buf).map_err(host_errno)?,
+            (Backing::Host, _) => std::io::stderr().write_all(buf).map_err(host_errno)?,
+            (Backing::Captured(captured), 1) => captured.borrow_mut().stdout.extend(buf),
+            (Backing::Captured(captured), _) => captured.borrow_mut().stderr.extend(buf),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(FileStat {
+            kind: FileKind::CharDevice,
+            perm: 0o620,
+            size: 0,
+            ino: self.fd as u64 + 1,
+            nlink: 1,
+            mtime_ns: 0,
+        })
+    }
+
+    fn is_tty(&self) -> bool {
+        matches!(self.backing, Backing::Host)
+    }
+}
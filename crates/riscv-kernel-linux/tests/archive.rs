@@ -0,0 +1,106 @@
+//! Tar and cpio archives unpacked as the guest's root filesystem, read back
+//! through the syscalls.
+
+mod common;
+
+use common::{cpio, syscall};
+use riscv_kernel_linux::{ArchiveError, LinuxError, MockLinux};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+/// A ustar archive of `(name, typeflag, contents)` entries, the contents
+/// of a symlink being its target.
+fn tar(entries: &[(&str, u8, &[u8])]) -> Vec<u8> {
+    let mut archive = vec![];
+    for &(name, typeflag, data) in entries {
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        let size = if typeflag == b'2' { 0 } else { data.len() };
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        header[156] = typeflag;
+        if typeflag == b'2' {
+            header[157..157 + data.len()].copy_from_slice(data);
+        }
+        header[257..263].copy_from_slice(b"ustar\0");
+        archive.extend(header);
+        if size > 0 {
+            archive.extend(data);
+            archive.resize(archive.len().next_multiple_of(512), 0);
+        }
+    }
+    // Two zero blocks end it
+    archive.resize(archive.len() + 1024, 0);
+    archive
+}
+
+fn machine(archive: Vec<u8>) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder().mount_archive(archive).build().unwrap();
+    Machine::new(kernel)
+}
+
+fn open(machine: &mut Machine<MockLinux>, path: &str, flags: u32) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644])
+}
+
+/// The contents of `path`, or the error opening it.
+fn read(machine: &mut Machine<MockLinux>, path: &str) -> Result<String, i32> {
+    let fd = open(machine, path, 0);
+    if fd < 0 {
+        return Err(fd);
+    }
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    syscall(machine, Sysno::close, &[fd as u32]);
+    let bytes = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    Ok(String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[test]
+fn tar_files_dirs_and_links_are_readable() {
+    let archive = tar(&[
+        ("etc/", b'5', b""),
+        ("etc/motd", b'0', b"welcome\n"),
+        ("motd", b'2', b"etc/motd"),
+    ]);
+    let mut machine = machine(archive);
+    assert_eq!(read(&mut machine, "/etc/motd").unwrap(), "welcome\n");
+    assert_eq!(read(&mut machine, "/motd").unwrap(), "welcome\n");
+    assert_eq!(read(&mut machine, "/etc/issue"), Err(-libc_riscv32::ENOENT));
+}
+
+#[test]
+fn cpio_files_are_readable_and_writable() {
+    let archive = cpio(&[
+        ("bin", libc_riscv32::S_IFDIR | 0o755, b""),
+        ("bin/greeting", libc_riscv32::S_IFREG | 0o644, b"hi\n"),
+    ]);
+    let mut machine = machine(archive.clone());
+    assert_eq!(read(&mut machine, "/bin/greeting").unwrap(), "hi\n");
+
+    // Changes stay in the guest's copy
+    let flags = libc_riscv32::O_WRONLY | libc_riscv32::O_CREAT;
+    let fd = open(&mut machine, "/bin/new", flags) as u32;
+    machine.mem.copy_to(BUF, b"new\n").unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 4]), 4);
+    syscall(&mut machine, Sysno::close, &[fd]);
+    assert_eq!(read(&mut machine, "/bin/new").unwrap(), "new\n");
+
+    let mut fresh = self::machine(archive);
+    assert_eq!(read(&mut fresh, "/bin/new"), Err(-libc_riscv32::ENOENT));
+}
+
+#[test]
+fn unknown_archives_fail_to_build() {
+    let res = MockLinux::builder()
+        .mount_archive(b"PK\x03\x04 not a tar".to_vec())
+        .build();
+    match res {
+        Err(LinuxError::Archive(ArchiveError::UnknownFormat)) => {}
+        res => panic!("expected an archive error, got {:?}", res.err()),
+    }
+}
@@ -0,0 +1,50 @@
+//! Configuring [`MockLinux`] through its builder.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{CapturedStdio, Entropy, LinuxError, MockLinux, StdioMode};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const BUF: u32 = 0x1000;
+
+fn random_bytes(entropy: Entropy) -> Vec<u8> {
+    let kernel = MockLinux::builder().entropy(entropy).build().unwrap();
+    let mut machine = Machine::new(kernel);
+    assert_eq!(syscall(&mut machine, Sysno::getrandom, &[BUF, 13, 0]), 13);
+    machine.mem.slice(BUF, 13).unwrap().to_vec()
+}
+
+#[test]
+fn seeded_entropy_is_reproducible() {
+    let bytes = random_bytes(Entropy::Seeded(1));
+    assert_eq!(bytes, random_bytes(Entropy::Seeded(1)));
+    assert_ne!(bytes, random_bytes(Entropy::Seeded(2)));
+    assert_ne!(bytes, vec![0; 13]);
+    assert_eq!(random_bytes(Entropy::Zero), vec![0; 13]);
+}
+
+#[test]
+fn captured_stdio_reaches_the_guest() {
+    let captured = CapturedStdio::shared("in");
+    let kernel = MockLinux::builder()
+        .stdio(StdioMode::Captured(captured.clone()))
+        .build()
+        .unwrap();
+    let mut machine = Machine::new(kernel);
+    assert_eq!(syscall(&mut machine, Sysno::read, &[0, BUF, 8]), 2);
+    assert_eq!(syscall(&mut machine, Sysno::write, &[1, BUF, 2]), 2);
+    assert_eq!(captured.borrow().stdout, b"in");
+}
+
+#[test]
+fn missing_host_directory_fails_to_build() {
+    let res = MockLinux::builder()
+        .mount_host("/data", "/nonexistent/derisc", false)
+        .build();
+    match res {
+        Err(LinuxError::Mount { guest_path, .. }) => assert_eq!(guest_path, "/data"),
+        res => panic!("expected a mount error, got {res:?}"),
+    }
+}
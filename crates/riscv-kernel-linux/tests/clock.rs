@@ -0,0 +1,98 @@
+//! The deterministic virtual clock: what a guest reads from it follows from
+//! the instructions it ran and the syscalls it made, never goes back, and is
+//! the same every run.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{ClockMode, MockLinux, Timespec64, VirtualClock};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const CODE: u32 = 0x1000;
+const TIMES: u32 = 0x8000;
+const NS_PER_INST: u64 = 10;
+
+/// Reads `CLOCK_MONOTONIC` into `TIMES` five times, then exits.
+const READ_CLOCK: [u32; 12] = [
+    0x0000_8437, // lui s0, 0x8
+    0x0050_0493, // li s1, 5
+    0x0010_0513, // loop: li a0, 1
+    0x0004_0593, // mv a1, s0
+    0x1930_0893, // li a7, 403
+    0x0000_0073, // ecall
+    0x0104_0413, // addi s0, s0, 16
+    0xfff4_8493, // addi s1, s1, -1
+    0xfe04_94e3, // bnez s1, loop
+    0x0000_0513, // li a0, 0
+    0x05d0_0893, // li a7, 93
+    0x0000_0073, // ecall
+];
+
+fn machine() -> Machine<MockLinux> {
+    let mut clock = VirtualClock::new(ClockMode::Deterministic);
+    clock.ns_per_inst = NS_PER_INST;
+    let mut machine = Machine::new(MockLinux::builder().clock(clock).build().unwrap());
+    machine.mem.copy_to(CODE, &READ_CLOCK).unwrap();
+    machine.hart.pc = CODE;
+    machine
+}
+
+/// What the guest read.
+fn run(machine: &mut Machine<MockLinux>) -> Vec<u64> {
+    machine.run().unwrap();
+    (0..5)
+        .map(|i| {
+            let time = machine.mem.load::<Timespec64>(TIMES + 16 * i);
+            time.to_ns().unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn guest_reads_the_same_times_every_run() {
+    let times = run(&mut machine());
+    assert_eq!(run(&mut machine()), times);
+
+    // Each read is 7 instructions and one clock_gettime64 after the last
+    let cost = VirtualClock::default().costs.cost(Sysno::clock_gettime64);
+    for pair in times.windows(2) {
+        assert_eq!(pair[1] - pair[0], 7 * NS_PER_INST + cost);
+    }
+    // 5 instructions in, and the call is charged before the clock is read
+    assert_eq!(times[0], 5 * NS_PER_INST + cost);
+}
+
+#[test]
+fn sleeps_move_time_forward_but_not_cpu_time() {
+    let mut machine = machine();
+    let read = |machine: &mut Machine<MockLinux>, clockid: u32| {
+        syscall(machine, Sysno::clock_gettime64, &[clockid, TIMES]);
+        machine.mem.load::<Timespec64>(TIMES).to_ns().unwrap()
+    };
+    let monotonic = libc_riscv32::CLOCK_MONOTONIC;
+    let cpu = libc_riscv32::CLOCK_PROCESS_CPUTIME_ID;
+    let (before, cpu_before) = (read(&mut machine, monotonic), read(&mut machine, cpu));
+    let realtime = read(&mut machine, libc_riscv32::CLOCK_REALTIME);
+
+    machine
+        .mem
+        .write(TIMES, Timespec64::from_ns(1_000_000))
+        .unwrap();
+    assert_eq!(
+        syscall(
+            &mut machine,
+            Sysno::clock_nanosleep_time64,
+            &[monotonic, 0, TIMES, 0]
+        ),
+        0
+    );
+    let after = read(&mut machine, monotonic);
+    assert!(after >= before + 1_000_000);
+    assert_eq!(read(&mut machine, cpu), cpu_before);
+    // Realtime moves with it, from the configured epoch
+    let epoch = VirtualClock::default().epoch_ns;
+    let cost = VirtualClock::default().costs.cost(Sysno::clock_gettime64);
+    assert_eq!(realtime - epoch, before + 2 * cost);
+    assert!(read(&mut machine, libc_riscv32::CLOCK_REALTIME) - epoch > after);
+}
@@ -0,0 +1,283 @@
+//! Calling syscalls straight through the kernel's entry, cpio archives and
+//! host directories to mount, a small hand-built rv32 shared object for
+//! the loader tests, and capturing what the kernel logs.
+// Each test crate uses a different part of this
+#![allow(dead_code)]
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use riscv_kernel_linux::{LinuxError, MockLinux};
+use riscv_vm::{
+    error::MachineError,
+    machine::{Kernel, Machine},
+    riscv_inst::Reg,
+};
+use syscalls::riscv32::Sysno;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+/// Make syscall `sysno` with `args`, returning what it left in `a0`.
+pub fn syscall(machine: &mut Machine<MockLinux>, sysno: Sysno, args: &[u32]) -> i32 {
+    try_syscall(machine, sysno, args).unwrap()
+}
+
+/// Like [`syscall`], for syscalls that may stop the machine.
+pub fn try_syscall(
+    machine: &mut Machine<MockLinux>,
+    sysno: Sysno,
+    args: &[u32],
+) -> Result<i32, MachineError<LinuxError>> {
+    let Machine {
+        hart, mem, kernel, ..
+    } = machine;
+    hart.set_reg(Reg::A7, sysno.id() as u32);
+    for (n, &arg) in args.iter().enumerate() {
+        hart.set_arg(n, arg);
+    }
+    kernel.syscall(hart, mem)?;
+    Ok(hart.return_value() as i32)
+}
+
+/// A newc cpio archive of `(name, mode, contents)` entries.
+pub fn cpio(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+    let mut archive = vec![];
+    let trailer: (&str, u32, &[u8]) = ("TRAILER!!!", 0, &[]);
+    for &(name, mode, data) in entries.iter().chain([&trailer]) {
+        // ino, mode, uid, gid, nlink, mtime, filesize, four device numbers,
+        // namesize and check, as 8 hex digits each
+        let (size, name_size) = (data.len(), name.len() + 1);
+        let header = format!(
+            "070701{:08x}{mode:08x}{:032x}{size:08x}{:032x}{name_size:08x}{:08x}",
+            0, 0, 0, 0
+        );
+        archive.extend(header.bytes());
+        archive.extend(name.bytes().chain([0]));
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+    archive
+}
+
+/// A fresh host directory holding `files`, removed when dropped.
+pub struct HostDir(pub PathBuf);
+
+impl HostDir {
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("derisc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        Self(dir)
+    }
+}
+
+impl Drop for HostDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A subscriber keeping every event logged while it's the default, with
+/// the spans it was logged in.
+#[derive(Debug, Clone, Default)]
+pub struct Logs(Arc<Mutex<Logged>>);
+
+#[derive(Debug, Default)]
+struct Logged {
+    /// Every span created, like `syscall{name=close pc=0x1000}`, by id - 1
+    spans: Vec<String>,
+    /// Ids of the spans entered, innermost last
+    entered: Vec<u64>,
+    events: Vec<LogEvent>,
+}
+
+/// An event's message and the spans it was logged in, outermost first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEvent {
+    pub level: Level,
+    pub message: String,
+    pub spans: Vec<String>,
+}
+
+impl Logs {
+    /// Run `f` with this as the default subscriber.
+    pub fn capture<T>(&self, f: impl FnOnce() -> T) -> T {
+        tracing::subscriber::with_default(self.clone(), f)
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        let events = self.events().into_iter();
+        events.map(|event| event.message).collect()
+    }
+
+    pub fn events(&self) -> Vec<LogEvent> {
+        self.0.lock().unwrap().events.clone()
+    }
+}
+
+/// The message and other fields of an event or span, the others rendered
+/// like `name=close pc=0x1000`.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    others: Vec<String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            name => self.others.push(format!("{name}={value}")),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            name => self.others.push(format!("{name}={value:?}")),
+        }
+    }
+}
+
+impl Subscriber for Logs {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let span = format!("{}{{{}}}", attrs.metadata().name(), fields.others.join(" "));
+        let mut logged = self.0.lock().unwrap();
+        logged.spans.push(span);
+        span::Id::from_u64(logged.spans.len() as u64)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut logged = self.0.lock().unwrap();
+        let spans = logged
+            .entered
+            .iter()
+            .map(|&id| logged.spans[id as usize - 1].clone())
+            .collect();
+        logged.events.push(LogEvent {
+            level: *event.metadata().level(),
+            message: fields.message,
+            spans,
+        });
+    }
+
+    fn enter(&self, id: &span::Id) {
+        self.0.lock().unwrap().entered.push(id.into_u64());
+    }
+
+    fn exit(&self, id: &span::Id) {
+        let mut logged = self.0.lock().unwrap();
+        if let Some(at) = logged.entered.iter().rposition(|&e| e == id.into_u64()) {
+            logged.entered.remove(at);
+        }
+    }
+}
+
+pub const TEXT: usize = 0x80;
+pub const DATA: usize = 0x100;
+pub const DYNSYM: usize = 0x120;
+pub const DYNSTR: usize = 0x160;
+pub const HASH: usize = 0x180;
+pub const RELA: usize = 0x1a0;
+pub const DYNAMIC: usize = 0x1d0;
+pub const END: usize = 0x228;
+
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+/// A shared object exporting `answer`, which returns 42 and is also its
+/// entry point, and `table`, which points at it, and importing `host_value`
+/// with binding `import_bind`.
+///
+/// `DATA` holds `table`, a pointer to `answer` through a relative
+/// relocation, `&host_value + 4`, and a one-entry init array.
+pub fn shared_object(import_bind: u8) -> Vec<u8> {
+    let mut elf = vec![0u8; END];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        elf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+    let words = |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+
+    // ELF header: 32-bit little-endian ET_DYN for RISC-V, two program headers
+    put(0, b"\x7fELF\x01\x01\x01");
+    put(16, &words(&[3 | 243 << 16, 1, TEXT as u32, 52, 0, 0]));
+    put(40, &words(&[52 | 32 << 16, 2 | 40 << 16, 0]));
+    // PT_LOAD of the whole file plus some bss, and PT_DYNAMIC
+    let end = END as u32;
+    put(52, &words(&[1, 0, 0, 0, end, end + 0x100, 7, 0x1000]));
+    let dynamic = DYNAMIC as u32;
+    put(
+        84,
+        &words(&[2, dynamic, dynamic, 0, end - dynamic, end - dynamic, 6, 4]),
+    );
+
+    // answer: li a0, 42; ret
+    put(TEXT, &words(&[0x02a0_0513, 0x0000_8067]));
+
+    // Symbols: null, answer, table, host_value
+    let sym = |name: u32, value: u32, size: u32, info: u8, shndx: u16| -> Vec<u8> {
+        let mut sym = words(&[name, value, size]);
+        sym.extend([info, 0]);
+        sym.extend(shndx.to_le_bytes());
+        sym
+    };
+    put(DYNSYM + 16, &sym(1, TEXT as u32, 8, STB_GLOBAL << 4 | 2, 1));
+    put(DYNSYM + 32, &sym(8, DATA as u32, 4, STB_GLOBAL << 4 | 1, 1));
+    put(DYNSYM + 48, &sym(14, 0, 0, import_bind << 4, 0));
+    put(DYNSTR, b"\0answer\0table\0host_value\0");
+    // SysV hash table, just for the symbol count
+    put(HASH, &words(&[1, 4, 1, 0, 0, 0, 0]));
+
+    // R_RISCV_32 = 1, R_RISCV_RELATIVE = 3
+    let data = DATA as u32;
+    let relocs = [
+        (data, 1 << 8 | 1, 0),
+        (data + 4, 3, TEXT as u32),
+        (data + 8, 3 << 8 | 1, 4),
+        (data + 12, 3, TEXT as u32),
+    ];
+    for (i, (offset, info, addend)) in relocs.into_iter().enumerate() {
+        put(RELA + 12 * i, &words(&[offset, info, addend]));
+    }
+
+    let dyns = [
+        (4, HASH as u32),   // DT_HASH
+        (5, DYNSTR as u32), // DT_STRTAB
+        (6, DYNSYM as u32), // DT_SYMTAB
+        (10, 25),           // DT_STRSZ
+        (11, 16),           // DT_SYMENT
+        (7, RELA as u32),   // DT_RELA
+        (8, 48),            // DT_RELASZ
+        (9, 12),            // DT_RELAENT
+        (25, data + 12),    // DT_INIT_ARRAY
+        (27, 4),            // DT_INIT_ARRAYSZ
+        (0, 0),             // DT_NULL
+    ];
+    for (i, (tag, val)) in dyns.into_iter().enumerate() {
+        put(DYNAMIC + 8 * i, &words(&[tag, val]));
+    }
+
+    elf
+}
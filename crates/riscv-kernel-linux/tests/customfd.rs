@@ -0,0 +1,126 @@
+//! Descriptors whose files the embedder supplies, used by the guest like any
+//! other.
+
+mod common;
+
+use std::{collections::VecDeque, io::SeekFrom};
+
+use common::syscall;
+use riscv_kernel_linux::{FileKind, FileLike, FileStat, KernelResult, MockLinux};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const BUF: u32 = 0x1000;
+const POLLFD: u32 = 0x2000;
+const TIMESPEC: u32 = 0x3000;
+
+/// A sensor that queues readings and records what it's told.
+#[derive(Debug, Default)]
+struct Sensor {
+    readings: VecDeque<u8>,
+    commands: Vec<u8>,
+}
+
+impl FileLike for Sensor {
+    fn read(&mut self, buf: &mut [u8]) -> KernelResult<usize> {
+        if self.readings.is_empty() {
+            return Err(libc_riscv32::EAGAIN);
+        }
+        let n = buf.len().min(self.readings.len());
+        for (byte, reading) in buf.iter_mut().zip(self.readings.drain(..n)) {
+            *byte = reading;
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> KernelResult<usize> {
+        self.commands.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> KernelResult<u64> {
+        Err(libc_riscv32::ESPIPE)
+    }
+
+    fn stat(&self) -> KernelResult<FileStat> {
+        Ok(FileStat {
+            kind: FileKind::CharDevice,
+            perm: 0o666,
+            size: 0,
+            ino: 0,
+            nlink: 1,
+            mtime_ns: 0,
+        })
+    }
+
+    fn poll(&mut self) -> u16 {
+        let readable = if self.readings.is_empty() {
+            0
+        } else {
+            libc_riscv32::POLLIN
+        };
+        readable | libc_riscv32::POLLOUT
+    }
+}
+
+/// The events `fd` is ready for, polling without waiting.
+fn poll(machine: &mut Machine<MockLinux>, fd: i32) -> u16 {
+    let events = libc_riscv32::POLLIN | libc_riscv32::POLLOUT;
+    machine
+        .mem
+        .copy_to(POLLFD, &[fd as u32, events as u32])
+        .unwrap();
+    machine.mem.copy_to(TIMESPEC, &[0u32; 4]).unwrap();
+    syscall(machine, Sysno::ppoll_time64, &[POLLFD, 1, TIMESPEC, 0, 8]);
+    machine.mem.load(POLLFD + 6)
+}
+
+#[test]
+fn guest_uses_installed_files() {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    let fd = machine
+        .kernel
+        .install_file(Sensor::default(), "/dev/sensor", libc_riscv32::O_RDWR)
+        .unwrap();
+    assert_eq!(fd, 3);
+    let arg = fd as u32;
+
+    assert_eq!(
+        syscall(&mut machine, Sysno::read, &[arg, BUF, 16]),
+        -libc_riscv32::EAGAIN
+    );
+    assert_eq!(poll(&mut machine, fd), libc_riscv32::POLLOUT);
+
+    let sensor = machine.kernel.file_as::<Sensor>(fd).unwrap();
+    sensor.readings.extend([21, 22, 23]);
+    assert_eq!(
+        poll(&mut machine, fd),
+        libc_riscv32::POLLIN | libc_riscv32::POLLOUT
+    );
+    assert_eq!(syscall(&mut machine, Sysno::read, &[arg, BUF, 16]), 3);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 3).unwrap(), [21, 22, 23]);
+
+    machine.mem.write_cstr(BUF, b"calibrate").unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::write, &[arg, BUF, 9]), 9);
+    let sensor = machine.kernel.file_as::<Sensor>(fd).unwrap();
+    assert_eq!(sensor.commands, b"calibrate");
+
+    // Only files of the type asked for are found
+    assert!(machine.kernel.file_as::<Sensor>(1).is_none());
+    syscall(&mut machine, Sysno::close, &[arg]);
+    assert!(machine.kernel.file_as::<Sensor>(fd).is_none());
+}
+
+#[test]
+fn installed_files_can_replace_stdio() {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    let sensor = Sensor {
+        readings: b"input".iter().copied().collect(),
+        ..Sensor::default()
+    };
+    machine
+        .kernel
+        .install_file_at(0, sensor, "/dev/sensor", libc_riscv32::O_RDONLY);
+    assert_eq!(syscall(&mut machine, Sysno::read, &[0, BUF, 16]), 5);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 5).unwrap(), b"input");
+}
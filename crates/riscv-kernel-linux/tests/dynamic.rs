@@ -0,0 +1,175 @@
+//! Starting dynamically linked programs through their interpreter, and the
+//! private file mappings a dynamic linker loads libraries with, on files in
+//! an in-memory root filesystem.
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{cpio, shared_object, syscall, STB_WEAK, TEXT};
+use riscv_kernel_linux::{LinuxError, MockLinux};
+use riscv_vm::{machine::Machine, riscv_inst::Reg};
+use syscalls::riscv32::Sysno;
+
+const NAME: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+
+const PROGRAM: u32 = 0x10000;
+const INTERP: usize = 0xa0;
+const ENTRY: usize = 0x100;
+
+/// An executable at `PROGRAM` asking for the interpreter `interp`, with
+/// `PT_PHDR`, `PT_INTERP` and `PT_LOAD` program headers.
+fn dynamic_program(interp: &str) -> Vec<u8> {
+    let mut elf = vec![0u8; 0x110];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        elf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+    let words = |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+
+    // ELF header: 32-bit little-endian ET_EXEC for RISC-V
+    put(0, b"\x7fELF\x01\x01\x01");
+    let entry = PROGRAM + ENTRY as u32;
+    put(16, &words(&[2 | 243 << 16, 1, entry, 52, 0, 0]));
+    put(40, &words(&[52 | 32 << 16, 3 | 40 << 16, 0]));
+
+    let interp_len = interp.len() as u32 + 1;
+    let (phdr, offset) = (PROGRAM + 52, INTERP as u32);
+    let program_headers = [
+        [6, 52, phdr, phdr, 96, 96, 4, 4],
+        [3, offset, PROGRAM + offset, 0, interp_len, interp_len, 4, 1],
+        [1, 0, PROGRAM, PROGRAM, 0x110, 0x110, 5, 0x1000],
+    ];
+    for (i, ph) in program_headers.iter().enumerate() {
+        put(52 + 32 * i, &words(ph));
+    }
+    put(INTERP, interp.as_bytes());
+    // ret
+    put(ENTRY, &words(&[0x0000_8067]));
+
+    elf
+}
+
+fn machine(files: &[(&str, &[u8])]) -> Machine<MockLinux> {
+    let mode = libc_riscv32::S_IFREG | 0o644;
+    let entries: Vec<_> = files
+        .iter()
+        .map(|&(name, data)| (name, mode, data))
+        .collect();
+    let kernel = MockLinux::builder()
+        .mount_archive(cpio(&entries))
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+/// The auxiliary vector on the initial stack of a program without
+/// environment variables.
+fn auxv(machine: &Machine<MockLinux>) -> BTreeMap<u32, u32> {
+    let sp = machine.hart.get_reg(Reg::Sp);
+    let argc = machine.mem.load::<u32>(sp);
+    // argc, argv, its terminator and the empty environment's
+    let mut addr = sp + 4 * (argc + 3);
+    let mut auxv = BTreeMap::new();
+    loop {
+        let [key, value]: [u32; 2] = machine.mem.load(addr);
+        if key == libc_riscv32::AT_NULL {
+            return auxv;
+        }
+        auxv.insert(key, value);
+        addr += 8;
+    }
+}
+
+#[test]
+fn program_starts_in_its_interpreter() {
+    let interp = shared_object(STB_WEAK);
+    let mut machine = machine(&[("lib/ld.so", &interp)]);
+    let Machine {
+        hart, mem, kernel, ..
+    } = &mut machine;
+    let program = dynamic_program("/lib/ld.so");
+    kernel
+        .load_static_elf(hart, mem, &program, &["prog"], &[])
+        .unwrap();
+
+    let base = machine.mem.mmap_top;
+    assert_eq!(base % 4096, 0);
+    assert_eq!(machine.hart.pc, base + TEXT as u32);
+    // answer: li a0, 42
+    assert_eq!(machine.mem.load::<u32>(base + TEXT as u32), 0x02a0_0513);
+
+    let auxv = auxv(&machine);
+    assert_eq!(auxv[&libc_riscv32::AT_BASE], base);
+    assert_eq!(auxv[&libc_riscv32::AT_ENTRY], PROGRAM + ENTRY as u32);
+    assert_eq!(auxv[&libc_riscv32::AT_PHDR], PROGRAM + 52);
+    assert_eq!(auxv[&libc_riscv32::AT_PHENT], 32);
+    assert_eq!(auxv[&libc_riscv32::AT_PHNUM], 3);
+}
+
+#[test]
+fn missing_interpreter_fails_to_load() {
+    let mut machine = machine(&[]);
+    let Machine {
+        hart, mem, kernel, ..
+    } = &mut machine;
+    let program = dynamic_program("/lib/ld.so");
+    match kernel.load_static_elf(hart, mem, &program, &["prog"], &[]) {
+        Err(LinuxError::Interpreter { path, errno }) => {
+            assert_eq!(path, "/lib/ld.so");
+            assert_eq!(errno, libc_riscv32::ENOENT);
+        }
+        res => panic!("expected an interpreter error, got {res:?}"),
+    }
+}
+
+#[test]
+fn segment_in_the_last_page_fails_to_load() {
+    // A static executable whose only segment has nowhere for brk to start
+    let words = |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+    let top = 0xffff_f800;
+    let mut program = b"\x7fELF\x01\x01\x01".to_vec();
+    program.resize(16, 0);
+    program.extend(words(&[2 | 243 << 16, 1, top, 52, 0, 0]));
+    program.extend(words(&[52 | 32 << 16, 1 | 40 << 16, 0]));
+    program.extend(words(&[1, 0, top, top, 0, 0x100, 6, 0x1000]));
+
+    let mut machine = machine(&[]);
+    let Machine {
+        hart, mem, kernel, ..
+    } = &mut machine;
+    match kernel.load_static_elf(hart, mem, &program, &["prog"], &[]) {
+        Err(LinuxError::InvalidSegment { vaddr, len }) => {
+            assert_eq!((vaddr, len), (top, 0x100));
+        }
+        res => panic!("expected an invalid segment, got {res:?}"),
+    }
+}
+
+#[test]
+fn files_map_privately_from_an_offset() {
+    let mut data = vec![1; 0x1000];
+    data.extend([2; 0x800]);
+    let mut machine = machine(&[("lib/data", &data)]);
+    machine.mem.write_cstr(NAME, b"/lib/data").unwrap();
+    let cwd = libc_riscv32::AT_FDCWD as u32;
+    let fd = syscall(&mut machine, Sysno::openat, &[cwd, NAME, 0, 0]) as u32;
+    assert!(fd > 2);
+
+    let (prot, private) = (libc_riscv32::PROT_READ, libc_riscv32::MAP_PRIVATE);
+    let addr = syscall(
+        &mut machine,
+        Sysno::mmap,
+        &[0, 0x2000, prot, private, fd, 1],
+    ) as u32;
+    let mapped = machine.mem.slice::<u8>(addr, 0x2000).unwrap();
+    assert!(mapped[..0x800].iter().all(|&b| b == 2));
+    assert!(mapped[0x800..].iter().all(|&b| b == 0));
+    // The file position is left alone
+    assert_eq!(syscall(&mut machine, Sysno::read, &[fd, BUF, 1]), 1);
+    assert_eq!(machine.mem.load::<u8>(BUF), 1);
+
+    let shared = libc_riscv32::MAP_SHARED;
+    let ret = syscall(&mut machine, Sysno::mmap, &[0, 0x1000, prot, shared, fd, 0]);
+    assert_eq!(ret, -libc_riscv32::ENODEV);
+}
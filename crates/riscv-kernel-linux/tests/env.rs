@@ -0,0 +1,84 @@
+//! The `argv` and environment a guest starts with.
+
+use riscv_kernel_linux::{GuestEnv, MockLinux, DEFAULT_PATH};
+use riscv_vm::{machine::Machine, riscv_inst::Reg};
+
+const BASE: u32 = 0x10000;
+
+/// A static executable at `BASE` that only returns.
+fn program() -> Vec<u8> {
+    let words = |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+    let mut elf = vec![0u8; 0x58];
+    elf[..7].copy_from_slice(b"\x7fELF\x01\x01\x01");
+    elf[16..40].copy_from_slice(&words(&[2 | 243 << 16, 1, BASE + 0x54, 52, 0, 0]));
+    elf[40..52].copy_from_slice(&words(&[52 | 32 << 16, 1 | 40 << 16, 0]));
+    elf[52..84].copy_from_slice(&words(&[1, 0, BASE, BASE, 0x58, 0x58, 5, 0x1000]));
+    // ret
+    elf[84..].copy_from_slice(&words(&[0x0000_8067]));
+    elf
+}
+
+/// The `argv` and `envp` strings on the initial stack.
+fn load(env: &GuestEnv) -> (Vec<String>, Vec<String>) {
+    let mut machine = Machine::new(MockLinux::default());
+    let Machine {
+        hart, mem, kernel, ..
+    } = &mut machine;
+    kernel
+        .load_elf_with_env(hart, mem, &program(), env)
+        .unwrap();
+
+    let strings = |mut addr: u32| {
+        let mut strings = vec![];
+        loop {
+            let ptr = mem.load::<u32>(addr);
+            if ptr == 0 {
+                return (strings, addr + 4);
+            }
+            strings.push(mem.read_string(ptr, 256).unwrap());
+            addr += 4;
+        }
+    };
+    let sp = hart.get_reg(Reg::Sp);
+    let (argv, envp) = strings(sp + 4);
+    assert_eq!(mem.load::<u32>(sp) as usize, argv.len());
+    (argv, strings(envp).0)
+}
+
+#[test]
+fn preset_fills_in_a_login_environment() {
+    let env = GuestEnv::preset("prog").arg("-v");
+    let (argv, envp) = load(&env);
+    assert_eq!(argv, ["prog", "-v"]);
+    assert_eq!(
+        envp,
+        [
+            format!("PATH={DEFAULT_PATH}"),
+            "LANG=C.UTF-8".to_string(),
+            "TERM=xterm".to_string()
+        ]
+    );
+}
+
+#[test]
+fn variables_can_be_overridden_and_removed() {
+    let env = GuestEnv::preset("prog")
+        .var("LANG", "en_US.UTF-8")
+        .var("HOME", "/root")
+        .remove_var("TERM");
+    assert_eq!(env.get("LANG"), Some("en_US.UTF-8"));
+    assert_eq!(env.get("TERM"), None);
+    let (_, envp) = load(&env);
+    assert_eq!(
+        envp,
+        [
+            format!("PATH={DEFAULT_PATH}"),
+            "LANG=en_US.UTF-8".to_string(),
+            "HOME=/root".to_string()
+        ]
+    );
+
+    let (argv, envp) = load(&GuestEnv::new("bare"));
+    assert_eq!(argv, ["bare"]);
+    assert!(envp.is_empty());
+}
@@ -0,0 +1,89 @@
+//! Waiting on eventfds and timerfds with epoll, the timers running on the
+//! virtual clock.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const BUF: u32 = 0x1000;
+const EVENT: u32 = 0x2000;
+const EVENTS: u32 = 0x3000;
+const SPEC: u32 = 0x4000;
+
+/// Watch `fd` for `events`, tagged with `data`.
+fn watch(machine: &mut Machine<MockLinux>, epfd: u32, fd: u32, events: u32, data: u64) {
+    // struct epoll_event: events, padding, then data
+    machine.mem.write(EVENT, [events, 0]).unwrap();
+    machine.mem.write(EVENT + 8, data).unwrap();
+    let add = libc_riscv32::EPOLL_CTL_ADD;
+    assert_eq!(
+        syscall(machine, Sysno::epoll_ctl, &[epfd, add, fd, EVENT]),
+        0
+    );
+}
+
+/// Wait up to `timeout_ms` (forever if negative), returning the ready
+/// events and their data.
+fn wait(machine: &mut Machine<MockLinux>, epfd: u32, timeout_ms: i32) -> Vec<(u32, u64)> {
+    let args = [epfd, EVENTS, 4, timeout_ms as u32, 0, 0];
+    let ready = syscall(machine, Sysno::epoll_pwait, &args);
+    assert!(ready >= 0, "epoll_pwait: {ready}");
+    (0..ready as u32)
+        .map(|i| {
+            let event = EVENTS + 16 * i;
+            (machine.mem.load(event), machine.mem.load(event + 8))
+        })
+        .collect()
+}
+
+#[test]
+fn eventfd_writes_wake_epoll() {
+    let mut machine = Machine::new(MockLinux::new(false));
+    let nonblock = libc_riscv32::EFD_NONBLOCK;
+    let efd = syscall(&mut machine, Sysno::eventfd2, &[0, nonblock]) as u32;
+    let epfd = syscall(&mut machine, Sysno::epoll_create1, &[0]) as u32;
+    watch(&mut machine, epfd, efd, libc_riscv32::EPOLLIN, 7);
+    assert_eq!(wait(&mut machine, epfd, 0), []);
+
+    machine.mem.write(BUF, 3u64).unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::write, &[efd, BUF, 8]), 8);
+    assert_eq!(wait(&mut machine, epfd, 0), [(libc_riscv32::EPOLLIN, 7)]);
+
+    // Reading the count empties it
+    assert_eq!(syscall(&mut machine, Sysno::read, &[efd, BUF, 8]), 8);
+    assert_eq!(machine.mem.load::<u64>(BUF), 3);
+    assert_eq!(wait(&mut machine, epfd, 0), []);
+
+    // Already watched, and once closed, it's forgotten
+    let add = libc_riscv32::EPOLL_CTL_ADD;
+    let ret = syscall(&mut machine, Sysno::epoll_ctl, &[epfd, add, efd, EVENT]);
+    assert_eq!(ret, -libc_riscv32::EEXIST);
+    assert_eq!(syscall(&mut machine, Sysno::close, &[efd]), 0);
+    assert_eq!(wait(&mut machine, epfd, 0), []);
+}
+
+#[test]
+fn waiting_forever_sleeps_until_a_timer_fires() {
+    let mut machine = Machine::new(MockLinux::new(false));
+    let monotonic = 1;
+    let tfd = syscall(&mut machine, Sysno::timerfd_create, &[monotonic, 0]) as u32;
+    let epfd = syscall(&mut machine, Sysno::epoll_create1, &[0]) as u32;
+    watch(&mut machine, epfd, tfd, libc_riscv32::EPOLLIN, 1);
+
+    // Nothing armed, nothing to wait for
+    assert_eq!(wait(&mut machine, epfd, -1), []);
+
+    // One-shot in 5ms: struct itimerspec's interval, then value
+    machine.mem.write(SPEC, [0u64, 0, 0, 5_000_000]).unwrap();
+    let ret = syscall(&mut machine, Sysno::timerfd_settime64, &[tfd, 0, SPEC, 0]);
+    assert_eq!(ret, 0);
+    assert_eq!(wait(&mut machine, epfd, 1), []);
+    assert_eq!(wait(&mut machine, epfd, -1), [(libc_riscv32::EPOLLIN, 1)]);
+
+    // It fired once
+    assert_eq!(syscall(&mut machine, Sysno::read, &[tfd, BUF, 8]), 8);
+    assert_eq!(machine.mem.load::<u64>(BUF), 1);
+}
@@ -0,0 +1,115 @@
+//! The guest errno table matches the generic Linux numbering with one name
+//! per value, host errors reach the guest by name, and the kernel's own
+//! error codes are stable and unique.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use common::{syscall, HostDir};
+use libc_riscv32::{errno_name, ERRNO_TABLE};
+use riscv_kernel_linux::{LinuxError, MockLinux};
+use riscv_vm::{error::MemoryError, machine::Machine};
+use syscalls::{riscv32::Sysno, Errno};
+
+const PATH: u32 = 0x1000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+#[test]
+fn errno_values_and_names_are_unique() {
+    let values: Vec<_> = ERRNO_TABLE.iter().map(|&(errno, _)| errno).collect();
+    assert!(values.is_sorted_by(|a, b| a < b), "{values:?}");
+    let names: BTreeSet<_> = ERRNO_TABLE.iter().map(|&(_, name)| name).collect();
+    assert_eq!(names.len(), ERRNO_TABLE.len());
+
+    // Every value from EPERM to EHWPOISON but the two the generic table
+    // only has aliases for
+    let expected = (1..=133).filter(|errno| ![41, 58].contains(errno));
+    assert!(values.into_iter().eq(expected));
+}
+
+#[test]
+fn errno_values_match_linux() {
+    for (errno, name) in ERRNO_TABLE {
+        assert_eq!(Errno::new(errno).name(), Some(name), "{errno}");
+        assert_eq!(errno_name(errno), Some(name));
+    }
+    assert_eq!(errno_name(0), None);
+    assert_eq!(errno_name(41), None);
+    // Aliases share their value
+    assert_eq!(libc_riscv32::EWOULDBLOCK, libc_riscv32::EAGAIN);
+    assert_eq!(libc_riscv32::EDEADLOCK, libc_riscv32::EDEADLK);
+    assert_eq!(libc_riscv32::ENOTSUP, libc_riscv32::EOPNOTSUPP);
+}
+
+#[test]
+fn host_errors_are_translated() {
+    let dir = HostDir::new("errno", &[("file", "")]);
+    let kernel = MockLinux::builder()
+        .mount_host("/data", &dir.0, false)
+        .build()
+        .unwrap();
+    let mut machine = Machine::new(kernel);
+    let mut open = |path: &str, flags: u32| {
+        machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+        syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644])
+    };
+
+    assert_eq!(open("/data/missing", 0), -libc_riscv32::ENOENT);
+    // O_WRONLY
+    assert_eq!(open("/data", 1), -libc_riscv32::EISDIR);
+    // O_WRONLY | O_CREAT | O_EXCL
+    assert_eq!(open("/data/file", 0o301), -libc_riscv32::EEXIST);
+    assert_eq!(open("/data/file/x", 0), -libc_riscv32::ENOTDIR);
+}
+
+#[test]
+fn kernel_error_codes_are_stable_and_unique() {
+    let memory = || MemoryError::Unterminated {
+        addr: 0,
+        max_len: 0,
+    };
+    let errors = [
+        (LinuxError::UnknownSyscall { nr: 0, pc: 0 }, 901),
+        (goblin::error::Error::Malformed(String::new()).into(), 902),
+        (LinuxError::InvalidSegment { vaddr: 0, len: 0 }, 903),
+        (LinuxError::NullInArgument(String::new()), 904),
+        (LinuxError::Stack(memory()), 905),
+        (
+            LinuxError::Mount {
+                guest_path: "/data".to_string(),
+                host_dir: "/data".into(),
+                source: std::io::Error::other("test"),
+            },
+            906,
+        ),
+        (riscv_kernel_linux::ArchiveError::UnknownFormat.into(), 907),
+        (LinuxError::NotALibrary, 908),
+        (LinuxError::NoSpaceForLibrary { size: 0 }, 909),
+        (LinuxError::UnresolvedSymbol(String::new()), 910),
+        (
+            LinuxError::UnsupportedRelocation { kind: 0, offset: 0 },
+            911,
+        ),
+        (LinuxError::MapLibrary(memory()), 912),
+        (
+            LinuxError::Interpreter {
+                path: String::new(),
+                errno: libc_riscv32::ENOENT,
+            },
+            913,
+        ),
+        (
+            LinuxError::ProtectSegment {
+                vaddr: 0,
+                source: memory(),
+            },
+            914,
+        ),
+    ];
+    let mut seen = BTreeSet::new();
+    for (error, code) in errors {
+        assert_eq!(error.code(), code, "{error}");
+        assert!(seen.insert(code), "{code} is used twice");
+    }
+}
@@ -0,0 +1,68 @@
+//! Exiting tears down the process and tells every exit hook how it ended.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use common::syscall;
+use riscv_kernel_linux::{MockLinux, ProcessExit};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const CODE: u32 = 0x1000;
+const NAME: u32 = 0x2000;
+
+/// `exit_group(status)`
+fn exit_group(status: i32) -> [u32; 3] {
+    [
+        (status as u32) << 20 | 0x513, // li a0, status
+        0x05e0_0893,                   // li a7, 94
+        0x0000_0073,                   // ecall
+    ]
+}
+
+fn machine(status: i32) -> Machine<MockLinux> {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    machine.mem.copy_to(CODE, &exit_group(status)).unwrap();
+    machine.hart.pc = CODE;
+    machine
+}
+
+#[test]
+fn exit_hooks_see_the_status_and_usage() {
+    let mut machine = machine(3);
+    let exits = Rc::new(RefCell::new(vec![]));
+    for hook in 0..2 {
+        let exits = exits.clone();
+        machine
+            .kernel
+            .on_exit(move |exit: &ProcessExit| exits.borrow_mut().push((hook, *exit)));
+    }
+    machine.mem.write_cstr(NAME, b"open").unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::memfd_create, &[NAME, 0]), 3);
+    assert_eq!(machine.kernel.exit_status(), None);
+
+    machine.run().unwrap();
+
+    let exit = *machine.kernel.exit_status().unwrap();
+    assert_eq!(exit.code, 3);
+    // Usage from before teardown, counting the memfd and both syscalls
+    assert_eq!(exit.stats.inst_count, 2);
+    assert_eq!(exit.stats.syscalls, 2);
+    assert_eq!(exit.stats.open_files, 4);
+    // Every hook, in the order they were added
+    assert_eq!(*exits.borrow(), [(0, exit), (1, exit)]);
+
+    // Nothing is left open
+    let Machine {
+        hart, mem, kernel, ..
+    } = &mut machine;
+    assert_eq!(kernel.stats(hart, mem).open_files, 0);
+}
+
+#[test]
+fn negative_statuses_are_reported_as_is() {
+    let mut machine = machine(-1);
+    machine.run().unwrap();
+    assert_eq!(machine.kernel.exit_status().unwrap().code, u32::MAX);
+}
@@ -0,0 +1,115 @@
+//! The `fetch` hypercall, answered by the embedder within a policy.
+
+use std::{cell::RefCell, io, rc::Rc};
+
+use riscv_kernel_linux::{FetchPolicy, FetchService, MockLinux, HYPERCALL_FETCH};
+use riscv_vm::{
+    machine::{Kernel, Machine},
+    riscv_inst::Reg,
+};
+
+const URL: u32 = 0x1000;
+const REQUEST: u32 = 0x2000;
+const RESPONSE: u32 = 0x3000;
+
+fn fetch(machine: &mut Machine<MockLinux>, url: &str, request: &[u8], cap: u32) -> i32 {
+    let Machine {
+        hart, mem, kernel, ..
+    } = machine;
+    mem.copy_to(URL, url.as_bytes()).unwrap();
+    mem.copy_to(REQUEST, request).unwrap();
+    hart.set_reg(Reg::A7, HYPERCALL_FETCH);
+    let args = [
+        URL,
+        url.len() as u32,
+        REQUEST,
+        request.len() as u32,
+        RESPONSE,
+        cap,
+    ];
+    for (n, arg) in args.into_iter().enumerate() {
+        hart.set_arg(n, arg);
+    }
+    kernel.syscall(hart, mem).unwrap();
+    hart.return_value() as i32
+}
+
+fn machine(gas_limit: u64) -> (Machine<MockLinux>, Rc<RefCell<Vec<String>>>) {
+    let fetched = Rc::new(RefCell::new(vec![]));
+    let log = fetched.clone();
+    let policy = FetchPolicy {
+        allow: vec!["https://api.example.com/v1/".to_string()],
+        max_request_bytes: 16,
+        max_response_bytes: 32,
+        gas_per_call: 100,
+        gas_per_byte: 1,
+    };
+    let service = FetchService::new(policy, move |url, request| {
+        log.borrow_mut().push(url.to_string());
+        match url.rsplit('/').next().unwrap() {
+            "echo" => Ok([b"echo: ", request].concat()),
+            "big" => Ok(vec![0; 64]),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    });
+    let kernel = MockLinux::builder()
+        .fetch(service)
+        .gas_limit(gas_limit)
+        .build()
+        .unwrap();
+    (Machine::new(kernel), fetched)
+}
+
+#[test]
+fn fetches_allowed_urls() {
+    let (mut machine, fetched) = machine(u64::MAX);
+    let echo = "https://api.example.com/v1/echo";
+    assert_eq!(fetch(&mut machine, echo, b"hi", 64), 8);
+    assert_eq!(machine.mem.slice::<u8>(RESPONSE, 8).unwrap(), b"echo: hi");
+    // Responses are cut to fit, but the full length is returned
+    assert_eq!(fetch(&mut machine, echo, b"there", 4), 11);
+    assert_eq!(machine.mem.slice::<u8>(RESPONSE, 8).unwrap(), b"echo: hi");
+
+    let missing = "https://api.example.com/v1/missing";
+    assert_eq!(fetch(&mut machine, missing, b"", 64), -libc_riscv32::ENOENT);
+    assert_eq!(fetched.borrow().len(), 3);
+
+    // Refused without asking the embedder
+    for (url, request, errno) in [
+        (
+            "https://api.example.com/v2/echo",
+            &b""[..],
+            libc_riscv32::EACCES,
+        ),
+        ("https://api.example.com/v1", b"", libc_riscv32::EACCES),
+        (echo, &[0; 17], libc_riscv32::E2BIG),
+    ] {
+        assert_eq!(fetch(&mut machine, url, request, 64), -errno);
+    }
+    assert_eq!(fetched.borrow().len(), 3);
+
+    let big = "https://api.example.com/v1/big";
+    assert_eq!(fetch(&mut machine, big, b"", 64), -libc_riscv32::EFBIG);
+
+    // Guests without a service can't fetch at all
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    assert_eq!(fetch(&mut machine, echo, b"", 64), -libc_riscv32::ENOSYS);
+}
+
+#[test]
+fn fetches_are_charged_gas() {
+    let echo = "https://api.example.com/v1/echo";
+    // The call and URL, then the request and response
+    let cost = 100 + echo.len() as u64 + 2 + 8;
+    let (mut machine, fetched) = machine(2 * cost + 1);
+    assert_eq!(fetch(&mut machine, echo, b"hi", 64), 8);
+    assert_eq!(machine.kernel.gas_used(), cost);
+    assert_eq!(fetch(&mut machine, echo, b"hi", 64), 8);
+    assert_eq!(fetch(&mut machine, echo, b"hi", 64), -libc_riscv32::EDQUOT);
+    assert_eq!(machine.kernel.gas_used(), 2 * cost);
+    assert_eq!(fetched.borrow().len(), 2);
+
+    machine.kernel.reset(&mut machine.mem);
+    assert_eq!(machine.kernel.gas_used(), 0);
+    assert_eq!(fetch(&mut machine, echo, b"hi", 64), 8);
+}
@@ -0,0 +1,160 @@
+//! Vectored and positional reads and writes, called straight through the
+//! syscall entry on a memfd.
+
+mod common;
+
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::{machine::Machine, marshal::GuestSlice};
+use syscalls::riscv32::Sysno;
+
+const NAME: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const IOV: u32 = 0x3000;
+const POS: u32 = 0x4000;
+
+struct Guest {
+    machine: Machine<MockLinux>,
+    fd: u32,
+}
+
+impl Guest {
+    /// A guest with a memfd holding `contents`, positioned at its start.
+    fn with_file(contents: &[u8]) -> Self {
+        let mut machine = Machine::new(MockLinux::new(false));
+        machine.mem.write_cstr(NAME, b"file").unwrap();
+        let mut guest = Guest { machine, fd: 0 };
+        guest.fd = guest.syscall(Sysno::memfd_create, &[NAME, 0]) as u32;
+        guest.write_buf(contents);
+        guest.syscall(Sysno::write, &[guest.fd, BUF, contents.len() as u32]);
+        guest.seek(0);
+        guest
+    }
+
+    fn syscall(&mut self, sysno: Sysno, args: &[u32]) -> i32 {
+        common::syscall(&mut self.machine, sysno, args)
+    }
+
+    fn seek(&mut self, offset: u32) {
+        let ret = self.syscall(Sysno::lseek, &[self.fd, 0, offset, POS, 0]);
+        assert_eq!(ret, 0);
+    }
+
+    fn position(&mut self) -> u64 {
+        self.syscall(Sysno::lseek, &[self.fd, 0, 0, POS, 1]);
+        self.machine.mem.load(POS)
+    }
+
+    fn write_buf(&mut self, bytes: &[u8]) {
+        self.machine.mem.copy_to(BUF, bytes).unwrap();
+    }
+
+    fn read_buf(&self, len: u32) -> Vec<u8> {
+        self.machine.mem.slice(BUF, len).unwrap().to_vec()
+    }
+
+    /// Point `lens.len()` iovecs at consecutive parts of the buffer.
+    fn set_iovecs(&mut self, lens: &[u32]) -> u32 {
+        let mut ptr = BUF;
+        for (i, &len) in lens.iter().enumerate() {
+            let slice = GuestSlice { ptr, len };
+            self.machine.mem.store(IOV + 8 * i as u32, slice);
+            ptr += len;
+        }
+        lens.len() as u32
+    }
+}
+
+#[test]
+fn pread_leaves_the_position_alone() {
+    let mut guest = Guest::with_file(b"hello, world");
+    guest.seek(2);
+    let ret = guest.syscall(Sysno::pread64, &[guest.fd, BUF, 5, 7, 0]);
+    assert_eq!(ret, 5);
+    assert_eq!(guest.read_buf(5), b"world");
+    assert_eq!(guest.position(), 2);
+}
+
+#[test]
+fn pwrite_at_an_offset() {
+    let mut guest = Guest::with_file(b"hello, world");
+    guest.write_buf(b"there");
+    let ret = guest.syscall(Sysno::pwrite64, &[guest.fd, BUF, 5, 7, 0]);
+    assert_eq!(ret, 5);
+    assert_eq!(guest.position(), 0);
+
+    guest.syscall(Sysno::read, &[guest.fd, BUF, 12]);
+    assert_eq!(guest.read_buf(12), b"hello, there");
+}
+
+#[test]
+fn offsets_use_both_registers() {
+    let mut guest = Guest::with_file(b"data");
+    let ret = guest.syscall(Sysno::pread64, &[guest.fd, BUF, 4, 0, 1]);
+    assert_eq!(ret, 0, "reading 4 GiB in is past the end");
+    let ret = guest.syscall(Sysno::pread64, &[guest.fd, BUF, 4, 0, 0x8000_0000]);
+    assert_eq!(ret, -libc_riscv32::EINVAL);
+}
+
+#[test]
+fn readv_fills_iovecs_in_order() {
+    let mut guest = Guest::with_file(b"abcdefgh");
+    let iovcnt = guest.set_iovecs(&[3, 2, 8]);
+    let ret = guest.syscall(Sysno::readv, &[guest.fd, IOV, iovcnt]);
+    assert_eq!(ret, 8);
+    assert_eq!(guest.read_buf(8), b"abcdefgh");
+    assert_eq!(guest.position(), 8);
+}
+
+#[test]
+fn preadv_and_pwritev() {
+    let mut guest = Guest::with_file(b"0123456789");
+    guest.write_buf(b"abcd");
+    let iovcnt = guest.set_iovecs(&[1, 3]);
+    let ret = guest.syscall(Sysno::pwritev, &[guest.fd, IOV, iovcnt, 4, 0]);
+    assert_eq!(ret, 4);
+
+    let iovcnt = guest.set_iovecs(&[6, 4]);
+    let ret = guest.syscall(Sysno::preadv, &[guest.fd, IOV, iovcnt, 0, 0]);
+    assert_eq!(ret, 10);
+    assert_eq!(guest.read_buf(10), b"0123abcd89");
+    assert_eq!(guest.position(), 0);
+}
+
+#[test]
+fn positional_io_on_stdio_is_espipe() {
+    let mut guest = Guest::with_file(b"");
+    let ret = guest.syscall(Sysno::pwrite64, &[1, BUF, 1, 0, 0]);
+    assert_eq!(ret, -libc_riscv32::ESPIPE);
+}
+
+#[test]
+fn writev_rejects_more_than_iov_max_iovecs() {
+    let mut guest = Guest::with_file(b"");
+    let iovcnt = guest.set_iovecs(&[0; 4]);
+    let ret = guest.syscall(Sysno::writev, &[guest.fd, IOV, iovcnt]);
+    assert_eq!(ret, 0);
+
+    // Far more than the address space holds, let alone the host
+    for iovcnt in [libc_riscv32::IOV_MAX + 1, 0x1fff_ffff, u32::MAX] {
+        let ret = guest.syscall(Sysno::writev, &[guest.fd, IOV, iovcnt]);
+        assert_eq!(ret, -libc_riscv32::EINVAL, "iovcnt {iovcnt}");
+    }
+}
+
+#[test]
+fn vectored_io_rejects_more_than_iov_max_iovecs() {
+    let mut guest = Guest::with_file(b"data");
+    let iovcnt = libc_riscv32::IOV_MAX + 1;
+    for (sysno, args) in [
+        (Sysno::readv, [guest.fd, IOV, iovcnt, 0, 0]),
+        (Sysno::preadv, [guest.fd, IOV, iovcnt, 0, 0]),
+        (Sysno::pwritev, [guest.fd, IOV, iovcnt, 0, 0]),
+    ] {
+        assert_eq!(
+            guest.syscall(sysno, &args),
+            -libc_riscv32::EINVAL,
+            "{sysno}"
+        );
+    }
+    assert_eq!(guest.position(), 0);
+}
@@ -0,0 +1,96 @@
+//! Accounting for what the guest does with files, and failing it once it
+//! goes over its quota.
+
+mod common;
+
+use common::{cpio, syscall};
+use riscv_kernel_linux::{FsQuota, FsUsage, MockLinux};
+use riscv_vm::machine::{Kernel, Machine};
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const IOV: u32 = 0x3000;
+const AT_FDCWD: u32 = -100i32 as u32;
+const CREATE: u32 = libc_riscv32::O_CREAT | libc_riscv32::O_RDWR;
+
+fn machine(quota: FsQuota) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder()
+        // An empty, writable root
+        .mount_archive(cpio(&[]))
+        .fs_quota(quota)
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+fn open(machine: &mut Machine<MockLinux>, path: &str, flags: u32) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644])
+}
+
+#[test]
+fn file_io_is_counted() {
+    let mut machine = machine(FsQuota::default());
+    let fd = open(&mut machine, "/a", CREATE) as u32;
+    assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 100]), 100);
+    syscall(&mut machine, Sysno::lseek, &[fd, 0, 0, IOV, 0]);
+    assert_eq!(syscall(&mut machine, Sysno::read, &[fd, BUF, 60]), 60);
+    // Consoles don't count
+    assert_eq!(syscall(&mut machine, Sysno::write, &[1, BUF, 10]), 10);
+    machine.mem.write_cstr(PATH, b"/dir").unwrap();
+    syscall(&mut machine, Sysno::mkdirat, &[AT_FDCWD, PATH, 0o755]);
+    // Nor does opening an existing file
+    open(&mut machine, "/a", CREATE);
+
+    let usage = FsUsage {
+        bytes_read: 60,
+        bytes_written: 100,
+        files_created: 2,
+    };
+    assert_eq!(machine.kernel.fs_usage(), usage);
+
+    let Machine { mem, kernel, .. } = &mut machine;
+    kernel.reset(mem);
+    assert_eq!(machine.kernel.fs_usage(), FsUsage::default());
+}
+
+#[test]
+fn writes_stop_at_the_byte_quota() {
+    let mut machine = machine(FsQuota {
+        bytes_written: Some(150),
+        ..Default::default()
+    });
+    let fd = open(&mut machine, "/a", CREATE) as u32;
+    assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 100]), 100);
+
+    // Short, then failing
+    machine.mem.copy_to(IOV, &[BUF, 40, BUF, 40]).unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::writev, &[fd, IOV, 2]), 50);
+    assert_eq!(
+        syscall(&mut machine, Sysno::write, &[fd, BUF, 1]),
+        -libc_riscv32::EDQUOT
+    );
+    // Consoles aren't limited
+    assert_eq!(syscall(&mut machine, Sysno::write, &[1, BUF, 10]), 10);
+    assert_eq!(machine.kernel.fs_usage().bytes_written, 150);
+}
+
+#[test]
+fn creating_stops_at_the_file_quota() {
+    let mut machine = machine(FsQuota {
+        files: Some(1),
+        disk_full: true,
+        ..Default::default()
+    });
+    assert!(open(&mut machine, "/a", CREATE) >= 0);
+    assert_eq!(open(&mut machine, "/b", CREATE), -libc_riscv32::ENOSPC);
+    machine.mem.write_cstr(PATH, b"/dir").unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::mkdirat, &[AT_FDCWD, PATH, 0o755]),
+        -libc_riscv32::ENOSPC
+    );
+    // Existing files can still be opened
+    assert!(open(&mut machine, "/a", CREATE) >= 0);
+    assert_eq!(machine.kernel.fs_usage().files_created, 1);
+}
@@ -0,0 +1,22 @@
+//! End-to-end runs of the prebuilt C guests through the test harness.
+
+use riscv_kernel_linux::harness::GuestTest;
+
+const INPUTS: &[u8] = include_bytes!("../../../riscv/guest_c/inputs");
+
+#[test]
+fn args_and_env_reach_the_guest() {
+    GuestTest::new(INPUTS)
+        .args(["inputs", "a b"])
+        .env(["HOME=/root"])
+        .run()
+        .unwrap()
+        .assert_exit(0)
+        .assert_stdout("argv[0] = inputs\nargv[1] = a b\nenvp[0] = HOME=/root\n")
+        .assert_stderr("");
+}
+
+#[test]
+fn invalid_elf_fails_to_load() {
+    assert!(GuestTest::new(b"not an elf").run().is_err());
+}
@@ -0,0 +1,125 @@
+//! A guest hibernated with files open, restored into a fresh kernel, reads
+//! and writes them on from where it left off.
+
+mod common;
+
+use common::{cpio, syscall, HostDir};
+use riscv_kernel_linux::{Entropy, MockLinux};
+use riscv_vm::{error::HibernateError, machine::Machine};
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const POS: u32 = 0x3000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+const FILES: &[(&str, &str)] = &[("hello.txt", "hello from the host\n")];
+
+/// Files the guest opens, and the bytes it reads from each.
+const OPEN: [(&str, u32); 4] = [
+    ("/etc/motd", 8),
+    ("/notes", 0),
+    ("/scratch", 0),
+    ("/data/hello.txt", 6),
+];
+
+fn machine(dir: &HostDir) -> Machine<MockLinux> {
+    let motd = libc_riscv32::S_IFREG | 0o644;
+    let kernel = MockLinux::builder()
+        .mount_archive(cpio(&[("etc/motd", motd, b"welcome to the machine\n")]))
+        .mount_host("/data", &dir.0, false)
+        .entropy(Entropy::Seeded(7))
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+fn open(machine: &mut Machine<MockLinux>, path: &str) -> u32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    let flags = libc_riscv32::O_RDWR | libc_riscv32::O_CREAT;
+    let fd = syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644]);
+    assert!(fd > 2, "opening {path}: {fd}");
+    fd as u32
+}
+
+fn read(machine: &mut Machine<MockLinux>, fd: u32, len: u32) -> Vec<u8> {
+    let n = syscall(machine, Sysno::read, &[fd, BUF, len]);
+    assert!(n >= 0, "reading fd {fd}: {n}");
+    machine.mem.slice::<u8>(BUF, n as u32).unwrap().to_vec()
+}
+
+fn write(machine: &mut Machine<MockLinux>, fd: u32, bytes: &[u8]) {
+    machine.mem.copy_to(BUF, bytes).unwrap();
+    let n = syscall(machine, Sysno::write, &[fd, BUF, bytes.len() as u32]);
+    assert_eq!(n, bytes.len() as i32);
+}
+
+fn rewind(machine: &mut Machine<MockLinux>, fd: u32) {
+    assert_eq!(syscall(machine, Sysno::lseek, &[fd, 0, 0, POS, 0]), 0);
+}
+
+/// What the guest sees going on with its descriptors.
+fn carry_on(machine: &mut Machine<MockLinux>, fds: &[u32], event: u32) -> Vec<Vec<u8>> {
+    let mut seen: Vec<_> = fds.iter().map(|&fd| read(machine, fd, 0x100)).collect();
+    write(machine, fds[1], b" and more");
+    rewind(machine, fds[1]);
+    seen.push(read(machine, fds[1], 0x100));
+    // The same file, through the filesystem
+    let notes = open(machine, "/notes");
+    seen.push(read(machine, notes, 0x100));
+    seen.push(read(machine, event, 8));
+    assert_eq!(syscall(machine, Sysno::getrandom, &[BUF, 8, 0]), 8);
+    seen.push(machine.mem.slice::<u8>(BUF, 8).unwrap().to_vec());
+    seen
+}
+
+#[test]
+fn open_files_survive_hibernation() {
+    let dir = HostDir::new("hibernate-fds", FILES);
+    let mut machine = machine(&dir);
+    let fds: Vec<u32> = OPEN
+        .iter()
+        .map(|&(path, len)| {
+            let fd = open(&mut machine, path);
+            read(&mut machine, fd, len);
+            fd
+        })
+        .collect();
+    write(&mut machine, fds[1], b"draft");
+    // Still open once unlinked, with nothing else left pointing at it
+    write(&mut machine, fds[2], b"orphan");
+    rewind(&mut machine, fds[2]);
+    machine.mem.write_cstr(PATH, b"/scratch").unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::unlinkat, &[AT_FDCWD, PATH, 0]),
+        0
+    );
+    let event = syscall(&mut machine, Sysno::eventfd2, &[3, 0]) as u32;
+    syscall(&mut machine, Sysno::getrandom, &[BUF, 8, 0]);
+
+    let mut image = vec![];
+    machine.hibernate(&mut image).unwrap();
+    let mut restored = self::machine(&dir);
+    restored.restore(image.as_slice()).unwrap();
+
+    let seen = carry_on(&mut machine, &fds, event);
+    assert_eq!(carry_on(&mut restored, &fds, event), seen);
+    assert_eq!(seen[0], b"to the machine\n");
+    assert_eq!(seen[2], b"orphan");
+    assert_eq!(seen[3], b"from the host\n");
+    assert_eq!(seen[4], b"draft and more");
+    assert_eq!(seen[5], seen[4]);
+    assert_eq!(seen[6], 3u64.to_ne_bytes());
+}
+
+#[test]
+fn sockets_prevent_hibernation() {
+    let dir = HostDir::new("hibernate-socket", FILES);
+    let mut machine = machine(&dir);
+    let ty = libc_riscv32::SOCK_STREAM;
+    let args = [libc_riscv32::AF_UNIX, ty, 0, BUF];
+    assert_eq!(syscall(&mut machine, Sysno::socketpair, &args), 0);
+
+    let err = machine.hibernate(vec![]).unwrap_err();
+    assert!(matches!(err, HibernateError::KernelUnsupported));
+}
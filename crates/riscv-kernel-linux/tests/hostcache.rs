@@ -0,0 +1,108 @@
+//! Host mounts answering lookups and reads from a cache, and forgetting it
+//! when the guest changes something.
+
+mod common;
+
+use common::{syscall, HostDir};
+use riscv_kernel_linux::{HostCache, HostCacheStats, MockLinux};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+const FILES: &[(&str, &str)] = &[("include/stdio.h", "int puts(const char *);\n")];
+
+fn machine(dir: &HostDir, capacity: u64) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder()
+        .host_cache(HostCache::new(capacity))
+        .mount_host("/src", &dir.0, false)
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+fn stat(machine: &mut Machine<MockLinux>, path: &str) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::statx, &[AT_FDCWD, PATH, 0, 0xfff, BUF])
+}
+
+/// The contents of `path`, or the error opening it.
+fn read(machine: &mut Machine<MockLinux>, path: &str) -> Result<String, i32> {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    let fd = syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]);
+    if fd < 0 {
+        return Err(fd);
+    }
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    syscall(machine, Sysno::close, &[fd as u32]);
+    let bytes = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    Ok(String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+fn stats(machine: &Machine<MockLinux>) -> HostCacheStats {
+    machine.kernel.host_cache().unwrap().stats()
+}
+
+#[test]
+fn lookups_and_reads_are_cached() {
+    let dir = HostDir::new("cache-hits", FILES);
+    let mut machine = machine(&dir, 1 << 20);
+    for _ in 0..3 {
+        assert_eq!(
+            stat(&mut machine, "/src/include/missing.h"),
+            -libc_riscv32::ENOENT
+        );
+        let header = read(&mut machine, "/src/include/stdio.h").unwrap();
+        assert_eq!(header, "int puts(const char *);\n");
+    }
+
+    let stats = stats(&machine);
+    assert_eq!((stats.stat_hits, stats.stat_misses), (4, 2));
+    assert_eq!((stats.read_hits, stats.read_misses), (2, 1));
+
+    // What the host does behind the guest's back isn't seen until asked
+    std::fs::write(dir.0.join("include/missing.h"), "").unwrap();
+    assert_eq!(
+        stat(&mut machine, "/src/include/missing.h"),
+        -libc_riscv32::ENOENT
+    );
+    machine.kernel.host_cache().unwrap().invalidate();
+    assert_eq!(stat(&mut machine, "/src/include/missing.h"), 0);
+}
+
+#[test]
+fn guest_changes_invalidate() {
+    let dir = HostDir::new("cache-writes", FILES);
+    let mut machine = machine(&dir, 1 << 20);
+    assert!(read(&mut machine, "/src/include/stdio.h").is_ok());
+
+    machine
+        .mem
+        .write_cstr(PATH, b"/src/include/stdio.h")
+        .unwrap();
+    let flags = libc_riscv32::O_WRONLY | libc_riscv32::O_APPEND;
+    let fd = syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0]) as u32;
+    machine
+        .mem
+        .write_cstr(BUF, b"int printf(const char *, ...);\n")
+        .unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 31]), 31);
+
+    let header = read(&mut machine, "/src/include/stdio.h").unwrap();
+    assert!(header.ends_with("int printf(const char *, ...);\n"));
+    assert_eq!(stats(&machine).invalidations, 1);
+}
+
+#[test]
+fn big_files_are_read_from_the_host() {
+    let dir = HostDir::new("cache-big", FILES);
+    let mut machine = machine(&dir, 8);
+    for _ in 0..2 {
+        assert!(read(&mut machine, "/src/include/stdio.h").is_ok());
+    }
+    let stats = stats(&machine);
+    assert_eq!((stats.read_hits, stats.read_misses), (0, 2));
+    assert_eq!(machine.kernel.host_cache().unwrap().cached_bytes(), 0);
+}
@@ -0,0 +1,76 @@
+//! Host directories mounted into the guest: what the guest reads and
+//! writes through them reaches the host, unless they're read-only.
+
+mod common;
+
+use common::{syscall, HostDir};
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+const FILES: &[(&str, &str)] = &[("hello.txt", "hello from the host\n")];
+
+fn machine(dir: &HostDir, read_only: bool) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder()
+        .mount_host("/data", &dir.0, read_only)
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+fn open(machine: &mut Machine<MockLinux>, path: &str, flags: u32) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644])
+}
+
+fn read(machine: &mut Machine<MockLinux>, path: &str) -> String {
+    let fd = open(machine, path, 0);
+    assert!(fd >= 0, "opening {path}: {fd}");
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    syscall(machine, Sysno::close, &[fd as u32]);
+    let bytes = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn guest_reads_and_writes_reach_the_host() {
+    let dir = HostDir::new("hostfs-rw", FILES);
+    let mut machine = machine(&dir, false);
+    assert_eq!(
+        read(&mut machine, "/data/hello.txt"),
+        "hello from the host\n"
+    );
+
+    let flags = libc_riscv32::O_WRONLY | libc_riscv32::O_CREAT;
+    let fd = open(&mut machine, "/data/reply.txt", flags) as u32;
+    machine.mem.copy_to(BUF, b"hello from the guest\n").unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 21]), 21);
+    assert_eq!(syscall(&mut machine, Sysno::close, &[fd]), 0);
+
+    let reply = std::fs::read_to_string(dir.0.join("reply.txt")).unwrap();
+    assert_eq!(reply, "hello from the guest\n");
+    // And back, through the mount
+    assert_eq!(read(&mut machine, "/data/reply.txt"), reply);
+    // Nothing outside the mount is the host's
+    assert_eq!(open(&mut machine, "/hello.txt", 0), -libc_riscv32::ENOENT);
+}
+
+#[test]
+fn read_only_mounts_refuse_writes() {
+    let dir = HostDir::new("hostfs-ro", FILES);
+    let mut machine = machine(&dir, true);
+    assert_eq!(
+        read(&mut machine, "/data/hello.txt"),
+        "hello from the host\n"
+    );
+
+    let flags = libc_riscv32::O_WRONLY | libc_riscv32::O_CREAT;
+    for path in ["/data/hello.txt", "/data/reply.txt"] {
+        assert_eq!(open(&mut machine, path, flags), -libc_riscv32::EROFS);
+    }
+    assert!(!dir.0.join("reply.txt").exists());
+}
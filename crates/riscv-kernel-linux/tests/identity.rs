@@ -0,0 +1,110 @@
+//! The guest's user, as the uid and gid syscalls and `/etc` report it, and
+//! the umask applied to files it creates.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{Identity, MockLinux};
+use riscv_vm::machine::{Kernel, Machine};
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const IDS: u32 = 0x3000;
+const AT_FDCWD: u32 = -100i32 as u32;
+const UNCHANGED: u32 = u32::MAX;
+
+fn machine(identity: Identity) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder().identity(identity).build().unwrap();
+    Machine::new(kernel)
+}
+
+fn read_file(machine: &mut Machine<MockLinux>, path: &str) -> String {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    let fd = syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]);
+    assert!(fd >= 0, "opening {path}: {fd}");
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    let bytes = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+fn resuid(machine: &mut Machine<MockLinux>) -> [u32; 3] {
+    syscall(machine, Sysno::getresuid, &[IDS, IDS + 4, IDS + 8]);
+    machine.mem.load(IDS)
+}
+
+#[test]
+fn etc_describes_the_user() {
+    let mut machine = machine(Identity::user("alice", 1000, 100));
+    assert_eq!(syscall(&mut machine, Sysno::getuid, &[]), 1000);
+    assert_eq!(syscall(&mut machine, Sysno::geteuid, &[]), 1000);
+    assert_eq!(syscall(&mut machine, Sysno::getgid, &[]), 100);
+    assert_eq!(syscall(&mut machine, Sysno::getegid, &[]), 100);
+
+    let passwd = read_file(&mut machine, "/etc/passwd");
+    assert!(passwd.starts_with("root:x:0:0:"));
+    assert!(passwd.contains("\nalice:x:1000:100:alice:/home/alice:/bin/sh\n"));
+    let group = read_file(&mut machine, "/etc/group");
+    assert!(group.contains("\nalice:x:100:alice\n"));
+
+    // Root by default
+    let mut machine = Machine::new(MockLinux::new(false));
+    assert_eq!(syscall(&mut machine, Sysno::getuid, &[]), 0);
+    let passwd = read_file(&mut machine, "/etc/passwd");
+    assert!(passwd.starts_with("root:x:0:0:root:/root:/bin/sh\n"));
+}
+
+#[test]
+fn unprivileged_users_only_switch_among_their_ids() {
+    let mut machine = machine(Identity::user("alice", 1000, 1000));
+    assert_eq!(
+        syscall(&mut machine, Sysno::setuid, &[0]),
+        -libc_riscv32::EPERM
+    );
+    assert_eq!(
+        syscall(&mut machine, Sysno::setgroups, &[0, 0]),
+        -libc_riscv32::EPERM
+    );
+    assert_eq!(syscall(&mut machine, Sysno::setuid, &[1000]), 0);
+    assert_eq!(resuid(&mut machine), [1000; 3]);
+
+    // Root can drop privileges, keeping them saved, and take them back
+    let mut machine = self::machine(Identity::default());
+    let ret = syscall(&mut machine, Sysno::setresuid, &[UNCHANGED, 1000, 0]);
+    assert_eq!(ret, 0);
+    assert_eq!(resuid(&mut machine), [0, 1000, 0]);
+    assert_eq!(syscall(&mut machine, Sysno::setuid, &[0]), 0);
+    assert_eq!(resuid(&mut machine), [0, 0, 0]);
+
+    // For good
+    assert_eq!(syscall(&mut machine, Sysno::setuid, &[1000]), 0);
+    assert_eq!(resuid(&mut machine), [1000; 3]);
+    assert_eq!(
+        syscall(&mut machine, Sysno::setuid, &[0]),
+        -libc_riscv32::EPERM
+    );
+
+    // Until the machine is reset
+    let Machine { mem, kernel, .. } = &mut machine;
+    kernel.reset(mem);
+    assert_eq!(syscall(&mut machine, Sysno::geteuid, &[]), 0);
+}
+
+#[test]
+fn umask_applies_to_created_files() {
+    let mut machine = machine(Identity {
+        umask: 0o027,
+        ..Identity::default()
+    });
+    machine.mem.write_cstr(PATH, b"/etc/new").unwrap();
+    let flags = libc_riscv32::O_CREAT | libc_riscv32::O_WRONLY;
+    let fd = syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o666]);
+    assert!(fd >= 0);
+    let ret = syscall(&mut machine, Sysno::statx, &[AT_FDCWD, PATH, 0, 0xfff, BUF]);
+    assert_eq!(ret, 0);
+    let mode: u16 = machine.mem.load(BUF + 28);
+    assert_eq!(mode & 0o777, 0o640);
+
+    assert_eq!(syscall(&mut machine, Sysno::umask, &[0o7077]), 0o027);
+    assert_eq!(syscall(&mut machine, Sysno::umask, &[0]), 0o077);
+}
@@ -0,0 +1,124 @@
+//! The key-value hypercalls, backed by a store from the embedder.
+
+use std::collections::BTreeMap;
+
+use riscv_kernel_linux::{
+    KvPolicy, KvService, MockLinux, HYPERCALL_KV_DELETE, HYPERCALL_KV_GET, HYPERCALL_KV_NEXT,
+    HYPERCALL_KV_PUT,
+};
+use riscv_vm::{
+    machine::{Kernel, Machine},
+    riscv_inst::Reg,
+};
+
+const KEY: u32 = 0x1000;
+const VALUE: u32 = 0x2000;
+
+fn hypercall(machine: &mut Machine<MockLinux>, nr: u32, args: &[u32]) -> i32 {
+    let Machine {
+        hart, mem, kernel, ..
+    } = machine;
+    hart.set_reg(Reg::A7, nr);
+    for (n, &arg) in args.iter().enumerate() {
+        hart.set_arg(n, arg);
+    }
+    kernel.syscall(hart, mem).unwrap();
+    hart.return_value() as i32
+}
+
+fn put(machine: &mut Machine<MockLinux>, key: &[u8], value: &[u8]) -> i32 {
+    machine.mem.copy_to(KEY, key).unwrap();
+    machine.mem.copy_to(VALUE, value).unwrap();
+    let args = [KEY, key.len() as u32, VALUE, value.len() as u32];
+    hypercall(machine, HYPERCALL_KV_PUT, &args)
+}
+
+fn get(machine: &mut Machine<MockLinux>, key: &[u8]) -> Result<Vec<u8>, i32> {
+    machine.mem.copy_to(KEY, key).unwrap();
+    let n = hypercall(
+        machine,
+        HYPERCALL_KV_GET,
+        &[KEY, key.len() as u32, VALUE, 64],
+    );
+    if n < 0 {
+        return Err(-n);
+    }
+    Ok(machine.mem.slice::<u8>(VALUE, n as u32).unwrap().to_vec())
+}
+
+/// Every key in order, iterating from the guest.
+fn keys(machine: &mut Machine<MockLinux>) -> Vec<Vec<u8>> {
+    let mut keys = vec![];
+    let mut key = (0, 0);
+    loop {
+        let n = hypercall(machine, HYPERCALL_KV_NEXT, &[key.0, key.1, VALUE, 64]);
+        if n == -libc_riscv32::ENOENT {
+            return keys;
+        }
+        let next = machine.mem.slice::<u8>(VALUE, n as u32).unwrap().to_vec();
+        machine.mem.copy_to(KEY, &next).unwrap();
+        key = (KEY, n as u32);
+        keys.push(next);
+    }
+}
+
+fn machine(gas_limit: u64) -> Machine<MockLinux> {
+    let policy = KvPolicy {
+        max_key_bytes: 8,
+        max_value_bytes: 16,
+        gas_per_read: 10,
+        gas_per_write: 100,
+        gas_per_byte: 1,
+    };
+    let store = BTreeMap::from([(b"seeded".to_vec(), b"1".to_vec())]);
+    let kernel = MockLinux::builder()
+        .kv(KvService::new(policy, store))
+        .gas_limit(gas_limit)
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+#[test]
+fn guests_get_put_delete_and_iterate() {
+    let mut machine = machine(u64::MAX);
+    assert_eq!(get(&mut machine, b"seeded").unwrap(), b"1");
+    assert_eq!(get(&mut machine, b"b"), Err(libc_riscv32::ENOENT));
+    assert_eq!(put(&mut machine, b"b", b"bee"), 0);
+    assert_eq!(put(&mut machine, b"", b"empty"), 0);
+    assert_eq!(put(&mut machine, b"a", b"ay"), 0);
+    assert_eq!(get(&mut machine, b"b").unwrap(), b"bee");
+    assert_eq!(put(&mut machine, b"b", b"overwritten"), 0);
+    assert_eq!(get(&mut machine, b"b").unwrap(), b"overwritten");
+    assert_eq!(
+        keys(&mut machine),
+        [&b""[..], b"a", b"b", b"seeded"].map(<[u8]>::to_vec)
+    );
+
+    machine.mem.copy_to(KEY, b"a").unwrap();
+    assert_eq!(hypercall(&mut machine, HYPERCALL_KV_DELETE, &[KEY, 1]), 0);
+    assert_eq!(
+        hypercall(&mut machine, HYPERCALL_KV_DELETE, &[KEY, 1]),
+        -libc_riscv32::ENOENT
+    );
+    assert_eq!(put(&mut machine, b"too long!", b""), -libc_riscv32::E2BIG);
+    assert_eq!(put(&mut machine, b"c", &[0; 17]), -libc_riscv32::EFBIG);
+
+    // The embedder sees what the guest stored
+    let store = machine.kernel.kv().unwrap().store();
+    assert_eq!(store.get(b"b").unwrap().unwrap(), b"overwritten");
+    assert_eq!(store.get(b"a").unwrap(), None);
+}
+
+#[test]
+fn calls_are_charged_gas() {
+    // A write per put, with its key and value, then a read with both
+    let mut machine = machine(2 * (100 + 2) + 10 + 2);
+    assert_eq!(put(&mut machine, b"k", b"v"), 0);
+    assert_eq!(machine.kernel.gas_used(), 102);
+    assert_eq!(put(&mut machine, b"k", b"v"), 0);
+    assert_eq!(put(&mut machine, b"k", b"v"), -libc_riscv32::EDQUOT);
+    assert_eq!(get(&mut machine, b"k").unwrap(), b"v");
+    assert_eq!(get(&mut machine, b"k"), Err(libc_riscv32::EDQUOT));
+    assert_eq!(machine.kernel.gas_used(), 2 * 102 + 12);
+}
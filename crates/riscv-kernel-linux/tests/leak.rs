@@ -0,0 +1,60 @@
+//! Leak tracking through the guest's `malloc` family, on a prebuilt C guest.
+
+use riscv_kernel_linux::{elf_symbols, MockLinux};
+use riscv_vm::machine::{Machine, MachineConfig};
+
+/// `malloc(100)`s and returns without freeing it.
+const ALLOC: &[u8] = include_bytes!("../../../riscv/guest_c/alloc");
+
+fn run(config: MachineConfig) -> Machine<MockLinux> {
+    let mut machine = Machine::with_config(MockLinux::deterministic(false), config);
+    let elf = machine
+        .kernel
+        .load_static_elf(&mut machine.hart, &mut machine.mem, ALLOC, &["alloc"], &[])
+        .unwrap();
+    machine.symbols = elf_symbols(&elf);
+    machine.run().unwrap();
+    machine
+}
+
+#[test]
+fn unfreed_malloc_is_reported() {
+    let machine = run(MachineConfig {
+        leak_check: true,
+        ..Default::default()
+    });
+    let leaks = machine.leaks.as_ref().unwrap();
+    let live: Vec<_> = leaks.live().collect();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].size, 100);
+
+    let caller = machine.symbols.symbolize(live[0].backtrace[0]).unwrap().0;
+    assert_eq!(caller, "main");
+
+    let mut report = vec![];
+    leaks
+        .write_report(&mut report, &machine.mem, &machine.symbols)
+        .unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(
+        report.starts_with("1 of 1 allocations not freed, 100 bytes;"),
+        "{report}"
+    );
+    assert!(report.contains("#0 main+"), "{report}");
+}
+
+#[test]
+fn shadow_stack_gives_full_backtraces() {
+    let machine = run(MachineConfig {
+        leak_check: true,
+        shadow_stack: true,
+        ..Default::default()
+    });
+    let leak = machine.leaks.as_ref().unwrap().live().next().unwrap();
+    assert!(leak.backtrace.len() > 1, "{:?}", leak.backtrace);
+}
+
+#[test]
+fn off_by_default() {
+    assert!(run(MachineConfig::default()).leaks.is_none());
+}
@@ -0,0 +1,65 @@
+//! Loading a shared object next to the main program, on a small hand-built
+//! rv32 ELF.
+
+mod common;
+
+use common::{shared_object, DATA, END, STB_GLOBAL, STB_WEAK, TEXT};
+use riscv_kernel_linux::{LinuxError, MockLinux};
+use riscv_vm::{error::MachineError, machine::Machine};
+
+const HOST_VALUE: u32 = 0x5000;
+
+fn machine() -> Machine<MockLinux> {
+    let mut machine = Machine::new(MockLinux::new(false));
+    machine.symbols.insert("host_value", HOST_VALUE, 4);
+    machine
+}
+
+#[test]
+fn library_is_relocated_and_callable() {
+    let mut machine = machine();
+    let mmap_top = machine.mem.mmap_top;
+    let lib = machine.load_library(&shared_object(STB_GLOBAL)).unwrap();
+    assert_eq!(lib.base % 4096, 0);
+    assert!(lib.base + lib.size <= mmap_top);
+    assert_eq!(machine.mem.mmap_top, lib.base);
+
+    let answer = lib.base + TEXT as u32;
+    assert_eq!(lib.symbols.lookup("answer"), Some(answer));
+    assert_eq!(
+        machine.symbols.lookup("table"),
+        Some(lib.base + DATA as u32)
+    );
+    assert_eq!(machine.symbols.lookup("host_value"), Some(HOST_VALUE));
+
+    let data: [u32; 4] = machine.mem.load(lib.base + DATA as u32);
+    assert_eq!(data, [answer, answer, HOST_VALUE + 4, answer]);
+    assert_eq!(lib.constructors, vec![answer]);
+    // bss past the end of the file is zeroed
+    assert_eq!(machine.mem.load::<u32>(lib.base + END as u32), 0);
+
+    let answer = machine.symbols.lookup("answer").unwrap();
+    assert_eq!(machine.call(answer, &[]).unwrap(), 42);
+}
+
+#[test]
+fn libraries_stack_downwards() {
+    let mut machine = machine();
+    let first = machine.load_library(&shared_object(STB_GLOBAL)).unwrap();
+    let second = machine.load_library(&shared_object(STB_GLOBAL)).unwrap();
+    assert!(second.base + second.size <= first.base);
+}
+
+#[test]
+fn missing_imports_fail_unless_weak() {
+    let mut machine = Machine::new(MockLinux::new(false));
+    match machine.load_library(&shared_object(STB_GLOBAL)) {
+        Err(MachineError::Kernel(LinuxError::UnresolvedSymbol(name))) => {
+            assert_eq!(name, "host_value")
+        }
+        res => panic!("expected an unresolved symbol, got {res:?}"),
+    }
+
+    let lib = machine.load_library(&shared_object(STB_WEAK)).unwrap();
+    assert_eq!(machine.mem.load::<u32>(lib.base + DATA as u32 + 8), 4);
+}
@@ -0,0 +1,112 @@
+//! Memfds sized with `ftruncate` and mapped into the guest: shared
+//! mappings see each other's writes and the host's, private ones get a copy.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const NAME: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const PAGE: u32 = 0x1000;
+const RW: u32 = libc_riscv32::PROT_READ | libc_riscv32::PROT_WRITE;
+
+/// A machine with a two page memfd holding "hello" at its start.
+fn memfd() -> (Machine<MockLinux>, u32) {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    machine.mem.write_cstr(NAME, b"buffer").unwrap();
+    let fd = syscall(&mut machine, Sysno::memfd_create, &[NAME, 0]);
+    assert!(fd >= 0, "memfd_create: {fd}");
+    let fd = fd as u32;
+    assert_eq!(
+        syscall(&mut machine, Sysno::ftruncate, &[fd, 2 * PAGE, 0]),
+        0
+    );
+    machine.mem.copy_to(BUF, b"hello").unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 5]), 5);
+    (machine, fd)
+}
+
+fn mmap(machine: &mut Machine<MockLinux>, fd: u32, flags: u32, pgoff: u32) -> i32 {
+    syscall(machine, Sysno::mmap, &[0, 2 * PAGE, RW, flags, fd, pgoff])
+}
+
+#[test]
+fn shared_mappings_share_the_memfd() {
+    let (mut machine, fd) = memfd();
+    let shm = machine.kernel.shared_memory(fd as i32).unwrap();
+    assert_eq!(shm.len().unwrap(), 2 * PAGE as u64);
+
+    let shared = libc_riscv32::MAP_SHARED;
+    let a = mmap(&mut machine, fd, shared, 0) as u32;
+    let b = mmap(&mut machine, fd, shared, 0) as u32;
+    assert_ne!(a, b);
+    assert_eq!(machine.mem.slice::<u8>(a, 5).unwrap(), b"hello");
+
+    // Through one mapping, seen in the other and by the host
+    machine.mem.copy_to(a + PAGE, b"world").unwrap();
+    assert_eq!(machine.mem.slice::<u8>(b + PAGE, 5).unwrap(), b"world");
+    let mut host = [0; 5];
+    shm.read_at(&mut host, PAGE as u64).unwrap();
+    assert_eq!(&host, b"world");
+    let (start, region) = machine.kernel.shared_region(a + PAGE).unwrap();
+    assert_eq!((start, region.offset, region.len), (a, 0, 2 * PAGE));
+
+    // And back
+    shm.write_at(b"HELLO", 0).unwrap();
+    assert_eq!(machine.mem.slice::<u8>(b, 5).unwrap(), b"HELLO");
+
+    // Unmapping one page leaves the rest of the mapping in place
+    assert_eq!(syscall(&mut machine, Sysno::munmap, &[a, PAGE]), 0);
+    let (start, region) = machine.kernel.shared_region(a + PAGE).unwrap();
+    assert_eq!(
+        (start, region.offset, region.len),
+        (a + PAGE, PAGE as u64, PAGE)
+    );
+    assert!(machine.kernel.shared_region(a).is_none());
+}
+
+#[test]
+fn private_mappings_get_a_copy() {
+    let (mut machine, fd) = memfd();
+    let private = mmap(&mut machine, fd, libc_riscv32::MAP_PRIVATE, 0) as u32;
+    assert_eq!(machine.mem.slice::<u8>(private, 5).unwrap(), b"hello");
+    assert!(machine.kernel.shared_region(private).is_none());
+
+    machine.mem.copy_to(private, b"HELLO").unwrap();
+    let shm = machine.kernel.shared_memory(fd as i32).unwrap();
+    let mut host = [0; 5];
+    shm.read_at(&mut host, 0).unwrap();
+    assert_eq!(&host, b"hello");
+}
+
+#[test]
+fn bad_memfd_calls_fail_with_errno() {
+    let (mut machine, fd) = memfd();
+    let einval = -libc_riscv32::EINVAL;
+
+    assert_eq!(
+        syscall(&mut machine, Sysno::memfd_create, &[NAME, 0x100]),
+        einval
+    );
+    assert_eq!(
+        syscall(&mut machine, Sysno::memfd_create, &[u32::MAX, 0]),
+        -libc_riscv32::EFAULT
+    );
+    assert_eq!(
+        syscall(&mut machine, Sysno::ftruncate, &[fd, 0, u32::MAX]),
+        einval
+    );
+    assert_eq!(
+        syscall(&mut machine, Sysno::ftruncate, &[99, 0, 0]),
+        -libc_riscv32::EBADF
+    );
+
+    // Only within the object, and only memfds
+    let shared = libc_riscv32::MAP_SHARED;
+    assert_eq!(mmap(&mut machine, fd, shared, 1), -libc_riscv32::ENXIO);
+    assert_eq!(mmap(&mut machine, 0, shared, 0), -libc_riscv32::ENODEV);
+    assert_eq!(mmap(&mut machine, 99, shared, 0), -libc_riscv32::EBADF);
+}
@@ -0,0 +1,94 @@
+//! A running guest moved to another machine with pre-copy migration, its
+//! memory sent in rounds while it runs, carries on there as it would have.
+
+mod common;
+
+use common::{cpio, syscall};
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::{hart::HartContext, machine::Machine, migrate::PreCopy};
+use syscalls::riscv32::Sysno;
+
+const CODE: u32 = 0x1000;
+const DATA: u32 = 0x8000;
+const PATH: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+/// Counts forever, storing the count at `DATA` and appending it to fd 3.
+const COUNTER: [u32; 9] = [
+    0x0000_82b7, // lui t0, 0x8
+    0x0014_0413, // loop: addi s0, s0, 1
+    0x0082_a023, // sw s0, 0(t0)
+    0x0030_0513, // li a0, 3
+    0x0002_8593, // mv a1, t0
+    0x0040_0613, // li a2, 4
+    0x0400_0893, // li a7, 64
+    0x0000_0073, // ecall
+    0xfe5f_f06f, // j loop
+];
+
+fn machine() -> Machine<MockLinux> {
+    let kernel = MockLinux::builder()
+        .mount_archive(cpio(&[]))
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+fn step(machine: &mut Machine<MockLinux>, steps: usize) {
+    for _ in 0..steps {
+        machine.step().unwrap();
+    }
+}
+
+/// The hart, the count in memory, and the counts written so far.
+fn observe(machine: &mut Machine<MockLinux>) -> (HartContext, u64, u32, Vec<u8>) {
+    machine.mem.write_cstr(PATH, b"/counts").unwrap();
+    let fd = syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]) as u32;
+    let len = syscall(machine, Sysno::read, &[fd, 0x10000, 0x1000]);
+    syscall(machine, Sysno::close, &[fd]);
+    let counts = machine.mem.slice::<u8>(0x10000, len as u32).unwrap();
+    (
+        machine.hart.context(),
+        machine.hart.inst_count,
+        machine.mem.load(DATA),
+        counts.to_vec(),
+    )
+}
+
+#[test]
+fn migrated_guest_carries_on_identically() {
+    let mut machine = machine();
+    machine.mem.write_cstr(PATH, b"/counts").unwrap();
+    let flags = libc_riscv32::O_WRONLY | libc_riscv32::O_CREAT;
+    assert_eq!(
+        syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644]),
+        3
+    );
+    machine.mem.copy_to(CODE, &COUNTER).unwrap();
+    machine.hart.pc = CODE;
+
+    let mut copy = PreCopy::new(vec![], &machine).unwrap();
+    let first = copy.send_dirty(&machine).unwrap();
+    for _ in 0..2 {
+        step(&mut machine, 40);
+        // Only the count's page changes while the guest runs
+        assert_eq!(copy.send_dirty(&machine).unwrap(), 1);
+    }
+    step(&mut machine, 40);
+    let stream = copy.finish(&machine).unwrap();
+    assert!(first > 1);
+
+    let mut destination = self::machine();
+    destination.restore(stream.as_slice()).unwrap();
+    // To the end of the 27th count
+    step(&mut machine, 97);
+    step(&mut destination, 97);
+    let (context, inst_count, count, counts) = observe(&mut destination);
+    assert_eq!(
+        (context, inst_count, count, counts.clone()),
+        observe(&mut machine)
+    );
+    assert_eq!(count, 27);
+    assert_eq!(counts.len(), 4 * 27);
+    assert_eq!(counts[counts.len() - 4..], 27u32.to_le_bytes());
+}
@@ -0,0 +1,102 @@
+//! Guest panics and aborts stop the machine with the panic message from
+//! stderr and a symbolized backtrace, when panics are caught.
+
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::{
+    error::MachineError,
+    machine::{Machine, MachineConfig},
+    panic::{GuestBacktrace, GuestPanic},
+};
+
+const CODE: u32 = 0x1000;
+const MESSAGE: u32 = 0x8000;
+const PANIC: u32 = CODE + 0x20;
+
+const STDERR: &str = "starting\nthread 'main' panicked at src/main.rs:2:5:\nboom\n";
+
+/// Writes [`STDERR`] to stderr, then calls the function at [`PANIC`].
+fn program() -> [u32; 10] {
+    [
+        0x0020_0513,                          // li a0, 2
+        0x0000_85b7,                          // lui a1, 0x8
+        (STDERR.len() as u32) << 20 | 0x0613, // li a2, len
+        0x0400_0893,                          // li a7, 64
+        0x0000_0073,                          // ecall
+        0x00c0_00ef,                          // jal ra, PANIC
+        0x0010_0073,                          // ebreak
+        0x0000_0013,                          // nop
+        0x0000_0013,                          // PANIC: nop
+        0x0010_0073,                          // ebreak
+    ]
+}
+
+fn machine(catch_panics: bool, symbol: &str) -> Machine<MockLinux> {
+    let config = MachineConfig {
+        catch_panics,
+        ..Default::default()
+    };
+    let kernel = MockLinux::builder().build().unwrap();
+    let mut machine = Machine::with_config(kernel, config);
+    machine.mem.copy_to(CODE, &program()).unwrap();
+    machine.mem.copy_to(MESSAGE, STDERR.as_bytes()).unwrap();
+    machine.hart.pc = CODE;
+    machine.symbols.insert("main", CODE, 0x20);
+    machine.symbols.insert(symbol, PANIC, 8);
+    machine
+}
+
+fn caught(machine: &mut Machine<MockLinux>) -> GuestPanic {
+    match machine.run() {
+        Err(MachineError::GuestPanic(panic)) => *panic,
+        res => panic!("expected a guest panic, got {res:?}"),
+    }
+}
+
+#[test]
+fn panics_are_caught_with_their_message() {
+    let mut machine = machine(true, "rust_panic");
+    let panic = caught(&mut machine);
+
+    // Before running any of it
+    assert_eq!(machine.hart.pc, PANIC);
+    assert_eq!(machine.hart.inst_count, 6);
+    let message = "thread 'main' panicked at src/main.rs:2:5:\nboom";
+    assert_eq!(
+        panic,
+        GuestPanic {
+            symbol: "rust_panic",
+            message: Some(message.to_string()),
+            backtrace: GuestBacktrace(vec![
+                "rust_panic (0x00001020)".to_string(),
+                "main+0x18 (0x00001018)".to_string(),
+            ]),
+        }
+    );
+    assert_eq!(
+        panic.to_string(),
+        format!(
+            "Guest panic: {message}\nGuest backtrace (innermost first):\n  \
+             #0 rust_panic (0x00001020)\n  #1 main+0x18 (0x00001018)"
+        )
+    );
+}
+
+#[test]
+fn aborts_are_caught_without_a_message() {
+    let mut machine = machine(true, "abort");
+    machine
+        .mem
+        .memset(MESSAGE, b'.', STDERR.len() as u32)
+        .unwrap();
+    let panic = caught(&mut machine);
+    assert_eq!((panic.symbol, &panic.message), ("abort", &None));
+    assert!(panic.to_string().starts_with("Guest called abort\n"));
+}
+
+#[test]
+fn uncaught_panics_run_on() {
+    let mut machine = machine(false, "rust_panic");
+    machine.run().unwrap();
+    // Through the panic function to its ebreak
+    assert_eq!(machine.hart.pc, PANIC + 4);
+}
@@ -0,0 +1,97 @@
+//! Poisoning memory the guest unmaps, and catching its uses after.
+
+mod common;
+
+use riscv_kernel_linux::{LinuxError, MockLinux};
+use riscv_vm::{
+    error::{MachineError, MemoryAccess, MemoryError},
+    machine::{Machine, MachineConfig},
+    protect::{Poison, POISON_BYTE},
+    riscv_inst::Reg,
+};
+use syscalls::riscv32::Sysno;
+
+const CODE: u32 = 0x1000;
+const LOAD: u32 = 0x0005_a503; // lw a0, 0(a1)
+const HEAP: u32 = 0x10000;
+
+fn syscall(machine: &mut Machine<MockLinux>, sysno: Sysno, args: &[u32]) -> u32 {
+    common::syscall(machine, sysno, args) as u32
+}
+
+fn machine(poison: Poison) -> Machine<MockLinux> {
+    let config = MachineConfig {
+        poison,
+        ..Default::default()
+    };
+    let mut machine = Machine::with_config(MockLinux::builder().build().unwrap(), config);
+    machine.mem.store(CODE, LOAD);
+    machine.mem.brk = HEAP;
+    machine
+}
+
+fn mmap(machine: &mut Machine<MockLinux>, addr: u32, flags: u32) -> u32 {
+    syscall(
+        machine,
+        Sysno::mmap,
+        &[addr, 0x1000, 3, flags, -1i32 as u32, 0],
+    )
+}
+
+/// Load the word at `addr` from the guest.
+fn load(machine: &mut Machine<MockLinux>, addr: u32) -> Result<u32, MachineError<LinuxError>> {
+    machine.hart.pc = CODE;
+    machine.hart.set_reg(Reg::A1, addr);
+    machine.step()?;
+    Ok(machine.hart.get_reg(Reg::A0))
+}
+
+#[test]
+fn unmapped_memory_is_filled() {
+    let mut machine = machine(Poison::Fill);
+    let addr = mmap(&mut machine, 0, 0x22);
+    machine.mem.store(addr, 42u32);
+    syscall(&mut machine, Sysno::munmap, &[addr, 0x1000]);
+
+    assert!(machine.mem.protection.is_none());
+    let poison = u32::from_ne_bytes([POISON_BYTE; 4]);
+    assert_eq!(load(&mut machine, addr).unwrap(), poison);
+}
+
+#[test]
+fn uses_after_unmap_are_caught_until_remapped() {
+    let mut machine = machine(Poison::Trap);
+    let addr = mmap(&mut machine, 0, 0x22);
+    assert_eq!(load(&mut machine, addr).unwrap(), 0);
+    syscall(&mut machine, Sysno::munmap, &[addr, 0x1000]);
+
+    let err = load(&mut machine, addr + 8).unwrap_err();
+    assert!(matches!(
+        err,
+        MachineError::Memory(MemoryError::Poisoned {
+            access: MemoryAccess::Load,
+            addr: a,
+        }) if a == addr + 8
+    ));
+    let fault = machine.fault(err);
+    assert_eq!((fault.code(), fault.context.pc), (210, CODE));
+
+    // MAP_FIXED
+    mmap(&mut machine, addr, 0x32);
+    assert_eq!(load(&mut machine, addr + 8).unwrap(), 0);
+}
+
+#[test]
+fn heap_given_back_is_poisoned() {
+    let mut machine = machine(Poison::Trap);
+    syscall(&mut machine, Sysno::brk, &[HEAP + 0x2800]);
+    syscall(&mut machine, Sysno::brk, &[HEAP + 0x800]);
+
+    // The page the break is in is still the guest's
+    assert_eq!(load(&mut machine, HEAP + 0x7fc).unwrap(), 0);
+    assert!(load(&mut machine, HEAP + 0x1000).is_err());
+    assert!(load(&mut machine, HEAP + 0x2000).is_err());
+
+    syscall(&mut machine, Sysno::brk, &[HEAP + 0x3000]);
+    assert_eq!(load(&mut machine, HEAP + 0x2000).unwrap(), 0);
+}
@@ -0,0 +1,105 @@
+//! `prctl` process attributes: the name, truncated like the kernel's and
+//! shown in `/proc/self/status`, and the flags that only go one way.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const NAME: u32 = 0x1000;
+const PATH: u32 = 0x2000;
+const BUF: u32 = 0x3000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+fn prctl(machine: &mut Machine<MockLinux>, option: u32, args: &[u32]) -> i32 {
+    let args: Vec<u32> = [option].iter().chain(args).copied().collect();
+    syscall(machine, Sysno::prctl, &args)
+}
+
+fn get_name(machine: &mut Machine<MockLinux>) -> Vec<u8> {
+    machine.mem.memset(NAME, 0xff, 16).unwrap();
+    assert_eq!(prctl(machine, libc_riscv32::PR_GET_NAME, &[NAME]), 0);
+    machine.mem.slice::<u8>(NAME, 16).unwrap().to_vec()
+}
+
+/// `/proc/self/status` field `name`.
+fn status(machine: &mut Machine<MockLinux>, name: &str) -> String {
+    machine.mem.write_cstr(PATH, b"/proc/self/status").unwrap();
+    let fd = syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]);
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    syscall(machine, Sysno::close, &[fd as u32]);
+    let status = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    let status = String::from_utf8(status.to_vec()).unwrap();
+    let line = status.lines().find(|line| line.starts_with(name)).unwrap();
+    line.split_once('\t').unwrap().1.to_string()
+}
+
+#[test]
+fn names_are_truncated_to_15_bytes() {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+
+    machine.mem.write_cstr(NAME, b"worker").unwrap();
+    assert_eq!(prctl(&mut machine, libc_riscv32::PR_SET_NAME, &[NAME]), 0);
+    assert_eq!(get_name(&mut machine), b"worker\0\0\0\0\0\0\0\0\0\0");
+    assert_eq!(status(&mut machine, "Name:"), "worker");
+
+    machine
+        .mem
+        .copy_to(NAME, b"a-much-longer-thread-name")
+        .unwrap();
+    assert_eq!(prctl(&mut machine, libc_riscv32::PR_SET_NAME, &[NAME]), 0);
+    assert_eq!(get_name(&mut machine), b"a-much-longer-t\0");
+    assert_eq!(status(&mut machine, "Name:"), "a-much-longer-t");
+
+    let efault = -libc_riscv32::EFAULT;
+    for option in [libc_riscv32::PR_SET_NAME, libc_riscv32::PR_GET_NAME] {
+        assert_eq!(prctl(&mut machine, option, &[u32::MAX - 8]), efault);
+    }
+}
+
+#[test]
+fn no_new_privs_sticks() {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    let (set, get) = (
+        libc_riscv32::PR_SET_NO_NEW_PRIVS,
+        libc_riscv32::PR_GET_NO_NEW_PRIVS,
+    );
+    let einval = -libc_riscv32::EINVAL;
+
+    assert_eq!(prctl(&mut machine, get, &[0, 0, 0, 0]), 0);
+    assert_eq!(prctl(&mut machine, set, &[0, 0, 0, 0]), einval);
+    assert_eq!(prctl(&mut machine, set, &[1, 0, 0, 1]), einval);
+    assert_eq!(prctl(&mut machine, set, &[1, 0, 0, 0]), 0);
+    assert_eq!(prctl(&mut machine, get, &[0, 0, 0, 0]), 1);
+    assert_eq!(prctl(&mut machine, get, &[1, 0, 0, 0]), einval);
+    assert_eq!(status(&mut machine, "NoNewPrivs:"), "1");
+}
+
+#[test]
+fn dumpable_and_pdeathsig_round_trip() {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    let einval = -libc_riscv32::EINVAL;
+
+    assert_eq!(prctl(&mut machine, libc_riscv32::PR_GET_DUMPABLE, &[]), 1);
+    assert_eq!(prctl(&mut machine, libc_riscv32::PR_SET_DUMPABLE, &[0]), 0);
+    assert_eq!(prctl(&mut machine, libc_riscv32::PR_GET_DUMPABLE, &[]), 0);
+    assert_eq!(
+        prctl(&mut machine, libc_riscv32::PR_SET_DUMPABLE, &[2]),
+        einval
+    );
+
+    assert_eq!(prctl(&mut machine, libc_riscv32::PR_SET_PDEATHSIG, &[9]), 0);
+    assert_eq!(
+        prctl(&mut machine, libc_riscv32::PR_GET_PDEATHSIG, &[BUF]),
+        0
+    );
+    assert_eq!(machine.mem.load::<u32>(BUF), 9);
+    assert_eq!(
+        prctl(&mut machine, libc_riscv32::PR_SET_PDEATHSIG, &[65]),
+        einval
+    );
+
+    assert_eq!(prctl(&mut machine, 9999, &[]), einval);
+}
@@ -0,0 +1,73 @@
+//! Page permissions from `mmap` and `mprotect`, and guests stopped for
+//! asking for writable code under W^X.
+
+mod common;
+
+use common::try_syscall as syscall;
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::{
+    error::{MachineError, MemoryError},
+    machine::{Machine, MachineConfig},
+    protect::{ExecProtection, PagePerms},
+};
+use syscalls::riscv32::Sysno;
+
+const PROT_RW: u32 = 3;
+const PROT_RX: u32 = 5;
+const PROT_RWX: u32 = 7;
+const MAP_PRIVATE_ANONYMOUS: u32 = 0x22;
+
+fn machine(mode: ExecProtection) -> Machine<MockLinux> {
+    let config = MachineConfig {
+        exec_protection: mode,
+        ..Default::default()
+    };
+    Machine::with_config(MockLinux::builder().build().unwrap(), config)
+}
+
+fn perms(machine: &Machine<MockLinux>, addr: u32) -> PagePerms {
+    machine.mem.protection.as_ref().unwrap().get(addr)
+}
+
+fn map(machine: &mut Machine<MockLinux>, prot: u32) -> u32 {
+    let args = [0, 0x2000, prot, MAP_PRIVATE_ANONYMOUS, -1i32 as u32, 0];
+    syscall(machine, Sysno::mmap, &args).unwrap() as u32
+}
+
+#[test]
+fn mappings_get_their_permissions() {
+    let mut machine = machine(ExecProtection::WriteXorExecute);
+    let addr = map(&mut machine, PROT_RW);
+    assert_eq!(perms(&machine, addr), PagePerms::DATA);
+
+    // Code written, then made executable, as JITs do under W^X
+    let ret = syscall(&mut machine, Sysno::mprotect, &[addr, 0x1000, PROT_RX]);
+    assert_eq!(ret.unwrap(), 0);
+    assert_eq!(perms(&machine, addr), PagePerms::CODE);
+    assert_eq!(perms(&machine, addr + 0x1000), PagePerms::DATA);
+}
+
+#[test]
+fn writable_code_stops_the_guest() {
+    let mut machine = machine(ExecProtection::WriteXorExecute);
+    let addr = map(&mut machine, PROT_RW);
+    for (sysno, args) in [
+        (Sysno::mprotect, vec![addr, 0x1000, PROT_RWX]),
+        (
+            Sysno::mmap,
+            vec![0, 0x1000, PROT_RWX, MAP_PRIVATE_ANONYMOUS, -1i32 as u32, 0],
+        ),
+    ] {
+        let err = syscall(&mut machine, sysno, &args).unwrap_err();
+        assert!(matches!(
+            err,
+            MachineError::Memory(MemoryError::WriteXorExecute { .. })
+        ));
+    }
+    assert_eq!(perms(&machine, addr), PagePerms::DATA);
+
+    // Which is fine without W^X
+    let mut machine = self::machine(ExecProtection::Regions);
+    let addr = map(&mut machine, PROT_RWX);
+    assert_eq!(perms(&machine, addr), PagePerms::ALL);
+}
@@ -0,0 +1,66 @@
+//! Host names the guest resolves through `/etc/hosts`, with no network.
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use common::syscall;
+use riscv_kernel_linux::{HostTable, MockLinux, SystemInfo};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+fn read(machine: &mut Machine<MockLinux>, path: &str) -> String {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    let fd = syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]);
+    assert!(fd >= 0, "{path}: {fd}");
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    syscall(machine, Sysno::close, &[fd as u32]);
+    let bytes = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn hosts_lists_the_table() {
+    let db = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5));
+    let hosts: HostTable = [
+        ("db.internal", db),
+        ("api.example.com", IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        ("db.internal", IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6))),
+    ]
+    .into_iter()
+    .collect();
+    let kernel = MockLinux::builder()
+        .system(SystemInfo {
+            nodename: "builder".to_string(),
+            ..SystemInfo::default()
+        })
+        .hosts(hosts)
+        .build()
+        .unwrap();
+    assert_eq!(
+        kernel.hosts().lookup("db.internal"),
+        [db, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6))]
+    );
+    let mut machine = Machine::new(kernel);
+
+    let hosts = read(&mut machine, "/etc/hosts");
+    let lines: Vec<&str> = hosts.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "127.0.0.1\tlocalhost",
+            "::1\tlocalhost ip6-localhost ip6-loopback",
+            "127.0.1.1\tbuilder",
+            "10.0.0.5\tdb.internal",
+            "::1\tapi.example.com",
+            "10.0.0.6\tdb.internal",
+        ]
+    );
+    assert_eq!(read(&mut machine, "/etc/hostname"), "builder\n");
+    assert!(read(&mut machine, "/etc/nsswitch.conf").contains("hosts: files\n"));
+    assert!(!read(&mut machine, "/etc/resolv.conf").contains("nameserver"));
+}
@@ -0,0 +1,188 @@
+//! The scheduler syscalls and `riscv_hwprobe` agree on the configured CPU
+//! topology, and refuse what an unprivileged process couldn't do.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{CpuTopology, MockLinux};
+use riscv_vm::{machine::Machine, riscv_inst::Extensions};
+use syscalls::riscv32::Sysno;
+
+const MASK: u32 = 0x1000;
+const PARAM: u32 = 0x2000;
+const PAIRS: u32 = 0x3000;
+
+/// A guest told it has 40 RV32IMAC CPUs.
+fn machine() -> Machine<MockLinux> {
+    let topology = CpuTopology {
+        cpus: 40,
+        isa: Extensions::RV32IMAC,
+    };
+    Machine::new(MockLinux::builder().topology(topology).build().unwrap())
+}
+
+#[test]
+fn affinity_covers_every_cpu() {
+    let mut machine = machine();
+    let pid = syscall(&mut machine, Sysno::getpid, &[]) as u32;
+
+    for who in [0, pid] {
+        machine.mem.memset(MASK, 0, 16).unwrap();
+        // Two 32-bit longs' worth
+        let len = syscall(&mut machine, Sysno::sched_getaffinity, &[who, 16, MASK]);
+        assert_eq!(len, 8);
+        let mask = machine.mem.slice::<u8>(MASK, 16).unwrap();
+        assert_eq!(
+            mask,
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    // Any online CPU is fine to be pinned to
+    machine.mem.copy_to(MASK, &[0u8, 0, 0, 0, 0x80]).unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::sched_setaffinity, &[0, 5, MASK]),
+        0
+    );
+    machine.mem.copy_to(MASK, &[0u8, 0, 0, 0, 0, 1]).unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::sched_setaffinity, &[0, 6, MASK]),
+        -libc_riscv32::EINVAL
+    );
+
+    assert_eq!(syscall(&mut machine, Sysno::sched_yield, &[]), 0);
+    machine.mem.write(PARAM, [7u32, 7]).unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::getcpu, &[PARAM, PARAM + 4, 0]),
+        0
+    );
+    assert_eq!(machine.mem.load::<[u32; 2]>(PARAM), [0, 0]);
+}
+
+#[test]
+fn bad_affinity_calls_fail_with_errno() {
+    let mut machine = machine();
+    let einval = -libc_riscv32::EINVAL;
+    // Too small for 40 CPUs, and not a whole number of longs
+    for len in [4, 6] {
+        assert_eq!(
+            syscall(&mut machine, Sysno::sched_getaffinity, &[0, len, MASK]),
+            einval
+        );
+    }
+    for call in [Sysno::sched_getaffinity, Sysno::sched_setaffinity] {
+        assert_eq!(
+            syscall(&mut machine, call, &[12345, 8, MASK]),
+            -libc_riscv32::ESRCH
+        );
+        assert_eq!(
+            syscall(&mut machine, call, &[0, 8, u32::MAX - 4]),
+            -libc_riscv32::EFAULT
+        );
+    }
+}
+
+#[test]
+fn only_unprivileged_policies_can_be_set() {
+    let mut machine = machine();
+    let setscheduler = |machine: &mut Machine<MockLinux>, policy: u32, priority: i32| {
+        machine.mem.write(PARAM, priority).unwrap();
+        syscall(machine, Sysno::sched_setscheduler, &[0, policy, PARAM])
+    };
+
+    assert_eq!(
+        syscall(&mut machine, Sysno::sched_getscheduler, &[0]),
+        libc_riscv32::SCHED_OTHER as i32
+    );
+    assert_eq!(setscheduler(&mut machine, libc_riscv32::SCHED_BATCH, 0), 0);
+    assert_eq!(
+        setscheduler(&mut machine, libc_riscv32::SCHED_OTHER, 5),
+        -libc_riscv32::EINVAL
+    );
+    assert_eq!(
+        setscheduler(&mut machine, libc_riscv32::SCHED_FIFO, 10),
+        -libc_riscv32::EPERM
+    );
+    assert_eq!(setscheduler(&mut machine, 42, 0), -libc_riscv32::EINVAL);
+    assert_eq!(
+        syscall(&mut machine, Sysno::sched_getscheduler, &[12345]),
+        -libc_riscv32::ESRCH
+    );
+
+    machine.mem.write(PARAM, 7i32).unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::sched_getparam, &[0, PARAM]), 0);
+    assert_eq!(machine.mem.load::<i32>(PARAM), 0);
+
+    let range = |machine: &mut Machine<MockLinux>, policy: u32| {
+        (
+            syscall(machine, Sysno::sched_get_priority_min, &[policy]),
+            syscall(machine, Sysno::sched_get_priority_max, &[policy]),
+        )
+    };
+    assert_eq!(range(&mut machine, libc_riscv32::SCHED_RR), (1, 99));
+    assert_eq!(range(&mut machine, libc_riscv32::SCHED_IDLE), (0, 0));
+    let einval = -libc_riscv32::EINVAL;
+    assert_eq!(range(&mut machine, 42), (einval, einval));
+}
+
+#[test]
+fn hwprobe_describes_the_isa() {
+    let mut machine = machine();
+    let unknown = 1000i64;
+    machine
+        .mem
+        .write(
+            PAIRS,
+            [
+                libc_riscv32::RISCV_HWPROBE_KEY_IMA_EXT_0,
+                0,
+                libc_riscv32::RISCV_HWPROBE_KEY_BASE_BEHAVIOR,
+                0,
+                unknown,
+                0,
+            ],
+        )
+        .unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::riscv_hwprobe, &[PAIRS, 3, 0, 0, 0]),
+        0
+    );
+    let pairs = machine.mem.load::<[i64; 6]>(PAIRS);
+    assert_eq!(
+        pairs,
+        [
+            libc_riscv32::RISCV_HWPROBE_KEY_IMA_EXT_0,
+            libc_riscv32::RISCV_HWPROBE_IMA_C as i64,
+            libc_riscv32::RISCV_HWPROBE_KEY_BASE_BEHAVIOR,
+            libc_riscv32::RISCV_HWPROBE_BASE_BEHAVIOR_IMA as i64,
+            -1,
+            0,
+        ]
+    );
+
+    // Asking which CPUs match a pair the ISA doesn't: none do
+    machine
+        .mem
+        .write(
+            PAIRS,
+            [
+                libc_riscv32::RISCV_HWPROBE_KEY_IMA_EXT_0,
+                libc_riscv32::RISCV_HWPROBE_IMA_FD as i64,
+            ],
+        )
+        .unwrap();
+    machine.mem.memset(MASK, 0xff, 8).unwrap();
+    let which = libc_riscv32::RISCV_HWPROBE_WHICH_CPUS;
+    let args = [PAIRS, 1, 8, MASK, which];
+    assert_eq!(syscall(&mut machine, Sysno::riscv_hwprobe, &args), 0);
+    assert_eq!(machine.mem.load::<u64>(MASK), 0);
+
+    assert_eq!(
+        syscall(&mut machine, Sysno::riscv_hwprobe, &[PAIRS, 1, 0, 0, 0x100]),
+        -libc_riscv32::EINVAL
+    );
+    assert_eq!(
+        syscall(&mut machine, Sysno::riscv_hwprobe, &[PAIRS, 1, 8, 0, 0]),
+        -libc_riscv32::EFAULT
+    );
+}
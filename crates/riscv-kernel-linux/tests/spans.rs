@@ -0,0 +1,95 @@
+//! What's logged while handling a syscall is in a `syscall` span naming
+//! it, and the machine logs halting and quotas outside of any.
+
+mod common;
+
+use common::{syscall, LogEvent, Logs};
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::{
+    machine::{Machine, MachineConfig},
+    quota::Quotas,
+};
+use syscalls::riscv32::Sysno;
+use tracing::Level;
+
+const CODE: u32 = 0x1000;
+const PATH: u32 = 0x8000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+/// Exits with status 3.
+const EXIT: [u32; 3] = [
+    0x0030_0513, // li a0, 3
+    0x05e0_0893, // li a7, 94
+    0x0000_0073, // ecall
+];
+
+fn machine(quotas: Quotas) -> Machine<MockLinux> {
+    let config = MachineConfig {
+        quotas,
+        ..Default::default()
+    };
+    let kernel = MockLinux::builder().build().unwrap();
+    let mut machine = Machine::with_config(kernel, config);
+    machine.mem.copy_to(CODE, &EXIT).unwrap();
+    machine.hart.pc = CODE;
+    machine
+}
+
+/// The event logged with `message`.
+fn find(events: &[LogEvent], message: &str) -> LogEvent {
+    let event = events.iter().find(|event| event.message == message);
+    event
+        .unwrap_or_else(|| panic!("no {message:?} in {events:#?}"))
+        .clone()
+}
+
+#[test]
+fn syscall_events_are_in_its_span() {
+    let mut machine = machine(Quotas::default());
+    machine.mem.write_cstr(PATH, b"/missing").unwrap();
+    let logs = Logs::default();
+    logs.capture(|| {
+        syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]);
+        syscall(&mut machine, Sysno::close, &[99]);
+    });
+    let events = logs.events();
+
+    let span = "syscall{name=openat pc=0x1000}";
+    let opened = find(&events, "openat: \"/missing\" flags=0o0 mode=0o0");
+    assert_eq!(opened.spans, [span]);
+    let traced = find(
+        &events,
+        "openat(dirfd=-100, pathname=0x8000, flags=0x0, mode=0x0) = -1 ENOENT",
+    );
+    assert_eq!(
+        (traced.level, traced.spans),
+        (Level::DEBUG, vec![span.to_string()])
+    );
+    let closed = find(&events, "close(fd=99) = -1 EBADF");
+    assert_eq!(closed.spans, ["syscall{name=close pc=0x1000}"]);
+}
+
+#[test]
+fn halting_is_logged_after_the_syscall() {
+    let mut machine = machine(Quotas::default());
+    let logs = Logs::default();
+    logs.capture(|| machine.run()).unwrap();
+    let events = logs.events();
+
+    let exited = find(&events, "exit_group(status=3) = ?");
+    assert_eq!(exited.spans, ["syscall{name=exit_group pc=0x1008}"]);
+    let halted = find(&events, "machine halted");
+    assert_eq!((halted.level, halted.spans), (Level::DEBUG, vec![]));
+}
+
+#[test]
+fn exceeded_quotas_are_warned_about() {
+    let mut machine = machine(Quotas {
+        instructions: Some(1),
+        ..Default::default()
+    });
+    let logs = Logs::default();
+    assert!(logs.capture(|| machine.run()).is_err());
+    let warned = find(&logs.events(), "instruction quota exceeded");
+    assert_eq!((warned.level, warned.spans), (Level::WARN, vec![]));
+}
@@ -0,0 +1,45 @@
+//! Each syscall is logged like strace, with its arguments named and
+//! formatted from the syscall table and errors by their errno name.
+
+mod common;
+
+use common::{syscall, try_syscall, Logs};
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+/// The strace lines among what `f` logged.
+fn strace(f: impl FnOnce()) -> Vec<String> {
+    let logs = Logs::default();
+    logs.capture(f);
+    let lines = logs.messages().into_iter();
+    lines.filter(|line| line.contains(") = ")).collect()
+}
+
+#[test]
+fn calls_are_logged_with_named_arguments() {
+    let mut machine = Machine::new(MockLinux::builder().build().unwrap());
+    machine.mem.write_cstr(PATH, b"/missing").unwrap();
+    let mut pid = 0;
+    let lines = strace(|| {
+        pid = syscall(&mut machine, Sysno::getpid, &[]);
+        syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, 0o101, 0o644]);
+        syscall(&mut machine, Sysno::close, &[99]);
+        try_syscall(&mut machine, Sysno::exit_group, &[3]).unwrap();
+    });
+    assert!(machine.kernel.exit_status().is_some());
+
+    assert_eq!(lines.len(), 4, "{lines:#?}");
+    assert_eq!(lines[0], format!("getpid() = {pid}"));
+    // Descriptors signed, flags in hex
+    assert_eq!(
+        lines[1],
+        "openat(dirfd=-100, pathname=0x1000, flags=0x41, mode=0x1a4) = -1 ENOENT"
+    );
+    assert_eq!(lines[2], "close(fd=99) = -1 EBADF");
+    // Nothing returns from exit
+    assert_eq!(lines[3], "exit_group(status=3) = ?");
+}
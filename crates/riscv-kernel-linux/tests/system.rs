@@ -0,0 +1,71 @@
+//! What `uname` and `sysinfo` tell the guest about the machine.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{MockLinux, SystemInfo};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const BUF: u32 = 0x1000;
+const UTS_LEN: u32 = 65;
+
+fn machine(system: SystemInfo) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder().system(system).build().unwrap();
+    Machine::new(kernel)
+}
+
+/// The `utsname` fields the guest got.
+fn uname(machine: &mut Machine<MockLinux>) -> Vec<String> {
+    assert_eq!(syscall(machine, Sysno::uname, &[BUF]), 0);
+    (0..6)
+        .map(|n| {
+            let field = machine.mem.slice::<u8>(BUF + n * UTS_LEN, UTS_LEN).unwrap();
+            let len = field.iter().position(|&b| b == 0).unwrap();
+            String::from_utf8(field[..len].to_vec()).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn uname_reports_the_configured_system() {
+    let mut machine = machine(SystemInfo {
+        nodename: "builder".to_string(),
+        release: "5.15.0".to_string(),
+        ..SystemInfo::default()
+    });
+    assert_eq!(
+        uname(&mut machine),
+        ["Linux", "builder", "5.15.0", "#1 SMP", "riscv32", "(none)"]
+    );
+
+    // Names too long for the struct are cut short
+    let mut machine = self::machine(SystemInfo {
+        nodename: "x".repeat(100),
+        ..SystemInfo::default()
+    });
+    assert_eq!(uname(&mut machine)[1], "x".repeat(64));
+}
+
+#[test]
+fn sysinfo_reports_guest_memory() {
+    let mut machine = machine(SystemInfo {
+        total_ram: 256 << 20,
+        ..SystemInfo::default()
+    });
+    machine.mem.memset(0x10_0000, 1, 0x4000).unwrap();
+    assert_eq!(syscall(&mut machine, Sysno::sysinfo, &[BUF]), 0);
+    let [totalram, freeram]: [u32; 2] = machine.mem.load(BUF + 16);
+    let procs: u16 = machine.mem.load(BUF + 40);
+    let mem_unit: u32 = machine.mem.load(BUF + 52);
+    assert_eq!((totalram, mem_unit, procs), (256 << 20, 1, 1));
+    let used = totalram - freeram;
+    assert!((0x4000..0x10_0000).contains(&used), "{used:#x} bytes used");
+
+    // Counted in pages when bytes would overflow
+    let mut machine = self::machine(SystemInfo::default());
+    assert_eq!(syscall(&mut machine, Sysno::sysinfo, &[BUF]), 0);
+    let totalram: u32 = machine.mem.load(BUF + 16);
+    let mem_unit: u32 = machine.mem.load(BUF + 52);
+    assert_eq!((totalram, mem_unit), (1 << 20, 4096));
+}
@@ -0,0 +1,130 @@
+//! The 64-bit time syscalls on a deterministic clock: `pselect6` waiting out
+//! its timeout or returning the ready descriptors, and `clock_nanosleep`
+//! sleeping until an absolute deadline.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{ClockMode, MockLinux, SyscallCosts, Timespec64, VirtualClock};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const READ_SET: u32 = 0x1000;
+const WRITE_SET: u32 = 0x1100;
+const TIMESPEC: u32 = 0x2000;
+
+const MS: u64 = 1_000_000;
+
+fn machine() -> Machine<MockLinux> {
+    let mut clock = VirtualClock::new(ClockMode::Deterministic);
+    clock.costs = SyscallCosts::zero();
+    Machine::new(MockLinux::builder().clock(clock).build().unwrap())
+}
+
+fn now(machine: &mut Machine<MockLinux>, clockid: u32) -> u64 {
+    assert_eq!(
+        syscall(machine, Sysno::clock_gettime64, &[clockid, TIMESPEC]),
+        0
+    );
+    machine.mem.load::<Timespec64>(TIMESPEC).to_ns().unwrap()
+}
+
+fn sleep_until(machine: &mut Machine<MockLinux>, clockid: u32, deadline: u64) -> i32 {
+    machine
+        .mem
+        .write(TIMESPEC, Timespec64::from_ns(deadline))
+        .unwrap();
+    let abstime = libc_riscv32::TIMER_ABSTIME;
+    syscall(
+        machine,
+        Sysno::clock_nanosleep_time64,
+        &[clockid, abstime, TIMESPEC, 0],
+    )
+}
+
+/// `pselect6` on fds `0..nfds`, with a read and a write set and a timeout.
+fn pselect(machine: &mut Machine<MockLinux>, nfds: i32, sets: [u32; 2], timeout: u64) -> i32 {
+    machine.mem.write(READ_SET, sets[0]).unwrap();
+    machine.mem.write(WRITE_SET, sets[1]).unwrap();
+    machine
+        .mem
+        .write(TIMESPEC, Timespec64::from_ns(timeout))
+        .unwrap();
+    let args = [nfds as u32, READ_SET, WRITE_SET, 0, TIMESPEC, 0];
+    syscall(machine, Sysno::pselect6_time64, &args)
+}
+
+#[test]
+fn pselect6_waits_out_its_timeout() {
+    let mut machine = machine();
+    let fd = syscall(&mut machine, Sysno::eventfd2, &[0, 0]);
+    let bit = 1 << fd;
+    let start = now(&mut machine, libc_riscv32::CLOCK_MONOTONIC);
+
+    // Nothing to read from an empty eventfd
+    assert_eq!(pselect(&mut machine, fd + 1, [bit, 0], 5 * MS), 0);
+    assert_eq!(machine.mem.load::<u32>(READ_SET), 0);
+    let waited = now(&mut machine, libc_riscv32::CLOCK_MONOTONIC) - start;
+    assert_eq!(waited, 5 * MS);
+}
+
+#[test]
+fn pselect6_returns_the_ready_fds() {
+    let mut machine = machine();
+    let fd = syscall(&mut machine, Sysno::eventfd2, &[0, 0]);
+    let bit = 1 << fd;
+    let start = now(&mut machine, libc_riscv32::CLOCK_MONOTONIC);
+
+    // Always writable, and readable once it counts
+    assert_eq!(pselect(&mut machine, fd + 1, [bit, bit], 5 * MS), 1);
+    assert_eq!(machine.mem.load::<u32>(READ_SET), 0);
+    assert_eq!(machine.mem.load::<u32>(WRITE_SET), bit);
+    machine.mem.write(TIMESPEC, 1u64).unwrap();
+    assert_eq!(
+        syscall(&mut machine, Sysno::write, &[fd as u32, TIMESPEC, 8]),
+        8
+    );
+    assert_eq!(pselect(&mut machine, fd + 1, [bit, bit], 5 * MS), 2);
+    assert_eq!(machine.mem.load::<u32>(READ_SET), bit);
+    assert_eq!(machine.mem.load::<u32>(WRITE_SET), bit);
+    // Without waiting
+    assert_eq!(now(&mut machine, libc_riscv32::CLOCK_MONOTONIC), start);
+}
+
+#[test]
+fn pselect6_rejects_bad_fds() {
+    let mut machine = machine();
+    // Up to the descriptor limit
+    assert_eq!(pselect(&mut machine, 1024, [0, 0], 0), 0);
+    for nfds in [1025, -1] {
+        assert_eq!(
+            pselect(&mut machine, nfds, [0, 0], 0),
+            -libc_riscv32::EINVAL
+        );
+    }
+    // Not open
+    assert_eq!(
+        pselect(&mut machine, 8, [1 << 7, 0], 0),
+        -libc_riscv32::EBADF
+    );
+}
+
+#[test]
+fn clock_nanosleep_sleeps_until_an_absolute_deadline() {
+    let mut machine = machine();
+    let monotonic = libc_riscv32::CLOCK_MONOTONIC;
+    let start = now(&mut machine, monotonic);
+
+    assert_eq!(sleep_until(&mut machine, monotonic, start + 1_000 * MS), 0);
+    assert_eq!(now(&mut machine, monotonic), start + 1_000 * MS);
+    // A deadline that has passed doesn't sleep at all
+    assert_eq!(sleep_until(&mut machine, monotonic, start), 0);
+    assert_eq!(now(&mut machine, monotonic), start + 1_000 * MS);
+
+    // Or by the wall clock
+    let realtime = libc_riscv32::CLOCK_REALTIME;
+    let wall = now(&mut machine, realtime);
+    assert_eq!(sleep_until(&mut machine, realtime, wall + 2_000 * MS), 0);
+    assert_eq!(now(&mut machine, realtime), wall + 2_000 * MS);
+    assert_eq!(now(&mut machine, monotonic), start + 3_000 * MS);
+}
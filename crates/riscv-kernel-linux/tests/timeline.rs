@@ -0,0 +1,98 @@
+//! The timeline of heap and mapping changes, recorded from syscalls made
+//! straight through the syscall entry.
+
+mod common;
+
+use riscv_kernel_linux::{MockLinux, RegionChange, RegionEvent};
+use riscv_vm::{machine::Machine, riscv_inst::Reg, symbols::SymbolTable};
+use syscalls::riscv32::Sysno;
+
+const ECALL: u32 = 0x1234;
+const CALLER: u32 = 0x5678;
+const HEAP: u32 = 0x10000;
+
+fn syscall(machine: &mut Machine<MockLinux>, sysno: Sysno, args: &[u32]) -> i32 {
+    machine.hart.pc = ECALL;
+    machine.hart.set_reg(Reg::Ra, CALLER);
+    common::syscall(machine, sysno, args)
+}
+
+fn machine() -> Machine<MockLinux> {
+    let kernel = MockLinux::builder().memory_timeline(true).build().unwrap();
+    let mut machine = Machine::new(kernel);
+    machine.mem.brk = HEAP;
+    machine
+}
+
+#[test]
+fn records_successful_changes() {
+    let mut machine = machine();
+    // Queries and failures change nothing
+    syscall(&mut machine, Sysno::brk, &[0]);
+    assert!(syscall(&mut machine, Sysno::munmap, &[0x1001, 0x1000]) < 0);
+    machine.hart.inst_count = 7;
+    syscall(&mut machine, Sysno::brk, &[HEAP + 0x2000]);
+    let map = syscall(
+        &mut machine,
+        Sysno::mmap,
+        &[0, 5000, 3, 0x22, -1i32 as u32, 0],
+    ) as u32;
+    syscall(&mut machine, Sysno::munmap, &[map, 0x1000]);
+
+    let events = machine.kernel.memory_timeline.as_ref().unwrap().events();
+    let changes: Vec<_> = events
+        .iter()
+        .map(|event| (event.change, event.addr, event.size))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (RegionChange::Brk, 0, HEAP + 0x2000),
+            (RegionChange::Mmap, map, 0x2000),
+            (RegionChange::Munmap, map, 0x1000),
+        ]
+    );
+    assert_eq!(
+        events[0],
+        RegionEvent {
+            change: RegionChange::Brk,
+            inst_count: 7,
+            addr: 0,
+            size: HEAP + 0x2000,
+            pc: ECALL,
+            caller: CALLER,
+        }
+    );
+}
+
+#[test]
+fn writes_json_with_callers() {
+    let mut machine = machine();
+    syscall(&mut machine, Sysno::brk, &[HEAP + 0x1000]);
+    syscall(
+        &mut machine,
+        Sysno::mmap,
+        &[0, 0x1000, 3, 0x22, -1i32 as u32, 0],
+    );
+    let mut symbols = SymbolTable::new();
+    symbols.insert("malloc", 0x5600, 0x100);
+
+    let mut json = vec![];
+    let timeline = machine.kernel.memory_timeline.as_ref().unwrap();
+    timeline.write_json(&mut json, &symbols).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    let lines: Vec<_> = json.lines().collect();
+    assert_eq!(lines.len(), 4, "{json}");
+    assert_eq!((lines[0], lines[3]), ("[", "]"));
+    assert!(lines[1].starts_with(r#"  {"kind": "brk", "inst_count": 0,"#));
+    assert!(lines[1].ends_with(r#""caller_symbol": "malloc+0x78"},"#));
+    assert!(lines[2].starts_with(r#"  {"kind": "mmap""#));
+    assert!(lines[2].ends_with('}'));
+}
+
+#[test]
+fn off_by_default() {
+    let mut machine = Machine::new(MockLinux::new(false));
+    syscall(&mut machine, Sysno::brk, &[HEAP]);
+    assert!(machine.kernel.memory_timeline.is_none());
+}
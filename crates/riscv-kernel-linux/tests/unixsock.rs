@@ -0,0 +1,249 @@
+//! UNIX domain sockets, connected to each other inside the VM.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const BUF: u32 = 0x1000;
+const ADDR: u32 = 0x2000;
+const ADDRLEN: u32 = 0x2100;
+const FDS: u32 = 0x3000;
+
+fn machine() -> Machine<MockLinux> {
+    Machine::new(MockLinux::builder().build().unwrap())
+}
+
+fn socket(machine: &mut Machine<MockLinux>, ty: u32) -> u32 {
+    let fd = syscall(machine, Sysno::socket, &[libc_riscv32::AF_UNIX, ty, 0]);
+    assert!(fd >= 0);
+    fd as u32
+}
+
+/// Write a `sockaddr_un` for `path` at `ADDR`, returning its length.
+fn sockaddr(machine: &mut Machine<MockLinux>, path: &[u8]) -> u32 {
+    let family = (libc_riscv32::AF_UNIX as u16).to_ne_bytes();
+    let addr = [&family, path, b"\0"].concat();
+    machine.mem.copy_to(ADDR, &addr).unwrap();
+    addr.len() as u32
+}
+
+/// The `sun_path` written at `ADDR`, with its length at `ADDRLEN`.
+fn written_path(machine: &Machine<MockLinux>) -> Vec<u8> {
+    let len: u32 = machine.mem.load(ADDRLEN);
+    let path = machine.mem.slice::<u8>(ADDR + 2, len - 2).unwrap();
+    path.strip_suffix(b"\0").unwrap_or(path).to_vec()
+}
+
+fn send(machine: &mut Machine<MockLinux>, fd: u32, data: &[u8]) -> i32 {
+    machine.mem.copy_to(BUF, data).unwrap();
+    syscall(machine, Sysno::write, &[fd, BUF, data.len() as u32])
+}
+
+fn recv(machine: &mut Machine<MockLinux>, fd: u32, len: u32) -> Result<Vec<u8>, i32> {
+    let n = syscall(machine, Sysno::read, &[fd, BUF, len]);
+    if n < 0 {
+        return Err(-n);
+    }
+    Ok(machine.mem.slice::<u8>(BUF, n as u32).unwrap().to_vec())
+}
+
+/// The events `fd` is ready for, polling without waiting.
+fn poll(machine: &mut Machine<MockLinux>, fd: u32) -> u16 {
+    let events = libc_riscv32::POLLIN | libc_riscv32::POLLOUT | libc_riscv32::POLLRDHUP;
+    machine.mem.copy_to(FDS, &[fd, events as u32]).unwrap();
+    machine.mem.copy_to(FDS + 8, &[0u32; 4]).unwrap();
+    syscall(machine, Sysno::ppoll_time64, &[FDS, 1, FDS + 8, 0, 8]);
+    machine.mem.load(FDS + 6)
+}
+
+#[test]
+fn socketpairs_carry_streams_until_closed() {
+    let mut machine = machine();
+    let ty = libc_riscv32::SOCK_STREAM | libc_riscv32::SOCK_CLOEXEC;
+    assert_eq!(
+        syscall(
+            &mut machine,
+            Sysno::socketpair,
+            &[libc_riscv32::AF_UNIX, ty, 0, FDS]
+        ),
+        0
+    );
+    let [a, b]: [u32; 2] = machine.mem.load(FDS);
+
+    assert_eq!(recv(&mut machine, b, 16), Err(libc_riscv32::EAGAIN));
+    assert_eq!(send(&mut machine, a, b"hello, "), 7);
+    assert_eq!(send(&mut machine, a, b"world"), 5);
+    assert_eq!(
+        poll(&mut machine, b),
+        libc_riscv32::POLLIN | libc_riscv32::POLLOUT
+    );
+    // Streams read across writes
+    assert_eq!(recv(&mut machine, b, 9).unwrap(), b"hello, wo");
+    assert_eq!(recv(&mut machine, b, 16).unwrap(), b"rld");
+    assert_eq!(send(&mut machine, b, b"back"), 4);
+    assert_eq!(recv(&mut machine, a, 16).unwrap(), b"back");
+
+    syscall(&mut machine, Sysno::close, &[a]);
+    let hup = libc_riscv32::POLLIN | libc_riscv32::POLLRDHUP | libc_riscv32::POLLHUP;
+    assert_eq!(poll(&mut machine, b) & hup, hup);
+    assert_eq!(recv(&mut machine, b, 16).unwrap(), b"");
+    assert_eq!(send(&mut machine, b, b"gone"), -libc_riscv32::EPIPE);
+
+    // There's no network
+    let inet = [libc_riscv32::AF_INET, libc_riscv32::SOCK_STREAM, 0];
+    assert_eq!(
+        syscall(&mut machine, Sysno::socket, &inet),
+        -libc_riscv32::EAFNOSUPPORT
+    );
+}
+
+#[test]
+fn listeners_accept_connections() {
+    let mut machine = machine();
+    let server = socket(&mut machine, libc_riscv32::SOCK_STREAM);
+    let client = socket(&mut machine, libc_riscv32::SOCK_STREAM);
+
+    let len = sockaddr(&mut machine, b"/tmp/server.sock");
+    assert_eq!(
+        syscall(&mut machine, Sysno::connect, &[client, ADDR, len]),
+        -libc_riscv32::ENOENT
+    );
+    assert_eq!(syscall(&mut machine, Sysno::bind, &[server, ADDR, len]), 0);
+    // Bound but not listening
+    assert_eq!(
+        syscall(&mut machine, Sysno::connect, &[client, ADDR, len]),
+        -libc_riscv32::ECONNREFUSED
+    );
+    assert_eq!(syscall(&mut machine, Sysno::listen, &[server, 8]), 0);
+    assert_eq!(poll(&mut machine, server), 0);
+    assert_eq!(
+        syscall(&mut machine, Sysno::connect, &[client, ADDR, len]),
+        0
+    );
+    assert_eq!(poll(&mut machine, server), libc_riscv32::POLLIN);
+
+    machine.mem.store(ADDRLEN, 110u32);
+    let accept = [server, ADDR, ADDRLEN, libc_riscv32::SOCK_NONBLOCK];
+    let conn = syscall(&mut machine, Sysno::accept4, &accept) as u32;
+    // The client never bound, so is unnamed
+    assert_eq!(machine.mem.load::<u32>(ADDRLEN), 2);
+    assert_eq!(
+        syscall(&mut machine, Sysno::accept4, &accept),
+        -libc_riscv32::EAGAIN
+    );
+
+    assert_eq!(send(&mut machine, client, b"ping"), 4);
+    assert_eq!(recv(&mut machine, conn, 16).unwrap(), b"ping");
+    assert_eq!(send(&mut machine, conn, b"pong"), 4);
+    assert_eq!(recv(&mut machine, client, 16).unwrap(), b"pong");
+
+    machine.mem.store(ADDRLEN, 110u32);
+    let peer = [client, ADDR, ADDRLEN];
+    assert_eq!(syscall(&mut machine, Sysno::getpeername, &peer), 0);
+    assert_eq!(written_path(&machine), b"/tmp/server.sock");
+
+    // The name is taken until the listener is closed
+    let other = socket(&mut machine, libc_riscv32::SOCK_STREAM);
+    let len = sockaddr(&mut machine, b"/tmp/server.sock");
+    assert_eq!(
+        syscall(&mut machine, Sysno::bind, &[other, ADDR, len]),
+        -libc_riscv32::EADDRINUSE
+    );
+    syscall(&mut machine, Sysno::close, &[server]);
+    assert_eq!(syscall(&mut machine, Sysno::bind, &[other, ADDR, len]), 0);
+}
+
+#[test]
+fn datagrams_keep_their_boundaries() {
+    let mut machine = machine();
+    let a = socket(&mut machine, libc_riscv32::SOCK_DGRAM);
+    let b = socket(&mut machine, libc_riscv32::SOCK_DGRAM);
+    let a_len = sockaddr(&mut machine, b"\0a");
+    assert_eq!(syscall(&mut machine, Sysno::bind, &[a, ADDR, a_len - 1]), 0);
+    let b_len = sockaddr(&mut machine, b"\0b");
+    assert_eq!(syscall(&mut machine, Sysno::bind, &[b, ADDR, b_len - 1]), 0);
+
+    // `b` is still at `ADDR`
+    for message in [&b"one"[..], b"three"] {
+        machine.mem.copy_to(BUF, message).unwrap();
+        let sendto = [a, BUF, message.len() as u32, 0, ADDR, b_len - 1];
+        assert_eq!(
+            syscall(&mut machine, Sysno::sendto, &sendto),
+            message.len() as i32
+        );
+    }
+    assert_eq!(recv(&mut machine, b, 2).unwrap(), b"on");
+
+    machine.mem.store(ADDRLEN, 110u32);
+    let recvfrom = [b, BUF, 16, 0, ADDR, ADDRLEN];
+    assert_eq!(syscall(&mut machine, Sysno::recvfrom, &recvfrom), 5);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 5).unwrap(), b"three");
+    assert_eq!(written_path(&machine), b"\0a");
+
+    // Unconnected sockets need somewhere to send
+    assert_eq!(send(&mut machine, b, b"x"), -libc_riscv32::EDESTADDRREQ);
+    let a_len = sockaddr(&mut machine, b"\0a");
+    let connect = [b, ADDR, a_len - 1];
+    assert_eq!(syscall(&mut machine, Sysno::connect, &connect), 0);
+    assert_eq!(send(&mut machine, b, b"reply"), 5);
+    assert_eq!(recv(&mut machine, a, 16).unwrap(), b"reply");
+
+    syscall(&mut machine, Sysno::close, &[a]);
+    assert_eq!(send(&mut machine, b, b"x"), -libc_riscv32::ECONNREFUSED);
+}
+
+#[test]
+fn msghdr_iovecs_are_bounded() {
+    const MSG: u32 = 0x4000;
+    const IOV: u32 = 0x4100;
+    let mut machine = machine();
+    syscall(
+        &mut machine,
+        Sysno::socketpair,
+        &[libc_riscv32::AF_UNIX, libc_riscv32::SOCK_DGRAM, 0, FDS],
+    );
+    let [a, b]: [u32; 2] = machine.mem.read(FDS).unwrap();
+    let msghdr = |machine: &mut Machine<MockLinux>, iovlen: u32| {
+        machine
+            .mem
+            .copy_to(MSG, &[0, 0, IOV, iovlen, 0, 0, 0])
+            .unwrap();
+    };
+
+    // Gathered from two iovecs into one datagram
+    machine.mem.copy_to(BUF, b"hello, world").unwrap();
+    machine.mem.copy_to(IOV, &[BUF, 5, BUF + 5, 7]).unwrap();
+    msghdr(&mut machine, 2);
+    assert_eq!(syscall(&mut machine, Sysno::sendmsg, &[a, MSG, 0]), 12);
+
+    // A guest can ask for far more than is waiting without the host
+    // allocating it
+    machine.mem.copy_to(BUF, &[0u8; 12]).unwrap();
+    machine.mem.copy_to(IOV, &[BUF, 0x4000_0000]).unwrap();
+    msghdr(&mut machine, 1);
+    assert_eq!(syscall(&mut machine, Sysno::recvmsg, &[b, MSG, 0]), 12);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 12).unwrap(), b"hello, world");
+
+    // But not more than an ssize_t in all, or more than IOV_MAX iovecs
+    machine
+        .mem
+        .copy_to(IOV, &[BUF, 0x4000_0000, BUF, 0x4000_0000])
+        .unwrap();
+    msghdr(&mut machine, 2);
+    for sysno in [Sysno::sendmsg, Sysno::recvmsg] {
+        assert_eq!(
+            syscall(&mut machine, sysno, &[a, MSG, 0]),
+            -libc_riscv32::EINVAL
+        );
+    }
+    msghdr(&mut machine, libc_riscv32::IOV_MAX + 1);
+    for sysno in [Sysno::sendmsg, Sysno::recvmsg] {
+        assert_eq!(
+            syscall(&mut machine, sysno, &[a, MSG, 0]),
+            -libc_riscv32::EINVAL
+        );
+    }
+}
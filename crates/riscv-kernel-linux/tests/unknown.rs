@@ -0,0 +1,78 @@
+//! Syscalls the kernel doesn't implement fail with `ENOSYS` and the guest
+//! carries on, unless the policy is to stop the machine there.
+
+use riscv_kernel_linux::{LinuxError, MockLinux, UnknownSyscallPolicy};
+use riscv_vm::{
+    error::MachineError,
+    machine::{Kernel, Machine},
+    riscv_inst::Reg,
+};
+
+const CODE: u32 = 0x1000;
+
+/// Makes syscall 217, `add_key`, keeps what it returned in `s0` and exits.
+const ADD_KEY: [u32; 6] = [
+    0x0d90_0893, // li a7, 217
+    0x0000_0073, // ecall
+    0x0005_0413, // mv s0, a0
+    0x0000_0513, // li a0, 0
+    0x05d0_0893, // li a7, 93
+    0x0000_0073, // ecall
+];
+
+fn machine(policy: UnknownSyscallPolicy) -> Machine<MockLinux> {
+    let kernel = MockLinux::builder()
+        .unknown_syscalls(policy)
+        .build()
+        .unwrap();
+    let mut machine = Machine::new(kernel);
+    machine.mem.copy_to(CODE, &ADD_KEY).unwrap();
+    machine.hart.pc = CODE;
+    machine
+}
+
+#[test]
+fn enosys_and_log_let_the_guest_carry_on() {
+    for policy in [UnknownSyscallPolicy::Enosys, UnknownSyscallPolicy::Log] {
+        let mut machine = machine(policy);
+        machine.run().unwrap();
+        assert_eq!(machine.hart.get_reg(Reg::S0), -libc_riscv32::ENOSYS as u32);
+        assert_eq!(machine.kernel.exit_status().unwrap().code, 0);
+
+        // Numbers that aren't syscalls at all too
+        let Machine {
+            hart, mem, kernel, ..
+        } = &mut machine;
+        hart.set_reg(Reg::A7, 9999);
+        kernel.syscall(hart, mem).unwrap();
+        assert_eq!(hart.return_value(), -libc_riscv32::ENOSYS as u32);
+    }
+}
+
+#[test]
+fn abort_stops_at_the_syscall() {
+    let mut machine = machine(UnknownSyscallPolicy::Abort);
+    match machine.run() {
+        Err(MachineError::Kernel(LinuxError::UnknownSyscall { nr, pc })) => {
+            assert_eq!((nr, pc), (217, CODE + 4));
+        }
+        res => panic!("expected an unknown syscall, got {res:?}"),
+    }
+    assert_eq!(machine.kernel.exit_status(), None);
+    assert_eq!(machine.hart.get_reg(Reg::S0), 0);
+}
+
+#[test]
+fn policies_parse_from_their_names() {
+    for policy in [
+        UnknownSyscallPolicy::Enosys,
+        UnknownSyscallPolicy::Log,
+        UnknownSyscallPolicy::Abort,
+    ] {
+        assert_eq!(policy.to_string().parse(), Ok(policy));
+    }
+    assert_eq!(
+        "panic".parse::<UnknownSyscallPolicy>(),
+        Err("unknown syscall policy \"panic\", expected enosys, log or abort".to_string())
+    );
+}
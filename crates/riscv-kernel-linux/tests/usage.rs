@@ -0,0 +1,94 @@
+//! `getrusage` and `/proc/self/status`, reporting the guest's time from the
+//! virtual clock and its memory from the pages it touched.
+
+mod common;
+
+use common::syscall;
+use riscv_kernel_linux::{ClockMode, MockLinux, SyscallCosts, VirtualClock};
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const BUF: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+/// A machine 1.5 seconds into the guest, charged 1.5ms per `getrusage`.
+fn machine() -> Machine<MockLinux> {
+    let mut clock = VirtualClock::new(ClockMode::Deterministic);
+    clock.costs = SyscallCosts::zero();
+    clock.costs.set(Sysno::getrusage, 1_500_000);
+    let mut machine = Machine::new(MockLinux::builder().clock(clock).build().unwrap());
+    machine.hart.inst_count = 1_500_000_000;
+    machine
+}
+
+/// `ru_utime`, `ru_stime` and `ru_maxrss` from `getrusage(who)`.
+fn getrusage(machine: &mut Machine<MockLinux>, who: i32) -> ([i32; 2], [i32; 2], i32) {
+    assert_eq!(syscall(machine, Sysno::getrusage, &[who as u32, BUF]), 0);
+    let field = |n: u32| machine.mem.load::<i32>(BUF + 4 * n);
+    ([field(0), field(1)], [field(2), field(3)], field(4))
+}
+
+#[test]
+fn getrusage_reports_virtual_time_and_peak_memory() {
+    let mut machine = machine();
+    machine.mem.memset(0x10_0000, 1, 0x4000).unwrap();
+
+    let (utime, stime, maxrss) = getrusage(&mut machine, libc_riscv32::RUSAGE_SELF);
+    assert_eq!(utime, [1, 500_000]);
+    // This call included
+    assert_eq!(stime, [0, 1500]);
+    assert!(maxrss >= 16, "{maxrss} kB");
+
+    // The only thread is the whole process
+    let (utime, stime, thread_rss) = getrusage(&mut machine, libc_riscv32::RUSAGE_THREAD);
+    assert_eq!((utime, stime), ([1, 500_000], [0, 3000]));
+    assert!(thread_rss >= maxrss);
+
+    // And it has no children
+    let children = getrusage(&mut machine, libc_riscv32::RUSAGE_CHILDREN);
+    assert_eq!(children, ([0; 2], [0; 2], 0));
+}
+
+#[test]
+fn bad_getrusage_calls_fail_with_errno() {
+    let mut machine = machine();
+    assert_eq!(
+        syscall(&mut machine, Sysno::getrusage, &[2, BUF]),
+        -libc_riscv32::EINVAL
+    );
+    assert_eq!(
+        syscall(&mut machine, Sysno::getrusage, &[0, u32::MAX - 8]),
+        -libc_riscv32::EFAULT
+    );
+}
+
+#[test]
+fn proc_self_status_describes_the_process() {
+    let mut machine = machine();
+    machine.mem.memset(0x10_0000, 1, 0x4000).unwrap();
+    machine.mem.write_cstr(PATH, b"/proc/self/status").unwrap();
+    let fd = syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, 0, 0]);
+    assert!(fd >= 0, "{fd}");
+    let len = syscall(&mut machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    let status = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    let status = String::from_utf8(status.to_vec()).unwrap();
+
+    let field = |name: &str| {
+        let line = status.lines().find(|line| line.starts_with(name));
+        line.unwrap().split_once('\t').unwrap().1.trim().to_string()
+    };
+    let pid = syscall(&mut machine, Sysno::getpid, &[]);
+    assert_eq!(field("Pid:"), pid.to_string());
+    assert_eq!(field("Threads:"), "1");
+    let kb = |name: &str| field(name).trim_end_matches(" kB").parse::<u64>().unwrap();
+    assert!(kb("VmRSS:") >= 16);
+    assert!(kb("VmHWM:") >= kb("VmRSS:"));
+
+    // Read only
+    let flags = libc_riscv32::O_WRONLY;
+    assert_eq!(
+        syscall(&mut machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0]),
+        -libc_riscv32::EACCES
+    );
+}
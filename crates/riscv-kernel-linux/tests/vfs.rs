@@ -0,0 +1,205 @@
+//! Creating, removing and renaming directory entries, and unnamed
+//! `O_TMPFILE` files, in the in-memory root and in host mounts.
+
+mod common;
+
+use common::{cpio, syscall, HostDir};
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::machine::Machine;
+use syscalls::riscv32::Sysno;
+
+const PATH: u32 = 0x1000;
+const PATH2: u32 = 0x1800;
+const BUF: u32 = 0x2000;
+const AT_FDCWD: u32 = -100i32 as u32;
+
+fn machine(host: &HostDir) -> Machine<MockLinux> {
+    let root = cpio(&[
+        ("etc", 0o040755, b""),
+        ("etc/motd", 0o100644, b"hi\n"),
+        ("tmp", 0o040755, b""),
+    ]);
+    let kernel = MockLinux::builder()
+        .mount_archive(root)
+        .mount_host("/data", &host.0, false)
+        .build()
+        .unwrap();
+    Machine::new(kernel)
+}
+
+fn mkdir(machine: &mut Machine<MockLinux>, path: &str) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::mkdirat, &[AT_FDCWD, PATH, 0o755])
+}
+
+fn unlink(machine: &mut Machine<MockLinux>, path: &str, flags: u32) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::unlinkat, &[AT_FDCWD, PATH, flags])
+}
+
+fn rename(machine: &mut Machine<MockLinux>, from: &str, to: &str, flags: u32) -> i32 {
+    machine.mem.write_cstr(PATH, from.as_bytes()).unwrap();
+    machine.mem.write_cstr(PATH2, to.as_bytes()).unwrap();
+    let args = [AT_FDCWD, PATH, AT_FDCWD, PATH2, flags];
+    syscall(machine, Sysno::renameat2, &args)
+}
+
+fn open(machine: &mut Machine<MockLinux>, path: &str, flags: u32) -> i32 {
+    machine.mem.write_cstr(PATH, path.as_bytes()).unwrap();
+    syscall(machine, Sysno::openat, &[AT_FDCWD, PATH, flags, 0o644])
+}
+
+/// The contents of `path`, or the error opening it.
+fn read(machine: &mut Machine<MockLinux>, path: &str) -> Result<String, i32> {
+    let fd = open(machine, path, 0);
+    if fd < 0 {
+        return Err(fd);
+    }
+    let len = syscall(machine, Sysno::read, &[fd as u32, BUF, 0x1000]);
+    syscall(machine, Sysno::close, &[fd as u32]);
+    let bytes = machine.mem.slice::<u8>(BUF, len as u32).unwrap();
+    Ok(String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+fn create(machine: &mut Machine<MockLinux>, path: &str, contents: &str) {
+    let flags = libc_riscv32::O_WRONLY | libc_riscv32::O_CREAT;
+    let fd = open(machine, path, flags);
+    assert!(fd >= 0, "creating {path}: {fd}");
+    machine.mem.copy_to(BUF, contents.as_bytes()).unwrap();
+    let len = contents.len() as u32;
+    assert_eq!(
+        syscall(machine, Sysno::write, &[fd as u32, BUF, len]),
+        len as i32
+    );
+    syscall(machine, Sysno::close, &[fd as u32]);
+}
+
+#[test]
+fn directories_are_made_and_removed() {
+    let host = HostDir::new("vfs-dirs", &[]);
+    let mut machine = machine(&host);
+    let (eexist, enoent) = (-libc_riscv32::EEXIST, -libc_riscv32::ENOENT);
+    let removedir = libc_riscv32::AT_REMOVEDIR;
+
+    for root in ["/tmp", "/data"] {
+        let dir = format!("{root}/dir");
+        assert_eq!(mkdir(&mut machine, &dir), 0);
+        assert_eq!(mkdir(&mut machine, &dir), eexist);
+        assert_eq!(mkdir(&mut machine, &format!("{root}/no/dir")), enoent);
+
+        let file = format!("{dir}/file");
+        create(&mut machine, &file, "x");
+        assert_eq!(
+            unlink(&mut machine, &dir, removedir),
+            -libc_riscv32::ENOTEMPTY
+        );
+        assert_eq!(unlink(&mut machine, &dir, 0), -libc_riscv32::EISDIR);
+        assert_eq!(
+            unlink(&mut machine, &file, removedir),
+            -libc_riscv32::ENOTDIR
+        );
+        assert_eq!(unlink(&mut machine, &file, 0), 0);
+        assert_eq!(read(&mut machine, &file), Err(enoent));
+        assert_eq!(unlink(&mut machine, &dir, removedir), 0);
+        assert_eq!(unlink(&mut machine, &dir, removedir), enoent);
+    }
+    assert!(!host.0.join("dir").exists());
+
+    assert_eq!(unlink(&mut machine, "/tmp", 0x1), -libc_riscv32::EINVAL);
+    // Mount points stay put
+    assert_eq!(mkdir(&mut machine, "/data"), eexist);
+    assert_eq!(
+        unlink(&mut machine, "/data", removedir),
+        -libc_riscv32::EBUSY
+    );
+}
+
+#[test]
+fn renames_move_replace_and_exchange() {
+    let host = HostDir::new("vfs-rename", &[]);
+    let mut machine = machine(&host);
+    create(&mut machine, "/tmp/a", "a");
+    create(&mut machine, "/tmp/b", "b");
+
+    assert_eq!(rename(&mut machine, "/tmp/a", "/tmp/c", 0), 0);
+    assert_eq!(read(&mut machine, "/tmp/a"), Err(-libc_riscv32::ENOENT));
+    assert_eq!(read(&mut machine, "/tmp/c").unwrap(), "a");
+
+    let noreplace = libc_riscv32::RENAME_NOREPLACE;
+    assert_eq!(
+        rename(&mut machine, "/tmp/c", "/tmp/b", noreplace),
+        -libc_riscv32::EEXIST
+    );
+    let exchange = libc_riscv32::RENAME_EXCHANGE;
+    assert_eq!(rename(&mut machine, "/tmp/c", "/tmp/b", exchange), 0);
+    assert_eq!(read(&mut machine, "/tmp/b").unwrap(), "a");
+    assert_eq!(read(&mut machine, "/tmp/c").unwrap(), "b");
+    assert_eq!(
+        rename(&mut machine, "/tmp/c", "/tmp/a", exchange),
+        -libc_riscv32::ENOENT
+    );
+    assert_eq!(
+        rename(&mut machine, "/tmp/c", "/tmp/b", exchange | noreplace),
+        -libc_riscv32::EINVAL
+    );
+    // Replacing drops what was there
+    assert_eq!(rename(&mut machine, "/tmp/c", "/tmp/b", 0), 0);
+    assert_eq!(read(&mut machine, "/tmp/b").unwrap(), "b");
+
+    // Directories move with what's in them, but not into themselves
+    assert_eq!(mkdir(&mut machine, "/tmp/dir"), 0);
+    create(&mut machine, "/tmp/dir/f", "f");
+    assert_eq!(rename(&mut machine, "/tmp/dir", "/etc/dir", 0), 0);
+    assert_eq!(read(&mut machine, "/etc/dir/f").unwrap(), "f");
+    assert_eq!(
+        rename(&mut machine, "/etc/dir", "/etc/dir/sub", 0),
+        -libc_riscv32::EINVAL
+    );
+    assert_eq!(
+        rename(&mut machine, "/etc/dir", "/etc", 0),
+        -libc_riscv32::ENOTEMPTY
+    );
+
+    // Not across mounts
+    assert_eq!(
+        rename(&mut machine, "/tmp/b", "/data/b", 0),
+        -libc_riscv32::EXDEV
+    );
+    create(&mut machine, "/data/x", "x");
+    assert_eq!(rename(&mut machine, "/data/x", "/data/y", 0), 0);
+    assert_eq!(std::fs::read_to_string(host.0.join("y")).unwrap(), "x");
+    assert!(!host.0.join("x").exists());
+}
+
+#[test]
+fn tmpfiles_have_no_name() {
+    let host = HostDir::new("vfs-tmpfile", &[]);
+    let mut machine = machine(&host);
+    let tmpfile = libc_riscv32::O_TMPFILE;
+
+    for dir in ["/tmp", "/data"] {
+        let fd = open(&mut machine, dir, tmpfile | libc_riscv32::O_RDWR);
+        assert!(fd >= 0, "{dir}: {fd}");
+        let fd = fd as u32;
+        machine.mem.copy_to(BUF, b"scratch").unwrap();
+        assert_eq!(syscall(&mut machine, Sysno::write, &[fd, BUF, 7]), 7);
+        syscall(&mut machine, Sysno::lseek, &[fd, 0, 0, BUF + 0x100, 0]);
+        machine.mem.copy_to(BUF, &[0; 7]).unwrap();
+        assert_eq!(syscall(&mut machine, Sysno::read, &[fd, BUF, 7]), 7);
+        assert_eq!(machine.mem.slice::<u8>(BUF, 7).unwrap(), b"scratch");
+        assert_eq!(syscall(&mut machine, Sysno::close, &[fd]), 0);
+    }
+    assert_eq!(std::fs::read_dir(&host.0).unwrap().count(), 0);
+
+    // It needs to be writable, and in a directory
+    assert_eq!(open(&mut machine, "/tmp", tmpfile), -libc_riscv32::EINVAL);
+    let wronly = tmpfile | libc_riscv32::O_WRONLY;
+    assert_eq!(
+        open(&mut machine, "/etc/motd", wronly),
+        -libc_riscv32::ENOTDIR
+    );
+    assert_eq!(
+        open(&mut machine, "/tmp/none", wronly),
+        -libc_riscv32::ENOENT
+    );
+}
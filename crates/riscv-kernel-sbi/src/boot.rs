@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+use goblin::elf::{
+    program_header::{PF_W, PF_X, PT_LOAD},
+    Elf,
+};
+use riscv_vm::{hart::Hart32, memory::Memory, protect::PagePerms, riscv_inst::Reg};
+
+use crate::{fdt::Fdt, BootError};
+
+/// Start of RAM, matching QEMU's `virt` machine.
+pub const RAM_BASE: u32 = 0x8000_0000;
+/// Where the device tree goes, past the end of any kernel that fits.
+pub const DTB_ADDR: u32 = 0x8220_0000;
+/// Where the initrd goes, leaving the kernel room to grow past its image.
+pub const INITRD_ADDR: u32 = 0x8800_0000;
+/// End of the RAM the loader will use.
+const RAM_END: u32 = 0xC000_0000;
+
+/// Used when the header's `text_offset` is 0, as in the rv32 default config.
+const DEFAULT_TEXT_OFFSET: u32 = 0x40_0000;
+
+// Image header fields
+const HEADER_LEN: usize = 64;
+const TEXT_OFFSET: usize = 8;
+const MAGIC: usize = 48;
+const MAGIC2: usize = 56;
+
+/// Where [`load_linux`] put everything.
+#[derive(Debug, Clone)]
+pub struct BootLayout {
+    pub kernel: Range<u32>,
+    pub dtb: Range<u32>,
+    pub initrd: Option<Range<u32>>,
+}
+
+/// Check the header of a RISC-V Linux `Image` and return its load address.
+fn image_load_addr(image: &[u8]) -> Result<u32, BootError> {
+    if image.len() < HEADER_LEN {
+        return Err(BootError::BadImage);
+    }
+    let magic = &image[MAGIC..MAGIC + 8] == b"RISCV\0\0\0";
+    let magic2 = &image[MAGIC2..MAGIC2 + 4] == b"RSC\x05";
+    if !magic && !magic2 {
+        return Err(BootError::BadImage);
+    }
+
+    let text_offset = u64::from_le_bytes(image[TEXT_OFFSET..TEXT_OFFSET + 8].try_into().unwrap());
+    match text_offset {
+        0 => Ok(RAM_BASE + DEFAULT_TEXT_OFFSET),
+        offset if offset < (DTB_ADDR - RAM_BASE) as u64 => Ok(RAM_BASE + offset as u32),
+        _ => Err(BootError::BadImage),
+    }
+}
+
+/// Copy `bytes` to `addr`, failing if they'd run past `limit`.
+fn place(
+    mem: &mut Memory,
+    what: &'static str,
+    addr: u32,
+    bytes: &[u8],
+    limit: u32,
+) -> Result<Range<u32>, BootError> {
+    let too_large = || BootError::TooLarge {
+        what,
+        len: bytes.len(),
+        limit,
+    };
+    let len = u32::try_from(bytes.len()).map_err(|_| too_large())?;
+    match addr.checked_add(len) {
+        Some(end) if end <= limit => {
+            mem.copy_to(addr, bytes)?;
+            Ok(addr..end)
+        }
+        _ => Err(too_large()),
+    }
+}
+
+/// Load a Linux kernel `Image`, device tree and optional initrd the way a
+/// bootloader would, and point the hart at the kernel.
+///
+/// The kernel goes at [`RAM_BASE`] plus the `text_offset` from its header, the
+/// device tree at [`DTB_ADDR`], and the initrd at [`INITRD_ADDR`]. The initrd's
+/// location and `bootargs`, if given, are written into the tree's `/chosen`
+/// node. The kernel is entered with the hart ID in `a0` and the device tree
+/// address in `a1`, as the boot protocol requires.
+pub fn load_linux(
+    hart: &mut Hart32,
+    mem: &mut Memory,
+    image: &[u8],
+    dtb: &[u8],
+    initrd: Option<&[u8]>,
+    bootargs: Option<&str>,
+) -> Result<BootLayout, BootError> {
+    let kernel_addr = image_load_addr(image)?;
+    let kernel = place(mem, "Kernel image", kernel_addr, image, DTB_ADDR)?;
+
+    let initrd = initrd
+        .map(|initrd| place(mem, "Initrd", INITRD_ADDR, initrd, RAM_END))
+        .transpose()?;
+
+    let mut fdt = Fdt::parse(dtb)?;
+    if let Some(initrd) = &initrd {
+        fdt.set_chosen("linux,initrd-start", &(initrd.start as u64).to_be_bytes())?;
+        fdt.set_chosen("linux,initrd-end", &(initrd.end as u64).to_be_bytes())?;
+    }
+    if let Some(bootargs) = bootargs {
+        let mut value = bootargs.as_bytes().to_vec();
+        value.push(0);
+        fdt.set_chosen("bootargs", &value)?;
+    }
+    let dtb = place(mem, "Device tree", DTB_ADDR, &fdt.to_bytes(), INITRD_ADDR)?;
+
+    hart.set_reg(Reg::A0, 0);
+    hart.set_reg(Reg::A1, dtb.start);
+    hart.pc = kernel.start;
+
+    Ok(BootLayout {
+        kernel,
+        dtb,
+        initrd,
+    })
+}
+
+/// Load a bare-metal ELF, such as a guest built to run on SBI firmware, and
+/// point the hart at its entry with the hart ID in `a0`. No device tree is
+/// passed, so `a1` is 0.
+pub fn load_elf<'a>(
+    hart: &mut Hart32,
+    mem: &mut Memory,
+    bytes: &'a [u8],
+) -> Result<Elf<'a>, BootError> {
+    let elf = Elf::parse(bytes)?;
+    for ph in elf.program_headers.iter().filter(|ph| ph.p_type == PT_LOAD) {
+        let data = bytes
+            .get(ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize)
+            .ok_or_else(|| {
+                goblin::error::Error::Malformed("segment past end of file".to_string())
+            })?;
+        // BSS is already zero in fresh memory
+        mem.copy_to(ph.p_vaddr as u32, data)?;
+        let perms = PagePerms {
+            write: ph.p_flags & PF_W != 0,
+            exec: ph.p_flags & PF_X != 0,
+        };
+        mem.protect(ph.p_vaddr as u32, ph.p_memsz as u32, perms)?;
+    }
+
+    hart.set_reg(Reg::A0, 0);
+    hart.set_reg(Reg::A1, 0);
+    hart.pc = elf.entry as u32;
+
+    Ok(elf)
+}
@@ -0,0 +1,179 @@
+use crate::BootError;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+// Header fields, as big-endian u32s
+const TOTALSIZE: usize = 4;
+const OFF_DT_STRUCT: usize = 8;
+const OFF_DT_STRINGS: usize = 12;
+const SIZE_DT_STRINGS: usize = 32;
+const SIZE_DT_STRUCT: usize = 36;
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn set_be32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+fn align4(n: usize) -> usize {
+    n.next_multiple_of(4)
+}
+
+/// A flattened device tree, edited in place.
+///
+/// Only what the boot loader needs: setting properties of `/chosen`, which
+/// is created if it's missing. Blobs must have the usual layout, with the
+/// structure block before the strings block.
+#[derive(Debug, Clone)]
+pub struct Fdt {
+    /// Header and memory reservation map, up to the structure block
+    head: Vec<u8>,
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+impl Fdt {
+    pub fn parse(blob: &[u8]) -> Result<Self, BootError> {
+        let field = |offset| be32(blob, offset).ok_or(BootError::BadDtb);
+        if field(0)? != FDT_MAGIC {
+            return Err(BootError::BadDtb);
+        }
+        let off_struct = field(OFF_DT_STRUCT)? as usize;
+        let off_strings = field(OFF_DT_STRINGS)? as usize;
+        let size_struct = field(SIZE_DT_STRUCT)? as usize;
+        let size_strings = field(SIZE_DT_STRINGS)? as usize;
+        if off_struct + size_struct > off_strings || off_strings + size_strings > blob.len() {
+            return Err(BootError::BadDtb);
+        }
+
+        Ok(Self {
+            head: blob[..off_struct].to_vec(),
+            structure: blob[off_struct..off_struct + size_struct].to_vec(),
+            strings: blob[off_strings..off_strings + size_strings].to_vec(),
+        })
+    }
+
+    /// Serialize the tree, with the header updated to match.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut blob = self.head.clone();
+        let off_strings = blob.len() + self.structure.len();
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+
+        let total = blob.len() as u32;
+        set_be32(&mut blob, TOTALSIZE, total);
+        set_be32(&mut blob, OFF_DT_STRINGS, off_strings as u32);
+        set_be32(&mut blob, SIZE_DT_STRUCT, self.structure.len() as u32);
+        set_be32(&mut blob, SIZE_DT_STRINGS, self.strings.len() as u32);
+        blob
+    }
+
+    /// Offset of `name` in the strings block, adding it if needed.
+    fn string(&mut self, name: &str) -> u32 {
+        let mut offset = 0;
+        for s in self.strings.split(|&b| b == 0) {
+            if s == name.as_bytes() {
+                return offset as u32;
+            }
+            offset += s.len() + 1;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        offset
+    }
+
+    /// Walk the structure block, returning the offset just past the name of
+    /// `/chosen` and of the root node's `FDT_END_NODE`.
+    fn find_chosen(&self) -> Result<(Option<usize>, usize), BootError> {
+        let token = |offset| be32(&self.structure, offset).ok_or(BootError::BadDtb);
+        let mut offset = 0;
+        let mut depth = 0;
+        let mut chosen = None;
+        loop {
+            match token(offset)? {
+                FDT_BEGIN_NODE => {
+                    let name_start = offset + 4;
+                    let name_len = self.structure[name_start..]
+                        .iter()
+                        .position(|&b| b == 0)
+                        .ok_or(BootError::BadDtb)?;
+                    let name = &self.structure[name_start..name_start + name_len];
+                    offset = name_start + align4(name_len + 1);
+                    depth += 1;
+                    if depth == 2 && name == b"chosen" {
+                        chosen = Some(offset);
+                    }
+                }
+                FDT_END_NODE => {
+                    if depth == 1 {
+                        return Ok((chosen, offset));
+                    }
+                    depth -= 1;
+                    offset += 4;
+                }
+                FDT_PROP => {
+                    let len = token(offset + 4)? as usize;
+                    offset += 12 + align4(len);
+                }
+                FDT_NOP => offset += 4,
+                _ => return Err(BootError::BadDtb),
+            }
+        }
+    }
+
+    /// The range of property `name_offset` among the properties starting at `offset`.
+    fn find_prop(&self, mut offset: usize, name_offset: u32) -> Option<std::ops::Range<usize>> {
+        loop {
+            match be32(&self.structure, offset)? {
+                FDT_PROP => {
+                    let end = offset + 12 + align4(be32(&self.structure, offset + 4)? as usize);
+                    if be32(&self.structure, offset + 8)? == name_offset {
+                        return Some(offset..end);
+                    }
+                    offset = end;
+                }
+                FDT_NOP => offset += 4,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Set property `name` of `/chosen`, replacing it if it exists.
+    pub fn set_chosen(&mut self, name: &str, value: &[u8]) -> Result<(), BootError> {
+        let name_offset = self.string(name);
+        let mut prop = Vec::with_capacity(12 + align4(value.len()));
+        prop.extend_from_slice(&FDT_PROP.to_be_bytes());
+        prop.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        prop.extend_from_slice(&name_offset.to_be_bytes());
+        prop.extend_from_slice(value);
+        prop.resize(12 + align4(value.len()), 0);
+
+        match self.find_chosen()? {
+            (Some(offset), _) => {
+                // Properties come before subnodes, so right after the name is always valid
+                let range = self
+                    .find_prop(offset, name_offset)
+                    .unwrap_or(offset..offset);
+                self.structure.splice(range, prop);
+            }
+            (None, root_end) => {
+                let mut node = FDT_BEGIN_NODE.to_be_bytes().to_vec();
+                node.extend_from_slice(b"chosen\0\0");
+                node.extend_from_slice(&prop);
+                node.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+                self.structure.splice(root_end..root_end, node);
+            }
+        }
+        Ok(())
+    }
+}
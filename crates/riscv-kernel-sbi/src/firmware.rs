@@ -0,0 +1,289 @@
+use std::{
+    io::Write,
+    sync::mpsc::{Receiver, TryRecvError},
+};
+
+use riscv_vm::{
+    clint::CLINT_BASE,
+    csr::Csr,
+    error::MachineError,
+    hart::Hart32,
+    machine::{Kernel, StepResult},
+    memory::Memory,
+    quota::KernelUsage,
+    uart::spawn_stdin_reader,
+};
+
+// Legacy (v0.1) extension IDs, passed in a7
+const LEGACY_SET_TIMER: u32 = 0x00;
+const LEGACY_CONSOLE_PUTCHAR: u32 = 0x01;
+const LEGACY_CONSOLE_GETCHAR: u32 = 0x02;
+const LEGACY_CLEAR_IPI: u32 = 0x03;
+const LEGACY_SEND_IPI: u32 = 0x04;
+const LEGACY_REMOTE_FENCE_I: u32 = 0x05;
+const LEGACY_REMOTE_SFENCE_VMA: u32 = 0x06;
+const LEGACY_REMOTE_SFENCE_VMA_ASID: u32 = 0x07;
+const LEGACY_SHUTDOWN: u32 = 0x08;
+
+// Extension IDs, with the function ID in a6
+const EXT_BASE: u32 = 0x10;
+const EXT_TIME: u32 = 0x5449_4D45;
+const EXT_IPI: u32 = 0x0073_5049;
+const EXT_HSM: u32 = 0x0048_534D;
+
+// Base extension functions
+const BASE_GET_SPEC_VERSION: u32 = 0;
+const BASE_GET_IMPL_ID: u32 = 1;
+const BASE_GET_IMPL_VERSION: u32 = 2;
+const BASE_PROBE_EXTENSION: u32 = 3;
+const BASE_GET_MVENDORID: u32 = 4;
+const BASE_GET_MARCHID: u32 = 5;
+const BASE_GET_MIMPID: u32 = 6;
+
+// HSM extension functions
+const HSM_HART_START: u32 = 0;
+const HSM_HART_STOP: u32 = 1;
+const HSM_HART_GET_STATUS: u32 = 2;
+const HSM_HART_SUSPEND: u32 = 3;
+
+const HSM_STATUS_STARTED: u32 = 0;
+const HSM_SUSPEND_RETENTIVE: u32 = 0;
+
+/// SBI specification version 2.0, as `major << 24 | minor`.
+const SPEC_VERSION: u32 = 2 << 24;
+/// Implementation ID reported by `get_impl_id`, outside the range assigned
+/// to real firmware.
+pub const SBI_IMPL_ID: u32 = 0xDE15C;
+
+pub const SBI_SUCCESS: i32 = 0;
+pub const SBI_ERR_NOT_SUPPORTED: i32 = -2;
+pub const SBI_ERR_INVALID_PARAM: i32 = -3;
+pub const SBI_ERR_ALREADY_AVAILABLE: i32 = -6;
+
+// CLINT registers for hart 0
+const CLINT_MSIP: u32 = CLINT_BASE;
+const CLINT_MTIMECMP: u32 = CLINT_BASE + 0x4000;
+
+/// Supervisor binary interface firmware, answering the guest's `ecall`s the
+/// way OpenSBI would.
+///
+/// Implements the base, timer, IPI and hart state management extensions, and
+/// the legacy console, timer, IPI and shutdown calls. The timer and IPIs go
+/// through the CLINT at [`CLINT_BASE`], which must be attached for them to
+/// work. There's one hart, with ID 0.
+///
+/// There is no S-mode or MMU, so the guest runs in M-mode and takes the timer
+/// and software interrupts as M-mode interrupts.
+#[derive(Debug, Default)]
+pub struct SbiFirmware {
+    /// Started on the first `console_getchar`
+    stdin: Option<Receiver<u8>>,
+    /// Set by `shutdown` or `hart_stop`
+    shutdown: bool,
+    usage: KernelUsage,
+}
+
+/// Result of an SBI call, returned in `a0` and `a1`.
+type SbiRet = Result<u32, i32>;
+
+impl SbiFirmware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the guest shut the machine down, rather than stopping some other way.
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Write a CLINT register, warning if there's no CLINT to write to.
+    fn clint_write(mem: &mut Memory, addr: u32, value: u32) {
+        if !mem.mmio.write(addr, 4, value) {
+            tracing::warn!("SBI call needs a CLINT at {CLINT_BASE:#010x}, but none is attached");
+        }
+    }
+
+    fn set_timer(mem: &mut Memory, low: u32, high: u32) {
+        // Write the high half first so the timer can't fire early on the old high half
+        Self::clint_write(mem, CLINT_MTIMECMP + 4, u32::MAX);
+        Self::clint_write(mem, CLINT_MTIMECMP, low);
+        Self::clint_write(mem, CLINT_MTIMECMP + 4, high);
+    }
+
+    fn getchar(&mut self) -> i32 {
+        let stdin = self.stdin.get_or_insert_with(spawn_stdin_reader);
+        match stdin.try_recv() {
+            Ok(byte) => byte as i32,
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => -1,
+        }
+    }
+
+    fn is_supported(eid: u32) -> bool {
+        matches!(
+            eid,
+            LEGACY_SET_TIMER..=LEGACY_SHUTDOWN | EXT_BASE | EXT_TIME | EXT_IPI | EXT_HSM
+        )
+    }
+
+    /// Handle a legacy call, returning its result for `a0`, or `None` to halt.
+    fn legacy(&mut self, eid: u32, hart: &Hart32, mem: &mut Memory) -> Option<i32> {
+        let [a0, a1] = hart.args();
+        let ret = match eid {
+            LEGACY_SET_TIMER => {
+                Self::set_timer(mem, a0, a1);
+                0
+            }
+            LEGACY_CONSOLE_PUTCHAR => {
+                self.usage.bytes_written += 1;
+                let mut stdout = std::io::stdout().lock();
+                let _ = stdout.write_all(&[a0 as u8]).and_then(|_| stdout.flush());
+                0
+            }
+            LEGACY_CONSOLE_GETCHAR => {
+                let c = self.getchar();
+                self.usage.bytes_read += (c >= 0) as u64;
+                c
+            }
+            LEGACY_CLEAR_IPI => {
+                Self::clint_write(mem, CLINT_MSIP, 0);
+                0
+            }
+            LEGACY_SEND_IPI => {
+                // The hart mask is in guest memory; only hart 0 exists
+                if mem.load::<u32>(a0) & 1 != 0 {
+                    Self::clint_write(mem, CLINT_MSIP, 1);
+                }
+                0
+            }
+            // With one hart and no caches or TLB, there's nothing to fence
+            LEGACY_REMOTE_FENCE_I | LEGACY_REMOTE_SFENCE_VMA | LEGACY_REMOTE_SFENCE_VMA_ASID => 0,
+            LEGACY_SHUTDOWN => {
+                self.shutdown = true;
+                return None;
+            }
+            _ => SBI_ERR_NOT_SUPPORTED,
+        };
+        Some(ret)
+    }
+
+    fn base(&self, fid: u32, hart: &Hart32) -> SbiRet {
+        match fid {
+            BASE_GET_SPEC_VERSION => Ok(SPEC_VERSION),
+            BASE_GET_IMPL_ID => Ok(SBI_IMPL_ID),
+            BASE_GET_IMPL_VERSION => Ok(1),
+            BASE_PROBE_EXTENSION => Ok(Self::is_supported(hart.arg(0)) as u32),
+            BASE_GET_MVENDORID => Ok(hart.csr(Csr::Mvendorid)),
+            BASE_GET_MARCHID => Ok(hart.csr(Csr::Marchid)),
+            BASE_GET_MIMPID => Ok(hart.csr(Csr::Mimpid)),
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        }
+    }
+
+    fn send_ipi(mem: &mut Memory, hart_mask: u32, hart_mask_base: u32) -> SbiRet {
+        // A base of -1 means every hart, ignoring the mask
+        if hart_mask_base != u32::MAX {
+            let other_harts = match hart_mask_base {
+                0 => hart_mask & !1,
+                _ => hart_mask,
+            };
+            if other_harts != 0 {
+                return Err(SBI_ERR_INVALID_PARAM);
+            }
+            if hart_mask & 1 == 0 {
+                return Ok(0);
+            }
+        }
+        Self::clint_write(mem, CLINT_MSIP, 1);
+        Ok(0)
+    }
+
+    /// Handle a hart state management call, or `None` to halt.
+    fn hsm(&mut self, fid: u32, hart: &Hart32) -> Option<SbiRet> {
+        let hartid = hart.arg(0);
+        let ret = match fid {
+            // Hart 0 is the only hart, and it's already running
+            HSM_HART_START if hartid == 0 => Err(SBI_ERR_ALREADY_AVAILABLE),
+            HSM_HART_START => Err(SBI_ERR_INVALID_PARAM),
+            HSM_HART_STOP => {
+                // Nothing is left to run once the only hart stops
+                self.shutdown = true;
+                return None;
+            }
+            HSM_HART_GET_STATUS if hartid == 0 => Ok(HSM_STATUS_STARTED),
+            HSM_HART_GET_STATUS => Err(SBI_ERR_INVALID_PARAM),
+            // Retentive suspend is a `wfi`, which doesn't wait. Non-retentive
+            // suspend would resume elsewhere, which isn't supported.
+            HSM_HART_SUSPEND if hart.arg(0) == HSM_SUSPEND_RETENTIVE => Ok(0),
+            _ => Err(SBI_ERR_NOT_SUPPORTED),
+        };
+        Some(ret)
+    }
+}
+
+impl Kernel for SbiFirmware {
+    type Error = std::convert::Infallible;
+
+    fn syscall(
+        &mut self,
+        hart: &mut Hart32,
+        mem: &mut Memory,
+    ) -> Result<StepResult, MachineError<Self::Error>> {
+        let eid = hart.arg(7);
+        let fid = hart.arg(6);
+        let _span =
+            tracing::debug_span!("sbi", eid, fid, pc = format_args!("{:#x}", hart.pc)).entered();
+        self.usage.syscalls += 1;
+
+        if (LEGACY_SET_TIMER..=LEGACY_SHUTDOWN).contains(&eid) {
+            // Legacy calls return a single value in a0
+            let Some(ret) = self.legacy(eid, hart, mem) else {
+                return Ok(StepResult::Halt);
+            };
+            hart.set_return(ret as u32);
+            return Ok(StepResult::Ok);
+        }
+
+        let ret = match (eid, fid) {
+            (EXT_BASE, _) => self.base(fid, hart),
+            (EXT_TIME, 0) => {
+                Self::set_timer(mem, hart.arg(0), hart.arg(1));
+                Ok(0)
+            }
+            (EXT_IPI, 0) => Self::send_ipi(mem, hart.arg(0), hart.arg(1)),
+            (EXT_HSM, _) => match self.hsm(fid, hart) {
+                Some(ret) => ret,
+                None => return Ok(StepResult::Halt),
+            },
+            _ => {
+                tracing::debug!("Unsupported SBI call {eid:#x}/{fid} at {:#010x}", hart.pc);
+                Err(SBI_ERR_NOT_SUPPORTED)
+            }
+        };
+        let (error, value) = match ret {
+            Ok(value) => (SBI_SUCCESS, value),
+            Err(error) => (error, 0),
+        };
+        hart.set_return_pair(error as u32, value);
+
+        Ok(StepResult::Ok)
+    }
+
+    fn usage(&self) -> KernelUsage {
+        self.usage
+    }
+
+    /// Just the shutdown flag, since the timer and IPIs live in the CLINT
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(vec![self.shutdown as u8])
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), String> {
+        match state {
+            [shutdown] => {
+                self.shutdown = *shutdown != 0;
+                Ok(())
+            }
+            _ => Err(format!("expected 1 byte of SBI state, got {}", state.len())),
+        }
+    }
+}
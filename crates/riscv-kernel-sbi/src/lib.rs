@@ -0,0 +1,31 @@
+mod boot;
+mod fdt;
+mod firmware;
+
+pub use boot::{load_elf, load_linux, BootLayout, DTB_ADDR, INITRD_ADDR, RAM_BASE};
+pub use fdt::Fdt;
+pub use firmware::{
+    SbiFirmware, SBI_ERR_ALREADY_AVAILABLE, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED,
+    SBI_IMPL_ID, SBI_SUCCESS,
+};
+
+use riscv_vm::error::MemoryError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BootError {
+    #[error("Kernel image is not a RISC-V Linux Image")]
+    BadImage,
+    #[error("Failed to parse ELF: {0}")]
+    BadElf(#[from] goblin::error::Error),
+    #[error("Device tree blob is malformed")]
+    BadDtb,
+    #[error("{what} ({len} bytes) doesn't fit below {limit:#010x}")]
+    TooLarge {
+        what: &'static str,
+        len: usize,
+        limit: u32,
+    },
+    #[error("Memory error: {0}")]
+    Memory(#[from] MemoryError),
+}
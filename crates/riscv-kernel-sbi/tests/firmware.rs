@@ -0,0 +1,117 @@
+//! SBI calls made by a guest `ecall`: the base extension describing the
+//! firmware, the timer extension and legacy call arming the CLINT, and
+//! calls to extensions the firmware doesn't have.
+
+use riscv_kernel_sbi::{SbiFirmware, SBI_ERR_NOT_SUPPORTED, SBI_IMPL_ID, SBI_SUCCESS};
+use riscv_vm::{
+    clint::{Clint, CLINT_BASE},
+    machine::Machine,
+    riscv_inst::Reg,
+    trap::{Interrupt, CSR_MCAUSE, CSR_MIE, CSR_MSTATUS, CSR_MTVEC, MCAUSE_INTERRUPT, MSTATUS_MIE},
+};
+
+const CODE: u32 = 0x1000;
+const HANDLER: u32 = 0x2000;
+
+const ECALL: u32 = 0x0000_0073;
+const EBREAK: u32 = 0x0010_0073;
+/// j .
+const SPIN: u32 = 0x0000_006f;
+
+const LEGACY_SET_TIMER: u32 = 0x00;
+const EXT_BASE: u32 = 0x10;
+const EXT_TIME: u32 = 0x5449_4D45;
+const EXT_HSM: u32 = 0x0048_534D;
+/// Not an extension anyone has defined
+const EXT_UNKNOWN: u32 = 0x0BAD_0BAD;
+
+/// A machine with a CLINT ticking every instruction, about to run `program`.
+fn machine(program: &[u32]) -> Machine<SbiFirmware> {
+    let mut machine = Machine::new(SbiFirmware::new());
+    machine
+        .mem
+        .mmio
+        .attach(CLINT_BASE, Box::new(Clint::new(1)))
+        .unwrap();
+    machine.mem.copy_to(CODE, program).unwrap();
+    machine.hart.pc = CODE;
+    machine
+}
+
+/// Make SBI call `eid`/`fid` with `args` from the guest, returning `a0` and
+/// `a1`: the error and the value.
+fn call(eid: u32, fid: u32, args: &[u32]) -> (i32, u32) {
+    let mut machine = machine(&[ECALL, EBREAK]);
+    machine.hart.set_reg(Reg::A7, eid);
+    machine.hart.set_reg(Reg::A6, fid);
+    for (n, &arg) in args.iter().enumerate() {
+        machine.hart.set_arg(n, arg);
+    }
+    machine.run().unwrap();
+    assert_eq!(machine.hart.pc, CODE + 4);
+    let a0 = machine.hart.get_reg(Reg::A0);
+    (a0 as i32, machine.hart.get_reg(Reg::A1))
+}
+
+#[test]
+fn base_extension_describes_the_firmware() {
+    // SBI 2.0
+    assert_eq!(call(EXT_BASE, 0, &[]), (SBI_SUCCESS, 2 << 24));
+    assert_eq!(call(EXT_BASE, 1, &[]), (SBI_SUCCESS, SBI_IMPL_ID));
+    assert_eq!(call(EXT_BASE, 2, &[]), (SBI_SUCCESS, 1));
+    for (eid, present) in [(EXT_BASE, 1), (EXT_TIME, 1), (EXT_HSM, 1), (EXT_UNKNOWN, 0)] {
+        assert_eq!(call(EXT_BASE, 3, &[eid]), (SBI_SUCCESS, present));
+    }
+    // No such base function
+    assert_eq!(call(EXT_BASE, 7, &[]), (SBI_ERR_NOT_SUPPORTED, 0));
+}
+
+#[test]
+fn timer_extension_interrupts_at_the_deadline() {
+    let mut machine = machine(&[ECALL, SPIN]);
+    machine.mem.copy_to(HANDLER, &[EBREAK]).unwrap();
+    machine.hart.set_csr(CSR_MTVEC, HANDLER);
+    machine
+        .hart
+        .set_csr(CSR_MIE, Interrupt::MachineTimer.mask());
+    machine.hart.set_csr(CSR_MSTATUS, MSTATUS_MIE);
+    machine.hart.set_reg(Reg::A7, EXT_TIME);
+    machine.hart.set_reg(Reg::A6, 0);
+    machine.hart.set_arg(0, 100);
+    machine.hart.set_arg(1, 0);
+
+    machine.step().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0) as i32, SBI_SUCCESS);
+    let mtimecmp = [0, 4].map(|half| machine.mem.mmio.read(CLINT_BASE + 0x4000 + half, 4));
+    assert_eq!(mtimecmp, [Some(100), Some(0)]);
+
+    // Spinning until mtime reaches 100
+    machine.run().unwrap();
+    assert_eq!(machine.hart.pc, HANDLER);
+    let cause = MCAUSE_INTERRUPT | Interrupt::MachineTimer as u32;
+    assert_eq!(machine.hart.csr(CSR_MCAUSE), cause);
+    assert_eq!(machine.hart.inst_count, 100);
+}
+
+#[test]
+fn legacy_set_timer_arms_the_clint() {
+    let mut machine = machine(&[ECALL, EBREAK]);
+    machine.hart.set_reg(Reg::A7, LEGACY_SET_TIMER);
+    machine.hart.set_arg(0, 0x1234_5678);
+    machine.hart.set_arg(1, 9);
+    machine.run().unwrap();
+
+    // Legacy calls return just a0
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0);
+    let mtimecmp = [0, 4].map(|half| machine.mem.mmio.read(CLINT_BASE + 0x4000 + half, 4));
+    assert_eq!(mtimecmp, [Some(0x1234_5678), Some(9)]);
+}
+
+#[test]
+fn unknown_extensions_are_not_supported() {
+    for fid in [0, 1] {
+        assert_eq!(call(EXT_UNKNOWN, fid, &[]), (SBI_ERR_NOT_SUPPORTED, 0));
+    }
+    // Nor functions the timer extension doesn't have
+    assert_eq!(call(EXT_TIME, 1, &[]), (SBI_ERR_NOT_SUPPORTED, 0));
+}
@@ -0,0 +1,114 @@
+//! Derive macros for `riscv-vm`, re-exported from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, punctuated::Punctuated, Data, DeriveInput, Error, Fields, LitInt, Member,
+    Meta, Token,
+};
+
+/// Implement `GuestValue` for a `#[repr(C)]` struct by reading and writing
+/// each field at its offset. `#[guest(size = N)]` fails the build unless
+/// the struct is `N` bytes.
+#[proc_macro_derive(GuestValue, attributes(guest))]
+pub fn derive_guest_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    guest_value(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn guest_value(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "GuestValue can't be derived for generic types",
+        ));
+    }
+    check_repr(input)?;
+
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            name,
+            "GuestValue can only be derived for structs",
+        ));
+    };
+    let members: Vec<Member> = match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|f| Member::Named(f.ident.clone().unwrap()))
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len()).map(Member::from).collect(),
+        Fields::Unit => vec![],
+    };
+
+    let size_check = guest_size(input)?.map(|size| {
+        let msg = format!("`{name}` must be {size} bytes to match the guest ABI");
+        quote! {
+            const _: () = ::core::assert!(::core::mem::size_of::<#name>() == #size, #msg);
+        }
+    });
+
+    Ok(quote! {
+        impl ::riscv_vm::marshal::GuestValue for #name {
+            #[inline]
+            fn read_from(mem: &::riscv_vm::memory::Memory, addr: u32) -> Self {
+                Self {
+                    #(#members: ::riscv_vm::marshal::GuestValue::read_from(
+                        mem,
+                        addr.wrapping_add(::core::mem::offset_of!(Self, #members) as u32),
+                    ),)*
+                }
+            }
+
+            #[inline]
+            fn write_to(self, mem: &mut ::riscv_vm::memory::Memory, addr: u32) {
+                #(::riscv_vm::marshal::GuestValue::write_to(
+                    self.#members,
+                    mem,
+                    addr.wrapping_add(::core::mem::offset_of!(Self, #members) as u32),
+                );)*
+            }
+        }
+
+        #size_check
+    })
+}
+
+/// Field offsets only match the guest's with a C layout.
+fn check_repr(input: &DeriveInput) -> syn::Result<()> {
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        let reprs = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        if reprs
+            .iter()
+            .any(|r| r.path().is_ident("C") || r.path().is_ident("transparent"))
+        {
+            return Ok(());
+        }
+    }
+
+    Err(Error::new_spanned(
+        &input.ident,
+        "GuestValue needs #[repr(C)] or #[repr(transparent)]",
+    ))
+}
+
+/// The size given by `#[guest(size = N)]`, if any.
+fn guest_size(input: &DeriveInput) -> syn::Result<Option<usize>> {
+    let mut size = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("guest")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("size") {
+                size = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `size = N`"))
+            }
+        })?;
+    }
+
+    Ok(size)
+}
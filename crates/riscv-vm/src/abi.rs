@@ -0,0 +1,108 @@
+use riscv_inst::Reg;
+
+use crate::{error::MemoryError, hart::Hart32, marshal::GuestValue, memory::Memory};
+
+/// Number of integer argument registers, `a0` through `a7`.
+pub const ARG_REGS: usize = 8;
+
+/// The calling-convention registers at some point in a call, from
+/// [`Hart32::read_frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub pc: u32,
+    /// Return address
+    pub ra: u32,
+    pub sp: u32,
+    /// Frame pointer (`s0`), if the guest was built to keep one
+    pub fp: u32,
+    /// `a0` through `a7`
+    pub args: [u32; ARG_REGS],
+}
+
+/// Access by the standard calling convention, for syscall handlers and
+/// host calls into the guest.
+impl Hart32 {
+    /// Argument register `n`, i.e. `a<n>`.
+    ///
+    /// # Panics
+    /// If `n` isn't below [`ARG_REGS`].
+    pub fn arg(&self, n: usize) -> u32 {
+        self.get_reg(Self::arg_reg(n))
+    }
+
+    pub fn set_arg(&mut self, n: usize, val: u32) {
+        self.set_reg(Self::arg_reg(n), val)
+    }
+
+    /// The first `N` arguments.
+    pub fn args<const N: usize>(&self) -> [u32; N] {
+        std::array::from_fn(|n| self.arg(n))
+    }
+
+    /// The return value, `a0`.
+    pub fn return_value(&self) -> u32 {
+        self.get_reg(Reg::A0)
+    }
+
+    pub fn set_return(&mut self, val: u32) {
+        self.set_reg(Reg::A0, val)
+    }
+
+    /// Return a pair, in `a0` and `a1`, like a 64-bit value or an SBI
+    /// `(error, value)` result.
+    pub fn set_return_pair(&mut self, a0: u32, a1: u32) {
+        self.set_reg(Reg::A0, a0);
+        self.set_reg(Reg::A1, a1);
+    }
+
+    /// Push `val` onto the stack, returning its address.
+    ///
+    /// `sp` moves down by `val`'s size; keeping it 16-byte aligned across
+    /// calls is up to the caller.
+    pub fn stack_push<T: GuestValue>(
+        &mut self,
+        mem: &mut Memory,
+        val: T,
+    ) -> Result<u32, MemoryError> {
+        let sp = self
+            .get_reg(Reg::Sp)
+            .wrapping_sub(std::mem::size_of::<T>() as u32);
+        mem.write(sp, val)?;
+        self.set_reg(Reg::Sp, sp);
+
+        Ok(sp)
+    }
+
+    /// Pop a `T` off the stack, undoing [`Hart32::stack_push`].
+    pub fn stack_pop<T: GuestValue>(&mut self, mem: &Memory) -> Result<T, MemoryError> {
+        let sp = self.get_reg(Reg::Sp);
+        let val = mem.read(sp)?;
+        self.set_reg(Reg::Sp, sp + std::mem::size_of::<T>() as u32);
+
+        Ok(val)
+    }
+
+    pub fn read_frame(&self) -> CallFrame {
+        CallFrame {
+            pc: self.pc,
+            ra: self.get_reg(Reg::Ra),
+            sp: self.get_reg(Reg::Sp),
+            fp: self.get_reg(Reg::S0),
+            args: self.args(),
+        }
+    }
+
+    /// Call sites of the live calls, innermost first. With a shadow stack
+    /// that's every call; otherwise only the caller, from `ra`.
+    pub fn backtrace(&self) -> Vec<u32> {
+        match &self.shadow_stack {
+            Some(shadow) => shadow.frames().iter().rev().map(|f| f.call_site).collect(),
+            None => vec![self.get_reg(Reg::Ra)],
+        }
+    }
+
+    fn arg_reg(n: usize) -> Reg {
+        assert!(n < ARG_REGS, "a{n} isn't an argument register");
+        Reg::checked_from(Reg::A0 as u8 + n as u8).unwrap()
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{error::AllocError, memory::Memory};
+
+/// Granularity (and minimum size) of arena reservations from the guest mmap area.
+const ARENA_CHUNK: u32 = 0x10_0000;
+
+/// Strategy used by [`Machine::guest_alloc`](crate::machine::Machine::guest_alloc)
+/// to place host-provided buffers in guest memory.
+#[derive(Debug)]
+pub enum GuestAllocator {
+    /// A host-managed arena, carved out of the guest's mmap area.
+    Arena(Arena),
+    /// Call into the guest's own allocator. musl's malloc returns 16-byte
+    /// aligned blocks, so larger alignments are rejected.
+    Malloc { malloc: u32, free: u32 },
+}
+
+impl GuestAllocator {
+    pub const MALLOC_ALIGN: u32 = 16;
+
+    pub fn malloc(malloc: u32, free: u32) -> Self {
+        Self::Malloc { malloc, free }
+    }
+}
+
+impl Default for GuestAllocator {
+    fn default() -> Self {
+        Self::Arena(Arena::default())
+    }
+}
+
+/// A first-fit allocator over regions reserved below `Memory::mmap_top`.
+#[derive(Debug, Default)]
+pub struct Arena {
+    /// Free blocks, address -> size
+    free: BTreeMap<u32, u32>,
+    /// Live allocations, address -> size
+    live: HashMap<u32, u32>,
+}
+
+impl Arena {
+    pub fn alloc(&mut self, mem: &mut Memory, size: u32, align: u32) -> Result<u32, AllocError> {
+        if !align.is_power_of_two() {
+            return Err(AllocError::InvalidAlignment { align });
+        }
+        let size = size.max(1);
+
+        let addr = match self.find_fit(size, align) {
+            Some(addr) => addr,
+            None => {
+                self.grow(mem, size, align)?;
+                self.find_fit(size, align)
+                    .ok_or(AllocError::OutOfMemory { size })?
+            }
+        };
+
+        mem.memset(addr, 0, size)?;
+        Ok(addr)
+    }
+
+    pub fn free(&mut self, addr: u32) -> Result<(), AllocError> {
+        let size = self
+            .live
+            .remove(&addr)
+            .ok_or(AllocError::InvalidFree { addr })?;
+        self.insert_free(addr, size);
+
+        Ok(())
+    }
+
+    /// Bytes currently handed out to the host.
+    pub fn allocated(&self) -> u32 {
+        self.live.values().sum()
+    }
+
+    fn find_fit(&mut self, size: u32, align: u32) -> Option<u32> {
+        let (block, block_size, addr) = self.free.iter().find_map(|(&block, &block_size)| {
+            let addr = block.checked_next_multiple_of(align)?;
+            let end = addr.checked_add(size)?;
+            (end <= block + block_size).then_some((block, block_size, addr))
+        })?;
+
+        self.free.remove(&block);
+        if addr > block {
+            self.free.insert(block, addr - block);
+        }
+        let end = addr + size;
+        if end < block + block_size {
+            self.free.insert(end, block + block_size - end);
+        }
+        self.live.insert(addr, size);
+
+        Some(addr)
+    }
+
+    /// Reserve a new region from the top of the guest mmap area.
+    fn grow(&mut self, mem: &mut Memory, size: u32, align: u32) -> Result<(), AllocError> {
+        let want = size
+            .checked_add(align)
+            .and_then(|s| s.checked_next_multiple_of(ARENA_CHUNK))
+            .ok_or(AllocError::OutOfMemory { size })?;
+        let base = mem
+            .mmap_top
+            .checked_sub(want)
+            .filter(|&base| base >= mem.brk)
+            .ok_or(AllocError::OutOfMemory { size })?;
+
+        mem.mmap_top = base;
+        self.insert_free(base, want);
+
+        Ok(())
+    }
+
+    /// Return a block to the free list, coalescing with its neighbours.
+    fn insert_free(&mut self, mut addr: u32, mut size: u32) {
+        if let Some((&prev, &prev_size)) = self.free.range(..addr).next_back() {
+            if prev + prev_size == addr {
+                self.free.remove(&prev);
+                addr = prev;
+                size += prev_size;
+            }
+        }
+        if let Some(next_size) = self.free.remove(&(addr + size)) {
+            size += next_size;
+        }
+        self.free.insert(addr, size);
+    }
+}
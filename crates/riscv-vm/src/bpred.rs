@@ -0,0 +1,203 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::symbols::SymbolTable;
+
+/// A conditional branch predictor model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictorConfig {
+    /// Backward branches taken, forward branches not taken
+    Static,
+    /// 2-bit saturating counters indexed by `bits` bits of the branch address
+    Bimodal { bits: u32 },
+    /// 2-bit saturating counters indexed by `bits` bits of the branch address
+    /// XORed with as many bits of global branch history
+    Gshare { bits: u32 },
+}
+
+impl PredictorConfig {
+    /// Largest counter table, in index bits.
+    pub const MAX_BITS: u32 = 24;
+}
+
+impl std::fmt::Display for PredictorConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Static => write!(f, "static"),
+            Self::Bimodal { bits } => write!(f, "bimodal:{bits}"),
+            Self::Gshare { bits } => write!(f, "gshare:{bits}"),
+        }
+    }
+}
+
+/// Parses `static`, `bimodal:BITS` or `gshare:BITS`, where the table has
+/// `2^BITS` counters.
+impl std::str::FromStr for PredictorConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!("invalid branch predictor {s:?}, expected static, bimodal:BITS or gshare:BITS")
+        };
+        let (kind, bits) = match s.split_once(':') {
+            Some((kind, bits)) => (kind, Some(bits.parse::<u32>().map_err(|_| usage())?)),
+            None => (s, None),
+        };
+        if bits.is_some_and(|bits| !(1..=Self::MAX_BITS).contains(&bits)) {
+            return Err(format!(
+                "invalid branch predictor {s:?}: BITS must be between 1 and {}",
+                Self::MAX_BITS
+            ));
+        }
+        match (kind, bits) {
+            ("static", None) => Ok(Self::Static),
+            ("bimodal", Some(bits)) => Ok(Self::Bimodal { bits }),
+            ("gshare", Some(bits)) => Ok(Self::Gshare { bits }),
+            _ => Err(usage()),
+        }
+    }
+}
+
+/// Predictions made and how many were wrong.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PredictorStats {
+    pub predictions: u64,
+    pub mispredictions: u64,
+}
+
+impl PredictorStats {
+    /// Fraction of predictions that were right, or 1 if there were none.
+    pub fn accuracy(&self) -> f64 {
+        match self.predictions {
+            0 => 1.0,
+            n => 1.0 - self.mispredictions as f64 / n as f64,
+        }
+    }
+
+    fn record(&mut self, correct: bool) {
+        self.predictions += 1;
+        self.mispredictions += !correct as u64;
+    }
+}
+
+/// Prediction statistics for one branch instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchSite {
+    pub pc: u32,
+    pub stats: PredictorStats,
+}
+
+/// Predicts every conditional branch the hart executes, keeping overall and
+/// per-branch accuracy.
+#[derive(Debug, Clone)]
+pub struct BranchPredictor {
+    config: PredictorConfig,
+    /// 2-bit counters, taken when 2 or 3
+    counters: Vec<u8>,
+    /// Global history, most recent outcome in bit 0
+    history: u32,
+    pub stats: PredictorStats,
+    sites: HashMap<u32, PredictorStats>,
+}
+
+impl BranchPredictor {
+    pub fn new(config: PredictorConfig) -> Self {
+        let counters = match config {
+            PredictorConfig::Static => 0,
+            PredictorConfig::Bimodal { bits } | PredictorConfig::Gshare { bits } => 1 << bits,
+        };
+        Self {
+            config,
+            // Weakly not taken
+            counters: vec![1; counters],
+            history: 0,
+            stats: PredictorStats::default(),
+            sites: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> PredictorConfig {
+        self.config
+    }
+
+    fn index(&self, pc: u32) -> usize {
+        // Instructions are at least 2-byte aligned
+        let pc = pc >> 1;
+        let mask = self.counters.len() as u32 - 1;
+        match self.config {
+            PredictorConfig::Gshare { .. } => ((pc ^ self.history) & mask) as usize,
+            _ => (pc & mask) as usize,
+        }
+    }
+
+    /// Predict the branch at `pc` with displacement `offset`, then train on
+    /// the actual outcome. Returns whether the prediction was right.
+    #[inline]
+    pub fn record(&mut self, pc: u32, offset: i32, taken: bool) -> bool {
+        let predicted = match self.config {
+            PredictorConfig::Static => offset < 0,
+            _ => {
+                let index = self.index(pc);
+                let counter = &mut self.counters[index];
+                let predicted = *counter >= 2;
+                *counter = if taken {
+                    (*counter + 1).min(3)
+                } else {
+                    counter.saturating_sub(1)
+                };
+                predicted
+            }
+        };
+        self.history = (self.history << 1) | taken as u32;
+
+        let correct = predicted == taken;
+        self.stats.record(correct);
+        self.sites.entry(pc).or_default().record(correct);
+        correct
+    }
+
+    /// Every branch seen, most mispredictions first.
+    pub fn sites(&self) -> Vec<BranchSite> {
+        let mut sites = self
+            .sites
+            .iter()
+            .map(|(&pc, &stats)| BranchSite { pc, stats })
+            .collect::<Vec<_>>();
+        sites.sort_by_key(|site| (std::cmp::Reverse(site.stats.mispredictions), site.pc));
+
+        sites
+    }
+
+    /// Write overall accuracy and the `limit` worst predicted branches as a table.
+    pub fn write_report(
+        &self,
+        w: &mut impl Write,
+        symbols: &SymbolTable,
+        limit: usize,
+    ) -> std::io::Result<()> {
+        writeln!(
+            w,
+            "Branch predictor {}: {} branches, {} mispredicted ({:.2}% accurate)",
+            self.config,
+            self.stats.predictions,
+            self.stats.mispredictions,
+            self.stats.accuracy() * 100.0
+        )?;
+        writeln!(
+            w,
+            "{:>12} {:>12} {:>9}  branch",
+            "executed", "mispredicted", "accuracy"
+        )?;
+        for site in self.sites().into_iter().take(limit) {
+            writeln!(
+                w,
+                "{:>12} {:>12} {:>8.2}%  {}",
+                site.stats.predictions,
+                site.stats.mispredictions,
+                site.stats.accuracy() * 100.0,
+                symbols.describe(site.pc)
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::symbols::SymbolTable;
+
+/// Geometry of a simulated cache. Every dimension is a power of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Total capacity in bytes
+    pub size: u32,
+    /// Lines per set
+    pub ways: u32,
+    /// Line size in bytes
+    pub line: u32,
+}
+
+impl CacheConfig {
+    /// A cache of `size` bytes, or `None` if the dimensions aren't powers of
+    /// two or there isn't room for at least one set.
+    pub fn new(size: u32, ways: u32, line: u32) -> Option<Self> {
+        let valid = [size, ways, line].iter().all(|n| n.is_power_of_two())
+            && ways.checked_mul(line).is_some_and(|set| set <= size);
+        valid.then_some(Self { size, ways, line })
+    }
+
+    pub fn sets(&self) -> u32 {
+        self.size / (self.ways * self.line)
+    }
+}
+
+impl std::fmt::Display for CacheConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.size, self.ways, self.line)
+    }
+}
+
+/// Parses `SIZE:WAYS:LINE`, with an optional `k` or `m` suffix on the size,
+/// e.g. `32k:4:64`.
+impl std::str::FromStr for CacheConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid cache {s:?}, expected SIZE:WAYS:LINE, e.g. 32k:4:64");
+        let mut parts = s.split(':');
+        let (Some(size), Some(ways), Some(line), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(usage());
+        };
+        let (size, scale) = match size.to_ascii_lowercase() {
+            s if s.ends_with('k') => (s.trim_end_matches('k').to_string(), 1 << 10),
+            s if s.ends_with('m') => (s.trim_end_matches('m').to_string(), 1 << 20),
+            s => (s, 1),
+        };
+        let num = |n: &str| n.parse::<u32>().map_err(|_| usage());
+        let size = num(&size)?.checked_mul(scale).ok_or_else(usage)?;
+        Self::new(size, num(ways)?, num(line)?)
+            .ok_or_else(|| format!("invalid cache {s:?}: sizes must be powers of two"))
+    }
+}
+
+/// Hits and misses for some stream of accesses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// Fraction of accesses that missed, or 0 if there were none.
+    pub fn miss_rate(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            n => self.misses as f64 / n as f64,
+        }
+    }
+
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+}
+
+impl std::ops::AddAssign for CacheStats {
+    fn add_assign(&mut self, other: Self) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+    }
+}
+
+/// A set-associative cache with LRU replacement, tracking tags only.
+///
+/// Stores allocate like loads. An access is charged to the line holding its
+/// first byte, even if it straddles two lines.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    config: CacheConfig,
+    /// Tags of each set, most recently used first
+    sets: Vec<Vec<u32>>,
+    line_shift: u32,
+    set_mask: u32,
+    pub stats: CacheStats,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            sets: vec![Vec::with_capacity(config.ways as usize); config.sets() as usize],
+            line_shift: config.line.trailing_zeros(),
+            set_mask: config.sets() - 1,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    /// Access the line containing `addr`, returning whether it hit.
+    pub fn access(&mut self, addr: u32) -> bool {
+        let line = addr >> self.line_shift;
+        let set = &mut self.sets[(line & self.set_mask) as usize];
+        let hit = match set.iter().position(|&tag| tag == line) {
+            Some(way) => {
+                set[..=way].rotate_right(1);
+                true
+            }
+            None => {
+                if set.len() == self.config.ways as usize {
+                    set.pop();
+                }
+                set.insert(0, line);
+                false
+            }
+        };
+        self.stats.record(hit);
+        hit
+    }
+
+    /// Empty the cache, keeping its statistics.
+    pub fn flush(&mut self) {
+        self.sets.iter_mut().for_each(Vec::clear);
+    }
+}
+
+/// Cache statistics for one guest function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCacheStats {
+    /// Function name, or the instruction address if it has no symbol
+    pub function: String,
+    pub icache: CacheStats,
+    pub dcache: CacheStats,
+}
+
+/// Instruction and data caches fed by the hart's fetches, loads and stores,
+/// with statistics kept per instruction so they can be grouped by function.
+///
+/// MMIO accesses bypass the data cache.
+#[derive(Debug, Clone)]
+pub struct CacheSim {
+    pub icache: Option<Cache>,
+    pub dcache: Option<Cache>,
+    /// Instruction and data cache stats by the instruction responsible
+    by_pc: HashMap<u32, [CacheStats; 2]>,
+}
+
+impl CacheSim {
+    pub fn new(icache: Option<CacheConfig>, dcache: Option<CacheConfig>) -> Self {
+        Self {
+            icache: icache.map(Cache::new),
+            dcache: dcache.map(Cache::new),
+            by_pc: HashMap::new(),
+        }
+    }
+
+    /// Fetch the instruction at `pc`, returning whether it hit, or `None`
+    /// without an instruction cache.
+    #[inline]
+    pub fn fetch(&mut self, pc: u32) -> Option<bool> {
+        let hit = self.icache.as_mut()?.access(pc);
+        self.by_pc.entry(pc).or_default()[0].record(hit);
+        Some(hit)
+    }
+
+    /// Load or store `addr` for the instruction at `pc`, returning whether it
+    /// hit, or `None` without a data cache.
+    #[inline]
+    pub fn data(&mut self, pc: u32, addr: u32) -> Option<bool> {
+        let hit = self.dcache.as_mut()?.access(addr);
+        self.by_pc.entry(pc).or_default()[1].record(hit);
+        Some(hit)
+    }
+
+    /// Stats grouped by the function containing each instruction, most misses first.
+    pub fn by_function(&self, symbols: &SymbolTable) -> Vec<FunctionCacheStats> {
+        let mut functions = HashMap::<String, [CacheStats; 2]>::new();
+        for (&pc, stats) in &self.by_pc {
+            let function = match symbols.containing(pc) {
+                Some(sym) => sym.name.clone(),
+                None => format!("{pc:#010x}"),
+            };
+            let entry = functions.entry(function).or_default();
+            entry[0] += stats[0];
+            entry[1] += stats[1];
+        }
+
+        let mut functions = functions
+            .into_iter()
+            .map(|(function, [icache, dcache])| FunctionCacheStats {
+                function,
+                icache,
+                dcache,
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            let misses = |f: &FunctionCacheStats| f.icache.misses + f.dcache.misses;
+            misses(b)
+                .cmp(&misses(a))
+                .then_with(|| a.function.cmp(&b.function))
+        });
+
+        functions
+    }
+
+    /// Write overall and per-function miss rates as a table.
+    pub fn write_report(&self, w: &mut impl Write, symbols: &SymbolTable) -> std::io::Result<()> {
+        for (name, cache) in [("I$", &self.icache), ("D$", &self.dcache)] {
+            if let Some(cache) = cache {
+                let stats = cache.stats;
+                writeln!(
+                    w,
+                    "{name} {}: {} accesses, {} misses ({:.2}%)",
+                    cache.config(),
+                    stats.accesses(),
+                    stats.misses,
+                    stats.miss_rate() * 100.0
+                )?;
+            }
+        }
+
+        writeln!(
+            w,
+            "{:>12} {:>8} {:>12} {:>8}  function",
+            "I$ misses", "rate", "D$ misses", "rate"
+        )?;
+        for f in self.by_function(symbols) {
+            writeln!(
+                w,
+                "{:>12} {:>7.2}% {:>12} {:>7.2}%  {}",
+                f.icache.misses,
+                f.icache.miss_rate() * 100.0,
+                f.dcache.misses,
+                f.dcache.miss_rate() * 100.0,
+                f.function
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, io::Write};
+
+use riscv_inst::Reg;
+
+use crate::symbols::SymbolTable;
+
+/// How an indirect jump is used, following the RAS hints for JALR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BranchKind {
+    /// Links `ra`/`t0`, e.g. a function pointer call
+    Call,
+    /// Jumps through `ra`/`t0` without linking it
+    Return,
+    /// Any other indirect jump: tail calls, jump tables
+    Jump,
+}
+
+impl BranchKind {
+    pub fn classify(rd: Reg, rs1: Reg) -> Self {
+        let is_link = |r: Reg| matches!(r, Reg::Ra | Reg::T0);
+        if is_link(rd) {
+            BranchKind::Call
+        } else if is_link(rs1) {
+            BranchKind::Return
+        } else {
+            BranchKind::Jump
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            BranchKind::Call => "call",
+            BranchKind::Return => "return",
+            BranchKind::Jump => "jump",
+        }
+    }
+}
+
+/// An indirect branch edge and how often it was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchEdge {
+    pub source: u32,
+    pub target: u32,
+    pub kind: BranchKind,
+    pub count: u64,
+}
+
+/// Table of indirect branch targets (JALR, C.JR, C.JALR) observed during execution.
+#[derive(Debug, Clone, Default)]
+pub struct BranchTrace {
+    edges: HashMap<(u32, u32), (BranchKind, u64)>,
+}
+
+impl BranchTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn record(&mut self, source: u32, target: u32, kind: BranchKind) {
+        self.edges.entry((source, target)).or_insert((kind, 0)).1 += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.edges.clear();
+    }
+
+    /// All edges, ordered by source then target.
+    pub fn edges(&self) -> Vec<BranchEdge> {
+        let mut edges = self
+            .edges
+            .iter()
+            .map(|(&(source, target), &(kind, count))| BranchEdge {
+                source,
+                target,
+                kind,
+                count,
+            })
+            .collect::<Vec<_>>();
+        edges.sort_by_key(|e| (e.source, e.target));
+
+        edges
+    }
+
+    /// Distinct targets of the indirect branch at `source`.
+    pub fn targets(&self, source: u32) -> Vec<u32> {
+        let mut targets = self
+            .edges
+            .keys()
+            .filter(|&&(s, _)| s == source)
+            .map(|&(_, t)| t)
+            .collect::<Vec<_>>();
+        targets.sort_unstable();
+
+        targets
+    }
+
+    /// Edges whose target doesn't fit the function layout described by `symbols`.
+    ///
+    /// Calls must land on a function entry, jumps on an entry (tail call) or within
+    /// the source's own function, and returns anywhere inside a known function.
+    pub fn validate(&self, symbols: &SymbolTable) -> Vec<BranchEdge> {
+        let is_entry = |addr| symbols.symbolize(addr).is_some_and(|(_, off)| off == 0);
+        let function = |addr| symbols.containing(addr).map(|sym| sym.addr);
+
+        self.edges()
+            .into_iter()
+            .filter(|edge| {
+                let valid = match edge.kind {
+                    BranchKind::Call => is_entry(edge.target),
+                    BranchKind::Jump => {
+                        is_entry(edge.target)
+                            || function(edge.target)
+                                .is_some_and(|f| function(edge.source) == Some(f))
+                    }
+                    BranchKind::Return => function(edge.target).is_some(),
+                };
+                !valid
+            })
+            .collect()
+    }
+
+    /// Write the table as CSV: `source,target,kind,count,source_sym,target_sym`.
+    pub fn write_csv(&self, w: &mut impl Write, symbols: &SymbolTable) -> std::io::Result<()> {
+        writeln!(w, "source,target,kind,count,source_sym,target_sym")?;
+        for edge in self.edges() {
+            writeln!(
+                w,
+                "{:#010x},{:#010x},{},{},{},{}",
+                edge.source,
+                edge.target,
+                edge.kind.name(),
+                edge.count,
+                symbol_field(symbols, edge.source),
+                symbol_field(symbols, edge.target),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn symbol_field(symbols: &SymbolTable, addr: u32) -> String {
+    match symbols.symbolize(addr) {
+        Some((name, off)) => format!("{name}+{off:#x}"),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use crate::{memory::Memory, mmio::MmioDevice, plic::IrqLine};
+
+/// Where a channel usually lives, after the virtio block device.
+pub const CHANNEL_BASE: u32 = 0x1000_2000;
+
+/// The channel's PLIC source, after the UART's.
+pub const CHANNEL_IRQ: u32 = 11;
+
+/// Largest message, in bytes.
+pub const CHANNEL_MTU: u32 = 4096;
+
+// Register offsets
+/// `STATUS_*` bits, read-only
+pub const CHANNEL_STATUS: u32 = 0x00;
+/// Length of the message in the receive buffer, or 0 if there's none
+pub const CHANNEL_RX_LEN: u32 = 0x04;
+/// Writing `n` sends the first `n` bytes of the transmit buffer
+pub const CHANNEL_TX_LEN: u32 = 0x08;
+/// Writing anything drops the received message, bringing in the next one
+pub const CHANNEL_RX_POP: u32 = 0x0C;
+/// `STATUS_RX_READY` and `STATUS_TX_READY` bits to raise the interrupt on
+pub const CHANNEL_IE: u32 = 0x10;
+/// The received message, read-only
+pub const CHANNEL_RX_BUF: u32 = 0x1000;
+/// The message to send
+pub const CHANNEL_TX_BUF: u32 = 0x2000;
+
+/// A message is waiting in the receive buffer.
+pub const STATUS_RX_READY: u32 = 1 << 0;
+/// The peer has room for another message.
+pub const STATUS_TX_READY: u32 = 1 << 1;
+/// The other end was dropped. Messages already received can still be read.
+pub const STATUS_PEER_CLOSED: u32 = 1 << 2;
+/// A message was sent without room for it and dropped. Cleared by reading
+/// `STATUS`.
+pub const STATUS_TX_DROPPED: u32 = 1 << 3;
+
+/// Messages in flight one way, at most `capacity` of them.
+#[derive(Debug)]
+struct Queue {
+    messages: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+/// One end of a bounded, bidirectional message channel between machines.
+///
+/// Each direction holds up to `capacity` messages. Ends can move between
+/// threads, so machines on different threads can talk through [`Channel`]
+/// devices, and the host can hold an end and talk to a guest itself.
+#[derive(Debug)]
+pub struct ChannelEnd {
+    tx: Arc<Mutex<Queue>>,
+    rx: Arc<Mutex<Queue>>,
+}
+
+impl ChannelEnd {
+    /// Two connected ends, each direction holding up to `capacity` messages.
+    pub fn pair(capacity: usize) -> (Self, Self) {
+        let queue = || {
+            Arc::new(Mutex::new(Queue {
+                messages: VecDeque::new(),
+                capacity: capacity.max(1),
+            }))
+        };
+        let (a, b) = (queue(), queue());
+        (
+            Self {
+                tx: a.clone(),
+                rx: b.clone(),
+            },
+            Self { tx: b, rx: a },
+        )
+    }
+
+    /// Send `message` to the other end, handing it back if there's no room
+    /// or it's longer than [`CHANNEL_MTU`].
+    pub fn try_send(&self, message: Vec<u8>) -> Result<(), Vec<u8>> {
+        let mut tx = self.tx.lock().unwrap();
+        if tx.messages.len() >= tx.capacity || message.len() > CHANNEL_MTU as usize {
+            return Err(message);
+        }
+        tx.messages.push_back(message);
+        Ok(())
+    }
+
+    /// Take the next message from the other end, if there is one.
+    pub fn try_recv(&self) -> Option<Vec<u8>> {
+        self.rx.lock().unwrap().messages.pop_front()
+    }
+
+    /// Whether the other end has room for another message.
+    pub fn can_send(&self) -> bool {
+        let tx = self.tx.lock().unwrap();
+        tx.messages.len() < tx.capacity
+    }
+
+    /// Whether the other end was dropped.
+    pub fn is_peer_closed(&self) -> bool {
+        // Each queue is shared by both ends, and nothing else
+        Arc::strong_count(&self.tx) == 1
+    }
+}
+
+/// A [`ChannelEnd`] as a guest device.
+///
+/// The guest writes a message into the transmit buffer and its length into
+/// `TX_LEN` to send it, and reads `RX_LEN` and the receive buffer to take
+/// one, then pops it with `RX_POP`. It should wait for `STATUS_TX_READY`
+/// before sending, as messages sent to a full channel are dropped.
+#[derive(Debug)]
+pub struct Channel {
+    end: ChannelEnd,
+    /// Front of the received messages, taken out so the guest can read it
+    /// without locking
+    rx_message: Option<Vec<u8>>,
+    tx_buf: Vec<u8>,
+    tx_dropped: bool,
+    ie: u32,
+    irq: Option<IrqLine>,
+}
+
+impl Channel {
+    pub fn new(end: ChannelEnd) -> Self {
+        Self {
+            end,
+            rx_message: None,
+            tx_buf: vec![0; CHANNEL_MTU as usize],
+            tx_dropped: false,
+            ie: 0,
+            irq: None,
+        }
+    }
+
+    /// Raise `irq` while an enabled condition holds.
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    /// Bring the next message into the receive buffer if it's empty,
+    /// returning whether there's one.
+    fn poll_rx(&mut self) -> bool {
+        if self.rx_message.is_none() {
+            self.rx_message = self.end.try_recv();
+        }
+        self.rx_message.is_some()
+    }
+
+    fn status(&mut self) -> u32 {
+        let mut status = 0;
+        if self.poll_rx() {
+            status |= STATUS_RX_READY;
+        }
+        if self.end.can_send() {
+            status |= STATUS_TX_READY;
+        }
+        if self.end.is_peer_closed() {
+            status |= STATUS_PEER_CLOSED;
+        }
+        if std::mem::take(&mut self.tx_dropped) {
+            status |= STATUS_TX_DROPPED;
+        }
+        status
+    }
+
+    fn send(&mut self, len: u32) {
+        let len = len.min(CHANNEL_MTU) as usize;
+        if self.end.try_send(self.tx_buf[..len].to_vec()).is_err() {
+            self.tx_dropped = true;
+        }
+    }
+}
+
+impl MmioDevice for Channel {
+    fn size(&self) -> u32 {
+        CHANNEL_TX_BUF + CHANNEL_MTU
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        match offset {
+            CHANNEL_STATUS => self.status(),
+            CHANNEL_RX_LEN => {
+                self.poll_rx();
+                self.rx_message.as_ref().map_or(0, |m| m.len() as u32)
+            }
+            CHANNEL_IE => self.ie,
+            CHANNEL_RX_BUF..CHANNEL_TX_BUF => {
+                self.poll_rx();
+                let message = self.rx_message.as_deref().unwrap_or_default();
+                let start = (offset - CHANNEL_RX_BUF) as usize;
+                (0..width as usize).rev().fold(0, |value, i| {
+                    value << 8 | message.get(start + i).copied().unwrap_or(0) as u32
+                })
+            }
+            _ if offset >= CHANNEL_TX_BUF => {
+                let start = (offset - CHANNEL_TX_BUF) as usize;
+                (0..width as usize).rev().fold(0, |value, i| {
+                    value << 8 | self.tx_buf.get(start + i).copied().unwrap_or(0) as u32
+                })
+            }
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        match offset {
+            CHANNEL_TX_LEN => self.send(value),
+            CHANNEL_RX_POP => {
+                self.poll_rx();
+                self.rx_message = None;
+            }
+            CHANNEL_IE => self.ie = value & (STATUS_RX_READY | STATUS_TX_READY),
+            _ if offset >= CHANNEL_TX_BUF => {
+                let start = (offset - CHANNEL_TX_BUF) as usize;
+                for (i, byte) in value.to_le_bytes()[..width as usize].iter().enumerate() {
+                    if let Some(b) = self.tx_buf.get_mut(start + i) {
+                        *b = *byte;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, _mem: &mut Memory, _now: u64) -> u32 {
+        // Interrupts go through the PLIC, not straight to the hart
+        if self.irq.is_none() {
+            return 0;
+        }
+        let rx = self.ie & STATUS_RX_READY != 0 && self.poll_rx();
+        let tx = self.ie & STATUS_TX_READY != 0 && self.end.can_send();
+        if let Some(irq) = &self.irq {
+            irq.set(rx || tx);
+        }
+        0
+    }
+}
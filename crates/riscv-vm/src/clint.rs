@@ -0,0 +1,112 @@
+use crate::{memory::Memory, mmio::MmioDevice, trap::Interrupt};
+
+/// Where the CLINT usually lives, matching QEMU's `virt` machine.
+pub const CLINT_BASE: u32 = 0x0200_0000;
+
+// Register offsets for hart 0, in the SiFive layout
+const MSIP: u32 = 0x0000;
+const MTIMECMP: u32 = 0x4000;
+const MTIME: u32 = 0xBFF8;
+
+/// A core-local interruptor for the one hart: `msip` raises a software
+/// interrupt, and the timer interrupt is pending while `mtime >= mtimecmp`.
+///
+/// `mtime` advances with instructions retired rather than wall-clock time, so
+/// timer interrupts land on the same instruction every run.
+#[derive(Debug, Clone)]
+pub struct Clint {
+    /// Instructions per `mtime` tick
+    insts_per_tick: u64,
+    /// Instructions retired as of the last tick
+    now: u64,
+    /// Added to the instruction-derived time, so the guest can set `mtime`
+    mtime_offset: u64,
+    mtimecmp: u64,
+    msip: bool,
+}
+
+impl Clint {
+    /// A CLINT whose `mtime` ticks every `insts_per_tick` instructions.
+    pub fn new(insts_per_tick: u64) -> Self {
+        Self {
+            insts_per_tick: insts_per_tick.max(1),
+            now: 0,
+            mtime_offset: 0,
+            // Reset value is unspecified; this keeps the timer quiet until it's armed
+            mtimecmp: u64::MAX,
+            msip: false,
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        (self.now / self.insts_per_tick).wrapping_add(self.mtime_offset)
+    }
+
+    fn set_mtime(&mut self, mtime: u64) {
+        self.mtime_offset = mtime.wrapping_sub(self.now / self.insts_per_tick);
+    }
+
+    /// The 32-bit register containing `offset`.
+    fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            MSIP => self.msip as u32,
+            MTIMECMP => self.mtimecmp as u32,
+            o if o == MTIMECMP + 4 => (self.mtimecmp >> 32) as u32,
+            MTIME => self.mtime() as u32,
+            o if o == MTIME + 4 => (self.mtime() >> 32) as u32,
+            _ => 0,
+        }
+    }
+
+    fn write_word(&mut self, offset: u32, value: u32) {
+        let low = |reg: u64| (reg & !0xFFFF_FFFF) | value as u64;
+        let high = |reg: u64| (reg & 0xFFFF_FFFF) | (value as u64) << 32;
+        match offset {
+            MSIP => self.msip = value & 1 != 0,
+            MTIMECMP => self.mtimecmp = low(self.mtimecmp),
+            o if o == MTIMECMP + 4 => self.mtimecmp = high(self.mtimecmp),
+            MTIME => self.set_mtime(low(self.mtime())),
+            o if o == MTIME + 4 => self.set_mtime(high(self.mtime())),
+            _ => {}
+        }
+    }
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl MmioDevice for Clint {
+    fn size(&self) -> u32 {
+        0x10000
+    }
+
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        self.read_word(offset & !3) >> ((offset & 3) * 8)
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        let aligned = offset & !3;
+        let shift = (offset & 3) * 8;
+        let mask = match width {
+            4 => u32::MAX,
+            _ => ((1 << (width * 8)) - 1) << shift,
+        };
+        let old = self.read_word(aligned);
+        self.write_word(aligned, (old & !mask) | ((value << shift) & mask));
+    }
+
+    fn tick(&mut self, _mem: &mut Memory, now: u64) -> u32 {
+        self.now = now;
+        let mut lines = 0;
+        if self.msip {
+            lines |= Interrupt::MachineSoftware.mask();
+        }
+        if self.mtime() >= self.mtimecmp {
+            lines |= Interrupt::MachineTimer.mask();
+        }
+        lines
+    }
+}
@@ -0,0 +1,275 @@
+//! Systems of cooperating guests: a [`Cluster`] owns many machines, the
+//! channels between them and the filesystem images they start from, and
+//! runs them round-robin on the calling thread.
+//!
+//! Scheduling is deterministic. Each round gives every runnable guest a
+//! slice of instructions, in the order they were spawned, so a cluster run
+//! twice from the same state does the same thing.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    channel::{Channel, ChannelEnd, CHANNEL_BASE, CHANNEL_MTU, CHANNEL_TX_BUF},
+    error::{ClusterError, MachineError},
+    machine::{Kernel, Machine, MachineState},
+};
+
+/// Distance between the channels [`Cluster::connect`] attaches to a guest,
+/// the first at [`CHANNEL_BASE`].
+pub const CHANNEL_STRIDE: u32 = (CHANNEL_TX_BUF + CHANNEL_MTU).next_power_of_two();
+
+/// A guest in a [`Cluster`], valid until it's killed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GuestId(u32);
+
+/// Where a guest is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestStatus {
+    /// Scheduled each round
+    Runnable,
+    /// Skipped until [`Cluster::resume`]
+    Paused,
+    /// Halted, see [`Machine::exit_code`] and the kernel for how
+    Halted,
+    /// Stopped at an `ebreak`, breakpoint or watchpoint; resuming runs on
+    Breakpoint,
+    /// Stopped by an error, see [`Cluster::take_error`]
+    Failed,
+}
+
+impl GuestStatus {
+    pub fn is_runnable(self) -> bool {
+        self == GuestStatus::Runnable
+    }
+}
+
+/// A snapshot of a guest, see [`Cluster::inspect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestInfo {
+    pub name: String,
+    pub status: GuestStatus,
+    pub pc: u32,
+    pub instructions: u64,
+    /// Channels attached with [`Cluster::connect`] or [`Cluster::open_channel`]
+    pub channels: u32,
+}
+
+struct Guest<K: Kernel> {
+    name: String,
+    machine: Machine<K>,
+    paused: bool,
+    error: Option<MachineError<K::Error>>,
+    channels: u32,
+}
+
+impl<K: Kernel> Guest<K> {
+    fn status(&self) -> GuestStatus {
+        if self.error.is_some() {
+            return GuestStatus::Failed;
+        }
+        match self.machine.state {
+            MachineState::Halted => GuestStatus::Halted,
+            MachineState::Breakpoint => GuestStatus::Breakpoint,
+            MachineState::Running if self.paused => GuestStatus::Paused,
+            MachineState::Running => GuestStatus::Runnable,
+        }
+    }
+}
+
+/// Machines run together, with what they share.
+pub struct Cluster<K: Kernel> {
+    guests: BTreeMap<GuestId, Guest<K>>,
+    next_id: u32,
+    /// Instructions each guest runs per round
+    slice: u64,
+    rounds: u64,
+    /// Filesystem images guests can start from, by name
+    images: BTreeMap<String, Arc<[u8]>>,
+}
+
+impl<K: Kernel> Cluster<K> {
+    /// An empty cluster giving each guest `slice` instructions a round.
+    /// Smaller slices interleave guests more finely, at some cost.
+    pub fn new(slice: u64) -> Self {
+        Self {
+            guests: BTreeMap::new(),
+            next_id: 0,
+            slice: slice.max(1),
+            rounds: 0,
+            images: BTreeMap::new(),
+        }
+    }
+
+    /// Add `machine`, loaded and ready to run, to the next round.
+    pub fn spawn(&mut self, name: impl Into<String>, machine: Machine<K>) -> GuestId {
+        let id = GuestId(self.next_id);
+        self.next_id += 1;
+        self.guests.insert(
+            id,
+            Guest {
+                name: name.into(),
+                machine,
+                paused: false,
+                error: None,
+                channels: 0,
+            },
+        );
+        id
+    }
+
+    /// Stop scheduling `id` until it's resumed, returning whether it's a
+    /// guest of this cluster.
+    pub fn pause(&mut self, id: GuestId) -> bool {
+        self.guests.get_mut(&id).map(|g| g.paused = true).is_some()
+    }
+
+    /// Schedule a paused guest again, or run one stopped at a breakpoint on
+    /// past it, returning whether it's a guest of this cluster.
+    pub fn resume(&mut self, id: GuestId) -> bool {
+        let Some(guest) = self.guests.get_mut(&id) else {
+            return false;
+        };
+        guest.paused = false;
+        guest.machine.resume();
+        true
+    }
+
+    /// Remove `id` for good, handing back its machine. Once that's dropped,
+    /// its channels' peers see
+    /// [`STATUS_PEER_CLOSED`](crate::channel::STATUS_PEER_CLOSED).
+    pub fn kill(&mut self, id: GuestId) -> Option<Machine<K>> {
+        self.guests.remove(&id).map(|g| g.machine)
+    }
+
+    pub fn inspect(&self, id: GuestId) -> Option<GuestInfo> {
+        let guest = self.guests.get(&id)?;
+        Some(GuestInfo {
+            name: guest.name.clone(),
+            status: guest.status(),
+            pc: guest.machine.hart.pc,
+            instructions: guest.machine.hart.inst_count,
+            channels: guest.channels,
+        })
+    }
+
+    /// Guests still in the cluster, in scheduling order.
+    pub fn guests(&self) -> impl Iterator<Item = GuestId> + '_ {
+        self.guests.keys().copied()
+    }
+
+    /// The first guest named `name`.
+    pub fn find(&self, name: &str) -> Option<GuestId> {
+        self.guests
+            .iter()
+            .find_map(|(&id, g)| (g.name == name).then_some(id))
+    }
+
+    pub fn machine(&self, id: GuestId) -> Option<&Machine<K>> {
+        self.guests.get(&id).map(|g| &g.machine)
+    }
+
+    /// The guest's machine, to change it between rounds.
+    pub fn machine_mut(&mut self, id: GuestId) -> Option<&mut Machine<K>> {
+        self.guests.get_mut(&id).map(|g| &mut g.machine)
+    }
+
+    /// The error a [`GuestStatus::Failed`] guest stopped with, leaving it
+    /// runnable again if it's still running.
+    pub fn take_error(&mut self, id: GuestId) -> Option<MachineError<K::Error>> {
+        self.guests.get_mut(&id)?.error.take()
+    }
+
+    /// Connect `a` and `b` with a [`Channel`] holding `capacity` messages
+    /// each way, returning where each guest finds its end: the next free
+    /// [`CHANNEL_STRIDE`] from [`CHANNEL_BASE`]. Channels don't raise
+    /// interrupts, so guests poll them.
+    pub fn connect(
+        &mut self,
+        a: GuestId,
+        b: GuestId,
+        capacity: usize,
+    ) -> Result<(u32, u32), ClusterError> {
+        for id in [a, b] {
+            if !self.guests.contains_key(&id) {
+                return Err(ClusterError::NoSuchGuest(id.0));
+            }
+        }
+        let (end_a, end_b) = ChannelEnd::pair(capacity);
+        let base_a = self.attach(a, end_a)?;
+        let base_b = self.attach(b, end_b)?;
+        Ok((base_a, base_b))
+    }
+
+    /// Attach a channel to `id` whose other end the host keeps, returning
+    /// where the guest finds it.
+    pub fn open_channel(
+        &mut self,
+        id: GuestId,
+        capacity: usize,
+    ) -> Result<(u32, ChannelEnd), ClusterError> {
+        let (guest_end, host_end) = ChannelEnd::pair(capacity);
+        Ok((self.attach(id, guest_end)?, host_end))
+    }
+
+    fn attach(&mut self, id: GuestId, end: ChannelEnd) -> Result<u32, ClusterError> {
+        let guest = self
+            .guests
+            .get_mut(&id)
+            .ok_or(ClusterError::NoSuchGuest(id.0))?;
+        let base = CHANNEL_BASE + guest.channels * CHANNEL_STRIDE;
+        guest
+            .machine
+            .mem
+            .mmio
+            .attach(base, Box::new(Channel::new(end)))?;
+        guest.channels += 1;
+        Ok(base)
+    }
+
+    /// Share a filesystem image, such as a tar archive for the kernel to
+    /// mount, under `name`. Guests spawned from it each get their own
+    /// copy to change, so it's a snapshot the whole cluster starts from.
+    pub fn add_image(&mut self, name: impl Into<String>, image: impl Into<Arc<[u8]>>) {
+        self.images.insert(name.into(), image.into());
+    }
+
+    pub fn image(&self, name: &str) -> Option<Arc<[u8]>> {
+        self.images.get(name).cloned()
+    }
+
+    /// Rounds run so far.
+    pub fn rounds(&self) -> u64 {
+        self.rounds
+    }
+
+    /// Give every runnable guest its slice, returning whether any is still
+    /// runnable. A guest that fails stops there, keeping its error for
+    /// [`Cluster::take_error`], and the others carry on.
+    pub fn run_round(&mut self) -> bool {
+        let mut runnable = false;
+        for guest in self.guests.values_mut() {
+            if !guest.status().is_runnable() {
+                continue;
+            }
+            for _ in 0..self.slice {
+                if let Err(e) = guest.machine.step() {
+                    guest.error = Some(e);
+                    break;
+                }
+                if !guest.machine.state.is_running() {
+                    break;
+                }
+            }
+            runnable |= guest.status().is_runnable();
+        }
+        self.rounds += 1;
+
+        runnable
+    }
+
+    /// Run rounds until no guest is runnable, as they halt, fail, pause or
+    /// stop at breakpoints.
+    pub fn run(&mut self) {
+        while self.run_round() {}
+    }
+}
@@ -0,0 +1,130 @@
+//! The scalar crypto instructions from Zkne, Zknd and Zknh.
+//!
+//! The `aes32*` instructions each do one byte of an AES round, which host
+//! AES instructions can't do alone, so they use tables built at compile time.
+
+/// Multiply in GF(2^8), modulo the AES polynomial.
+const fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+        b >>= 1;
+    }
+    product
+}
+
+const fn gf_inv(a: u8) -> u8 {
+    // a^254 = a^-1, and 0 maps to 0
+    let mut inv = 1;
+    let mut i = 0;
+    while i < 254 {
+        inv = gf_mul(inv, a);
+        i += 1;
+    }
+    inv
+}
+
+const SBOX: [u8; 256] = {
+    let mut sbox = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let b = gf_inv(i as u8);
+        sbox[i] =
+            b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4) ^ 0x63;
+        i += 1;
+    }
+    sbox
+};
+
+const INV_SBOX: [u8; 256] = {
+    let mut inv = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[SBOX[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+};
+
+/// Byte `bs` of `rs2`, substituted then, if `mix`, mixed into a column, and
+/// rotated back into place before being xored into `rs1`.
+fn aes32(rs1: u32, rs2: u32, bs: u32, decrypt: bool, mix: bool) -> u32 {
+    let shamt = bs * 8;
+    let byte = (rs2 >> shamt) as u8;
+    let word = match (decrypt, mix) {
+        (false, false) => SBOX[byte as usize] as u32,
+        (false, true) => {
+            let s = SBOX[byte as usize];
+            u32::from_le_bytes([gf_mul(s, 2), s, s, gf_mul(s, 3)])
+        }
+        (true, false) => INV_SBOX[byte as usize] as u32,
+        (true, true) => {
+            let s = INV_SBOX[byte as usize];
+            u32::from_le_bytes([gf_mul(s, 14), gf_mul(s, 9), gf_mul(s, 13), gf_mul(s, 11)])
+        }
+    };
+    rs1 ^ word.rotate_left(shamt)
+}
+
+pub(crate) fn aes32esi(rs1: u32, rs2: u32, bs: u32) -> u32 {
+    aes32(rs1, rs2, bs, false, false)
+}
+
+pub(crate) fn aes32esmi(rs1: u32, rs2: u32, bs: u32) -> u32 {
+    aes32(rs1, rs2, bs, false, true)
+}
+
+pub(crate) fn aes32dsi(rs1: u32, rs2: u32, bs: u32) -> u32 {
+    aes32(rs1, rs2, bs, true, false)
+}
+
+pub(crate) fn aes32dsmi(rs1: u32, rs2: u32, bs: u32) -> u32 {
+    aes32(rs1, rs2, bs, true, true)
+}
+
+pub(crate) fn sha256sig0(x: u32) -> u32 {
+    x.rotate_right(7) ^ x.rotate_right(18) ^ (x >> 3)
+}
+
+pub(crate) fn sha256sig1(x: u32) -> u32 {
+    x.rotate_right(17) ^ x.rotate_right(19) ^ (x >> 10)
+}
+
+pub(crate) fn sha256sum0(x: u32) -> u32 {
+    x.rotate_right(2) ^ x.rotate_right(13) ^ x.rotate_right(22)
+}
+
+pub(crate) fn sha256sum1(x: u32) -> u32 {
+    x.rotate_right(6) ^ x.rotate_right(11) ^ x.rotate_right(25)
+}
+
+// The SHA-512 functions on a 64-bit word split between `rs1` and `rs2`. The
+// `h` forms give its high half, with that half in `rs1`, the `l` forms its
+// low half, with that half in `rs1`, and the sums whichever half is in `rs1`.
+
+pub(crate) fn sha512sig0h(rs1: u32, rs2: u32) -> u32 {
+    (rs1 >> 1) ^ (rs1 >> 7) ^ (rs1 >> 8) ^ (rs2 << 31) ^ (rs2 << 24)
+}
+
+pub(crate) fn sha512sig0l(rs1: u32, rs2: u32) -> u32 {
+    (rs1 >> 1) ^ (rs1 >> 7) ^ (rs1 >> 8) ^ (rs2 << 31) ^ (rs2 << 25) ^ (rs2 << 24)
+}
+
+pub(crate) fn sha512sig1h(rs1: u32, rs2: u32) -> u32 {
+    (rs1 << 3) ^ (rs1 >> 6) ^ (rs1 >> 19) ^ (rs2 >> 29) ^ (rs2 << 13)
+}
+
+pub(crate) fn sha512sig1l(rs1: u32, rs2: u32) -> u32 {
+    (rs1 << 3) ^ (rs1 >> 6) ^ (rs1 >> 19) ^ (rs2 >> 29) ^ (rs2 << 26) ^ (rs2 << 13)
+}
+
+pub(crate) fn sha512sum0r(rs1: u32, rs2: u32) -> u32 {
+    (rs1 << 25) ^ (rs1 << 30) ^ (rs1 >> 28) ^ (rs2 >> 7) ^ (rs2 >> 2) ^ (rs2 << 4)
+}
+
+pub(crate) fn sha512sum1r(rs1: u32, rs2: u32) -> u32 {
+    (rs1 << 23) ^ (rs1 >> 14) ^ (rs1 >> 18) ^ (rs2 >> 9) ^ (rs2 << 18) ^ (rs2 << 14)
+}
@@ -0,0 +1,160 @@
+use std::{borrow::Cow, fmt::Display, str::FromStr};
+
+use crate::trap::{Interrupt, CSR_MIP};
+
+macro_rules! csrs {
+    ($($(#[$doc:meta])* $csr:ident = $number:literal => $name:literal,)*) => {
+        /// A CSR by name. [`Hart32::csr`](crate::hart::Hart32::csr) and
+        /// [`Hart32::set_csr`](crate::hart::Hart32::set_csr) take these as
+        /// well as raw 12-bit numbers.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        pub enum Csr {
+            $($(#[$doc])* $csr = $number,)*
+        }
+
+        impl Csr {
+            pub const ALL: &[Csr] = &[$(Self::$csr),*];
+
+            /// The name assemblers use, e.g. `mstatus`.
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(Self::$csr => $name,)*
+                }
+            }
+        }
+    };
+}
+
+csrs! {
+    Fflags = 0x001 => "fflags",
+    Frm = 0x002 => "frm",
+    Fcsr = 0x003 => "fcsr",
+    Sstatus = 0x100 => "sstatus",
+    Sie = 0x104 => "sie",
+    Stvec = 0x105 => "stvec",
+    Scounteren = 0x106 => "scounteren",
+    Sscratch = 0x140 => "sscratch",
+    Sepc = 0x141 => "sepc",
+    Scause = 0x142 => "scause",
+    Stval = 0x143 => "stval",
+    Sip = 0x144 => "sip",
+    Satp = 0x180 => "satp",
+    Mstatus = 0x300 => "mstatus",
+    Misa = 0x301 => "misa",
+    Medeleg = 0x302 => "medeleg",
+    Mideleg = 0x303 => "mideleg",
+    Mie = 0x304 => "mie",
+    Mtvec = 0x305 => "mtvec",
+    Mcounteren = 0x306 => "mcounteren",
+    Mstatush = 0x310 => "mstatush",
+    Mcountinhibit = 0x320 => "mcountinhibit",
+    Mscratch = 0x340 => "mscratch",
+    Mepc = 0x341 => "mepc",
+    Mcause = 0x342 => "mcause",
+    Mtval = 0x343 => "mtval",
+    Mip = 0x344 => "mip",
+    Mcycle = 0xB00 => "mcycle",
+    Minstret = 0xB02 => "minstret",
+    Mcycleh = 0xB80 => "mcycleh",
+    Minstreth = 0xB82 => "minstreth",
+    Cycle = 0xC00 => "cycle",
+    Time = 0xC01 => "time",
+    Instret = 0xC02 => "instret",
+    Cycleh = 0xC80 => "cycleh",
+    Timeh = 0xC81 => "timeh",
+    Instreth = 0xC82 => "instreth",
+    Mvendorid = 0xF11 => "mvendorid",
+    Marchid = 0xF12 => "marchid",
+    Mimpid = 0xF13 => "mimpid",
+    Mhartid = 0xF14 => "mhartid",
+}
+
+impl Csr {
+    pub const fn number(self) -> u16 {
+        self as u16
+    }
+
+    /// The named CSR numbered `number`, if it has a variant. The numbered
+    /// counters and their selectors don't; [`csr_name`] names those.
+    pub fn from_number(number: u16) -> Option<Self> {
+        Self::ALL.iter().copied().find(|csr| csr.number() == number)
+    }
+}
+
+impl From<Csr> for u16 {
+    fn from(csr: Csr) -> Self {
+        csr.number()
+    }
+}
+
+impl Display for Csr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Csr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|csr| csr.name() == s)
+            .ok_or_else(|| format!("Unknown CSR {s:?}"))
+    }
+}
+
+/// The name of CSR `csr`, including the numbered counters like
+/// `mhpmcounter3`, or its number in hex if it has none.
+pub fn csr_name(csr: u16) -> Cow<'static, str> {
+    if let Some(csr) = Csr::from_number(csr) {
+        return Cow::Borrowed(csr.name());
+    }
+    let n = csr & 0x1F;
+    match csr {
+        0xB03..=0xB1F => format!("mhpmcounter{n}"),
+        0xB83..=0xB9F => format!("mhpmcounter{n}h"),
+        0xC03..=0xC1F => format!("hpmcounter{n}"),
+        0xC83..=0xC9F => format!("hpmcounter{n}h"),
+        0x323..=0x33F => format!("mhpmevent{n}"),
+        _ => format!("{csr:#05x}"),
+    }
+    .into()
+}
+
+/// How a CSR instruction changes the CSR it reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsrOp {
+    /// `csrrw` and `csrrwi`: replace it with the source
+    Write,
+    /// `csrrs` and `csrrsi`: set the bits set in the source
+    Set,
+    /// `csrrc` and `csrrci`: clear the bits set in the source
+    Clear,
+}
+
+impl CsrOp {
+    /// The CSR's new value, before read-only bits are restored.
+    pub const fn apply(self, old: u32, src: u32) -> u32 {
+        match self {
+            Self::Write => src,
+            Self::Set => old | src,
+            Self::Clear => old & !src,
+        }
+    }
+}
+
+/// Bits of `csr` that CSR instructions can't change. Writes leave them as
+/// they were, as for WARL fields.
+///
+/// Counters are handled by [`Hpm`](crate::hpm::Hpm), which drops writes to
+/// the read-only user counters and `time`.
+pub const fn read_only_bits(csr: u16) -> u32 {
+    match csr {
+        // Driven by devices, through `Hart32::set_interrupt_lines`
+        CSR_MIP => Interrupt::LINES,
+        _ => 0,
+    }
+}
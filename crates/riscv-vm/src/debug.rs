@@ -0,0 +1,118 @@
+use crate::{
+    error::MachineError,
+    machine::{Kernel, Machine, MachineState},
+};
+
+/// Where [`Machine::finish`] or [`Machine::next`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugStop {
+    /// It got where it was going
+    Reached,
+    /// The guest stopped at a breakpoint on the way, see
+    /// [`Machine::resume`]
+    Breakpoint,
+    /// The machine halted on the way
+    Halted,
+}
+
+impl<K: Kernel> Machine<K> {
+    /// Stop before the guest function `name` runs, returning its address,
+    /// or `None` if there's no such symbol.
+    ///
+    /// The machine stops with [`MachineState::Breakpoint`], like at an
+    /// `ebreak` the hart hands to the debugger, and [`Machine::resume`]
+    /// continues from the function's first instruction.
+    pub fn break_at(&mut self, name: &str) -> Option<u32> {
+        let addr = self.symbols.lookup(name)?;
+        self.break_at_addr(addr);
+
+        Some(addr)
+    }
+
+    /// Like [`Machine::break_at`], for the instruction at `addr`.
+    pub fn break_at_addr(&mut self, addr: u32) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Remove the breakpoint at `addr`, returning whether there was one.
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Addresses with a breakpoint, in order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Stop if there's a breakpoint at the pc, unless the machine was just
+    /// resumed from it.
+    pub(crate) fn hit_breakpoint(&mut self) -> bool {
+        let pc = self.hart.pc;
+        if self.breakpoint_hit.take() == Some(pc) || !self.breakpoints.contains(&pc) {
+            return false;
+        }
+        tracing::debug!(pc, "stopped at breakpoint");
+        self.breakpoint_hit = Some(pc);
+        self.state = MachineState::Breakpoint;
+
+        true
+    }
+
+    /// Run until the current function returns to its caller, resuming from
+    /// a breakpoint first.
+    ///
+    /// Calls are tracked by the shadow stack, so this returns `None` without
+    /// running if [`MachineConfig::shadow_stack`] is off or the guest isn't
+    /// in any call it saw.
+    ///
+    /// [`MachineConfig::shadow_stack`]: crate::machine::MachineConfig::shadow_stack
+    pub fn finish(&mut self) -> Option<Result<DebugStop, MachineError<K::Error>>> {
+        let depth = self.call_depth().filter(|&depth| depth > 0)?;
+        self.resume();
+
+        Some(self.run_while(|m| m.call_depth() >= Some(depth)))
+    }
+
+    /// Execute one instruction, running through any function it calls, and
+    /// resuming from a breakpoint first.
+    ///
+    /// Like [`Machine::finish`], this needs the shadow stack and returns
+    /// `None` without it.
+    // Named like the debugger command, not an iterator
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<DebugStop, MachineError<K::Error>>> {
+        let depth = self.call_depth()?;
+        self.resume();
+        match self.run_while(|_| false) {
+            Ok(DebugStop::Reached) if self.call_depth()? > depth => {}
+            stop => return Some(stop),
+        }
+
+        Some(self.run_while(|m| m.call_depth() > Some(depth)))
+    }
+
+    /// Calls live on the shadow stack, if there is one.
+    fn call_depth(&self) -> Option<usize> {
+        let shadow = self.hart.shadow_stack.as_ref()?;
+        Some(shadow.frames().len())
+    }
+
+    /// Step at least once and for as long as `more` says to, stopping early
+    /// at breakpoints and when the machine halts.
+    fn run_while(
+        &mut self,
+        mut more: impl FnMut(&Self) -> bool,
+    ) -> Result<DebugStop, MachineError<K::Error>> {
+        loop {
+            if self.state == MachineState::Running {
+                self.step()?;
+            }
+            match self.state {
+                MachineState::Running if more(self) => {}
+                MachineState::Running => return Ok(DebugStop::Reached),
+                MachineState::Breakpoint => return Ok(DebugStop::Breakpoint),
+                MachineState::Halted => return Ok(DebugStop::Halted),
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use crate::{
+    memory::Memory,
+    mmio::{DeviceStop, MmioDevice},
+};
+
+/// Where the debug console usually lives, at the address of QEMU `virt`'s
+/// test device, which likewise ends the run.
+pub const DEBUG_CONSOLE_BASE: u32 = 0x0010_0000;
+
+// Register offsets, all write-only
+/// Writing a byte prints it
+pub const DEBUG_PUTCHAR: u32 = 0x00;
+/// Writing a word prints it in hex, on its own line
+pub const DEBUG_PUT_U32: u32 = 0x04;
+/// Writing a word stops the machine with it as the exit code
+pub const DEBUG_EXIT: u32 = 0x08;
+/// Writing the address of a NUL-terminated message stops the machine with
+/// a failed assertion
+pub const DEBUG_ASSERT_FAIL: u32 = 0x0C;
+
+/// Longest assertion message read from the guest, in bytes.
+pub const MAX_ASSERT_MESSAGE: u32 = 1024;
+
+/// A minimal output and exit device that works without any kernel, so
+/// bare-metal guests can report progress and results with plain stores.
+///
+/// Exits and assertion failures stop the machine before the next
+/// instruction, see [`Machine::exit_code`](crate::machine::Machine::exit_code).
+pub struct DebugConsole {
+    out: Box<dyn Write>,
+    stop: Option<DeviceStop>,
+    /// Address of an assertion message, read from memory on the next tick
+    assert_message: Option<u32>,
+}
+
+impl DebugConsole {
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            stop: None,
+            assert_message: None,
+        }
+    }
+
+    /// Print to the host's stdout.
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+
+    fn print(&mut self, bytes: &[u8]) {
+        if let Err(e) = self.out.write_all(bytes).and_then(|()| self.out.flush()) {
+            tracing::warn!("debug console: failed to write: {e}");
+        }
+    }
+}
+
+impl MmioDevice for DebugConsole {
+    fn size(&self) -> u32 {
+        0x10
+    }
+
+    fn read(&mut self, _offset: u32, _width: u32) -> u32 {
+        0
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        match offset {
+            DEBUG_PUTCHAR => self.print(&[value as u8]),
+            DEBUG_PUT_U32 => self.print(format!("{value:#010x}\n").as_bytes()),
+            DEBUG_EXIT => {
+                self.stop.get_or_insert(DeviceStop::Exit(value));
+            }
+            DEBUG_ASSERT_FAIL => {
+                self.assert_message.get_or_insert(value);
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, mem: &mut Memory, _now: u64) -> u32 {
+        if let Some(addr) = self.assert_message.take() {
+            let message = mem
+                .bytes_null_terminated(addr, Some(MAX_ASSERT_MESSAGE))
+                .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+                .unwrap_or_else(|e| format!("<unreadable message at {addr:#010x}: {e}>"));
+            self.stop.get_or_insert(DeviceStop::AssertFailed(message));
+        }
+        0
+    }
+
+    fn stop(&mut self) -> Option<DeviceStop> {
+        self.stop.take()
+    }
+}
@@ -2,10 +2,17 @@ use std::error::Error;
 
 use thiserror::Error;
 
-#[derive(Debug)]
+use crate::{
+    panic::{GuestBacktrace, GuestPanic},
+    quota::Resource,
+    shadow::ShadowViolation,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryAccess {
     Load,
     Store,
+    Fetch,
 }
 
 #[derive(Error, Debug)]
@@ -16,6 +23,16 @@ pub enum HartError {
     IllegalInst { addr: u32, inst: u32 },
     #[error("Unimplemented instruction \"0x{inst:08x}\" at address {addr:#08x}")]
     UnimplementedInst { addr: u32, inst: u32 },
+    #[error(
+        "Instruction {mnemonic} (\"0x{inst:08x}\") at address {addr:#08x} is forbidden by policy"
+    )]
+    ForbiddenInst {
+        addr: u32,
+        inst: u32,
+        mnemonic: &'static str,
+    },
+    #[error("Shadow stack violation: {0}")]
+    ShadowStack(ShadowViolation),
 }
 
 impl HartError {
@@ -28,6 +45,28 @@ impl HartError {
     pub const fn unimplemented(addr: u32, inst: u32) -> Self {
         Self::UnimplementedInst { addr, inst }
     }
+
+    /// Stable number for this kind of error, in `100..200`.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::InvalidInst { .. } => 100,
+            Self::IllegalInst { .. } => 101,
+            Self::UnimplementedInst { .. } => 102,
+            Self::ForbiddenInst { .. } => 103,
+            Self::ShadowStack(_) => 104,
+        }
+    }
+
+    /// Address and encoding of the instruction that failed, if it's known.
+    pub const fn inst(&self) -> Option<(u32, u32)> {
+        match *self {
+            Self::InvalidInst { addr, inst }
+            | Self::IllegalInst { addr, inst }
+            | Self::UnimplementedInst { addr, inst }
+            | Self::ForbiddenInst { addr, inst, .. } => Some((addr, inst)),
+            Self::ShadowStack(_) => None,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -46,6 +85,76 @@ pub enum MemoryError {
         addr: u32,
         len: u32,
     },
+    #[error("No terminator within {max_len} bytes of {addr:#08x}")]
+    Unterminated { addr: u32, max_len: u32 },
+    #[error("MMIO device at {addr:#08x} ({len:#x} bytes) overlaps another device")]
+    MmioOverlap { addr: u32, len: u32 },
+    #[error("Failed to remap {len:#x} bytes at {addr:#08x}: {source}")]
+    Remap {
+        addr: u32,
+        len: u32,
+        source: std::io::Error,
+    },
+    #[error("Failed to reserve {len:#x} bytes of guest memory: {source}")]
+    Reserve { len: usize, source: std::io::Error },
+    #[error("Failed to back guest memory with hugepages: {source}")]
+    HugePages { source: std::io::Error },
+    #[error("Memory access ({access:?}) at {addr:#08x} isn't permitted by its page")]
+    Protection { access: MemoryAccess, addr: u32 },
+    #[error("Pages at {addr:#08x} ({len:#x} bytes) can't be both writable and executable")]
+    WriteXorExecute { addr: u32, len: u32 },
+    #[error("Memory access ({access:?}) at {addr:#08x} is to memory the guest unmapped")]
+    Poisoned { access: MemoryAccess, addr: u32 },
+    #[error("Failed to find which guest pages are resident: {source}")]
+    Residency { source: std::io::Error },
+}
+
+impl MemoryError {
+    /// Stable number for this kind of error, in `200..300`.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::UnalignedMemoryAccess { .. } => 200,
+            Self::OutOfBoundsMemoryAccess { .. } => 201,
+            Self::OverflowMemoryAccess { .. } => 202,
+            Self::Unterminated { .. } => 203,
+            Self::MmioOverlap { .. } => 204,
+            Self::Remap { .. } => 205,
+            Self::Reserve { .. } => 206,
+            Self::HugePages { .. } => 207,
+            Self::Protection { .. } => 208,
+            Self::WriteXorExecute { .. } => 209,
+            Self::Poisoned { .. } => 210,
+            Self::Residency { .. } => 211,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AllocError {
+    #[error("Guest allocation of {size} bytes failed: out of memory")]
+    OutOfMemory { size: u32 },
+    #[error("Invalid allocation alignment {align}")]
+    InvalidAlignment { align: u32 },
+    #[error("Free of unallocated guest address {addr:#08x}")]
+    InvalidFree { addr: u32 },
+    #[error("Guest malloc returned NULL for {size} bytes")]
+    GuestMallocFailed { size: u32 },
+    #[error("Memory error: {0}")]
+    Memory(#[from] MemoryError),
+}
+
+impl AllocError {
+    /// Stable number for this kind of error, in `300..400`, or the memory
+    /// error's code.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::OutOfMemory { .. } => 300,
+            Self::InvalidAlignment { .. } => 301,
+            Self::InvalidFree { .. } => 302,
+            Self::GuestMallocFailed { .. } => 303,
+            Self::Memory(e) => e.code(),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -54,6 +163,135 @@ pub enum MachineError<E: Error> {
     Hart(#[from] HartError),
     #[error("Memory error: {0}")]
     Memory(#[from] MemoryError),
+    #[error("Allocation error: {0}")]
+    Alloc(#[from] AllocError),
+    #[error("Machine halted during a call into the guest at {func:#08x}")]
+    CallHalted { func: u32 },
+    #[error("Call into the guest with {count} arguments, more than fit in registers")]
+    TooManyArgs { count: usize },
+    #[error("{0}")]
+    GuestPanic(Box<GuestPanic>),
+    /// `backtrace` is where the guest was when it was stopped, which for an
+    /// instruction quota shows where it was spinning
+    #[error("Guest exceeded its {resource} quota of {limit}")]
+    QuotaExceeded {
+        resource: Resource,
+        limit: u64,
+        backtrace: GuestBacktrace,
+    },
+    #[error("The kernel doesn't support {0}")]
+    Unsupported(&'static str),
+    #[error("Can't patch the function at {addr:#08x}: {reason}")]
+    Unpatchable { addr: u32, reason: &'static str },
     #[error("Kernel error: {0}")]
     Kernel(E),
 }
+
+impl<E: Error> MachineError<E> {
+    /// Stable number for this kind of error, for embedders to match on or
+    /// report without parsing messages. Codes are grouped by where the
+    /// error came from: `1xx` the hart, `2xx` memory, `3xx` the guest
+    /// allocator, `4xx` the machine, and `900` the kernel. Codes are never
+    /// reused once assigned.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::Hart(e) => e.code(),
+            Self::Memory(e) => e.code(),
+            Self::Alloc(e) => e.code(),
+            Self::CallHalted { .. } => 400,
+            Self::GuestPanic(_) => 401,
+            Self::QuotaExceeded { .. } => 402,
+            Self::Unsupported(_) => 403,
+            Self::Unpatchable { .. } => 404,
+            Self::TooManyArgs { .. } => 405,
+            Self::Kernel(_) => 900,
+        }
+    }
+}
+
+/// Where the guest was when a [`MachineError`] stopped it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuestContext {
+    pub hart: u32,
+    pub pc: u32,
+    /// Encoding of the instruction at `pc`, or 0 if it couldn't be fetched
+    pub inst: u32,
+    /// Instructions retired before the error
+    pub inst_count: u64,
+}
+
+/// A [`MachineError`] with the guest context it happened in, from
+/// [`Machine::fault`](crate::machine::Machine::fault).
+#[derive(Error, Debug)]
+#[error("{error} [code {code}, hart {hart}, pc {pc:#010x}, instruction {inst:#010x}]",
+    code = error.code(), hart = context.hart, pc = context.pc, inst = context.inst)]
+pub struct Fault<E: Error + 'static> {
+    #[source]
+    pub error: MachineError<E>,
+    pub context: GuestContext,
+}
+
+impl<E: Error + 'static> Fault<E> {
+    pub const fn code(&self) -> u16 {
+        self.error.code()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HibernateError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Memory error: {0}")]
+    Memory(#[from] MemoryError),
+    #[error("Not a machine image")]
+    BadMagic,
+    #[error("Machine image format {version} needs a reader of format {compat} or newer")]
+    TooNew { version: u16, compat: u16 },
+    #[error("Machine image has no {0} section")]
+    MissingSection(String),
+    #[error("Machine image has an unknown required section {0}")]
+    UnknownSection(String),
+    #[error("Machine image section {section} is corrupt: {reason}")]
+    Corrupt { section: String, reason: String },
+    #[error("The kernel can't save its state")]
+    KernelUnsupported,
+    #[error("The kernel rejected its saved state: {0}")]
+    Kernel(String),
+}
+
+impl HibernateError {
+    /// Stable number for this kind of error, in `500..600`, or the memory
+    /// error's code.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::Io(_) => 500,
+            Self::Memory(e) => e.code(),
+            Self::BadMagic => 501,
+            Self::TooNew { .. } => 502,
+            Self::MissingSection(_) => 503,
+            Self::UnknownSection(_) => 504,
+            Self::Corrupt { .. } => 505,
+            Self::KernelUnsupported => 506,
+            Self::Kernel(_) => 507,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ClusterError {
+    #[error("No guest {0} in the cluster")]
+    NoSuchGuest(u32),
+    #[error("Memory error: {0}")]
+    Memory(#[from] MemoryError),
+}
+
+impl ClusterError {
+    /// Stable number for this kind of error, in `600..700`, or the memory
+    /// error's code.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::NoSuchGuest(_) => 600,
+            Self::Memory(e) => e.code(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Host implementations of the guest's bulk memory functions, which
+//! otherwise spend most of their time interpreting byte and word loops.
+
+use crate::{
+    hart::Hart32,
+    machine::{Kernel, Machine},
+    memory::Memory,
+};
+
+/// The guest functions [`Machine::accelerate_mem_functions`] replaces.
+pub const MEM_FUNCTIONS: [&str; 3] = ["memcpy", "memmove", "memset"];
+
+impl<K: Kernel> Machine<K> {
+    /// Run the guest's `memcpy`, `memmove` and `memset` as host copies over
+    /// guest memory, returning which of them the guest has.
+    ///
+    /// They're [intercepted](Machine::intercept), so the instructions they
+    /// would have retired aren't counted, profiled or traced. Ranges that
+    /// touch an MMIO device or wrap around the address space are copied a
+    /// byte at a time, as the guest would.
+    pub fn accelerate_mem_functions(&mut self) -> Vec<&'static str> {
+        MEM_FUNCTIONS
+            .into_iter()
+            .filter(|&name| {
+                let f = match name {
+                    "memset" => set,
+                    // Overlapping `memcpy`s are undefined, so copying them
+                    // like `memmove` is as good as anything
+                    _ => copy,
+                };
+                self.intercept(name, move |hart, mem, _| f(hart, mem))
+                    .is_some()
+            })
+            .collect()
+    }
+}
+
+/// `memmove(dst, src, n)`
+fn copy(hart: &mut Hart32, mem: &mut Memory) -> u32 {
+    let [dst, src, n] = hart.args();
+    hart.invalidate_reservation(dst, n);
+    if in_memory(mem, dst, n) && in_memory(mem, src, n) {
+        // Safety: both ranges are within the address space
+        unsafe { std::ptr::copy(mem.ptr(src), mem.ptr_mut(dst), n as usize) };
+    } else {
+        let bytes: Vec<u8> = (0..n)
+            .map(|i| {
+                let addr = src.wrapping_add(i);
+                mem.mmio
+                    .read(addr, 1)
+                    .map_or_else(|| mem.load(addr), |b| b as u8)
+            })
+            .collect();
+        for (i, b) in (0..n).zip(bytes) {
+            store_byte(mem, dst.wrapping_add(i), b);
+        }
+    }
+    dst
+}
+
+/// `memset(dst, c, n)`
+fn set(hart: &mut Hart32, mem: &mut Memory) -> u32 {
+    let [dst, c, n] = hart.args();
+    hart.invalidate_reservation(dst, n);
+    if in_memory(mem, dst, n) {
+        // Safety: the range is within the address space
+        unsafe { std::ptr::write_bytes(mem.ptr_mut(dst), c as u8, n as usize) };
+    } else {
+        for i in 0..n {
+            store_byte(mem, dst.wrapping_add(i), c as u8);
+        }
+    }
+    dst
+}
+
+/// Whether `addr..addr + len` is plain memory, without wrapping.
+fn in_memory(mem: &Memory, addr: u32, len: u32) -> bool {
+    addr.checked_add(len).is_some() && !mem.mmio.overlaps(addr, len)
+}
+
+fn store_byte(mem: &mut Memory, addr: u32, b: u8) {
+    if !mem.mmio.write(addr, 1, b as u32) {
+        mem.store(addr, b);
+    }
+}
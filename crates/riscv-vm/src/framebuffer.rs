@@ -0,0 +1,51 @@
+use crate::memory::Memory;
+
+/// Guest address of the framebuffer, above the initial stack.
+pub const FRAMEBUFFER_BASE: u32 = 0xE000_0000;
+
+/// Largest supported width or height, which keeps the framebuffer in the
+/// address space above [`FRAMEBUFFER_BASE`].
+pub const FRAMEBUFFER_MAX_DIM: u32 = 4096;
+
+/// A simple linear framebuffer at [`FRAMEBUFFER_BASE`].
+///
+/// Pixels are 4 bytes, `R, G, B, A` in memory order, with rows packed
+/// top to bottom. Guest memory is flat, so the guest draws with ordinary
+/// stores and the host reads the pixels back whenever it presents a frame.
+/// Alpha is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Framebuffer {
+    /// A framebuffer of `width` x `height` pixels, or `None` if either is 0 or
+    /// larger than [`FRAMEBUFFER_MAX_DIM`].
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        let valid = 1..=FRAMEBUFFER_MAX_DIM;
+        (valid.contains(&width) && valid.contains(&height)).then_some(Self { width, height })
+    }
+
+    /// Size of the framebuffer in bytes.
+    pub const fn size(&self) -> u32 {
+        self.width * self.height * 4
+    }
+
+    /// The raw RGBA bytes.
+    pub fn pixels<'a>(&self, mem: &'a Memory) -> &'a [u8] {
+        mem.slice(FRAMEBUFFER_BASE, self.size())
+            .expect("framebuffer is in bounds")
+    }
+
+    /// Convert the current frame to `0x00RRGGBB` words, as most host windowing
+    /// libraries want.
+    pub fn to_rgb32(&self, mem: &Memory, out: &mut Vec<u32>) {
+        out.clear();
+        out.extend(
+            self.pixels(mem)
+                .chunks_exact(4)
+                .map(|px| u32::from_be_bytes([0, px[0], px[1], px[2]])),
+        );
+    }
+}
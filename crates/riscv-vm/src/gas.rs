@@ -0,0 +1,174 @@
+use std::{collections::HashMap, io::Write};
+
+use riscv_inst::Reg;
+
+use crate::{shadow::classify_jump, symbols::SymbolTable};
+
+/// A call the [`GasProfiler`] is inside of.
+#[derive(Debug, Clone, Copy)]
+struct GasFrame {
+    callee: u32,
+    ret: u32,
+    /// Instructions profiled before the call
+    entered_at: u64,
+}
+
+/// Instructions, the unit instruction quotas meter, spent in one guest
+/// function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionGas {
+    /// Function name, or the address if it has no symbol
+    pub function: String,
+    /// Instructions executed in the function itself
+    pub own: u64,
+    /// Instructions executed in the function and everything it called.
+    /// Recursive calls are only counted once.
+    pub total: u64,
+    /// Times the function was called
+    pub calls: u64,
+}
+
+/// Attributes every instruction the hart executes to the function it is in,
+/// and to the calls it is under, tracked like the
+/// [`ShadowStack`](crate::shadow::ShadowStack) does but without checking
+/// returns.
+#[derive(Debug, Clone, Default)]
+pub struct GasProfiler {
+    /// Instructions executed at each address
+    by_pc: HashMap<u32, u64>,
+    /// Live calls, outermost first
+    frames: Vec<GasFrame>,
+    /// Instructions under finished calls, and the number of calls, by callee
+    by_callee: HashMap<u32, (u64, u64)>,
+    /// Where profiling started, which is under everything that follows
+    entry: Option<u32>,
+    instructions: u64,
+}
+
+impl GasProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instructions profiled so far.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Count the instruction at `pc`.
+    #[inline]
+    pub fn step(&mut self, pc: u32) {
+        *self.by_pc.entry(pc).or_default() += 1;
+        self.entry.get_or_insert(pc);
+        self.instructions += 1;
+    }
+
+    /// Track a jump to `target`, linking `rd` and jumping through `rs1`
+    /// (JALR only), with `ret` as the return address.
+    #[inline]
+    pub fn on_jump(&mut self, target: u32, rd: Reg, rs1: Option<Reg>, ret: u32) {
+        let (links, returns) = classify_jump(rd, rs1);
+        if returns {
+            // Returns that match no call, say from before profiling started,
+            // leave the stack alone
+            if let Some(depth) = self.frames.iter().rposition(|f| f.ret == target) {
+                while self.frames.len() > depth {
+                    let frame = self.frames.pop().unwrap();
+                    self.finish(frame);
+                }
+            }
+        }
+        if links {
+            self.frames.push(GasFrame {
+                callee: target,
+                ret,
+                entered_at: self.instructions,
+            });
+        }
+    }
+
+    fn finish(&mut self, frame: GasFrame) {
+        let (total, calls) = self.by_callee.entry(frame.callee).or_default();
+        *calls += 1;
+        // The outermost live call of a recursive function covers the others
+        if !self.frames.iter().any(|f| f.callee == frame.callee) {
+            *total += self.instructions - frame.entered_at;
+        }
+    }
+
+    /// Instructions by function, most executed in the function itself first.
+    /// Calls that haven't returned yet count up to now.
+    pub fn by_function(&self, symbols: &SymbolTable) -> Vec<FunctionGas> {
+        let name = |addr: u32| match symbols.containing(addr) {
+            Some(sym) => sym.name.clone(),
+            None => format!("{addr:#010x}"),
+        };
+        let mut functions = HashMap::<String, FunctionGas>::new();
+        for (&pc, &count) in &self.by_pc {
+            functions.entry(name(pc)).or_default().own += count;
+        }
+
+        let mut finished = self.clone();
+        while let Some(frame) = finished.frames.pop() {
+            finished.finish(frame);
+        }
+        for (&callee, &(total, calls)) in &finished.by_callee {
+            let function = functions.entry(name(callee)).or_default();
+            function.total += total;
+            function.calls += calls;
+        }
+        if let Some(entry) = self.entry {
+            functions.entry(name(entry)).or_default().total = self.instructions;
+        }
+
+        let mut functions = functions
+            .into_iter()
+            .map(|(function, gas)| FunctionGas {
+                function,
+                // Functions only ever tail called are under no call of
+                // their own
+                total: gas.total.max(gas.own),
+                ..gas
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            b.own
+                .cmp(&a.own)
+                .then_with(|| b.total.cmp(&a.total))
+                .then_with(|| a.function.cmp(&b.function))
+        });
+
+        functions
+    }
+
+    /// Write the `limit` functions that executed the most instructions
+    /// themselves as a table, with their share of all instructions.
+    pub fn write_report(
+        &self,
+        w: &mut impl Write,
+        symbols: &SymbolTable,
+        limit: usize,
+    ) -> std::io::Result<()> {
+        let percent = |n: u64| n as f64 * 100.0 / self.instructions.max(1) as f64;
+        writeln!(w, "Gas profile: {} instructions", self.instructions)?;
+        writeln!(
+            w,
+            "{:>12} {:>7} {:>12} {:>7} {:>10}  function",
+            "own", "", "total", "", "calls"
+        )?;
+        for f in self.by_function(symbols).into_iter().take(limit) {
+            writeln!(
+                w,
+                "{:>12} {:>6.2}% {:>12} {:>6.2}% {:>10}  {}",
+                f.own,
+                percent(f.own),
+                f.total,
+                percent(f.total),
+                f.calls,
+                f.function
+            )?;
+        }
+
+        Ok(())
+    }
+}
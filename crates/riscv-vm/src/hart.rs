@@ -1,29 +1,216 @@
-use riscv_inst::{codegen::rv32imasc::Rv32IMASC, Reg};
+use riscv_inst::{codegen::rv32imasfdc::Rv32IMASFDC, Extension, Extensions, FReg, Reg};
 
 use crate::{
+    bpred::BranchPredictor,
+    cache::{Cache, CacheSim},
+    cfi::{BranchKind, BranchTrace},
+    crypto,
+    csr::{csr_name, read_only_bits, Csr, CsrOp},
     error::{HartError, MachineError, MemoryAccess, MemoryError},
+    gas::GasProfiler,
+    hpm::{Hpm, HpmEvent},
     machine::{Kernel, StepResult},
     memory::Memory,
+    pipeline::PipelineTrace,
+    policy::InstPolicy,
+    shadow::ShadowStack,
+    softfloat::{classify, sign_inject, Env, Format, Rounding, SignOp, F32, F64},
+    trace::TraceWriter,
+    trap::{
+        trap_target, EbreakMode, Interrupt, CSR_MCAUSE, CSR_MEPC, CSR_MIE, CSR_MIP, CSR_MSTATUS,
+        CSR_MTVAL, CSR_MTVEC, MCAUSE_BREAKPOINT, MCAUSE_INTERRUPT, MSTATUS_MIE, MSTATUS_MPIE,
+        MSTATUS_MPP,
+    },
+    watch::{WatchHit, Watchpoint},
 };
 
+/// Architectural register state of a hart, used to save and restore execution contexts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HartContext {
+    pub regs: [u32; 32],
+    /// F and D registers, with single-precision values NaN-boxed
+    pub fregs: [u64; 32],
+    pub pc: u32,
+}
+
+/// `pause` from Zihintpause, a `fence w, 0` hint.
+const PAUSE: u32 = 0x0100_000f;
+
+const FFLAGS: u16 = Csr::Fflags as u16;
+const FRM: u16 = Csr::Frm as u16;
+const FCSR: u16 = Csr::Fcsr as u16;
+/// The accrued exception flags and rounding mode fields of `fcsr`
+const FFLAGS_MASK: u32 = 0x1f;
+const FRM_MASK: u32 = 0x7 << 5;
+
+/// Host implementation of the instructions declared in the custom opcode spec,
+/// see [`Hart32::custom_handler`]. It's given the decoded instruction and its
+/// raw encoding.
+pub type CustomHandler =
+    Box<dyn FnMut(&mut Hart32, &mut Memory, Rv32IMASFDC, u32) -> Result<StepResult, HartError>>;
+
+/// Embedder hooks into instruction execution, installed in [`Hart32::hooks`].
+pub trait HartExtension {
+    /// Execute `raw`, at `pc`, which isn't an instruction the decoder knows.
+    ///
+    /// Return `Ok(None)` to leave it an invalid instruction. On
+    /// [`StepResult::Ok`] the instruction retires, and if the hook didn't move
+    /// `hart.pc`, execution continues after it.
+    fn on_unknown_instruction(
+        &mut self,
+        hart: &mut Hart32,
+        raw: u32,
+        pc: u32,
+        mem: &mut Memory,
+    ) -> Result<Option<StepResult>, HartError> {
+        let _ = (hart, raw, pc, mem);
+        Ok(None)
+    }
+}
+
+/// What an AMO on an address that isn't 4-byte aligned does. LR/SC always
+/// trap when misaligned, as Zam doesn't cover them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MisalignedAtomics {
+    /// Fail with [`MemoryError::UnalignedMemoryAccess`], as the base A
+    /// extension allows
+    #[default]
+    Trap,
+    /// Perform the AMO anyway, as with the Zam extension. There's one hart,
+    /// so it's still atomic.
+    Emulate,
+}
+
+impl std::fmt::Display for MisalignedAtomics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Trap => "trap",
+            Self::Emulate => "emulate",
+        })
+    }
+}
+
+impl std::str::FromStr for MisalignedAtomics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trap" => Ok(Self::Trap),
+            "emulate" => Ok(Self::Emulate),
+            _ => Err(format!(
+                "unknown misaligned atomics option {s:?}, expected trap or emulate"
+            )),
+        }
+    }
+}
+
 /// A simple CPU for RV32I instructions
 pub struct Hart32 {
     regs: [u32; 32],
+    fregs: [u64; 32],
     csrs: [u32; 4096],
     pub pc: u32,
     pub inst_count: u64,
-    /// Atomic memory reservation set on this hart
+    /// Address of the word reserved by the last `lr.w`, until an `sc.w`, a
+    /// store or AMO overlapping it, or a trap. Hosts writing guest memory
+    /// behind the hart's back should clear it.
     pub amo_rsv: Option<u32>,
+    /// What misaligned AMOs do
+    pub misaligned_atomics: MisalignedAtomics,
+    /// What `ebreak` does
+    pub ebreak: EbreakMode,
+    /// Extensions this hart accepts; anything else is an illegal instruction
+    pub extensions: Extensions,
+    /// Opcode-level restrictions, checked after `extensions`
+    pub policy: InstPolicy,
+    /// Return-address shadow stack, if enabled
+    pub shadow_stack: Option<ShadowStack>,
+    /// Indirect branch target table, if enabled
+    pub branch_trace: Option<BranchTrace>,
+    /// Performance counters
+    pub hpm: Hpm,
+    /// Instruction and data cache models, if enabled
+    pub cache_sim: Option<CacheSim>,
+    /// Conditional branch predictor model, if enabled
+    pub branch_predictor: Option<BranchPredictor>,
+    /// Pipeline timing trace of retired instructions, if enabled
+    pub pipeline_trace: Option<PipelineTrace>,
+    /// Compressed trace of retired instructions and what they wrote, if enabled
+    pub inst_trace: Option<TraceWriter>,
+    /// Instructions by function and call, if enabled
+    pub gas_profiler: Option<GasProfiler>,
+    /// Executes [`Xcustom`](riscv_inst::Extension::Xcustom) instructions, which are illegal without
+    /// one. The pc advances past the instruction afterwards, so the handler
+    /// can't jump.
+    pub custom_handler: Option<CustomHandler>,
+    /// Embedder hooks, if any
+    pub hooks: Option<Box<dyn HartExtension>>,
+    /// Accesses to stop after, see [`Machine::watch`](crate::machine::Machine::watch)
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    /// The access the hart last stopped after
+    pub(crate) watch_hit: Option<WatchHit>,
+    /// Set by `pause` and waiting `wrs` instructions, which say the guest is
+    /// spinning, until the runner yields for them
+    pub(crate) spinning: bool,
 }
 
 impl Hart32 {
     pub fn new() -> Self {
         Hart32 {
             regs: [0; 32],
+            fregs: [0; 32],
             csrs: [0; 4096],
             pc: 0,
             inst_count: 0,
             amo_rsv: None,
+            misaligned_atomics: MisalignedAtomics::Trap,
+            ebreak: EbreakMode::Kernel,
+            extensions: Extensions::RV32IMASC,
+            policy: InstPolicy::AllowAll,
+            shadow_stack: None,
+            branch_trace: None,
+            hpm: Hpm::new(),
+            cache_sim: None,
+            branch_predictor: None,
+            pipeline_trace: None,
+            inst_trace: None,
+            gas_profiler: None,
+            custom_handler: None,
+            hooks: None,
+            watchpoints: vec![],
+            watch_hit: None,
+            spinning: false,
+        }
+    }
+
+    /// Go back to the state of a new hart: registers, CSRs, counters and the
+    /// reservation are cleared, and enabled simulators start over cold.
+    /// Configuration, like `extensions` and the hooks, is kept, as are the
+    /// pipeline and instruction traces, which are written as they go.
+    pub fn reset(&mut self) {
+        self.regs = [0; 32];
+        self.fregs = [0; 32];
+        self.csrs = [0; 4096];
+        self.pc = 0;
+        self.inst_count = 0;
+        self.amo_rsv = None;
+        self.watch_hit = None;
+        self.hpm = Hpm::new();
+        if let Some(shadow) = &mut self.shadow_stack {
+            *shadow = ShadowStack::new();
+        }
+        if let Some(trace) = &mut self.branch_trace {
+            *trace = BranchTrace::new();
+        }
+        if let Some(sim) = &mut self.cache_sim {
+            let config = |cache: &Option<Cache>| cache.as_ref().map(Cache::config);
+            *sim = CacheSim::new(config(&sim.icache), config(&sim.dcache));
+        }
+        if let Some(predictor) = &mut self.branch_predictor {
+            *predictor = BranchPredictor::new(predictor.config());
+        }
+        if let Some(profiler) = &mut self.gas_profiler {
+            *profiler = GasProfiler::new();
         }
     }
 
@@ -50,16 +237,291 @@ impl Hart32 {
         (start as usize..=end as usize).map(|i| (unsafe { Reg::from_u5(i as u8) }, self.regs[i]))
     }
 
+    /// Read an F or D register's raw bits.
+    #[inline(always)]
+    pub const fn get_freg(&self, r: FReg) -> u64 {
+        self.fregs[r as usize]
+    }
+
+    /// Write an F or D register. Single-precision values must be NaN-boxed,
+    /// with the upper 32 bits set, or instructions read them as NaN.
+    #[inline(always)]
+    pub const fn set_freg(&mut self, r: FReg, val: u64) {
+        self.fregs[r as usize] = val;
+    }
+
+    /// An F or D register as a value in `fmt`. A single-precision value
+    /// that isn't NaN-boxed reads as the canonical NaN.
+    #[inline(always)]
+    fn fp_reg(&self, fmt: Format, r: FReg) -> u64 {
+        let bits = self.fregs[r as usize];
+        match fmt {
+            F32 if bits >> 32 != 0xffff_ffff => F32.canonical_nan(),
+            F32 => bits & 0xffff_ffff,
+            _ => bits,
+        }
+    }
+
+    /// Write a value in `fmt`, NaN-boxing single-precision ones.
+    #[inline(always)]
+    fn set_fp_reg(&mut self, fmt: Format, r: FReg, bits: u64) {
+        let bits = match fmt {
+            F32 => bits | 0xffff_ffff_0000_0000,
+            _ => bits,
+        };
+        if let Some(trace) = &mut self.inst_trace {
+            trace.record_freg(r, bits);
+        }
+        self.fregs[r as usize] = bits;
+    }
+
+    pub fn context(&self) -> HartContext {
+        HartContext {
+            regs: self.regs,
+            fregs: self.fregs,
+            pc: self.pc,
+        }
+    }
+
+    /// Switch to `ctx`. The reservation belongs to the context switched
+    /// away from, so it's dropped, as a kernel would on a context switch.
+    pub fn restore_context(&mut self, ctx: &HartContext) {
+        self.regs = ctx.regs;
+        self.regs[0] = 0;
+        self.fregs = ctx.fregs;
+        self.pc = ctx.pc;
+        self.amo_rsv = None;
+    }
+
+    /// The value of `csr`, a [`Csr`](crate::csr::Csr) or its number.
+    pub fn csr(&self, csr: impl Into<u16>) -> u32 {
+        let csr = csr.into() & 0xFFF;
+        let fcsr = self.csrs[Csr::Fcsr as usize];
+        // `fflags` and `frm` are fields of `fcsr`
+        match csr {
+            FFLAGS => fcsr & FFLAGS_MASK,
+            FRM => (fcsr & FRM_MASK) >> 5,
+            _ => self.csrs[csr as usize],
+        }
+    }
+
+    pub fn set_csr(&mut self, csr: impl Into<u16>, val: u32) {
+        let csr = csr.into() & 0xFFF;
+        let fcsr = &mut self.csrs[Csr::Fcsr as usize];
+        match csr {
+            FFLAGS => *fcsr = (*fcsr & !FFLAGS_MASK) | (val & FFLAGS_MASK),
+            FRM => *fcsr = (*fcsr & !FRM_MASK) | ((val << 5) & FRM_MASK),
+            FCSR => *fcsr = val & (FRM_MASK | FFLAGS_MASK),
+            _ => self.csrs[csr as usize] = val,
+        }
+    }
+
+    /// Run a CSR instruction's read-modify-write of `csr`, returning the old
+    /// value for `rd`.
+    ///
+    /// `write` is false for `csrrs` and `csrrc` from x0, and their immediate
+    /// forms with 0, which only read, so writes' side effects don't happen.
+    /// Otherwise the CSR is written even if its value doesn't change, and
+    /// even if `rd` is x0. Read-only bits keep their value.
+    pub fn csr_instruction(
+        &mut self,
+        csr: impl Into<u16>,
+        op: CsrOp,
+        src: u32,
+        write: bool,
+    ) -> u32 {
+        let csr = csr.into() & 0xFFF;
+        let hpm = Hpm::is_hpm_csr(csr);
+        if hpm {
+            self.set_csr(csr, self.hpm.read(csr, self.inst_count));
+        }
+        let old = self.csr(csr);
+        if write {
+            let read_only = read_only_bits(csr);
+            self.set_csr(csr, (op.apply(old, src) & !read_only) | (old & read_only));
+            if hpm {
+                self.hpm.write(csr, self.csr(csr), self.inst_count);
+            }
+        }
+        tracing::trace!(csr = %csr_name(csr), old, new = self.csr(csr), "CSR access");
+        old
+    }
+
+    /// Set the device-driven `mip` bits, [`Interrupt::LINES`], to `lines`.
+    pub fn set_interrupt_lines(&mut self, lines: u32) {
+        let mip = self.csr(CSR_MIP);
+        self.set_csr(
+            CSR_MIP,
+            (mip & !Interrupt::LINES) | (lines & Interrupt::LINES),
+        );
+    }
+
+    /// Take the highest priority interrupt that's pending in `mip`, enabled in
+    /// `mie` and globally enabled by `mstatus.MIE`, trapping to `mtvec`.
+    ///
+    /// The hart always runs in M-mode, so there's no delegation.
+    pub fn take_interrupt(&mut self) -> Option<Interrupt> {
+        let mstatus = self.csr(CSR_MSTATUS);
+        if mstatus & MSTATUS_MIE == 0 {
+            return None;
+        }
+        let ready = self.csr(CSR_MIP) & self.csr(CSR_MIE);
+        let interrupt = Interrupt::BY_PRIORITY
+            .into_iter()
+            .find(|i| ready & i.mask() != 0)?;
+
+        tracing::trace!(?interrupt, pc = self.pc, "taking interrupt");
+        self.set_csr(CSR_MEPC, self.pc);
+        self.set_csr(CSR_MCAUSE, MCAUSE_INTERRUPT | interrupt as u32);
+        // Interrupts were enabled, so MPIE is set, and they stay off until `mret`
+        self.set_csr(
+            CSR_MSTATUS,
+            (mstatus & !MSTATUS_MIE) | MSTATUS_MPIE | MSTATUS_MPP,
+        );
+        self.pc = trap_target(self.csr(CSR_MTVEC), interrupt);
+        self.amo_rsv = None;
+
+        Some(interrupt)
+    }
+
+    /// Enter the trap handler for exception `cause` at the current
+    /// instruction, returning its address.
+    fn raise_exception(&mut self, cause: u32, tval: u32) -> u32 {
+        let mstatus = self.csr(CSR_MSTATUS);
+        let mpie = if mstatus & MSTATUS_MIE != 0 {
+            MSTATUS_MPIE
+        } else {
+            0
+        };
+        self.set_csr(CSR_MEPC, self.pc);
+        self.set_csr(CSR_MCAUSE, cause);
+        self.set_csr(CSR_MTVAL, tval);
+        self.set_csr(
+            CSR_MSTATUS,
+            (mstatus & !(MSTATUS_MIE | MSTATUS_MPIE)) | mpie | MSTATUS_MPP,
+        );
+        self.amo_rsv = None;
+        // Exceptions go to BASE even when interrupts are vectored
+        self.csr(CSR_MTVEC) & !0b11
+    }
+
+    /// Drop the reservation if a store of `len` bytes at `addr` overlaps it.
+    #[inline(always)]
+    pub(crate) fn invalidate_reservation(&mut self, addr: u32, len: u32) {
+        if let Some(rsv) = self.amo_rsv {
+            let (addr, rsv) = (addr as u64, rsv as u64);
+            if addr < rsv + 4 && rsv < addr + len as u64 {
+                self.amo_rsv = None;
+            }
+        }
+    }
+
+    /// Account for a conditional branch at `pc` with displacement `offset`.
+    #[inline(always)]
+    fn branch(&mut self, offset: i32, taken: bool) {
+        self.hpm.branch(taken);
+        if let Some(predictor) = &mut self.branch_predictor {
+            if !predictor.record(self.pc, offset, taken) {
+                self.hpm.count(HpmEvent::BranchMispredicts);
+            }
+        }
+    }
+
+    /// Run the custom instruction handler on `op`.
+    fn custom(
+        &mut self,
+        mem: &mut Memory,
+        op: Rv32IMASFDC,
+        inst: u32,
+    ) -> Result<StepResult, HartError> {
+        let Some(mut handler) = self.custom_handler.take() else {
+            return Err(HartError::illegal(self.pc, inst));
+        };
+        let res = handler(self, mem, op, inst);
+        self.custom_handler.get_or_insert(handler);
+        res
+    }
+
+    /// Offer the undecodable instruction `inst` to the hooks, failing with
+    /// [`HartError::InvalidInst`] if none take it.
+    fn unknown(&mut self, mem: &mut Memory, inst: u32) -> Result<StepResult, HartError> {
+        let pc = self.pc;
+        let invalid = HartError::InvalidInst { addr: pc, inst };
+        let Some(mut hooks) = self.hooks.take() else {
+            return Err(invalid);
+        };
+        let res = hooks.on_unknown_instruction(self, inst, pc, mem);
+        self.hooks.get_or_insert(hooks);
+
+        let res = res?.ok_or(invalid)?;
+        if !matches!(res, StepResult::Ok) {
+            return Ok(res);
+        }
+        self.inst_count += 1;
+        if self.pc == pc {
+            let len = if inst & 0b11 == 0b11 { 4 } else { 2 };
+            self.pc = pc.wrapping_add(len);
+        }
+        Ok(res)
+    }
+
     pub fn step<K: Kernel>(
         &mut self,
         mem: &mut Memory,
         kernel: &mut K,
     ) -> Result<StepResult, MachineError<K::Error>> {
         let inst = mem.load::<u32>(self.pc);
-        let op = Rv32IMASC::parse(inst).ok_or(HartError::InvalidInst {
-            addr: self.pc,
-            inst,
-        })?;
+        self.step_decoded(mem, kernel, inst, Rv32IMASFDC::parse(inst))
+    }
+
+    /// Like [`Hart32::step`], with `inst`, the instruction at `pc`, already
+    /// decoded to `op`.
+    pub(crate) fn step_decoded<K: Kernel>(
+        &mut self,
+        mem: &mut Memory,
+        kernel: &mut K,
+        inst: u32,
+        op: Option<Rv32IMASFDC>,
+    ) -> Result<StepResult, MachineError<K::Error>> {
+        if let Some(trace) = &mut self.inst_trace {
+            // From an instruction that didn't retire
+            trace.discard_pending();
+        }
+        if let Some(protection) = &mem.protection {
+            protection.fetch(self.pc)?;
+        }
+        if let Some(profiler) = &mut self.gas_profiler {
+            profiler.step(self.pc);
+        }
+        if let Some(hit) = self.cache_sim.as_mut().and_then(|sim| sim.fetch(self.pc)) {
+            self.hpm.count(if hit {
+                HpmEvent::ICacheHits
+            } else {
+                HpmEvent::ICacheMisses
+            });
+        }
+        let Some(op) = op else {
+            return Ok(self.unknown(mem, inst)?);
+        };
+        #[cfg(feature = "trace-instructions")]
+        tracing::trace!(
+            target: "riscv_vm::inst",
+            pc = format_args!("{:#010x}", self.pc),
+            inst = format_args!("{inst:#010x}"),
+            "{}",
+            op.mnemonic()
+        );
+        if !self.extensions.contains(op.extension()) {
+            return Err(HartError::illegal(self.pc, inst).into());
+        }
+        if !self.policy.permits(&op) {
+            return Err(HartError::ForbiddenInst {
+                addr: self.pc,
+                inst,
+                mnemonic: op.mnemonic(),
+            }
+            .into());
+        }
 
         let pc_inc = if inst & 0b11 == 0b11 { 4 } else { 2 };
         let mut next_pc = self.pc.wrapping_add(pc_inc);
@@ -68,11 +530,110 @@ impl Hart32 {
             ($reg: expr) => {
                 self.get_reg($reg)
             };
-            ($reg: expr, $val: expr) => {
-                self.set_reg($reg, $val as u32)
+            ($reg: expr, $val: expr) => {{
+                let (reg, val) = ($reg, $val as u32);
+                if let Some(trace) = &mut self.inst_trace {
+                    trace.record_reg(reg, val);
+                }
+                self.set_reg(reg, val)
+            }};
+        }
+
+        macro_rules! track_jump {
+            ($rd: expr, $rs1: expr, $target: expr) => {
+                let target = $target;
+                if let Some(shadow) = &mut self.shadow_stack {
+                    shadow
+                        .on_jump(self.pc, target, $rd, $rs1, next_pc)
+                        .map_err(HartError::ShadowStack)?;
+                }
+                if let Some(profiler) = &mut self.gas_profiler {
+                    profiler.on_jump(target, $rd, $rs1, next_pc);
+                }
+                if let (Some(trace), Some(rs1)) = (&mut self.branch_trace, $rs1) {
+                    trace.record(self.pc, target, BranchKind::classify($rd, rs1));
+                }
+            };
+        }
+
+        // Data cache access, if the cache simulator is on
+        macro_rules! dcache {
+            ($addr:expr) => {
+                if let Some(hit) = self
+                    .cache_sim
+                    .as_mut()
+                    .and_then(|sim| sim.data(self.pc, $addr))
+                {
+                    self.hpm.count(if hit {
+                        HpmEvent::DCacheHits
+                    } else {
+                        HpmEvent::DCacheMisses
+                    });
+                }
+            };
+        }
+
+        // Stop after this instruction if the access hits a watchpoint
+        let mut watched = false;
+        macro_rules! watch {
+            ($access:expr, $addr:expr, $size:expr, $val:expr) => {
+                if !self.watchpoints.is_empty() {
+                    watched |= self.watch($access, $addr, $size, $val as u32);
+                }
             };
         }
 
+        // Guest loads and stores, which may hit an MMIO device
+        macro_rules! load {
+            ($ty:ty, $addr:expr) => {{
+                let addr = $addr;
+                self.hpm.count(HpmEvent::Loads);
+                if let Some(protection) = &mem.protection {
+                    protection.load(addr, size_of::<$ty>() as u32)?;
+                }
+                let val = match mem.mmio.read(addr, size_of::<$ty>().min(4) as u32) {
+                    // Device registers are 32 bits, so doublewords are two
+                    // word accesses, low word first
+                    Some(low) if size_of::<$ty>() == 8 => {
+                        let high = mem.mmio.read(addr.wrapping_add(4), 4).unwrap_or(0);
+                        (low as u64 | (high as u64) << 32) as $ty
+                    }
+                    Some(val) => val as $ty,
+                    None => {
+                        dcache!(addr);
+                        mem.load::<$ty>(addr)
+                    }
+                };
+                watch!(MemoryAccess::Load, addr, size_of::<$ty>() as u32, val);
+                val
+            }};
+        }
+
+        macro_rules! store {
+            ($ty:ty, $addr:expr, $val:expr) => {{
+                let (addr, val) = ($addr, $val);
+                self.hpm.count(HpmEvent::Stores);
+                if let Some(protection) = &mem.protection {
+                    protection.store(addr, size_of::<$ty>() as u32)?;
+                }
+                watch!(MemoryAccess::Store, addr, size_of::<$ty>() as u32, val);
+                self.invalidate_reservation(addr, size_of::<$ty>() as u32);
+                let width = size_of::<$ty>().min(4) as u32;
+                if mem.mmio.write(addr, width, val as u32) {
+                    if size_of::<$ty>() == 8 {
+                        mem.mmio
+                            .write(addr.wrapping_add(4), 4, (val as u64 >> 32) as u32);
+                    }
+                } else {
+                    dcache!(addr);
+                    mem.store::<$ty>(addr, val);
+                    if let Some(trace) = &mut self.inst_trace {
+                        trace.record_store(addr, size_of::<$ty>() as u8);
+                    }
+                }
+            }};
+        }
+
         macro_rules! reg_imm_op {
             (|$inst:ident.$rs1:ident, $inst2:ident.$imm:ident| $body:expr) => {{
                 let $rs1 = reg!($inst.$rs1(inst));
@@ -110,34 +671,63 @@ impl Hart32 {
                 let $rs1 = reg!($inst.$rs1(inst));
                 let $rs2 = reg!($inst2.$rs2(inst));
                 let offset = $inst.imm(inst);
-                if $body {
+                let taken = $body;
+                self.branch(offset, taken);
+                if taken {
                     next_pc = self.pc.wrapping_add_signed(offset);
                 }
             }};
         }
 
         macro_rules! csr_op {
-            (|$inst:ident.$csr:ident, $inst2:ident.$rs1:ident| $body:expr) => {{
-                let $csr = $inst.$csr(inst) as usize;
-                let $rs1 = reg!($inst2.$rs1(inst));
-                reg!($inst.rd(inst), self.csrs[$csr]);
-                $body;
+            (|$inst:ident.$csr:ident, $inst2:ident.$rs1:ident| $op:expr) => {{
+                let rs1 = $inst2.$rs1(inst);
+                // `csrrs` and `csrrc` from x0 only read
+                let write = $op == CsrOp::Write || rs1 != Reg::Zero;
+                let old = self.csr_instruction($inst.$csr(inst) as u16, $op, reg!(rs1), write);
+                reg!($inst.rd(inst), old);
             }};
         }
 
         macro_rules! csr_imm_op {
-            (|$inst:ident.$csr:ident, $inst2:ident.$imm:ident| $body:expr) => {{
-                let $csr = $inst.$csr(inst) as usize;
-                let $imm = $inst2.$imm(inst);
-                reg!($inst.rd(inst), self.csrs[$csr]);
-                $body;
+            (|$inst:ident.$csr:ident, $inst2:ident.$imm:ident| $op:expr) => {{
+                let imm = $inst2.$imm(inst);
+                // As from x0, a zero immediate only reads for `csrrsi` and `csrrci`
+                let write = $op == CsrOp::Write || imm != 0;
+                let old = self.csr_instruction($inst.$csr(inst) as u16, $op, imm, write);
+                reg!($inst.rd(inst), old);
+            }};
+        }
+
+        macro_rules! ebreak {
+            () => {{
+                self.amo_rsv = None;
+                match self.ebreak {
+                    EbreakMode::Kernel => match kernel.ebreak(self, mem)? {
+                        StepResult::Ok => {}
+                        res => return Ok(res),
+                    },
+                    // Not retired, the host resumes past it
+                    EbreakMode::Debugger => return Ok(StepResult::Breakpoint),
+                    EbreakMode::Trap => next_pc = self.raise_exception(MCAUSE_BREAKPOINT, self.pc),
+                }
             }};
         }
 
+        // Reserved compressed encodings are illegal, rather than executing
+        // as the instruction they'd otherwise decode to
+        macro_rules! reserved_if {
+            ($reserved:expr) => {
+                if $reserved {
+                    return Err(HartError::illegal(self.pc, inst).into());
+                }
+            };
+        }
+
         macro_rules! amo_op {
             (|$inst:ident, $old:ident, $rs2:ident| $body:expr) => {{
                 let addr = reg!($inst.rs1(inst));
-                if addr & 3 != 0 {
+                if addr & 3 != 0 && self.misaligned_atomics == MisalignedAtomics::Trap {
                     return Err(MemoryError::UnalignedMemoryAccess {
                         access: MemoryAccess::Load,
                         addr,
@@ -145,107 +735,217 @@ impl Hart32 {
                     }
                     .into());
                 }
+                if let Some(protection) = &mem.protection {
+                    protection.store(addr, 4)?;
+                }
+                self.hpm.count(HpmEvent::Atomics);
+                dcache!(addr);
                 let $old = mem.load::<u32>(addr);
                 reg!($inst.rd(inst), $old);
                 let $rs2 = reg!($inst.rs2(inst));
-                mem.store::<u32>(addr, $body as u32);
+                watch!(MemoryAccess::Load, addr, 4, $old);
+                let new = $body as u32;
+                watch!(MemoryAccess::Store, addr, 4, new);
+                self.invalidate_reservation(addr, 4);
+                mem.store::<u32>(addr, new);
+                if let Some(trace) = &mut self.inst_trace {
+                    trace.record_store(addr, 4);
+                }
             }};
         }
 
-        match op {
-            Rv32IMASC::Lui(lui) => imm_op!(|lui.imm| imm),
-            Rv32IMASC::Auipc(auipc) => imm_op!(|auipc.imm| self.pc.wrapping_add_signed(imm)),
-            Rv32IMASC::Jal(jal) => imm_op!(|jal.imm| {
-                let res = next_pc;
-                next_pc = self.pc.wrapping_add_signed(imm);
-                res
-            }),
-            Rv32IMASC::Jalr(jalr) => reg_imm_op!(|jalr.rs1, jalr.imm| {
-                let res = next_pc;
-                next_pc = rs1.wrapping_add_signed(imm);
+        // The rounding mode for an F or D instruction's `rm` field, or for
+        // instructions without one, which only raise flags. Reserved modes,
+        // in the field or in `frm`, are illegal
+        macro_rules! fp_env {
+            ($inst:ident) => {
+                match Rounding::from_rm($inst.rm(inst), self.csr(FRM)) {
+                    Some(mode) => Env::new(mode),
+                    None => return Err(HartError::illegal(self.pc, inst).into()),
+                }
+            };
+            () => {
+                Env::new(Rounding::Rne)
+            };
+        }
+
+        // Evaluate `$body` in `$env`, accruing its exception flags in `fflags`
+        macro_rules! fp_op {
+            ($env:expr, |$e:ident| $body:expr) => {{
+                let mut $e = $env;
+                let res = $body;
+                self.csrs[FCSR as usize] |= $e.flags as u32;
                 res
-            }),
-            Rv32IMASC::Beq(beq) => branch_op!(|beq.rs1, beq.rs2| rs1 == rs2),
-            Rv32IMASC::Bne(bne) => branch_op!(|bne.rs1, bne.rs2| rs1 != rs2),
-            Rv32IMASC::Blt(blt) => branch_op!(|blt.rs1, blt.rs2| (rs1 as i32) < (rs2 as i32)),
-            Rv32IMASC::Bge(bge) => branch_op!(|bge.rs1, bge.rs2| (rs1 as i32) >= (rs2 as i32)),
-            Rv32IMASC::Bltu(bltu) => branch_op!(|bltu.rs1, bltu.rs2| rs1 < rs2),
-            Rv32IMASC::Bgeu(bgeu) => branch_op!(|bgeu.rs1, bgeu.rs2| rs1 >= rs2),
-            Rv32IMASC::Lb(lb) => reg_imm_op!(
-                |lb.rs1, lb.imm| mem.load::<i8>(rs1.wrapping_add_signed(imm)) as i32
+            }};
+        }
+
+        macro_rules! fp_unary_op {
+            ($fmt:expr, $env:expr, |$inst:ident, $e:ident, $a:ident| $body:expr) => {{
+                let $a = self.fp_reg($fmt, $inst.frs1(inst));
+                let res = fp_op!($env, |$e| $body);
+                self.set_fp_reg($fmt, $inst.frd(inst), res);
+            }};
+        }
+
+        macro_rules! fp_binary_op {
+            ($fmt:expr, $env:expr, |$inst:ident, $e:ident, $a:ident, $b:ident| $body:expr) => {{
+                let $a = self.fp_reg($fmt, $inst.frs1(inst));
+                let $b = self.fp_reg($fmt, $inst.frs2(inst));
+                let res = fp_op!($env, |$e| $body);
+                self.set_fp_reg($fmt, $inst.frd(inst), res);
+            }};
+        }
+
+        macro_rules! fp_fma_op {
+            ($fmt:expr, $inst:ident, $negate_product:expr, $negate_addend:expr) => {{
+                let operands = (
+                    self.fp_reg($fmt, $inst.frs1(inst)),
+                    self.fp_reg($fmt, $inst.frs2(inst)),
+                    self.fp_reg($fmt, $inst.frs3(inst)),
+                );
+                let res = fp_op!(fp_env!($inst), |env| env.fma(
+                    $fmt,
+                    operands,
+                    $negate_product,
+                    $negate_addend
+                ));
+                self.set_fp_reg($fmt, $inst.frd(inst), res);
+            }};
+        }
+
+        macro_rules! fp_cmp_op {
+            ($fmt:expr, |$inst:ident, $e:ident, $a:ident, $b:ident| $body:expr) => {{
+                let $a = self.fp_reg($fmt, $inst.frs1(inst));
+                let $b = self.fp_reg($fmt, $inst.frs2(inst));
+                let res = fp_op!(fp_env!(), |$e| $body);
+                reg!($inst.rd(inst), res as u32);
+            }};
+        }
+
+        // Compressed F and D loads and stores are part of C, so need checking
+        // for their own extension too
+        macro_rules! needs {
+            ($ext:expr) => {
+                if !self.extensions.contains($ext) {
+                    return Err(HartError::illegal(self.pc, inst).into());
+                }
+            };
+        }
+
+        match op {
+            Rv32IMASFDC::Lui(lui) => imm_op!(|lui.imm| imm),
+            Rv32IMASFDC::Auipc(auipc) => imm_op!(|auipc.imm| self.pc.wrapping_add_signed(imm)),
+            Rv32IMASFDC::Jal(jal) => {
+                track_jump!(
+                    jal.rd(inst),
+                    None,
+                    self.pc.wrapping_add_signed(jal.imm(inst))
+                );
+                imm_op!(|jal.imm| {
+                    let res = next_pc;
+                    next_pc = self.pc.wrapping_add_signed(imm);
+                    res
+                })
+            }
+            Rv32IMASFDC::Jalr(jalr) => {
+                track_jump!(
+                    jalr.rd(inst),
+                    Some(jalr.rs1(inst)),
+                    reg!(jalr.rs1(inst)).wrapping_add_signed(jalr.imm(inst))
+                );
+                reg_imm_op!(|jalr.rs1, jalr.imm| {
+                    let res = next_pc;
+                    next_pc = rs1.wrapping_add_signed(imm);
+                    res
+                })
+            }
+            Rv32IMASFDC::Beq(beq) => branch_op!(|beq.rs1, beq.rs2| rs1 == rs2),
+            Rv32IMASFDC::Bne(bne) => branch_op!(|bne.rs1, bne.rs2| rs1 != rs2),
+            Rv32IMASFDC::Blt(blt) => branch_op!(|blt.rs1, blt.rs2| (rs1 as i32) < (rs2 as i32)),
+            Rv32IMASFDC::Bge(bge) => branch_op!(|bge.rs1, bge.rs2| (rs1 as i32) >= (rs2 as i32)),
+            Rv32IMASFDC::Bltu(bltu) => branch_op!(|bltu.rs1, bltu.rs2| rs1 < rs2),
+            Rv32IMASFDC::Bgeu(bgeu) => branch_op!(|bgeu.rs1, bgeu.rs2| rs1 >= rs2),
+            Rv32IMASFDC::Lb(lb) => reg_imm_op!(
+                |lb.rs1, lb.imm| load!(i8, rs1.wrapping_add_signed(imm)) as i32
             ),
-            Rv32IMASC::Lh(lh) => reg_imm_op!(
-                |lh.rs1, lh.imm| mem.load::<i16>(rs1.wrapping_add_signed(imm)) as i32
+            Rv32IMASFDC::Lh(lh) => reg_imm_op!(
+                |lh.rs1, lh.imm| load!(i16, rs1.wrapping_add_signed(imm)) as i32
             ),
-            Rv32IMASC::Lw(lw) => reg_imm_op!(
-                |lw.rs1, lw.imm| mem.load::<u32>(rs1.wrapping_add_signed(imm))
+            Rv32IMASFDC::Lw(lw) => reg_imm_op!(
+                |lw.rs1, lw.imm| load!(u32, rs1.wrapping_add_signed(imm))
             ),
-            Rv32IMASC::Lbu(lbu) => reg_imm_op!(
-                |lbu.rs1, lbu.imm| mem.load::<u8>(rs1.wrapping_add_signed(imm))
+            Rv32IMASFDC::Lbu(lbu) => reg_imm_op!(
+                |lbu.rs1, lbu.imm| load!(u8, rs1.wrapping_add_signed(imm))
             ),
-            Rv32IMASC::Lhu(lhu) => reg_imm_op!(
-                |lhu.rs1, lhu.imm| mem.load::<u16>(rs1.wrapping_add_signed(imm))
+            Rv32IMASFDC::Lhu(lhu) => reg_imm_op!(
+                |lhu.rs1, lhu.imm| load!(u16, rs1.wrapping_add_signed(imm))
             ),
-            Rv32IMASC::Sb(sb) => {
-                store_op!(|sb.rs1, sb.rs2, addr| mem.store::<u8>(addr, rs2 as u8))
+            Rv32IMASFDC::Sb(sb) => {
+                store_op!(|sb.rs1, sb.rs2, addr| store!(u8, addr, rs2 as u8))
             }
-            Rv32IMASC::Sh(sh) => {
-                store_op!(|sh.rs1, sh.rs2, addr| mem.store::<u16>(addr, rs2 as u16))
+            Rv32IMASFDC::Sh(sh) => {
+                store_op!(|sh.rs1, sh.rs2, addr| store!(u16, addr, rs2 as u16))
             }
-            Rv32IMASC::Sw(sw) => {
-                store_op!(|sw.rs1, sw.rs2, addr| mem.store::<u32>(addr, rs2))
+            Rv32IMASFDC::Sw(sw) => {
+                store_op!(|sw.rs1, sw.rs2, addr| store!(u32, addr, rs2))
             }
-            Rv32IMASC::Addi(addi) => {
+            Rv32IMASFDC::Addi(addi) => {
                 reg_imm_op!(|addi.rs1, addi.imm| rs1.wrapping_add_signed(imm))
             }
-            Rv32IMASC::Slti(slti) => reg_imm_op!(|slti.rs1, slti.imm| (rs1 as i32) < imm),
-            Rv32IMASC::Sltiu(sltiu) => reg_imm_op!(|sltiu.rs1, sltiu.imm| rs1 < (imm as u32)),
-            Rv32IMASC::Xori(xori) => reg_imm_op!(|xori.rs1, xori.imm| rs1 ^ (imm as u32)),
-            Rv32IMASC::Ori(ori) => reg_imm_op!(|ori.rs1, ori.imm| rs1 | (imm as u32)),
-            Rv32IMASC::Andi(andi) => reg_imm_op!(|andi.rs1, andi.imm| rs1 & (imm as u32)),
-            Rv32IMASC::Slli(slli) => reg_imm_op!(|slli.rs1, slli.shamt| rs1 << shamt),
-            Rv32IMASC::Srli(srli) => reg_imm_op!(|srli.rs1, srli.shamt| rs1 >> shamt),
-            Rv32IMASC::Srai(srai) => reg_imm_op!(|srai.rs1, srai.shamt| rs1 as i32 >> shamt),
-            Rv32IMASC::Add(add) => reg_reg_op!(|add.rs1, add.rs2| rs1.wrapping_add(rs2)),
-            Rv32IMASC::Sub(sub) => reg_reg_op!(|sub.rs1, sub.rs2| rs1.wrapping_sub(rs2)),
-            Rv32IMASC::Sll(sll) => reg_reg_op!(|sll.rs1, sll.rs2| rs1 << (rs2 & 0x1f)),
-            Rv32IMASC::Slt(slt) => reg_reg_op!(|slt.rs1, slt.rs2| (rs1 as i32) < (rs2 as i32)),
-            Rv32IMASC::Sltu(sltu) => reg_reg_op!(|sltu.rs1, sltu.rs2| rs1 < rs2),
-            Rv32IMASC::Xor(xor) => reg_reg_op!(|xor.rs1, xor.rs2| rs1 ^ rs2),
-            Rv32IMASC::Srl(srl) => reg_reg_op!(|srl.rs1, srl.rs2| rs1 >> (rs2 & 0x1f)),
-            Rv32IMASC::Sra(sra) => {
+            Rv32IMASFDC::Slti(slti) => reg_imm_op!(|slti.rs1, slti.imm| (rs1 as i32) < imm),
+            Rv32IMASFDC::Sltiu(sltiu) => reg_imm_op!(|sltiu.rs1, sltiu.imm| rs1 < (imm as u32)),
+            Rv32IMASFDC::Xori(xori) => reg_imm_op!(|xori.rs1, xori.imm| rs1 ^ (imm as u32)),
+            Rv32IMASFDC::Ori(ori) => reg_imm_op!(|ori.rs1, ori.imm| rs1 | (imm as u32)),
+            Rv32IMASFDC::Andi(andi) => reg_imm_op!(|andi.rs1, andi.imm| rs1 & (imm as u32)),
+            Rv32IMASFDC::Slli(slli) => reg_imm_op!(|slli.rs1, slli.shamt| rs1 << shamt),
+            Rv32IMASFDC::Srli(srli) => reg_imm_op!(|srli.rs1, srli.shamt| rs1 >> shamt),
+            Rv32IMASFDC::Srai(srai) => reg_imm_op!(|srai.rs1, srai.shamt| rs1 as i32 >> shamt),
+            Rv32IMASFDC::Add(add) => reg_reg_op!(|add.rs1, add.rs2| rs1.wrapping_add(rs2)),
+            Rv32IMASFDC::Sub(sub) => reg_reg_op!(|sub.rs1, sub.rs2| rs1.wrapping_sub(rs2)),
+            Rv32IMASFDC::Sll(sll) => reg_reg_op!(|sll.rs1, sll.rs2| rs1 << (rs2 & 0x1f)),
+            Rv32IMASFDC::Slt(slt) => reg_reg_op!(|slt.rs1, slt.rs2| (rs1 as i32) < (rs2 as i32)),
+            Rv32IMASFDC::Sltu(sltu) => reg_reg_op!(|sltu.rs1, sltu.rs2| rs1 < rs2),
+            Rv32IMASFDC::Xor(xor) => reg_reg_op!(|xor.rs1, xor.rs2| rs1 ^ rs2),
+            Rv32IMASFDC::Srl(srl) => reg_reg_op!(|srl.rs1, srl.rs2| rs1 >> (rs2 & 0x1f)),
+            Rv32IMASFDC::Sra(sra) => {
                 reg_reg_op!(|sra.rs1, sra.rs2| (rs1 as i32 >> (rs2 & 0x1f)) as u32)
             }
-            Rv32IMASC::Or(or) => reg_reg_op!(|or.rs1, or.rs2| rs1 | rs2),
-            Rv32IMASC::And(and) => reg_reg_op!(|and.rs1, and.rs2| rs1 & rs2),
-            Rv32IMASC::Fence(_) => {}
-            Rv32IMASC::FenceI(_) => {}
-            Rv32IMASC::Ecall(_) => match kernel.syscall(self, mem)? {
-                StepResult::Ok => {}
-                res => return Ok(res),
-            },
-            Rv32IMASC::Ebreak(_) => match kernel.ebreak(self, mem)? {
-                StepResult::Ok => {}
-                res => return Ok(res),
-            },
-            Rv32IMASC::Unimp(_) => {
+            Rv32IMASFDC::Or(or) => reg_reg_op!(|or.rs1, or.rs2| rs1 | rs2),
+            Rv32IMASFDC::And(and) => reg_reg_op!(|and.rs1, and.rs2| rs1 & rs2),
+            // `pause` is a fence hint, ordering nothing but saying the guest
+            // is in a spin loop
+            Rv32IMASFDC::Fence(_) => self.spinning |= inst == PAUSE,
+            Rv32IMASFDC::FenceI(_) => {}
+            Rv32IMASFDC::Ecall(_) => {
+                // Traps lose the reservation, and the kernel may write anywhere
+                self.amo_rsv = None;
+                match kernel.syscall(self, mem)? {
+                    StepResult::Ok => {}
+                    res => return Ok(res),
+                }
+                if let Some(trace) = &mut self.inst_trace {
+                    trace.record_reg(Reg::A0, self.regs[Reg::A0 as usize]);
+                }
+            }
+            Rv32IMASFDC::Ebreak(_) => ebreak!(),
+            Rv32IMASFDC::Unimp(_) => {
                 return Err(HartError::IllegalInst {
                     addr: self.pc,
                     inst,
                 }
                 .into())
             }
-            Rv32IMASC::Mul(mul) => reg_reg_op!(|mul.rs1, mul.rs2| rs1.wrapping_mul(rs2)),
-            Rv32IMASC::Mulh(mulh) => reg_reg_op!(
+            Rv32IMASFDC::Mul(mul) => reg_reg_op!(|mul.rs1, mul.rs2| rs1.wrapping_mul(rs2)),
+            Rv32IMASFDC::Mulh(mulh) => reg_reg_op!(
                 |mulh.rs1, mulh.rs2| (rs1 as i32 as i64).wrapping_mul(rs2 as i32 as i64) >> 32
             ),
-            Rv32IMASC::Mulhsu(mulhsu) => reg_reg_op!(
+            Rv32IMASFDC::Mulhsu(mulhsu) => reg_reg_op!(
                 |mulhsu.rs1, mulhsu.rs2| (((rs1 as i32 as i64) * (rs2 as i64)) >> 32) as u32
             ),
-            Rv32IMASC::Mulhu(mulhu) => reg_reg_op!(
+            Rv32IMASFDC::Mulhu(mulhu) => reg_reg_op!(
                 |mulhu.rs1, mulhu.rs2| ((rs1 as u64 * rs2 as u64) >> 32) as u32
             ),
-            Rv32IMASC::Div(div) => reg_reg_op!(
+            Rv32IMASFDC::Div(div) => reg_reg_op!(
                 |div.rs1, div.rs2| {
                     let rs1 = rs1 as i32;
                     let rs2 = rs2 as i32;
@@ -260,7 +960,7 @@ impl Hart32 {
                     }
                 }
             ),
-            Rv32IMASC::Divu(divu) => reg_reg_op!(
+            Rv32IMASFDC::Divu(divu) => reg_reg_op!(
                 |divu.rs1, divu.rs2| {
                     let rs2 = rs2 as u32;
                     if rs2 == 0 {
@@ -271,7 +971,7 @@ impl Hart32 {
                     }
                 }
             ),
-            Rv32IMASC::Rem(rem) => reg_reg_op!(
+            Rv32IMASFDC::Rem(rem) => reg_reg_op!(
                 |rem.rs1, rem.rs2| {
                     let rs1 = rs1 as i32;
                     let rs2 = rs2 as i32;
@@ -286,7 +986,7 @@ impl Hart32 {
                     }
                 }
             ),
-            Rv32IMASC::Remu(remu) => reg_reg_op!(
+            Rv32IMASFDC::Remu(remu) => reg_reg_op!(
                 |remu.rs1, remu.rs2| {
                     let rs2 = rs2 as u32;
                     if rs2 == 0 {
@@ -297,25 +997,37 @@ impl Hart32 {
                     }
                 }
             ),
-            Rv32IMASC::Uret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::Sret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::Hret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::Mret(_) => {
-                // TODO: Not erroring because the ISA tests use this.
-                // But we haven't implemented privilege levels yet.
-            }
-            Rv32IMASC::Dret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::SfenceVm(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::SfenceVma(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::Wfi(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
-            Rv32IMASC::Csrrw(rw) => csr_op!(|rw.csr12, rw.rs1| self.csrs[csr12] = rs1),
-            Rv32IMASC::Csrrs(rs) => csr_op!(|rs.csr12, rs.rs1| self.csrs[csr12] |= rs1),
-            Rv32IMASC::Csrrc(rc) => csr_op!(|rc.csr12, rc.rs1| self.csrs[csr12] &= !rs1),
-            Rv32IMASC::Csrrwi(wi) => csr_imm_op!(|wi.csr12, wi.imm| self.csrs[csr12] = imm),
-            Rv32IMASC::Csrrsi(ri) => csr_imm_op!(|ri.csr12, ri.imm| self.csrs[csr12] |= imm),
-            Rv32IMASC::Csrrci(ci) => csr_imm_op!(|ci.csr12, ci.imm| self.csrs[csr12] &= !imm),
-            // We don't care about reservation set on single-hart ( i think )
-            Rv32IMASC::LrW(lr_w) => {
+            Rv32IMASFDC::Uret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
+            Rv32IMASFDC::Sret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
+            Rv32IMASFDC::Hret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
+            Rv32IMASFDC::Mret(_) => {
+                // Only M-mode exists, so MPP is always M-mode
+                let mstatus = self.csr(CSR_MSTATUS);
+                let mie = if mstatus & MSTATUS_MPIE != 0 {
+                    MSTATUS_MIE
+                } else {
+                    0
+                };
+                self.set_csr(CSR_MSTATUS, (mstatus & !MSTATUS_MIE) | mie | MSTATUS_MPIE);
+                next_pc = self.csr(CSR_MEPC);
+            }
+            Rv32IMASFDC::Dret(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
+            Rv32IMASFDC::SfenceVm(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
+            Rv32IMASFDC::SfenceVma(_) => return Err(HartError::unimplemented(self.pc, inst).into()),
+            // Waiting isn't required, and interrupts are checked before every instruction
+            Rv32IMASFDC::Wfi(_) => {}
+            // Nothing else can write the reserved word while this hart waits
+            // for it to change, so only stall until the next yield
+            Rv32IMASFDC::WrsNto(_) | Rv32IMASFDC::WrsSto(_) => {
+                self.spinning |= self.amo_rsv.is_some()
+            }
+            Rv32IMASFDC::Csrrw(rw) => csr_op!(|rw.csr12, rw.rs1| CsrOp::Write),
+            Rv32IMASFDC::Csrrs(rs) => csr_op!(|rs.csr12, rs.rs1| CsrOp::Set),
+            Rv32IMASFDC::Csrrc(rc) => csr_op!(|rc.csr12, rc.rs1| CsrOp::Clear),
+            Rv32IMASFDC::Csrrwi(wi) => csr_imm_op!(|wi.csr12, wi.imm| CsrOp::Write),
+            Rv32IMASFDC::Csrrsi(ri) => csr_imm_op!(|ri.csr12, ri.imm| CsrOp::Set),
+            Rv32IMASFDC::Csrrci(ci) => csr_imm_op!(|ci.csr12, ci.imm| CsrOp::Clear),
+            Rv32IMASFDC::LrW(lr_w) => {
                 let addr = reg!(lr_w.rs1(inst));
                 if addr & 3 != 0 {
                     return Err(MemoryError::UnalignedMemoryAccess {
@@ -325,12 +1037,18 @@ impl Hart32 {
                     }
                     .into());
                 }
-                // TODO: Not sure if this is how the spec defines the
-                // "reservation set" for lr/sc
+                if let Some(protection) = &mem.protection {
+                    protection.load(addr, 4)?;
+                }
+                // The reservation set is the addressed word
                 self.amo_rsv = Some(addr);
-                reg!(lr_w.rd(inst), mem.load::<u32>(addr));
+                self.hpm.count(HpmEvent::Atomics);
+                dcache!(addr);
+                let val = mem.load::<u32>(addr);
+                watch!(MemoryAccess::Load, addr, 4, val);
+                reg!(lr_w.rd(inst), val);
             }
-            Rv32IMASC::ScW(sc_w) => {
+            Rv32IMASFDC::ScW(sc_w) => {
                 let addr = reg!(sc_w.rs1(inst));
                 if addr & 3 != 0 {
                     return Err(MemoryError::UnalignedMemoryAccess {
@@ -340,136 +1058,450 @@ impl Hart32 {
                     }
                     .into());
                 }
+                if let Some(protection) = &mem.protection {
+                    protection.store(addr, 4)?;
+                }
+                self.hpm.count(HpmEvent::Atomics);
+                dcache!(addr);
                 if self.amo_rsv.take() == Some(addr) {
-                    mem.store::<u32>(addr, reg!(sc_w.rs2(inst)));
+                    let val = reg!(sc_w.rs2(inst));
+                    watch!(MemoryAccess::Store, addr, 4, val);
+                    mem.store::<u32>(addr, val);
+                    if let Some(trace) = &mut self.inst_trace {
+                        trace.record_store(addr, 4);
+                    }
                     reg!(sc_w.rd(inst), 0);
                 } else {
                     reg!(sc_w.rd(inst), 1);
                 }
             }
-            Rv32IMASC::AmoswapW(swap) => amo_op!(|swap, old, rs2| rs2),
-            Rv32IMASC::AmoaddW(add) => amo_op!(|add, old, rs2| old.wrapping_add(rs2)),
-            Rv32IMASC::AmoxorW(xor) => amo_op!(|xor, old, rs2| old ^ rs2),
-            Rv32IMASC::AmoorW(or) => amo_op!(|or, old, rs2| old | rs2),
-            Rv32IMASC::AmoandW(and) => amo_op!(|and, old, rs2| old & rs2),
-            Rv32IMASC::AmominW(min) => amo_op!(|min, old, rs2| (old as i32).min(rs2 as i32)),
-            Rv32IMASC::AmomaxW(max) => amo_op!(|max, old, rs2| (old as i32).max(rs2 as i32)),
-            Rv32IMASC::AmominuW(minu) => amo_op!(|minu, old, rs2| old.min(rs2)),
-            Rv32IMASC::AmomaxuW(maxu) => amo_op!(|maxu, old, rs2| old.max(rs2)),
-            Rv32IMASC::CAddi4spn(addi4spn) => {
+            Rv32IMASFDC::AmoswapW(swap) => amo_op!(|swap, old, rs2| rs2),
+            Rv32IMASFDC::AmoaddW(add) => amo_op!(|add, old, rs2| old.wrapping_add(rs2)),
+            Rv32IMASFDC::AmoxorW(xor) => amo_op!(|xor, old, rs2| old ^ rs2),
+            Rv32IMASFDC::AmoorW(or) => amo_op!(|or, old, rs2| old | rs2),
+            Rv32IMASFDC::AmoandW(and) => amo_op!(|and, old, rs2| old & rs2),
+            Rv32IMASFDC::AmominW(min) => amo_op!(|min, old, rs2| (old as i32).min(rs2 as i32)),
+            Rv32IMASFDC::AmomaxW(max) => amo_op!(|max, old, rs2| (old as i32).max(rs2 as i32)),
+            Rv32IMASFDC::AmominuW(minu) => amo_op!(|minu, old, rs2| old.min(rs2)),
+            Rv32IMASFDC::AmomaxuW(maxu) => amo_op!(|maxu, old, rs2| old.max(rs2)),
+            Rv32IMASFDC::CAddi4spn(addi4spn) => {
                 let imm = addi4spn.imm(inst);
+                reserved_if!(imm == 0);
                 let rd = addi4spn.rd(inst);
-                self.set_reg(rd, reg!(Reg::Sp).wrapping_add(imm));
+                reg!(rd, reg!(Reg::Sp).wrapping_add(imm));
             }
-            Rv32IMASC::CLw(lw) => {
+            Rv32IMASFDC::CLw(lw) => {
                 let addr = reg!(lw.rs1(inst)).wrapping_add(lw.imm(inst));
-                reg!(lw.rd(inst), mem.load::<u32>(addr));
+                reg!(lw.rd(inst), load!(u32, addr));
             }
-            Rv32IMASC::CSw(sw) => {
+            Rv32IMASFDC::CSw(sw) => {
                 let addr = reg!(sw.rs1(inst)).wrapping_add(sw.imm(inst));
-                mem.store::<u32>(addr, reg!(sw.rs2(inst)));
+                store!(u32, addr, reg!(sw.rs2(inst)));
             }
-            Rv32IMASC::CAddi(caddi) => {
+            Rv32IMASFDC::CAddi(caddi) => {
                 let rs1rd = caddi.rs1rd(inst);
-                self.set_reg(rs1rd, reg!(rs1rd).wrapping_add_signed(caddi.imm(inst)));
+                reg!(rs1rd, reg!(rs1rd).wrapping_add_signed(caddi.imm(inst)));
             }
-            Rv32IMASC::CAddi16sp(caddi16sp) => {
+            Rv32IMASFDC::CAddi16sp(caddi16sp) => {
                 let imm = caddi16sp.imm(inst);
+                reserved_if!(imm == 0);
                 let rs1rd = caddi16sp.rs1rd(inst);
-                self.set_reg(rs1rd, reg!(Reg::Sp).wrapping_add_signed(imm));
+                reg!(rs1rd, reg!(Reg::Sp).wrapping_add_signed(imm));
             }
-            Rv32IMASC::CLwsp(lwsp) => {
+            Rv32IMASFDC::CLwsp(lwsp) => {
+                reserved_if!(lwsp.rd(inst) == Reg::Zero);
                 let addr = reg!(Reg::Sp).wrapping_add(lwsp.imm(inst));
-                reg!(lwsp.rd(inst), mem.load::<u32>(addr));
+                reg!(lwsp.rd(inst), load!(u32, addr));
             }
-            Rv32IMASC::CSwsp(swsp) => {
+            Rv32IMASFDC::CSwsp(swsp) => {
                 let addr = reg!(Reg::Sp).wrapping_add(swsp.imm(inst));
-                mem.store::<u32>(addr, reg!(swsp.rs2(inst)));
+                store!(u32, addr, reg!(swsp.rs2(inst)));
             }
-            Rv32IMASC::CNop(_) => {}
-            Rv32IMASC::CJal(cjal) => {
+            Rv32IMASFDC::CNop(_) => {}
+            Rv32IMASFDC::CJal(cjal) => {
+                track_jump!(Reg::Ra, None, self.pc.wrapping_add_signed(cjal.imm(inst)));
                 reg!(Reg::Ra, next_pc);
                 next_pc = self.pc.wrapping_add_signed(cjal.imm(inst));
             }
-            Rv32IMASC::CLi(cli) => reg!(cli.rs1rd(inst), cli.imm(inst)),
-            Rv32IMASC::CLui(clui) => reg!(clui.rd(inst), clui.imm(inst)),
-            Rv32IMASC::CSrli(csrli) => {
+            Rv32IMASFDC::CLi(cli) => reg!(cli.rs1rd(inst), cli.imm(inst)),
+            // With rd = x0 it's a hint, and does nothing
+            Rv32IMASFDC::CLui(clui) => {
+                reserved_if!(clui.imm(inst) == 0);
+                reg!(clui.rd(inst), clui.imm(inst));
+            }
+            Rv32IMASFDC::CSrli(csrli) => {
                 let rd = csrli.rs1rd(inst);
                 reg!(rd, reg!(rd) >> csrli.shamt(inst));
             }
-            Rv32IMASC::CSrai(csrai) => {
+            Rv32IMASFDC::CSrai(csrai) => {
                 let rd = csrai.rs1rd(inst);
                 reg!(rd, (reg!(rd) as i32) >> csrai.shamt(inst));
             }
-            Rv32IMASC::CAndi(candi) => {
+            Rv32IMASFDC::CAndi(candi) => {
                 let rd = candi.rs1rd(inst);
                 reg!(rd, reg!(rd) & candi.imm(inst) as u32);
             }
-            Rv32IMASC::CSub(csub) => {
+            Rv32IMASFDC::CSub(csub) => {
                 let rs1rd = csub.rs1rd(inst);
                 let rs2 = reg!(csub.rs2(inst));
                 reg!(rs1rd, reg!(rs1rd).wrapping_sub(rs2));
             }
-            Rv32IMASC::CXor(cxor) => {
+            Rv32IMASFDC::CXor(cxor) => {
                 let rs1rd = cxor.rs1rd(inst);
                 let rs2 = reg!(cxor.rs2(inst));
                 reg!(rs1rd, reg!(rs1rd) ^ rs2);
             }
-            Rv32IMASC::COr(cor) => {
+            Rv32IMASFDC::COr(cor) => {
                 let rs1rd = cor.rs1rd(inst);
                 let rs2 = reg!(cor.rs2(inst));
                 reg!(rs1rd, reg!(rs1rd) | rs2);
             }
-            Rv32IMASC::CAnd(cand) => {
+            Rv32IMASFDC::CAnd(cand) => {
                 let rs1rd = cand.rs1rd(inst);
                 let rs2 = reg!(cand.rs2(inst));
                 reg!(rs1rd, reg!(rs1rd) & rs2);
             }
-            Rv32IMASC::CJ(cj) => {
+            Rv32IMASFDC::CJ(cj) => {
                 next_pc = self.pc.wrapping_add_signed(cj.imm(inst));
             }
-            Rv32IMASC::CBeqz(cbeqz) => {
-                if reg!(cbeqz.rs1(inst)) == 0 {
+            Rv32IMASFDC::CBeqz(cbeqz) => {
+                let taken = reg!(cbeqz.rs1(inst)) == 0;
+                self.branch(cbeqz.imm(inst), taken);
+                if taken {
                     next_pc = self.pc.wrapping_add_signed(cbeqz.imm(inst));
                 }
             }
-            Rv32IMASC::CBnez(cbnez) => {
-                if reg!(cbnez.rs1(inst)) != 0 {
+            Rv32IMASFDC::CBnez(cbnez) => {
+                let taken = reg!(cbnez.rs1(inst)) != 0;
+                self.branch(cbnez.imm(inst), taken);
+                if taken {
                     next_pc = self.pc.wrapping_add_signed(cbnez.imm(inst));
                 }
             }
-            Rv32IMASC::CSlli(cslli) => {
+            Rv32IMASFDC::CSlli(cslli) => {
                 let rd = cslli.rs1rd(inst);
                 reg!(rd, reg!(rd) << cslli.shamt(inst));
             }
-            Rv32IMASC::CJr(cjr) => {
+            Rv32IMASFDC::CJr(cjr) => {
+                reserved_if!(cjr.rs1(inst) == Reg::Zero);
+                track_jump!(Reg::Zero, Some(cjr.rs1(inst)), reg!(cjr.rs1(inst)));
                 next_pc = reg!(cjr.rs1(inst));
             }
-            Rv32IMASC::CMv(cmv) => reg!(cmv.rd(inst), reg!(cmv.rs2(inst))),
-            Rv32IMASC::CEbreak(_) => match kernel.ebreak(self, mem)? {
-                StepResult::Ok => {}
-                res => return Ok(res),
-            },
-            Rv32IMASC::CJalr(cjalr) => {
+            Rv32IMASFDC::CMv(cmv) => reg!(cmv.rd(inst), reg!(cmv.rs2(inst))),
+            Rv32IMASFDC::CEbreak(_) => ebreak!(),
+            Rv32IMASFDC::CJalr(cjalr) => {
+                track_jump!(Reg::Ra, Some(cjalr.rs1(inst)), reg!(cjalr.rs1(inst)));
                 reg!(Reg::Ra, next_pc);
                 next_pc = reg!(cjalr.rs1(inst));
             }
-            Rv32IMASC::CAdd(cadd) => {
+            Rv32IMASFDC::CAdd(cadd) => {
                 let rs1rd = cadd.rs1rd(inst);
                 reg!(rs1rd, reg!(rs1rd).wrapping_add(reg!(cadd.rs2(inst))));
             }
-            Rv32IMASC::CUnimp(_) => {
+            Rv32IMASFDC::CUnimp(_) => {
                 return Err(HartError::IllegalInst {
                     addr: self.pc,
                     inst,
                 }
                 .into())
             }
+            Rv32IMASFDC::Aes32esi(aes) => {
+                reg_reg_op!(|aes.rs1, aes.rs2| crypto::aes32esi(rs1, rs2, aes.bs(inst)))
+            }
+            Rv32IMASFDC::Aes32esmi(aes) => {
+                reg_reg_op!(|aes.rs1, aes.rs2| crypto::aes32esmi(rs1, rs2, aes.bs(inst)))
+            }
+            Rv32IMASFDC::Aes32dsi(aes) => {
+                reg_reg_op!(|aes.rs1, aes.rs2| crypto::aes32dsi(rs1, rs2, aes.bs(inst)))
+            }
+            Rv32IMASFDC::Aes32dsmi(aes) => {
+                reg_reg_op!(|aes.rs1, aes.rs2| crypto::aes32dsmi(rs1, rs2, aes.bs(inst)))
+            }
+            Rv32IMASFDC::Sha256sig0(sha) => {
+                reg!(sha.rd(inst), crypto::sha256sig0(reg!(sha.rs1(inst))))
+            }
+            Rv32IMASFDC::Sha256sig1(sha) => {
+                reg!(sha.rd(inst), crypto::sha256sig1(reg!(sha.rs1(inst))))
+            }
+            Rv32IMASFDC::Sha256sum0(sha) => {
+                reg!(sha.rd(inst), crypto::sha256sum0(reg!(sha.rs1(inst))))
+            }
+            Rv32IMASFDC::Sha256sum1(sha) => {
+                reg!(sha.rd(inst), crypto::sha256sum1(reg!(sha.rs1(inst))))
+            }
+            Rv32IMASFDC::Sha512sig0h(sha) => {
+                reg_reg_op!(|sha.rs1, sha.rs2| crypto::sha512sig0h(rs1, rs2))
+            }
+            Rv32IMASFDC::Sha512sig0l(sha) => {
+                reg_reg_op!(|sha.rs1, sha.rs2| crypto::sha512sig0l(rs1, rs2))
+            }
+            Rv32IMASFDC::Sha512sig1h(sha) => {
+                reg_reg_op!(|sha.rs1, sha.rs2| crypto::sha512sig1h(rs1, rs2))
+            }
+            Rv32IMASFDC::Sha512sig1l(sha) => {
+                reg_reg_op!(|sha.rs1, sha.rs2| crypto::sha512sig1l(rs1, rs2))
+            }
+            Rv32IMASFDC::Sha512sum0r(sha) => {
+                reg_reg_op!(|sha.rs1, sha.rs2| crypto::sha512sum0r(rs1, rs2))
+            }
+            Rv32IMASFDC::Sha512sum1r(sha) => {
+                reg_reg_op!(|sha.rs1, sha.rs2| crypto::sha512sum1r(rs1, rs2))
+            }
+            Rv32IMASFDC::Flw(flw) => {
+                let addr = reg!(flw.rs1(inst)).wrapping_add_signed(flw.imm(inst));
+                let val = load!(u32, addr);
+                self.set_fp_reg(F32, flw.frd(inst), val as u64);
+            }
+            // Transfers keep the bits, boxed or not
+            Rv32IMASFDC::Fsw(fsw) => {
+                let addr = reg!(fsw.rs1(inst)).wrapping_add_signed(fsw.imm(inst));
+                store!(u32, addr, self.get_freg(fsw.frs2(inst)) as u32);
+            }
+            Rv32IMASFDC::FmaddS(fma) => fp_fma_op!(F32, fma, false, false),
+            Rv32IMASFDC::FmsubS(fma) => fp_fma_op!(F32, fma, false, true),
+            Rv32IMASFDC::FnmsubS(fma) => fp_fma_op!(F32, fma, true, false),
+            Rv32IMASFDC::FnmaddS(fma) => fp_fma_op!(F32, fma, true, true),
+            Rv32IMASFDC::FaddS(op) => {
+                fp_binary_op!(F32, fp_env!(op), |op, env, a, b| env.add(F32, a, b))
+            }
+            Rv32IMASFDC::FsubS(op) => {
+                fp_binary_op!(F32, fp_env!(op), |op, env, a, b| env.sub(F32, a, b))
+            }
+            Rv32IMASFDC::FmulS(op) => {
+                fp_binary_op!(F32, fp_env!(op), |op, env, a, b| env.mul(F32, a, b))
+            }
+            Rv32IMASFDC::FdivS(op) => {
+                fp_binary_op!(F32, fp_env!(op), |op, env, a, b| env.div(F32, a, b))
+            }
+            Rv32IMASFDC::FsqrtS(op) => {
+                fp_unary_op!(F32, fp_env!(op), |op, env, a| env.sqrt(F32, a))
+            }
+            Rv32IMASFDC::FsgnjS(op) => {
+                fp_binary_op!(F32, fp_env!(), |op, _env, a, b| sign_inject(
+                    F32,
+                    a,
+                    b,
+                    SignOp::Copy
+                ))
+            }
+            Rv32IMASFDC::FsgnjnS(op) => {
+                fp_binary_op!(F32, fp_env!(), |op, _env, a, b| sign_inject(
+                    F32,
+                    a,
+                    b,
+                    SignOp::Negate
+                ))
+            }
+            Rv32IMASFDC::FsgnjxS(op) => {
+                fp_binary_op!(F32, fp_env!(), |op, _env, a, b| sign_inject(
+                    F32,
+                    a,
+                    b,
+                    SignOp::Xor
+                ))
+            }
+            Rv32IMASFDC::FminS(op) => {
+                fp_binary_op!(F32, fp_env!(), |op, env, a, b| env
+                    .min_max(F32, a, b, false))
+            }
+            Rv32IMASFDC::FmaxS(op) => {
+                fp_binary_op!(F32, fp_env!(), |op, env, a, b| env.min_max(F32, a, b, true))
+            }
+            Rv32IMASFDC::FleS(op) => fp_cmp_op!(F32, |op, env, a, b| env.lt(F32, a, b, true)),
+            Rv32IMASFDC::FltS(op) => fp_cmp_op!(F32, |op, env, a, b| env.lt(F32, a, b, false)),
+            Rv32IMASFDC::FeqS(op) => fp_cmp_op!(F32, |op, env, a, b| env.eq(F32, a, b)),
+            Rv32IMASFDC::FcvtWS(op) => {
+                let a = self.fp_reg(F32, op.frs1(inst));
+                reg!(
+                    op.rd(inst),
+                    fp_op!(fp_env!(op), |env| env.float_to_int(F32, a, true))
+                );
+            }
+            Rv32IMASFDC::FcvtWuS(op) => {
+                let a = self.fp_reg(F32, op.frs1(inst));
+                reg!(
+                    op.rd(inst),
+                    fp_op!(fp_env!(op), |env| env.float_to_int(F32, a, false))
+                );
+            }
+            Rv32IMASFDC::FcvtSW(op) => {
+                let a = reg!(op.rs1(inst));
+                let res = fp_op!(fp_env!(op), |env| env.int_to_float(F32, a, true));
+                self.set_fp_reg(F32, op.frd(inst), res);
+            }
+            Rv32IMASFDC::FcvtSWu(op) => {
+                let a = reg!(op.rs1(inst));
+                let res = fp_op!(fp_env!(op), |env| env.int_to_float(F32, a, false));
+                self.set_fp_reg(F32, op.frd(inst), res);
+            }
+            Rv32IMASFDC::FmvXS(op) => reg!(op.rd(inst), self.get_freg(op.frs1(inst)) as u32),
+            Rv32IMASFDC::FclassS(op) => {
+                reg!(op.rd(inst), classify(F32, self.fp_reg(F32, op.frs1(inst))))
+            }
+            Rv32IMASFDC::FmvSX(op) => self.set_fp_reg(F32, op.frd(inst), reg!(op.rs1(inst)) as u64),
+            Rv32IMASFDC::Fld(fld) => {
+                let addr = reg!(fld.rs1(inst)).wrapping_add_signed(fld.imm(inst));
+                let val = load!(u64, addr);
+                self.set_fp_reg(F64, fld.frd(inst), val);
+            }
+            Rv32IMASFDC::Fsd(fsd) => {
+                let addr = reg!(fsd.rs1(inst)).wrapping_add_signed(fsd.imm(inst));
+                store!(u64, addr, self.get_freg(fsd.frs2(inst)));
+            }
+            Rv32IMASFDC::FmaddD(fma) => fp_fma_op!(F64, fma, false, false),
+            Rv32IMASFDC::FmsubD(fma) => fp_fma_op!(F64, fma, false, true),
+            Rv32IMASFDC::FnmsubD(fma) => fp_fma_op!(F64, fma, true, false),
+            Rv32IMASFDC::FnmaddD(fma) => fp_fma_op!(F64, fma, true, true),
+            Rv32IMASFDC::FaddD(op) => {
+                fp_binary_op!(F64, fp_env!(op), |op, env, a, b| env.add(F64, a, b))
+            }
+            Rv32IMASFDC::FsubD(op) => {
+                fp_binary_op!(F64, fp_env!(op), |op, env, a, b| env.sub(F64, a, b))
+            }
+            Rv32IMASFDC::FmulD(op) => {
+                fp_binary_op!(F64, fp_env!(op), |op, env, a, b| env.mul(F64, a, b))
+            }
+            Rv32IMASFDC::FdivD(op) => {
+                fp_binary_op!(F64, fp_env!(op), |op, env, a, b| env.div(F64, a, b))
+            }
+            Rv32IMASFDC::FsqrtD(op) => {
+                fp_unary_op!(F64, fp_env!(op), |op, env, a| env.sqrt(F64, a))
+            }
+            Rv32IMASFDC::FsgnjD(op) => {
+                fp_binary_op!(F64, fp_env!(), |op, _env, a, b| sign_inject(
+                    F64,
+                    a,
+                    b,
+                    SignOp::Copy
+                ))
+            }
+            Rv32IMASFDC::FsgnjnD(op) => {
+                fp_binary_op!(F64, fp_env!(), |op, _env, a, b| sign_inject(
+                    F64,
+                    a,
+                    b,
+                    SignOp::Negate
+                ))
+            }
+            Rv32IMASFDC::FsgnjxD(op) => {
+                fp_binary_op!(F64, fp_env!(), |op, _env, a, b| sign_inject(
+                    F64,
+                    a,
+                    b,
+                    SignOp::Xor
+                ))
+            }
+            Rv32IMASFDC::FminD(op) => {
+                fp_binary_op!(F64, fp_env!(), |op, env, a, b| env
+                    .min_max(F64, a, b, false))
+            }
+            Rv32IMASFDC::FmaxD(op) => {
+                fp_binary_op!(F64, fp_env!(), |op, env, a, b| env.min_max(F64, a, b, true))
+            }
+            Rv32IMASFDC::FcvtSD(op) => {
+                let a = self.fp_reg(F64, op.frs1(inst));
+                let res = fp_op!(fp_env!(op), |env| env.convert(F64, F32, a));
+                self.set_fp_reg(F32, op.frd(inst), res);
+            }
+            Rv32IMASFDC::FcvtDS(op) => {
+                let a = self.fp_reg(F32, op.frs1(inst));
+                let res = fp_op!(fp_env!(op), |env| env.convert(F32, F64, a));
+                self.set_fp_reg(F64, op.frd(inst), res);
+            }
+            Rv32IMASFDC::FleD(op) => fp_cmp_op!(F64, |op, env, a, b| env.lt(F64, a, b, true)),
+            Rv32IMASFDC::FltD(op) => fp_cmp_op!(F64, |op, env, a, b| env.lt(F64, a, b, false)),
+            Rv32IMASFDC::FeqD(op) => fp_cmp_op!(F64, |op, env, a, b| env.eq(F64, a, b)),
+            Rv32IMASFDC::FcvtWD(op) => {
+                let a = self.fp_reg(F64, op.frs1(inst));
+                reg!(
+                    op.rd(inst),
+                    fp_op!(fp_env!(op), |env| env.float_to_int(F64, a, true))
+                );
+            }
+            Rv32IMASFDC::FcvtWuD(op) => {
+                let a = self.fp_reg(F64, op.frs1(inst));
+                reg!(
+                    op.rd(inst),
+                    fp_op!(fp_env!(op), |env| env.float_to_int(F64, a, false))
+                );
+            }
+            Rv32IMASFDC::FcvtDW(op) => {
+                let a = reg!(op.rs1(inst));
+                let res = fp_op!(fp_env!(op), |env| env.int_to_float(F64, a, true));
+                self.set_fp_reg(F64, op.frd(inst), res);
+            }
+            Rv32IMASFDC::FcvtDWu(op) => {
+                let a = reg!(op.rs1(inst));
+                let res = fp_op!(fp_env!(op), |env| env.int_to_float(F64, a, false));
+                self.set_fp_reg(F64, op.frd(inst), res);
+            }
+            Rv32IMASFDC::FclassD(op) => {
+                reg!(op.rd(inst), classify(F64, self.fp_reg(F64, op.frs1(inst))))
+            }
+            Rv32IMASFDC::CFlw(flw) => {
+                needs!(Extension::F);
+                let addr = reg!(flw.rs1(inst)).wrapping_add(flw.imm(inst));
+                let val = load!(u32, addr);
+                self.set_fp_reg(F32, flw.frd(inst), val as u64);
+            }
+            Rv32IMASFDC::CFsw(fsw) => {
+                needs!(Extension::F);
+                let addr = reg!(fsw.rs1(inst)).wrapping_add(fsw.imm(inst));
+                store!(u32, addr, self.get_freg(fsw.frs2(inst)) as u32);
+            }
+            Rv32IMASFDC::CFlwsp(flwsp) => {
+                needs!(Extension::F);
+                let addr = reg!(Reg::Sp).wrapping_add(flwsp.imm(inst));
+                let val = load!(u32, addr);
+                self.set_fp_reg(F32, flwsp.frd(inst), val as u64);
+            }
+            Rv32IMASFDC::CFswsp(fswsp) => {
+                needs!(Extension::F);
+                let addr = reg!(Reg::Sp).wrapping_add(fswsp.imm(inst));
+                store!(u32, addr, self.get_freg(fswsp.frs2(inst)) as u32);
+            }
+            Rv32IMASFDC::CFld(fld) => {
+                needs!(Extension::D);
+                let addr = reg!(fld.rs1(inst)).wrapping_add(fld.imm(inst));
+                let val = load!(u64, addr);
+                self.set_fp_reg(F64, fld.frd(inst), val);
+            }
+            Rv32IMASFDC::CFsd(fsd) => {
+                needs!(Extension::D);
+                let addr = reg!(fsd.rs1(inst)).wrapping_add(fsd.imm(inst));
+                store!(u64, addr, self.get_freg(fsd.frs2(inst)));
+            }
+            Rv32IMASFDC::CFldsp(fldsp) => {
+                needs!(Extension::D);
+                let addr = reg!(Reg::Sp).wrapping_add(fldsp.imm(inst));
+                let val = load!(u64, addr);
+                self.set_fp_reg(F64, fldsp.frd(inst), val);
+            }
+            Rv32IMASFDC::CFsdsp(fsdsp) => {
+                needs!(Extension::D);
+                let addr = reg!(Reg::Sp).wrapping_add(fsdsp.imm(inst));
+                store!(u64, addr, self.get_freg(fsdsp.frs2(inst)));
+            }
+            // Only custom instructions are left, if any were declared
+            #[allow(unreachable_patterns)]
+            _ => match self.custom(mem, op, inst)? {
+                StepResult::Ok => {}
+                res => return Ok(res),
+            },
         }
 
+        if let Some(trace) = &mut self.pipeline_trace {
+            trace.retire(self.pc, inst, &op, &self.hpm);
+        }
+        if let Some(trace) = &mut self.inst_trace {
+            trace.retire(self.pc, inst);
+        }
         self.inst_count += 1;
         self.pc = next_pc;
 
+        if watched {
+            return Ok(StepResult::Watchpoint);
+        }
         Ok(StepResult::Ok)
     }
 }
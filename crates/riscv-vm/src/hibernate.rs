@@ -0,0 +1,439 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib_with_limit};
+
+use crate::{
+    error::HibernateError,
+    hart::HartContext,
+    hpm::Hpm,
+    machine::{Kernel, Machine, MachineState},
+    memory::PAGE_SIZE,
+    protect::PAGES,
+};
+
+pub const MAGIC: [u8; 8] = *b"DRSCHIBR";
+/// Version of the format written, and the newest one read.
+pub const FORMAT_VERSION: u16 = 1;
+/// Oldest reader version that can read what's written.
+const COMPAT_VERSION: u16 = 1;
+
+/// The payload is a zlib stream.
+pub const SECTION_COMPRESSED: u32 = 1 << 0;
+/// Readers that don't know the section must reject the image.
+pub const SECTION_REQUIRED: u32 = 1 << 1;
+
+/// Flags of the sections written.
+pub(crate) const REQUIRED: u32 = SECTION_COMPRESSED | SECTION_REQUIRED;
+
+pub(crate) const HART: [u8; 4] = *b"HART";
+pub(crate) const MEM: [u8; 4] = *b"MEM ";
+pub(crate) const KERNEL: [u8; 4] = *b"KERN";
+pub(crate) const FP: [u8; 4] = *b"FP  ";
+pub(crate) const HPM: [u8; 4] = *b"HPM ";
+pub(crate) const PROT: [u8; 4] = *b"PROT";
+pub(crate) const END: [u8; 4] = *b"END ";
+
+/// zlib level, favoring speed since images are mostly zero pages and code.
+const COMPRESSION_LEVEL: u8 = 1;
+const CSR_COUNT: usize = 4096;
+
+fn section_name(tag: [u8; 4]) -> String {
+    String::from_utf8_lossy(&tag).trim_end().to_string()
+}
+
+pub(crate) fn write_header(w: &mut impl Write) -> std::io::Result<()> {
+    w.write_all(&MAGIC)?;
+    w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    w.write_all(&COMPAT_VERSION.to_le_bytes())?;
+    w.write_all(&[0; 4])
+}
+
+pub(crate) fn write_section(
+    w: &mut impl Write,
+    tag: [u8; 4],
+    flags: u32,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let compressed;
+    let stored = if flags & SECTION_COMPRESSED != 0 {
+        compressed = compress_to_vec_zlib(payload, COMPRESSION_LEVEL);
+        &compressed
+    } else {
+        payload
+    };
+    w.write_all(&tag)?;
+    w.write_all(&flags.to_le_bytes())?;
+    w.write_all(&(payload.len() as u64).to_le_bytes())?;
+    w.write_all(&(stored.len() as u64).to_le_bytes())?;
+    w.write_all(stored)
+}
+
+/// Reads fields out of a section payload.
+struct Fields<'a> {
+    section: [u8; 4],
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn corrupt(&self, reason: &str) -> HibernateError {
+        HibernateError::Corrupt {
+            section: section_name(self.section),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], HibernateError> {
+        if self.data.len() < len {
+            return Err(self.corrupt("truncated"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, HibernateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, HibernateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, HibernateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn finish(&self) -> Result<(), HibernateError> {
+        if !self.data.is_empty() {
+            return Err(self.corrupt("trailing data"));
+        }
+        Ok(())
+    }
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// A section's tag and decompressed payload.
+type Section = ([u8; 4], Vec<u8>);
+
+/// Read the known sections of an image, checking the header first.
+fn read_sections(r: &mut impl Read) -> Result<Vec<Section>, HibernateError> {
+    if read_array::<8>(r)? != MAGIC {
+        return Err(HibernateError::BadMagic);
+    }
+    let version = u16::from_le_bytes(read_array(r)?);
+    let compat = u16::from_le_bytes(read_array(r)?);
+    read_array::<4>(r)?;
+    if compat > FORMAT_VERSION {
+        return Err(HibernateError::TooNew { version, compat });
+    }
+
+    let mut sections = vec![];
+    loop {
+        let tag = read_array::<4>(r)?;
+        let flags = u32::from_le_bytes(read_array(r)?);
+        let len = u64::from_le_bytes(read_array(r)?);
+        let stored_len = u64::from_le_bytes(read_array(r)?);
+        if tag == END {
+            return Ok(sections);
+        }
+
+        let mut stored = vec![];
+        r.take(stored_len).read_to_end(&mut stored)?;
+        if stored.len() as u64 != stored_len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        if ![HART, FP, HPM, PROT, MEM, KERNEL].contains(&tag) {
+            if flags & SECTION_REQUIRED != 0 {
+                return Err(HibernateError::UnknownSection(section_name(tag)));
+            }
+            continue;
+        }
+        let payload = if flags & SECTION_COMPRESSED != 0 {
+            decompress_to_vec_zlib_with_limit(&stored, len as usize).map_err(|_| {
+                HibernateError::Corrupt {
+                    section: section_name(tag),
+                    reason: "bad zlib stream".to_string(),
+                }
+            })?
+        } else {
+            stored
+        };
+        if payload.len() as u64 != len {
+            return Err(HibernateError::Corrupt {
+                section: section_name(tag),
+                reason: "wrong length".to_string(),
+            });
+        }
+        sections.push((tag, payload));
+    }
+}
+
+impl<K: Kernel> Machine<K> {
+    /// Write the guest's state to `w` as a versioned image, which
+    /// [`Machine::restore`] can load into a machine in another process or
+    /// built by a later version of this crate.
+    ///
+    /// Fails with [`HibernateError::KernelUnsupported`] if the kernel can't
+    /// save its state.
+    ///
+    /// An image is a header followed by sections, all little-endian:
+    ///
+    /// ```text
+    /// header   magic: [u8; 8] = "DRSCHIBR"
+    ///          version: u16       format version the image was written with
+    ///          compat: u16        oldest format version that can read it
+    ///          reserved: u32
+    /// section  tag: [u8; 4]
+    ///          flags: u32         SECTION_COMPRESSED | SECTION_REQUIRED
+    ///          len: u64           payload length once decompressed
+    ///          stored_len: u64    payload length in the image
+    ///          payload: [u8; stored_len]
+    /// ```
+    ///
+    /// The last section is `END `. Compressed payloads are zlib streams. A
+    /// reader rejects images whose `compat` is newer than its own
+    /// [`FORMAT_VERSION`], and required sections it doesn't know, but skips
+    /// other unknown sections, so later versions can add optional state
+    /// without breaking older readers.
+    ///
+    /// The sections are:
+    ///
+    /// - `HART`: pc: u32, inst_count: u64, has_reservation: u8,
+    ///   reservation: u32, halted: u8, regs: [u32; 32], csrs: [u32; 4096]
+    /// - `FP  `, optional: fregs: [u64; 32]. Images without it restore with
+    ///   the F and D registers zeroed.
+    /// - `HPM `, optional: events: [u64; 11], selectors: [u32; 32],
+    ///   offsets: [u64; 32], inhibit: u32, frozen: [u64; 32], the
+    ///   performance counters as [`Hpm`] keeps them. Images without it
+    ///   restore the counters from their CSRs in `HART`, losing the totals
+    ///   behind [`Hpm::event_count`].
+    /// - `PROT`, only with [page protection](crate::protect) on: a byte per
+    ///   page, `1` if writable, `2` if executable and `4` if poisoned. It's
+    ///   ignored when restoring into a machine without page protection.
+    /// - `MEM `: brk: u32, mmap_top: u32, count: u32, then `count` resident
+    ///   pages as address: u32 and [`PAGE_SIZE`] bytes. There may be several,
+    ///   as in a [`PreCopy`](crate::migrate::PreCopy) stream, in which case
+    ///   later pages replace earlier ones, and the last one has the layout.
+    /// - `KERN`: whatever [`Kernel::save_state`] returned
+    ///
+    /// Host-side state isn't saved: configuration, symbols, interceptors, MMIO
+    /// devices and simulators, including the cache simulator and branch
+    /// predictor, are the embedder's to set up again before restoring.
+    pub fn hibernate(&self, mut w: impl Write) -> Result<(), HibernateError> {
+        let kernel = self.kernel_state()?;
+        write_header(&mut w)?;
+        self.write_hart_sections(&mut w)?;
+        let pages = self.mem.resident_page_addrs()?;
+        write_section(&mut w, MEM, REQUIRED, &self.mem_state(&pages))?;
+        write_section(&mut w, KERNEL, REQUIRED, &kernel)?;
+        write_section(&mut w, END, 0, &[])?;
+        w.flush()?;
+
+        Ok(())
+    }
+
+    pub(crate) fn kernel_state(&self) -> Result<Vec<u8>, HibernateError> {
+        self.kernel
+            .save_state()
+            .ok_or(HibernateError::KernelUnsupported)
+    }
+
+    /// Write the `HART`, `FP  `, `HPM ` and `PROT` sections.
+    pub(crate) fn write_hart_sections(&self, w: &mut impl Write) -> std::io::Result<()> {
+        write_section(w, HART, REQUIRED, &self.hart_state())?;
+        write_section(w, FP, SECTION_COMPRESSED, &self.fp_state())?;
+        write_section(w, HPM, SECTION_COMPRESSED, &self.hpm_state())?;
+        if let Some(protection) = &self.mem.protection {
+            write_section(w, PROT, REQUIRED, &protection.to_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Payload of the `HART` section.
+    pub(crate) fn hart_state(&self) -> Vec<u8> {
+        let mut hart = vec![];
+        let context = self.hart.context();
+        hart.extend(context.pc.to_le_bytes());
+        hart.extend(self.hart.inst_count.to_le_bytes());
+        hart.push(self.hart.amo_rsv.is_some() as u8);
+        hart.extend(self.hart.amo_rsv.unwrap_or(0).to_le_bytes());
+        hart.push((self.state == MachineState::Halted) as u8);
+        hart.extend(context.regs.iter().flat_map(|reg| reg.to_le_bytes()));
+        hart.extend((0..CSR_COUNT as u16).flat_map(|csr| self.hart.csr(csr).to_le_bytes()));
+
+        hart
+    }
+
+    /// Payload of the `FP  ` section.
+    pub(crate) fn fp_state(&self) -> Vec<u8> {
+        let context = self.hart.context();
+        context
+            .fregs
+            .iter()
+            .flat_map(|reg| reg.to_le_bytes())
+            .collect()
+    }
+
+    /// Payload of the `HPM ` section.
+    fn hpm_state(&self) -> Vec<u8> {
+        let hpm = &self.hart.hpm;
+        let mut state = vec![];
+        state.extend(hpm.events.iter().flat_map(|n| n.to_le_bytes()));
+        state.extend(hpm.selectors.iter().flat_map(|n| n.to_le_bytes()));
+        state.extend(hpm.offsets.iter().flat_map(|n| n.to_le_bytes()));
+        state.extend(hpm.inhibit.to_le_bytes());
+        state.extend(hpm.frozen.iter().flat_map(|n| n.to_le_bytes()));
+
+        state
+    }
+
+    /// Payload of a `MEM ` section with the pages at `pages`.
+    pub(crate) fn mem_state(&self, pages: &[u32]) -> Vec<u8> {
+        let mut mem = Vec::with_capacity(12 + pages.len() * (4 + PAGE_SIZE));
+        mem.extend(self.mem.brk.to_le_bytes());
+        mem.extend(self.mem.mmap_top.to_le_bytes());
+        mem.extend((pages.len() as u32).to_le_bytes());
+        for &addr in pages {
+            mem.extend(addr.to_le_bytes());
+            mem.extend(self.mem.slice::<u8>(addr, PAGE_SIZE as u32).unwrap());
+        }
+
+        mem
+    }
+
+    /// Replace the guest's state with an image written by
+    /// [`Machine::hibernate`].
+    ///
+    /// The machine should be configured like the one that was hibernated.
+    /// Nothing changes unless the whole image is valid, though the kernel
+    /// may be left half restored if it rejects its state.
+    pub fn restore(&mut self, mut r: impl Read) -> Result<(), HibernateError> {
+        let sections = read_sections(&mut r)?;
+        let section = |tag| {
+            sections
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map(|(_, payload)| Fields {
+                    section: tag,
+                    data: payload,
+                })
+                .ok_or_else(|| HibernateError::MissingSection(section_name(tag)))
+        };
+
+        let mut hart = section(HART)?;
+        let pc = hart.u32()?;
+        let inst_count = hart.u64()?;
+        let has_rsv = hart.u8()? != 0;
+        let rsv = hart.u32()?;
+        let halted = hart.u8()? != 0;
+        let mut regs = [0; 32];
+        for reg in &mut regs {
+            *reg = hart.u32()?;
+        }
+        let mut csrs = vec![0; CSR_COUNT];
+        for csr in &mut csrs {
+            *csr = hart.u32()?;
+        }
+        hart.finish()?;
+
+        let mut fregs = [0; 32];
+        if let Ok(mut fp) = section(FP) {
+            for reg in &mut fregs {
+                *reg = fp.u64()?;
+            }
+            fp.finish()?;
+        }
+
+        let hpm = match section(HPM) {
+            Ok(mut fields) => {
+                let mut hpm = Hpm::new();
+                for n in &mut hpm.events {
+                    *n = fields.u64()?;
+                }
+                for n in &mut hpm.selectors {
+                    *n = fields.u32()?;
+                }
+                for n in &mut hpm.offsets {
+                    *n = fields.u64()?;
+                }
+                hpm.inhibit = fields.u32()?;
+                for n in &mut hpm.frozen {
+                    *n = fields.u64()?;
+                }
+                fields.finish()?;
+                Some(hpm)
+            }
+            Err(_) => None,
+        };
+
+        let protection = match section(PROT) {
+            Ok(fields) if fields.data.len() != PAGES => return Err(fields.corrupt("wrong length")),
+            Ok(fields) => Some(fields.data),
+            Err(_) => None,
+        };
+
+        // Later `MEM ` sections replace pages from earlier ones
+        let mut pages = BTreeMap::new();
+        let mut layout = None;
+        for (_, payload) in sections.iter().filter(|(tag, _)| *tag == MEM) {
+            let mut mem = Fields {
+                section: MEM,
+                data: payload,
+            };
+            layout = Some((mem.u32()?, mem.u32()?));
+            for _ in 0..mem.u32()? {
+                let addr = mem.u32()?;
+                if !(addr as usize).is_multiple_of(PAGE_SIZE) {
+                    return Err(mem.corrupt("unaligned page"));
+                }
+                pages.insert(addr, mem.bytes(PAGE_SIZE)?);
+            }
+            mem.finish()?;
+        }
+        let (brk, mmap_top) =
+            layout.ok_or_else(|| HibernateError::MissingSection(section_name(MEM)))?;
+
+        let kernel = section(KERNEL)?;
+        self.kernel
+            .restore_state(kernel.data)
+            .map_err(HibernateError::Kernel)?;
+
+        self.hart.restore_context(&HartContext { regs, fregs, pc });
+        self.hart.inst_count = inst_count;
+        self.hart.amo_rsv = has_rsv.then_some(rsv);
+        for (csr, value) in csrs.into_iter().enumerate() {
+            self.hart.set_csr(csr as u16, value);
+        }
+        if let Some(hpm) = hpm {
+            self.hart.hpm = hpm;
+        }
+        self.state = if halted {
+            MachineState::Halted
+        } else {
+            MachineState::Running
+        };
+
+        for addr in self.mem.resident_page_addrs()? {
+            self.mem.memset(addr, 0, PAGE_SIZE as u32)?;
+        }
+        for (addr, page) in pages {
+            self.mem.copy_to(addr, page)?;
+        }
+        self.mem.brk = brk;
+        self.mem.mmap_top = mmap_top;
+        if let (Some(protection), Some(bytes)) = (&mut self.mem.protection, protection) {
+            protection.restore_bytes(bytes);
+        }
+
+        Ok(())
+    }
+}
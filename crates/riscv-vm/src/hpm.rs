@@ -0,0 +1,237 @@
+use crate::csr::Csr;
+
+/// Events the `mhpmevent` CSRs can select, by the value written to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum HpmEvent {
+    /// Loads, including from MMIO devices, but not atomics
+    Loads = 1,
+    /// Stores, including to MMIO devices, but not atomics
+    Stores = 2,
+    /// `lr`, `sc` and AMOs
+    Atomics = 3,
+    /// Conditional branches executed
+    Branches = 4,
+    /// Conditional branches taken
+    BranchesTaken = 5,
+    /// Instruction cache hits, with the cache simulator on
+    ICacheHits = 6,
+    /// Instruction cache misses, with the cache simulator on
+    ICacheMisses = 7,
+    /// Data cache hits, with the cache simulator on
+    DCacheHits = 8,
+    /// Data cache misses, with the cache simulator on
+    DCacheMisses = 9,
+    /// Conditional branches mispredicted, with a branch predictor on
+    BranchMispredicts = 10,
+}
+
+impl HpmEvent {
+    pub const ALL: [HpmEvent; 10] = [
+        HpmEvent::Loads,
+        HpmEvent::Stores,
+        HpmEvent::Atomics,
+        HpmEvent::Branches,
+        HpmEvent::BranchesTaken,
+        HpmEvent::ICacheHits,
+        HpmEvent::ICacheMisses,
+        HpmEvent::DCacheHits,
+        HpmEvent::DCacheMisses,
+        HpmEvent::BranchMispredicts,
+    ];
+
+    /// The event selected by an `mhpmevent` value, if it's one we count.
+    pub fn from_selector(selector: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|&e| e as u32 == selector)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HpmEvent::Loads => "loads",
+            HpmEvent::Stores => "stores",
+            HpmEvent::Atomics => "atomics",
+            HpmEvent::Branches => "branches",
+            HpmEvent::BranchesTaken => "branches-taken",
+            HpmEvent::ICacheHits => "icache-hits",
+            HpmEvent::ICacheMisses => "icache-misses",
+            HpmEvent::DCacheHits => "dcache-hits",
+            HpmEvent::DCacheMisses => "dcache-misses",
+            HpmEvent::BranchMispredicts => "branch-mispredicts",
+        }
+    }
+}
+
+const EVENTS: usize = HpmEvent::ALL.len() + 1;
+
+// Counter CSRs, each followed by 31 more for counters 1-31
+const CSR_MCYCLE: u16 = Csr::Mcycle.number();
+const CSR_MCYCLEH: u16 = Csr::Mcycleh.number();
+const CSR_CYCLE: u16 = Csr::Cycle.number();
+const CSR_CYCLEH: u16 = Csr::Cycleh.number();
+const CSR_MCOUNTINHIBIT: u16 = Csr::Mcountinhibit.number();
+/// `mhpmevent3`; events 0-2 don't exist
+const CSR_MHPMEVENT3: u16 = 0x323;
+const CSR_MHPMEVENT31: u16 = 0x33F;
+
+// Counter numbers with fixed meanings
+const CYCLE: usize = 0;
+const TIME: usize = 1;
+const INSTRET: usize = 2;
+
+/// Performance counters: `mcycle`, `minstret`, and `mhpmcounter3`-`31` with
+/// their `mhpmevent` selectors, plus the user-level read-only shadows.
+///
+/// Every event is always counted. The counter CSRs are views onto those
+/// totals, rebased whenever the guest writes a counter or changes what it
+/// selects, so counting costs an increment whether or not the guest uses
+/// them. Cycles are instructions retired, so `mcycle` equals `minstret`.
+/// `time` isn't implemented; it reads as 0.
+#[derive(Debug, Clone)]
+pub struct Hpm {
+    /// Occurrences of each event since reset, indexed by selector
+    pub(crate) events: [u64; EVENTS],
+    /// The event each counter selects
+    pub(crate) selectors: [u32; 32],
+    /// Added to the selected total to get the counter's value
+    pub(crate) offsets: [u64; 32],
+    /// `mcountinhibit`
+    pub(crate) inhibit: u32,
+    /// Values of the inhibited counters
+    pub(crate) frozen: [u64; 32],
+}
+
+impl Hpm {
+    pub fn new() -> Self {
+        Self {
+            events: [0; EVENTS],
+            selectors: [0; 32],
+            offsets: [0; 32],
+            inhibit: 0,
+            frozen: [0; 32],
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn count(&mut self, event: HpmEvent) {
+        self.events[event as usize] += 1;
+    }
+
+    #[inline(always)]
+    pub(crate) fn branch(&mut self, taken: bool) {
+        self.count(HpmEvent::Branches);
+        self.events[HpmEvent::BranchesTaken as usize] += taken as u64;
+    }
+
+    /// Occurrences of `event` since reset, regardless of how the guest has
+    /// programmed the counters.
+    pub fn event_count(&self, event: HpmEvent) -> u64 {
+        self.events[event as usize]
+    }
+
+    /// Whether `csr` is one of the counter, event selector or inhibit CSRs.
+    #[inline(always)]
+    pub(crate) fn is_hpm_csr(csr: u16) -> bool {
+        matches!(csr & 0xF60, 0xB00 | 0xC00) || (CSR_MCOUNTINHIBIT..=CSR_MHPMEVENT31).contains(&csr)
+    }
+
+    fn raw(&self, counter: usize, inst_count: u64) -> u64 {
+        match counter {
+            CYCLE | INSTRET => inst_count,
+            TIME => 0,
+            _ => self
+                .events
+                .get(self.selectors[counter] as usize)
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+
+    /// Value of counter `counter`, `0`-`31`.
+    pub fn counter(&self, counter: usize, inst_count: u64) -> u64 {
+        if self.inhibit & (1 << counter) != 0 {
+            self.frozen[counter]
+        } else {
+            self.raw(counter, inst_count)
+                .wrapping_add(self.offsets[counter])
+        }
+    }
+
+    fn set_counter(&mut self, counter: usize, value: u64, inst_count: u64) {
+        if counter == TIME {
+            return;
+        }
+        if self.inhibit & (1 << counter) != 0 {
+            self.frozen[counter] = value;
+        }
+        self.offsets[counter] = value.wrapping_sub(self.raw(counter, inst_count));
+    }
+
+    /// Which counter `csr` accesses, and whether it's the high half.
+    fn counter_csr(csr: u16) -> Option<(usize, bool)> {
+        let counter = (csr & 0x1F) as usize;
+        match csr & !0x1F {
+            CSR_MCYCLE | CSR_CYCLE => Some((counter, false)),
+            CSR_MCYCLEH | CSR_CYCLEH => Some((counter, true)),
+            _ => None,
+        }
+    }
+
+    /// Current value of an HPM CSR.
+    pub(crate) fn read(&self, csr: u16, inst_count: u64) -> u32 {
+        match csr {
+            CSR_MCOUNTINHIBIT => self.inhibit,
+            CSR_MHPMEVENT3..=CSR_MHPMEVENT31 => self.selectors[(csr - 0x320) as usize],
+            _ => match Self::counter_csr(csr) {
+                Some((counter, false)) => self.counter(counter, inst_count) as u32,
+                Some((counter, true)) => (self.counter(counter, inst_count) >> 32) as u32,
+                None => 0,
+            },
+        }
+    }
+
+    /// Write an HPM CSR. The user-level counters are read-only, so writes to
+    /// them are dropped.
+    pub(crate) fn write(&mut self, csr: u16, value: u32, inst_count: u64) {
+        match csr {
+            CSR_MCOUNTINHIBIT => {
+                // Bit 1 is `time`, which can't be inhibited
+                let inhibit = value & !(1 << TIME);
+                for counter in 0..32 {
+                    let bit = 1 << counter;
+                    if (self.inhibit ^ inhibit) & bit == 0 {
+                        continue;
+                    }
+                    let current = self.counter(counter, inst_count);
+                    self.inhibit ^= bit;
+                    self.frozen[counter] = current;
+                    self.set_counter(counter, current, inst_count);
+                }
+            }
+            CSR_MHPMEVENT3..=CSR_MHPMEVENT31 => {
+                let counter = (csr - 0x320) as usize;
+                let current = self.counter(counter, inst_count);
+                self.selectors[counter] = value;
+                self.set_counter(counter, current, inst_count);
+            }
+            _ if csr >= CSR_CYCLE => {}
+            _ => {
+                let Some((counter, high)) = Self::counter_csr(csr) else {
+                    return;
+                };
+                let current = self.counter(counter, inst_count);
+                let value = if high {
+                    (current & 0xFFFF_FFFF) | (value as u64) << 32
+                } else {
+                    (current & !0xFFFF_FFFF) | value as u64
+                };
+                self.set_counter(counter, value, inst_count);
+            }
+        }
+    }
+}
+
+impl Default for Hpm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
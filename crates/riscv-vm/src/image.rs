@@ -0,0 +1,118 @@
+use crate::{
+    error::{HibernateError, MemoryError},
+    hart::HartContext,
+    leak::LeakTracker,
+    machine::{Kernel, Machine, MachineState},
+    memory::PAGE_SIZE,
+};
+
+const CSR_COUNT: usize = 4096;
+
+/// The guest's state at one point, kept in host memory, from
+/// [`Machine::image`].
+///
+/// Taken right after loading a program, it lets the same machine run it
+/// again and again through [`Machine::reset_to_image`], which is much
+/// cheaper than building a machine and loading the program each time.
+#[derive(Debug, Clone)]
+pub struct MachineImage {
+    context: HartContext,
+    csrs: Box<[u32]>,
+    inst_count: u64,
+    halted: bool,
+    /// Resident pages by address, in order
+    pages: Vec<(u32, Box<[u8]>)>,
+    brk: u32,
+    mmap_top: u32,
+    /// From [`Kernel::save_state`], if the kernel can save it
+    kernel: Option<Vec<u8>>,
+}
+
+impl MachineImage {
+    /// Guest memory the image holds, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+}
+
+impl<K: Kernel> Machine<K> {
+    /// Capture the guest's state for [`Machine::reset_to_image`].
+    pub fn image(&self) -> Result<MachineImage, MemoryError> {
+        let pages = self
+            .mem
+            .resident_page_addrs()?
+            .into_iter()
+            .map(|addr| {
+                let page = self.mem.slice::<u8>(addr, PAGE_SIZE as u32)?;
+                Ok((addr, page.into()))
+            })
+            .collect::<Result<_, MemoryError>>()?;
+
+        Ok(MachineImage {
+            context: self.hart.context(),
+            csrs: (0..CSR_COUNT as u16)
+                .map(|csr| self.hart.csr(csr))
+                .collect(),
+            inst_count: self.hart.inst_count,
+            halted: self.state == MachineState::Halted,
+            pages,
+            brk: self.mem.brk,
+            mmap_top: self.mem.mmap_top,
+            kernel: self.kernel.save_state(),
+        })
+    }
+
+    /// Go back to `image`, without reloading anything. The hart is
+    /// [reset](crate::hart::Hart32::reset) first, so simulators and the
+    /// leak tracker start over too.
+    ///
+    /// The kernel restores the state saved in the image, or if it had none,
+    /// [resets](Kernel::reset) itself; if it can't do either, this fails
+    /// with [`HibernateError::KernelUnsupported`] before changing anything.
+    pub fn reset_to_image(&mut self, image: &MachineImage) -> Result<(), HibernateError> {
+        match &image.kernel {
+            Some(state) => self
+                .kernel
+                .restore_state(state)
+                .map_err(HibernateError::Kernel)?,
+            None if self.kernel.reset(&mut self.mem) => {}
+            None => return Err(HibernateError::KernelUnsupported),
+        }
+        let resident = self.mem.resident_page_addrs()?;
+
+        self.hart.reset();
+        self.hart.restore_context(&image.context);
+        self.hart.inst_count = image.inst_count;
+        for (csr, &value) in image.csrs.iter().enumerate() {
+            self.hart.set_csr(csr as u16, value);
+        }
+        self.state = if image.halted {
+            MachineState::Halted
+        } else {
+            MachineState::Running
+        };
+        self.exit_code = None;
+        self.breakpoint_hit = None;
+        if let Some(leaks) = &mut self.leaks {
+            *leaks = LeakTracker::new();
+        }
+
+        // Pages the guest touched since are zeroed, the image's overwritten
+        for addr in resident {
+            if image
+                .pages
+                .binary_search_by_key(&addr, |&(a, _)| a)
+                .is_err()
+            {
+                self.mem.memset(addr, 0, PAGE_SIZE as u32)?;
+            }
+        }
+        for (addr, page) in &image.pages {
+            self.mem.copy_to(*addr, page)?;
+        }
+        self.mem.brk = image.brk;
+        self.mem.mmap_top = image.mmap_top;
+
+        Ok(())
+    }
+}
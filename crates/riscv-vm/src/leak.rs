@@ -0,0 +1,236 @@
+use std::{collections::BTreeMap, io::Write};
+
+use riscv_inst::Reg;
+
+use crate::{hart::Hart32, memory::Memory, symbols::SymbolTable};
+
+/// The guest allocator entry points [`LeakTracker`] watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AllocFn {
+    /// `malloc(size)`
+    Malloc,
+    /// `calloc(count, size)`
+    Calloc,
+    /// `realloc(ptr, size)`
+    Realloc,
+    /// `aligned_alloc(align, size)` and `memalign(align, size)`
+    AlignedAlloc,
+    /// `posix_memalign(&ptr, align, size)`, which returns the pointer through memory
+    PosixMemalign,
+    /// `free(ptr)`
+    Free,
+}
+
+const ALLOC_SYMBOLS: [(&str, AllocFn); 7] = [
+    ("malloc", AllocFn::Malloc),
+    ("calloc", AllocFn::Calloc),
+    ("realloc", AllocFn::Realloc),
+    ("aligned_alloc", AllocFn::AlignedAlloc),
+    ("memalign", AllocFn::AlignedAlloc),
+    ("posix_memalign", AllocFn::PosixMemalign),
+    ("free", AllocFn::Free),
+];
+
+/// An allocator call waiting for its return.
+#[derive(Debug, Clone)]
+struct PendingCall {
+    func: AllocFn,
+    /// Return address and stack pointer at entry, which match again on return
+    ret: u32,
+    sp: u32,
+    size: u32,
+    /// The pointer being reallocated, or where `posix_memalign` stores its result
+    ptr: u32,
+    backtrace: Vec<u32>,
+}
+
+/// A guest allocation that hasn't been freed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub addr: u32,
+    pub size: u32,
+    /// Call sites, innermost first, see [`Hart32::backtrace`]
+    pub backtrace: Vec<u32>,
+    /// Instructions retired when it was allocated
+    pub inst_count: u64,
+}
+
+/// Unfreed allocations with the same backtrace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakSite {
+    pub backtrace: Vec<u32>,
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// Tracks the guest's heap allocations through its `malloc` family, found
+/// by symbol, to report the ones never freed.
+///
+/// Only the outermost allocator call counts, so a `realloc` built on
+/// `malloc` and `free` is one reallocation. brk and mmap growth are
+/// tracked too, for allocators the symbol hooks miss.
+#[derive(Debug, Clone, Default)]
+pub struct LeakTracker {
+    /// Allocator entry points, resolved from the symbols on the first step
+    entries: Option<Vec<(u32, AllocFn)>>,
+    pending: Option<PendingCall>,
+    live: BTreeMap<u32, Allocation>,
+    brk_start: u32,
+    mmap_start: u32,
+    pub allocs: u64,
+    pub frees: u64,
+}
+
+impl LeakTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track the instruction about to run at `hart.pc`.
+    #[inline]
+    pub(crate) fn step(&mut self, hart: &Hart32, mem: &Memory, symbols: &SymbolTable) {
+        let entries = self.entries.get_or_insert_with(|| {
+            self.brk_start = mem.brk;
+            self.mmap_start = mem.mmap_top;
+            ALLOC_SYMBOLS
+                .into_iter()
+                .filter_map(|(name, func)| Some((symbols.lookup(name)?, func)))
+                .collect()
+        });
+
+        match &self.pending {
+            Some(call) if hart.pc == call.ret && hart.get_reg(Reg::Sp) == call.sp => {
+                let call = self.pending.take().unwrap();
+                self.returned(call, hart, mem);
+            }
+            Some(_) => {}
+            None => {
+                if let Some(&(_, func)) = entries.iter().find(|&&(addr, _)| addr == hart.pc) {
+                    self.entered(func, hart);
+                }
+            }
+        }
+    }
+
+    fn entered(&mut self, func: AllocFn, hart: &Hart32) {
+        let [a0, a1, a2] = hart.args();
+        let (size, ptr) = match func {
+            AllocFn::Malloc => (a0, 0),
+            AllocFn::Calloc => (a0.saturating_mul(a1), 0),
+            AllocFn::Realloc => (a1, a0),
+            AllocFn::AlignedAlloc => (a1, 0),
+            AllocFn::PosixMemalign => (a2, a0),
+            AllocFn::Free => {
+                self.free(a0);
+                return;
+            }
+        };
+        self.pending = Some(PendingCall {
+            func,
+            ret: hart.get_reg(Reg::Ra),
+            sp: hart.get_reg(Reg::Sp),
+            size,
+            ptr,
+            backtrace: hart.backtrace(),
+        });
+    }
+
+    fn returned(&mut self, call: PendingCall, hart: &Hart32, mem: &Memory) {
+        let addr = match call.func {
+            AllocFn::PosixMemalign if hart.return_value() == 0 => mem.load(call.ptr),
+            AllocFn::PosixMemalign => 0,
+            _ => hart.return_value(),
+        };
+        // A failed realloc leaves the old allocation alone
+        if call.func == AllocFn::Realloc && (addr != 0 || call.size == 0) {
+            self.free(call.ptr);
+        }
+        if addr == 0 {
+            return;
+        }
+
+        self.allocs += 1;
+        self.live.insert(
+            addr,
+            Allocation {
+                addr,
+                size: call.size,
+                backtrace: call.backtrace,
+                inst_count: hart.inst_count,
+            },
+        );
+    }
+
+    fn free(&mut self, addr: u32) {
+        if self.live.remove(&addr).is_some() {
+            self.frees += 1;
+        }
+    }
+
+    /// Allocations not freed yet, by address.
+    pub fn live(&self) -> impl Iterator<Item = &Allocation> {
+        self.live.values()
+    }
+
+    /// How far brk and the mmap region have grown since tracking started.
+    pub fn heap_growth(&self, mem: &Memory) -> (u32, u32) {
+        if self.entries.is_none() {
+            return (0, 0);
+        }
+        (
+            mem.brk.saturating_sub(self.brk_start),
+            self.mmap_start.saturating_sub(mem.mmap_top),
+        )
+    }
+
+    pub fn leaked_bytes(&self) -> u64 {
+        self.live().map(|a| a.size as u64).sum()
+    }
+
+    /// Live allocations grouped by backtrace, most bytes first.
+    pub fn by_site(&self) -> Vec<LeakSite> {
+        let mut sites: BTreeMap<&[u32], LeakSite> = BTreeMap::new();
+        for alloc in self.live() {
+            let site = sites.entry(&alloc.backtrace).or_insert_with(|| LeakSite {
+                backtrace: alloc.backtrace.clone(),
+                count: 0,
+                bytes: 0,
+            });
+            site.count += 1;
+            site.bytes += alloc.size as u64;
+        }
+
+        let mut sites: Vec<_> = sites.into_values().collect();
+        sites.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.count.cmp(&a.count)));
+        sites
+    }
+
+    /// Write a summary, then the unfreed allocations by allocation site.
+    pub fn write_report(
+        &self,
+        w: &mut impl Write,
+        mem: &Memory,
+        symbols: &SymbolTable,
+    ) -> std::io::Result<()> {
+        let (brk, mmap) = self.heap_growth(mem);
+        writeln!(
+            w,
+            "{} of {} allocations not freed, {} bytes; brk grew {brk} bytes, mmap {mmap} bytes",
+            self.live.len(),
+            self.allocs,
+            self.leaked_bytes(),
+        )?;
+        for site in self.by_site() {
+            writeln!(
+                w,
+                "{} bytes in {} allocations from:",
+                site.bytes, site.count
+            )?;
+            for (i, &addr) in site.backtrace.iter().enumerate() {
+                writeln!(w, "  #{i} {}", symbols.describe(addr))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,52 @@
+// Lets `#[derive(GuestValue)]` name this crate from inside it
+extern crate self as riscv_vm;
+
+pub mod abi;
+pub mod alloc;
+pub mod bpred;
+pub mod cache;
+pub mod cfi;
+pub mod channel;
+pub mod clint;
+pub mod cluster;
+mod crypto;
+pub mod csr;
+pub mod debug;
+pub mod debugcon;
 pub mod error;
+pub mod fastpath;
+pub mod framebuffer;
+pub mod gas;
 pub mod hart;
+pub mod hibernate;
+pub mod hpm;
+pub mod image;
+pub mod leak;
+pub mod lockstep;
 pub mod machine;
+pub mod marshal;
 pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod migrate;
+pub mod mmio;
+pub mod panic;
+pub mod patch;
+pub mod pipeline;
+pub mod plic;
+pub mod policy;
+pub mod protect;
+pub mod quota;
+pub mod shadow;
+pub mod shm;
+mod softfloat;
+pub mod symbols;
+pub mod trace;
+pub mod trace_query;
+pub mod trap;
+pub mod uart;
+pub mod virtio_blk;
+pub mod watch;
+pub mod watchdog;
 
 pub use riscv_inst;
@@ -0,0 +1,104 @@
+//! Experimental lock-step execution of many machines over the same guest
+//! code with different data, SIMT style, for fuzzing and parameter sweeps.
+//!
+//! Each round steps every running machine once. Machines at the same pc
+//! with the same instruction there share one decode, so the cost of
+//! decoding is spread across the group; machines that branch differently
+//! split into groups of their own, and merge again when their pcs do.
+
+use riscv_inst::codegen::rv32imasfdc::Rv32IMASFDC;
+
+use crate::{
+    error::MachineError,
+    machine::{Kernel, Machine, MachineState},
+};
+
+/// How much decoding lock-step execution saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockStepStats {
+    /// Machine steps taken
+    pub steps: u64,
+    /// Instructions decoded for them
+    pub decodes: u64,
+}
+
+/// Machines run one instruction at a time each, in lock step.
+pub struct LockStep<K: Kernel> {
+    machines: Vec<Machine<K>>,
+    /// The error each machine stopped with, if it did
+    errors: Vec<Option<MachineError<K::Error>>>,
+    /// Decodes shared this round, by pc and instruction
+    decoded: Vec<(u32, u32, Option<Rv32IMASFDC>)>,
+    stats: LockStepStats,
+}
+
+impl<K: Kernel> LockStep<K> {
+    /// Run `machines`, which are usually loaded with the same program and
+    /// given different inputs.
+    pub fn new(machines: Vec<Machine<K>>) -> Self {
+        let errors = machines.iter().map(|_| None).collect();
+        Self {
+            machines,
+            errors,
+            decoded: vec![],
+            stats: LockStepStats::default(),
+        }
+    }
+
+    pub fn machines(&self) -> &[Machine<K>] {
+        &self.machines
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [Machine<K>] {
+        &mut self.machines
+    }
+
+    pub fn into_machines(self) -> Vec<Machine<K>> {
+        self.machines
+    }
+
+    pub fn stats(&self) -> LockStepStats {
+        self.stats
+    }
+
+    /// Step every running machine once, returning whether any still is.
+    /// Machines that fail stop, and keep their error for [`LockStep::run`].
+    pub fn step(&mut self) -> bool {
+        self.decoded.clear();
+        let mut running = false;
+        for (machine, error) in self.machines.iter_mut().zip(&mut self.errors) {
+            if machine.state != MachineState::Running || error.is_some() {
+                continue;
+            }
+            let pc = machine.hart.pc;
+            let inst = machine.mem.load::<u32>(pc);
+            let op = match self.decoded.iter().find(|&&(p, i, _)| (p, i) == (pc, inst)) {
+                Some(&(_, _, op)) => op,
+                None => {
+                    let op = Rv32IMASFDC::parse(inst);
+                    self.decoded.push((pc, inst, op));
+                    self.stats.decodes += 1;
+                    op
+                }
+            };
+            self.stats.steps += 1;
+            match machine.step_decoded(Some((pc, inst, op))) {
+                Ok(()) => running |= machine.state == MachineState::Running,
+                Err(e) => *error = Some(e),
+            }
+        }
+
+        running
+    }
+
+    /// Run every machine until it stops, returning how each one did, in
+    /// order. Like [`Machine::run`], machines stopped at an `ebreak` stop
+    /// here too.
+    pub fn run(&mut self) -> Vec<Result<(), MachineError<K::Error>>> {
+        while self.step() {}
+        self.errors
+            .iter_mut()
+            .map(|error| error.take().map_or(Ok(()), Err))
+            .collect()
+    }
+}
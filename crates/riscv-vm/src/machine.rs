@@ -1,9 +1,43 @@
-use std::error::Error;
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    ops::ControlFlow,
+    task::{Context, Poll},
+    time::Instant,
+};
 
-use crate::{error::MachineError, hart::Hart32, memory::Memory};
+use riscv_inst::{codegen::rv32imasfdc::Rv32IMASFDC, Extension, Extensions, Reg};
+
+use crate::{
+    abi::ARG_REGS,
+    alloc::GuestAllocator,
+    bpred::{BranchPredictor, PredictorConfig},
+    cache::{CacheConfig, CacheSim},
+    cfi::BranchTrace,
+    error::{AllocError, Fault, GuestContext, HartError, MachineError, MemoryError},
+    framebuffer::Framebuffer,
+    gas::GasProfiler,
+    hart::{Hart32, MisalignedAtomics},
+    leak::LeakTracker,
+    marshal::{GuestPtr, GuestValue},
+    memory::{HugePages, Memory, PAGE_SIZE},
+    mmio::{DeviceStop, MmioBus},
+    panic::{GuestBacktrace, GuestPanic, PanicTraps},
+    policy::InstPolicy,
+    protect::{ExecProtection, PageProtection, Poison},
+    quota::{KernelUsage, Quotas, Resource, ResourceUsage, QUOTA_STRIDE},
+    shadow::{ShadowFrame, ShadowStack, ShadowViolation},
+    symbols::SymbolTable,
+    trap::EbreakMode,
+    watchdog::{CancelToken, RunAsync, RunExit, YieldEvery, CHECK_STRIDE},
+};
+
+/// Return address used for host-initiated guest calls. Never executed,
+/// the call loop stops as soon as the guest returns here.
+pub const CALL_RETURN_ADDR: u32 = 0xFFFF_FFF0;
 
 pub trait Kernel {
-    type Error: Error;
+    type Error: Error + 'static;
     fn syscall(
         &mut self,
         hart: &mut Hart32,
@@ -17,17 +51,90 @@ pub trait Kernel {
     ) -> Result<StepResult, MachineError<Self::Error>> {
         Ok(StepResult::Halt)
     }
+
+    /// Recent guest output to stderr, if the kernel keeps it.
+    fn stderr_tail(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the kernel is ready to run the guest, for
+    /// [`Machine::run_async`]. Kernels backed by host I/O return
+    /// `Poll::Pending` while a guest request is in flight, waking `cx` when
+    /// it completes.
+    fn poll_io(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    /// The kernel's state for [`Machine::hibernate`], or `None` if it can't
+    /// be saved.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restore state returned by [`Kernel::save_state`], possibly by an
+    /// older version of the kernel, for [`Machine::restore`].
+    fn restore_state(&mut self, _state: &[u8]) -> Result<(), String> {
+        Err("the kernel can't restore saved state".to_string())
+    }
+
+    /// Forget what the guest did since it was loaded, for
+    /// [`Machine::reset_to_image`] on kernels that can't
+    /// [save their state](Kernel::save_state). Returns `false` if the kernel
+    /// can't do that either.
+    fn reset(&mut self, _mem: &mut Memory) -> bool {
+        false
+    }
+
+    /// Syscalls and I/O the kernel has accounted for, for [`Machine::usage`]
+    /// and [`MachineConfig::quotas`].
+    fn usage(&self) -> KernelUsage {
+        KernelUsage::default()
+    }
+
+    /// Map the position-independent ELF `elf` into the guest after what's
+    /// already loaded, like a manual `dlopen`, for [`Machine::load_library`].
+    /// Its imports are resolved against `symbols`. Returns `None` if the
+    /// kernel can't load libraries.
+    fn load_library(
+        &mut self,
+        _mem: &mut Memory,
+        _symbols: &SymbolTable,
+        _elf: &[u8],
+    ) -> Option<Result<LoadedLibrary, Self::Error>> {
+        None
+    }
+}
+
+/// A library mapped by [`Kernel::load_library`].
+#[derive(Debug, Clone)]
+pub struct LoadedLibrary {
+    /// Load address, which the library's own addresses are relative to
+    pub base: u32,
+    /// Bytes mapped from `base`
+    pub size: u32,
+    /// Symbols the library defines, at their loaded addresses
+    pub symbols: SymbolTable,
+    /// The `DT_INIT` function and `DT_INIT_ARRAY` entries, in the order
+    /// they should run; loading doesn't run them
+    pub constructors: Vec<u32>,
 }
 
 pub enum StepResult {
     Ok,
     Halt,
+    /// Stop at a breakpoint, leaving the pc at the instruction
+    Breakpoint,
+    /// Stop after an instruction that hit a watchpoint, leaving the pc at
+    /// the next one
+    Watchpoint,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MachineState {
     Running,
     Halted,
+    /// Stopped at an `ebreak`, see [`Machine::resume`]
+    Breakpoint,
 }
 
 impl MachineState {
@@ -36,30 +143,255 @@ impl MachineState {
     }
 }
 
+/// Options fixed at [`Machine`] construction.
+#[derive(Debug, Clone, Default)]
+pub struct MachineConfig {
+    /// Extensions the hart implements. Instructions from other extensions
+    /// raise an illegal instruction error.
+    pub extensions: Extensions,
+    /// Opcode allow/deny list applied on top of `extensions`.
+    pub policy: InstPolicy,
+    /// Whether AMOs on misaligned addresses trap or run, as with Zam.
+    pub misaligned_atomics: MisalignedAtomics,
+    /// What `ebreak` does.
+    pub ebreak: EbreakMode,
+    /// Maintain a return-address shadow stack, failing on mismatched returns.
+    pub shadow_stack: bool,
+    /// Record indirect branch targets into [`Hart32::branch_trace`].
+    pub branch_trace: bool,
+    /// Stop with [`MachineError::GuestPanic`] when the guest enters `rust_panic`
+    /// or `abort`. Panics are caught before unwinding, even if the guest would
+    /// have caught them itself.
+    pub catch_panics: bool,
+    /// Attach a framebuffer at [`FRAMEBUFFER_BASE`](crate::framebuffer::FRAMEBUFFER_BASE).
+    pub framebuffer: Option<Framebuffer>,
+    /// Simulate an instruction cache, recording into [`Hart32::cache_sim`].
+    pub icache: Option<CacheConfig>,
+    /// Simulate a data cache, recording into [`Hart32::cache_sim`].
+    pub dcache: Option<CacheConfig>,
+    /// Simulate a branch predictor, recording into [`Hart32::branch_predictor`].
+    pub branch_predictor: Option<PredictorConfig>,
+    /// Count instructions by function into [`Hart32::gas_profiler`].
+    pub gas_profile: bool,
+    /// Stop with [`MachineError::QuotaExceeded`] once the guest uses too much.
+    pub quotas: Quotas,
+    /// Track the guest's `malloc` family into [`Machine::leaks`].
+    pub leak_check: bool,
+    /// What host pages back guest memory.
+    pub huge_pages: HugePages,
+    /// Which pages the guest may execute and write, see [`Memory::protection`].
+    /// Kernels mark the code they load and map.
+    pub exec_protection: ExecProtection,
+    /// What happens to memory the guest unmaps, see [`Memory::poison_range`].
+    pub poison: Poison,
+}
+
+/// Host replacement for a guest function, see [`Machine::intercept`].
+pub type Interceptor<K> = Box<dyn FnMut(&mut Hart32, &mut Memory, &mut K) -> u32>;
+
 pub struct Machine<K: Kernel> {
     pub hart: Hart32,
     pub mem: Memory,
     pub kernel: K,
     pub state: MachineState,
+    pub symbols: SymbolTable,
+    pub allocator: GuestAllocator,
+    catch_panics: bool,
+    /// Resolved from `symbols` on the first step
+    panic_traps: Option<PanicTraps>,
+    pub framebuffer: Option<Framebuffer>,
+    /// Host functions by the guest entry point they replace
+    interceptors: HashMap<u32, Interceptor<K>>,
+    /// Addresses to stop at before executing, see [`Machine::break_at`]
+    pub(crate) breakpoints: BTreeSet<u32>,
+    /// The breakpoint the machine stopped at, which it runs past on resuming
+    pub(crate) breakpoint_hit: Option<u32>,
+    pub quotas: Quotas,
+    /// Instruction count at which to check `quotas` next
+    next_quota_check: u64,
+    /// Guest allocations not freed yet, with [`MachineConfig::leak_check`]
+    pub leaks: Option<LeakTracker>,
+    /// Exit code the guest stopped with through a device, such as the
+    /// [`DebugConsole`](crate::debugcon::DebugConsole)
+    pub exit_code: Option<u32>,
 }
 
 impl<K: Kernel> Machine<K> {
     pub fn new(kernel: K) -> Self {
-        Self {
-            hart: Hart32::new(),
-            mem: Memory::new(),
+        Self::with_config(kernel, MachineConfig::default())
+    }
+
+    /// Build a machine, panicking if the guest address space can't be
+    /// reserved. [`Machine::try_with_config`] reports that as an error.
+    pub fn with_config(kernel: K, config: MachineConfig) -> Self {
+        Self::try_with_config(kernel, config).expect("Failed to allocate memory")
+    }
+
+    pub fn try_with_config(kernel: K, config: MachineConfig) -> Result<Self, MemoryError> {
+        let mut mem = Memory::with_huge_pages(config.huge_pages)?;
+        mem.protection = PageProtection::new(config.exec_protection, config.poison);
+        mem.poison = config.poison;
+        let mut hart = Hart32::new();
+        hart.extensions = config.extensions;
+        hart.policy = config.policy;
+        hart.misaligned_atomics = config.misaligned_atomics;
+        hart.ebreak = config.ebreak;
+        hart.shadow_stack = config.shadow_stack.then(ShadowStack::new);
+        hart.branch_trace = config.branch_trace.then(BranchTrace::new);
+        if config.icache.is_some() || config.dcache.is_some() {
+            hart.cache_sim = Some(CacheSim::new(config.icache, config.dcache));
+        }
+        hart.branch_predictor = config.branch_predictor.map(BranchPredictor::new);
+        hart.gas_profiler = config.gas_profile.then(GasProfiler::new);
+
+        Ok(Self {
+            hart,
+            mem,
             kernel,
             state: MachineState::Running,
+            symbols: SymbolTable::new(),
+            allocator: GuestAllocator::default(),
+            catch_panics: config.catch_panics,
+            panic_traps: None,
+            framebuffer: config.framebuffer,
+            interceptors: HashMap::new(),
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            quotas: config.quotas,
+            next_quota_check: 0,
+            leaks: config.leak_check.then(LeakTracker::new),
+            exit_code: None,
+        })
+    }
+
+    /// Attach the guest's current context to `error`, which just stopped
+    /// it. The hart doesn't advance past a failing instruction, so this is
+    /// where it failed.
+    pub fn fault(&self, error: MachineError<K::Error>) -> Fault<K::Error> {
+        let pc = match error {
+            MachineError::Hart(ref e) => e.inst().map_or(self.hart.pc, |(addr, _)| addr),
+            _ => self.hart.pc,
+        };
+        let inst = self.mem.load::<u32>(pc);
+        Fault {
+            error,
+            context: GuestContext {
+                // The only hart
+                hart: 0,
+                pc,
+                inst: if inst & 0b11 == 0b11 {
+                    inst
+                } else {
+                    inst & 0xFFFF
+                },
+                inst_count: self.hart.inst_count,
+            },
         }
     }
 
+    /// Where the guest is now, symbolized like a [`GuestPanic`]'s backtrace.
+    /// For a run stopped by a quota it's in the error; after
+    /// [`RunExit::Cancelled`] or [`RunExit::Stopped`] this shows where the
+    /// guest was spinning.
+    pub fn backtrace(&self) -> GuestBacktrace {
+        GuestBacktrace::capture(&self.hart, &self.symbols)
+    }
+
     pub fn step(&mut self) -> Result<(), MachineError<K::Error>> {
-        match self.hart.step(&mut self.mem, &mut self.kernel)? {
-            StepResult::Ok => Ok(()),
+        self.step_decoded(None)
+    }
+
+    /// Like [`Machine::step`], reusing `decoded`, a pc with the instruction
+    /// there and its decoding, if the hart is still about to run it.
+    pub(crate) fn step_decoded(
+        &mut self,
+        decoded: Option<(u32, u32, Option<Rv32IMASFDC>)>,
+    ) -> Result<(), MachineError<K::Error>> {
+        if (!self.breakpoints.is_empty() || self.breakpoint_hit.is_some()) && self.hit_breakpoint()
+        {
+            return Ok(());
+        }
+        if self.hart.inst_count >= self.next_quota_check {
+            self.check_quotas()?;
+        }
+        if self.catch_panics {
+            self.check_panic()?;
+        }
+        if let Some(leaks) = &mut self.leaks {
+            leaks.step(&self.hart, &self.mem, &self.symbols);
+        }
+        if let Some(lines) = MmioBus::tick(&mut self.mem, self.hart.inst_count) {
+            if let Some(stop) = self.mem.mmio.take_stop() {
+                return self.device_stop(stop);
+            }
+            self.hart.set_interrupt_lines(lines);
+            self.hart.take_interrupt();
+        }
+        if !self.interceptors.is_empty() && self.run_interceptor() {
+            return Ok(());
+        }
+        let result = match decoded {
+            Some((pc, inst, op)) if pc == self.hart.pc && self.mem.load::<u32>(pc) == inst => self
+                .hart
+                .step_decoded(&mut self.mem, &mut self.kernel, inst, op),
+            _ => self.hart.step(&mut self.mem, &mut self.kernel),
+        };
+        match result? {
+            StepResult::Ok => {}
             StepResult::Halt => {
+                tracing::debug!(
+                    pc = self.hart.pc,
+                    inst_count = self.hart.inst_count,
+                    "machine halted"
+                );
+                self.state = MachineState::Halted;
+                return Ok(());
+            }
+            StepResult::Breakpoint => {
+                tracing::debug!(pc = self.hart.pc, "stopped at breakpoint");
+                self.state = MachineState::Breakpoint;
+                return Ok(());
+            }
+            StepResult::Watchpoint => {
+                self.state = MachineState::Breakpoint;
+                return Ok(());
+            }
+        }
+        if self.quotas.syscalls.is_some() || self.quotas.io_bytes.is_some() {
+            self.check_kernel_quotas()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop as a device asked, before the next instruction.
+    fn device_stop(&mut self, stop: DeviceStop) -> Result<(), MachineError<K::Error>> {
+        match stop {
+            DeviceStop::Exit(code) => {
+                tracing::debug!(code, "device requested exit");
+                self.exit_code = Some(code);
                 self.state = MachineState::Halted;
                 Ok(())
             }
+            DeviceStop::AssertFailed(message) => {
+                let mut panic = GuestPanic::capture("assert_fail", &self.hart, &self.symbols, None);
+                panic.message = Some(message);
+                Err(MachineError::GuestPanic(Box::new(panic)))
+            }
+        }
+    }
+
+    /// Continue after stopping at a breakpoint, from the instruction after
+    /// the `ebreak`, or the one at a [breakpoint](Machine::break_at) or
+    /// after a [watchpoint](Machine::watch).
+    pub fn resume(&mut self) {
+        let watched = self.hart.watch_hit.take().is_some();
+        if self.state == MachineState::Breakpoint && (self.breakpoint_hit.is_some() || watched) {
+            self.state = MachineState::Running;
+        } else if self.state == MachineState::Breakpoint {
+            let compressed = self.mem.load::<u16>(self.hart.pc) & 0b11 != 0b11;
+            self.hart.pc = self.hart.pc.wrapping_add(if compressed { 2 } else { 4 });
+            self.state = MachineState::Running;
         }
     }
 
@@ -70,4 +402,348 @@ impl<K: Kernel> Machine<K> {
 
         Ok(())
     }
+
+    /// Like [`Machine::run`], but hand control to `f` as often as `every`
+    /// asks, so the host can stay responsive and stop runaway guests without
+    /// a thread per machine.
+    ///
+    /// Returns early if `cancel` is cancelled or `f` breaks, leaving the
+    /// machine running so it can be resumed.
+    pub fn run_with(
+        &mut self,
+        every: YieldEvery,
+        cancel: &CancelToken,
+        mut f: impl FnMut(&mut Self) -> ControlFlow<()>,
+    ) -> Result<RunExit, MachineError<K::Error>> {
+        let mut steps = 0;
+        let mut last_yield = Instant::now();
+        while self.state == MachineState::Running {
+            let mut budget = every
+                .instructions
+                .map_or(CHECK_STRIDE, |n| n.saturating_sub(steps).max(1));
+            if every.interval.is_some() {
+                budget = budget.min(CHECK_STRIDE);
+            }
+            for _ in 0..budget {
+                if self.state != MachineState::Running {
+                    break;
+                }
+                self.step()?;
+                steps += 1;
+            }
+
+            if cancel.is_cancelled() {
+                return Ok(RunExit::Cancelled);
+            }
+            let due = every.instructions.is_some_and(|n| steps >= n)
+                || every.interval.is_some_and(|d| last_yield.elapsed() >= d);
+            if due {
+                steps = 0;
+                last_yield = Instant::now();
+                if f(self).is_break() {
+                    return Ok(RunExit::Stopped);
+                }
+            }
+        }
+
+        if self.state == MachineState::Breakpoint {
+            return Ok(RunExit::Breakpoint);
+        }
+        Ok(RunExit::Halted)
+    }
+
+    /// Run until the machine halts as a future, yielding to the executor
+    /// every `every` instructions and whenever [`Kernel::poll_io`] is pending,
+    /// so many machines can share a few executor threads. Spin loops yield
+    /// early too, at each `pause` or `wrs` holding a reservation.
+    ///
+    /// Dropping the future stops the run, leaving the machine running so it
+    /// can be resumed.
+    pub fn run_async(&mut self, every: u64) -> RunAsync<'_, K> {
+        RunAsync::new(self, every)
+    }
+
+    /// Resources the guest has used so far. Memory counts as none if the
+    /// host can't say which pages are resident, which a memory quota
+    /// reports as an error instead.
+    pub fn usage(&self) -> ResourceUsage {
+        let kernel = self.kernel.usage();
+        ResourceUsage {
+            instructions: self.hart.inst_count,
+            syscalls: kernel.syscalls,
+            memory_bytes: self.resident_bytes().unwrap_or(0),
+            bytes_read: kernel.bytes_read,
+            bytes_written: kernel.bytes_written,
+        }
+    }
+
+    fn resident_bytes(&self) -> Result<u64, MemoryError> {
+        Ok((self.mem.resident_pages()? * PAGE_SIZE) as u64)
+    }
+
+    /// Fail if the guest is over its instruction or memory quota, and
+    /// schedule the next check.
+    fn check_quotas(&mut self) -> Result<(), MachineError<K::Error>> {
+        let inst_count = self.hart.inst_count;
+        if let Some(limit) = self.quotas.instructions {
+            // Stop before the instruction past the limit
+            if inst_count >= limit {
+                tracing::warn!(limit, "instruction quota exceeded");
+                return Err(MachineError::QuotaExceeded {
+                    resource: Resource::Instructions,
+                    limit,
+                    backtrace: self.backtrace(),
+                });
+            }
+        }
+        if let Some(limit) = self.quotas.memory_bytes {
+            let used = self.resident_bytes()?;
+            if used > limit {
+                tracing::warn!(limit, used, "memory quota exceeded");
+                return Err(MachineError::QuotaExceeded {
+                    resource: Resource::Memory,
+                    limit,
+                    backtrace: self.backtrace(),
+                });
+            }
+        }
+        // Counting resident pages is slow, so memory is only checked every
+        // `QUOTA_STRIDE` instructions. Check again then even without quotas,
+        // in case some are set later.
+        let next = inst_count.saturating_add(QUOTA_STRIDE);
+        self.next_quota_check = self
+            .quotas
+            .instructions
+            .map_or(next, |limit| limit.min(next));
+
+        Ok(())
+    }
+
+    /// Fail if the guest is over its syscall or I/O quota.
+    fn check_kernel_quotas(&self) -> Result<(), MachineError<K::Error>> {
+        let usage = self.kernel.usage();
+        let used = [
+            (Resource::Syscalls, usage.syscalls),
+            (Resource::Io, usage.bytes_read + usage.bytes_written),
+        ];
+        for (resource, used) in used {
+            let limit = self.quotas.get(resource);
+            if let Some(limit) = limit.filter(|&limit| used > limit) {
+                tracing::warn!(%resource, limit, used, "quota exceeded");
+                return Err(MachineError::QuotaExceeded {
+                    resource,
+                    limit,
+                    backtrace: self.backtrace(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Execute the instructions declared in the custom opcode spec with `f`,
+    /// enabling [`Extension::Xcustom`] on the hart.
+    ///
+    /// `f` gets the decoded instruction and its encoding, reads operands
+    /// through the instruction's accessors and writes results to the hart.
+    pub fn custom_instructions(
+        &mut self,
+        f: impl FnMut(&mut Hart32, &mut Memory, Rv32IMASFDC, u32) -> Result<StepResult, HartError>
+            + 'static,
+    ) {
+        self.hart.extensions = self.hart.extensions.with(Extension::Xcustom);
+        self.hart.custom_handler = Some(Box::new(f));
+    }
+
+    /// Replace the guest function `name` with `f`, returning `None` if there's no such symbol.
+    ///
+    /// Whenever the guest enters the function, `f` runs instead with the guest's
+    /// arguments in `a0`-`a7`, and its result is returned to the caller in `a0`.
+    /// `f` may set other registers, such as `a1` for 64-bit results.
+    pub fn intercept(
+        &mut self,
+        name: &str,
+        f: impl FnMut(&mut Hart32, &mut Memory, &mut K) -> u32 + 'static,
+    ) -> Option<()> {
+        let addr = self.symbols.lookup(name)?;
+        self.intercept_at(addr, f);
+
+        Some(())
+    }
+
+    /// Like [`Machine::intercept`], for the function at `addr`.
+    pub fn intercept_at(
+        &mut self,
+        addr: u32,
+        f: impl FnMut(&mut Hart32, &mut Memory, &mut K) -> u32 + 'static,
+    ) {
+        self.interceptors.insert(addr, Box::new(f));
+    }
+
+    /// Let the guest function at `addr` run normally again.
+    pub fn remove_intercept(&mut self, addr: u32) -> Option<Interceptor<K>> {
+        self.interceptors.remove(&addr)
+    }
+
+    /// Run the interceptor at `pc`, if any, and return from the guest function.
+    fn run_interceptor(&mut self) -> bool {
+        let Some(f) = self.interceptors.get_mut(&self.hart.pc) else {
+            return false;
+        };
+        let ret = f(&mut self.hart, &mut self.mem, &mut self.kernel);
+        self.hart.set_return(ret);
+
+        // Return as if by `ret`
+        let pc = self.hart.pc;
+        let target = self.hart.get_reg(Reg::Ra);
+        if let Some(shadow) = &mut self.hart.shadow_stack {
+            // The call pushed a frame for this return address, so this can't fail
+            let _ = shadow.on_jump(pc, target, Reg::Zero, Some(Reg::Ra), 0);
+        }
+        self.hart.pc = target;
+
+        true
+    }
+
+    fn check_panic(&mut self) -> Result<(), MachineError<K::Error>> {
+        let traps = self
+            .panic_traps
+            .get_or_insert_with(|| PanicTraps::new(&self.symbols));
+        match traps.hit(self.hart.pc) {
+            Some(symbol) => {
+                let stderr = self.kernel.stderr_tail();
+                let panic = GuestPanic::capture(symbol, &self.hart, &self.symbols, stderr);
+                Err(MachineError::GuestPanic(Box::new(panic)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Symbolized description of a shadow stack violation and the calls live at the time.
+    pub fn shadow_stack_report(&self, violation: &ShadowViolation) -> Option<String> {
+        let shadow = self.hart.shadow_stack.as_ref()?;
+        Some(shadow.report(violation, &self.symbols))
+    }
+
+    /// Call the guest function at `func` with up to 8 word arguments, returning `a0`.
+    ///
+    /// The hart's register state is saved and restored around the call, so this
+    /// can be used between any two instructions.
+    pub fn call(&mut self, func: u32, args: &[u32]) -> Result<u32, MachineError<K::Error>> {
+        if args.len() > ARG_REGS {
+            return Err(MachineError::TooManyArgs { count: args.len() });
+        }
+        let saved = self.hart.context();
+
+        for (i, &arg) in args.iter().enumerate() {
+            self.hart.set_arg(i, arg);
+        }
+        self.hart.set_reg(Reg::Ra, CALL_RETURN_ADDR);
+        // The return to the host has to match a call, whatever the guest
+        // was in the middle of
+        let shadow_depth = self.hart.shadow_stack.as_mut().map(|shadow| {
+            let depth = shadow.frames().len();
+            shadow.push(ShadowFrame {
+                call_site: saved.pc,
+                callee: func,
+                ret: CALL_RETURN_ADDR,
+            });
+            depth
+        });
+        self.hart.pc = func;
+
+        let res = loop {
+            if self.hart.pc == CALL_RETURN_ADDR {
+                break Ok(self.hart.return_value());
+            }
+            if let Err(e) = self.step() {
+                break Err(e);
+            }
+            if !self.state.is_running() {
+                break Err(MachineError::CallHalted { func });
+            }
+        };
+
+        self.hart.restore_context(&saved);
+        if let (Some(shadow), Some(depth)) = (&mut self.hart.shadow_stack, shadow_depth) {
+            shadow.truncate(depth);
+        }
+        res
+    }
+
+    /// Load a shared library through the kernel, see [`Kernel::load_library`],
+    /// and add its symbols to [`Machine::symbols`] so the host can find and
+    /// [`Machine::call`] them. Its constructors aren't run.
+    pub fn load_library(&mut self, elf: &[u8]) -> Result<LoadedLibrary, MachineError<K::Error>> {
+        let library = self
+            .kernel
+            .load_library(&mut self.mem, &self.symbols, elf)
+            .ok_or(MachineError::Unsupported("loading libraries"))?
+            .map_err(MachineError::Kernel)?;
+        for sym in library.symbols.iter() {
+            self.symbols.insert(sym.name.clone(), sym.addr, sym.size);
+        }
+
+        Ok(library)
+    }
+
+    /// Allocate `size` bytes of guest memory, aligned to `align`.
+    pub fn guest_alloc(&mut self, size: u32, align: u32) -> Result<u32, MachineError<K::Error>> {
+        match self.allocator {
+            GuestAllocator::Arena(ref mut arena) => Ok(arena.alloc(&mut self.mem, size, align)?),
+            GuestAllocator::Malloc { malloc, .. } => {
+                if !align.is_power_of_two() || align > GuestAllocator::MALLOC_ALIGN {
+                    return Err(AllocError::InvalidAlignment { align }.into());
+                }
+                match self.call(malloc, &[size])? {
+                    0 => Err(AllocError::GuestMallocFailed { size }.into()),
+                    addr => Ok(addr),
+                }
+            }
+        }
+    }
+
+    /// Free memory previously returned by [`Machine::guest_alloc`].
+    pub fn guest_free(&mut self, addr: u32) -> Result<(), MachineError<K::Error>> {
+        match self.allocator {
+            GuestAllocator::Arena(ref mut arena) => Ok(arena.free(addr)?),
+            GuestAllocator::Malloc { free, .. } => self.call(free, &[addr]).map(|_| ()),
+        }
+    }
+
+    /// Copy `bytes` into a fresh guest allocation.
+    pub fn alloc_bytes(&mut self, bytes: &[u8]) -> Result<GuestPtr<u8>, MachineError<K::Error>> {
+        self.alloc_slice(bytes)
+    }
+
+    /// Copy `items` into a fresh, naturally aligned guest allocation.
+    pub fn alloc_slice<T: GuestValue + Copy>(
+        &mut self,
+        items: &[T],
+    ) -> Result<GuestPtr<T>, MachineError<K::Error>> {
+        let size = std::mem::size_of_val(items) as u32;
+        let addr = self.guest_alloc(size, std::mem::align_of::<T>() as u32)?;
+        self.mem.write_slice(addr, items)?;
+
+        Ok(GuestPtr::new(addr))
+    }
+
+    /// Copy `s` into a fresh guest allocation as a NUL-terminated C string.
+    pub fn alloc_str(&mut self, s: &str) -> Result<GuestPtr<u8>, MachineError<K::Error>> {
+        let addr = self.guest_alloc(s.len() as u32 + 1, 1)?;
+        self.mem.write_cstr(addr, s.as_bytes())?;
+
+        Ok(GuestPtr::new(addr))
+    }
+
+    /// Switch to the guest's own `malloc`/`free`, looked up by symbol.
+    ///
+    /// The guest's libc must be initialized before allocating, e.g. by running until `main`.
+    pub fn use_guest_malloc(&mut self) -> Option<()> {
+        let malloc = self.symbols.lookup("malloc")?;
+        let free = self.symbols.lookup("free")?;
+        self.allocator = GuestAllocator::malloc(malloc, free);
+
+        Some(())
+    }
 }
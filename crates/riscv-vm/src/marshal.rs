@@ -0,0 +1,271 @@
+use std::{ffi::CStr, fmt::Debug, marker::PhantomData};
+
+use crate::{
+    error::{MemoryAccess, MemoryError},
+    memory::Memory,
+};
+
+/// Derive [`GuestValue`] for a `#[repr(C)]` struct of `GuestValue` fields.
+///
+/// Fields are read and written one by one at their offsets, so padding in
+/// guest memory is left alone. `#[guest(size = N)]` checks the struct's size
+/// against the guest ABI at compile time:
+///
+/// ```
+/// use riscv_vm::{marshal::GuestValue, memory::Memory};
+///
+/// #[derive(GuestValue, Debug, PartialEq)]
+/// #[repr(C)]
+/// #[guest(size = 16)]
+/// struct EpollEvent {
+///     events: u32,
+///     data: u64,
+/// }
+///
+/// let mut mem = Memory::new();
+/// mem.store(0x1000, EpollEvent { events: 1, data: 2 });
+/// assert_eq!(mem.load::<u32>(0x1000), 1);
+/// assert_eq!(mem.load::<EpollEvent>(0x1000), EpollEvent { events: 1, data: 2 });
+/// ```
+pub use riscv_vm_derive::GuestValue;
+
+/// A value that can be loaded from and stored to guest memory, in the
+/// guest's (little-endian) byte order.
+///
+/// Implemented for the fixed-size integers, arrays and [`GuestPtr`]s, and
+/// derivable for structs.
+pub trait GuestValue: Sized {
+    fn read_from(mem: &Memory, addr: u32) -> Self;
+
+    fn write_to(self, mem: &mut Memory, addr: u32);
+}
+
+macro_rules! impl_guest_value {
+    ($($t:ty),*) => {$(
+        impl GuestValue for $t {
+            #[inline]
+            fn read_from(mem: &Memory, addr: u32) -> Self {
+                <$t>::from_le(mem.load_primitive(addr))
+            }
+
+            #[inline]
+            fn write_to(self, mem: &mut Memory, addr: u32) {
+                mem.store_primitive(addr, self.to_le())
+            }
+        }
+    )*}
+}
+impl_guest_value!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl<T: GuestValue, const N: usize> GuestValue for [T; N] {
+    fn read_from(mem: &Memory, addr: u32) -> Self {
+        let size = std::mem::size_of::<T>() as u32;
+        std::array::from_fn(|i| T::read_from(mem, addr.wrapping_add(i as u32 * size)))
+    }
+
+    fn write_to(self, mem: &mut Memory, addr: u32) {
+        let size = std::mem::size_of::<T>() as u32;
+        for (i, val) in self.into_iter().enumerate() {
+            val.write_to(mem, addr.wrapping_add(i as u32 * size));
+        }
+    }
+}
+
+/// A typed pointer into guest memory.
+#[repr(transparent)]
+pub struct GuestPtr<T = u8> {
+    addr: u32,
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T> GuestPtr<T> {
+    pub const fn new(addr: u32) -> Self {
+        Self {
+            addr,
+            _ty: PhantomData,
+        }
+    }
+
+    pub const fn null() -> Self {
+        Self::new(0)
+    }
+
+    pub const fn addr(self) -> u32 {
+        self.addr
+    }
+
+    pub const fn is_null(self) -> bool {
+        self.addr == 0
+    }
+
+    /// Offset by `count` elements of `T`.
+    pub const fn add(self, count: u32) -> Self {
+        Self::new(
+            self.addr
+                .wrapping_add(count.wrapping_mul(std::mem::size_of::<T>() as u32)),
+        )
+    }
+
+    pub const fn cast<U>(self) -> GuestPtr<U> {
+        GuestPtr::new(self.addr)
+    }
+}
+
+impl<T: GuestValue> GuestPtr<T> {
+    pub fn read(self, mem: &Memory) -> T {
+        mem.load(self.addr)
+    }
+
+    pub fn write(self, mem: &mut Memory, val: T) {
+        mem.store(self.addr, val)
+    }
+}
+
+impl<T> GuestValue for GuestPtr<T> {
+    fn read_from(mem: &Memory, addr: u32) -> Self {
+        Self::new(mem.load(addr))
+    }
+
+    fn write_to(self, mem: &mut Memory, addr: u32) {
+        mem.store(addr, self.addr)
+    }
+}
+
+impl<T> Clone for GuestPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for GuestPtr<T> {}
+
+impl<T> PartialEq for GuestPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+    }
+}
+
+impl<T> Eq for GuestPtr<T> {}
+
+impl<T> Debug for GuestPtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GuestPtr({:#010x})", self.addr)
+    }
+}
+
+impl<T> From<u32> for GuestPtr<T> {
+    fn from(addr: u32) -> Self {
+        Self::new(addr)
+    }
+}
+
+/// A `(pointer, length)` pair in guest memory, laid out like `struct iovec`
+/// or a Rust `&[u8]` on rv32.
+#[repr(C)]
+#[derive(GuestValue, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[guest(size = 8)]
+pub struct GuestSlice {
+    pub ptr: u32,
+    pub len: u32,
+}
+
+impl Memory {
+    /// Read a NUL-terminated string of at most `max_len` bytes (excluding the NUL).
+    pub fn read_cstr(&self, addr: u32, max_len: u32) -> Result<&CStr, MemoryError> {
+        let bytes = self.bytes_null_terminated(addr, Some(max_len))?;
+        let len = bytes.len() as u32;
+        if len == max_len && self.load::<u8>(addr.wrapping_add(len)) != 0 {
+            return Err(MemoryError::Unterminated { addr, max_len });
+        }
+
+        // Safety: `bytes_null_terminated` stopped at a NUL byte, which lies within
+        // the address space, so including it in the slice is in bounds.
+        let with_nul = unsafe { std::slice::from_raw_parts(self.ptr(addr), len as usize + 1) };
+        Ok(CStr::from_bytes_with_nul(with_nul).expect("string ends at its first NUL"))
+    }
+
+    /// Read a NUL-terminated string as UTF-8 (lossily), capped at `max_len` bytes.
+    pub fn read_string(&self, addr: u32, max_len: u32) -> Result<String, MemoryError> {
+        self.read_cstr(addr, max_len)
+            .map(|s| s.to_string_lossy().into_owned())
+    }
+
+    /// Write `s` followed by a NUL terminator.
+    pub fn write_cstr(&mut self, addr: u32, s: &[u8]) -> Result<(), MemoryError> {
+        self.copy_to(addr, s)?;
+        self.copy_to(addr.wrapping_add(s.len() as u32), &[0u8])
+    }
+
+    /// Copy `len` elements starting at `addr` into a host `Vec`.
+    pub fn read_vec<T: GuestValue>(&self, addr: u32, len: u32) -> Result<Vec<T>, MemoryError> {
+        let size = std::mem::size_of::<T>() as u32;
+        len.checked_mul(size)
+            .and_then(|bytes| addr.checked_add(bytes))
+            .ok_or(MemoryError::OverflowMemoryAccess {
+                access: MemoryAccess::Load,
+                addr,
+                len: len.saturating_mul(size),
+            })?;
+
+        Ok((0..len).map(|i| self.load(addr + i * size)).collect())
+    }
+
+    /// Store `items` consecutively starting at `addr`.
+    pub fn write_slice<T: GuestValue + Copy>(
+        &mut self,
+        addr: u32,
+        items: &[T],
+    ) -> Result<(), MemoryError> {
+        let size = std::mem::size_of::<T>() as u32;
+        (items.len() as u32)
+            .checked_mul(size)
+            .and_then(|bytes| addr.checked_add(bytes))
+            .ok_or(MemoryError::OverflowMemoryAccess {
+                access: MemoryAccess::Store,
+                addr,
+                len: (items.len() as u32).saturating_mul(size),
+            })?;
+
+        for (i, &item) in items.iter().enumerate() {
+            self.store(addr + i as u32 * size, item);
+        }
+        Ok(())
+    }
+
+    /// Read a NULL-terminated array of pointers (e.g. `argv`), with at most `max` entries.
+    pub fn read_ptr_array(&self, addr: u32, max: u32) -> Result<Vec<u32>, MemoryError> {
+        let mut ptrs = vec![];
+        for i in 0..max {
+            let entry = addr
+                .checked_add(i * 4)
+                .ok_or(MemoryError::OverflowMemoryAccess {
+                    access: MemoryAccess::Load,
+                    addr,
+                    len: i * 4,
+                })?;
+            match self.load::<u32>(entry) {
+                0 => return Ok(ptrs),
+                ptr => ptrs.push(ptr),
+            }
+        }
+
+        Err(MemoryError::Unterminated {
+            addr,
+            max_len: max * 4,
+        })
+    }
+
+    /// Read `count` consecutive [`GuestSlice`]s, e.g. an `iovec` array.
+    pub fn read_guest_slices(&self, addr: u32, count: u32) -> Result<Vec<GuestSlice>, MemoryError> {
+        self.read_vec(addr, count)
+    }
+
+    pub fn write_guest_slice(&mut self, addr: u32, slice: GuestSlice) -> Result<(), MemoryError> {
+        self.write(addr, slice)
+    }
+
+    /// The bytes a [`GuestSlice`] refers to.
+    pub fn guest_slice_bytes(&self, slice: GuestSlice) -> Result<&[u8], MemoryError> {
+        self.slice(slice.ptr, slice.len)
+    }
+}
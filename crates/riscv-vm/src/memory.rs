@@ -1,21 +1,84 @@
 mod _sealed {
-    /// A trait to ensure that only Primitives can be loaded/stored.
+    /// A trait to ensure that only Primitives are accessed directly.
     pub trait Primitive {}
     macro_rules! impl_primitive {
         ($($t:ty),*) => {$(impl Primitive for $t {})*}
     }
-    impl_primitive!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+    impl_primitive!(u8, u16, u32, u64, i8, i16, i32, i64);
 }
 use _sealed::Primitive;
 
-use crate::error::{MemoryAccess, MemoryError};
+use crate::{
+    error::{MemoryAccess, MemoryError},
+    marshal::GuestValue,
+    mmio::MmioBus,
+    protect::{PageProtection, Poison},
+    shm::SharedMemory,
+};
 
 pub const PAGE_SIZE: usize = 4096;
+/// Initial `mmap_top`; anonymous mappings grow down from here.
+pub const MMAP_BASE: u32 = 0xC000_0000;
 pub const MEMORY_SIZE: usize = const {
     // Assert that usize > u32.
     assert!(std::mem::size_of::<usize>() > std::mem::size_of::<u32>());
     u32::MAX as usize + PAGE_SIZE
 };
+/// Explicit hugepage mappings are rounded up to this, a multiple of every
+/// hugepage size the host might default to.
+const HUGE_PAGE_ALIGN: usize = 1 << 30;
+
+/// What host pages back guest memory. Hugepages cut host TLB misses for
+/// guests that touch a lot of memory, at the cost of populating 2MiB at a
+/// time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HugePages {
+    /// Regular pages
+    #[default]
+    Off,
+    /// Ask for transparent hugepages with `madvise(MADV_HUGEPAGE)`, which
+    /// the host applies as it can
+    Transparent,
+    /// `MAP_HUGETLB` pages from the host's hugepage pool, which has to be
+    /// big enough for all the memory the guest touches: running out is a
+    /// `SIGBUS`. An empty pool is an error up front. Shared mappings can't
+    /// be placed over these.
+    Explicit,
+}
+
+/// Free pages in the host's hugepage pool, if it says.
+fn free_huge_pages() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("HugePages_Free:"))?;
+    line.trim().parse().ok()
+}
+
+impl std::fmt::Display for HugePages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Transparent => "transparent",
+            Self::Explicit => "explicit",
+        })
+    }
+}
+
+impl std::str::FromStr for HugePages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "transparent" => Ok(Self::Transparent),
+            "explicit" => Ok(Self::Explicit),
+            _ => Err(format!(
+                "unknown hugepage mode {s:?}, expected off, transparent or explicit"
+            )),
+        }
+    }
+}
 
 /// A fully encompassing memory struct, using `mmap` to allocate
 /// the full 32-bit (+1 page) address space.
@@ -24,41 +87,132 @@ pub const MEMORY_SIZE: usize = const {
 /// without bounds-checking.
 pub struct Memory {
     ptr: *mut u8,
+    /// Length of the host mapping, at least [`MEMORY_SIZE`]
+    len: usize,
+    huge_pages: HugePages,
     pub brk: u32,
     pub mmap_top: u32,
+    /// Devices that guest loads and stores are routed to
+    pub mmio: MmioBus,
+    /// Which pages the guest may write and execute, if that's enforced
+    pub protection: Option<PageProtection>,
+    /// What happens to memory the guest unmaps, see [`Memory::poison_range`]
+    pub poison: Poison,
 }
 
 impl Memory {
+    /// Reserve the guest address space, panicking if the host can't.
+    /// [`Memory::try_new`] reports that as an error instead.
     pub fn new() -> Self {
+        Self::try_new().expect("Failed to allocate memory")
+    }
+
+    pub fn try_new() -> Result<Self, MemoryError> {
+        Self::with_huge_pages(HugePages::Off)
+    }
+
+    /// Reserve the guest address space, backed by `huge_pages`.
+    pub fn with_huge_pages(huge_pages: HugePages) -> Result<Self, MemoryError> {
+        if huge_pages == HugePages::Explicit && free_huge_pages() == Some(0) {
+            return Err(MemoryError::HugePages {
+                source: std::io::Error::other("the host's hugepage pool is empty"),
+            });
+        }
+        let (len, flags) = match huge_pages {
+            HugePages::Explicit => (
+                MEMORY_SIZE.next_multiple_of(HUGE_PAGE_ALIGN),
+                libc::MAP_HUGETLB | libc::MAP_NORESERVE,
+            ),
+            HugePages::Off | HugePages::Transparent => (MEMORY_SIZE, 0),
+        };
         let ptr = unsafe {
             libc::mmap(
                 std::ptr::null_mut(),
-                MEMORY_SIZE,
+                len,
                 libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
                 -1,
                 0,
             )
         };
 
         if ptr == libc::MAP_FAILED {
-            panic!("Failed to allocate memory");
+            return Err(MemoryError::Reserve {
+                len,
+                source: std::io::Error::last_os_error(),
+            });
         }
 
-        Self {
+        let mem = Self {
             ptr: ptr as *mut u8,
+            len,
+            huge_pages,
             brk: 0,
-            mmap_top: 0xC000_0000u32, // Start mmap at 3GB, downwards
+            mmap_top: MMAP_BASE, // Start mmap at 3GB, downwards
+            mmio: MmioBus::default(),
+            protection: None,
+            poison: Poison::Off,
+        };
+        if huge_pages == HugePages::Transparent
+            && unsafe { libc::madvise(ptr, len, libc::MADV_HUGEPAGE) } != 0
+        {
+            return Err(MemoryError::HugePages {
+                source: std::io::Error::last_os_error(),
+            });
         }
+
+        Ok(mem)
+    }
+
+    /// What host pages back guest memory.
+    pub const fn huge_pages(&self) -> HugePages {
+        self.huge_pages
+    }
+
+    /// Load a `T` at `addr`, without checking that it ends within the address
+    /// space. [`Memory::read`] does.
+    #[inline]
+    pub fn load<T: GuestValue>(&self, addr: u32) -> T {
+        T::read_from(self, addr)
     }
 
-    pub fn load<T: Primitive>(&self, addr: u32) -> T {
+    #[inline]
+    pub fn store<T: GuestValue>(&mut self, addr: u32, val: T) {
+        val.write_to(self, addr)
+    }
+
+    /// Like [`Memory::load`], but fails if `T` would run past the end of the
+    /// address space.
+    pub fn read<T: GuestValue>(&self, addr: u32) -> Result<T, MemoryError> {
+        Self::check_value::<T>(MemoryAccess::Load, addr)?;
+        Ok(self.load(addr))
+    }
+
+    /// Like [`Memory::store`], but fails if `val` would run past the end of
+    /// the address space.
+    pub fn write<T: GuestValue>(&mut self, addr: u32, val: T) -> Result<(), MemoryError> {
+        Self::check_value::<T>(MemoryAccess::Store, addr)?;
+        self.store(addr, val);
+        Ok(())
+    }
+
+    fn check_value<T>(access: MemoryAccess, addr: u32) -> Result<(), MemoryError> {
+        let len = std::mem::size_of::<T>() as u32;
+        match addr.checked_add(len) {
+            Some(_) => Ok(()),
+            None => Err(MemoryError::OverflowMemoryAccess { access, addr, len }),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn load_primitive<T: Primitive>(&self, addr: u32) -> T {
         // Safety: Primitive types are guaranteed not to overflow the address space,
         // where `addr + size_of::<T>() < MEMORY_SIZE` is guaranteed.
         unsafe { (self.ptr.add(addr as usize) as *const T).read_unaligned() }
     }
 
-    pub fn store<T: Primitive>(&mut self, addr: u32, val: T) {
+    #[inline]
+    pub(crate) fn store_primitive<T: Primitive>(&mut self, addr: u32, val: T) {
         // Safety: Primitive types are guaranteed not to overflow the address space,
         // where `addr + size_of::<T>() < MEMORY_SIZE` is guaranteed.
         unsafe { (self.ptr.add(addr as usize) as *mut T).write_unaligned(val) }
@@ -84,6 +238,17 @@ impl Memory {
         Ok(unsafe { std::slice::from_raw_parts(self.ptr(addr) as *const T, len as usize) })
     }
 
+    pub fn slice_mut<T: Sized>(&mut self, addr: u32, len: u32) -> Result<&mut [T], MemoryError> {
+        len.checked_mul(std::mem::size_of::<T>() as u32)
+            .and_then(|l| l.checked_add(addr))
+            .ok_or(MemoryError::OverflowMemoryAccess {
+                access: MemoryAccess::Store,
+                addr,
+                len: len.saturating_mul(std::mem::size_of::<T>() as u32),
+            })?;
+        Ok(unsafe { std::slice::from_raw_parts_mut(self.ptr_mut(addr) as *mut T, len as usize) })
+    }
+
     pub fn bytes_null_terminated(
         &self,
         addr: u32,
@@ -91,10 +256,10 @@ impl Memory {
     ) -> Result<&[u8], MemoryError> {
         let max_addr = max_len
             .map_or(Some(u32::MAX), |m| addr.checked_add(m))
-            .ok_or(MemoryError::OverflowMemoryAccess {
+            .ok_or_else(|| MemoryError::OverflowMemoryAccess {
                 access: MemoryAccess::Load,
                 addr,
-                len: max_len.unwrap(),
+                len: max_len.unwrap_or_default(),
             })?;
 
         let mut cur = addr;
@@ -135,6 +300,108 @@ impl Memory {
             Ok(())
         }
     }
+
+    /// Number of guest pages backed by host memory, i.e. ever touched.
+    ///
+    /// Pages aren't returned to the host when the guest frees them, so this is
+    /// also the peak, except after shared mappings are detached.
+    pub fn resident_pages(&self) -> Result<usize, MemoryError> {
+        let residency = self.residency()?;
+        Ok(residency.iter().filter(|&&page| page & 1 != 0).count())
+    }
+
+    /// Addresses of the guest pages backed by host memory, in order.
+    pub fn resident_page_addrs(&self) -> Result<Vec<u32>, MemoryError> {
+        Ok(self
+            .residency()?
+            .iter()
+            .enumerate()
+            // The extra page past 4GiB only backs accesses straddling the end
+            .take((u32::MAX as usize + 1) / PAGE_SIZE)
+            .filter(|&(_, &page)| page & 1 != 0)
+            .map(|(page, _)| (page * PAGE_SIZE) as u32)
+            .collect())
+    }
+
+    /// One byte per guest page, with bit 0 set if the page is resident.
+    fn residency(&self) -> Result<Vec<u8>, MemoryError> {
+        let mut residency = vec![0u8; MEMORY_SIZE.div_ceil(PAGE_SIZE)];
+        let res = unsafe {
+            libc::mincore(
+                self.ptr as *mut libc::c_void,
+                MEMORY_SIZE,
+                residency.as_mut_ptr(),
+            )
+        };
+        if res != 0 {
+            let source = std::io::Error::last_os_error();
+            return Err(MemoryError::Residency { source });
+        }
+
+        Ok(residency)
+    }
+
+    /// Map `len` bytes of `shm`, starting at `offset`, over `[addr, addr + len)`.
+    ///
+    /// `addr`, `len` and `offset` must be page-aligned. Accesses to pages past the
+    /// end of `shm` fault on the host, so callers should keep the range in bounds.
+    pub fn map_shared(
+        &mut self,
+        addr: u32,
+        shm: &SharedMemory,
+        offset: u64,
+        len: u32,
+    ) -> Result<(), MemoryError> {
+        self.check_remap(addr, len)?;
+        shm.mmap(self.ptr_mut(addr), offset, len as usize, libc::MAP_FIXED)
+            .map_err(|source| MemoryError::Remap { addr, len, source })?;
+
+        Ok(())
+    }
+
+    /// Replace `[addr, addr + len)` with fresh zeroed private pages, detaching
+    /// any shared mapping there.
+    pub fn unmap_shared(&mut self, addr: u32, len: u32) -> Result<(), MemoryError> {
+        self.check_remap(addr, len)?;
+        let ptr = unsafe {
+            libc::mmap(
+                self.ptr_mut(addr) as *mut libc::c_void,
+                len as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(MemoryError::Remap {
+                addr,
+                len,
+                source: std::io::Error::last_os_error(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_remap(&self, addr: u32, len: u32) -> Result<(), MemoryError> {
+        if !(addr as usize).is_multiple_of(PAGE_SIZE) || !(len as usize).is_multiple_of(PAGE_SIZE) {
+            return Err(MemoryError::UnalignedMemoryAccess {
+                access: MemoryAccess::Store,
+                addr,
+                required: PAGE_SIZE as u32,
+            });
+        }
+        if addr.checked_add(len).is_none() {
+            return Err(MemoryError::OverflowMemoryAccess {
+                access: MemoryAccess::Store,
+                addr,
+                len,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for Memory {
@@ -146,7 +413,7 @@ impl Default for Memory {
 impl Drop for Memory {
     fn drop(&mut self) {
         unsafe {
-            libc::munmap(self.ptr as *mut libc::c_void, MEMORY_SIZE);
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
         }
     }
 }
@@ -0,0 +1,87 @@
+use std::{error::Error, time::Instant};
+
+use ::metrics::{counter, gauge, Label};
+
+use crate::{
+    error::MachineError,
+    machine::{Kernel, Machine},
+    quota::ResourceUsage,
+};
+
+/// Reports a machine's statistics through the `metrics` facade, to whatever
+/// recorder the host installed.
+///
+/// Call [`MachineMetrics::record`] periodically, for example from the
+/// [`Machine::run_with`] callback, and [`MachineMetrics::record_fault`] when
+/// the machine fails. Every metric carries the labels given at construction,
+/// so many machines can share a recorder:
+///
+/// - `derisc_instructions_total`, `derisc_syscalls_total`,
+///   `derisc_read_bytes_total` and `derisc_written_bytes_total` counters
+/// - `derisc_instructions_per_second` and `derisc_syscalls_per_second`
+///   gauges, averaged since the previous [`MachineMetrics::record`]
+/// - a `derisc_memory_bytes` gauge of resident guest memory
+/// - a `derisc_faults_total` counter, labeled by `kind` and error `code`
+pub struct MachineMetrics {
+    labels: Vec<Label>,
+    /// Usage and time of the previous `record`, for rates
+    last: Option<(ResourceUsage, Instant)>,
+}
+
+impl MachineMetrics {
+    /// Report with `labels`, typically naming the machine.
+    pub fn new(labels: impl IntoIterator<Item = (&'static str, String)>) -> Self {
+        Self {
+            labels: labels
+                .into_iter()
+                .map(|(key, value)| Label::new(key, value))
+                .collect(),
+            last: None,
+        }
+    }
+
+    /// Report `machine`'s usage so far.
+    pub fn record<K: Kernel>(&mut self, machine: &Machine<K>) {
+        let usage = machine.usage();
+        let now = Instant::now();
+        let labels = || self.labels.clone();
+
+        counter!("derisc_instructions_total", labels()).absolute(usage.instructions);
+        counter!("derisc_syscalls_total", labels()).absolute(usage.syscalls);
+        counter!("derisc_read_bytes_total", labels()).absolute(usage.bytes_read);
+        counter!("derisc_written_bytes_total", labels()).absolute(usage.bytes_written);
+        gauge!("derisc_memory_bytes", labels()).set(usage.memory_bytes as f64);
+
+        if let Some((last, at)) = self.last {
+            let secs = now.duration_since(at).as_secs_f64();
+            if secs > 0.0 {
+                let rate = |now: u64, then: u64| now.saturating_sub(then) as f64 / secs;
+                gauge!("derisc_instructions_per_second", labels())
+                    .set(rate(usage.instructions, last.instructions));
+                gauge!("derisc_syscalls_per_second", labels())
+                    .set(rate(usage.syscalls, last.syscalls));
+            }
+        }
+        self.last = Some((usage, now));
+    }
+
+    /// Count `error`, which stopped the machine.
+    pub fn record_fault<E: Error>(&self, error: &MachineError<E>) {
+        let kind = match error {
+            MachineError::Hart(_) => "hart",
+            MachineError::Memory(_) => "memory",
+            MachineError::Alloc(_) => "alloc",
+            MachineError::CallHalted { .. } => "call_halted",
+            MachineError::TooManyArgs { .. } => "too_many_args",
+            MachineError::GuestPanic(_) => "guest_panic",
+            MachineError::QuotaExceeded { .. } => "quota",
+            MachineError::Unsupported(_) => "unsupported",
+            MachineError::Unpatchable { .. } => "unpatchable",
+            MachineError::Kernel(_) => "kernel",
+        };
+        let mut labels = self.labels.clone();
+        labels.push(Label::new("kind", kind));
+        labels.push(Label::new("code", error.code().to_string()));
+        counter!("derisc_faults_total", labels).increment(1);
+    }
+}
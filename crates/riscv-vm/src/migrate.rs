@@ -0,0 +1,85 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::{
+    error::HibernateError,
+    hibernate::{write_header, write_section, END, KERNEL, MEM, REQUIRED},
+    machine::{Kernel, Machine},
+    memory::PAGE_SIZE,
+};
+
+/// Streams a machine to [`Machine::restore`] in another process or on
+/// another host while the guest keeps running, for moving long-lived guests
+/// off a host without stopping them for a full copy.
+///
+/// Memory is copied in rounds with [`PreCopy::send_dirty`], each sending the
+/// pages the guest changed since the round before, with the guest running in
+/// between. Once the rounds are small enough, stop the guest and call
+/// [`PreCopy::finish`] to send the last pages with the hart and kernel. The
+/// guest is then only stopped for that last round.
+///
+/// The stream is a hibernate image with a `MEM ` section per round.
+/// Changes are found by comparing each resident page with the copy of it
+/// last sent, so a round costs a pass over guest memory, and the stream
+/// keeps a copy of the guest's resident memory. Comparing the bytes, rather
+/// than hashes of them, means no change can go unsent.
+pub struct PreCopy<W: Write> {
+    w: W,
+    /// Every page as last sent, by address
+    sent: HashMap<u32, Box<[u8]>>,
+    /// Pages sent so far, counting pages sent more than once
+    pub pages_sent: u64,
+}
+
+impl<W: Write> PreCopy<W> {
+    /// Start streaming `machine` to `w`, failing early if its kernel can't
+    /// be saved.
+    pub fn new<K: Kernel>(mut w: W, machine: &Machine<K>) -> Result<Self, HibernateError> {
+        machine.kernel_state()?;
+        write_header(&mut w)?;
+
+        Ok(Self {
+            w,
+            sent: HashMap::new(),
+            pages_sent: 0,
+        })
+    }
+
+    /// Send the pages that changed since they were last sent, returning how
+    /// many there were.
+    pub fn send_dirty<K: Kernel>(&mut self, machine: &Machine<K>) -> Result<usize, HibernateError> {
+        let mut dirty = vec![];
+        for addr in machine.mem.resident_page_addrs()? {
+            let page = machine.mem.slice::<u8>(addr, PAGE_SIZE as u32)?;
+            match self.sent.get_mut(&addr) {
+                Some(sent) if **sent == *page => {}
+                Some(sent) => {
+                    sent.copy_from_slice(page);
+                    dirty.push(addr);
+                }
+                None => {
+                    self.sent.insert(addr, page.into());
+                    dirty.push(addr);
+                }
+            }
+        }
+
+        write_section(&mut self.w, MEM, REQUIRED, &machine.mem_state(&dirty))?;
+        self.w.flush()?;
+        self.pages_sent += dirty.len() as u64;
+
+        Ok(dirty.len())
+    }
+
+    /// Send the last changed pages, the hart and the kernel, ending the
+    /// stream. The guest must not run again on this side afterwards.
+    pub fn finish<K: Kernel>(mut self, machine: &Machine<K>) -> Result<W, HibernateError> {
+        let kernel = machine.kernel_state()?;
+        self.send_dirty(machine)?;
+        machine.write_hart_sections(&mut self.w)?;
+        write_section(&mut self.w, KERNEL, REQUIRED, &kernel)?;
+        write_section(&mut self.w, END, 0, &[])?;
+        self.w.flush()?;
+
+        Ok(self.w)
+    }
+}
@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use crate::{error::MemoryError, memory::Memory};
+
+/// A device with memory-mapped registers.
+pub trait MmioDevice {
+    /// Size of the register window in bytes.
+    fn size(&self) -> u32;
+
+    /// Read `width` (1, 2 or 4) bytes at `offset` into the window.
+    fn read(&mut self, offset: u32, width: u32) -> u32;
+
+    /// Write the low `width` bytes of `value` at `offset` into the window.
+    fn write(&mut self, offset: u32, width: u32, value: u32);
+
+    /// Advance to `now`, in instructions retired, and return the `mip` bits
+    /// the device is asserting. Devices that do DMA access `mem` here.
+    fn tick(&mut self, _mem: &mut Memory, _now: u64) -> u32 {
+        0
+    }
+
+    /// Whether the guest asked the device to stop the machine, checked
+    /// after each tick.
+    fn stop(&mut self) -> Option<DeviceStop> {
+        None
+    }
+}
+
+/// A device's request to stop the machine, see [`MmioDevice::stop`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceStop {
+    /// Halt, with the guest's exit code
+    Exit(u32),
+    /// Fail with a guest-reported assertion message
+    AssertFailed(String),
+}
+
+struct Mapping {
+    base: u32,
+    size: u32,
+    device: Box<dyn MmioDevice>,
+}
+
+/// Devices attached to guest memory.
+///
+/// Guest loads and stores that land in a device's window go to the device
+/// instead of memory. Host accesses through [`Memory`](crate::memory::Memory)
+/// always see plain memory.
+#[derive(Default)]
+pub struct MmioBus {
+    devices: Vec<Mapping>,
+    /// Smallest range covering every device, so most accesses skip the lookup
+    span: Range<u32>,
+    /// The first stop requested by a device, until taken
+    stop: Option<DeviceStop>,
+}
+
+impl MmioBus {
+    /// Attach `device` at `base`, failing if it overlaps another device.
+    pub fn attach(&mut self, base: u32, device: Box<dyn MmioDevice>) -> Result<(), MemoryError> {
+        let size = device.size();
+        let overlap = || MemoryError::MmioOverlap {
+            addr: base,
+            len: size,
+        };
+        let end = base.checked_add(size).ok_or_else(overlap)?;
+        if self
+            .devices
+            .iter()
+            .any(|m| base < m.base + m.size && m.base < end)
+        {
+            return Err(overlap());
+        }
+
+        self.span = if self.devices.is_empty() {
+            base..end
+        } else {
+            self.span.start.min(base)..self.span.end.max(end)
+        };
+        self.devices.push(Mapping { base, size, device });
+        Ok(())
+    }
+
+    fn find(&mut self, addr: u32) -> Option<(&mut (dyn MmioDevice + 'static), u32)> {
+        self.devices
+            .iter_mut()
+            .find(|m| (m.base..m.base + m.size).contains(&addr))
+            .map(|m| (m.device.as_mut(), addr - m.base))
+    }
+
+    /// Whether any device's window overlaps `addr..addr + len`.
+    pub fn overlaps(&self, addr: u32, len: u32) -> bool {
+        let end = addr as u64 + len as u64;
+        self.devices
+            .iter()
+            .any(|m| (addr as u64) < (m.base + m.size) as u64 && (m.base as u64) < end)
+    }
+
+    /// Tick every device on `mem`'s bus, returning the interrupt lines they
+    /// assert, or `None` if there are no devices.
+    #[inline(always)]
+    pub fn tick(mem: &mut Memory, now: u64) -> Option<u32> {
+        if mem.mmio.devices.is_empty() {
+            return None;
+        }
+        // Detach the devices so they can access memory
+        let mut devices = std::mem::take(&mut mem.mmio.devices);
+        let lines = devices.iter_mut().fold(0, |lines, m| {
+            let lines = lines | m.device.tick(mem, now);
+            if let Some(stop) = m.device.stop() {
+                mem.mmio.stop.get_or_insert(stop);
+            }
+            lines
+        });
+        mem.mmio.devices = devices;
+        Some(lines)
+    }
+
+    /// Take the pending stop request, if a device made one.
+    pub fn take_stop(&mut self) -> Option<DeviceStop> {
+        self.stop.take()
+    }
+
+    /// Read from the device at `addr`, or `None` if there isn't one.
+    #[inline(always)]
+    pub fn read(&mut self, addr: u32, width: u32) -> Option<u32> {
+        if !self.span.contains(&addr) {
+            return None;
+        }
+        let (device, offset) = self.find(addr)?;
+        Some(device.read(offset, width))
+    }
+
+    /// Write to the device at `addr`, returning `false` if there isn't one.
+    #[inline(always)]
+    pub fn write(&mut self, addr: u32, width: u32, value: u32) -> bool {
+        if !self.span.contains(&addr) {
+            return false;
+        }
+        let Some((device, offset)) = self.find(addr) else {
+            return false;
+        };
+        device.write(offset, width, value);
+        true
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt::Display;
+
+use crate::{hart::Hart32, symbols::SymbolTable};
+
+/// Guest functions that mean the guest is going down: `rust_panic` starts
+/// unwinding, and `abort` is where `panic = "abort"` and C assertions end up.
+const PANIC_SYMBOLS: [&str; 2] = ["rust_panic", "abort"];
+
+/// A panic or abort caught in the guest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestPanic {
+    /// The function that was entered, from [`PANIC_SYMBOLS`], or
+    /// `assert_fail` for the [`DebugConsole`](crate::debugcon::DebugConsole)
+    pub symbol: &'static str,
+    /// The panic message, if the guest printed one to stderr
+    pub message: Option<String>,
+    pub backtrace: GuestBacktrace,
+}
+
+impl GuestPanic {
+    /// Capture a panic at the entry of `symbol`.
+    pub(crate) fn capture(
+        symbol: &'static str,
+        hart: &Hart32,
+        symbols: &SymbolTable,
+        stderr: Option<String>,
+    ) -> Self {
+        Self {
+            symbol,
+            message: stderr.as_deref().and_then(panic_message),
+            backtrace: GuestBacktrace::capture(hart, symbols),
+        }
+    }
+}
+
+/// Symbolized guest frames, innermost first, from
+/// [`Machine::backtrace`](crate::machine::Machine::backtrace).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GuestBacktrace(pub Vec<String>);
+
+impl GuestBacktrace {
+    /// Where `hart` is now, then its live calls.
+    ///
+    /// With a shadow stack that's every live call; otherwise it only has the
+    /// caller, from `ra`.
+    pub(crate) fn capture(hart: &Hart32, symbols: &SymbolTable) -> Self {
+        let mut frames = vec![symbols.describe(hart.pc)];
+        frames.extend(
+            hart.backtrace()
+                .into_iter()
+                .map(|addr| symbols.describe(addr)),
+        );
+        Self(frames)
+    }
+}
+
+impl Display for GuestBacktrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Guest backtrace (innermost first):")?;
+        for (i, frame) in self.0.iter().enumerate() {
+            write!(f, "\n  #{i} {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GuestPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.message {
+            Some(message) => writeln!(f, "Guest panic: {message}")?,
+            None => writeln!(f, "Guest called {}", self.symbol)?,
+        }
+        write!(f, "{}", self.backtrace)
+    }
+}
+
+/// The last panic message in `stderr`, from `panicked at` to the end.
+///
+/// The default panic hook prints `thread '<name>' panicked at <location>:`
+/// followed by the message, before the panic reaches [`PANIC_SYMBOLS`].
+fn panic_message(stderr: &str) -> Option<String> {
+    let at = stderr.rfind("panicked at")?;
+    let start = stderr[..at].rfind('\n').map_or(0, |nl| nl + 1);
+    Some(stderr[start..].trim_end().to_string())
+}
+
+/// Entry addresses of the [`PANIC_SYMBOLS`] the guest has.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PanicTraps(Vec<(u32, &'static str)>);
+
+impl PanicTraps {
+    pub fn new(symbols: &SymbolTable) -> Self {
+        Self(
+            PANIC_SYMBOLS
+                .into_iter()
+                .filter_map(|name| Some((symbols.lookup(name)?, name)))
+                .collect(),
+        )
+    }
+
+    #[inline]
+    pub fn hit(&self, pc: u32) -> Option<&'static str> {
+        self.0
+            .iter()
+            .find(|&&(addr, _)| addr == pc)
+            .map(|&(_, name)| name)
+    }
+}
@@ -0,0 +1,133 @@
+//! Patching guest code, to instrument binaries there's no source for:
+//! stubs assembled on the host are injected into unused guest memory, and
+//! functions are redirected to them by trampolines written over their
+//! first instructions.
+
+use crate::{
+    error::MachineError,
+    machine::{Kernel, Machine},
+    trace_query::inst_len,
+};
+
+/// Bytes a trampoline overwrites: `auipc t1, %hi(target)` and
+/// `jr %lo(target)(t1)`.
+pub const TRAMPOLINE_LEN: u32 = 8;
+
+/// A function redirected by [`Machine::redirect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// Entry point of the redirected function
+    pub addr: u32,
+    /// Where it jumps to instead
+    pub stub: u32,
+    /// The whole instructions the trampoline was written over
+    pub displaced: Vec<u8>,
+    /// Runs the displaced instructions and jumps to the rest of the
+    /// function, for stubs to call it as it was. `None` if one of them
+    /// depends on where it runs, such as a branch or `auipc`, or writes
+    /// `t1`, which the jump back needs.
+    pub original: Option<u32>,
+}
+
+impl<K: Kernel> Machine<K> {
+    /// Copy `code` into unused guest memory, from [`Machine::guest_alloc`],
+    /// returning where it went.
+    pub fn inject(&mut self, code: &[u8]) -> Result<u32, MachineError<K::Error>> {
+        let addr = self.guest_alloc(code.len() as u32, 4)?;
+        self.mem.copy_to(addr, code)?;
+
+        Ok(addr)
+    }
+
+    /// Make the function at `addr` jump to `stub` on entry, which then runs
+    /// in its place and returns to its caller.
+    ///
+    /// The trampoline clobbers `t1`, which the calling convention lets a
+    /// function do on entry. Code that branches back into the first
+    /// [`TRAMPOLINE_LEN`] bytes of the function will run into it.
+    pub fn redirect(&mut self, addr: u32, stub: u32) -> Result<Patch, MachineError<K::Error>> {
+        if let Some(sym) = self.symbols.containing(addr) {
+            if sym.size != 0 && sym.addr + sym.size - addr < TRAMPOLINE_LEN {
+                return Err(MachineError::Unpatchable {
+                    addr,
+                    reason: "the function is too short for a trampoline",
+                });
+            }
+        }
+
+        let mut len = 0;
+        let mut relocatable = true;
+        while len < TRAMPOLINE_LEN {
+            let inst = self.mem.load::<u32>(addr.wrapping_add(len));
+            relocatable &= !pc_relative(inst) && !writes_t1(inst);
+            len += inst_len(inst);
+        }
+        let displaced = self.mem.slice::<u8>(addr, len)?.to_vec();
+
+        let original = if relocatable {
+            let thunk = self.guest_alloc(len + TRAMPOLINE_LEN, 4)?;
+            self.mem.copy_to(thunk, &displaced)?;
+            self.mem
+                .copy_to(thunk + len, &trampoline(thunk + len, addr + len))?;
+            Some(thunk)
+        } else {
+            None
+        };
+        self.mem.copy_to(addr, &trampoline(addr, stub))?;
+
+        Ok(Patch {
+            addr,
+            stub,
+            displaced,
+            original,
+        })
+    }
+
+    /// Put back the instructions `patch` displaced, and free its
+    /// [original](Patch::original) thunk. The stub stays where it was.
+    pub fn unpatch(&mut self, patch: &Patch) -> Result<(), MachineError<K::Error>> {
+        self.mem.copy_to(patch.addr, &patch.displaced)?;
+        if let Some(thunk) = patch.original {
+            self.guest_free(thunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `auipc t1` and `jr` from `pc` to `target`.
+fn trampoline(pc: u32, target: u32) -> [u32; 2] {
+    let offset = target.wrapping_sub(pc);
+    let hi = offset.wrapping_add(0x800) & !0xfff;
+    let lo = offset.wrapping_sub(hi) & 0xfff;
+    [hi | 6 << 7 | 0x17, lo << 20 | 6 << 15 | 0x67]
+}
+
+/// Whether `inst` does something different at another address.
+fn pc_relative(inst: u32) -> bool {
+    if inst_len(inst) == 4 {
+        // `auipc`, `jal`, branches
+        matches!(inst & 0x7f, 0x17 | 0x6f | 0x63)
+    } else {
+        // `c.jal`, `c.j`, `c.beqz`, `c.bnez`
+        inst & 0b11 == 0b01 && matches!(inst >> 13 & 0b111, 1 | 5 | 6 | 7)
+    }
+}
+
+/// Whether `inst` may write `t1`, erring towards yes.
+fn writes_t1(inst: u32) -> bool {
+    let rd = inst >> 7 & 0x1f;
+    if inst_len(inst) == 4 {
+        // Stores and branches have no `rd`
+        rd == 6 && !matches!(inst & 0x7f, 0x23 | 0x63)
+    } else {
+        // The formats with a full register in `rd`
+        let funct3 = inst >> 13 & 0b111;
+        rd == 6
+            && match inst & 0b11 {
+                0b01 => matches!(funct3, 0 | 2 | 3),
+                0b10 => matches!(funct3, 0 | 2 | 4),
+                _ => false,
+            }
+    }
+}
@@ -0,0 +1,189 @@
+use std::{collections::BTreeMap, io::Write};
+
+use riscv_inst::codegen::rv32imasfdc::Rv32IMASFDC;
+
+use crate::{
+    csr::csr_name,
+    hpm::{Hpm, HpmEvent},
+};
+
+/// Stages of the modeled pipeline, as named in the trace.
+const STAGES: [&str; 5] = ["F", "D", "X", "M", "W"];
+const FETCH: usize = 0;
+const EXECUTE: usize = 2;
+const MEMORY: usize = 3;
+
+/// Cycles added to fetch or memory access by a cache miss.
+pub const MISS_PENALTY: u64 = 10;
+/// Execute latency of multiplies.
+pub const MUL_LATENCY: u64 = 3;
+/// Execute latency of divides and remainders.
+pub const DIV_LATENCY: u64 = 20;
+
+/// Writes a pipeline trace in the Kanata format read by the Konata viewer.
+///
+/// Retired instructions are timed on a classic in-order five stage pipeline
+/// (fetch, decode, execute, memory, writeback), one instruction per stage.
+/// Each stage takes a cycle, except:
+///
+/// - fetch takes [`MISS_PENALTY`] more on an instruction cache miss, and
+///   memory as much more on a data cache miss, with the cache simulator on
+/// - execute takes [`MUL_LATENCY`] for multiplies and [`DIV_LATENCY`] for
+///   divides
+/// - a mispredicted branch, with a branch predictor on, holds the next fetch
+///   until the branch leaves execute
+///
+/// An instruction stalled in a stage holds the one behind it in the previous
+/// stage. There's no forwarding model, so data hazards don't stall, and
+/// squashed wrong-path instructions aren't shown.
+pub struct PipelineTrace {
+    out: Box<dyn Write>,
+    /// The first write error, after which nothing more is written
+    error: Option<std::io::Error>,
+    /// Instructions retired so far, used as the trace ID
+    retired: u64,
+    /// Cycles at which the last instruction entered each stage, and retired
+    last_enter: [u64; STAGES.len() + 1],
+    /// Earliest cycle the next instruction can be fetched
+    redirect: u64,
+    /// Cycle of the last command written
+    cycle: u64,
+    /// Commands not yet written, by cycle
+    pending: BTreeMap<u64, Vec<String>>,
+    /// Event counts as of the last instruction
+    icache_misses: u64,
+    dcache_misses: u64,
+    mispredicts: u64,
+}
+
+impl PipelineTrace {
+    pub fn new(out: impl Write + 'static) -> Self {
+        let mut trace = Self {
+            out: Box::new(out),
+            error: None,
+            retired: 0,
+            last_enter: [0; STAGES.len() + 1],
+            redirect: 0,
+            cycle: 0,
+            pending: BTreeMap::new(),
+            icache_misses: 0,
+            dcache_misses: 0,
+            mispredicts: 0,
+        };
+        trace.write_line("Kanata\t0004".to_string());
+        trace.write_line("C=\t0".to_string());
+        trace
+    }
+
+    fn write_line(&mut self, line: String) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{line}") {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Write pending commands before `cycle`, which no later instruction can precede.
+    fn flush_before(&mut self, cycle: u64) {
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() >= cycle {
+                break;
+            }
+            let (at, lines) = entry.remove_entry();
+            if at > self.cycle {
+                self.write_line(format!("C\t{}", at - self.cycle));
+                self.cycle = at;
+            }
+            for line in lines {
+                self.write_line(line);
+            }
+        }
+    }
+
+    fn at(&mut self, cycle: u64, line: String) {
+        self.pending.entry(cycle).or_default().push(line);
+    }
+
+    /// Record the instruction `inst` at `pc`, which just retired, using the
+    /// events counted in `hpm` since the last one to time it.
+    pub fn retire(&mut self, pc: u32, inst: u32, op: &Rv32IMASFDC, hpm: &Hpm) {
+        let delta = |last: &mut u64, event| {
+            let now = hpm.event_count(event);
+            let delta = now.wrapping_sub(*last);
+            *last = now;
+            delta != 0
+        };
+        let icache_miss = delta(&mut self.icache_misses, HpmEvent::ICacheMisses);
+        let dcache_miss = delta(&mut self.dcache_misses, HpmEvent::DCacheMisses);
+        let mispredict = delta(&mut self.mispredicts, HpmEvent::BranchMispredicts);
+
+        let mnemonic = op.mnemonic();
+        let mut latency = [1; STAGES.len()];
+        if icache_miss {
+            latency[FETCH] += MISS_PENALTY;
+        }
+        if dcache_miss {
+            latency[MEMORY] += MISS_PENALTY;
+        }
+        if mnemonic.starts_with("mul") {
+            latency[EXECUTE] = MUL_LATENCY;
+        } else if mnemonic.starts_with("div") || mnemonic.starts_with("rem") {
+            latency[EXECUTE] = DIV_LATENCY;
+        }
+
+        // Enter each stage once this instruction is done with the previous
+        // one and the instruction ahead has moved on
+        let mut enter = [0; STAGES.len() + 1];
+        enter[0] = self.redirect.max(self.last_enter[1]);
+        for stage in 1..=STAGES.len() {
+            let ahead = self.last_enter.get(stage + 1).copied().unwrap_or(0);
+            enter[stage] = (enter[stage - 1] + latency[stage - 1]).max(ahead);
+        }
+        self.redirect = if mispredict { enter[EXECUTE + 1] } else { 0 };
+
+        let id = self.retired;
+        self.at(enter[0], format!("I\t{id}\t{id}\t0"));
+        let encoding = if inst & 0b11 == 0b11 {
+            format!("{inst:08x}")
+        } else {
+            format!("{:04x}", inst & 0xFFFF)
+        };
+        // CSR instructions name the CSR, in the immediate's place
+        let operand = if mnemonic.starts_with("csrr") {
+            format!(" {}", csr_name((inst >> 20) as u16))
+        } else {
+            String::new()
+        };
+        self.at(
+            enter[0],
+            format!("L\t{id}\t0\t{pc:08x}: {mnemonic}{operand} ({encoding})"),
+        );
+        for (stage, name) in STAGES.iter().enumerate() {
+            self.at(enter[stage], format!("S\t{id}\t0\t{name}"));
+        }
+        self.at(enter[STAGES.len()], format!("R\t{id}\t{id}\t0"));
+
+        self.last_enter = enter;
+        self.retired += 1;
+        self.flush_before(enter[1].max(self.redirect));
+    }
+
+    /// Write everything still pending and flush the output, returning the
+    /// first write error, if any.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.flush_before(u64::MAX);
+        if let Err(e) = self.out.flush() {
+            self.error.get_or_insert(e);
+        }
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PipelineTrace {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
@@ -0,0 +1,171 @@
+use std::{cell::Cell, rc::Rc};
+
+use crate::{memory::Memory, mmio::MmioDevice, trap::Interrupt};
+
+/// Where the PLIC usually lives, matching QEMU's `virt` machine.
+pub const PLIC_BASE: u32 = 0x0C00_0000;
+
+/// Interrupt sources, including the reserved source 0.
+pub const PLIC_SOURCES: u32 = 64;
+
+// Register offsets. Only context 0, hart 0 in M-mode, exists.
+const PRIORITY: u32 = 0x00_0000;
+const PENDING: u32 = 0x00_1000;
+const ENABLE: u32 = 0x00_2000;
+const THRESHOLD: u32 = 0x20_0000;
+const CLAIM_COMPLETE: u32 = 0x20_0004;
+
+const WORDS: usize = PLIC_SOURCES as usize / 32;
+
+/// A device's interrupt line into the [`Plic`]. The device holds it high
+/// while it wants service.
+#[derive(Debug, Clone, Default)]
+pub struct IrqLine(Rc<Cell<bool>>);
+
+impl IrqLine {
+    pub fn set(&self, high: bool) {
+        self.0.set(high);
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.0.get()
+    }
+}
+
+/// A platform-level interrupt controller routing device interrupts to the
+/// hart's external interrupt.
+///
+/// Lines are level-triggered. A high line becomes pending unless its source
+/// has been claimed and not yet completed. The hart's external interrupt is
+/// raised while an enabled source with priority above the threshold is pending.
+#[derive(Debug, Clone)]
+pub struct Plic {
+    lines: Vec<IrqLine>,
+    priority: [u32; PLIC_SOURCES as usize],
+    pending: [u32; WORDS],
+    enable: [u32; WORDS],
+    /// Claimed and not yet completed
+    in_service: [u32; WORDS],
+    threshold: u32,
+}
+
+impl Plic {
+    pub fn new() -> Self {
+        Self {
+            lines: (0..PLIC_SOURCES).map(|_| IrqLine::default()).collect(),
+            priority: [0; PLIC_SOURCES as usize],
+            pending: [0; WORDS],
+            enable: [0; WORDS],
+            in_service: [0; WORDS],
+            threshold: 0,
+        }
+    }
+
+    /// The line for interrupt `source`, or `None` if it's 0 or out of range.
+    pub fn line(&self, source: u32) -> Option<IrqLine> {
+        match source {
+            0 => None,
+            _ => self.lines.get(source as usize).cloned(),
+        }
+    }
+
+    fn bit(bits: &[u32; WORDS], source: usize) -> bool {
+        bits[source / 32] & (1 << (source % 32)) != 0
+    }
+
+    fn set_bit(bits: &mut [u32; WORDS], source: usize, set: bool) {
+        let mask = 1 << (source % 32);
+        if set {
+            bits[source / 32] |= mask;
+        } else {
+            bits[source / 32] &= !mask;
+        }
+    }
+
+    /// Latch high lines into `pending`, except for sources in service.
+    fn sample(&mut self) {
+        for source in 1..PLIC_SOURCES as usize {
+            if self.lines[source].is_high() && !Self::bit(&self.in_service, source) {
+                Self::set_bit(&mut self.pending, source, true);
+            }
+        }
+    }
+
+    /// The highest priority pending and enabled source, lowest ID first on ties.
+    fn best(&self) -> Option<usize> {
+        (1..PLIC_SOURCES as usize)
+            .filter(|&s| Self::bit(&self.pending, s) && Self::bit(&self.enable, s))
+            .filter(|&s| self.priority[s] > 0)
+            .max_by_key(|&s| (self.priority[s], std::cmp::Reverse(s)))
+    }
+
+    fn claim(&mut self) -> u32 {
+        self.sample();
+        let Some(source) = self.best() else {
+            return 0;
+        };
+        Self::set_bit(&mut self.pending, source, false);
+        Self::set_bit(&mut self.in_service, source, true);
+        source as u32
+    }
+
+    fn complete(&mut self, source: u32) {
+        if (1..PLIC_SOURCES).contains(&source) {
+            Self::set_bit(&mut self.in_service, source as usize, false);
+        }
+    }
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmioDevice for Plic {
+    fn size(&self) -> u32 {
+        0x40_0000
+    }
+
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        let word = |base: u32| ((offset - base) / 4) as usize;
+        match offset & !3 {
+            o if o < PRIORITY + PLIC_SOURCES * 4 => self.priority[word(PRIORITY)],
+            o if (PENDING..PENDING + WORDS as u32 * 4).contains(&o) => {
+                self.sample();
+                self.pending[word(PENDING)]
+            }
+            o if (ENABLE..ENABLE + WORDS as u32 * 4).contains(&o) => self.enable[word(ENABLE)],
+            THRESHOLD => self.threshold,
+            CLAIM_COMPLETE => self.claim(),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        let word = |base: u32| ((offset - base) / 4) as usize;
+        match offset & !3 {
+            // Source 0 doesn't exist, so its priority stays 0
+            PRIORITY => {}
+            o if o < PRIORITY + PLIC_SOURCES * 4 => self.priority[word(PRIORITY)] = value & 7,
+            o if (ENABLE..ENABLE + WORDS as u32 * 4).contains(&o) => {
+                self.enable[word(ENABLE)] = value;
+                self.enable[0] &= !1;
+            }
+            THRESHOLD => self.threshold = value & 7,
+            CLAIM_COMPLETE => self.complete(value),
+            // Pending bits are read-only
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, _mem: &mut Memory, _now: u64) -> u32 {
+        self.sample();
+        match self.best() {
+            Some(source) if self.priority[source] > self.threshold => {
+                Interrupt::MachineExternal.mask()
+            }
+            _ => 0,
+        }
+    }
+}
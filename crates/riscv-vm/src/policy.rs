@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+use riscv_inst::{codegen::rv32imasfdc::Rv32IMASFDC, Extension};
+
+const _: () = assert!(Rv32IMASFDC::COUNT <= 256, "OpcodeSet is a 256-bit bitset");
+
+/// Which opcodes a hart may execute, on top of its extension set.
+///
+/// A rejected opcode raises [`HartError::ForbiddenInst`](crate::error::HartError::ForbiddenInst)
+/// instead of executing.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum InstPolicy {
+    #[default]
+    AllowAll,
+    /// Only opcodes in the set may execute
+    Allow(OpcodeSet),
+    /// Opcodes in the set may not execute
+    Deny(OpcodeSet),
+}
+
+impl InstPolicy {
+    #[inline(always)]
+    pub fn permits(&self, op: &Rv32IMASFDC) -> bool {
+        match self {
+            InstPolicy::AllowAll => true,
+            InstPolicy::Allow(set) => set.contains(op),
+            InstPolicy::Deny(set) => !set.contains(op),
+        }
+    }
+}
+
+/// A set of rv32 opcodes, keyed by [`Rv32IMASFDC::id`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpcodeSet([u128; 2]);
+
+impl OpcodeSet {
+    pub const EMPTY: Self = Self([0; 2]);
+
+    /// Every opcode belonging to `ext`.
+    pub fn extension(ext: Extension) -> Self {
+        Self::matching(|_, e| e == ext)
+    }
+
+    /// The read-modify-write atomics (`amo*`), but not LR/SC.
+    pub fn amo() -> Self {
+        Self::matching(|mnemonic, _| mnemonic.starts_with("amo"))
+    }
+
+    /// All CSR accesses.
+    pub fn csr() -> Self {
+        Self::extension(Extension::Zicsr)
+    }
+
+    /// Every opcode for which `f(mnemonic, extension)` holds.
+    pub fn matching(f: impl Fn(&str, Extension) -> bool) -> Self {
+        Rv32IMASFDC::MNEMONICS
+            .iter()
+            .zip(Rv32IMASFDC::EXTENSIONS)
+            .enumerate()
+            .filter(|(_, (mnemonic, ext))| f(mnemonic, *ext))
+            .fold(Self::EMPTY, |mut set, (id, _)| {
+                set.0[id / 128] |= 1 << (id % 128);
+                set
+            })
+    }
+
+    /// Build a set from mnemonics such as `"amoswap.w"` or `"c.ebreak"`.
+    pub fn from_mnemonics<'a>(
+        mnemonics: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, UnknownOpcode> {
+        let mut set = Self::EMPTY;
+        for mnemonic in mnemonics {
+            set.insert(mnemonic)?;
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, mnemonic: &str) -> Result<(), UnknownOpcode> {
+        let id = Rv32IMASFDC::MNEMONICS
+            .iter()
+            .position(|&m| m == mnemonic)
+            .ok_or_else(|| UnknownOpcode(mnemonic.to_string()))?;
+        self.0[id / 128] |= 1 << (id % 128);
+        Ok(())
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self([self.0[0] | other.0[0], self.0[1] | other.0[1]])
+    }
+
+    #[inline(always)]
+    pub const fn contains(&self, op: &Rv32IMASFDC) -> bool {
+        let id = op.id() as usize;
+        self.0[id / 128] & (1 << (id % 128)) != 0
+    }
+
+    pub fn mnemonics(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Rv32IMASFDC::MNEMONICS
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| self.0[id / 128] & (1 << (id % 128)) != 0)
+            .map(|(_, &m)| m)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOpcode(pub String);
+
+impl Display for UnknownOpcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown rv32 opcode {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOpcode {}
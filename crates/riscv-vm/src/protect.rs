@@ -0,0 +1,329 @@
+//! Page permissions, so untrusted guests only run code from pages loaded or
+//! mapped as code, and can be held to W^X, and poisoning of pages the guest
+//! unmaps, to catch it using them after.
+//!
+//! Only the hart's accesses are checked. Reads are allowed from any page
+//! that isn't poisoned, and the host, kernels and devices access guest
+//! memory freely.
+
+use crate::{
+    error::{MemoryAccess, MemoryError},
+    memory::{Memory, PAGE_SIZE},
+};
+
+/// Pages in the 32-bit address space.
+pub(crate) const PAGES: usize = 1 << 20;
+
+/// Whether and how guest page permissions are enforced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecProtection {
+    /// Any page can be written and executed
+    #[default]
+    Off,
+    /// Pages can only be executed, or written, as the kernel mapped them
+    Regions,
+    /// As with `Regions`, and mapping a page both writable and executable
+    /// is a [`MemoryError::WriteXorExecute`]
+    WriteXorExecute,
+}
+
+impl std::fmt::Display for ExecProtection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Regions => "regions",
+            Self::WriteXorExecute => "wx",
+        })
+    }
+}
+
+impl std::str::FromStr for ExecProtection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "regions" => Ok(Self::Regions),
+            "wx" => Ok(Self::WriteXorExecute),
+            _ => Err(format!(
+                "unknown execution protection {s:?}, expected off, regions or wx"
+            )),
+        }
+    }
+}
+
+/// What happens to memory the guest unmaps, see [`Memory::poison_range`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Poison {
+    /// It keeps its contents
+    #[default]
+    Off,
+    /// It's filled with [`POISON_BYTE`]s, which stand out in values read
+    /// from it
+    Fill,
+    /// As with `Fill`, and its pages stay trapped until they're mapped
+    /// again: guest accesses to them are a [`MemoryError::Poisoned`]
+    Trap,
+}
+
+/// What poisoned memory is filled with, as in the Linux kernel's freed
+/// slab objects.
+pub const POISON_BYTE: u8 = 0x6b;
+
+impl std::fmt::Display for Poison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Fill => "fill",
+            Self::Trap => "trap",
+        })
+    }
+}
+
+impl std::str::FromStr for Poison {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "fill" => Ok(Self::Fill),
+            "trap" => Ok(Self::Trap),
+            _ => Err(format!(
+                "unknown poison mode {s:?}, expected off, fill or trap"
+            )),
+        }
+    }
+}
+
+/// What a page may be used for besides reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagePerms {
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl PagePerms {
+    /// Writable data, which pages are until mapped otherwise
+    pub const DATA: Self = Self {
+        write: true,
+        exec: false,
+    };
+    pub const CODE: Self = Self {
+        write: false,
+        exec: true,
+    };
+    pub const READ_ONLY: Self = Self {
+        write: false,
+        exec: false,
+    };
+    /// Writable code, which [`ExecProtection::WriteXorExecute`] forbids
+    pub const ALL: Self = Self {
+        write: true,
+        exec: true,
+    };
+}
+
+/// Permissions of every guest page, and which are poisoned, see
+/// [`Memory::protection`].
+pub struct PageProtection {
+    mode: ExecProtection,
+    pages: Vec<Page>,
+}
+
+#[derive(Clone, Copy)]
+struct Page {
+    perms: PagePerms,
+    poisoned: bool,
+}
+
+impl PageProtection {
+    /// Permissions enforced as `mode` says, and pages trapped if `poison`
+    /// does, or `None` if neither is on. Every page starts as
+    /// [`PagePerms::DATA`], or [`PagePerms::ALL`] with permissions off.
+    pub fn new(mode: ExecProtection, poison: Poison) -> Option<Self> {
+        let page = Page {
+            perms: Self::enforced(mode, PagePerms::DATA),
+            poisoned: false,
+        };
+        (mode != ExecProtection::Off || poison == Poison::Trap).then(|| Self {
+            mode,
+            pages: vec![page; PAGES],
+        })
+    }
+
+    pub fn mode(&self) -> ExecProtection {
+        self.mode
+    }
+
+    /// Permissions of the page holding `addr`.
+    pub fn get(&self, addr: u32) -> PagePerms {
+        self.pages[page(addr)].perms
+    }
+
+    /// Whether the page holding `addr` is poisoned.
+    pub fn is_poisoned(&self, addr: u32) -> bool {
+        self.pages[page(addr)].poisoned
+    }
+
+    /// Fail if the pages of `addr..addr + len` can't be given `perms`.
+    pub fn check(&self, addr: u32, len: u32, perms: PagePerms) -> Result<(), MemoryError> {
+        if self.mode == ExecProtection::WriteXorExecute && perms == PagePerms::ALL {
+            return Err(MemoryError::WriteXorExecute { addr, len });
+        }
+
+        Ok(())
+    }
+
+    /// Give every page overlapping `addr..addr + len` `perms`, mapping
+    /// them again if they were poisoned.
+    pub fn set(&mut self, addr: u32, len: u32, perms: PagePerms) -> Result<(), MemoryError> {
+        self.check(addr, len, perms)?;
+        let page = Page {
+            perms: Self::enforced(self.mode, perms),
+            poisoned: false,
+        };
+        self.fill(addr, len, page)
+    }
+
+    /// Trap guest accesses to every page overlapping `addr..addr + len`.
+    pub fn poison(&mut self, addr: u32, len: u32) -> Result<(), MemoryError> {
+        let page = Page {
+            perms: PagePerms::READ_ONLY,
+            poisoned: true,
+        };
+        self.fill(addr, len, page)
+    }
+
+    fn fill(&mut self, addr: u32, len: u32, page: Page) -> Result<(), MemoryError> {
+        if len == 0 {
+            return Ok(());
+        }
+        let last = addr
+            .checked_add(len - 1)
+            .ok_or(MemoryError::OverflowMemoryAccess {
+                access: MemoryAccess::Store,
+                addr,
+                len,
+            })?;
+        self.pages[self::page(addr)..=self::page(last)].fill(page);
+
+        Ok(())
+    }
+
+    /// Each page's permissions and poisoning as a byte, `1` if writable, `2`
+    /// if executable and `4` if poisoned, for machine images.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.pages
+            .iter()
+            .map(|page| {
+                page.perms.write as u8 | (page.perms.exec as u8) << 1 | (page.poisoned as u8) << 2
+            })
+            .collect()
+    }
+
+    /// Replace every page's permissions and poisoning with `bytes`, one per
+    /// page as [`PageProtection::to_bytes`] wrote them.
+    pub(crate) fn restore_bytes(&mut self, bytes: &[u8]) {
+        for (page, &byte) in self.pages.iter_mut().zip(bytes) {
+            *page = Page {
+                perms: PagePerms {
+                    write: byte & 1 != 0,
+                    exec: byte & 2 != 0,
+                },
+                poisoned: byte & 4 != 0,
+            };
+        }
+    }
+
+    /// `perms`, unless permissions aren't enforced
+    fn enforced(mode: ExecProtection, perms: PagePerms) -> PagePerms {
+        match mode {
+            ExecProtection::Off => PagePerms::ALL,
+            _ => perms,
+        }
+    }
+
+    /// Fail unless the instruction at `pc` may run.
+    #[inline]
+    pub(crate) fn fetch(&self, pc: u32) -> Result<(), MemoryError> {
+        let page = self.pages[page(pc)];
+        if !page.perms.exec {
+            return Err(Self::denied(page, MemoryAccess::Fetch, pc));
+        }
+
+        Ok(())
+    }
+
+    /// Fail unless `size` bytes at `addr` may be loaded.
+    #[inline]
+    pub(crate) fn load(&self, addr: u32, size: u32) -> Result<(), MemoryError> {
+        let last = addr.wrapping_add(size - 1);
+        if self.is_poisoned(addr) || self.is_poisoned(last) {
+            return Err(MemoryError::Poisoned {
+                access: MemoryAccess::Load,
+                addr,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fail unless `size` bytes at `addr` may be stored to.
+    #[inline]
+    pub(crate) fn store(&self, addr: u32, size: u32) -> Result<(), MemoryError> {
+        let last = addr.wrapping_add(size - 1);
+        for page in [self.pages[page(addr)], self.pages[page(last)]] {
+            if !page.perms.write {
+                return Err(Self::denied(page, MemoryAccess::Store, addr));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Why `access` to `page` failed.
+    fn denied(page: Page, access: MemoryAccess, addr: u32) -> MemoryError {
+        if page.poisoned {
+            MemoryError::Poisoned { access, addr }
+        } else {
+            MemoryError::Protection { access, addr }
+        }
+    }
+}
+
+impl Memory {
+    /// Give the pages of `addr..addr + len` `perms`, if permissions are
+    /// enforced.
+    pub fn protect(&mut self, addr: u32, len: u32, perms: PagePerms) -> Result<(), MemoryError> {
+        match &mut self.protection {
+            Some(protection) => protection.set(addr, len, perms),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if [`Memory::protect`] would refuse `perms`, such as under W^X.
+    pub fn check_protect(&self, addr: u32, len: u32, perms: PagePerms) -> Result<(), MemoryError> {
+        match &self.protection {
+            Some(protection) => protection.check(addr, len, perms),
+            None => Ok(()),
+        }
+    }
+
+    /// Poison `addr..addr + len`, which the guest unmapped, as
+    /// [`Memory::poison`] says. Kernels call this as they unmap memory;
+    /// mapping it again with [`Memory::protect`] lifts the trap.
+    pub fn poison_range(&mut self, addr: u32, len: u32) -> Result<(), MemoryError> {
+        if self.poison == Poison::Off {
+            return Ok(());
+        }
+        self.memset(addr, POISON_BYTE, len)?;
+        match &mut self.protection {
+            Some(protection) if self.poison == Poison::Trap => protection.poison(addr, len),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn page(addr: u32) -> usize {
+    addr as usize / PAGE_SIZE
+}
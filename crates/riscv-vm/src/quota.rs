@@ -0,0 +1,90 @@
+/// Instructions between checks of [`Quotas::memory_bytes`].
+pub(crate) const QUOTA_STRIDE: u64 = 1 << 22;
+
+/// What a kernel has accounted for so far, see
+/// [`Kernel::usage`](crate::machine::Kernel::usage).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KernelUsage {
+    /// Syscalls the guest made
+    pub syscalls: u64,
+    /// Bytes the guest read from files, pipes and consoles
+    pub bytes_read: u64,
+    /// Bytes the guest wrote to files, pipes and consoles
+    pub bytes_written: u64,
+}
+
+/// Resources a machine has used, from
+/// [`Machine::usage`](crate::machine::Machine::usage).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub instructions: u64,
+    pub syscalls: u64,
+    /// Guest memory backed by host memory. Pages are never given back, so
+    /// this only grows.
+    pub memory_bytes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl ResourceUsage {
+    /// How much of `resource` has been used.
+    pub fn get(&self, resource: Resource) -> u64 {
+        match resource {
+            Resource::Instructions => self.instructions,
+            Resource::Syscalls => self.syscalls,
+            Resource::Memory => self.memory_bytes,
+            Resource::Io => self.bytes_read + self.bytes_written,
+        }
+    }
+}
+
+/// A resource with a quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Instructions,
+    Syscalls,
+    /// Resident guest memory, in bytes
+    Memory,
+    /// Bytes read and written
+    Io,
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Instructions => "instruction",
+            Self::Syscalls => "syscall",
+            Self::Memory => "memory",
+            Self::Io => "I/O",
+        })
+    }
+}
+
+/// Hard limits on what a machine may use. Going over one stops the machine
+/// with [`MachineError::QuotaExceeded`](crate::error::MachineError::QuotaExceeded).
+///
+/// The guest stops right before the instruction past its instruction quota,
+/// and right after the syscall that takes it over its syscall or I/O quota.
+/// Memory is only checked every few million instructions, so a guest can
+/// overshoot its memory quota by what it touches in between.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quotas {
+    pub instructions: Option<u64>,
+    pub syscalls: Option<u64>,
+    /// Resident guest memory, in bytes
+    pub memory_bytes: Option<u64>,
+    /// Bytes read and written
+    pub io_bytes: Option<u64>,
+}
+
+impl Quotas {
+    /// The quota on `resource`, if any.
+    pub fn get(&self, resource: Resource) -> Option<u64> {
+        match resource {
+            Resource::Instructions => self.instructions,
+            Resource::Syscalls => self.syscalls,
+            Resource::Memory => self.memory_bytes,
+            Resource::Io => self.io_bytes,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::{Display, Write};
+
+use riscv_inst::Reg;
+
+use crate::symbols::SymbolTable;
+
+/// A call recorded on the [`ShadowStack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShadowFrame {
+    /// Address of the call instruction
+    pub call_site: u32,
+    /// Address of the callee
+    pub callee: u32,
+    /// Return address written to the link register
+    pub ret: u32,
+}
+
+/// A return to an address that doesn't match any live call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShadowViolation {
+    /// Address of the return instruction
+    pub pc: u32,
+    pub target: u32,
+    /// Return address of the innermost live call
+    pub expected: u32,
+}
+
+impl Display for ShadowViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Return at {:#010x} to {:#010x}, expected {:#010x}",
+            self.pc, self.target, self.expected
+        )
+    }
+}
+
+/// Whether a jump linking `rd` through `rs1` (JALR only) is a call and
+/// whether it is a return, by the RAS hints from the ISA manual: a jump
+/// writing `ra`/`t0` is a call, and a jump through `ra`/`t0` that doesn't
+/// link it is a return.
+#[inline]
+pub(crate) fn classify_jump(rd: Reg, rs1: Option<Reg>) -> (bool, bool) {
+    let is_link = |r: Reg| matches!(r, Reg::Ra | Reg::T0);
+    let links = is_link(rd);
+    let returns = rs1.is_some_and(|rs1| is_link(rs1) && rs1 != rd);
+    (links, returns)
+}
+
+/// Return-address shadow stack, maintained on JAL/JALR.
+///
+/// Calls and returns are classified using the RAS hints from the ISA manual: a jump
+/// writing `ra`/`t0` is a call, and a jump through `ra`/`t0` that doesn't link is a
+/// return. Returns to a frame further down the stack (`longjmp`, unwinding) pop every
+/// frame above it; returns that match no frame are violations.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowStack {
+    frames: Vec<ShadowFrame>,
+}
+
+impl ShadowStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Live calls, outermost first.
+    pub fn frames(&self) -> &[ShadowFrame] {
+        &self.frames
+    }
+
+    /// Record a call made other than by a jump, such as the host's.
+    pub fn push(&mut self, frame: ShadowFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Drop the calls above the first `depth`.
+    pub fn truncate(&mut self, depth: usize) {
+        self.frames.truncate(depth);
+    }
+
+    /// Track a jump at `pc` to `target`, linking `rd` and jumping through `rs1` (JALR only).
+    #[inline]
+    pub fn on_jump(
+        &mut self,
+        pc: u32,
+        target: u32,
+        rd: Reg,
+        rs1: Option<Reg>,
+        ret: u32,
+    ) -> Result<(), ShadowViolation> {
+        let (links, returns) = classify_jump(rd, rs1);
+        if returns {
+            self.pop(pc, target)?;
+        }
+        if links {
+            self.frames.push(ShadowFrame {
+                call_site: pc,
+                callee: target,
+                ret,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn pop(&mut self, pc: u32, target: u32) -> Result<(), ShadowViolation> {
+        // Returning from a frame entered before tracking started
+        let Some(top) = self.frames.last() else {
+            return Ok(());
+        };
+        match self.frames.iter().rposition(|f| f.ret == target) {
+            Some(depth) => {
+                self.frames.truncate(depth);
+                Ok(())
+            }
+            None => Err(ShadowViolation {
+                pc,
+                target,
+                expected: top.ret,
+            }),
+        }
+    }
+
+    /// Describe `violation` along with the live call stack, symbolized with `symbols`.
+    pub fn report(&self, violation: &ShadowViolation, symbols: &SymbolTable) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "Return address corrupted at {}:",
+            symbols.describe(violation.pc)
+        )
+        .unwrap();
+        writeln!(out, "  returning to {}", symbols.describe(violation.target)).unwrap();
+        writeln!(
+            out,
+            "  expected     {}",
+            symbols.describe(violation.expected)
+        )
+        .unwrap();
+        writeln!(out, "Call stack (innermost first):").unwrap();
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            writeln!(
+                out,
+                "  #{i} {} called from {}",
+                symbols.describe(frame.callee),
+                symbols.describe(frame.call_site)
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
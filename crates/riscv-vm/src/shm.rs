@@ -0,0 +1,151 @@
+use std::{
+    ffi::CString,
+    fs::File,
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::fs::FileExt,
+    },
+    sync::Arc,
+};
+
+/// A shared memory object backed by a host `memfd`.
+///
+/// Every guest mapping of the object maps the same host pages, so writes are
+/// visible to all of them, and to the embedder through [`SharedMemory::map`],
+/// without copying. Since the pages are `MAP_SHARED` on the host, they also stay
+/// shared with a forked host process, unlike the rest of guest memory.
+///
+/// Clones refer to the same object.
+#[derive(Debug, Clone)]
+pub struct SharedMemory {
+    file: Arc<File>,
+    name: Arc<str>,
+}
+
+impl SharedMemory {
+    /// Create an empty object. `name` is informational only.
+    pub fn new(name: &str) -> io::Result<Self> {
+        let c_name = CString::new(name.replace('\0', "")).unwrap();
+        let fd = unsafe { libc::memfd_create(c_name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            file: Arc::new(unsafe { File::from_raw_fd(fd) }),
+            name: name.into(),
+        })
+    }
+
+    /// Create an object of `len` zeroed bytes.
+    pub fn with_len(name: &str, len: u64) -> io::Result<Self> {
+        let shm = Self::new(name)?;
+        shm.set_len(len)?;
+        Ok(shm)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Grow or shrink the object, zero-filling new bytes.
+    pub fn set_len(&self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.file.read_at(buf, offset)
+    }
+
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.file.write_at(buf, offset)
+    }
+
+    /// Whether `self` and `other` are the same object.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.file, &other.file)
+    }
+
+    /// Map the whole object into the host's address space.
+    ///
+    /// The mapping doesn't follow later [`SharedMemory::set_len`] calls.
+    pub fn map(&self) -> io::Result<SharedMapping> {
+        let len = self.len()? as usize;
+        if len == 0 {
+            return Ok(SharedMapping {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+        let ptr = self.mmap(std::ptr::null_mut(), 0, len, 0)?;
+
+        Ok(SharedMapping { ptr, len })
+    }
+
+    /// `mmap` `len` bytes of the object at `offset` to `addr`, with extra `flags`.
+    pub(crate) fn mmap(
+        &self,
+        addr: *mut u8,
+        offset: u64,
+        len: usize,
+        flags: i32,
+    ) -> io::Result<*mut u8> {
+        let ptr = unsafe {
+            libc::mmap(
+                addr as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | flags,
+                self.file.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(ptr as *mut u8)
+    }
+}
+
+/// A host view of a [`SharedMemory`] object.
+#[derive(Debug)]
+pub struct SharedMapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl SharedMapping {
+    pub fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        if self.len == 0 {
+            return &mut [];
+        }
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for SharedMapping {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
@@ -0,0 +1,627 @@
+//! IEEE 754 binary32 and binary64 arithmetic in software, for the F and D
+//! extensions.
+//!
+//! Host floats can't give RISC-V's results: Rust's `f32` and `f64` only round
+//! to nearest, don't report exception flags, and leave NaN payloads to the
+//! host FPU. So values here are bit patterns, every operation works on exact
+//! integer significands and rounds once, in [`Env::round`], and any NaN
+//! produced is the canonical one. Tininess is detected after rounding, as
+//! RISC-V requires.
+
+/// Exception flags, as laid out in `fflags`.
+pub(crate) const NX: u8 = 1 << 0;
+pub(crate) const UF: u8 = 1 << 1;
+pub(crate) const OF: u8 = 1 << 2;
+pub(crate) const DZ: u8 = 1 << 3;
+pub(crate) const NV: u8 = 1 << 4;
+
+/// A rounding mode, as encoded in an instruction's `rm` field and in `frm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rounding {
+    /// To nearest, ties to even
+    Rne = 0,
+    /// Towards zero
+    Rtz = 1,
+    /// Down, towards negative infinity
+    Rdn = 2,
+    /// Up, towards positive infinity
+    Rup = 3,
+    /// To nearest, ties away from zero
+    Rmm = 4,
+}
+
+impl Rounding {
+    /// The mode for an instruction's `rm` field, where 7 defers to `frm`.
+    /// Reserved values of either are `None`, making the instruction illegal.
+    pub(crate) fn from_rm(rm: u32, frm: u32) -> Option<Self> {
+        match if rm == 7 { frm } else { rm } {
+            0 => Some(Self::Rne),
+            1 => Some(Self::Rtz),
+            2 => Some(Self::Rdn),
+            3 => Some(Self::Rup),
+            4 => Some(Self::Rmm),
+            _ => None,
+        }
+    }
+}
+
+/// An interchange format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Format {
+    exp_bits: u32,
+    frac_bits: u32,
+}
+
+pub(crate) const F32: Format = Format {
+    exp_bits: 8,
+    frac_bits: 23,
+};
+
+pub(crate) const F64: Format = Format {
+    exp_bits: 11,
+    frac_bits: 52,
+};
+
+impl Format {
+    const fn bias(self) -> i32 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+
+    const fn max_exp(self) -> u64 {
+        (1 << self.exp_bits) - 1
+    }
+
+    const fn frac_mask(self) -> u64 {
+        (1 << self.frac_bits) - 1
+    }
+
+    const fn sign(self, negative: bool) -> u64 {
+        (negative as u64) << (self.exp_bits + self.frac_bits)
+    }
+
+    /// The quiet NaN every operation returns instead of propagating its
+    /// operands' NaNs.
+    pub(crate) const fn canonical_nan(self) -> u64 {
+        self.max_exp() << self.frac_bits | 1 << (self.frac_bits - 1)
+    }
+
+    const fn zero(self, negative: bool) -> u64 {
+        self.sign(negative)
+    }
+
+    const fn inf(self, negative: bool) -> u64 {
+        self.sign(negative) | self.max_exp() << self.frac_bits
+    }
+
+    const fn max_finite(self, negative: bool) -> u64 {
+        self.inf(negative) - 1
+    }
+
+    fn unpack(self, bits: u64) -> Value {
+        let negative = bits >> (self.exp_bits + self.frac_bits) & 1 != 0;
+        let exp = (bits >> self.frac_bits) & self.max_exp();
+        let frac = bits & self.frac_mask();
+        let kind = match exp {
+            0 if frac == 0 => Kind::Zero,
+            // Subnormal, with the same scale as the smallest normal exponent
+            0 => Kind::Finite {
+                exp: 1 - self.bias() - self.frac_bits as i32,
+                sig: frac,
+            },
+            _ if exp == self.max_exp() && frac == 0 => Kind::Inf,
+            _ if exp == self.max_exp() => Kind::Nan {
+                signaling: frac >> (self.frac_bits - 1) == 0,
+            },
+            _ => Kind::Finite {
+                exp: exp as i32 - self.bias() - self.frac_bits as i32,
+                sig: frac | 1 << self.frac_bits,
+            },
+        };
+        Value { negative, kind }
+    }
+
+    fn is_nan(self, bits: u64) -> bool {
+        matches!(self.unpack(bits).kind, Kind::Nan { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    negative: bool,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Zero,
+    /// `sig * 2^exp`, with `sig` non-zero
+    Finite {
+        exp: i32,
+        sig: u64,
+    },
+    Inf,
+    Nan {
+        signaling: bool,
+    },
+}
+
+/// Shift `sig` right by `shift` bits, rounding the integer left as `mode`
+/// says for a value with sign `negative`, returning it and whether it's
+/// inexact. A negative `shift` shifts left, exactly.
+fn shift_round(sig: u128, shift: i32, negative: bool, mode: Rounding) -> (u128, bool) {
+    if shift <= 0 {
+        return (sig << -shift, false);
+    }
+    let shift = shift as u32;
+    let kept = sig.checked_shr(shift).unwrap_or(0);
+    // The first bit shifted out, and whether any after it are set
+    let half = shift <= 128 && (sig >> (shift - 1)) & 1 != 0;
+    let sticky = match shift {
+        1 => false,
+        2..=128 => sig & ((1 << (shift - 1)) - 1) != 0,
+        _ => sig != 0,
+    };
+    let up = match mode {
+        Rounding::Rne => half && (sticky || kept & 1 != 0),
+        Rounding::Rtz => false,
+        Rounding::Rdn => (half || sticky) && negative,
+        Rounding::Rup => (half || sticky) && !negative,
+        Rounding::Rmm => half,
+    };
+    (kept + up as u128, half || sticky)
+}
+
+/// Normalize `sig * 2^exp`, which has at most a product's 106 bits, so
+/// `sig`'s top bit is bit 124, leaving room for a carry out of an addition.
+fn normalize(exp: i32, sig: u128) -> (i32, u128) {
+    let shift = sig.leading_zeros() as i32 - 3;
+    (exp - shift, sig << shift)
+}
+
+/// Integer square root, and whether it's inexact.
+fn isqrt(n: u128) -> (u128, bool) {
+    let mut root = 0u128;
+    let mut rem = n;
+    let mut bit = 1u128 << 126;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    (root, rem != 0)
+}
+
+/// The rounding mode for one instruction, and the exception flags it raises.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Env {
+    pub(crate) mode: Rounding,
+    pub(crate) flags: u8,
+}
+
+impl Env {
+    pub(crate) fn new(mode: Rounding) -> Self {
+        Env { mode, flags: 0 }
+    }
+
+    /// The canonical NaN, raising invalid if any operand was signaling.
+    fn nan(&mut self, fmt: Format, operands: &[Value]) -> u64 {
+        if operands
+            .iter()
+            .any(|v| matches!(v.kind, Kind::Nan { signaling: true }))
+        {
+            self.flags |= NV;
+        }
+        fmt.canonical_nan()
+    }
+
+    fn invalid(&mut self, fmt: Format) -> u64 {
+        self.flags |= NV;
+        fmt.canonical_nan()
+    }
+
+    /// Round `sig * 2^exp` to `fmt`. If `sig`'s lowest bit stands in for
+    /// lost non-zero bits below it, there must be at least two bits below the
+    /// result's precision.
+    fn round(&mut self, fmt: Format, negative: bool, exp: i32, sig: u128) -> u64 {
+        if sig == 0 {
+            return fmt.zero(negative);
+        }
+        let frac_bits = fmt.frac_bits as i32;
+        let min_exp = 1 - fmt.bias();
+        // The exponent of the value's leading bit, and of the result's last
+        // bit, which is fixed for subnormals
+        let top = exp + 127 - sig.leading_zeros() as i32;
+        let mut last = (top - frac_bits).max(min_exp - frac_bits);
+        let (mut rounded, inexact) = shift_round(sig, last - exp, negative, self.mode);
+        if rounded >> (frac_bits + 1) != 0 {
+            // Rounded up to the next power of two
+            rounded >>= 1;
+            last += 1;
+        }
+
+        if inexact {
+            self.flags |= NX;
+            // Tiny if, rounded with an unbounded exponent, it's still below
+            // the smallest normal
+            if top < min_exp {
+                let (unbounded, _) = shift_round(sig, top - frac_bits - exp, negative, self.mode);
+                let carried = unbounded >> (frac_bits + 1) != 0;
+                if top + (carried as i32) < min_exp {
+                    self.flags |= UF;
+                }
+            }
+        }
+
+        let biased = if rounded >> frac_bits != 0 {
+            (last + frac_bits + fmt.bias()) as u64
+        } else {
+            0
+        };
+        if biased >= fmt.max_exp() {
+            self.flags |= OF | NX;
+            let to_inf = match self.mode {
+                Rounding::Rne | Rounding::Rmm => true,
+                Rounding::Rtz => false,
+                Rounding::Rdn => negative,
+                Rounding::Rup => !negative,
+            };
+            return if to_inf {
+                fmt.inf(negative)
+            } else {
+                fmt.max_finite(negative)
+            };
+        }
+        fmt.sign(negative) | biased << fmt.frac_bits | (rounded as u64 & fmt.frac_mask())
+    }
+
+    /// Round the sum of two finite values, either of which may be zero.
+    fn round_sum(
+        &mut self,
+        fmt: Format,
+        (x_neg, x_exp, x_sig): (bool, i32, u128),
+        (y_neg, y_exp, y_sig): (bool, i32, u128),
+    ) -> u64 {
+        if x_sig == 0 && y_sig == 0 {
+            return fmt.zero(if x_neg == y_neg {
+                x_neg
+            } else {
+                self.mode == Rounding::Rdn
+            });
+        }
+        if y_sig == 0 {
+            return self.round(fmt, x_neg, x_exp, x_sig);
+        }
+        if x_sig == 0 {
+            return self.round(fmt, y_neg, y_exp, y_sig);
+        }
+
+        let (x_exp, x_sig) = normalize(x_exp, x_sig);
+        let (y_exp, y_sig) = normalize(y_exp, y_sig);
+        // Align the smaller to the larger. Bits only fall off the smaller
+        // when it's far enough below that the guard bits keep rounding exact.
+        let ((big_neg, exp, big), (small_neg, small)) = if x_exp >= y_exp {
+            let small = sticky_shr(y_sig, (x_exp - y_exp) as u32);
+            ((x_neg, x_exp, x_sig), (y_neg, small))
+        } else {
+            let small = sticky_shr(x_sig, (y_exp - x_exp) as u32);
+            ((y_neg, y_exp, y_sig), (x_neg, small))
+        };
+
+        if big_neg == small_neg {
+            return self.round(fmt, big_neg, exp, big + small);
+        }
+        match big.cmp(&small) {
+            // Exact cancellation is +0, but for rounding down
+            std::cmp::Ordering::Equal => fmt.zero(self.mode == Rounding::Rdn),
+            std::cmp::Ordering::Greater => self.round(fmt, big_neg, exp, big - small),
+            std::cmp::Ordering::Less => self.round(fmt, small_neg, exp, small - big),
+        }
+    }
+
+    pub(crate) fn add(&mut self, fmt: Format, a: u64, b: u64) -> u64 {
+        let (x, y) = (fmt.unpack(a), fmt.unpack(b));
+        match (x.kind, y.kind) {
+            (Kind::Nan { .. }, _) | (_, Kind::Nan { .. }) => self.nan(fmt, &[x, y]),
+            (Kind::Inf, Kind::Inf) if x.negative != y.negative => self.invalid(fmt),
+            (Kind::Inf, _) => fmt.inf(x.negative),
+            (_, Kind::Inf) => fmt.inf(y.negative),
+            _ => self.round_sum(fmt, finite(x), finite(y)),
+        }
+    }
+
+    pub(crate) fn sub(&mut self, fmt: Format, a: u64, b: u64) -> u64 {
+        self.add(fmt, a, b ^ fmt.sign(true))
+    }
+
+    pub(crate) fn mul(&mut self, fmt: Format, a: u64, b: u64) -> u64 {
+        let (x, y) = (fmt.unpack(a), fmt.unpack(b));
+        let negative = x.negative != y.negative;
+        match (x.kind, y.kind) {
+            (Kind::Nan { .. }, _) | (_, Kind::Nan { .. }) => self.nan(fmt, &[x, y]),
+            (Kind::Inf, Kind::Zero) | (Kind::Zero, Kind::Inf) => self.invalid(fmt),
+            (Kind::Inf, _) | (_, Kind::Inf) => fmt.inf(negative),
+            (Kind::Zero, _) | (_, Kind::Zero) => fmt.zero(negative),
+            (Kind::Finite { exp: xe, sig: xs }, Kind::Finite { exp: ye, sig: ys }) => {
+                self.round(fmt, negative, xe + ye, xs as u128 * ys as u128)
+            }
+        }
+    }
+
+    /// `(a * b) + c`, with the product and addend negated as asked, rounded
+    /// once.
+    pub(crate) fn fma(
+        &mut self,
+        fmt: Format,
+        (a, b, c): (u64, u64, u64),
+        negate_product: bool,
+        negate_addend: bool,
+    ) -> u64 {
+        let (x, y, z) = (fmt.unpack(a), fmt.unpack(b), fmt.unpack(c));
+        let product_neg = (x.negative != y.negative) != negate_product;
+        let addend_neg = z.negative != negate_addend;
+        match (x.kind, y.kind, z.kind) {
+            // Invalid even when the addend is a quiet NaN
+            (Kind::Inf, Kind::Zero, _) | (Kind::Zero, Kind::Inf, _) => self.invalid(fmt),
+            (Kind::Nan { .. }, ..) | (_, Kind::Nan { .. }, _) | (.., Kind::Nan { .. }) => {
+                self.nan(fmt, &[x, y, z])
+            }
+            (Kind::Inf, ..) | (_, Kind::Inf, _) => match z.kind {
+                Kind::Inf if addend_neg != product_neg => self.invalid(fmt),
+                _ => fmt.inf(product_neg),
+            },
+            (.., Kind::Inf) => fmt.inf(addend_neg),
+            _ => {
+                let product = match (x.kind, y.kind) {
+                    (Kind::Finite { exp: xe, sig: xs }, Kind::Finite { exp: ye, sig: ys }) => {
+                        (product_neg, xe + ye, xs as u128 * ys as u128)
+                    }
+                    _ => (product_neg, 0, 0),
+                };
+                let (_, exp, sig) = finite(z);
+                self.round_sum(fmt, product, (addend_neg, exp, sig))
+            }
+        }
+    }
+
+    pub(crate) fn div(&mut self, fmt: Format, a: u64, b: u64) -> u64 {
+        let (x, y) = (fmt.unpack(a), fmt.unpack(b));
+        let negative = x.negative != y.negative;
+        match (x.kind, y.kind) {
+            (Kind::Nan { .. }, _) | (_, Kind::Nan { .. }) => self.nan(fmt, &[x, y]),
+            (Kind::Inf, Kind::Inf) | (Kind::Zero, Kind::Zero) => self.invalid(fmt),
+            (Kind::Inf, _) | (_, Kind::Zero) => {
+                if matches!(x.kind, Kind::Finite { .. }) {
+                    self.flags |= DZ;
+                }
+                fmt.inf(negative)
+            }
+            (_, Kind::Inf) | (Kind::Zero, _) => fmt.zero(negative),
+            (Kind::Finite { exp: xe, sig: xs }, Kind::Finite { exp: ye, sig: ys }) => {
+                // Both significands' top bits at bit 63, for a quotient of 64
+                // or 65 bits with the remainder as a sticky bit
+                let (xz, yz) = (xs.leading_zeros() as i32, ys.leading_zeros() as i32);
+                let (xs, ys) = ((xs << xz) as u128, (ys << yz) as u128);
+                let quotient = (xs << 64) / ys;
+                let sticky = (xs << 64) % ys != 0;
+                let exp = (xe - xz) - (ye - yz) - 64;
+                self.round(fmt, negative, exp, quotient | sticky as u128)
+            }
+        }
+    }
+
+    pub(crate) fn sqrt(&mut self, fmt: Format, a: u64) -> u64 {
+        let x = fmt.unpack(a);
+        match x.kind {
+            Kind::Nan { .. } => self.nan(fmt, &[x]),
+            Kind::Zero => a,
+            _ if x.negative => self.invalid(fmt),
+            Kind::Inf => a,
+            Kind::Finite { exp, sig } => {
+                // An even exponent, and as many significand bits as fit, for
+                // a root of over 60 bits
+                let shift = (sig.leading_zeros() as i32 + 64 - 2) & !1;
+                let (shift, exp) = if (exp - shift) % 2 != 0 {
+                    (shift + 1, exp - shift - 1)
+                } else {
+                    (shift, exp - shift)
+                };
+                let (root, inexact) = isqrt((sig as u128) << shift);
+                self.round(fmt, false, exp / 2, root | inexact as u128)
+            }
+        }
+    }
+
+    /// The smaller operand, or for `max` the larger, with -0 below +0. A NaN
+    /// operand is ignored unless both are NaNs.
+    pub(crate) fn min_max(&mut self, fmt: Format, a: u64, b: u64, max: bool) -> u64 {
+        let (x, y) = (fmt.unpack(a), fmt.unpack(b));
+        let (a_nan, b_nan) = (fmt.is_nan(a), fmt.is_nan(b));
+        if a_nan || b_nan {
+            let nan = self.nan(fmt, &[x, y]);
+            return match (a_nan, b_nan) {
+                (true, true) => nan,
+                (true, false) => b,
+                _ => a,
+            };
+        }
+        let a_less = total_key(fmt, a) < total_key(fmt, b);
+        if a_less != max {
+            a
+        } else {
+            b
+        }
+    }
+
+    /// `a == b`, raising invalid only for signaling NaNs.
+    pub(crate) fn eq(&mut self, fmt: Format, a: u64, b: u64) -> bool {
+        if fmt.is_nan(a) || fmt.is_nan(b) {
+            self.nan(fmt, &[fmt.unpack(a), fmt.unpack(b)]);
+            return false;
+        }
+        order_key(fmt, a) == order_key(fmt, b)
+    }
+
+    /// `a < b`, or `a <= b` if `or_equal`, raising invalid for any NaN.
+    pub(crate) fn lt(&mut self, fmt: Format, a: u64, b: u64, or_equal: bool) -> bool {
+        if fmt.is_nan(a) || fmt.is_nan(b) {
+            self.flags |= NV;
+            return false;
+        }
+        let (a, b) = (order_key(fmt, a), order_key(fmt, b));
+        a < b || (or_equal && a == b)
+    }
+
+    /// Convert between formats.
+    pub(crate) fn convert(&mut self, from: Format, to: Format, a: u64) -> u64 {
+        let x = from.unpack(a);
+        match x.kind {
+            Kind::Nan { .. } => self.nan(to, &[x]),
+            Kind::Inf => to.inf(x.negative),
+            Kind::Zero => to.zero(x.negative),
+            Kind::Finite { exp, sig } => self.round(to, x.negative, exp, sig as u128),
+        }
+    }
+
+    /// Convert to a 32-bit integer, signed or not, saturating and raising
+    /// invalid when out of range.
+    pub(crate) fn float_to_int(&mut self, fmt: Format, a: u64, signed: bool) -> u32 {
+        let x = fmt.unpack(a);
+        let (min, max) = if signed {
+            (i32::MIN as u32, i32::MAX as u32)
+        } else {
+            (0, u32::MAX)
+        };
+        match x.kind {
+            Kind::Nan { .. } => {
+                self.flags |= NV;
+                return max;
+            }
+            Kind::Inf => {}
+            Kind::Zero => return 0,
+            Kind::Finite { exp, sig } => {
+                let (magnitude, inexact) = if exp > 64 {
+                    (u128::MAX, false)
+                } else {
+                    shift_round(sig as u128, -exp, x.negative, self.mode)
+                };
+                let limit = match (signed, x.negative) {
+                    (false, false) => u32::MAX as u128,
+                    (false, true) => 0,
+                    (true, false) => i32::MAX as u128,
+                    (true, true) => 1 << 31,
+                };
+                if magnitude <= limit {
+                    if inexact {
+                        self.flags |= NX;
+                    }
+                    let magnitude = magnitude as u32;
+                    return if x.negative {
+                        magnitude.wrapping_neg()
+                    } else {
+                        magnitude
+                    };
+                }
+            }
+        }
+        self.flags |= NV;
+        if x.negative {
+            min
+        } else {
+            max
+        }
+    }
+
+    /// Convert from a 32-bit integer, signed or not.
+    pub(crate) fn int_to_float(&mut self, fmt: Format, value: u32, signed: bool) -> u64 {
+        let negative = signed && (value as i32) < 0;
+        let magnitude = if negative {
+            (value as i32).unsigned_abs()
+        } else {
+            value
+        };
+        self.round(fmt, negative, 0, magnitude as u128)
+    }
+}
+
+/// The sign, exponent and significand of a zero or finite value.
+fn finite(v: Value) -> (bool, i32, u128) {
+    match v.kind {
+        Kind::Finite { exp, sig } => (v.negative, exp, sig as u128),
+        _ => (v.negative, 0, 0),
+    }
+}
+
+/// Shift right, folding the bits shifted out into the lowest bit.
+fn sticky_shr(sig: u128, shift: u32) -> u128 {
+    match shift {
+        0 => sig,
+        1..=127 => sig >> shift | (sig & ((1 << shift) - 1) != 0) as u128,
+        _ => (sig != 0) as u128,
+    }
+}
+
+/// A key ordering non-NaN values numerically, with both zeros equal.
+fn order_key(fmt: Format, bits: u64) -> i128 {
+    let magnitude = (bits & !fmt.sign(true)) as i128;
+    if bits & fmt.sign(true) != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Like [`order_key`], but with -0 below +0.
+fn total_key(fmt: Format, bits: u64) -> i128 {
+    let key = order_key(fmt, bits) * 2;
+    if bits & fmt.sign(true) != 0 {
+        key - 1
+    } else {
+        key
+    }
+}
+
+/// `fclass`: a one-hot mask of the value's class.
+pub(crate) fn classify(fmt: Format, bits: u64) -> u32 {
+    let x = fmt.unpack(bits);
+    let subnormal = bits >> fmt.frac_bits & fmt.max_exp() == 0;
+    let bit = match (x.kind, x.negative) {
+        (Kind::Inf, true) => 0,
+        (Kind::Finite { .. }, true) if !subnormal => 1,
+        (Kind::Finite { .. }, true) => 2,
+        (Kind::Zero, true) => 3,
+        (Kind::Zero, false) => 4,
+        (Kind::Finite { .. }, false) if subnormal => 5,
+        (Kind::Finite { .. }, false) => 6,
+        (Kind::Inf, false) => 7,
+        (Kind::Nan { signaling: true }, _) => 8,
+        (Kind::Nan { signaling: false }, _) => 9,
+    };
+    1 << bit
+}
+
+/// `fsgnj`, `fsgnjn` and `fsgnjx`: `a` with its sign from `b`'s, `b`'s
+/// negated, or `b`'s xored with its own.
+pub(crate) fn sign_inject(fmt: Format, a: u64, b: u64, op: SignOp) -> u64 {
+    let sign = fmt.sign(true);
+    let b_sign = b & sign;
+    let new_sign = match op {
+        SignOp::Copy => b_sign,
+        SignOp::Negate => b_sign ^ sign,
+        SignOp::Xor => b_sign ^ (a & sign),
+    };
+    (a & !sign) | new_sign
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignOp {
+    Copy,
+    Negate,
+    Xor,
+}
@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// A guest function symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+    pub size: u32,
+}
+
+/// Guest symbols, indexed both by name and by address.
+///
+/// Populated by the loader, and used to find guest functions by name
+/// or to symbolize addresses.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    by_addr: BTreeMap<u32, Symbol>,
+    by_name: HashMap<String, u32>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, addr: u32, size: u32) {
+        let name = name.into();
+        self.by_name.insert(name.clone(), addr);
+        self.by_addr.insert(addr, Symbol { name, addr, size });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+
+    /// Address of the symbol named `name`.
+    pub fn lookup(&self, name: &str) -> Option<u32> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The symbol containing `addr`, if any.
+    ///
+    /// Symbols with an unknown (zero) size are assumed to extend up to the next symbol.
+    pub fn containing(&self, addr: u32) -> Option<&Symbol> {
+        let (_, sym) = self.by_addr.range(..=addr).next_back()?;
+        if sym.size == 0 || addr - sym.addr < sym.size {
+            Some(sym)
+        } else {
+            None
+        }
+    }
+
+    /// Symbolize `addr` as `(name, offset)`.
+    pub fn symbolize(&self, addr: u32) -> Option<(&str, u32)> {
+        self.containing(addr)
+            .map(|sym| (sym.name.as_str(), addr - sym.addr))
+    }
+
+    /// Format `addr` as `name+0xoffset`, falling back to the raw address.
+    pub fn describe(&self, addr: u32) -> String {
+        match self.symbolize(addr) {
+            Some((name, 0)) => format!("{name} ({addr:#010x})"),
+            Some((name, off)) => format!("{name}+{off:#x} ({addr:#010x})"),
+            None => format!("{addr:#010x}"),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.by_addr.values()
+    }
+}
@@ -0,0 +1,519 @@
+//! Compressed instruction traces, streamed to files as the hart runs.
+//!
+//! A trace is one or more files: `PATH`, then `PATH.1`, `PATH.2` and so on
+//! once [`TraceConfig::max_file_bytes`] is reached. Each file is
+//! [`TRACE_MAGIC`] followed by chunks of up to
+//! [`TraceConfig::chunk_records`] records, and ends in an index of its
+//! chunks. Everything is little-endian.
+//!
+//! A chunk is a header, then its records as one zstd frame:
+//!
+//! | Field            | Size |
+//! |------------------|------|
+//! | compressed bytes | 4    |
+//! | first record     | 8    |
+//! | records          | 4    |
+//! | lowest pc        | 4    |
+//! | highest pc       | 4    |
+//!
+//! The index repeats each chunk's header after the file offset of its
+//! frame (8 bytes), followed by the number of chunks (4 bytes) and
+//! [`INDEX_MAGIC`]. Files cut short, say by a crash, have no index;
+//! [`TraceReader`] rebuilds it from the chunk headers.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use riscv_inst::{FReg, Reg};
+
+/// Start of every trace file.
+pub const TRACE_MAGIC: &[u8; 8] = b"DRTRACE2";
+/// End of a trace file's index.
+pub const INDEX_MAGIC: &[u8; 8] = b"DRTRIDX1";
+
+const RECORD_BYTES: usize = 27;
+const CHUNK_HEADER_BYTES: usize = 24;
+const INDEX_ENTRY_BYTES: usize = 8 + CHUNK_HEADER_BYTES;
+
+/// One retired instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u32,
+    /// Encoding, with compressed instructions zero-extended
+    pub inst: u32,
+    /// The register the instruction wrote and its new value, including
+    /// `a0` after a syscall. Writes to `zero` aren't recorded.
+    pub rd: Option<(Reg, u32)>,
+    /// The F or D register the instruction wrote and its new bits,
+    /// NaN-boxed for single precision
+    pub frd: Option<(FReg, u64)>,
+    /// Address and size of the memory the instruction stored to, including
+    /// through `sc.w` and AMOs, but not through MMIO devices or the kernel
+    pub store: Option<(u32, u8)>,
+}
+
+impl TraceRecord {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (rd, value) = self.rd.map_or((0, 0), |(reg, value)| (reg as u8, value));
+        let (addr, len) = self.store.unwrap_or((0, 0));
+        // The top bit marks that an F register was written, since f0 can be
+        let (frd, bits) = self
+            .frd
+            .map_or((0, 0), |(reg, bits)| (0x80 | reg as u8, bits));
+        out.extend(self.pc.to_le_bytes());
+        out.extend(self.inst.to_le_bytes());
+        out.push(rd);
+        out.extend(value.to_le_bytes());
+        out.extend(addr.to_le_bytes());
+        out.push(len);
+        out.push(frd);
+        out.extend(bits.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let rd = Reg::checked_from(bytes[8]).filter(|&reg| reg != Reg::Zero);
+        let len = bytes[17];
+        let frd = FReg::checked_from(bytes[18] & 0x1f).filter(|_| bytes[18] & 0x80 != 0);
+        let bits = u64::from_le_bytes(bytes[19..27].try_into().unwrap());
+        Self {
+            pc: word(0),
+            inst: word(4),
+            rd: rd.map(|reg| (reg, word(9))),
+            frd: frd.map(|reg| (reg, bits)),
+            store: (len != 0).then(|| (word(13), len)),
+        }
+    }
+}
+
+/// Where a chunk of records is, from a trace file's index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceChunk {
+    /// Trace file the chunk is in, 0 for `PATH` and `n` for `PATH.n`
+    pub file: u32,
+    /// Offset of the compressed records in the file
+    pub offset: u64,
+    pub compressed_bytes: u32,
+    /// Number of the chunk's first record in the whole trace
+    pub first: u64,
+    pub records: u32,
+    /// Lowest and highest pc in the chunk, to skip chunks that can't
+    /// contain an address
+    pub min_pc: u32,
+    pub max_pc: u32,
+}
+
+impl TraceChunk {
+    fn encode_header(&self, out: &mut Vec<u8>) {
+        out.extend(self.compressed_bytes.to_le_bytes());
+        out.extend(self.first.to_le_bytes());
+        out.extend(self.records.to_le_bytes());
+        out.extend(self.min_pc.to_le_bytes());
+        out.extend(self.max_pc.to_le_bytes());
+    }
+
+    fn decode_header(file: u32, offset: u64, bytes: &[u8]) -> Self {
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        Self {
+            file,
+            offset,
+            compressed_bytes: word(0),
+            first: u64::from_le_bytes(bytes[4..12].try_into().unwrap()),
+            records: word(12),
+            min_pc: word(16),
+            max_pc: word(20),
+        }
+    }
+
+    /// Whether the chunk may have executed `pc`.
+    pub fn may_contain(&self, pc: u32) -> bool {
+        (self.min_pc..=self.max_pc).contains(&pc)
+    }
+}
+
+/// How a [`TraceWriter`] lays out its files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceConfig {
+    /// Records per compressed chunk, the unit a reader decompresses
+    pub chunk_records: u32,
+    /// Start a new file once one is this big, if set
+    pub max_file_bytes: Option<u64>,
+    /// zstd compression level
+    pub level: i32,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            chunk_records: 1 << 16,
+            max_file_bytes: None,
+            level: 3,
+        }
+    }
+}
+
+/// The `n`th file of the trace at `path`.
+pub fn trace_file_path(path: &Path, n: u32) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+/// Streams every instruction the hart retires to compressed trace files,
+/// keeping at most one chunk in memory. Set it as
+/// [`Hart32::inst_trace`](crate::hart::Hart32::inst_trace).
+///
+/// Write errors stop the trace, and are returned by
+/// [`TraceWriter::finish`]; the guest keeps running.
+pub struct TraceWriter {
+    path: PathBuf,
+    config: TraceConfig,
+    file: BufWriter<File>,
+    file_number: u32,
+    /// Bytes written to the current file
+    file_bytes: u64,
+    /// Chunks in the current file
+    index: Vec<TraceChunk>,
+    /// Encoded records of the chunk being filled
+    chunk: Vec<u8>,
+    chunk_records: u32,
+    min_pc: u32,
+    max_pc: u32,
+    /// Records written before the current chunk
+    written: u64,
+    /// What the current instruction did so far
+    pending: TraceRecord,
+    error: Option<std::io::Error>,
+    finished: bool,
+}
+
+impl TraceWriter {
+    /// Create the trace at `path`, removing files an earlier trace there
+    /// rotated to, so a reader doesn't mistake them for this one's.
+    pub fn create(path: impl AsRef<Path>, config: TraceConfig) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = Self::open_file(&path, 0)?;
+        for n in 1.. {
+            match std::fs::remove_file(trace_file_path(&path, n)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Self {
+            path,
+            config: TraceConfig {
+                chunk_records: config.chunk_records.max(1),
+                ..config
+            },
+            file,
+            file_number: 0,
+            file_bytes: TRACE_MAGIC.len() as u64,
+            index: vec![],
+            chunk: vec![],
+            chunk_records: 0,
+            min_pc: u32::MAX,
+            max_pc: 0,
+            written: 0,
+            pending: TraceRecord::default(),
+            error: None,
+            finished: false,
+        })
+    }
+
+    fn open_file(path: &Path, n: u32) -> std::io::Result<BufWriter<File>> {
+        let mut file = BufWriter::new(File::create(trace_file_path(path, n))?);
+        file.write_all(TRACE_MAGIC)?;
+        Ok(file)
+    }
+
+    /// Records retired so far.
+    pub fn records(&self) -> u64 {
+        self.written + self.chunk_records as u64
+    }
+
+    /// Note that the current instruction wrote `value` to `reg`.
+    #[inline]
+    pub(crate) fn record_reg(&mut self, reg: Reg, value: u32) {
+        if reg != Reg::Zero {
+            self.pending.rd = Some((reg, value));
+        }
+    }
+
+    /// Note that the current instruction wrote `bits` to `reg`.
+    #[inline]
+    pub(crate) fn record_freg(&mut self, reg: FReg, bits: u64) {
+        self.pending.frd = Some((reg, bits));
+    }
+
+    /// Note that the current instruction stored `len` bytes at `addr`.
+    #[inline]
+    pub(crate) fn record_store(&mut self, addr: u32, len: u8) {
+        self.pending.store = Some((addr, len));
+    }
+
+    /// Record the instruction `inst` at `pc`, which just retired.
+    pub(crate) fn retire(&mut self, pc: u32, inst: u32) {
+        // The fetch read past a compressed instruction
+        let inst = if inst & 0b11 == 0b11 {
+            inst
+        } else {
+            inst & 0xffff
+        };
+        let record = TraceRecord {
+            pc,
+            inst,
+            ..std::mem::take(&mut self.pending)
+        };
+        if self.error.is_some() || self.finished {
+            return;
+        }
+        record.encode(&mut self.chunk);
+        self.chunk_records += 1;
+        self.min_pc = self.min_pc.min(pc);
+        self.max_pc = self.max_pc.max(pc);
+        if self.chunk_records >= self.config.chunk_records {
+            if let Err(e) = self.flush_chunk() {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Forget what the current instruction did, when it didn't retire.
+    pub(crate) fn discard_pending(&mut self) {
+        self.pending = TraceRecord::default();
+    }
+
+    fn flush_chunk(&mut self) -> std::io::Result<()> {
+        if self.chunk_records == 0 {
+            return Ok(());
+        }
+        let compressed = zstd::bulk::compress(&self.chunk, self.config.level)?;
+        let chunk = TraceChunk {
+            file: self.file_number,
+            offset: self.file_bytes + CHUNK_HEADER_BYTES as u64,
+            compressed_bytes: compressed.len() as u32,
+            first: self.written,
+            records: self.chunk_records,
+            min_pc: self.min_pc,
+            max_pc: self.max_pc,
+        };
+        let mut header = Vec::with_capacity(CHUNK_HEADER_BYTES);
+        chunk.encode_header(&mut header);
+        self.file.write_all(&header)?;
+        self.file.write_all(&compressed)?;
+        self.file_bytes = chunk.offset + compressed.len() as u64;
+        self.index.push(chunk);
+
+        self.written += self.chunk_records as u64;
+        self.chunk.clear();
+        self.chunk_records = 0;
+        self.min_pc = u32::MAX;
+        self.max_pc = 0;
+
+        if self
+            .config
+            .max_file_bytes
+            .is_some_and(|max| self.file_bytes >= max)
+        {
+            self.write_index()?;
+            self.file_number += 1;
+            self.file = Self::open_file(&self.path, self.file_number)?;
+            self.file_bytes = TRACE_MAGIC.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    fn write_index(&mut self) -> std::io::Result<()> {
+        let mut index = Vec::with_capacity(self.index.len() * INDEX_ENTRY_BYTES + 12);
+        for chunk in self.index.drain(..) {
+            index.extend(chunk.offset.to_le_bytes());
+            chunk.encode_header(&mut index);
+        }
+        index.extend((index.len() as u32 / INDEX_ENTRY_BYTES as u32).to_le_bytes());
+        index.extend(INDEX_MAGIC);
+        self.file.write_all(&index)?;
+        self.file.flush()
+    }
+
+    /// Write the last chunk and the index, and flush, returning the first
+    /// write error, if any. Dropping the writer does this too, ignoring
+    /// errors.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if !self.finished && self.error.is_none() {
+            self.finished = true;
+            if let Err(e) = self.flush_chunk().and_then(|()| self.write_index()) {
+                self.error = Some(e);
+            }
+        }
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TraceWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl std::fmt::Debug for TraceWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraceWriter")
+            .field("path", &self.path)
+            .field("records", &self.records())
+            .finish_non_exhaustive()
+    }
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Reads traces written by a [`TraceWriter`] a chunk at a time.
+#[derive(Debug, Clone)]
+pub struct TraceReader {
+    path: PathBuf,
+    chunks: Vec<TraceChunk>,
+}
+
+impl TraceReader {
+    /// Open the trace at `path`, including the files it rotated to, and
+    /// load their indexes.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut chunks = vec![];
+        for n in 0.. {
+            let file_path = trace_file_path(&path, n);
+            if n > 0 && !file_path.exists() {
+                break;
+            }
+            chunks.extend(Self::read_index(&file_path, n)?);
+        }
+
+        Ok(Self { path, chunks })
+    }
+
+    fn read_index(path: &Path, n: u32) -> std::io::Result<Vec<TraceChunk>> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; TRACE_MAGIC.len()];
+        file.read_exact(&mut magic)?;
+        if &magic != TRACE_MAGIC {
+            return Err(invalid(format!("{} is not a trace", path.display())));
+        }
+
+        let len = file.seek(SeekFrom::End(0))?;
+        let mut footer = [0; 12];
+        if len >= (TRACE_MAGIC.len() + footer.len()) as u64 {
+            file.seek(SeekFrom::End(-(footer.len() as i64)))?;
+            file.read_exact(&mut footer)?;
+        }
+        if &footer[4..] == INDEX_MAGIC {
+            let count = u32::from_le_bytes(footer[..4].try_into().unwrap()) as usize;
+            let index_bytes = (count * INDEX_ENTRY_BYTES + footer.len()) as i64;
+            file.seek(SeekFrom::End(-index_bytes))?;
+            let mut index = vec![0; count * INDEX_ENTRY_BYTES];
+            file.read_exact(&mut index)?;
+            return Ok(index
+                .chunks_exact(INDEX_ENTRY_BYTES)
+                .map(|entry| {
+                    let offset = u64::from_le_bytes(entry[..8].try_into().unwrap());
+                    TraceChunk::decode_header(n, offset, &entry[8..])
+                })
+                .collect());
+        }
+
+        // No index, so walk the chunk headers, stopping at a cut-off chunk
+        let mut chunks = vec![];
+        let mut at = TRACE_MAGIC.len() as u64;
+        let mut header = [0; CHUNK_HEADER_BYTES];
+        loop {
+            file.seek(SeekFrom::Start(at))?;
+            if file.read_exact(&mut header).is_err() {
+                break;
+            }
+            let chunk = TraceChunk::decode_header(n, at + header.len() as u64, &header);
+            if chunk.offset + chunk.compressed_bytes as u64 > len {
+                break;
+            }
+            at = chunk.offset + chunk.compressed_bytes as u64;
+            chunks.push(chunk);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Every chunk, in order.
+    pub fn chunks(&self) -> &[TraceChunk] {
+        &self.chunks
+    }
+
+    /// Records in the trace.
+    pub fn len(&self) -> u64 {
+        self.chunks
+            .last()
+            .map_or(0, |chunk| chunk.first + chunk.records as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Decompress the records of `chunk`.
+    pub fn read_chunk(&self, chunk: &TraceChunk) -> std::io::Result<Vec<TraceRecord>> {
+        let mut file = File::open(trace_file_path(&self.path, chunk.file))?;
+        file.seek(SeekFrom::Start(chunk.offset))?;
+        let mut compressed = vec![0; chunk.compressed_bytes as usize];
+        file.read_exact(&mut compressed)?;
+        let bytes = zstd::bulk::decompress(&compressed, chunk.records as usize * RECORD_BYTES)?;
+        if bytes.len() != chunk.records as usize * RECORD_BYTES {
+            return Err(invalid(format!(
+                "chunk at record {} is {} bytes, expected {}",
+                chunk.first,
+                bytes.len(),
+                chunk.records as usize * RECORD_BYTES
+            )));
+        }
+
+        Ok(bytes
+            .chunks_exact(RECORD_BYTES)
+            .map(TraceRecord::decode)
+            .collect())
+    }
+
+    /// Every record from number `first` on, decompressing one chunk at a
+    /// time, with its number.
+    pub fn records_from(
+        &self,
+        first: u64,
+    ) -> impl Iterator<Item = std::io::Result<(u64, TraceRecord)>> + '_ {
+        let start = self
+            .chunks
+            .partition_point(|chunk| chunk.first + chunk.records as u64 <= first);
+        self.chunks[start..].iter().flat_map(move |chunk| {
+            let records = match self.read_chunk(chunk) {
+                Ok(records) => records,
+                Err(e) => return vec![Err(e)],
+            };
+            (chunk.first..)
+                .zip(records)
+                .filter(|&(n, _)| n >= first)
+                .map(Ok)
+                .collect()
+        })
+    }
+
+    /// Every record, with its number.
+    pub fn records(&self) -> impl Iterator<Item = std::io::Result<(u64, TraceRecord)>> + '_ {
+        self.records_from(0)
+    }
+}
@@ -0,0 +1,251 @@
+//! Queries over an instruction trace written by
+//! [`TraceWriter`](crate::trace::TraceWriter), streaming it a chunk at a
+//! time so they work on traces far bigger than memory.
+//!
+//! Calls and returns are recovered from consecutive records, without
+//! decoding instructions: an instruction that jumps and links `ra` or `t0`
+//! to the address after itself is a call, and a jump to the return address
+//! of a live call returns from it and every call above it.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Write,
+};
+
+use crate::{
+    riscv_inst::{codegen::rv32imasfdc::Rv32IMASFDC, Reg},
+    symbols::SymbolTable,
+    trace::{TraceReader, TraceRecord},
+};
+
+/// Executions of each pc, most executed first.
+pub fn hot_pcs(reader: &TraceReader) -> std::io::Result<Vec<(u32, u64)>> {
+    let mut counts = HashMap::<u32, u64>::new();
+    for record in reader.records() {
+        *counts.entry(record?.1.pc).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    Ok(counts)
+}
+
+/// Every write to `reg`, as the record number, the pc that wrote it and the
+/// value it wrote.
+pub fn reg_history(
+    reader: &TraceReader,
+    reg: Reg,
+) -> impl Iterator<Item = std::io::Result<(u64, u32, u32)>> + '_ {
+    reader.records().filter_map(move |record| match record {
+        Ok((n, record)) => match record.rd {
+            Some((rd, value)) if rd == reg => Some(Ok((n, record.pc, value))),
+            _ => None,
+        },
+        Err(e) => Some(Err(e)),
+    })
+}
+
+/// The first store that wrote the byte at `addr`.
+pub fn first_write(reader: &TraceReader, addr: u32) -> std::io::Result<Option<(u64, TraceRecord)>> {
+    for record in reader.records() {
+        let (n, record) = record?;
+        if let Some((start, len)) = record.store {
+            if addr.wrapping_sub(start) < len as u32 {
+                return Ok(Some((n, record)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Runs of `len` instructions retired one after another within a basic
+/// block, by mnemonic, most frequent first: the candidates for macro-op
+/// fusion. A run can end in a branch, but not continue past a taken one.
+pub fn sequences(
+    reader: &TraceReader,
+    len: usize,
+) -> std::io::Result<Vec<(Vec<&'static str>, u64)>> {
+    let mut counts = HashMap::<Vec<&'static str>, u64>::new();
+    let mut window = VecDeque::with_capacity(len);
+    let mut fallthrough = None;
+    for record in reader.records() {
+        let (_, record) = record?;
+        if fallthrough != Some(record.pc) {
+            window.clear();
+        }
+        fallthrough = Some(record.pc.wrapping_add(inst_len(record.inst)));
+        let Some(op) = Rv32IMASFDC::parse(record.inst) else {
+            window.clear();
+            continue;
+        };
+        if window.len() == len {
+            window.pop_front();
+        }
+        window.push_back(op.mnemonic());
+        if window.len() == len {
+            *counts.entry(window.iter().copied().collect()).or_default() += 1;
+        }
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(counts)
+}
+
+/// A function as called along one path from the start of the trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallNode {
+    /// Entry point, or the first pc in the trace for the root
+    pub callee: u32,
+    pub calls: u64,
+    /// Instructions retired in these calls, including their callees. Calls
+    /// that never returned count up to the end of the trace.
+    pub instructions: u64,
+    pub children: Vec<usize>,
+}
+
+/// Calls in a trace, merged by call path.
+#[derive(Debug, Clone, Default)]
+pub struct CallTree {
+    nodes: Vec<CallNode>,
+}
+
+struct Frame {
+    node: usize,
+    ret: u32,
+    entered_at: u64,
+}
+
+impl CallTree {
+    /// Replay the calls and returns in the trace.
+    pub fn build(reader: &TraceReader) -> std::io::Result<Self> {
+        let mut tree = Self::default();
+        let mut frames = Vec::<Frame>::new();
+        let mut prev: Option<TraceRecord> = None;
+        let mut count = 0;
+        for record in reader.records() {
+            let (n, record) = record?;
+            count = n + 1;
+            let Some(prev) = prev.replace(record) else {
+                tree.nodes.push(CallNode {
+                    callee: record.pc,
+                    calls: 1,
+                    instructions: 0,
+                    children: vec![],
+                });
+                continue;
+            };
+            let fallthrough = prev.pc.wrapping_add(inst_len(prev.inst));
+            if record.pc == fallthrough {
+                continue;
+            }
+
+            // Returns that match no call, say from before the trace started,
+            // leave the stack alone
+            if let Some(depth) = frames.iter().rposition(|f| f.ret == record.pc) {
+                for frame in frames.drain(depth..) {
+                    tree.nodes[frame.node].instructions += n - frame.entered_at;
+                }
+            }
+            if let Some((Reg::Ra | Reg::T0, ret)) = prev.rd.filter(|&(_, ret)| ret == fallthrough) {
+                let parent = frames.last().map_or(0, |f| f.node);
+                let node = tree.child(parent, record.pc);
+                tree.nodes[node].calls += 1;
+                frames.push(Frame {
+                    node,
+                    ret,
+                    entered_at: n,
+                });
+            }
+        }
+
+        for frame in frames {
+            tree.nodes[frame.node].instructions += count - frame.entered_at;
+        }
+        if let Some(root) = tree.nodes.first_mut() {
+            root.instructions = count;
+        }
+        Ok(tree)
+    }
+
+    fn child(&mut self, parent: usize, callee: u32) -> usize {
+        let existing = self.nodes[parent]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].callee == callee);
+        existing.unwrap_or_else(|| {
+            self.nodes.push(CallNode {
+                callee,
+                calls: 0,
+                instructions: 0,
+                children: vec![],
+            });
+            let node = self.nodes.len() - 1;
+            self.nodes[parent].children.push(node);
+            node
+        })
+    }
+
+    /// Where the trace started, if it has any records.
+    pub fn root(&self) -> Option<&CallNode> {
+        self.nodes.first()
+    }
+
+    pub fn node(&self, id: usize) -> &CallNode {
+        &self.nodes[id]
+    }
+
+    /// Write the tree, one call path per line indented by depth, with the
+    /// most expensive callees first, down to `max_depth` calls deep.
+    pub fn write_report(
+        &self,
+        w: &mut impl Write,
+        symbols: &SymbolTable,
+        max_depth: usize,
+    ) -> std::io::Result<()> {
+        writeln!(w, "{:>12} {:>10}  function", "total", "calls")?;
+        if !self.nodes.is_empty() {
+            self.write_node(w, symbols, 0, 0, max_depth)?;
+        }
+        Ok(())
+    }
+
+    fn write_node(
+        &self,
+        w: &mut impl Write,
+        symbols: &SymbolTable,
+        id: usize,
+        depth: usize,
+        max_depth: usize,
+    ) -> std::io::Result<()> {
+        let node = &self.nodes[id];
+        let name = match symbols.containing(node.callee) {
+            Some(sym) if sym.addr == node.callee => sym.name.clone(),
+            _ => symbols.describe(node.callee),
+        };
+        writeln!(
+            w,
+            "{:>12} {:>10}  {:indent$}{name}",
+            node.instructions,
+            node.calls,
+            "",
+            indent = depth * 2
+        )?;
+        if depth < max_depth {
+            let mut children = node.children.clone();
+            children.sort_by_key(|&child| std::cmp::Reverse(self.nodes[child].instructions));
+            for child in children {
+                self.write_node(w, symbols, child, depth + 1, max_depth)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn inst_len(inst: u32) -> u32 {
+    if inst & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
@@ -0,0 +1,102 @@
+use crate::csr::Csr;
+
+pub const CSR_MSTATUS: u16 = Csr::Mstatus.number();
+pub const CSR_MIE: u16 = Csr::Mie.number();
+pub const CSR_MTVEC: u16 = Csr::Mtvec.number();
+pub const CSR_MEPC: u16 = Csr::Mepc.number();
+pub const CSR_MCAUSE: u16 = Csr::Mcause.number();
+pub const CSR_MTVAL: u16 = Csr::Mtval.number();
+pub const CSR_MIP: u16 = Csr::Mip.number();
+
+pub const MSTATUS_MIE: u32 = 1 << 3;
+pub const MSTATUS_MPIE: u32 = 1 << 7;
+/// `mstatus.MPP`, always M-mode
+pub const MSTATUS_MPP: u32 = 0b11 << 11;
+
+/// `mcause` bit set for interrupts, as opposed to exceptions
+pub const MCAUSE_INTERRUPT: u32 = 1 << 31;
+
+/// `mcause` of a breakpoint exception
+pub const MCAUSE_BREAKPOINT: u32 = 3;
+
+/// `mtvec` mode where interrupts jump to `BASE + 4 * cause`
+const MTVEC_VECTORED: u32 = 1;
+
+/// What `ebreak` and `c.ebreak` do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EbreakMode {
+    /// Call [`Kernel::ebreak`](crate::machine::Kernel::ebreak), which halts
+    /// the machine unless the kernel handles it
+    #[default]
+    Kernel,
+    /// Stop in [`MachineState::Breakpoint`](crate::machine::MachineState::Breakpoint)
+    /// without executing it, for a debugger to inspect the guest and
+    /// [`Machine::resume`](crate::machine::Machine::resume) past it
+    Debugger,
+    /// Raise a breakpoint exception, trapping to `mtvec` with `mepc` and
+    /// `mtval` at the `ebreak`, for guests with their own trap handler
+    Trap,
+}
+
+impl std::fmt::Display for EbreakMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Kernel => "kernel",
+            Self::Debugger => "debugger",
+            Self::Trap => "trap",
+        })
+    }
+}
+
+impl std::str::FromStr for EbreakMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kernel" => Ok(Self::Kernel),
+            "debugger" => Ok(Self::Debugger),
+            "trap" => Ok(Self::Trap),
+            _ => Err(format!(
+                "unknown ebreak mode {s:?}, expected kernel, debugger or trap"
+            )),
+        }
+    }
+}
+
+/// A machine-level interrupt, numbered by its `mcause` code and `mip` bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Interrupt {
+    MachineSoftware = 3,
+    MachineTimer = 7,
+    MachineExternal = 11,
+}
+
+impl Interrupt {
+    /// In the order the spec says to take them when several are pending.
+    pub const BY_PRIORITY: [Interrupt; 3] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+    ];
+
+    /// The `mip`/`mie` bit for this interrupt.
+    pub const fn mask(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// `mip` bits driven by devices rather than software.
+    pub const LINES: u32 = Interrupt::MachineSoftware.mask()
+        | Interrupt::MachineTimer.mask()
+        | Interrupt::MachineExternal.mask();
+}
+
+/// Where `interrupt` traps to for the given `mtvec`.
+pub(crate) const fn trap_target(mtvec: u32, interrupt: Interrupt) -> u32 {
+    let base = mtvec & !0b11;
+    if mtvec & 0b11 == MTVEC_VECTORED {
+        base + 4 * interrupt as u32
+    } else {
+        base
+    }
+}
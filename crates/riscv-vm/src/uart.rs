@@ -0,0 +1,260 @@
+use std::{
+    io::{Read, Write},
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::{memory::Memory, mmio::MmioDevice, plic::IrqLine};
+
+/// Where the UART usually lives, matching QEMU's `virt` machine.
+pub const UART_BASE: u32 = 0x1000_0000;
+
+/// The UART's PLIC source on QEMU's `virt` machine.
+pub const UART_IRQ: u32 = 10;
+
+// Register offsets, with a register shift of 0
+const RBR_THR_DLL: u32 = 0;
+const IER_DLM: u32 = 1;
+const IIR_FCR: u32 = 2;
+const LCR: u32 = 3;
+const MCR: u32 = 4;
+const LSR: u32 = 5;
+const MSR: u32 = 6;
+const SCR: u32 = 7;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_THR_EMPTY: u8 = 1 << 1;
+
+const IIR_NO_INTERRUPT: u8 = 0x01;
+const IIR_THR_EMPTY: u8 = 0x02;
+const IIR_RX_AVAILABLE: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+const MCR_LOOPBACK: u8 = 1 << 4;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+const LSR_TX_EMPTY: u8 = 1 << 6;
+
+/// The host side of a channel-backed [`Uart16550`].
+#[derive(Debug)]
+pub struct UartChannel {
+    /// Bytes for the guest to receive
+    pub to_guest: Sender<u8>,
+    /// Bytes the guest transmitted
+    pub from_guest: Receiver<u8>,
+}
+
+enum Backend {
+    /// Transmit to stdout and receive from stdin. Stdin is read on a thread,
+    /// started the first time the guest checks for input.
+    Stdio {
+        input: Option<Receiver<u8>>,
+    },
+    Channel {
+        tx: Sender<u8>,
+        rx: Receiver<u8>,
+    },
+}
+
+/// An ns16550a-compatible UART.
+///
+/// Transmitted bytes go straight to the backend, so the transmitter is
+/// always empty and the baud rate is ignored. Without an interrupt line,
+/// `IIR` reports what would be pending but nothing is raised.
+pub struct Uart16550 {
+    backend: Backend,
+    irq: Option<IrqLine>,
+    /// Next received byte, taken from the backend when the guest looks for it
+    rx_byte: Option<u8>,
+    /// The transmitter-empty interrupt, raised by a write to `THR` or enabling
+    /// it in `IER`, and cleared by reading it from `IIR`
+    thr_empty: bool,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    divisor: u16,
+}
+
+impl Uart16550 {
+    fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            irq: None,
+            rx_byte: None,
+            thr_empty: false,
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            divisor: 0,
+        }
+    }
+
+    /// A UART connected to the host's stdin and stdout.
+    pub fn stdio() -> Self {
+        Self::new(Backend::Stdio { input: None })
+    }
+
+    /// A UART connected to channels, for tests and embedding.
+    pub fn channel() -> (Self, UartChannel) {
+        let (to_guest, rx) = mpsc::channel();
+        let (tx, from_guest) = mpsc::channel();
+        let uart = Self::new(Backend::Channel { tx, rx });
+        (
+            uart,
+            UartChannel {
+                to_guest,
+                from_guest,
+            },
+        )
+    }
+
+    /// Raise `irq` while an enabled interrupt is pending.
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        if self.mcr & MCR_LOOPBACK != 0 {
+            self.rx_byte.get_or_insert(byte);
+            return;
+        }
+        match &self.backend {
+            Backend::Stdio { .. } => {
+                let mut stdout = std::io::stdout().lock();
+                let _ = stdout.write_all(&[byte]);
+                let _ = stdout.flush();
+            }
+            Backend::Channel { tx, .. } => {
+                // If the host hung up, the byte goes nowhere, like on a disconnected line
+                let _ = tx.send(byte);
+            }
+        }
+    }
+
+    /// Fill `rx_byte` from the backend if it's empty, returning whether a byte is ready.
+    fn poll_rx(&mut self) -> bool {
+        if self.rx_byte.is_none() && self.mcr & MCR_LOOPBACK == 0 {
+            let rx = match &mut self.backend {
+                Backend::Stdio { input } => input.get_or_insert_with(spawn_stdin_reader),
+                Backend::Channel { rx, .. } => rx,
+            };
+            self.rx_byte = rx.try_recv().ok();
+        }
+        self.rx_byte.is_some()
+    }
+
+    fn iir(&mut self) -> u8 {
+        let fifo = if self.fcr & FCR_FIFO_ENABLE != 0 {
+            IIR_FIFO_ENABLED
+        } else {
+            0
+        };
+        let pending = if self.ier & IER_RX_AVAILABLE != 0 && self.poll_rx() {
+            IIR_RX_AVAILABLE
+        } else if self.ier & IER_THR_EMPTY != 0 && self.thr_empty {
+            self.thr_empty = false;
+            IIR_THR_EMPTY
+        } else {
+            IIR_NO_INTERRUPT
+        };
+        fifo | pending
+    }
+
+    fn read_reg(&mut self, offset: u32) -> u8 {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR_THR_DLL if dlab => self.divisor as u8,
+            RBR_THR_DLL => {
+                self.poll_rx();
+                self.rx_byte.take().unwrap_or(0)
+            }
+            IER_DLM if dlab => (self.divisor >> 8) as u8,
+            IER_DLM => self.ier,
+            IIR_FCR => self.iir(),
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => {
+                let ready = if self.poll_rx() { LSR_DATA_READY } else { 0 };
+                ready | LSR_THR_EMPTY | LSR_TX_EMPTY
+            }
+            // Carrier detect, data set ready and clear to send
+            MSR => 0xB0,
+            SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn write_reg(&mut self, offset: u32, value: u8) {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR_THR_DLL if dlab => self.divisor = (self.divisor & 0xFF00) | value as u16,
+            RBR_THR_DLL => {
+                self.transmit(value);
+                self.thr_empty = true;
+            }
+            IER_DLM if dlab => self.divisor = (self.divisor & 0x00FF) | (value as u16) << 8,
+            IER_DLM => {
+                if value & !self.ier & IER_THR_EMPTY != 0 {
+                    self.thr_empty = true;
+                }
+                self.ier = value & 0x0F;
+            }
+            IIR_FCR => self.fcr = value,
+            LCR => self.lcr = value,
+            MCR => self.mcr = value & 0x1F,
+            SCR => self.scr = value,
+            // LSR and MSR are read-only
+            _ => {}
+        }
+    }
+}
+
+impl MmioDevice for Uart16550 {
+    fn size(&self) -> u32 {
+        8
+    }
+
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        self.read_reg(offset) as u32
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        self.write_reg(offset, value as u8);
+    }
+
+    fn tick(&mut self, _mem: &mut Memory, _now: u64) -> u32 {
+        // Interrupts go through the PLIC, not straight to the hart
+        if self.irq.is_none() {
+            return 0;
+        }
+        let rx = self.ier & IER_RX_AVAILABLE != 0 && self.poll_rx();
+        let tx = self.ier & IER_THR_EMPTY != 0 && self.thr_empty;
+        if let Some(irq) = &self.irq {
+            irq.set(rx || tx);
+        }
+        0
+    }
+}
+
+/// Forward stdin to a channel, a byte at a time, until it closes.
+pub fn spawn_stdin_reader() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut byte = [0];
+        while let Ok(1) = std::io::stdin().read(&mut byte) {
+            if tx.send(byte[0]).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
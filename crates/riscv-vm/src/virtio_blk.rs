@@ -0,0 +1,394 @@
+use std::{fs::File, io, os::unix::fs::FileExt, path::Path};
+
+use crate::{memory::Memory, mmio::MmioDevice, plic::IrqLine};
+
+/// Where the first virtio-mmio transport usually lives, matching QEMU's `virt` machine.
+pub const VIRTIO_BASE: u32 = 0x1000_1000;
+
+/// The first virtio-mmio transport's PLIC source on QEMU's `virt` machine.
+pub const VIRTIO_IRQ: u32 = 1;
+
+pub const SECTOR_SIZE: u64 = 512;
+
+// virtio-mmio version 2 registers
+const MAGIC_VALUE: u32 = 0x000;
+const VERSION: u32 = 0x004;
+const DEVICE_ID: u32 = 0x008;
+const VENDOR_ID: u32 = 0x00c;
+const DEVICE_FEATURES: u32 = 0x010;
+const DEVICE_FEATURES_SEL: u32 = 0x014;
+const DRIVER_FEATURES: u32 = 0x020;
+const DRIVER_FEATURES_SEL: u32 = 0x024;
+const QUEUE_SEL: u32 = 0x030;
+const QUEUE_NUM_MAX: u32 = 0x034;
+const QUEUE_NUM: u32 = 0x038;
+const QUEUE_READY: u32 = 0x044;
+const QUEUE_NOTIFY: u32 = 0x050;
+const INTERRUPT_STATUS: u32 = 0x060;
+const INTERRUPT_ACK: u32 = 0x064;
+const STATUS: u32 = 0x070;
+const QUEUE_DESC_LOW: u32 = 0x080;
+const QUEUE_DESC_HIGH: u32 = 0x084;
+const QUEUE_DRIVER_LOW: u32 = 0x090;
+const QUEUE_DRIVER_HIGH: u32 = 0x094;
+const QUEUE_DEVICE_LOW: u32 = 0x0a0;
+const QUEUE_DEVICE_HIGH: u32 = 0x0a4;
+const CONFIG_GENERATION: u32 = 0x0fc;
+const CONFIG: u32 = 0x100;
+
+const MAGIC: u32 = 0x7472_6976; // "virt"
+const BLOCK_DEVICE: u32 = 2;
+const VENDOR: u32 = 0x4353_4952; // "RISC"
+
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+const VIRTIO_BLK_T_GET_ID: u32 = 8;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_IOERR: u8 = 1;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// `InterruptStatus` bits
+const INTERRUPT_USED_BUFFER: u32 = 1;
+const INTERRUPT_CONFIG_CHANGE: u32 = 2;
+
+/// `Status` bit set when the driver broke the queue
+const STATUS_NEEDS_RESET: u32 = 0x40;
+
+/// Entries in the one request queue.
+const QUEUE_SIZE: u32 = 128;
+
+/// Bytes in a `GET_ID` response.
+const ID_LEN: usize = 20;
+
+/// A guest buffer from a descriptor chain.
+#[derive(Debug, Clone, Copy)]
+struct Buffer {
+    addr: u32,
+    len: u32,
+    writable: bool,
+}
+
+/// The split virtqueue the driver set up.
+#[derive(Debug, Clone, Copy, Default)]
+struct Queue {
+    num: u32,
+    ready: bool,
+    desc: u64,
+    driver: u64,
+    device: u64,
+    /// Next available ring entry to process
+    last_avail: u16,
+}
+
+/// A virtio block device over the virtio-mmio transport, backed by a host
+/// image file.
+///
+/// Requests are handled on the tick after the driver notifies the queue,
+/// synchronously against the file. The image size is rounded down to whole
+/// sectors.
+#[derive(Debug)]
+pub struct VirtioBlk {
+    image: File,
+    read_only: bool,
+    sectors: u64,
+    irq: Option<IrqLine>,
+    device_features_sel: u32,
+    driver_features: u64,
+    driver_features_sel: u32,
+    queue: Queue,
+    notified: bool,
+    interrupt_status: u32,
+    status: u32,
+}
+
+impl VirtioBlk {
+    /// A block device for the image at `path`, opened read-only if `read_only`.
+    pub fn open(path: impl AsRef<Path>, read_only: bool) -> io::Result<Self> {
+        let image = File::options().read(true).write(!read_only).open(path)?;
+        Self::new(image, read_only)
+    }
+
+    /// A block device backed by `image`.
+    pub fn new(image: File, read_only: bool) -> io::Result<Self> {
+        let sectors = image.metadata()?.len() / SECTOR_SIZE;
+        Ok(Self {
+            image,
+            read_only,
+            sectors,
+            irq: None,
+            device_features_sel: 0,
+            driver_features: 0,
+            driver_features_sel: 0,
+            queue: Queue::default(),
+            notified: false,
+            interrupt_status: 0,
+            status: 0,
+        })
+    }
+
+    /// Raise `irq` while an interrupt is pending.
+    pub fn with_irq(mut self, irq: IrqLine) -> Self {
+        self.irq = Some(irq);
+        self
+    }
+
+    fn device_features(&self) -> u64 {
+        let ro = if self.read_only { VIRTIO_BLK_F_RO } else { 0 };
+        VIRTIO_F_VERSION_1 | VIRTIO_BLK_F_FLUSH | ro
+    }
+
+    fn reset(&mut self) {
+        self.device_features_sel = 0;
+        self.driver_features = 0;
+        self.driver_features_sel = 0;
+        self.queue = Queue::default();
+        self.notified = false;
+        self.interrupt_status = 0;
+        self.status = 0;
+    }
+
+    /// `struct virtio_blk_config`, of which only `capacity` and `blk_size` are set.
+    fn config(&self, offset: u32) -> u32 {
+        match offset {
+            0x00 => self.sectors as u32,
+            0x04 => (self.sectors >> 32) as u32,
+            0x14 => SECTOR_SIZE as u32,
+            _ => 0,
+        }
+    }
+
+    /// Handle every request the driver has made available.
+    fn process_queue(&mut self, mem: &mut Memory) {
+        let queue = self.queue;
+        if !queue.ready || queue.num == 0 {
+            return;
+        }
+        let (Ok(desc), Ok(avail), Ok(used)) = (
+            u32::try_from(queue.desc),
+            u32::try_from(queue.driver),
+            u32::try_from(queue.device),
+        ) else {
+            // Outside the 32-bit address space
+            self.needs_reset();
+            return;
+        };
+
+        let avail_idx = mem.load::<u16>(avail + 2);
+        let mut last_avail = queue.last_avail;
+        while last_avail != avail_idx {
+            let slot = last_avail as u32 % queue.num;
+            let head = mem.load::<u16>(avail + 4 + 2 * slot);
+            let Some(written) = self.handle_request(mem, desc, queue.num, head) else {
+                self.needs_reset();
+                break;
+            };
+
+            let used_idx = mem.load::<u16>(used + 2);
+            let elem = used + 4 + 8 * (used_idx as u32 % queue.num);
+            mem.store::<u32>(elem, head as u32);
+            mem.store::<u32>(elem + 4, written);
+            mem.store::<u16>(used + 2, used_idx.wrapping_add(1));
+
+            last_avail = last_avail.wrapping_add(1);
+        }
+        if last_avail != queue.last_avail {
+            self.queue.last_avail = last_avail;
+            self.interrupt_status |= INTERRUPT_USED_BUFFER;
+        }
+    }
+
+    /// Tell the driver the queue is broken and the device must be reset.
+    fn needs_reset(&mut self) {
+        self.status |= STATUS_NEEDS_RESET;
+        self.interrupt_status |= INTERRUPT_CONFIG_CHANGE;
+    }
+
+    /// Collect the chain starting at `head`, or `None` if it's malformed.
+    fn chain(mem: &Memory, desc: u32, num: u32, head: u16) -> Option<Vec<Buffer>> {
+        let mut buffers = vec![];
+        let mut index = head as u32;
+        loop {
+            if index >= num || buffers.len() > num as usize {
+                return None;
+            }
+            // `struct virtq_desc`
+            let entry = desc.checked_add(16 * index)?;
+            let addr = mem.load::<u64>(entry);
+            let flags = mem.load::<u16>(entry + 12);
+            buffers.push(Buffer {
+                addr: u32::try_from(addr).ok()?,
+                len: mem.load::<u32>(entry + 8),
+                writable: flags & VIRTQ_DESC_F_WRITE != 0,
+            });
+            if flags & VIRTQ_DESC_F_NEXT == 0 {
+                return Some(buffers);
+            }
+            index = mem.load::<u16>(entry + 14) as u32;
+        }
+    }
+
+    /// Run the request at `head`, returning how many bytes were written to
+    /// the guest, including the status byte, or `None` if it's malformed.
+    fn handle_request(&mut self, mem: &mut Memory, desc: u32, num: u32, head: u16) -> Option<u32> {
+        let buffers = Self::chain(mem, desc, num, head)?;
+        // Header first, status byte last, data in between
+        let (header, status) = (buffers.first()?, buffers.last()?);
+        if buffers.len() < 2 || header.len < 16 || header.writable || !status.writable {
+            return None;
+        }
+        let kind = mem.load::<u32>(header.addr);
+        let sector = mem.load::<u64>(header.addr + 8);
+        let data = &buffers[1..buffers.len() - 1];
+
+        let (result, written) = match kind {
+            VIRTIO_BLK_T_IN => self.read_sectors(mem, sector, data),
+            VIRTIO_BLK_T_OUT if self.read_only => (VIRTIO_BLK_S_IOERR, 0),
+            VIRTIO_BLK_T_OUT => self.write_sectors(mem, sector, data),
+            VIRTIO_BLK_T_FLUSH if self.read_only => (VIRTIO_BLK_S_OK, 0),
+            VIRTIO_BLK_T_FLUSH => match self.image.sync_data() {
+                Ok(()) => (VIRTIO_BLK_S_OK, 0),
+                Err(_) => (VIRTIO_BLK_S_IOERR, 0),
+            },
+            VIRTIO_BLK_T_GET_ID => {
+                let mut id = [0u8; ID_LEN];
+                id[..6].copy_from_slice(b"derisc");
+                match data.first() {
+                    Some(buf) if buf.writable => {
+                        let len = buf.len.min(ID_LEN as u32);
+                        match mem.copy_to(buf.addr, &id[..len as usize]) {
+                            Ok(()) => (VIRTIO_BLK_S_OK, len),
+                            Err(_) => (VIRTIO_BLK_S_IOERR, 0),
+                        }
+                    }
+                    _ => (VIRTIO_BLK_S_IOERR, 0),
+                }
+            }
+            _ => (VIRTIO_BLK_S_UNSUPP, 0),
+        };
+        mem.store::<u8>(status.addr, result);
+        Some(written + 1)
+    }
+
+    /// Whether `len` bytes from `sector` are within the image.
+    fn in_bounds(&self, sector: u64, len: u64) -> bool {
+        sector
+            .checked_mul(SECTOR_SIZE)
+            .and_then(|start| start.checked_add(len))
+            .is_some_and(|end| end <= self.sectors * SECTOR_SIZE)
+    }
+
+    fn read_sectors(&mut self, mem: &mut Memory, sector: u64, data: &[Buffer]) -> (u8, u32) {
+        let len: u64 = data.iter().map(|b| b.len as u64).sum();
+        if data.iter().any(|b| !b.writable) || !self.in_bounds(sector, len) {
+            return (VIRTIO_BLK_S_IOERR, 0);
+        }
+        let mut offset = sector * SECTOR_SIZE;
+        let mut written = 0;
+        for buf in data {
+            let Ok(dst) = mem.slice_mut::<u8>(buf.addr, buf.len) else {
+                return (VIRTIO_BLK_S_IOERR, written);
+            };
+            if self.image.read_exact_at(dst, offset).is_err() {
+                return (VIRTIO_BLK_S_IOERR, written);
+            }
+            offset += buf.len as u64;
+            written += buf.len;
+        }
+        (VIRTIO_BLK_S_OK, written)
+    }
+
+    fn write_sectors(&mut self, mem: &Memory, sector: u64, data: &[Buffer]) -> (u8, u32) {
+        let len: u64 = data.iter().map(|b| b.len as u64).sum();
+        if data.iter().any(|b| b.writable) || !self.in_bounds(sector, len) {
+            return (VIRTIO_BLK_S_IOERR, 0);
+        }
+        let mut offset = sector * SECTOR_SIZE;
+        for buf in data {
+            let Ok(src) = mem.slice::<u8>(buf.addr, buf.len) else {
+                return (VIRTIO_BLK_S_IOERR, 0);
+            };
+            if self.image.write_all_at(src, offset).is_err() {
+                return (VIRTIO_BLK_S_IOERR, 0);
+            }
+            offset += buf.len as u64;
+        }
+        (VIRTIO_BLK_S_OK, 0)
+    }
+}
+
+impl MmioDevice for VirtioBlk {
+    fn size(&self) -> u32 {
+        0x1000
+    }
+
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        let half = |value: u64, sel: u32| match sel {
+            0 => value as u32,
+            1 => (value >> 32) as u32,
+            _ => 0,
+        };
+        match offset {
+            MAGIC_VALUE => MAGIC,
+            VERSION => 2,
+            DEVICE_ID => BLOCK_DEVICE,
+            VENDOR_ID => VENDOR,
+            DEVICE_FEATURES => half(self.device_features(), self.device_features_sel),
+            QUEUE_NUM_MAX => QUEUE_SIZE,
+            QUEUE_READY => self.queue.ready as u32,
+            INTERRUPT_STATUS => self.interrupt_status,
+            STATUS => self.status,
+            CONFIG_GENERATION => 0,
+            o if o >= CONFIG => self.config((o - CONFIG) & !3) >> ((o & 3) * 8),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        let set_low = |reg: &mut u64| *reg = (*reg & !0xFFFF_FFFF) | value as u64;
+        let set_high = |reg: &mut u64| *reg = (*reg & 0xFFFF_FFFF) | (value as u64) << 32;
+        match offset {
+            DEVICE_FEATURES_SEL => self.device_features_sel = value,
+            DRIVER_FEATURES => match self.driver_features_sel {
+                0 => set_low(&mut self.driver_features),
+                1 => set_high(&mut self.driver_features),
+                _ => {}
+            },
+            DRIVER_FEATURES_SEL => self.driver_features_sel = value,
+            // There's only queue 0
+            QUEUE_SEL => {}
+            QUEUE_NUM => self.queue.num = value.min(QUEUE_SIZE),
+            QUEUE_READY => self.queue.ready = value & 1 != 0,
+            QUEUE_NOTIFY => self.notified = true,
+            INTERRUPT_ACK => self.interrupt_status &= !value,
+            STATUS if value == 0 => self.reset(),
+            STATUS => self.status = value,
+            QUEUE_DESC_LOW => set_low(&mut self.queue.desc),
+            QUEUE_DESC_HIGH => set_high(&mut self.queue.desc),
+            QUEUE_DRIVER_LOW => set_low(&mut self.queue.driver),
+            QUEUE_DRIVER_HIGH => set_high(&mut self.queue.driver),
+            QUEUE_DEVICE_LOW => set_low(&mut self.queue.device),
+            QUEUE_DEVICE_HIGH => set_high(&mut self.queue.device),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, mem: &mut Memory, _now: u64) -> u32 {
+        if std::mem::take(&mut self.notified) {
+            self.process_queue(mem);
+        }
+        if let Some(irq) = &self.irq {
+            irq.set(self.interrupt_status != 0);
+        }
+        // Interrupts go through the PLIC, not straight to the hart
+        0
+    }
+}
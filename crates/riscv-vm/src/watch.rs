@@ -0,0 +1,145 @@
+//! Watchpoints: stopping the guest when it accesses memory, optionally
+//! only for accesses of a given size or value.
+
+use crate::{
+    error::MemoryAccess,
+    hart::Hart32,
+    machine::{Kernel, Machine},
+};
+
+/// Accesses to stop at, see [`Machine::watch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// First byte watched
+    pub addr: u32,
+    /// Bytes watched from `addr`
+    pub len: u32,
+    /// Only loads or only stores, or `None` for both
+    pub access: Option<MemoryAccess>,
+    /// Only accesses of this many bytes
+    pub size: Option<u32>,
+    /// Only accesses of this value, zero-extended from the access size
+    pub value: Option<u32>,
+}
+
+impl Watchpoint {
+    /// Watch stores to any of `addr..addr + len`.
+    pub fn store(addr: u32, len: u32) -> Self {
+        Self::new(Some(MemoryAccess::Store), addr, len)
+    }
+
+    /// Watch loads from any of `addr..addr + len`.
+    pub fn load(addr: u32, len: u32) -> Self {
+        Self::new(Some(MemoryAccess::Load), addr, len)
+    }
+
+    /// Watch loads and stores to any of `addr..addr + len`.
+    pub fn access(addr: u32, len: u32) -> Self {
+        Self::new(None, addr, len)
+    }
+
+    fn new(access: Option<MemoryAccess>, addr: u32, len: u32) -> Self {
+        Self {
+            addr,
+            len,
+            access,
+            size: None,
+            value: None,
+        }
+    }
+
+    /// Only stop at accesses of `size` bytes.
+    pub fn with_size(mut self, size: u32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Only stop at accesses that load or store `value`.
+    pub fn with_value(mut self, value: u32) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Whether an access of `size` bytes at `addr`, of `value`, is one to
+    /// stop at.
+    pub fn matches(&self, access: MemoryAccess, addr: u32, size: u32, value: u32) -> bool {
+        let overlaps =
+            addr.wrapping_sub(self.addr) < self.len || self.addr.wrapping_sub(addr) < size;
+        overlaps
+            && self.access.is_none_or(|a| a == access)
+            && self.size.is_none_or(|s| s == size)
+            && self.value.is_none_or(|v| v == value)
+    }
+}
+
+/// The access that stopped the guest at a watchpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    /// The instruction that made the access
+    pub pc: u32,
+    pub access: MemoryAccess,
+    pub addr: u32,
+    pub size: u32,
+    /// Loaded or stored, zero-extended
+    pub value: u32,
+}
+
+impl<K: Kernel> Machine<K> {
+    /// Stop after any instruction making an access `watchpoint` matches.
+    ///
+    /// The machine stops with [`MachineState::Breakpoint`], after the
+    /// access and with the pc at the next instruction, and
+    /// [`Machine::watch_hit`] says which access it was until it's
+    /// [resumed](Machine::resume). Only the guest's loads, stores and
+    /// atomics are watched: the kernel, devices and
+    /// [intercepted](Machine::intercept) functions write memory unseen.
+    ///
+    /// [`MachineState::Breakpoint`]: crate::machine::MachineState::Breakpoint
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.hart.watchpoints.push(watchpoint);
+    }
+
+    /// Remove `watchpoint`, returning whether it was set.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.hart.watchpoints.len();
+        self.hart.watchpoints.retain(|w| w != watchpoint);
+        self.hart.watchpoints.len() != len
+    }
+
+    /// Watchpoints set, in the order they were.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.hart.watchpoints
+    }
+
+    /// The access the machine stopped at, if it's stopped at a watchpoint.
+    pub fn watch_hit(&self) -> Option<&WatchHit> {
+        self.hart.watch_hit.as_ref()
+    }
+}
+
+impl Hart32 {
+    /// Record the first watchpoint an access of `value` matches, if any,
+    /// returning whether one did.
+    pub(crate) fn watch(&mut self, access: MemoryAccess, addr: u32, size: u32, value: u32) -> bool {
+        let value = value & u32::MAX >> (32 - 8 * size);
+        let Some(watchpoint) = self
+            .watchpoints
+            .iter()
+            .find(|w| w.matches(access, addr, size, value))
+        else {
+            return false;
+        };
+        tracing::debug!(pc = self.pc, addr, size, value, "hit watchpoint");
+        self.watch_hit = Some(WatchHit {
+            watchpoint: watchpoint.clone(),
+            pc: self.pc,
+            access,
+            addr,
+            size,
+            value,
+        });
+
+        true
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    error::MachineError,
+    machine::{Kernel, Machine, MachineState},
+};
+
+/// Steps between clock reads when yielding on a time interval, and between
+/// cancellation checks otherwise.
+pub(crate) const CHECK_STRIDE: u64 = 4096;
+
+/// Cancels a [`Machine::run_with`](crate::machine::Machine::run_with) from
+/// anywhere, including other threads. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the run at its next check, within a few thousand instructions.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How often [`Machine::run_with`](crate::machine::Machine::run_with) calls
+/// back into the host. With both set, it calls back on whichever comes first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct YieldEvery {
+    /// Steps between callbacks
+    pub instructions: Option<u64>,
+    /// Wall-clock time between callbacks, checked every few thousand steps
+    pub interval: Option<Duration>,
+}
+
+impl YieldEvery {
+    pub fn instructions(n: u64) -> Self {
+        Self {
+            instructions: Some(n.max(1)),
+            interval: None,
+        }
+    }
+
+    pub fn interval(interval: Duration) -> Self {
+        Self {
+            instructions: None,
+            interval: Some(interval),
+        }
+    }
+}
+
+/// Why [`Machine::run_with`](crate::machine::Machine::run_with) returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunExit {
+    /// The machine halted
+    Halted,
+    /// The [`CancelToken`] was cancelled
+    Cancelled,
+    /// The callback asked to stop
+    Stopped,
+    /// The guest stopped at a breakpoint, see
+    /// [`Machine::resume`](crate::machine::Machine::resume)
+    Breakpoint,
+}
+
+/// Future returned by [`Machine::run_async`].
+#[must_use = "futures do nothing unless polled"]
+pub struct RunAsync<'a, K: Kernel> {
+    machine: &'a mut Machine<K>,
+    /// Steps between yields to the executor
+    every: u64,
+}
+
+impl<'a, K: Kernel> RunAsync<'a, K> {
+    pub(crate) fn new(machine: &'a mut Machine<K>, every: u64) -> Self {
+        Self {
+            machine,
+            every: every.max(1),
+        }
+    }
+}
+
+impl<K: Kernel> Future for RunAsync<'_, K> {
+    type Output = Result<(), MachineError<K::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let machine = &mut *this.machine;
+        for _ in 0..this.every {
+            if machine.state != MachineState::Running {
+                return Poll::Ready(Ok(()));
+            }
+            if machine.kernel.poll_io(cx).is_pending() {
+                return Poll::Pending;
+            }
+            machine.step()?;
+            if std::mem::take(&mut machine.hart.spinning) {
+                // The guest is waiting for something that won't happen while
+                // it holds the thread
+                break;
+            }
+        }
+        if machine.state != MachineState::Running {
+            return Poll::Ready(Ok(()));
+        }
+
+        // Out of budget or spinning, let other tasks run before continuing
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
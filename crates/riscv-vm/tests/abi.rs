@@ -0,0 +1,66 @@
+//! Calling-convention helpers on `Hart32`.
+
+use riscv_vm::{hart::Hart32, memory::Memory, riscv_inst::Reg};
+
+#[test]
+fn args_are_a_registers() {
+    let mut hart = Hart32::new();
+    for n in 0..8 {
+        hart.set_arg(n, n as u32 + 1);
+    }
+    assert_eq!(hart.get_reg(Reg::A0), 1);
+    assert_eq!(hart.get_reg(Reg::A7), 8);
+    assert_eq!(hart.args::<3>(), [1, 2, 3]);
+
+    hart.set_return_pair(9, 10);
+    assert_eq!(hart.return_value(), 9);
+    assert_eq!(hart.arg(1), 10);
+}
+
+#[test]
+#[should_panic(expected = "a8 isn't an argument register")]
+fn only_eight_arg_registers() {
+    Hart32::new().arg(8);
+}
+
+#[test]
+fn stack_push_and_pop() {
+    let mut hart = Hart32::new();
+    let mut mem = Memory::new();
+    hart.set_reg(Reg::Sp, 0x8000);
+
+    assert_eq!(hart.stack_push(&mut mem, 0x1122_3344u32).unwrap(), 0x7ffc);
+    assert_eq!(hart.stack_push(&mut mem, 0x55u8).unwrap(), 0x7ffb);
+    assert_eq!(mem.load::<u32>(0x7ffc), 0x1122_3344);
+
+    assert_eq!(hart.stack_pop::<u8>(&mem).unwrap(), 0x55);
+    assert_eq!(hart.stack_pop::<u32>(&mem).unwrap(), 0x1122_3344);
+    assert_eq!(hart.get_reg(Reg::Sp), 0x8000);
+}
+
+#[test]
+fn stack_push_fails_below_zero() {
+    let mut hart = Hart32::new();
+    let mut mem = Memory::new();
+    hart.set_reg(Reg::Sp, 2);
+
+    assert!(hart.stack_push(&mut mem, 0u32).is_err());
+    assert_eq!(hart.get_reg(Reg::Sp), 2);
+}
+
+#[test]
+fn frame_has_abi_registers() {
+    let mut hart = Hart32::new();
+    hart.pc = 0x1000;
+    hart.set_reg(Reg::Ra, 0x2000);
+    hart.set_reg(Reg::Sp, 0x3000);
+    hart.set_reg(Reg::S0, 0x3010);
+    hart.set_arg(2, 42);
+
+    let frame = hart.read_frame();
+    assert_eq!(
+        (frame.pc, frame.ra, frame.sp, frame.fp),
+        (0x1000, 0x2000, 0x3000, 0x3010)
+    );
+    assert_eq!(frame.args, [0, 0, 42, 0, 0, 0, 0, 0]);
+}
@@ -0,0 +1,96 @@
+//! Placing host buffers in guest memory with the arena allocator, and
+//! calling into the guest.
+
+mod common;
+
+use common::{NopKernel, EBREAK};
+use riscv_vm::{
+    alloc::GuestAllocator,
+    error::{AllocError, MachineError},
+    machine::Machine,
+};
+
+fn allocated(machine: &Machine<NopKernel>) -> u32 {
+    match &machine.allocator {
+        GuestAllocator::Arena(arena) => arena.allocated(),
+        GuestAllocator::Malloc { .. } => unreachable!(),
+    }
+}
+
+#[test]
+fn freed_neighbours_coalesce() {
+    let mut machine = Machine::new(NopKernel);
+    let a = machine.guest_alloc(0x100, 1).unwrap();
+    let b = machine.guest_alloc(0x100, 1).unwrap();
+    let c = machine.guest_alloc(0x100, 1).unwrap();
+    assert_eq!((b, c), (a + 0x100, a + 0x200));
+    assert_eq!(allocated(&machine), 0x300);
+
+    // Only once both are free is there room for both at `a`
+    machine.guest_free(a).unwrap();
+    assert_ne!(machine.guest_alloc(0x200, 1).unwrap(), a);
+    machine.guest_free(b).unwrap();
+    assert_eq!(machine.guest_alloc(0x200, 1).unwrap(), a);
+
+    let err = machine.guest_free(b).unwrap_err();
+    assert!(matches!(
+        err,
+        MachineError::Alloc(AllocError::InvalidFree { addr }) if addr == b
+    ));
+    assert_eq!(err.code(), 302);
+}
+
+#[test]
+fn allocations_are_aligned_and_zeroed() {
+    let mut machine = Machine::new(NopKernel);
+    let odd = machine.guest_alloc(3, 1).unwrap();
+    machine.mem.memset(odd, 0xff, 3).unwrap();
+    machine.guest_free(odd).unwrap();
+
+    let word = machine.guest_alloc(4, 4).unwrap();
+    assert_eq!(word % 4, 0);
+    // Reusing the first block, it's cleared
+    assert_eq!(word, odd);
+    assert_eq!(machine.mem.load::<u32>(word), 0);
+    let page = machine.guest_alloc(8, 0x1000).unwrap();
+    assert_eq!(page % 0x1000, 0);
+    assert!(page > word);
+
+    let err = machine.guest_alloc(8, 3).unwrap_err();
+    assert_eq!(err.code(), 301);
+}
+
+#[test]
+fn arena_grows_down_from_the_mmap_area() {
+    let mut machine = Machine::new(NopKernel);
+    let top = machine.mem.mmap_top;
+    let small = machine.guest_alloc(0x10, 1).unwrap();
+    let chunk = top - machine.mem.mmap_top;
+    assert!(chunk > 0 && small >= machine.mem.mmap_top);
+
+    // Too big for what's left, it takes a new region below the first
+    let big = machine.guest_alloc(chunk, 1).unwrap();
+    assert!(big < small);
+    assert!(machine.mem.mmap_top <= big);
+    assert_eq!(allocated(&machine), 0x10 + chunk);
+
+    // Never into the heap
+    machine.mem.brk = machine.mem.mmap_top - 1;
+    let err = machine.guest_alloc(2 * chunk, 1).unwrap_err();
+    assert!(matches!(
+        err,
+        MachineError::Alloc(AllocError::OutOfMemory { .. })
+    ));
+}
+
+#[test]
+fn calls_take_at_most_eight_arguments() {
+    // A function returning its first argument
+    let mut machine = common::machine(&[0x0000_8067, EBREAK]);
+    let func = machine.hart.pc;
+    assert_eq!(machine.call(func, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(), 1);
+
+    let err = machine.call(func, &[0; 9]).unwrap_err();
+    assert!(matches!(err, MachineError::TooManyArgs { count: 9 }));
+    assert_eq!(err.code(), 405);
+}
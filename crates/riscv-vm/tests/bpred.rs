@@ -0,0 +1,87 @@
+//! Branch predictor models on known outcome patterns, and the exact
+//! mispredictions of a guest loop.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    bpred::{BranchPredictor, PredictorConfig, PredictorStats},
+    hpm::HpmEvent,
+    machine::{Machine, MachineConfig},
+};
+
+/// Counts `s1` down from 10, branching back 9 times.
+const LOOP: [u32; 4] = [
+    0x00a0_0493, // li s1, 10
+    0xfff4_8493, // loop: addi s1, s1, -1
+    0xfe04_9ee3, // bnez s1, loop
+    EBREAK,
+];
+
+/// Mispredictions of the branch at `pc` with `offset` over `outcomes`.
+fn mispredictions(config: PredictorConfig, pc: u32, offset: i32, outcomes: &[bool]) -> u64 {
+    let mut predictor = BranchPredictor::new(config);
+    for &taken in outcomes {
+        predictor.record(pc, offset, taken);
+    }
+    predictor.stats.mispredictions
+}
+
+#[test]
+fn static_predicts_backward_branches_taken() {
+    let outcomes = [true, true, false, true];
+    assert_eq!(
+        mispredictions(PredictorConfig::Static, 0x100, -8, &outcomes),
+        1
+    );
+    assert_eq!(
+        mispredictions(PredictorConfig::Static, 0x100, 8, &outcomes),
+        3
+    );
+}
+
+#[test]
+fn bimodal_counters_saturate() {
+    let bimodal = PredictorConfig::Bimodal { bits: 4 };
+    // Weakly not taken to begin with, so only the first is wrong
+    assert_eq!(mispredictions(bimodal, 0x100, 8, &[true; 10]), 1);
+    // One not taken doesn't undo three taken
+    let outcomes = [true, true, true, false, true, true];
+    assert_eq!(mispredictions(bimodal, 0x100, 8, &outcomes), 2);
+    // Alternating outcomes flip the counter between the two weak states,
+    // so every prediction is wrong
+    let alternating: Vec<_> = (0..20).map(|i| i % 2 == 0).collect();
+    assert_eq!(mispredictions(bimodal, 0x100, 8, &alternating), 20);
+}
+
+#[test]
+fn gshare_learns_patterns_from_history() {
+    let alternating: Vec<_> = (0..20).map(|i| i % 2 == 0).collect();
+    let gshare = PredictorConfig::Gshare { bits: 4 };
+    // Wrong only until the history fills the index: the taken outcomes
+    // before then each see a fresh history
+    assert_eq!(mispredictions(gshare, 0x100, 8, &alternating), 3);
+}
+
+#[test]
+fn guest_loop_mispredicts_its_entry_and_exit() {
+    let config = MachineConfig {
+        branch_predictor: Some(PredictorConfig::Bimodal { bits: 8 }),
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &LOOP);
+    machine.run().unwrap();
+
+    let predictor = machine.hart.branch_predictor.as_ref().unwrap();
+    let stats = PredictorStats {
+        predictions: 10,
+        mispredictions: 2,
+    };
+    assert_eq!(predictor.stats, stats);
+    let sites = predictor.sites();
+    assert_eq!(sites.len(), 1);
+    assert_eq!((sites[0].pc, sites[0].stats), (CODE + 8, stats));
+    assert_eq!(predictor.stats.accuracy(), 0.8);
+    let hpm = &machine.hart.hpm;
+    assert_eq!(hpm.event_count(HpmEvent::BranchMispredicts), 2);
+}
@@ -0,0 +1,90 @@
+//! The cache simulator on known access patterns: LRU replacement within a
+//! set, conflicts between lines mapping to the same set, and the exact hits
+//! and misses of a guest loop.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    cache::{Cache, CacheConfig, CacheStats},
+    machine::{Machine, MachineConfig},
+};
+
+/// Increments the word at 0x8000 ten times.
+const LOOP: [u32; 8] = [
+    0x0000_8437, // lui s0, 0x8
+    0x00a0_0493, // li s1, 10
+    0x0004_2303, // loop: lw t1, 0(s0)
+    0x0013_0313, // addi t1, t1, 1
+    0x0064_2023, // sw t1, 0(s0)
+    0xfff4_8493, // addi s1, s1, -1
+    0xfe04_98e3, // bnez s1, loop
+    EBREAK,
+];
+
+fn hits(cache: &mut Cache, addrs: &[u32]) -> Vec<bool> {
+    addrs.iter().map(|&addr| cache.access(addr)).collect()
+}
+
+#[test]
+fn least_recently_used_line_is_evicted() {
+    // One set of two 64-byte lines
+    let mut cache = Cache::new(CacheConfig::new(128, 2, 64).unwrap());
+    let (a, b, c) = (0x0, 0x1040, 0x2080);
+    assert_eq!(
+        hits(&mut cache, &[a, a + 63, b, a, c, b, a, b]),
+        [false, true, false, true, false, false, false, true]
+    );
+    assert_eq!(cache.stats, CacheStats { hits: 3, misses: 5 });
+
+    // Flushing keeps the counts
+    cache.flush();
+    assert_eq!(hits(&mut cache, &[c]), [false]);
+    assert_eq!(cache.stats.accesses(), 9);
+}
+
+#[test]
+fn lines_in_the_same_set_conflict() {
+    // Direct mapped, two sets
+    let config = CacheConfig::new(128, 1, 64).unwrap();
+    assert_eq!(config.sets(), 2);
+    let mut cache = Cache::new(config);
+    // 0 and 128 share set 0, 64 has set 1 to itself
+    assert_eq!(
+        hits(&mut cache, &[0, 64, 128, 64, 0, 128]),
+        [false, false, false, true, false, false]
+    );
+    assert_eq!(cache.stats.miss_rate(), 5.0 / 6.0);
+
+    assert_eq!(CacheConfig::new(96, 1, 64), None);
+    assert_eq!(CacheConfig::new(64, 2, 64), None);
+    assert_eq!(
+        "32k:4:64".parse(),
+        Ok(CacheConfig::new(32 << 10, 4, 64).unwrap())
+    );
+}
+
+#[test]
+fn guest_loop_hits_after_the_first_touch() {
+    let config = MachineConfig {
+        icache: CacheConfig::new(256, 2, 64),
+        dcache: CacheConfig::new(256, 2, 64),
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &LOOP);
+    machine.symbols.insert("main", CODE, 4 * LOOP.len() as u32);
+    machine.run().unwrap();
+
+    let sim = machine.hart.cache_sim.as_ref().unwrap();
+    // 2 + 5 * 10 + 1 fetches, all from the one line the code fits in
+    let icache = sim.icache.as_ref().unwrap().stats;
+    assert_eq!((icache.hits, icache.misses), (52, 1));
+    // 10 loads and 10 stores of one word
+    let dcache = sim.dcache.as_ref().unwrap().stats;
+    assert_eq!((dcache.hits, dcache.misses), (19, 1));
+
+    let functions = sim.by_function(&machine.symbols);
+    assert_eq!(functions.len(), 1);
+    assert_eq!(functions[0].function, "main");
+    assert_eq!((functions[0].icache, functions[0].dcache), (icache, dcache));
+}
@@ -0,0 +1,84 @@
+//! Recording indirect branches and checking them against the function
+//! layout: a call into the middle of a function is flagged, ordinary calls
+//! and returns aren't.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    cfi::{BranchEdge, BranchKind},
+    machine::{Machine, MachineConfig},
+    riscv_inst::Reg,
+};
+
+const NOP: u32 = 0x0000_0013;
+const RET: u32 = 0x0000_8067;
+const F: u32 = CODE + 0x20;
+
+/// Calls `f` through a pointer, then calls 4 bytes past it, which lands
+/// on its `ret`.
+const PROGRAM: [u32; 10] = [
+    0x0000_0317, // auipc t1, 0
+    0x0203_0313, // addi t1, t1, 0x20
+    0x0003_00e7, // jalr ra, 0(t1)
+    0x0043_00e7, // jalr ra, 4(t1)
+    EBREAK,
+    NOP,
+    NOP,
+    NOP,
+    // f
+    0x0015_0513, // addi a0, a0, 1
+    RET,
+];
+
+fn edge(source: u32, target: u32, kind: BranchKind) -> BranchEdge {
+    BranchEdge {
+        source,
+        target,
+        kind,
+        count: 1,
+    }
+}
+
+#[test]
+fn calls_into_the_middle_of_a_function_are_flagged() {
+    let config = MachineConfig {
+        branch_trace: true,
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &PROGRAM);
+    machine.symbols.insert("main", CODE, 0x20);
+    machine.symbols.insert("f", F, 8);
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0), 1);
+
+    let trace = machine.hart.branch_trace.as_ref().unwrap();
+    let bad_call = edge(CODE + 0xc, F + 4, BranchKind::Call);
+    assert_eq!(
+        trace.edges(),
+        [
+            edge(CODE + 8, F, BranchKind::Call),
+            bad_call,
+            edge(F + 4, CODE + 0xc, BranchKind::Return),
+            edge(F + 4, CODE + 0x10, BranchKind::Return),
+        ]
+    );
+    assert_eq!(trace.targets(F + 4), [CODE + 0xc, CODE + 0x10]);
+    assert_eq!(trace.validate(&machine.symbols), [bad_call]);
+
+    let mut csv = vec![];
+    trace.write_csv(&mut csv, &machine.symbols).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines[0], "source,target,kind,count,source_sym,target_sym");
+    assert_eq!(lines[2], "0x0000100c,0x00001024,call,1,main+0xc,f+0x4");
+}
+
+#[test]
+fn jalr_hints_classify_branches() {
+    assert_eq!(BranchKind::classify(Reg::Ra, Reg::A5), BranchKind::Call);
+    assert_eq!(BranchKind::classify(Reg::T0, Reg::Ra), BranchKind::Call);
+    assert_eq!(BranchKind::classify(Reg::Zero, Reg::Ra), BranchKind::Return);
+    assert_eq!(BranchKind::classify(Reg::Zero, Reg::T0), BranchKind::Return);
+    assert_eq!(BranchKind::classify(Reg::Zero, Reg::A5), BranchKind::Jump);
+}
@@ -0,0 +1,141 @@
+//! Message channels: what one end sends the other receives in order, full
+//! channels refuse more, and the device raises its interrupt line while
+//! an enabled condition holds.
+
+use riscv_vm::{channel::*, memory::Memory, mmio::MmioDevice, plic::Plic};
+
+// PLIC registers
+const PENDING: u32 = 0x00_1000;
+const ENABLE: u32 = 0x00_2000;
+const CLAIM_COMPLETE: u32 = 0x20_0004;
+
+/// Send `message` through `device`'s transmit buffer.
+fn send(device: &mut Channel, message: &[u8]) {
+    for (i, chunk) in message.chunks(4).enumerate() {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let offset = CHANNEL_TX_BUF + 4 * i as u32;
+        device.write(offset, 4, u32::from_le_bytes(word));
+    }
+    device.write(CHANNEL_TX_LEN, 4, message.len() as u32);
+}
+
+/// Take the message in `device`'s receive buffer, if any.
+fn recv(device: &mut Channel) -> Option<Vec<u8>> {
+    let len = device.read(CHANNEL_RX_LEN, 4);
+    if len == 0 {
+        return None;
+    }
+    let message = (0..len)
+        .map(|i| device.read(CHANNEL_RX_BUF + i, 1) as u8)
+        .collect();
+    device.write(CHANNEL_RX_POP, 4, 0);
+    Some(message)
+}
+
+#[test]
+fn devices_talk_in_order() {
+    let (a, b) = ChannelEnd::pair(4);
+    let (mut a, mut b) = (Channel::new(a), Channel::new(b));
+    assert_eq!(b.read(CHANNEL_STATUS, 4), STATUS_TX_READY);
+
+    send(&mut a, b"hello");
+    send(&mut a, b"world!");
+    assert_eq!(b.read(CHANNEL_STATUS, 4), STATUS_RX_READY | STATUS_TX_READY);
+    // Halfwords and words from the buffer are little-endian
+    assert_eq!(b.read(CHANNEL_RX_BUF, 4), u32::from_le_bytes(*b"hell"));
+    assert_eq!(b.read(CHANNEL_RX_BUF + 4, 2), u32::from(b'o'));
+    assert_eq!(recv(&mut b).unwrap(), b"hello");
+    assert_eq!(recv(&mut b).unwrap(), b"world!");
+    assert_eq!(recv(&mut b), None);
+
+    // And back
+    send(&mut b, b"hi");
+    assert_eq!(recv(&mut a).unwrap(), b"hi");
+}
+
+#[test]
+fn the_host_can_hold_an_end() {
+    let (host, guest) = ChannelEnd::pair(2);
+    let mut guest = Channel::new(guest);
+
+    assert_eq!(host.try_send(b"ping".to_vec()), Ok(()));
+    assert_eq!(recv(&mut guest).unwrap(), b"ping");
+    send(&mut guest, b"pong");
+    assert_eq!(host.try_recv().unwrap(), b"pong");
+    assert_eq!(host.try_recv(), None);
+
+    // Too big to send
+    let big = vec![0; CHANNEL_MTU as usize + 1];
+    assert_eq!(host.try_send(big.clone()), Err(big));
+}
+
+#[test]
+fn full_channels_drop_and_report_it() {
+    let (host, guest) = ChannelEnd::pair(2);
+    let mut guest = Channel::new(guest);
+
+    send(&mut guest, b"1");
+    send(&mut guest, b"2");
+    assert_eq!(guest.read(CHANNEL_STATUS, 4) & STATUS_TX_READY, 0);
+    send(&mut guest, b"3");
+    // Reported once
+    assert_eq!(guest.read(CHANNEL_STATUS, 4), STATUS_TX_DROPPED);
+    assert_eq!(guest.read(CHANNEL_STATUS, 4), 0);
+
+    assert_eq!(host.try_recv().unwrap(), b"1");
+    assert_eq!(host.try_recv().unwrap(), b"2");
+    assert_eq!(host.try_recv(), None);
+    assert_eq!(host.try_send(b"a".to_vec()), Ok(()));
+    assert_eq!(host.try_send(b"b".to_vec()), Ok(()));
+    assert_eq!(host.try_send(b"c".to_vec()), Err(b"c".to_vec()));
+
+    // Closing leaves what was sent readable
+    drop(host);
+    let status = guest.read(CHANNEL_STATUS, 4);
+    assert_eq!(
+        status,
+        STATUS_RX_READY | STATUS_TX_READY | STATUS_PEER_CLOSED
+    );
+    assert_eq!(recv(&mut guest).unwrap(), b"a");
+    assert_eq!(recv(&mut guest).unwrap(), b"b");
+}
+
+/// Whether the channel's line was raised since the last call, claiming
+/// and completing its interrupt if so.
+fn raised(plic: &mut Plic, mem: &mut Memory) -> bool {
+    plic.tick(mem, 0);
+    if plic.read(PENDING, 4) & 1 << CHANNEL_IRQ == 0 {
+        return false;
+    }
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), CHANNEL_IRQ);
+    plic.write(CLAIM_COMPLETE, 4, CHANNEL_IRQ);
+    true
+}
+
+#[test]
+fn interrupts_follow_enabled_conditions() {
+    let (mut plic, mut mem) = (Plic::new(), Memory::new());
+    plic.write(4 * CHANNEL_IRQ, 4, 1);
+    plic.write(ENABLE, 4, 1 << CHANNEL_IRQ);
+    let (host, guest) = ChannelEnd::pair(1);
+    let mut guest = Channel::new(guest).with_irq(plic.line(CHANNEL_IRQ).unwrap());
+
+    guest.tick(&mut mem, 0);
+    assert!(!raised(&mut plic, &mut mem));
+    guest.write(CHANNEL_IE, 4, STATUS_RX_READY | STATUS_PEER_CLOSED);
+    assert_eq!(guest.read(CHANNEL_IE, 4), STATUS_RX_READY);
+    guest.tick(&mut mem, 1);
+    assert!(!raised(&mut plic, &mut mem));
+
+    host.try_send(b"x".to_vec()).unwrap();
+    guest.tick(&mut mem, 2);
+    assert!(raised(&mut plic, &mut mem));
+    recv(&mut guest);
+    guest.tick(&mut mem, 3);
+    assert!(!raised(&mut plic, &mut mem));
+
+    guest.write(CHANNEL_IE, 4, STATUS_TX_READY);
+    guest.tick(&mut mem, 4);
+    assert!(raised(&mut plic, &mut mem));
+}
@@ -0,0 +1,107 @@
+//! The CLINT's registers, one at a time: `msip` raising the software
+//! interrupt, `mtime` following retired instructions, and `mtimecmp`
+//! raising the timer interrupt once `mtime` reaches it.
+
+mod common;
+
+use common::EBREAK;
+use riscv_vm::{
+    clint::{Clint, CLINT_BASE},
+    memory::Memory,
+    mmio::MmioDevice,
+    trap::{Interrupt, CSR_MCAUSE, CSR_MIE, CSR_MSTATUS, CSR_MTVEC, MCAUSE_INTERRUPT, MSTATUS_MIE},
+};
+
+const MSIP: u32 = 0x0000;
+const MTIMECMP: u32 = 0x4000;
+const MTIME: u32 = 0xBFF8;
+
+const SOFTWARE: u32 = Interrupt::MachineSoftware.mask();
+const TIMER: u32 = Interrupt::MachineTimer.mask();
+
+fn read64(clint: &mut Clint, offset: u32) -> u64 {
+    clint.read(offset, 4) as u64 | (clint.read(offset + 4, 4) as u64) << 32
+}
+
+#[test]
+fn msip_raises_the_software_interrupt() {
+    let (mut clint, mut mem) = (Clint::new(1), Memory::new());
+    assert_eq!(clint.read(MSIP, 4), 0);
+    assert_eq!(clint.tick(&mut mem, 0), 0);
+
+    // Only bit 0 is writable
+    clint.write(MSIP, 4, 0xffff_ffff);
+    assert_eq!(clint.read(MSIP, 4), 1);
+    assert_eq!(clint.tick(&mut mem, 1), SOFTWARE);
+    clint.write(MSIP, 4, 2);
+    assert_eq!(clint.read(MSIP, 4), 0);
+    assert_eq!(clint.tick(&mut mem, 2), 0);
+}
+
+#[test]
+fn mtime_counts_ticks_of_retired_instructions() {
+    let (mut clint, mut mem) = (Clint::new(10), Memory::new());
+    clint.tick(&mut mem, 25);
+    assert_eq!(read64(&mut clint, MTIME), 2);
+
+    // Setting it moves the count along from there
+    clint.write(MTIME + 4, 4, 1);
+    clint.write(MTIME, 4, 0xffff_fff0);
+    assert_eq!(read64(&mut clint, MTIME), 0x1_ffff_fff0);
+    clint.tick(&mut mem, 75);
+    assert_eq!(read64(&mut clint, MTIME), 0x1_ffff_fff5);
+    // A byte at a time
+    assert_eq!(clint.read(MTIME + 1, 1) & 0xff, 0xff);
+    assert_eq!(clint.read(MTIME + 4, 1) & 0xff, 1);
+}
+
+#[test]
+fn mtimecmp_raises_the_timer_interrupt() {
+    let (mut clint, mut mem) = (Clint::new(1), Memory::new());
+    // Quiet until armed
+    assert_eq!(read64(&mut clint, MTIMECMP), u64::MAX);
+
+    clint.write(MTIMECMP + 4, 4, 0);
+    clint.write(MTIMECMP, 4, 100);
+    assert_eq!(read64(&mut clint, MTIMECMP), 100);
+    assert_eq!(clint.tick(&mut mem, 99), 0);
+    assert_eq!(clint.tick(&mut mem, 100), TIMER);
+    assert_eq!(clint.tick(&mut mem, 1000), TIMER);
+
+    // Pushing the deadline back clears it, byte by byte too
+    clint.write(MTIMECMP + 1, 1, 0x10);
+    assert_eq!(read64(&mut clint, MTIMECMP), 0x1064);
+    assert_eq!(clint.tick(&mut mem, 1000), 0);
+    clint.write(MSIP, 4, 1);
+    assert_eq!(clint.tick(&mut mem, 0x1064), SOFTWARE | TIMER);
+}
+
+#[test]
+fn guest_takes_the_timer_interrupt() {
+    let program = [
+        0x0200_42b7, // lui t0, 0x2004
+        0x0320_0313, // li t1, 50
+        0x0062_a023, // sw t1, 0(t0)
+        0x0002_a223, // sw zero, 4(t0)
+        0x0000_006f, // j .
+    ];
+    let mut machine = common::machine(&program);
+    machine
+        .mem
+        .mmio
+        .attach(CLINT_BASE, Box::new(Clint::new(1)))
+        .unwrap();
+    let handler = 0x2000;
+    machine.mem.copy_to(handler, &[EBREAK]).unwrap();
+    machine.hart.set_csr(CSR_MTVEC, handler);
+    machine.hart.set_csr(CSR_MIE, TIMER);
+    machine.hart.set_csr(CSR_MSTATUS, MSTATUS_MIE);
+
+    machine.run().unwrap();
+    assert_eq!(machine.hart.pc, handler);
+    assert_eq!(
+        machine.hart.csr(CSR_MCAUSE),
+        MCAUSE_INTERRUPT | Interrupt::MachineTimer as u32
+    );
+    assert_eq!(machine.hart.inst_count, 50);
+}
@@ -0,0 +1,138 @@
+//! Guests run together in a cluster, talking over channels, through their
+//! lifecycle.
+
+mod common;
+
+use std::convert::Infallible;
+
+use riscv_vm::{
+    channel::CHANNEL_BASE,
+    cluster::{Cluster, GuestStatus, CHANNEL_STRIDE},
+    error::{ClusterError, MachineError},
+    hart::Hart32,
+    machine::{Kernel, Machine, StepResult},
+    memory::Memory,
+    riscv_inst::Reg,
+};
+
+/// Sends the word 42 on the channel at `CHANNEL_BASE`.
+const SENDER: [u32; 7] = [
+    0x1000_22b7, // lui t0, 0x10002
+    0x1000_43b7, // lui t2, 0x10004
+    0x02a0_0313, // li t1, 42
+    0x0063_a023, // sw t1, 0(t2)
+    0x0040_0313, // li t1, 4
+    0x0062_a423, // sw t1, 8(t0)
+    0x0000_0073, // ecall
+];
+
+/// Waits for a message on the channel at `CHANNEL_BASE`, reading its first
+/// word into `a0`.
+const RECEIVER: [u32; 7] = [
+    0x1000_22b7, // lui t0, 0x10002
+    0x0002_a303, // loop: lw t1, 0(t0)
+    0x0013_7313, // andi t1, t1, 1
+    0xfe03_0ce3, // beqz t1, loop
+    0x1000_33b7, // lui t2, 0x10003
+    0x0003_a503, // lw a0, 0(t2)
+    0x0000_0073, // ecall
+];
+
+/// Halts on any `ecall`.
+struct HaltKernel;
+
+impl Kernel for HaltKernel {
+    type Error = Infallible;
+
+    fn syscall(
+        &mut self,
+        _hart: &mut Hart32,
+        _mem: &mut Memory,
+    ) -> Result<StepResult, MachineError<Infallible>> {
+        Ok(StepResult::Halt)
+    }
+}
+
+fn machine(program: &[u32]) -> Machine<HaltKernel> {
+    common::load(Machine::new(HaltKernel), program)
+}
+
+#[test]
+fn guests_talk_over_channels() {
+    let mut cluster = Cluster::new(4);
+    let receiver = cluster.spawn("receiver", machine(&RECEIVER));
+    let sender = cluster.spawn("sender", machine(&SENDER));
+    assert_eq!(
+        cluster.connect(receiver, sender, 1).unwrap(),
+        (CHANNEL_BASE, CHANNEL_BASE)
+    );
+    cluster.run();
+
+    for id in [receiver, sender] {
+        assert_eq!(cluster.inspect(id).unwrap().status, GuestStatus::Halted);
+    }
+    assert_eq!(cluster.machine(receiver).unwrap().hart.get_reg(Reg::A0), 42);
+    // The receiver spun in the first round, before the sender sent
+    assert!(cluster.rounds() > 2);
+    assert_eq!(cluster.find("sender"), Some(sender));
+
+    // Another channel goes after the first
+    let (base, _) = cluster.open_channel(sender, 1).unwrap();
+    assert_eq!(base, CHANNEL_BASE + CHANNEL_STRIDE);
+    assert_eq!(cluster.inspect(sender).unwrap().channels, 2);
+}
+
+#[test]
+fn guests_pause_resume_and_die() {
+    let mut cluster = Cluster::new(10);
+    let waiter = cluster.spawn("waiter", machine(&RECEIVER));
+    let (_, host) = cluster.open_channel(waiter, 1).unwrap();
+
+    // Paused, it isn't scheduled
+    assert!(cluster.pause(waiter));
+    assert!(!cluster.run_round());
+    let info = cluster.inspect(waiter).unwrap();
+    assert_eq!((info.status, info.instructions), (GuestStatus::Paused, 0));
+
+    // Resumed, it spins until the host sends something
+    assert!(cluster.resume(waiter));
+    assert!(cluster.run_round());
+    assert!(cluster.run_round());
+    let info = cluster.inspect(waiter).unwrap();
+    assert_eq!(
+        (info.status, info.instructions),
+        (GuestStatus::Runnable, 20)
+    );
+    host.try_send(7u32.to_le_bytes().to_vec()).unwrap();
+    cluster.run();
+    assert_eq!(cluster.machine(waiter).unwrap().hart.get_reg(Reg::A0), 7);
+
+    // A failing guest stops alone, keeping its error
+    let broken = cluster.spawn("broken", machine(&[0]));
+    let spinner = cluster.spawn("spinner", machine(&RECEIVER));
+    assert!(cluster.run_round());
+    assert_eq!(cluster.inspect(broken).unwrap().status, GuestStatus::Failed);
+    assert!(cluster.take_error(broken).is_some());
+
+    // Killed, it's gone, and its channels close
+    let (_, host) = cluster.open_channel(spinner, 1).unwrap();
+    let killed = cluster.kill(spinner).unwrap();
+    assert_eq!(killed.hart.inst_count, 10);
+    assert!(cluster.inspect(spinner).is_none());
+    drop(killed);
+    assert!(host.is_peer_closed());
+    let err = cluster.connect(waiter, spinner, 1).unwrap_err();
+    assert!(matches!(err, ClusterError::NoSuchGuest(_)));
+    assert_eq!(err.code(), 600);
+    assert_eq!(cluster.guests().collect::<Vec<_>>(), [waiter, broken]);
+}
+
+#[test]
+fn images_are_shared() {
+    let mut cluster = Cluster::<HaltKernel>::new(1);
+    cluster.add_image("rootfs", b"image".to_vec());
+    let a = cluster.image("rootfs").unwrap();
+    let b = cluster.image("rootfs").unwrap();
+    assert!(std::sync::Arc::ptr_eq(&a, &b));
+    assert!(cluster.image("missing").is_none());
+}
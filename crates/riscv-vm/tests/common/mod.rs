@@ -0,0 +1,45 @@
+//! A do-nothing kernel and hand-assembled programs loaded at a fixed
+//! address, shared by the machine tests.
+// Each test crate uses a different part of this
+#![allow(dead_code)]
+
+use std::convert::Infallible;
+
+use riscv_vm::{
+    error::MachineError,
+    hart::Hart32,
+    machine::{Kernel, Machine, StepResult},
+    memory::Memory,
+};
+
+/// Where programs are loaded.
+pub const CODE: u32 = 0x1000;
+
+pub const EBREAK: u32 = 0x0010_0073;
+
+/// Returns from every syscall without touching anything.
+pub struct NopKernel;
+
+impl Kernel for NopKernel {
+    type Error = Infallible;
+
+    fn syscall(
+        &mut self,
+        _hart: &mut Hart32,
+        _mem: &mut Memory,
+    ) -> Result<StepResult, MachineError<Infallible>> {
+        Ok(StepResult::Ok)
+    }
+}
+
+/// A [`NopKernel`] machine about to run `program` from [`CODE`].
+pub fn machine<T>(program: &[T]) -> Machine<NopKernel> {
+    load(Machine::new(NopKernel), program)
+}
+
+/// Load `program` into `machine` at [`CODE`] and point the hart at it.
+pub fn load<K: Kernel, T>(mut machine: Machine<K>, program: &[T]) -> Machine<K> {
+    machine.mem.copy_to(CODE, program).unwrap();
+    machine.hart.pc = CODE;
+    machine
+}
@@ -0,0 +1,104 @@
+//! Reserved and hint encodings of the C extension. Reserved encodings are
+//! illegal instructions, hints execute as no-ops.
+
+mod common;
+
+use std::convert::Infallible;
+
+use common::{NopKernel, CODE};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    machine::Machine,
+    riscv_inst::Reg,
+};
+
+const C_EBREAK: u16 = 0x9002;
+
+/// Run the compressed instruction `inst`, then a `c.ebreak`.
+fn run(inst: u16) -> (Machine<NopKernel>, Result<(), MachineError<Infallible>>) {
+    let mut machine = common::machine(&[inst, C_EBREAK]);
+    machine.hart.set_reg(Reg::Sp, 0x8000);
+    machine.hart.set_reg(Reg::A0, 0x1234);
+    let res = machine.run();
+    (machine, res)
+}
+
+fn assert_illegal(inst: u16) {
+    let (machine, res) = run(inst);
+    match res {
+        Err(MachineError::Hart(HartError::IllegalInst { addr, .. })) => assert_eq!(addr, CODE),
+        res => panic!("{inst:#06x} should be illegal, got {res:?}"),
+    }
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0x1234);
+    assert_eq!(machine.hart.get_reg(Reg::Sp), 0x8000);
+}
+
+fn assert_hint(inst: u16) {
+    let (machine, res) = run(inst);
+    res.unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0x1234);
+    assert_eq!(machine.hart.get_reg(Reg::Sp), 0x8000);
+}
+
+#[test]
+fn c_addi4spn_zero_immediate_is_reserved() {
+    // c.addi4spn a0, sp, 0
+    assert_illegal(0x0008);
+}
+
+#[test]
+fn c_addi16sp_zero_immediate_is_reserved() {
+    // c.addi16sp sp, 0
+    assert_illegal(0x6101);
+}
+
+#[test]
+fn c_lui_zero_immediate_is_reserved() {
+    // c.lui a0, 0
+    assert_illegal(0x6501);
+}
+
+#[test]
+fn c_lui_to_x0_is_a_hint() {
+    // c.lui x0, 1
+    assert_hint(0x6005);
+}
+
+#[test]
+fn c_jr_x0_is_reserved() {
+    // c.jr x0
+    assert_illegal(0x8002);
+}
+
+#[test]
+fn c_lwsp_to_x0_is_reserved() {
+    // c.lwsp x0, 0(sp)
+    assert_illegal(0x4002);
+}
+
+#[test]
+fn rv64_shift_amounts_are_reserved() {
+    // c.slli, c.srli and c.srai a0 by 32
+    for inst in [0x1502, 0x9101, 0x9501] {
+        assert_illegal(inst);
+    }
+}
+
+#[test]
+fn c_slli_by_zero_is_a_hint() {
+    // c.slli a0, 0
+    assert_hint(0x0502);
+}
+
+#[test]
+fn valid_neighbors_still_execute() {
+    // c.addi16sp sp, 16
+    let (machine, res) = run(0x6141);
+    res.unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::Sp), 0x8010);
+
+    // c.lui a0, 1
+    let (machine, res) = run(0x6505);
+    res.unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0x1000);
+}
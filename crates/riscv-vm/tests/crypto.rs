@@ -0,0 +1,145 @@
+//! Scalar crypto instructions, checked against FIPS-197 and FIPS-180-4
+//! values, and only executed by harts with their extension.
+
+mod common;
+
+use common::{CODE, EBREAK};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    riscv_inst::{Extension, Extensions, Reg},
+};
+
+const AES32ESMI_0: u32 = 0x26b5_0533; // aes32esmi a0, a0, a1, 0
+const AES32ESMI_1: u32 = 0x66c5_0533; // aes32esmi a0, a0, a2, 1
+const AES32ESMI_2: u32 = 0xa6d5_0533; // aes32esmi a0, a0, a3, 2
+const AES32ESMI_3: u32 = 0xe6e5_0533; // aes32esmi a0, a0, a4, 3
+const AES32ESI_2: u32 = 0xa2b5_0533; // aes32esi a0, a0, a1, 2
+const AES32DSI_2: u32 = 0xaab5_0533; // aes32dsi a0, a0, a1, 2
+const AES32DSMI_1: u32 = 0x6eb5_0533; // aes32dsmi a0, a0, a1, 1
+const SHA256SUM0: u32 = 0x1005_9513; // sha256sum0 a0, a1
+const SHA256SUM1: u32 = 0x1015_9513; // sha256sum1 a0, a1
+const SHA256SIG0: u32 = 0x1025_9513; // sha256sig0 a0, a1
+const SHA256SIG1: u32 = 0x1035_9513; // sha256sig1 a0, a1
+const SHA512SIG0H: u32 = 0x5cc5_8533; // sha512sig0h a0, a1, a2
+const SHA512SIG0L: u32 = 0x54c5_8533; // sha512sig0l a0, a1, a2
+const SHA512SIG1H: u32 = 0x5ec5_8533; // sha512sig1h a0, a1, a2
+const SHA512SIG1L: u32 = 0x56c5_8533; // sha512sig1l a0, a1, a2
+const SHA512SUM0R: u32 = 0x50c5_8533; // sha512sum0r a0, a1, a2
+const SHA512SUM1R: u32 = 0x52c5_8533; // sha512sum1r a0, a1, a2
+
+fn zkn() -> Extensions {
+    Extensions::RV32IMASC
+        .with(Extension::Zkne)
+        .with(Extension::Zknd)
+        .with(Extension::Zknh)
+}
+
+/// Run `program` to an `ebreak` with `regs` set, returning `a0`.
+fn run(program: &[u32], regs: &[(Reg, u32)]) -> u32 {
+    let mut machine = common::machine(&[program, &[EBREAK]].concat());
+    machine.hart.extensions = zkn();
+    for &(reg, value) in regs {
+        machine.hart.set_reg(reg, value);
+    }
+    machine.run().unwrap();
+    machine.hart.get_reg(Reg::A0)
+}
+
+/// Little-endian columns of an AES state.
+fn columns(state: [u8; 16]) -> [u32; 4] {
+    std::array::from_fn(|i| u32::from_le_bytes(state[i * 4..][..4].try_into().unwrap()))
+}
+
+#[test]
+fn aes_rounds_match_fips_197() {
+    // Round 1 of the FIPS-197 appendix B example: the state at its start,
+    // the round key, and the state at the start of round 2
+    let state = columns([
+        0x19, 0x3d, 0xe3, 0xbe, 0xa0, 0xf4, 0xe2, 0x2b, 0x9a, 0xc6, 0x8d, 0x2a, 0xe9, 0xf8, 0x48,
+        0x08,
+    ]);
+    let key = columns([
+        0xa0, 0xfa, 0xfe, 0x17, 0x88, 0x54, 0x2c, 0xb1, 0x23, 0xa3, 0x39, 0x39, 0x2a, 0x6c, 0x76,
+        0x05,
+    ]);
+    let next = columns([
+        0xa4, 0x9c, 0x7f, 0xf2, 0x68, 0x9f, 0x35, 0x2b, 0x6b, 0x5b, 0xea, 0x43, 0x02, 0x6a, 0x50,
+        0x49,
+    ]);
+
+    let round = [AES32ESMI_0, AES32ESMI_1, AES32ESMI_2, AES32ESMI_3];
+    for col in 0..4 {
+        // ShiftRows takes byte n of each column from the column n to the right
+        let regs = [
+            (Reg::A0, key[col]),
+            (Reg::A1, state[col]),
+            (Reg::A2, state[(col + 1) % 4]),
+            (Reg::A3, state[(col + 2) % 4]),
+            (Reg::A4, state[(col + 3) % 4]),
+        ];
+        assert_eq!(run(&round, &regs), next[col], "column {col}");
+    }
+
+    // The S-box and its inverse, on byte 2
+    for byte in 0..=255u32 {
+        let regs = [(Reg::A0, 0), (Reg::A1, byte << 16)];
+        let sub = run(&[AES32ESI_2], &regs);
+        assert_eq!(sub & !0x00ff_0000, 0);
+        let regs = [(Reg::A0, 0), (Reg::A1, sub)];
+        assert_eq!(run(&[AES32DSI_2], &regs), byte << 16);
+    }
+    // S(1) = 0x7c, so 0x7c inverts to 1, which InvMixColumns spreads as
+    // {0e, 09, 0d, 0b}, rotated into byte 1
+    let regs = [(Reg::A0, 0), (Reg::A1, 0x7c00)];
+    assert_eq!(run(&[AES32DSMI_1], &regs), 0x0d09_0e0b);
+}
+
+#[test]
+fn sha2_functions_match_fips_180_4() {
+    // Σ0(a) and Σ1(e) of SHA-256's initial hash value
+    assert_eq!(run(&[SHA256SUM0], &[(Reg::A1, 0x6a09_e667)]), 0xce20_b47e);
+    assert_eq!(run(&[SHA256SUM1], &[(Reg::A1, 0x510e_527f)]), 0x3587_272b);
+    let x = 0x1234_5678u32;
+    let sig0 = x.rotate_right(7) ^ x.rotate_right(18) ^ (x >> 3);
+    let sig1 = x.rotate_right(17) ^ x.rotate_right(19) ^ (x >> 10);
+    assert_eq!(run(&[SHA256SIG0], &[(Reg::A1, x)]), sig0);
+    assert_eq!(run(&[SHA256SIG1], &[(Reg::A1, x)]), sig1);
+
+    // Each half of the 64-bit functions
+    let x = 0x0123_4567_89ab_cdefu64;
+    let (hi, lo) = ((x >> 32) as u32, x as u32);
+    let sig0 = x.rotate_right(1) ^ x.rotate_right(8) ^ (x >> 7);
+    let sig1 = x.rotate_right(19) ^ x.rotate_right(61) ^ (x >> 6);
+    let sum0 = x.rotate_right(28) ^ x.rotate_right(34) ^ x.rotate_right(39);
+    let sum1 = x.rotate_right(14) ^ x.rotate_right(18) ^ x.rotate_right(41);
+    let high = [(Reg::A1, hi), (Reg::A2, lo)];
+    let low = [(Reg::A1, lo), (Reg::A2, hi)];
+    for (inst, regs, expected) in [
+        (SHA512SIG0H, high, sig0 >> 32),
+        (SHA512SIG0L, low, sig0),
+        (SHA512SIG1H, high, sig1 >> 32),
+        (SHA512SIG1L, low, sig1),
+        (SHA512SUM0R, high, sum0 >> 32),
+        (SHA512SUM0R, low, sum0),
+        (SHA512SUM1R, high, sum1 >> 32),
+        (SHA512SUM1R, low, sum1),
+    ] {
+        assert_eq!(run(&[inst], &regs), expected as u32, "{inst:#010x}");
+    }
+}
+
+#[test]
+fn crypto_needs_its_extension() {
+    let mut machine = common::machine(&[AES32ESI_2, SHA256SIG0]);
+    machine.hart.extensions = zkn().without(Extension::Zknh);
+    machine.step().unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::Hart(HartError::IllegalInst { addr, .. })) if addr == CODE + 4
+    ));
+
+    assert_eq!(
+        "rv32imac_zicsr_zkne_zknh".parse::<Extensions>().unwrap(),
+        zkn().without(Extension::S).without(Extension::Zknd)
+    );
+}
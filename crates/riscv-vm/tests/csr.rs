@@ -0,0 +1,133 @@
+//! CSR instruction semantics: which forms write, what `rd` gets, and which
+//! bits writes can't change, and CSR names.
+
+mod common;
+
+use common::{NopKernel, EBREAK};
+use riscv_vm::{
+    csr::{csr_name, Csr, CsrOp},
+    hart::Hart32,
+    machine::Machine,
+    riscv_inst::Reg,
+    trap::Interrupt,
+};
+
+const CSRW_MSCRATCH: u32 = 0x3402_9073; // csrrw x0, mscratch, t0
+const CSRRW_MSCRATCH_SWAP: u32 = 0x3402_92f3; // csrrw t0, mscratch, t0
+const CSRR_MSCRATCH: u32 = 0x3400_2573; // csrrs a0, mscratch, x0
+const CSRRS_MSCRATCH: u32 = 0x3402_a573; // csrrs a0, mscratch, t0
+const CSRRC_MSCRATCH: u32 = 0x3402_b573; // csrrc a0, mscratch, t0
+const CSRRSI_MSCRATCH_0: u32 = 0x3400_6573; // csrrsi a0, mscratch, 0
+const CSRRWI_MSCRATCH_5: u32 = 0x3402_d573; // csrrwi a0, mscratch, 5
+const CSRW_MIP: u32 = 0x3442_9073; // csrrw x0, mip, t0
+const CSRW_CYCLE: u32 = 0xc002_9073; // csrrw x0, cycle, t0
+const CSRW_MCYCLE: u32 = 0xb002_9073; // csrrw x0, mcycle, t0
+const CSRR_CYCLE: u32 = 0xc000_2573; // csrrs a0, cycle, x0
+const CSRW_MCOUNTINHIBIT: u32 = 0x3202_9073; // csrrw x0, mcountinhibit, t0
+const CSRR_MCOUNTINHIBIT: u32 = 0x3200_2573; // csrrs a0, mcountinhibit, x0
+
+/// Run `program` up to an `ebreak`, with `mscratch` and `t0` preset.
+fn run(program: &[u32], mscratch: u32, t0: u32) -> Machine<NopKernel> {
+    let mut machine = common::machine(&[program, &[EBREAK]].concat());
+    machine.hart.set_csr(Csr::Mscratch, mscratch);
+    machine.hart.set_reg(Reg::T0, t0);
+    machine.run().unwrap();
+    machine
+}
+
+#[test]
+fn csrrw_to_x0_still_writes() {
+    let machine = run(&[CSRW_MSCRATCH], 1, 0x55);
+    assert_eq!(machine.hart.csr(Csr::Mscratch), 0x55);
+}
+
+#[test]
+fn csrrw_reads_rs1_before_writing_rd() {
+    let machine = run(&[CSRRW_MSCRATCH_SWAP], 1, 2);
+    assert_eq!(machine.hart.csr(Csr::Mscratch), 2);
+    assert_eq!(machine.hart.get_reg(Reg::T0), 1);
+}
+
+#[test]
+fn csrrs_sets_and_returns_old_value() {
+    let machine = run(&[CSRRS_MSCRATCH], 0b0011, 0b0110);
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0b0011);
+    assert_eq!(machine.hart.csr(Csr::Mscratch), 0b0111);
+}
+
+#[test]
+fn csrrc_clears_and_returns_old_value() {
+    let machine = run(&[CSRRC_MSCRATCH], 0b0011, 0b0110);
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0b0011);
+    assert_eq!(machine.hart.csr(Csr::Mscratch), 0b0001);
+}
+
+#[test]
+fn csrrs_from_x0_only_reads() {
+    let machine = run(&[CSRR_MSCRATCH, CSRRSI_MSCRATCH_0], 0x1234, 0);
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0x1234);
+    assert_eq!(machine.hart.csr(Csr::Mscratch), 0x1234);
+}
+
+#[test]
+fn csrrwi_writes_immediate() {
+    let machine = run(&[CSRRWI_MSCRATCH_5], 9, 0);
+    assert_eq!(machine.hart.get_reg(Reg::A0), 9);
+    assert_eq!(machine.hart.csr(Csr::Mscratch), 5);
+}
+
+#[test]
+fn read_only_forms_skip_the_write() {
+    let mut hart = Hart32::new();
+    hart.set_csr(Csr::Mscratch, 0xf0);
+    for op in [CsrOp::Write, CsrOp::Set, CsrOp::Clear] {
+        assert_eq!(hart.csr_instruction(Csr::Mscratch, op, 0x0f, false), 0xf0);
+        assert_eq!(hart.csr(Csr::Mscratch), 0xf0);
+    }
+}
+
+#[test]
+fn mip_device_lines_are_read_only() {
+    let machine = run(&[CSRW_MIP], 0, u32::MAX);
+    let mip = machine.hart.csr(Csr::Mip);
+    assert_eq!(mip & Interrupt::LINES, 0);
+    assert_eq!(mip & !Interrupt::LINES, !Interrupt::LINES);
+}
+
+#[test]
+fn user_counters_ignore_writes() {
+    let machine = run(&[CSRW_CYCLE, CSRR_CYCLE], 0, 1_000_000);
+    assert!(machine.hart.get_reg(Reg::A0) < 10);
+}
+
+#[test]
+fn machine_counters_take_writes() {
+    let machine = run(&[CSRW_MCYCLE, CSRR_CYCLE], 0, 1_000_000);
+    let cycle = machine.hart.get_reg(Reg::A0);
+    assert!((1_000_000..1_000_010).contains(&cycle), "{cycle}");
+}
+
+#[test]
+fn time_cant_be_inhibited() {
+    let machine = run(&[CSRW_MCOUNTINHIBIT, CSRR_MCOUNTINHIBIT], 0, u32::MAX);
+    assert_eq!(machine.hart.get_reg(Reg::A0), !0b10);
+}
+
+#[test]
+fn counter_reads_through_csr_instruction() {
+    let mut hart = Hart32::new();
+    hart.inst_count = 42;
+    assert_eq!(hart.csr_instruction(Csr::Mcycle, CsrOp::Set, 0, false), 42);
+}
+
+#[test]
+fn csrs_have_assembler_names() {
+    assert_eq!(Csr::Mstatus.to_string(), "mstatus");
+    assert_eq!("mepc".parse(), Ok(Csr::Mepc));
+    assert!("mepcc".parse::<Csr>().is_err());
+    assert_eq!(Csr::from_number(0xC01), Some(Csr::Time));
+    assert_eq!(csr_name(0x340), "mscratch");
+    assert_eq!(csr_name(0xB03), "mhpmcounter3");
+    assert_eq!(csr_name(0xC9F), "hpmcounter31h");
+    assert_eq!(csr_name(0x7C0), "0x7c0");
+}
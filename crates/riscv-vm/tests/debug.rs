@@ -0,0 +1,102 @@
+//! Breaking at guest functions, stepping over and out of calls, and calling
+//! into the guest from the host.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    debug::DebugStop,
+    machine::{Machine, MachineConfig, MachineState},
+};
+
+const NOP: u32 = 0x0000_0013;
+const RET: u32 = 0x0000_8067; // jalr zero, 0(ra)
+
+const F: u32 = CODE + 0x10;
+
+/// `jal ra` from `CODE + from` to `CODE + to`.
+fn call(from: u32, to: u32) -> u32 {
+    let offset = to.wrapping_sub(from);
+    0xef | (offset & 0x7fe) << 20 | (offset & 0x800) << 9 | (offset & 0xff000)
+}
+
+/// `main` calls `f` twice, then halts.
+fn machine(shadow_stack: bool) -> Machine<NopKernel> {
+    let config = MachineConfig {
+        shadow_stack,
+        ..Default::default()
+    };
+    let program = [call(0, 0x10), call(4, 0x10), EBREAK, NOP, NOP, NOP, RET];
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &program);
+    machine.symbols.insert("main", CODE, 0x10);
+    machine.symbols.insert("f", F, 0xc);
+    machine
+}
+
+#[test]
+fn breakpoint_stops_before_function_and_finish_returns() {
+    let mut machine = machine(true);
+    assert_eq!(machine.break_at("f"), Some(F));
+    assert_eq!(machine.break_at("g"), None);
+
+    machine.run().unwrap();
+    assert_eq!(machine.state, MachineState::Breakpoint);
+    assert_eq!((machine.hart.pc, machine.hart.inst_count), (F, 1));
+
+    assert_eq!(machine.finish().unwrap().unwrap(), DebugStop::Reached);
+    assert_eq!((machine.hart.pc, machine.hart.inst_count), (CODE + 4, 4));
+
+    // The second call stops too, and resuming runs the breakpointed instruction
+    machine.run().unwrap();
+    assert_eq!((machine.hart.pc, machine.hart.inst_count), (F, 5));
+    assert!(machine.remove_breakpoint(F));
+    machine.resume();
+    machine.run().unwrap();
+    assert_eq!(machine.state, MachineState::Halted);
+    assert_eq!(machine.hart.inst_count, 8);
+}
+
+#[test]
+fn next_steps_over_calls() {
+    let mut machine = machine(true);
+    assert_eq!(machine.next().unwrap().unwrap(), DebugStop::Reached);
+    assert_eq!((machine.hart.pc, machine.hart.inst_count), (CODE + 4, 4));
+
+    // A breakpoint in the callee still stops it
+    machine.break_at("f");
+    assert_eq!(machine.next().unwrap().unwrap(), DebugStop::Breakpoint);
+    assert_eq!(machine.hart.pc, F);
+    assert_eq!(machine.next().unwrap().unwrap(), DebugStop::Reached);
+    assert_eq!(machine.hart.pc, F + 4);
+
+    assert_eq!(machine.finish().unwrap().unwrap(), DebugStop::Reached);
+    assert_eq!(machine.next().unwrap().unwrap(), DebugStop::Halted);
+}
+
+#[test]
+fn stepping_out_needs_the_shadow_stack() {
+    let mut machine = machine(false);
+    assert!(machine.next().is_none());
+    assert!(machine.finish().is_none());
+    // Nothing to finish before the first call
+    assert!(self::machine(true).finish().is_none());
+}
+
+#[test]
+fn host_calls_keep_the_shadow_stack_balanced() {
+    let mut machine = machine(true);
+    machine.break_at("f");
+    machine.run().unwrap();
+    assert!(machine.remove_breakpoint(F));
+    machine.resume();
+
+    // Called by the host from the middle of `f`, `f` returns to the host
+    // rather than to `main`
+    assert_eq!(machine.call(F, &[7]).unwrap(), 7);
+    let frames = machine.hart.shadow_stack.as_ref().unwrap().frames();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].ret, CODE + 4);
+
+    assert_eq!(machine.finish().unwrap().unwrap(), DebugStop::Reached);
+    assert_eq!(machine.hart.pc, CODE + 4);
+}
@@ -0,0 +1,94 @@
+//! The debug console: output and stopping the machine from a guest with no
+//! kernel behind it.
+
+mod common;
+
+use std::{cell::RefCell, convert::Infallible, io::Write, rc::Rc};
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    debugcon::{DebugConsole, DEBUG_CONSOLE_BASE},
+    error::MachineError,
+    machine::{Machine, MachineState},
+    riscv_inst::Reg,
+};
+
+const SB_PUTCHAR: u32 = 0x00b2_8023; // sb a1, 0(t0)
+const SW_PUT_U32: u32 = 0x00a2_a223; // sw a0, 4(t0)
+const SW_EXIT: u32 = 0x00c2_a423; // sw a2, 8(t0)
+const SW_ASSERT: u32 = 0x00d2_a623; // sw a3, 12(t0)
+const NOP: u32 = 0x0000_0013;
+
+const MESSAGE: u32 = 0x8000;
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run `program`, then a few `nop`s and an `ebreak`, with `t0` pointing at
+/// the console.
+fn run(
+    program: &[u32],
+) -> (
+    Machine<NopKernel>,
+    Result<(), MachineError<Infallible>>,
+    String,
+) {
+    let out = Output::default();
+    let mut machine = common::machine(&[program, &[NOP, NOP, EBREAK]].concat());
+    machine
+        .mem
+        .mmio
+        .attach(DEBUG_CONSOLE_BASE, Box::new(DebugConsole::new(out.clone())))
+        .unwrap();
+    machine.mem.write_cstr(MESSAGE, b"x == 1").unwrap();
+    machine.hart.set_reg(Reg::T0, DEBUG_CONSOLE_BASE);
+    machine.hart.set_reg(Reg::A0, 0xdead_beef);
+    machine.hart.set_reg(Reg::A1, b'!' as u32);
+    machine.hart.set_reg(Reg::A2, 3);
+    machine.hart.set_reg(Reg::A3, MESSAGE);
+
+    let res = machine.run();
+    let out = String::from_utf8(out.0.take()).unwrap();
+    (machine, res, out)
+}
+
+#[test]
+fn prints_bytes_and_words() {
+    let (machine, res, out) = run(&[SB_PUTCHAR, SW_PUT_U32, SB_PUTCHAR]);
+    res.unwrap();
+    assert_eq!(out, "!0xdeadbeef\n!");
+    assert_eq!(machine.exit_code, None);
+}
+
+#[test]
+fn exit_stops_before_the_next_instruction() {
+    let (machine, res, out) = run(&[SW_EXIT, SB_PUTCHAR]);
+    res.unwrap();
+    assert_eq!(machine.state, MachineState::Halted);
+    assert_eq!(machine.exit_code, Some(3));
+    assert_eq!(machine.hart.pc, CODE + 4);
+    assert_eq!(out, "");
+}
+
+#[test]
+fn assert_fail_reports_the_message() {
+    let (machine, res, _) = run(&[SW_ASSERT]);
+    match res {
+        Err(MachineError::GuestPanic(panic)) => {
+            assert_eq!(panic.symbol, "assert_fail");
+            assert_eq!(panic.message.as_deref(), Some("x == 1"));
+        }
+        res => panic!("expected an assertion failure, got {res:?}"),
+    }
+    assert_eq!(machine.exit_code, None);
+}
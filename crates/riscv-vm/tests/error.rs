@@ -0,0 +1,208 @@
+//! Error codes are stable: every kind of error keeps the number it was
+//! given, and no two kinds share one.
+
+use std::{collections::BTreeMap, convert::Infallible, io};
+
+use riscv_vm::{
+    error::{
+        AllocError, ClusterError, HartError, HibernateError, MachineError, MemoryAccess,
+        MemoryError,
+    },
+    panic::{GuestBacktrace, GuestPanic},
+    quota::Resource,
+    shadow::ShadowViolation,
+};
+
+fn io_error() -> io::Error {
+    io::Error::other("test")
+}
+
+fn hart_errors() -> Vec<(HartError, u16)> {
+    let violation = ShadowViolation {
+        pc: 0,
+        target: 0,
+        expected: 0,
+    };
+    vec![
+        (HartError::invalid(0, 0), 100),
+        (HartError::illegal(0, 0), 101),
+        (HartError::unimplemented(0, 0), 102),
+        (
+            HartError::ForbiddenInst {
+                addr: 0,
+                inst: 0,
+                mnemonic: "ecall",
+            },
+            103,
+        ),
+        (HartError::ShadowStack(violation), 104),
+    ]
+}
+
+fn memory_errors() -> Vec<(MemoryError, u16)> {
+    let access = MemoryAccess::Load;
+    let (addr, len) = (0, 0);
+    vec![
+        (
+            MemoryError::UnalignedMemoryAccess {
+                access,
+                addr,
+                required: 4,
+            },
+            200,
+        ),
+        (MemoryError::OutOfBoundsMemoryAccess { access, addr }, 201),
+        (MemoryError::OverflowMemoryAccess { access, addr, len }, 202),
+        (MemoryError::Unterminated { addr, max_len: 0 }, 203),
+        (MemoryError::MmioOverlap { addr, len }, 204),
+        (
+            MemoryError::Remap {
+                addr,
+                len,
+                source: io_error(),
+            },
+            205,
+        ),
+        (
+            MemoryError::Reserve {
+                len: 0,
+                source: io_error(),
+            },
+            206,
+        ),
+        (MemoryError::HugePages { source: io_error() }, 207),
+        (MemoryError::Protection { access, addr }, 208),
+        (MemoryError::WriteXorExecute { addr, len }, 209),
+        (MemoryError::Poisoned { access, addr }, 210),
+        (MemoryError::Residency { source: io_error() }, 211),
+    ]
+}
+
+fn alloc_errors() -> Vec<(AllocError, u16)> {
+    vec![
+        (AllocError::OutOfMemory { size: 0 }, 300),
+        (AllocError::InvalidAlignment { align: 3 }, 301),
+        (AllocError::InvalidFree { addr: 0 }, 302),
+        (AllocError::GuestMallocFailed { size: 0 }, 303),
+    ]
+}
+
+fn machine_errors() -> Vec<(MachineError<Infallible>, u16)> {
+    let panic = GuestPanic {
+        symbol: "abort",
+        message: None,
+        backtrace: GuestBacktrace::default(),
+    };
+    vec![
+        (MachineError::CallHalted { func: 0 }, 400),
+        (MachineError::GuestPanic(Box::new(panic)), 401),
+        (
+            MachineError::QuotaExceeded {
+                resource: Resource::Instructions,
+                limit: 0,
+                backtrace: GuestBacktrace::default(),
+            },
+            402,
+        ),
+        (MachineError::Unsupported("test"), 403),
+        (
+            MachineError::Unpatchable {
+                addr: 0,
+                reason: "test",
+            },
+            404,
+        ),
+        (MachineError::TooManyArgs { count: 9 }, 405),
+    ]
+}
+
+fn hibernate_errors() -> Vec<(HibernateError, u16)> {
+    let section = || "MEMORY".to_string();
+    vec![
+        (HibernateError::Io(io_error()), 500),
+        (HibernateError::BadMagic, 501),
+        (
+            HibernateError::TooNew {
+                version: 2,
+                compat: 2,
+            },
+            502,
+        ),
+        (HibernateError::MissingSection(section()), 503),
+        (HibernateError::UnknownSection(section()), 504),
+        (
+            HibernateError::Corrupt {
+                section: section(),
+                reason: "test".to_string(),
+            },
+            505,
+        ),
+        (HibernateError::KernelUnsupported, 506),
+        (HibernateError::Kernel("test".to_string()), 507),
+    ]
+}
+
+/// Every error's own code, by its message.
+fn codes() -> Vec<(u16, u16, String)> {
+    fn listed<E: std::fmt::Display>(
+        errors: Vec<(E, u16)>,
+        code: impl Fn(&E) -> u16,
+    ) -> Vec<(u16, u16, String)> {
+        let errors = errors.into_iter();
+        errors
+            .map(|(e, expected)| (code(&e), expected, e.to_string()))
+            .collect()
+    }
+    let mut codes = listed(hart_errors(), HartError::code);
+    codes.extend(listed(memory_errors(), MemoryError::code));
+    codes.extend(listed(alloc_errors(), AllocError::code));
+    codes.extend(listed(machine_errors(), MachineError::code));
+    codes.extend(listed(hibernate_errors(), HibernateError::code));
+    codes.extend(listed(
+        vec![(ClusterError::NoSuchGuest(1), 600)],
+        ClusterError::code,
+    ));
+    codes
+}
+
+#[test]
+fn codes_are_stable() {
+    for (code, expected, message) in codes() {
+        assert_eq!(code, expected, "{message}");
+    }
+}
+
+#[test]
+fn codes_are_unique() {
+    let mut seen = BTreeMap::new();
+    for (code, _, message) in codes() {
+        if let Some(other) = seen.insert(code, message.clone()) {
+            panic!("{message:?} and {other:?} are both {code}");
+        }
+    }
+    // The kernel's own errors are numbered from here
+    assert!(seen.range(900..).next().is_none());
+}
+
+#[test]
+fn wrapped_errors_keep_their_code() {
+    for (e, code) in hart_errors() {
+        assert_eq!(MachineError::<Infallible>::from(e).code(), code);
+    }
+    for (e, code) in memory_errors() {
+        assert_eq!(MachineError::<Infallible>::from(e).code(), code);
+    }
+    for (e, code) in alloc_errors() {
+        assert_eq!(MachineError::<Infallible>::from(e).code(), code);
+    }
+    let memory = || MemoryError::Unterminated {
+        addr: 0,
+        max_len: 0,
+    };
+    assert_eq!(AllocError::from(memory()).code(), 203);
+    assert_eq!(HibernateError::from(memory()).code(), 203);
+    assert_eq!(ClusterError::from(memory()).code(), 203);
+
+    let kernel = MachineError::Kernel(io_error());
+    assert_eq!(kernel.code(), 900);
+}
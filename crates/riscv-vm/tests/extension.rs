@@ -0,0 +1,88 @@
+//! A custom-0 instruction the decoder doesn't know, executed on the host
+//! through the hart's `HartExtension` hooks.
+
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use common::{CODE, EBREAK};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    hart::{Hart32, HartExtension},
+    machine::StepResult,
+    memory::Memory,
+    riscv_inst::Reg,
+};
+
+/// `mac rd, rs1, rs2`: `rd += rs1 * rs2`, as custom-0 with funct3 0.
+/// Other custom-0 encodings are left invalid.
+struct Mac {
+    executed: Rc<Cell<u32>>,
+}
+
+impl HartExtension for Mac {
+    fn on_unknown_instruction(
+        &mut self,
+        hart: &mut Hart32,
+        raw: u32,
+        pc: u32,
+        _mem: &mut Memory,
+    ) -> Result<Option<StepResult>, HartError> {
+        if raw & 0x7f != 0x0b || (raw >> 12) & 7 != 0 {
+            return Ok(None);
+        }
+        assert_eq!(pc, hart.pc);
+        let reg = |shift: u32| Reg::checked_from(((raw >> shift) & 0x1f) as u8).unwrap();
+        let (rd, rs1, rs2) = (reg(7), reg(15), reg(20));
+        let product = hart.get_reg(rs1).wrapping_mul(hart.get_reg(rs2));
+        hart.set_reg(rd, hart.get_reg(rd).wrapping_add(product));
+        self.executed.set(self.executed.get() + 1);
+        Ok(Some(StepResult::Ok))
+    }
+}
+
+const MAC_A2_A0_A1: u32 = 0x00b5_060b;
+/// funct3 1, which `Mac` doesn't take
+const UNTAKEN: u32 = 0x00b5_160b;
+
+#[test]
+fn custom_instruction_round_trips_through_the_hooks() {
+    let program = [
+        0x0060_0513, // li a0, 6
+        0x0070_0593, // li a1, 7
+        0x0010_0613, // li a2, 1
+        MAC_A2_A0_A1,
+        MAC_A2_A0_A1,
+        EBREAK,
+    ];
+    let executed = Rc::new(Cell::new(0));
+    let mut machine = common::machine(&program);
+    machine.hart.hooks = Some(Box::new(Mac {
+        executed: executed.clone(),
+    }));
+    machine.run().unwrap();
+
+    assert_eq!(machine.hart.get_reg(Reg::A2), 1 + 2 * 42);
+    assert_eq!(executed.get(), 2);
+    // Both retired, and execution carried on after each
+    assert_eq!(machine.hart.inst_count, 5);
+    assert_eq!(machine.hart.pc, CODE + 20);
+}
+
+#[test]
+fn declined_and_unhooked_instructions_are_invalid() {
+    for hooked in [true, false] {
+        let mut machine = common::machine(&[UNTAKEN, EBREAK]);
+        if hooked {
+            let executed = Rc::new(Cell::new(0));
+            machine.hart.hooks = Some(Box::new(Mac { executed }));
+        }
+        match machine.step() {
+            Err(MachineError::Hart(HartError::InvalidInst { addr, inst })) => {
+                assert_eq!((addr, inst), (CODE, UNTAKEN));
+            }
+            res => panic!("expected an invalid instruction, got {res:?}"),
+        }
+        assert_eq!(machine.hart.inst_count, 0);
+    }
+}
@@ -0,0 +1,80 @@
+//! Instructions from extensions the hart wasn't configured with raise an
+//! illegal instruction error instead of executing.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    machine::{Machine, MachineConfig},
+    riscv_inst::{Extension, Extensions, Reg},
+};
+
+const MUL: u32 = 0x02b5_0633; // mul a2, a0, a1
+const AMOADD: u32 = 0x00b4_252f; // amoadd.w a0, a1, (s0)
+const CSRR: u32 = 0x3400_2573; // csrr a0, mscratch
+const C_ADDI: u16 = 0x0505; // c.addi a0, 1
+
+/// A machine implementing `extensions`, about to run `program`.
+fn machine<T>(extensions: Extensions, program: &[T]) -> Machine<NopKernel> {
+    let config = MachineConfig {
+        extensions,
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), program);
+    machine.hart.set_reg(Reg::S0, 0x8000);
+    machine
+}
+
+fn assert_illegal(machine: &mut Machine<NopKernel>, inst: u32) {
+    match machine.step() {
+        Err(MachineError::Hart(HartError::IllegalInst { addr, inst: raw })) => {
+            assert_eq!((addr, raw), (CODE, inst));
+        }
+        res => panic!("expected {inst:#x} to be illegal, got {res:?}"),
+    }
+    // Nothing happened
+    assert_eq!(machine.hart.pc, CODE);
+    assert_eq!(machine.hart.inst_count, 0);
+}
+
+#[test]
+fn instructions_outside_the_extension_set_are_illegal() {
+    for (inst, ext) in [
+        (MUL, Extension::M),
+        (AMOADD, Extension::A),
+        (CSRR, Extension::Zicsr),
+    ] {
+        let without = Extensions::default().without(ext);
+        assert_illegal(&mut machine(without, &[inst, EBREAK]), inst);
+
+        let mut machine = machine(Extensions::default(), &[inst, EBREAK]);
+        machine.run().unwrap();
+        assert_eq!(machine.hart.inst_count, 1, "{ext:?}");
+    }
+
+    let rv32i = Extensions::default().without(Extension::C);
+    let mut compressed = machine(rv32i, &[C_ADDI, 0]);
+    assert_illegal(&mut compressed, C_ADDI as u32);
+}
+
+#[test]
+fn isa_strings_name_the_extension_set() {
+    let imac: Extensions = "rv32imac".parse().unwrap();
+    assert_eq!(imac, Extensions::RV32IMAC);
+    assert_eq!(imac.to_string(), "rv32imac");
+    assert!(!imac.contains(Extension::Zicsr));
+
+    let g: Extensions = "rv32gc_zknh".parse().unwrap();
+    for ext in [
+        Extension::F,
+        Extension::D,
+        Extension::Zicsr,
+        Extension::Zknh,
+    ] {
+        assert!(g.contains(ext), "{ext:?}");
+    }
+    assert_eq!(g.to_string().parse(), Ok(g));
+    assert!("rv64i".parse::<Extensions>().is_err());
+    assert!("rv32iy".parse::<Extensions>().is_err());
+}
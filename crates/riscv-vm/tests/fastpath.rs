@@ -0,0 +1,121 @@
+//! Running the guest's `memcpy`, `memmove` and `memset` on the host.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use common::{NopKernel, EBREAK};
+use riscv_vm::{
+    machine::{Machine, MachineState},
+    mmio::MmioDevice,
+    riscv_inst::Reg,
+};
+
+const MEMCPY: u32 = 0x2000;
+const MEMMOVE: u32 = 0x2100;
+const MEMSET: u32 = 0x2200;
+/// Holds an `ebreak` to return to
+const RET: u32 = 0x1000;
+const BUF: u32 = 0x8000;
+const DEVICE: u32 = 0xf000_0000;
+
+/// Reads back each byte's offset, and records byte writes.
+struct Recorder(Rc<RefCell<Vec<(u32, u8)>>>);
+
+impl MmioDevice for Recorder {
+    fn size(&self) -> u32 {
+        16
+    }
+
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        offset
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        self.0.borrow_mut().push((offset, value as u8));
+    }
+}
+
+/// A machine whose memory functions are all illegal instructions, so they
+/// fail unless they're replaced.
+fn machine() -> Machine<NopKernel> {
+    let mut machine = Machine::new(NopKernel);
+    machine.mem.store(RET, EBREAK);
+    machine.symbols.insert("memcpy", MEMCPY, 0x100);
+    machine.symbols.insert("memmove", MEMMOVE, 0x100);
+    machine.symbols.insert("memset", MEMSET, 0x100);
+    machine
+}
+
+fn call(machine: &mut Machine<NopKernel>, f: u32, args: [u32; 3]) -> u32 {
+    for (n, arg) in args.into_iter().enumerate() {
+        machine.hart.set_arg(n, arg);
+    }
+    machine.hart.set_reg(Reg::Ra, RET);
+    machine.hart.pc = f;
+    machine.state = MachineState::Running;
+    machine.run().unwrap();
+    machine.hart.get_reg(Reg::A0)
+}
+
+#[test]
+fn replaces_only_functions_the_guest_has() {
+    let mut machine = Machine::new(NopKernel);
+    machine.symbols.insert("memset", MEMSET, 0x100);
+    assert_eq!(machine.accelerate_mem_functions(), ["memset"]);
+}
+
+#[test]
+fn copies_and_sets_guest_memory() {
+    let mut machine = machine();
+    assert_eq!(
+        machine.accelerate_mem_functions(),
+        ["memcpy", "memmove", "memset"]
+    );
+    machine.mem.copy_to(BUF, b"hello world").unwrap();
+
+    assert_eq!(
+        call(&mut machine, MEMCPY, [BUF + 0x100, BUF, 11]),
+        BUF + 0x100
+    );
+    assert_eq!(
+        machine.mem.slice::<u8>(BUF + 0x100, 11).unwrap(),
+        b"hello world"
+    );
+
+    // Overlapping, in both directions
+    assert_eq!(call(&mut machine, MEMMOVE, [BUF + 2, BUF, 5]), BUF + 2);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 11).unwrap(), b"hehelloorld");
+    call(&mut machine, MEMMOVE, [BUF, BUF + 2, 5]);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 11).unwrap(), b"helloloorld");
+
+    assert_eq!(
+        call(&mut machine, MEMSET, [BUF + 4, b'!' as u32, 3]),
+        BUF + 4
+    );
+    assert_eq!(machine.mem.slice::<u8>(BUF, 11).unwrap(), b"hell!!!orld");
+    // Nothing retired but the `ebreak`s
+    assert_eq!(machine.hart.inst_count, 0);
+}
+
+#[test]
+fn devices_and_wrapping_go_a_byte_at_a_time() {
+    let writes = Rc::new(RefCell::new(vec![]));
+    let mut machine = machine();
+    machine
+        .mem
+        .mmio
+        .attach(DEVICE, Box::new(Recorder(writes.clone())))
+        .unwrap();
+    machine.accelerate_mem_functions();
+
+    call(&mut machine, MEMSET, [DEVICE + 2, 0xab, 2]);
+    assert_eq!(*writes.borrow(), [(2, 0xab), (3, 0xab)]);
+
+    call(&mut machine, MEMCPY, [BUF, DEVICE + 4, 3]);
+    assert_eq!(machine.mem.slice::<u8>(BUF, 3).unwrap(), [4, 5, 6]);
+
+    call(&mut machine, MEMSET, [u32::MAX - 1, 0xcd, 4]);
+    assert_eq!(machine.mem.load::<u8>(u32::MAX), 0xcd);
+    assert_eq!(machine.mem.load::<u16>(0), 0xcdcd);
+}
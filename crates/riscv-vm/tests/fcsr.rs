@@ -0,0 +1,526 @@
+//! `fcsr` fidelity: the exception flags and rounding of F and D results,
+//! checked against vectors from the `riscv-tests` rv32uf and rv32ud suites
+//! and cross-checked against the host's IEEE 754 arithmetic.
+
+// The vectors are written as the suites write them
+#![allow(clippy::excessive_precision, clippy::approx_constant)]
+
+mod common;
+
+use common::{NopKernel, CODE};
+use proptest::prelude::*;
+use riscv_vm::{
+    csr::Csr,
+    machine::Machine,
+    riscv_inst::{Extension, Extensions, FReg, Reg},
+};
+
+const FADD_S: u32 = 0x00b5_7553; // fadd.s fa0, fa0, fa1
+const FSUB_S: u32 = 0x08b5_7553; // fsub.s fa0, fa0, fa1
+const FMUL_S: u32 = 0x10b5_7553; // fmul.s fa0, fa0, fa1
+const FDIV_S: u32 = 0x18b5_7553; // fdiv.s fa0, fa0, fa1
+const FSQRT_S: u32 = 0x5805_7553; // fsqrt.s fa0, fa0
+const FMIN_S: u32 = 0x28b5_0553; // fmin.s fa0, fa0, fa1
+const FMAX_S: u32 = 0x28b5_1553; // fmax.s fa0, fa0, fa1
+const FMADD_S: u32 = 0x60b5_7543; // fmadd.s fa0, fa0, fa1, fa2
+const FMSUB_S: u32 = 0x60b5_7547; // fmsub.s fa0, fa0, fa1, fa2
+const FNMSUB_S: u32 = 0x60b5_754b; // fnmsub.s fa0, fa0, fa1, fa2
+const FNMADD_S: u32 = 0x60b5_754f; // fnmadd.s fa0, fa0, fa1, fa2
+const FEQ_S: u32 = 0xa0b5_2553; // feq.s a0, fa0, fa1
+const FLT_S: u32 = 0xa0b5_1553; // flt.s a0, fa0, fa1
+const FLE_S: u32 = 0xa0b5_0553; // fle.s a0, fa0, fa1
+const FCLASS_S: u32 = 0xe005_1553; // fclass.s a0, fa0
+const FCVT_W_S: u32 = 0xc005_1553; // fcvt.w.s a0, fa0, rtz
+const FCVT_WU_S: u32 = 0xc015_1553; // fcvt.wu.s a0, fa0, rtz
+const FCVT_S_W: u32 = 0xd005_7553; // fcvt.s.w fa0, a0
+const FADD_D: u32 = 0x02b5_7553; // fadd.d fa0, fa0, fa1
+const FSUB_D: u32 = 0x0ab5_7553; // fsub.d fa0, fa0, fa1
+const FMUL_D: u32 = 0x12b5_7553; // fmul.d fa0, fa0, fa1
+const FDIV_D: u32 = 0x1ab5_7553; // fdiv.d fa0, fa0, fa1
+const FSQRT_D: u32 = 0x5a05_7553; // fsqrt.d fa0, fa0
+const FMADD_D: u32 = 0x62b5_7543; // fmadd.d fa0, fa0, fa1, fa2
+const FEQ_D: u32 = 0xa2b5_2553; // feq.d a0, fa0, fa1
+const FLT_D: u32 = 0xa2b5_1553; // flt.d a0, fa0, fa1
+const FLE_D: u32 = 0xa2b5_0553; // fle.d a0, fa0, fa1
+const FCVT_W_D: u32 = 0xc205_1553; // fcvt.w.d a0, fa0, rtz
+const FCVT_WU_D: u32 = 0xc215_1553; // fcvt.wu.d a0, fa0, rtz
+const FCVT_D_W: u32 = 0xd205_0553; // fcvt.d.w fa0, a0
+const FCVT_S_D: u32 = 0x4015_7553; // fcvt.s.d fa0, fa0
+const FCVT_D_S: u32 = 0x4205_0553; // fcvt.d.s fa0, fa0
+
+const RTZ: u32 = 1;
+const RDN: u32 = 2;
+const RUP: u32 = 3;
+
+const NX: u32 = 1;
+const UF: u32 = 2;
+const OF: u32 = 4;
+const DZ: u32 = 8;
+const NV: u32 = 16;
+
+const CANONICAL_S: u64 = 0xffff_ffff_7fc0_0000;
+const CANONICAL_D: u64 = 0x7ff8_0000_0000_0000;
+const SNAN_S: f32 = f32::from_bits(0x7f80_0001);
+const SNAN_D: f64 = f64::from_bits(0x7ff0_0000_0000_0001);
+
+/// A hart that runs one instruction at a time on `fa0`..`fa2` and `a0`.
+struct Fpu(Machine<NopKernel>);
+
+/// What an instruction left behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Out {
+    fa0: u64,
+    a0: u32,
+    flags: u32,
+}
+
+impl Fpu {
+    fn new() -> Self {
+        let mut machine = Machine::new(NopKernel);
+        machine.hart.extensions = Extensions::RV32IMASC.with(Extension::F).with(Extension::D);
+        Self(machine)
+    }
+
+    /// Run `inst` with clear flags, `operands` in `fa0`..`fa2` and `a0`.
+    fn run(&mut self, inst: u32, operands: [u64; 3], a0: u32) -> Out {
+        let machine = &mut self.0;
+        machine.mem.copy_to(CODE, &[inst]).unwrap();
+        machine.hart.pc = CODE;
+        machine.hart.set_csr(Csr::Fcsr, 0);
+        for (r, bits) in [FReg::FA0, FReg::FA1, FReg::FA2].into_iter().zip(operands) {
+            machine.hart.set_freg(r, bits);
+        }
+        machine.hart.set_reg(Reg::A0, a0);
+        machine.step().unwrap();
+        Out {
+            fa0: machine.hart.get_freg(FReg::FA0),
+            a0: machine.hart.get_reg(Reg::A0),
+            flags: machine.hart.csr(Csr::Fflags),
+        }
+    }
+
+    fn s(&mut self, inst: u32, a: f32, b: f32, c: f32) -> Out {
+        self.run(inst, [a, b, c].map(boxed), 0)
+    }
+
+    fn d(&mut self, inst: u32, a: f64, b: f64, c: f64) -> Out {
+        self.run(inst, [a, b, c].map(f64::to_bits), 0)
+    }
+}
+
+/// Set an instruction's static rounding mode.
+const fn with_rm(inst: u32, rm: u32) -> u32 {
+    inst & !0x7000 | rm << 12
+}
+
+const fn boxed(f: f32) -> u64 {
+    f.to_bits() as u64 | 0xffff_ffff_0000_0000
+}
+
+#[test]
+fn single_arithmetic_matches_riscv_tests() {
+    let mut fpu = Fpu::new();
+    for (inst, flags, expected, a, b, c) in [
+        // fadd.S
+        (FADD_S, 0, 3.5, 2.5, 1.0, 0.0),
+        (FADD_S, NX, -1234.0, -1235.1, 1.1, 0.0),
+        (FADD_S, NX, 3.14159265, 3.14159265, 0.00000001, 0.0),
+        (FSUB_S, 0, 1.5, 2.5, 1.0, 0.0),
+        (FSUB_S, NX, -1234.0, -1235.1, -1.1, 0.0),
+        (FSUB_S, NX, 3.14159265, 3.14159265, 0.00000001, 0.0),
+        (FMUL_S, 0, 2.5, 2.5, 1.0, 0.0),
+        (FMUL_S, NX, 1358.61, -1235.1, -1.1, 0.0),
+        (FMUL_S, NX, 3.14159265e-8, 3.14159265, 0.00000001, 0.0),
+        // fdiv.S
+        (FDIV_S, NX, 1.1557273520668288, 3.14159265, 2.71828182, 0.0),
+        (FDIV_S, NX, -0.9991093838555584, -1234.0, 1235.1, 0.0),
+        (FDIV_S, 0, 3.14159265, 3.14159265, 1.0, 0.0),
+        (FSQRT_S, NX, 1.7724538498928541, 3.14159265, 0.0, 0.0),
+        (FSQRT_S, 0, 100.0, 10000.0, 0.0, 0.0),
+        (FSQRT_S, NX, 13.076696, 171.0, 0.0, 0.0),
+        // fmadd.S
+        (FMADD_S, 0, 3.5, 1.0, 2.5, 1.0),
+        (FMADD_S, NX, 1236.2, -1.0, -1235.1, 1.1),
+        (FMADD_S, 0, -12.0, 2.0, -5.0, -2.0),
+        (FNMADD_S, 0, -3.5, 1.0, 2.5, 1.0),
+        (FNMADD_S, NX, -1236.2, -1.0, -1235.1, 1.1),
+        (FNMADD_S, 0, 12.0, 2.0, -5.0, -2.0),
+        (FMSUB_S, 0, 1.5, 1.0, 2.5, 1.0),
+        (FMSUB_S, NX, 1234.0, -1.0, -1235.1, 1.1),
+        (FMSUB_S, 0, -8.0, 2.0, -5.0, -2.0),
+        (FNMSUB_S, 0, -1.5, 1.0, 2.5, 1.0),
+        (FNMSUB_S, NX, -1234.0, -1.0, -1235.1, 1.1),
+        (FNMSUB_S, 0, 8.0, 2.0, -5.0, -2.0),
+        // fmin.S
+        (FMIN_S, 0, 1.0, 2.5, 1.0, 0.0),
+        (FMIN_S, 0, -1235.1, -1235.1, 1.1, 0.0),
+        (FMIN_S, 0, -1.0, -1.0, f32::NAN, 0.0),
+        (FMIN_S, NV, 1.0, SNAN_S, 1.0, 0.0),
+        (FMIN_S, 0, -0.0, -0.0, 0.0, 0.0),
+        (FMAX_S, 0, 0.0, -0.0, 0.0, 0.0),
+        (FMAX_S, 0, 2.5, 2.5, 1.0, 0.0),
+        (FMAX_S, 0, 1.1, -1235.1, 1.1, 0.0),
+    ] {
+        let out = fpu.s(inst, a, b, c);
+        assert_eq!(
+            (f32::from_bits(out.fa0 as u32), out.flags),
+            (expected, flags),
+            "{inst:#010x} on {a}, {b}, {c}"
+        );
+    }
+}
+
+#[test]
+fn double_arithmetic_matches_riscv_tests() {
+    let mut fpu = Fpu::new();
+    for (inst, flags, expected, a, b, c) in [
+        // fadd.S
+        (FADD_D, 0, 3.5, 2.5, 1.0, 0.0),
+        (FADD_D, NX, -1234.0, -1235.1, 1.1, 0.0),
+        (FADD_D, NX, 3.14159266, 3.14159265, 0.00000001, 0.0),
+        (FSUB_D, 0, 1.5, 2.5, 1.0, 0.0),
+        (FSUB_D, NX, -1234.0, -1235.1, -1.1, 0.0),
+        (FSUB_D, NX, 3.1415926400000003, 3.14159265, 0.00000001, 0.0),
+        (FMUL_D, 0, 2.5, 2.5, 1.0, 0.0),
+        (FMUL_D, NX, 1358.61, -1235.1, -1.1, 0.0),
+        (FMUL_D, NX, 3.14159265e-8, 3.14159265, 0.00000001, 0.0),
+        // fdiv.S
+        (FDIV_D, NX, 1.1557273520668288, 3.14159265, 2.71828182, 0.0),
+        (FDIV_D, NX, -0.9991093838555584, -1234.0, 1235.1, 0.0),
+        (FDIV_D, 0, 3.14159265, 3.14159265, 1.0, 0.0),
+        (FSQRT_D, NX, 1.7724538498928541, 3.14159265, 0.0, 0.0),
+        (FSQRT_D, 0, 100.0, 10000.0, 0.0, 0.0),
+        (FSQRT_D, NX, 13.076696830622021, 171.0, 0.0, 0.0),
+        // fmadd.S
+        (FMADD_D, 0, 3.5, 1.0, 2.5, 1.0),
+        (FMADD_D, NX, 1236.1999999999999, -1.0, -1235.1, 1.1),
+        (FMADD_D, 0, -12.0, 2.0, -5.0, -2.0),
+    ] {
+        let out = fpu.d(inst, a, b, c);
+        assert_eq!(
+            (f64::from_bits(out.fa0), out.flags),
+            (expected, flags),
+            "{inst:#010x} on {a}, {b}, {c}"
+        );
+    }
+}
+
+#[test]
+fn invalid_operations_give_the_canonical_nan() {
+    let mut fpu = Fpu::new();
+    let inf = f32::INFINITY;
+    for (inst, a, b, c) in [
+        (FSUB_S, inf, inf, 0.0),
+        (FADD_S, inf, -inf, 0.0),
+        (FMUL_S, inf, 0.0, 0.0),
+        (FDIV_S, 0.0, 0.0, 0.0),
+        (FSQRT_S, -1.0, 0.0, 0.0),
+        (FADD_S, SNAN_S, 1.0, 0.0),
+        // Even with a quiet NaN addend, inf * 0 is invalid
+        (FMADD_S, inf, 0.0, f32::NAN),
+    ] {
+        let out = fpu.s(inst, a, b, c);
+        assert_eq!(
+            (out.fa0, out.flags),
+            (CANONICAL_S, NV),
+            "{inst:#010x} on {a}, {b}, {c}"
+        );
+    }
+    // Quiet NaNs propagate quietly, as the canonical NaN
+    let out = fpu.s(FADD_S, f32::from_bits(0x7fc1_2345), 1.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (CANONICAL_S, 0));
+    let out = fpu.s(FMIN_S, f32::NAN, f32::NAN, 0.0);
+    assert_eq!((out.fa0, out.flags), (CANONICAL_S, 0));
+
+    for (inst, a, b) in [
+        (FSUB_D, f64::INFINITY, f64::INFINITY),
+        (FDIV_D, f64::INFINITY, f64::INFINITY),
+        (FSQRT_D, -1.0, 0.0),
+        (FMUL_D, SNAN_D, 1.0),
+    ] {
+        let out = fpu.d(inst, a, b, 0.0);
+        assert_eq!(
+            (out.fa0, out.flags),
+            (CANONICAL_D, NV),
+            "{inst:#010x} on {a}, {b}"
+        );
+    }
+
+    // Narrowing and widening keep no NaN payload
+    let out = fpu.d(FCVT_S_D, SNAN_D, 0.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (CANONICAL_S, NV));
+    let out = fpu.s(FCVT_D_S, f32::NAN, 0.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (CANONICAL_D, 0));
+}
+
+#[test]
+fn divide_by_zero_overflow_and_underflow() {
+    let mut fpu = Fpu::new();
+    let out = fpu.s(FDIV_S, -1.0, 0.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (boxed(f32::NEG_INFINITY), DZ));
+    let out = fpu.d(FDIV_D, 1.0, -0.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (f64::NEG_INFINITY.to_bits(), DZ));
+    // Infinity over zero is exact, not a division by zero
+    let out = fpu.s(FDIV_S, f32::INFINITY, 0.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (boxed(f32::INFINITY), 0));
+
+    // Overflow rounds to infinity or the largest finite value by mode
+    for (rm, a, expected) in [
+        (0, f32::MAX, f32::INFINITY),
+        (RTZ, f32::MAX, f32::MAX),
+        (RDN, f32::MAX, f32::MAX),
+        (RUP, f32::MAX, f32::INFINITY),
+        (RUP, -f32::MAX, -f32::MAX),
+        (RDN, -f32::MAX, f32::NEG_INFINITY),
+    ] {
+        let out = fpu.s(with_rm(FMUL_S, rm), a, 2.0, 0.0);
+        assert_eq!(
+            (out.fa0, out.flags),
+            (boxed(expected), OF | NX),
+            "rm {rm} on {a}"
+        );
+    }
+    let out = fpu.d(FMUL_D, f64::MAX, f64::MAX, 0.0);
+    assert_eq!((out.fa0, out.flags), (f64::INFINITY.to_bits(), OF | NX));
+
+    // An exact subnormal result doesn't underflow
+    let min = f32::MIN_POSITIVE;
+    let out = fpu.s(FMUL_S, min, 0.5, 0.0);
+    assert_eq!((out.fa0, out.flags), (boxed(min / 2.0), 0));
+    // An inexact one does, even if it rounds up to the smallest normal
+    let out = fpu.s(FMUL_S, min, 1.0 - f32::EPSILON / 2.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (boxed(min), UF | NX));
+    // Tininess is detected after rounding, so a result that would round to
+    // the smallest normal with an unbounded exponent isn't tiny
+    let out = fpu.s(FMADD_S, -(2f32.powi(-76)), 2f32.powi(-75), min);
+    assert_eq!((out.fa0, out.flags), (boxed(min), NX));
+    let out = fpu.d(FCVT_S_D, 1e-40, 0.0, 0.0);
+    assert_eq!((out.fa0, out.flags), (boxed(1e-40), UF | NX));
+}
+
+#[test]
+fn conversions_match_riscv_tests() {
+    let mut fpu = Fpu::new();
+    let signed = [
+        (NX, -1, -1.1),
+        (0, -1, -1.0),
+        (NX, 0, -0.9),
+        (NX, 0, 0.9),
+        (0, 1, 1.0),
+        (NX, 1, 1.1),
+        (NV, i32::MIN, -3e9),
+        (NV, i32::MAX, 3e9),
+        (NV, i32::MAX, f64::NAN),
+        (NV, i32::MAX, f64::INFINITY),
+        (NV, i32::MIN, f64::NEG_INFINITY),
+    ];
+    let unsigned = [
+        (NV, 0, -3.0),
+        (NV, 0, -1.0),
+        (NX, 0, -0.9),
+        (NX, 0, 0.9),
+        (0, 1, 1.0),
+        (NX, 1, 1.1),
+        (NV, 0, -3e9),
+        (0, 3_000_000_000, 3e9),
+        (NV, u32::MAX, f64::NAN),
+        (NV, u32::MAX, f64::INFINITY),
+        (NV, 0, f64::NEG_INFINITY),
+    ];
+    for (flags, expected, a) in signed {
+        let out = fpu.s(FCVT_W_S, a as f32, 0.0, 0.0);
+        assert_eq!(
+            (out.a0 as i32, out.flags),
+            (expected, flags),
+            "fcvt.w.s {a}"
+        );
+        let out = fpu.d(FCVT_W_D, a, 0.0, 0.0);
+        assert_eq!(
+            (out.a0 as i32, out.flags),
+            (expected, flags),
+            "fcvt.w.d {a}"
+        );
+    }
+    for (flags, expected, a) in unsigned {
+        let out = fpu.s(FCVT_WU_S, a as f32, 0.0, 0.0);
+        assert_eq!((out.a0, out.flags), (expected, flags), "fcvt.wu.s {a}");
+        let out = fpu.d(FCVT_WU_D, a, 0.0, 0.0);
+        assert_eq!((out.a0, out.flags), (expected, flags), "fcvt.wu.d {a}");
+    }
+
+    // Integers beyond 24 bits round into a single, but always fit a double
+    let out = fpu.run(FCVT_S_W, [0; 3], 0x0100_0001);
+    assert_eq!((out.fa0, out.flags), (boxed(16_777_216.0), NX));
+    let out = fpu.run(FCVT_D_W, [0; 3], -7i32 as u32);
+    assert_eq!((out.fa0, out.flags), ((-7f64).to_bits(), 0));
+}
+
+#[test]
+fn comparisons_and_classes_match_riscv_tests() {
+    let mut fpu = Fpu::new();
+    for (inst, flags, expected, a, b) in [
+        (FEQ_S, 0, 1, -1.36, -1.36),
+        (FLE_S, 0, 1, -1.36, -1.36),
+        (FLT_S, 0, 0, -1.36, -1.36),
+        (FEQ_S, 0, 0, -1.37, -1.36),
+        (FLE_S, 0, 1, -1.37, -1.36),
+        (FLT_S, 0, 1, -1.37, -1.36),
+        (FEQ_S, 0, 1, -0.0, 0.0),
+        // feq is quiet; flt and fle signal on any NaN
+        (FEQ_S, 0, 0, f32::NAN, 0.0),
+        (FEQ_S, 0, 0, f32::NAN, f32::NAN),
+        (FEQ_S, NV, 0, SNAN_S, 0.0),
+        (FLT_S, NV, 0, f32::NAN, 0.0),
+        (FLE_S, NV, 0, f32::NAN, 0.0),
+    ] {
+        let out = fpu.s(inst, a, b, 0.0);
+        assert_eq!(
+            (out.a0, out.flags),
+            (expected, flags),
+            "{inst:#010x} on {a}, {b}"
+        );
+    }
+    for (inst, flags, expected, a, b) in [
+        (FEQ_D, 0, 1, -1.36, -1.36),
+        (FLT_D, 0, 1, -1.37, -1.36),
+        (FEQ_D, NV, 0, SNAN_D, 0.0),
+        (FLE_D, NV, 0, f64::NAN, 0.0),
+    ] {
+        let out = fpu.d(inst, a, b, 0.0);
+        assert_eq!(
+            (out.a0, out.flags),
+            (expected, flags),
+            "{inst:#010x} on {a}, {b}"
+        );
+    }
+
+    for (class, bits) in [
+        (0, 0xff80_0000),
+        (1, 0xbf80_0000),
+        (2, 0x807f_ffff),
+        (3, 0x8000_0000),
+        (4, 0x0000_0000),
+        (5, 0x007f_ffff),
+        (6, 0x3f80_0000),
+        (7, 0x7f80_0000),
+        (8, 0x7f80_0001),
+        (9, 0x7fc0_0000),
+    ] {
+        let out = fpu.s(FCLASS_S, f32::from_bits(bits), 0.0, 0.0);
+        assert_eq!(
+            (out.a0, out.flags),
+            (1 << class, 0),
+            "fclass.s {bits:#010x}"
+        );
+    }
+}
+
+#[test]
+fn fcsr_fields_alias_fflags_and_frm() {
+    let mut fpu = Fpu::new();
+    let hart = &mut fpu.0.hart;
+    hart.set_csr(Csr::Fcsr, u32::MAX);
+    assert_eq!(hart.csr(Csr::Fcsr), 0xff);
+    assert_eq!(hart.csr(Csr::Fflags), 0x1f);
+    assert_eq!(hart.csr(Csr::Frm), 7);
+    hart.set_csr(Csr::Fflags, NV | NX);
+    hart.set_csr(Csr::Frm, RDN);
+    assert_eq!(hart.csr(Csr::Fcsr), RDN << 5 | NV | NX);
+}
+
+/// Whether the hart's result is the host's, or both are NaN and the hart's is
+/// the canonical one.
+fn same_s(out: Out, host: f32) -> bool {
+    if host.is_nan() {
+        out.fa0 == CANONICAL_S
+    } else {
+        out.fa0 == boxed(host)
+    }
+}
+
+fn same_d(out: Out, host: f64) -> bool {
+    if host.is_nan() {
+        out.fa0 == CANONICAL_D
+    } else {
+        out.fa0 == host.to_bits()
+    }
+}
+
+/// Singles weighted towards the special and boundary values.
+fn single() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        3 => any::<u32>().prop_map(f32::from_bits),
+        1 => prop::sample::select(vec![
+            0.0,
+            -0.0,
+            1.0,
+            f32::MIN_POSITIVE,
+            f32::MAX,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+            f32::from_bits(1),
+        ]),
+    ]
+}
+
+fn double() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        3 => any::<u64>().prop_map(f64::from_bits),
+        1 => prop::sample::select(vec![
+            0.0,
+            -0.0,
+            1.0,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+            f64::from_bits(1),
+        ]),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(5_000))]
+
+    #[test]
+    fn singles_round_to_nearest_like_the_host(a in single(), b in single(), c in single()) {
+        let mut fpu = Fpu::new();
+        prop_assert!(same_s(fpu.s(FADD_S, a, b, c), a + b));
+        prop_assert!(same_s(fpu.s(FSUB_S, a, b, c), a - b));
+        prop_assert!(same_s(fpu.s(FMUL_S, a, b, c), a * b));
+        prop_assert!(same_s(fpu.s(FDIV_S, a, b, c), a / b));
+        prop_assert!(same_s(fpu.s(FSQRT_S, a, b, c), a.sqrt()));
+        prop_assert!(same_s(fpu.s(FMADD_S, a, b, c), a.mul_add(b, c)));
+        prop_assert!(same_d(fpu.s(FCVT_D_S, a, b, c), a as f64));
+        prop_assert_eq!(fpu.s(FLT_S, a, b, c).a0, (a < b) as u32);
+        prop_assert_eq!(fpu.s(FEQ_S, a, b, c).a0, (a == b) as u32);
+    }
+
+    #[test]
+    fn doubles_round_to_nearest_like_the_host(a in double(), b in double(), c in double()) {
+        let mut fpu = Fpu::new();
+        prop_assert!(same_d(fpu.d(FADD_D, a, b, c), a + b));
+        prop_assert!(same_d(fpu.d(FSUB_D, a, b, c), a - b));
+        prop_assert!(same_d(fpu.d(FMUL_D, a, b, c), a * b));
+        prop_assert!(same_d(fpu.d(FDIV_D, a, b, c), a / b));
+        prop_assert!(same_d(fpu.d(FSQRT_D, a, b, c), a.sqrt()));
+        prop_assert!(same_d(fpu.d(FMADD_D, a, b, c), a.mul_add(b, c)));
+        prop_assert!(same_s(fpu.d(FCVT_S_D, a, b, c), a as f32));
+        prop_assert_eq!(fpu.d(FLE_D, a, b, c).a0, (a <= b) as u32);
+        prop_assert_eq!(fpu.d(FEQ_D, a, b, c).a0, (a == b) as u32);
+    }
+
+    #[test]
+    fn conversions_truncate_like_the_host(a in double(), n in any::<i32>()) {
+        let mut fpu = Fpu::new();
+        // Rust's casts saturate and truncate, as RISC-V does outside NaN
+        prop_assume!(!a.is_nan());
+        prop_assert_eq!(fpu.d(FCVT_W_D, a, 0.0, 0.0).a0, a as i32 as u32);
+        prop_assert_eq!(fpu.d(FCVT_WU_D, a, 0.0, 0.0).a0, a as u32);
+        prop_assert_eq!(fpu.s(FCVT_W_S, a as f32, 0.0, 0.0).a0, a as f32 as i32 as u32);
+        prop_assert!(same_s(fpu.run(FCVT_S_W, [0; 3], n as u32), n as f32));
+        prop_assert!(same_d(fpu.run(FCVT_D_W, [0; 3], n as u32), n as f64));
+    }
+}
@@ -0,0 +1,257 @@
+//! F and D execution in software: NaN-boxing, `fcsr` flags and rounding
+//! modes, bit-exact regardless of the host's FPU.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    csr::Csr,
+    error::{HartError, MachineError},
+    machine::Machine,
+    mmio::MmioDevice,
+    riscv_inst::{Extension, Extensions, FReg, Reg},
+};
+
+const FADD_S: u32 = 0x00b5_7553; // fadd.s fa0, fa0, fa1
+const FDIV_S: u32 = 0x18b5_7553; // fdiv.s fa0, fa0, fa1
+const FSQRT_S: u32 = 0x5805_7553; // fsqrt.s fa0, fa0
+const FCVT_W_S: u32 = 0xc005_7553; // fcvt.w.s a0, fa0
+const FCVT_S_D: u32 = 0x4015_7553; // fcvt.s.d fa0, fa0
+const FMV_X_W: u32 = 0xe005_0553; // fmv.x.w a0, fa0
+const FCLASS_S: u32 = 0xe005_1553; // fclass.s a0, fa0
+const FMADD_D: u32 = 0x62b5_7543; // fmadd.d fa0, fa0, fa1, fa2
+const FLD: u32 = 0x0002_b587; // fld fa1, 0(t0)
+const FSD: u32 = 0x00b2_b427; // fsd fa1, 8(t0)
+const FRFLAGS: u32 = 0x0010_2573; // frflags a0
+const FSRM: u32 = 0x0025_1073; // fsrm a0
+
+const DATA: u32 = 0x8000;
+
+const RNE: u32 = 0;
+const RTZ: u32 = 1;
+const RDN: u32 = 2;
+const RUP: u32 = 3;
+const RMM: u32 = 4;
+
+const NX: u32 = 1;
+const OF: u32 = 4;
+const DZ: u32 = 8;
+const NV: u32 = 16;
+
+/// Set an instruction's static rounding mode.
+const fn with_rm(inst: u32, rm: u32) -> u32 {
+    inst & !0x7000 | rm << 12
+}
+
+const fn boxed(f: f32) -> u64 {
+    f.to_bits() as u64 | 0xffff_ffff_0000_0000
+}
+
+fn new_machine(program: &[u32]) -> Machine<NopKernel> {
+    let mut machine = common::machine(&[program, &[EBREAK]].concat());
+    machine.hart.extensions = Extensions::RV32IMASC.with(Extension::F).with(Extension::D);
+    machine
+}
+
+/// Run `inst` on `fa0` and `fa1`, returning `fa0` and the flags raised.
+fn run_s(inst: u32, a: f32, b: f32) -> (u64, u32) {
+    let mut machine = new_machine(&[inst]);
+    machine.hart.set_freg(FReg::FA0, boxed(a));
+    machine.hart.set_freg(FReg::FA1, boxed(b));
+    machine.run().unwrap();
+    (
+        machine.hart.get_freg(FReg::FA0),
+        machine.hart.csr(Csr::Fflags),
+    )
+}
+
+#[test]
+fn singles_are_nan_boxed() {
+    assert_eq!(run_s(FADD_S, 1.0, 2.0), (boxed(3.0), 0));
+
+    // An improperly boxed operand reads as the canonical NaN, quietly
+    let mut machine = new_machine(&[FADD_S, FMV_X_W]);
+    machine.hart.set_freg(FReg::FA0, 1f32.to_bits() as u64);
+    machine.hart.set_freg(FReg::FA1, boxed(1.0));
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_freg(FReg::FA0), 0xffff_ffff_7fc0_0000);
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0x7fc0_0000);
+    assert_eq!(machine.hart.csr(Csr::Fflags), 0);
+
+    // Narrowing a double boxes the single
+    let mut machine = new_machine(&[FCVT_S_D, FCLASS_S]);
+    machine.hart.set_freg(FReg::FA0, (-0.5f64).to_bits());
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_freg(FReg::FA0), boxed(-0.5));
+    // Negative normal
+    assert_eq!(machine.hart.get_reg(Reg::A0), 1 << 1);
+}
+
+#[test]
+fn flags_accumulate_in_fcsr() {
+    assert_eq!(run_s(FDIV_S, 1.0, 3.0), (boxed(1.0 / 3.0), NX));
+    assert_eq!(run_s(FDIV_S, 1.0, 0.0), (boxed(f32::INFINITY), DZ));
+    assert_eq!(run_s(FSQRT_S, -1.0, 0.0), (boxed(f32::NAN), NV));
+    assert_eq!(
+        run_s(FADD_S, f32::MAX, f32::MAX),
+        (boxed(f32::INFINITY), OF | NX)
+    );
+
+    // Flags stick until software clears them, and frflags reads them: -1/0
+    // divides by zero, then the square root of -inf is invalid
+    let mut machine = new_machine(&[FDIV_S, FSQRT_S, FRFLAGS]);
+    machine.hart.set_freg(FReg::FA0, boxed(-1.0));
+    machine.hart.set_freg(FReg::FA1, boxed(0.0));
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0), DZ | NV);
+    assert_eq!(machine.hart.csr(Csr::Fcsr), DZ | NV);
+}
+
+#[test]
+fn static_and_dynamic_rounding_modes() {
+    // Halfway between 1 and the next single up
+    let tie = f32::EPSILON / 2.0;
+    let up = 1.0 + f32::EPSILON;
+    for (rm, expected) in [(RNE, 1.0), (RTZ, 1.0), (RDN, 1.0), (RUP, up), (RMM, up)] {
+        assert_eq!(
+            run_s(with_rm(FADD_S, rm), 1.0, tie),
+            (boxed(expected), NX),
+            "rm {rm}"
+        );
+    }
+    for (rm, expected) in [(RTZ, -1), (RDN, -2), (RUP, -1), (RMM, -2), (RNE, -2)] {
+        let mut machine = new_machine(&[with_rm(FCVT_W_S, rm)]);
+        machine.hart.set_freg(FReg::FA0, boxed(-1.5));
+        machine.run().unwrap();
+        assert_eq!(machine.hart.get_reg(Reg::A0) as i32, expected, "rm {rm}");
+    }
+
+    // Out of range conversions saturate as invalid
+    let mut machine = new_machine(&[FCVT_W_S]);
+    machine.hart.set_freg(FReg::FA0, boxed(f32::NAN));
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0), i32::MAX as u32);
+    assert_eq!(machine.hart.csr(Csr::Fflags), NV);
+
+    // The dynamic mode comes from frm
+    let mut machine = new_machine(&[FSRM, FADD_S]);
+    machine.hart.set_reg(Reg::A0, RUP);
+    machine.hart.set_freg(FReg::FA0, boxed(1.0));
+    machine.hart.set_freg(FReg::FA1, boxed(tie));
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_freg(FReg::FA0), boxed(up));
+    assert_eq!(machine.hart.csr(Csr::Fcsr), RUP << 5 | NX);
+}
+
+#[test]
+fn reserved_rounding_modes_are_illegal() {
+    let mut machine = new_machine(&[with_rm(FADD_S, 5)]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::Hart(HartError::IllegalInst { .. }))
+    ));
+
+    // frm can hold a reserved mode, but dynamic rounding then traps
+    let mut machine = new_machine(&[FSRM, FADD_S]);
+    machine.hart.set_reg(Reg::A0, 6);
+    machine.step().unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::Hart(HartError::IllegalInst { addr, .. })) if addr == CODE + 4
+    ));
+}
+
+#[test]
+fn doubles_fuse_and_round_trip_memory() {
+    // Fused, the product's low bits survive the cancellation
+    let (a, b) = (1.0 + f64::EPSILON, 1.0 - f64::EPSILON);
+    let c = -1.0f64;
+    let mut machine = new_machine(&[FMADD_D, FLD, FSD]);
+    machine.hart.set_freg(FReg::FA0, a.to_bits());
+    machine.hart.set_freg(FReg::FA1, b.to_bits());
+    machine.hart.set_freg(FReg::FA2, c.to_bits());
+    machine.hart.set_reg(Reg::T0, DATA);
+    machine
+        .mem
+        .copy_to(DATA, &[0x89ab_cdefu32, 0x0123_4567])
+        .unwrap();
+    machine.run().unwrap();
+    assert_eq!(
+        f64::from_bits(machine.hart.get_freg(FReg::FA0)),
+        a.mul_add(b, c)
+    );
+    assert_eq!(machine.hart.get_freg(FReg::FA1), 0x0123_4567_89ab_cdef);
+    assert_eq!(machine.mem.load::<u32>(DATA + 8), 0x89ab_cdef);
+    assert_eq!(machine.mem.load::<u32>(DATA + 12), 0x0123_4567);
+}
+
+/// A device access: `'r'` or `'w'`, offset, width and value.
+type Access = (char, u32, u32, u32);
+
+/// Device registers that remember what was written, and log every access.
+#[derive(Default)]
+struct Registers {
+    words: [u32; 4],
+    log: Rc<RefCell<Vec<Access>>>,
+}
+
+impl MmioDevice for Registers {
+    fn size(&self) -> u32 {
+        16
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        let value = self.words[offset as usize / 4];
+        self.log.borrow_mut().push(('r', offset, width, value));
+        value
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        self.words[offset as usize / 4] = value;
+        self.log.borrow_mut().push(('w', offset, width, value));
+    }
+}
+
+#[test]
+fn doubles_reach_devices_as_two_words() {
+    let registers = Registers {
+        words: [0x89ab_cdef, 0x0123_4567, 0, 0],
+        ..Default::default()
+    };
+    let log = registers.log.clone();
+    let mut machine = new_machine(&[FLD, FSD]);
+    machine.mem.mmio.attach(DATA, Box::new(registers)).unwrap();
+    machine.hart.set_reg(Reg::T0, DATA);
+    machine.run().unwrap();
+
+    assert_eq!(machine.hart.get_freg(FReg::FA1), 0x0123_4567_89ab_cdef);
+    assert_eq!(
+        *log.borrow(),
+        [
+            ('r', 0, 4, 0x89ab_cdef),
+            ('r', 4, 4, 0x0123_4567),
+            ('w', 8, 4, 0x89ab_cdef),
+            ('w', 12, 4, 0x0123_4567),
+        ]
+    );
+}
+
+#[test]
+fn float_needs_its_extension() {
+    let mut machine = new_machine(&[FADD_S]);
+    machine.hart.extensions = Extensions::RV32IMASC;
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::Hart(HartError::IllegalInst { .. }))
+    ));
+
+    assert_eq!(
+        "rv32imafdc_zicsr".parse::<Extensions>().unwrap(),
+        Extensions::RV32IMASC
+            .without(Extension::S)
+            .with(Extension::F)
+            .with(Extension::D)
+    );
+}
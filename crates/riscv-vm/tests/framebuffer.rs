@@ -0,0 +1,52 @@
+//! The framebuffer: pixels the guest stores at `FRAMEBUFFER_BASE` read back
+//! row by row as RGB, and the sizes it can be made.
+
+mod common;
+
+use common::EBREAK;
+use riscv_vm::framebuffer::{Framebuffer, FRAMEBUFFER_BASE, FRAMEBUFFER_MAX_DIM};
+
+/// Paints pixel (1, 0) red and the last pixel of a 4x2 framebuffer blue.
+const PAINT: [u32; 6] = [
+    0xe000_02b7, // lui t0, 0xe0000
+    0x0ff0_0313, // li t1, 0xff
+    0x0062_a223, // sw t1, 4(t0)
+    0x00ff_0337, // lui t1, 0xff0
+    0x0062_ae23, // sw t1, 28(t0)
+    EBREAK,
+];
+
+#[test]
+fn guest_stores_are_pixels() {
+    let fb = Framebuffer::new(4, 2).unwrap();
+    let mut machine = common::machine(&PAINT);
+    machine.run().unwrap();
+
+    let pixels = fb.pixels(&machine.mem);
+    assert_eq!(pixels.len(), 4 * 2 * 4);
+    assert_eq!(pixels[4..8], [0xff, 0, 0, 0]);
+    let mut rgb = vec![];
+    fb.to_rgb32(&machine.mem, &mut rgb);
+    assert_eq!(rgb, [0, 0xff_0000, 0, 0, 0, 0, 0, 0x00_00ff]);
+
+    // Alpha doesn't show, and the next frame replaces the last
+    machine
+        .mem
+        .copy_to(FRAMEBUFFER_BASE, &[1u8, 2, 3, 4])
+        .unwrap();
+    fb.to_rgb32(&machine.mem, &mut rgb);
+    assert_eq!(rgb.len(), 8);
+    assert_eq!(rgb[0], 0x01_0203);
+}
+
+#[test]
+fn sizes_are_bounded() {
+    for (width, height) in [(0, 1), (1, 0), (FRAMEBUFFER_MAX_DIM + 1, 1)] {
+        assert_eq!(Framebuffer::new(width, height), None);
+    }
+    let largest = Framebuffer::new(FRAMEBUFFER_MAX_DIM, FRAMEBUFFER_MAX_DIM).unwrap();
+    // Within the address space
+    assert_eq!(largest.size(), 64 << 20);
+    assert!(FRAMEBUFFER_BASE.checked_add(largest.size()).is_some());
+    assert_eq!(Framebuffer::new(1, 1).unwrap().size(), 4);
+}
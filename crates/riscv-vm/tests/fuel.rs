@@ -0,0 +1,68 @@
+//! Where a guest stuck in a loop was when its instruction quota or a
+//! watchdog stopped it.
+
+mod common;
+
+use std::ops::ControlFlow;
+
+use common::{NopKernel, CODE};
+use riscv_vm::{
+    error::MachineError,
+    machine::{Machine, MachineConfig},
+    quota::{Quotas, Resource},
+    watchdog::{CancelToken, RunExit, YieldEvery},
+};
+
+const JAL_RA_8: u32 = 0x0080_00ef; // jal ra, 8
+const NOP: u32 = 0x0000_0013;
+const SPIN: u32 = 0x0000_006f; // j 0
+
+/// `main` calls `spin`, which never returns.
+fn machine(quotas: Quotas) -> Machine<NopKernel> {
+    let config = MachineConfig {
+        quotas,
+        ..Default::default()
+    };
+    let mut machine = common::load(
+        Machine::with_config(NopKernel, config),
+        &[JAL_RA_8, NOP, SPIN],
+    );
+    machine.symbols.insert("main", CODE, 8);
+    machine.symbols.insert("spin", CODE + 8, 4);
+    machine
+}
+
+#[test]
+fn instruction_quota_reports_where_the_guest_spun() {
+    let mut machine = machine(Quotas {
+        instructions: Some(100),
+        ..Default::default()
+    });
+    let backtrace = match machine.run() {
+        Err(MachineError::QuotaExceeded {
+            resource: Resource::Instructions,
+            limit: 100,
+            backtrace,
+        }) => backtrace,
+        res => panic!("expected the instruction quota, got {res:?}"),
+    };
+    assert_eq!(backtrace.0, ["spin (0x00001008)", "main+0x4 (0x00001004)"]);
+    assert_eq!(
+        backtrace.to_string(),
+        "Guest backtrace (innermost first):\n  #0 spin (0x00001008)\n  #1 main+0x4 (0x00001004)"
+    );
+}
+
+#[test]
+fn cancelled_run_can_be_symbolized() {
+    let mut machine = machine(Quotas::default());
+    let cancel = CancelToken::new();
+    let exit = machine
+        .run_with(YieldEvery::instructions(50), &cancel, |_| {
+            cancel.cancel();
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    assert_eq!(exit, RunExit::Cancelled);
+    assert_eq!(machine.backtrace().0[0], "spin (0x00001008)");
+}
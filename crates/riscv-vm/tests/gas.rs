@@ -0,0 +1,80 @@
+//! Attributing executed instructions to guest functions and their callers.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    gas::FunctionGas,
+    machine::{Machine, MachineConfig},
+};
+
+const NOP: u32 = 0x0000_0013;
+const RET: u32 = 0x0000_8067; // jalr zero, 0(ra)
+const RET_T0: u32 = 0x0002_8067; // jalr zero, 0(t0)
+
+/// `jal rd` from `CODE + from` to `CODE + to`, linking `ra` (1) or `t0` (5).
+fn call(rd: u32, from: u32, to: u32) -> u32 {
+    let offset = to.wrapping_sub(from);
+    0x6f | rd << 7 | (offset & 0x7fe) << 20 | (offset & 0x800) << 9 | (offset & 0xff000)
+}
+
+#[test]
+fn instructions_are_attributed_to_functions_and_callers() {
+    let config = MachineConfig {
+        gas_profile: true,
+        ..Default::default()
+    };
+    // main calls outer twice, which calls leaf once each time
+    let program = [
+        call(1, 0x0, 0x10),
+        call(1, 0x4, 0x10),
+        EBREAK,
+        NOP,
+        // outer
+        NOP,
+        call(5, 0x14, 0x20),
+        RET,
+        NOP,
+        // leaf, returning through t0 so outer keeps ra
+        NOP,
+        NOP,
+        RET_T0,
+    ];
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &program);
+    machine.symbols.insert("main", CODE, 0x10);
+    machine.symbols.insert("outer", CODE + 0x10, 0x10);
+    machine.symbols.insert("leaf", CODE + 0x20, 0xc);
+    machine.run().unwrap();
+
+    let profiler = machine.hart.gas_profiler.as_ref().unwrap();
+    assert_eq!(profiler.instructions(), 15);
+    let gas = |function: &str, own, total, calls| FunctionGas {
+        function: function.to_string(),
+        own,
+        total,
+        calls,
+    };
+    assert_eq!(
+        profiler.by_function(&machine.symbols),
+        [
+            // Ties go to the function with more under it
+            gas("outer", 6, 12, 2),
+            gas("leaf", 6, 6, 2),
+            gas("main", 3, 15, 0),
+        ]
+    );
+
+    let mut report = vec![];
+    profiler
+        .write_report(&mut report, &machine.symbols, 1)
+        .unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(
+        report.starts_with("Gas profile: 15 instructions\n"),
+        "{report}"
+    );
+    assert!(
+        report.trim_end().ends_with("80.00%          2  outer"),
+        "{report}"
+    );
+}
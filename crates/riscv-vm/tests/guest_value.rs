@@ -0,0 +1,88 @@
+//! Loading and storing `#[derive(GuestValue)]` structs: byte order, field
+//! offsets and padding.
+
+use riscv_vm::{
+    marshal::{GuestPtr, GuestValue},
+    memory::Memory,
+};
+
+const ADDR: u32 = 0x1000;
+
+#[derive(GuestValue, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+#[guest(size = 16)]
+struct Padded {
+    a: u8,
+    b: u64,
+}
+
+#[derive(GuestValue, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+#[guest(size = 32)]
+struct Nested {
+    inner: Padded,
+    words: [u16; 3],
+    ptr: GuestPtr<u32>,
+}
+
+#[derive(GuestValue, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+struct Fd(i32);
+
+#[test]
+fn fields_are_little_endian() {
+    let mut mem = Memory::new();
+    mem.store(
+        ADDR,
+        Padded {
+            a: 0x11,
+            b: 0x0102_0304_0506_0708,
+        },
+    );
+    assert_eq!(mem.load::<u8>(ADDR), 0x11);
+    assert_eq!(
+        mem.load::<[u8; 8]>(ADDR + 8),
+        [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+    );
+}
+
+#[test]
+fn padding_is_left_alone() {
+    let mut mem = Memory::new();
+    mem.store(ADDR, [0xffu8; 16]);
+    mem.store(ADDR, Padded::default());
+    assert_eq!(
+        mem.load::<[u8; 8]>(ADDR),
+        [0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+    );
+}
+
+#[test]
+fn nested_structs_round_trip() {
+    let mut mem = Memory::new();
+    let value = Nested {
+        inner: Padded { a: 1, b: 2 },
+        words: [3, 4, 5],
+        ptr: GuestPtr::new(0xdead_beef),
+    };
+    mem.store(ADDR, value);
+    assert_eq!(mem.load::<Nested>(ADDR), value);
+    assert_eq!(mem.load::<u16>(ADDR + 20), 5);
+    assert_eq!(mem.load::<u32>(ADDR + 24), 0xdead_beef);
+}
+
+#[test]
+fn tuple_structs_round_trip() {
+    let mut mem = Memory::new();
+    mem.store(ADDR, Fd(-1));
+    assert_eq!(mem.load::<u32>(ADDR), u32::MAX);
+    assert_eq!(mem.load::<Fd>(ADDR), Fd(-1));
+}
+
+#[test]
+fn checked_access_fails_past_the_end() {
+    let mut mem = Memory::new();
+    assert!(mem.write(u32::MAX - 4, Padded::default()).is_err());
+    assert!(mem.read::<Padded>(u32::MAX - 4).is_err());
+    assert!(mem.write(u32::MAX - 16, Padded::default()).is_ok());
+}
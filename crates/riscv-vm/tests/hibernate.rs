@@ -0,0 +1,172 @@
+//! A hibernated machine, restored into a fresh one, picks up exactly where
+//! it left off.
+
+mod common;
+
+use std::convert::Infallible;
+
+use riscv_vm::{
+    csr::{Csr, CsrOp},
+    error::{HibernateError, MachineError},
+    hart::{Hart32, HartContext},
+    hpm::HpmEvent,
+    machine::{Kernel, Machine, MachineConfig, StepResult},
+    memory::Memory,
+    protect::{ExecProtection, PagePerms},
+    riscv_inst::FReg,
+};
+
+const DATA: u32 = 0x8000;
+
+/// Counts to `DATA` forever, making a syscall each time.
+const COUNTER: [u32; 5] = [
+    0x0000_82b7, // lui t0, 0x8
+    0x0015_0513, // loop: addi a0, a0, 1
+    0x00a2_a023, // sw a0, 0(t0)
+    0x0000_0073, // ecall
+    0xff5f_f06f, // j loop
+];
+
+/// Counts syscalls, and can save the count.
+#[derive(Default)]
+struct CountingKernel {
+    syscalls: u32,
+}
+
+impl Kernel for CountingKernel {
+    type Error = Infallible;
+
+    fn syscall(
+        &mut self,
+        _hart: &mut Hart32,
+        _mem: &mut Memory,
+    ) -> Result<StepResult, MachineError<Infallible>> {
+        self.syscalls += 1;
+        Ok(StepResult::Ok)
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.syscalls.to_le_bytes().to_vec())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let count = state.try_into().map_err(|_| "bad syscall count")?;
+        self.syscalls = u32::from_le_bytes(count);
+        Ok(())
+    }
+}
+
+/// Everything the guest can see, and the kernel's count.
+#[derive(Debug, PartialEq)]
+struct Observed {
+    context: HartContext,
+    inst_count: u64,
+    mscratch: u32,
+    data: u32,
+    brk: u32,
+    syscalls: u32,
+}
+
+fn observe(machine: &Machine<CountingKernel>) -> Observed {
+    Observed {
+        context: machine.hart.context(),
+        inst_count: machine.hart.inst_count,
+        mscratch: machine.hart.csr(Csr::Mscratch),
+        data: machine.mem.load(DATA),
+        brk: machine.mem.brk,
+        syscalls: machine.kernel.syscalls,
+    }
+}
+
+fn step(machine: &mut Machine<CountingKernel>, steps: usize) {
+    for _ in 0..steps {
+        machine.step().unwrap();
+    }
+}
+
+#[test]
+fn restored_machine_runs_on_identically() {
+    let mut machine = common::load(Machine::new(CountingKernel::default()), &COUNTER);
+    machine.hart.set_freg(FReg::FA0, 0x4009_21fb_5444_2d18);
+    machine.hart.set_csr(Csr::Mscratch, 0xdead_beef);
+    machine.mem.brk = 0x2_0000;
+    step(&mut machine, 10);
+
+    let mut image = vec![];
+    machine.hibernate(&mut image).unwrap();
+    let at_hibernate = observe(&machine);
+    step(&mut machine, 25);
+
+    let mut restored = Machine::new(CountingKernel::default());
+    restored.restore(image.as_slice()).unwrap();
+    assert_eq!(observe(&restored), at_hibernate);
+    step(&mut restored, 25);
+    assert_eq!(observe(&restored), observe(&machine));
+    assert_eq!(restored.kernel.syscalls, 8);
+}
+
+#[test]
+fn bad_images_are_rejected() {
+    let machine = common::load(Machine::new(CountingKernel::default()), &COUNTER);
+    let mut image = vec![];
+    machine.hibernate(&mut image).unwrap();
+
+    let mut restored = Machine::new(CountingKernel::default());
+    let mut bad = image.clone();
+    bad[0] = b'X';
+    let err = restored.restore(bad.as_slice()).unwrap_err();
+    assert!(matches!(err, HibernateError::BadMagic));
+    assert_eq!(err.code(), 501);
+    let mut newer = image.clone();
+    newer[10..12].copy_from_slice(&2u16.to_le_bytes());
+    let err = restored.restore(newer.as_slice()).unwrap_err();
+    assert!(matches!(
+        err,
+        HibernateError::TooNew {
+            version: 1,
+            compat: 2
+        }
+    ));
+    let err = restored.restore(&image[..image.len() / 2]).unwrap_err();
+    assert!(matches!(err, HibernateError::Io(_)), "{err:?}");
+
+    // Kernels that can't save their state can't hibernate
+    let err = common::machine(&COUNTER).hibernate(vec![]).unwrap_err();
+    assert!(matches!(err, HibernateError::KernelUnsupported));
+}
+
+#[test]
+fn counters_and_page_permissions_are_restored() {
+    let config = MachineConfig {
+        exec_protection: ExecProtection::Regions,
+        ..Default::default()
+    };
+    let fresh = || Machine::with_config(CountingKernel::default(), config.clone());
+    let mut machine = common::load(fresh(), &COUNTER);
+    let protection = machine.mem.protection.as_mut().unwrap();
+    protection
+        .set(common::CODE, 0x1000, PagePerms::CODE)
+        .unwrap();
+    // mhpmcounter3 counts stores
+    let stores = HpmEvent::Stores as u32;
+    machine
+        .hart
+        .csr_instruction(0x323u16, CsrOp::Write, stores, true);
+    step(&mut machine, 10);
+
+    let mut image = vec![];
+    machine.hibernate(&mut image).unwrap();
+    let mut restored = fresh();
+    restored.restore(image.as_slice()).unwrap();
+    let protection = restored.mem.protection.as_ref().unwrap();
+    assert_eq!(protection.get(common::CODE), PagePerms::CODE);
+    assert_eq!(protection.get(DATA), PagePerms::DATA);
+
+    step(&mut machine, 25);
+    step(&mut restored, 25);
+    for machine in [&machine, &restored] {
+        let hpm = &machine.hart.hpm;
+        assert_eq!(hpm.event_count(HpmEvent::Stores), 9);
+        assert_eq!(hpm.counter(3, machine.hart.inst_count), 9);
+    }
+}
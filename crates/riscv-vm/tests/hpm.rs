@@ -0,0 +1,72 @@
+//! Performance counters programmed by the guest count exactly what it does,
+//! here a loop of ten loads, stores and branches, and stop while inhibited.
+
+mod common;
+
+use common::EBREAK;
+use riscv_vm::{hpm::HpmEvent, riscv_inst::Reg};
+
+/// Selects loads, stores and taken branches for `mhpmcounter3`-`5`, runs
+/// the loop, and reads them and `minstret` into `a0`-`a3`.
+const LOOP: [u32; 18] = [
+    0x0010_0293, // li t0, 1
+    0x3232_9073, // csrw mhpmevent3, t0
+    0x0020_0293, // li t0, 2
+    0x3242_9073, // csrw mhpmevent4, t0
+    0x0050_0293, // li t0, 5
+    0x3252_9073, // csrw mhpmevent5, t0
+    0x0000_8437, // lui s0, 0x8
+    0x00a0_0493, // li s1, 10
+    0x0004_2303, // loop: lw t1, 0(s0)
+    0x0013_0313, // addi t1, t1, 1
+    0x0064_2023, // sw t1, 0(s0)
+    0xfff4_8493, // addi s1, s1, -1
+    0xfe04_98e3, // bnez s1, loop
+    0xb030_2573, // csrr a0, mhpmcounter3
+    0xb040_25f3, // csrr a1, mhpmcounter4
+    0xb050_2673, // csrr a2, mhpmcounter5
+    0xb020_26f3, // csrr a3, minstret
+    EBREAK,
+];
+
+#[test]
+fn counters_count_the_selected_events() {
+    let mut machine = common::machine(&LOOP);
+    machine.run().unwrap();
+
+    let counters = [Reg::A0, Reg::A1, Reg::A2].map(|reg| machine.hart.get_reg(reg));
+    // The last branch falls through
+    assert_eq!(counters, [10, 10, 9]);
+    // Everything before the read: 8 to set up and 5 per iteration, plus
+    // the three counter reads
+    assert_eq!(machine.hart.get_reg(Reg::A3), 8 + 5 * 10 + 3);
+    assert_eq!(machine.mem.load::<u32>(0x8000), 10);
+
+    // Counted whether or not a counter selects them
+    let hpm = &machine.hart.hpm;
+    assert_eq!(hpm.event_count(HpmEvent::Branches), 10);
+    assert_eq!(hpm.event_count(HpmEvent::Atomics), 0);
+    assert_eq!(hpm.counter(3, machine.hart.inst_count), 10);
+}
+
+#[test]
+fn inhibited_counters_hold_still() {
+    let program = [
+        0x3202_5073, // csrwi mcountinhibit, 4
+        0x0000_0013, // nop
+        0x0000_0013, // nop
+        0xb020_2573, // csrr a0, minstret
+        0x3200_5073, // csrwi mcountinhibit, 0
+        0x0000_0013, // nop
+        0xb020_25f3, // csrr a1, minstret
+        EBREAK,
+    ];
+    let mut machine = common::machine(&program);
+    machine.run().unwrap();
+
+    // Frozen from the first instruction until it's let go, then counting on
+    // from where it stopped
+    assert_eq!(machine.hart.get_reg(Reg::A0), 0);
+    assert_eq!(machine.hart.get_reg(Reg::A1), 2);
+    assert_eq!(machine.hart.inst_count, 7);
+}
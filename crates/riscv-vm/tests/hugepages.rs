@@ -0,0 +1,26 @@
+//! Backing guest memory with host hugepages.
+
+use riscv_vm::{error::MemoryError, memory::HugePages, memory::Memory};
+
+#[test]
+fn parses_modes() {
+    for mode in [HugePages::Off, HugePages::Transparent, HugePages::Explicit] {
+        assert_eq!(mode.to_string().parse::<HugePages>(), Ok(mode));
+    }
+    assert!("huge".parse::<HugePages>().is_err());
+}
+
+#[test]
+fn transparent_hugepages_hold_guest_memory() {
+    // Hosts built without transparent hugepages can't be asked for them
+    let mut mem = match Memory::with_huge_pages(HugePages::Transparent) {
+        Ok(mem) => mem,
+        Err(MemoryError::HugePages { .. }) => return,
+        Err(e) => panic!("{e}"),
+    };
+    assert_eq!(mem.huge_pages(), HugePages::Transparent);
+    mem.store(0x1000_0000, 0xdead_beefu32);
+    mem.store(0xffff_fffc, 7u32);
+    assert_eq!(mem.load::<u32>(0x1000_0000), 0xdead_beef);
+    assert_eq!(mem.load::<u32>(0xffff_fffc), 7);
+}
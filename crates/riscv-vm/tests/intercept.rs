@@ -0,0 +1,63 @@
+//! Guest functions replaced by host closures: the closure gets the guest's
+//! arguments, and its result comes back to the caller as if the guest
+//! function had returned it.
+
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use common::{CODE, EBREAK};
+use riscv_vm::{machine::Machine, riscv_inst::Reg};
+
+const F: u32 = CODE + 0x18;
+
+/// Calls `f(6, 7)`, which adds, and keeps the result in `s0`.
+const PROGRAM: [u32; 8] = [
+    0x0060_0513, // li a0, 6
+    0x0070_0593, // li a1, 7
+    0x0100_00ef, // jal ra, f
+    0x0005_0413, // mv s0, a0
+    EBREAK,
+    0x0000_0013, // nop
+    // f
+    0x00b5_0533, // add a0, a0, a1
+    0x0000_8067, // ret
+];
+
+fn machine() -> Machine<common::NopKernel> {
+    let mut machine = common::machine(&PROGRAM);
+    machine.symbols.insert("f", F, 8);
+    machine
+}
+
+#[test]
+fn intercepted_functions_return_the_host_result() {
+    let mut machine = machine();
+    let calls = Rc::new(Cell::new(0));
+    let counted = calls.clone();
+    let found = machine.intercept("f", move |hart, _mem, _kernel| {
+        counted.set(counted.get() + 1);
+        assert_eq!(hart.pc, F);
+        hart.get_reg(Reg::A0) * hart.get_reg(Reg::A1)
+    });
+    assert_eq!(found, Some(()));
+    machine.run().unwrap();
+
+    assert_eq!(machine.hart.get_reg(Reg::S0), 42);
+    assert_eq!(calls.get(), 1);
+    // Back in the caller, past the call
+    assert_eq!(machine.hart.pc, CODE + 0x10);
+}
+
+#[test]
+fn removed_intercepts_run_the_guest_function() {
+    let mut machine = machine();
+    machine.intercept_at(F, |_, _, _| 0);
+    assert!(machine.remove_intercept(F).is_some());
+    assert!(machine.remove_intercept(F).is_none());
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::S0), 13);
+
+    // Nothing by that name to intercept
+    assert_eq!(machine.intercept("g", |_, _, _| 0), None);
+}
@@ -0,0 +1,68 @@
+//! Running machines in lock step, sharing decodes between those at the
+//! same instruction.
+
+mod common;
+
+use common::{NopKernel, CODE};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    lockstep::LockStep,
+    machine::{Machine, MachineState},
+    riscv_inst::Reg,
+};
+
+/// Adds 2 to `a1` `a0` times.
+const PROGRAM: [u32; 5] = [
+    0x0005_0863, // beqz a0, done
+    0x0025_8593, // loop: addi a1, a1, 2
+    0xfff5_0513, // addi a0, a0, -1
+    0xfe05_1ce3, // bnez a0, loop
+    0x0010_0073, // done: ebreak
+];
+
+fn machine(a0: u32) -> Machine<NopKernel> {
+    let mut machine = common::machine(&PROGRAM);
+    machine.hart.set_reg(Reg::A0, a0);
+    machine
+}
+
+#[test]
+fn machines_diverge_and_share_decodes() {
+    let inputs = [0, 3, 3, 10];
+    let mut lockstep = LockStep::new(inputs.map(machine).into());
+    let results = lockstep.run();
+    assert!(results.iter().all(Result::is_ok));
+
+    // Each matches running alone
+    for (machine, a0) in lockstep.machines().iter().zip(inputs) {
+        let mut alone = self::machine(a0);
+        alone.run().unwrap();
+        assert_eq!(machine.hart.get_reg(Reg::A1), 2 * a0);
+        assert_eq!(machine.hart.context(), alone.hart.context());
+        assert_eq!(machine.hart.inst_count, alone.hart.inst_count);
+        assert_eq!(machine.state, MachineState::Halted);
+    }
+
+    let stats = lockstep.stats();
+    let steps: u64 = inputs.iter().map(|&a0| 2 + 3 * a0 as u64).sum();
+    assert_eq!(stats.steps, steps);
+    // The longest run decodes each of its instructions once, and the others
+    // only their `ebreak`s, which they reach in different rounds
+    assert_eq!(stats.decodes, 2 + 3 * 10 + 2);
+}
+
+#[test]
+fn a_failing_machine_stops_alone() {
+    let mut broken = machine(3);
+    // `c.unimp`
+    broken.mem.store(CODE + 8, 0u32);
+    let mut lockstep = LockStep::new(vec![machine(3), broken]);
+    let results = lockstep.run();
+
+    assert!(results[0].is_ok());
+    assert!(matches!(
+        results[1],
+        Err(MachineError::Hart(HartError::IllegalInst { addr, .. })) if addr == CODE + 8
+    ));
+    assert_eq!(lockstep.machines()[0].hart.get_reg(Reg::A1), 6);
+}
@@ -0,0 +1,102 @@
+//! LR/SC reservation behavior: what keeps a reservation and what breaks it,
+//! and the result `sc.w` writes to `rd`.
+
+mod common;
+
+use common::{NopKernel, EBREAK};
+use riscv_vm::{machine::Machine, riscv_inst::Reg};
+
+const LR_W: u32 = 0x1002_a52f; // lr.w a0, (t0)
+const SC_W: u32 = 0x18c2_a5af; // sc.w a1, a2, (t0)
+const SC_W_OTHER: u32 = 0x18c3_25af; // sc.w a1, a2, (t1)
+const SW: u32 = 0x00d2_a023; // sw a3, 0(t0)
+const SB_HIGH: u32 = 0x00d2_81a3; // sb a3, 3(t0)
+const SW_NEXT: u32 = 0x00d2_a223; // sw a3, 4(t0)
+const AMOADD_W: u32 = 0x00c2_a52f; // amoadd.w a0, a2, (t0)
+const ECALL: u32 = 0x0000_0073;
+
+const WORD: u32 = 0x8000;
+const STORED: u32 = 0x1234_5678;
+const OTHER: u32 = 0xaaaa_aaaa;
+
+/// Run `program` up to an `ebreak`, with `t0` pointing at a zeroed word and
+/// `t1` at the word after it.
+fn run(program: &[u32]) -> Machine<NopKernel> {
+    let mut machine = common::machine(&[program, &[EBREAK]].concat());
+    machine.hart.set_reg(Reg::T0, WORD);
+    machine.hart.set_reg(Reg::T1, WORD + 4);
+    machine.hart.set_reg(Reg::A1, 0xdead_beef);
+    machine.hart.set_reg(Reg::A2, STORED);
+    machine.hart.set_reg(Reg::A3, OTHER);
+    machine.run().unwrap();
+    machine
+}
+
+fn sc_result(machine: &Machine<NopKernel>) -> u32 {
+    machine.hart.get_reg(Reg::A1)
+}
+
+#[test]
+fn sc_after_lr_succeeds() {
+    let machine = run(&[LR_W, SC_W]);
+    assert_eq!(sc_result(&machine), 0);
+    assert_eq!(machine.mem.load::<u32>(WORD), STORED);
+}
+
+#[test]
+fn sc_without_lr_fails() {
+    let machine = run(&[SC_W]);
+    assert_eq!(sc_result(&machine), 1);
+    assert_eq!(machine.mem.load::<u32>(WORD), 0);
+}
+
+#[test]
+fn sc_consumes_the_reservation() {
+    let machine = run(&[LR_W, SC_W_OTHER, SC_W]);
+    assert_eq!(sc_result(&machine), 1);
+    assert_eq!(machine.mem.load::<u32>(WORD), 0);
+    assert_eq!(machine.mem.load::<u32>(WORD + 4), 0);
+}
+
+#[test]
+fn store_to_reserved_word_breaks_reservation() {
+    let machine = run(&[LR_W, SW, SC_W]);
+    assert_eq!(sc_result(&machine), 1);
+    assert_eq!(machine.mem.load::<u32>(WORD), OTHER);
+}
+
+#[test]
+fn partial_store_to_reserved_word_breaks_reservation() {
+    let machine = run(&[LR_W, SB_HIGH, SC_W]);
+    assert_eq!(sc_result(&machine), 1);
+    assert_eq!(machine.mem.load::<u32>(WORD), 0xaa00_0000);
+}
+
+#[test]
+fn store_next_to_reserved_word_keeps_reservation() {
+    let machine = run(&[LR_W, SW_NEXT, SC_W]);
+    assert_eq!(sc_result(&machine), 0);
+    assert_eq!(machine.mem.load::<u32>(WORD), STORED);
+}
+
+#[test]
+fn amo_to_reserved_word_breaks_reservation() {
+    let machine = run(&[LR_W, AMOADD_W, SC_W]);
+    assert_eq!(sc_result(&machine), 1);
+    assert_eq!(machine.mem.load::<u32>(WORD), STORED);
+}
+
+#[test]
+fn trap_breaks_reservation() {
+    let machine = run(&[LR_W, ECALL, SC_W]);
+    assert_eq!(sc_result(&machine), 1);
+    assert_eq!(machine.mem.load::<u32>(WORD), 0);
+}
+
+#[test]
+fn context_switch_breaks_reservation() {
+    let mut machine = run(&[LR_W]);
+    let context = machine.hart.context();
+    machine.hart.restore_context(&context);
+    assert_eq!(machine.hart.amo_rsv, None);
+}
@@ -0,0 +1,106 @@
+//! Machine statistics reported through the `metrics` facade: counters
+//! follow the machine's usage, and faults are counted by kind and code.
+
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+use riscv_vm::{
+    error::MachineError, metrics::MachineMetrics, panic::GuestBacktrace, quota::Resource,
+};
+
+/// Keeps every counter and gauge by name and labels, like
+/// `derisc_syscalls_total{machine=a}`.
+#[derive(Default)]
+struct Recorded(Mutex<BTreeMap<String, Arc<AtomicU64>>>);
+
+impl Recorded {
+    fn handle(&self, key: &Key) -> Arc<AtomicU64> {
+        let labels: Vec<_> = key
+            .labels()
+            .map(|label| format!("{}={}", label.key(), label.value()))
+            .collect();
+        let name = format!("{}{{{}}}", key.name(), labels.join(","));
+        self.0.lock().unwrap().entry(name).or_default().clone()
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.0.lock().unwrap().contains_key(name)
+    }
+
+    fn counter(&self, name: &str) -> u64 {
+        self.0.lock().unwrap()[name].load(Ordering::Relaxed)
+    }
+
+    fn gauge(&self, name: &str) -> f64 {
+        f64::from_bits(self.counter(name))
+    }
+}
+
+impl Recorder for Recorded {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.handle(key))
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(self.handle(key))
+    }
+
+    fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::noop()
+    }
+}
+
+#[test]
+fn usage_is_reported_with_labels() {
+    let recorded = Recorded::default();
+    let mut machine = common::machine(&[0x0000_0013, 0x0000_0013, common::EBREAK]);
+    let mut metrics = MachineMetrics::new([("machine", "a".to_string())]);
+
+    metrics::with_local_recorder(&recorded, || metrics.record(&machine));
+    assert_eq!(recorded.counter("derisc_instructions_total{machine=a}"), 0);
+    let memory = recorded.gauge("derisc_memory_bytes{machine=a}");
+    assert_eq!(memory, machine.usage().memory_bytes as f64);
+    // No rates without a previous record
+    assert!(!recorded.has("derisc_instructions_per_second{machine=a}"));
+
+    machine.run().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1));
+    metrics::with_local_recorder(&recorded, || metrics.record(&machine));
+    assert_eq!(recorded.counter("derisc_instructions_total{machine=a}"), 2);
+    assert_eq!(recorded.counter("derisc_syscalls_total{machine=a}"), 0);
+    assert!(recorded.gauge("derisc_instructions_per_second{machine=a}") > 0.0);
+    assert_eq!(recorded.gauge("derisc_syscalls_per_second{machine=a}"), 0.0);
+}
+
+#[test]
+fn faults_are_counted_by_kind_and_code() {
+    let recorded = Recorded::default();
+    let metrics = MachineMetrics::new([("machine", "b".to_string())]);
+    let error = MachineError::<std::convert::Infallible>::QuotaExceeded {
+        resource: Resource::Instructions,
+        limit: 100,
+        backtrace: GuestBacktrace::default(),
+    };
+    metrics::with_local_recorder(&recorded, || {
+        metrics.record_fault(&error);
+        metrics.record_fault(&error);
+    });
+    assert_eq!(
+        recorded.counter("derisc_faults_total{machine=b,kind=quota,code=402}"),
+        2
+    );
+}
@@ -0,0 +1,89 @@
+//! Injecting stubs into the guest and redirecting functions to them.
+
+mod common;
+
+use common::NopKernel;
+use riscv_vm::{error::MachineError, machine::Machine};
+
+const DOUBLE: u32 = 0x2000;
+/// `a0 * 2`
+const DOUBLE_CODE: [u8; 10] = [
+    0x06, 0x05, // slli a0, a0, 1
+    0x01, 0x00, // nop
+    0x01, 0x00, // nop
+    0x01, 0x00, // nop
+    0x82, 0x80, // ret
+];
+
+fn machine(code: &[u8]) -> Machine<NopKernel> {
+    let mut machine = Machine::new(NopKernel);
+    machine.mem.copy_to(DOUBLE, code).unwrap();
+    machine.symbols.insert("double", DOUBLE, code.len() as u32);
+    machine
+}
+
+#[test]
+fn redirects_to_an_injected_stub() {
+    let mut machine = machine(&DOUBLE_CODE);
+    let stub = machine
+        .inject(&[
+            0x13, 0x05, 0x45, 0x06, // addi a0, a0, 100
+            0x82, 0x80, // ret
+        ])
+        .unwrap();
+
+    let patch = machine.redirect(DOUBLE, stub).unwrap();
+    assert_eq!(patch.displaced, DOUBLE_CODE[..8]);
+    assert_eq!(machine.call(DOUBLE, &[5]).unwrap(), 105);
+
+    machine.unpatch(&patch).unwrap();
+    assert_eq!(machine.mem.slice::<u8>(DOUBLE, 10).unwrap(), DOUBLE_CODE);
+    assert_eq!(machine.call(DOUBLE, &[5]).unwrap(), 10);
+}
+
+#[test]
+fn stubs_can_call_the_original() {
+    let mut machine = machine(&DOUBLE_CODE);
+    // Increments the argument, then tail-calls the word after it
+    let stub = machine
+        .inject(&[
+            0x05, 0x05, // addi a0, a0, 1
+            0x17, 0x03, 0x00, 0x00, // auipc t1, 0
+            0x03, 0x23, 0xa3, 0x00, // lw t1, 10(t1)
+            0x02, 0x83, // jr t1
+            0x00, 0x00, 0x00, 0x00, // .word original
+        ])
+        .unwrap();
+
+    let patch = machine.redirect(DOUBLE, stub).unwrap();
+    let original = patch.original.unwrap();
+    machine.mem.store(stub + 12, original);
+    assert_eq!(machine.call(DOUBLE, &[5]).unwrap(), 12);
+    assert_eq!(machine.call(original, &[5]).unwrap(), 10);
+}
+
+#[test]
+fn position_dependent_prologues_cant_be_called() {
+    let mut code = DOUBLE_CODE;
+    // auipc a1, 0
+    code[4..8].copy_from_slice(&[0x97, 0x05, 0x00, 0x00]);
+    let mut machine = machine(&code);
+    let stub = machine.inject(&[0x82, 0x80]).unwrap();
+
+    let patch = machine.redirect(DOUBLE, stub).unwrap();
+    assert_eq!(patch.original, None);
+    assert_eq!(machine.call(DOUBLE, &[5]).unwrap(), 5);
+}
+
+#[test]
+fn short_functions_are_refused() {
+    let mut machine = machine(&DOUBLE_CODE);
+    machine.symbols.insert("tiny", 0x3000, 4);
+
+    let err = machine.redirect(0x3000, DOUBLE).unwrap_err();
+    assert!(matches!(
+        err,
+        MachineError::Unpatchable { addr: 0x3000, .. }
+    ));
+    assert_eq!(err.code(), 404);
+}
@@ -0,0 +1,87 @@
+//! The pipeline trace times a known sequence exactly: one instruction per
+//! stage per cycle, with a multiply holding up the instructions behind it.
+
+mod common;
+
+use std::{cell::RefCell, collections::BTreeMap, io::Write, rc::Rc};
+
+use common::EBREAK;
+use riscv_vm::pipeline::{PipelineTrace, MUL_LATENCY};
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+const PROGRAM: [u32; 5] = [
+    0x0060_0513, // li a0, 6
+    0x0070_0593, // li a1, 7
+    0x02b5_0633, // mul a2, a0, a1
+    0x0016_0693, // addi a3, a2, 1
+    EBREAK,
+];
+
+/// The cycle each instruction entered each stage and retired, from a
+/// Kanata trace.
+fn timeline(trace: &str) -> BTreeMap<u32, Vec<(String, u64)>> {
+    let mut cycle = 0;
+    let mut stages = BTreeMap::<u32, Vec<_>>::new();
+    for line in trace.lines().skip(2) {
+        let fields: Vec<_> = line.split('\t').collect();
+        let id = || fields[1].parse().unwrap();
+        match fields[0] {
+            "C" => cycle += fields[1].parse::<u64>().unwrap(),
+            "S" => stages
+                .entry(id())
+                .or_default()
+                .push((fields[3].into(), cycle)),
+            "R" => stages.entry(id()).or_default().push(("R".into(), cycle)),
+            _ => {}
+        }
+    }
+    stages
+}
+
+#[test]
+fn multiply_stalls_the_instructions_behind_it() {
+    let out = Output::default();
+    let mut machine = common::machine(&PROGRAM);
+    machine.hart.pipeline_trace = Some(PipelineTrace::new(out.clone()));
+    machine.run().unwrap();
+    machine
+        .hart
+        .pipeline_trace
+        .take()
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    let trace = String::from_utf8(out.0.take()).unwrap();
+    assert!(trace.starts_with("Kanata\t0004\nC=\t0\n"));
+    assert!(trace.contains("L\t2\t0\t00001008: mul (02b50633)"));
+    let stages = |cycles: [u64; 6]| {
+        let names = ["F", "D", "X", "M", "W", "R"];
+        names
+            .iter()
+            .map(|&n| n.to_string())
+            .zip(cycles)
+            .collect::<Vec<_>>()
+    };
+    let m = MUL_LATENCY;
+    let expected = BTreeMap::from([
+        (0, stages([0, 1, 2, 3, 4, 5])),
+        (1, stages([1, 2, 3, 4, 5, 6])),
+        (2, stages([2, 3, 4, 4 + m, 5 + m, 6 + m])),
+        // Decoded, but can't execute until the multiply moves on
+        (3, stages([3, 4, 4 + m, 5 + m, 6 + m, 7 + m])),
+    ]);
+    assert_eq!(timeline(&trace), expected);
+}
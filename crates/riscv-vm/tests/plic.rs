@@ -0,0 +1,99 @@
+//! The PLIC's registers: source priorities and enables, pending bits
+//! latched from device lines, claim and complete, and the priority
+//! threshold gating the hart's external interrupt.
+
+use riscv_vm::{
+    memory::Memory,
+    mmio::MmioDevice,
+    plic::{Plic, PLIC_SOURCES},
+    trap::Interrupt,
+};
+
+const PRIORITY: u32 = 0x00_0000;
+const PENDING: u32 = 0x00_1000;
+const ENABLE: u32 = 0x00_2000;
+const THRESHOLD: u32 = 0x20_0000;
+const CLAIM_COMPLETE: u32 = 0x20_0004;
+
+const EXTERNAL: u32 = Interrupt::MachineExternal.mask();
+
+fn set_priority(plic: &mut Plic, source: u32, priority: u32) {
+    plic.write(PRIORITY + 4 * source, 4, priority);
+}
+
+#[test]
+fn priorities_and_enables_read_back() {
+    let mut plic = Plic::new();
+    set_priority(&mut plic, 5, 3);
+    // Priorities are 3 bits
+    set_priority(&mut plic, 63, 0xf);
+    assert_eq!(plic.read(PRIORITY + 4 * 5, 4), 3);
+    assert_eq!(plic.read(PRIORITY + 4 * 63, 4), 7);
+    // Source 0 doesn't exist
+    set_priority(&mut plic, 0, 1);
+    assert_eq!(plic.read(PRIORITY, 4), 0);
+
+    plic.write(ENABLE, 4, 0xffff_ffff);
+    plic.write(ENABLE + 4, 4, 0x8000_0001);
+    assert_eq!(plic.read(ENABLE, 4), 0xffff_fffe);
+    assert_eq!(plic.read(ENABLE + 4, 4), 0x8000_0001);
+
+    plic.write(THRESHOLD, 4, 0xf);
+    assert_eq!(plic.read(THRESHOLD, 4), 7);
+    assert!(plic.line(0).is_none());
+    assert!(plic.line(PLIC_SOURCES).is_none());
+}
+
+#[test]
+fn claim_takes_the_highest_priority_source_until_completed() {
+    let (mut plic, mut mem) = (Plic::new(), Memory::new());
+    let lines = [3, 7, 9].map(|source| plic.line(source).unwrap());
+    for (source, priority) in [(3, 1), (7, 2), (9, 2)] {
+        set_priority(&mut plic, source, priority);
+    }
+    plic.write(ENABLE, 4, 1 << 3 | 1 << 7 | 1 << 9);
+    lines.iter().for_each(|line| line.set(true));
+    assert_eq!(plic.read(PENDING, 4), 1 << 3 | 1 << 7 | 1 << 9);
+    assert_eq!(plic.tick(&mut mem, 0), EXTERNAL);
+
+    // Highest priority first, the lower ID of equals first
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), 7);
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), 9);
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), 3);
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), 0);
+    // In service, so the lines still being high doesn't make them pending
+    assert_eq!(plic.read(PENDING, 4), 0);
+    assert_eq!(plic.tick(&mut mem, 1), 0);
+
+    // Completing 7 lets its line in again; 3 went low first
+    lines[0].set(false);
+    plic.write(CLAIM_COMPLETE, 4, 7);
+    plic.write(CLAIM_COMPLETE, 4, 3);
+    assert_eq!(plic.read(PENDING, 4), 1 << 7);
+    assert_eq!(plic.tick(&mut mem, 2), EXTERNAL);
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), 7);
+}
+
+#[test]
+fn threshold_masks_lower_priorities() {
+    let (mut plic, mut mem) = (Plic::new(), Memory::new());
+    let line = plic.line(1).unwrap();
+    set_priority(&mut plic, 1, 4);
+    line.set(true);
+    // Pending, but not enabled
+    assert_eq!(plic.read(PENDING, 4), 1 << 1);
+    assert_eq!(plic.tick(&mut mem, 0), 0);
+
+    plic.write(ENABLE, 4, 1 << 1);
+    assert_eq!(plic.tick(&mut mem, 1), EXTERNAL);
+    // Only priorities above the threshold interrupt
+    plic.write(THRESHOLD, 4, 4);
+    assert_eq!(plic.tick(&mut mem, 2), 0);
+    plic.write(THRESHOLD, 4, 3);
+    assert_eq!(plic.tick(&mut mem, 3), EXTERNAL);
+    // Priority 0 never does
+    set_priority(&mut plic, 1, 0);
+    plic.write(THRESHOLD, 4, 0);
+    assert_eq!(plic.tick(&mut mem, 4), 0);
+    assert_eq!(plic.read(CLAIM_COMPLETE, 4), 0);
+}
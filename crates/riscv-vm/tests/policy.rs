@@ -0,0 +1,78 @@
+//! Opcode allow and deny lists: each policy lets through exactly what it
+//! says, and stops the rest with a forbidden instruction error naming it.
+
+mod common;
+
+use common::{NopKernel, CODE, EBREAK};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    machine::{Machine, MachineConfig},
+    policy::{InstPolicy, OpcodeSet, UnknownOpcode},
+    riscv_inst::Reg,
+};
+
+const ADD: u32 = 0x00b5_0533; // add a0, a0, a1
+const MUL: u32 = 0x02b5_0633; // mul a2, a0, a1
+const AMOADD: u32 = 0x00b4_252f; // amoadd.w a0, a1, (s0)
+
+/// Run `inst` under `policy`, returning the mnemonic it was forbidden as,
+/// if it was.
+fn forbidden(policy: &InstPolicy, inst: u32) -> Option<&'static str> {
+    let config = MachineConfig {
+        policy: policy.clone(),
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &[inst, EBREAK]);
+    machine.hart.set_reg(Reg::S0, 0x8000);
+    match machine.step() {
+        Ok(()) => {
+            assert_eq!(machine.hart.pc, CODE + 4);
+            None
+        }
+        Err(MachineError::Hart(HartError::ForbiddenInst {
+            addr,
+            inst: raw,
+            mnemonic,
+        })) => {
+            assert_eq!((addr, raw), (CODE, inst));
+            assert_eq!(machine.hart.inst_count, 0);
+            Some(mnemonic)
+        }
+        Err(e) => panic!("unexpected error {e}"),
+    }
+}
+
+#[test]
+fn each_policy_forbids_what_it_says() {
+    let allow_all = InstPolicy::AllowAll;
+    let allow = InstPolicy::Allow(OpcodeSet::from_mnemonics(["add", "ebreak"]).unwrap());
+    let deny =
+        InstPolicy::Deny(OpcodeSet::amo().union(OpcodeSet::from_mnemonics(["mul"]).unwrap()));
+
+    for inst in [ADD, MUL, AMOADD] {
+        assert_eq!(forbidden(&allow_all, inst), None);
+    }
+    assert_eq!(forbidden(&allow, ADD), None);
+    assert_eq!(forbidden(&allow, MUL), Some("mul"));
+    assert_eq!(forbidden(&allow, AMOADD), Some("amoadd.w"));
+    assert_eq!(forbidden(&deny, ADD), None);
+    assert_eq!(forbidden(&deny, MUL), Some("mul"));
+    assert_eq!(forbidden(&deny, AMOADD), Some("amoadd.w"));
+}
+
+#[test]
+fn opcode_sets_hold_their_mnemonics() {
+    let amo: Vec<_> = OpcodeSet::amo().mnemonics().collect();
+    assert!(amo.contains(&"amoswap.w"));
+    assert!(!amo.contains(&"lr.w"));
+    assert!(OpcodeSet::csr().mnemonics().all(|m| m.starts_with("csr")));
+
+    let set = OpcodeSet::from_mnemonics(["c.ebreak", "add"]).unwrap();
+    let mut mnemonics: Vec<_> = set.mnemonics().collect();
+    mnemonics.sort();
+    assert_eq!(mnemonics, ["add", "c.ebreak"]);
+    assert_eq!(
+        OpcodeSet::from_mnemonics(["add", "frobnicate"]),
+        Err(UnknownOpcode("frobnicate".to_string()))
+    );
+}
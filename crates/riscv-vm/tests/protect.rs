@@ -0,0 +1,93 @@
+//! Running code only from executable pages, and W^X.
+
+mod common;
+
+use common::{NopKernel, CODE};
+use riscv_vm::{
+    error::{MachineError, MemoryAccess, MemoryError},
+    machine::{Machine, MachineConfig, MachineState},
+    protect::{ExecProtection, PagePerms},
+    riscv_inst::Reg,
+};
+
+const PROGRAM: [u32; 2] = [
+    0x00a5_a023, // sw a0, 0(a1)
+    0x0010_0073, // ebreak
+];
+const DATA: u32 = 0x8000;
+
+fn machine(mode: ExecProtection, perms: PagePerms) -> Machine<NopKernel> {
+    let config = MachineConfig {
+        exec_protection: mode,
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &PROGRAM);
+    machine.mem.protect(CODE, 8, perms).unwrap();
+    machine.hart.set_reg(Reg::A0, 42);
+    machine
+}
+
+#[test]
+fn code_runs_and_data_is_written() {
+    let mut machine = machine(ExecProtection::Regions, PagePerms::CODE);
+    machine.hart.set_reg(Reg::A1, DATA);
+    machine.run().unwrap();
+    assert_eq!(machine.state, MachineState::Halted);
+    assert_eq!(machine.mem.load::<u32>(DATA), 42);
+}
+
+#[test]
+fn code_isnt_written_and_data_isnt_run() {
+    let mut machine = machine(ExecProtection::Regions, PagePerms::CODE);
+    machine.hart.set_reg(Reg::A1, CODE + 4);
+    let err = machine.run().unwrap_err();
+    assert!(matches!(
+        err,
+        MachineError::Memory(MemoryError::Protection {
+            access: MemoryAccess::Store,
+            addr,
+        }) if addr == CODE + 4
+    ));
+    assert_eq!(err.code(), 208);
+    assert_eq!(machine.mem.load::<u32>(CODE + 4), PROGRAM[1]);
+
+    machine.mem.copy_to(DATA, &PROGRAM).unwrap();
+    machine.hart.pc = DATA;
+    assert!(matches!(
+        machine.run(),
+        Err(MachineError::Memory(MemoryError::Protection {
+            access: MemoryAccess::Fetch,
+            addr: DATA,
+        }))
+    ));
+}
+
+#[test]
+fn write_xor_execute() {
+    // Writable code is allowed, unless W^X is enforced
+    let mut regions = machine(ExecProtection::Regions, PagePerms::ALL);
+    regions.hart.set_reg(Reg::A1, CODE + 8);
+    regions.run().unwrap();
+    assert_eq!(regions.mem.load::<u32>(CODE + 8), 42);
+
+    let mut machine = machine(ExecProtection::WriteXorExecute, PagePerms::CODE);
+    let err = machine.mem.protect(CODE, 8, PagePerms::ALL).unwrap_err();
+    assert!(matches!(
+        err,
+        MemoryError::WriteXorExecute { addr: CODE, len: 8 }
+    ));
+    assert_eq!(err.code(), 209);
+    assert_eq!(
+        machine.mem.protection.as_ref().unwrap().get(CODE),
+        PagePerms::CODE
+    );
+}
+
+#[test]
+fn off_checks_nothing() {
+    let mut machine = machine(ExecProtection::Off, PagePerms::READ_ONLY);
+    assert!(machine.mem.protection.is_none());
+    machine.hart.set_reg(Reg::A1, CODE + 8);
+    machine.run().unwrap();
+    assert_eq!(machine.mem.load::<u32>(CODE + 8), 42);
+}
@@ -0,0 +1,114 @@
+//! Instruction and memory quotas stop the guest with a quota error, at
+//! exactly the instruction that would go over.
+
+mod common;
+
+use common::{NopKernel, CODE};
+use riscv_vm::{
+    error::MachineError,
+    machine::{Machine, MachineConfig, MachineState},
+    panic::GuestBacktrace,
+    quota::{Quotas, Resource},
+};
+
+/// Counts `t0` down from 1000, then stops: 2001 instructions and the
+/// `ebreak`.
+const COUNTDOWN: [u32; 4] = [
+    0x3e80_0293, // li t0, 1000
+    0xfff2_8293, // loop: addi t0, t0, -1
+    0xfe02_9ee3, // bnez t0, loop
+    common::EBREAK,
+];
+
+fn countdown(quotas: Quotas) -> Machine<NopKernel> {
+    let config = MachineConfig {
+        quotas,
+        ..Default::default()
+    };
+    let mut machine = common::load(Machine::with_config(NopKernel, config), &COUNTDOWN);
+    machine.symbols.insert("countdown", CODE, 16);
+    machine
+}
+
+/// The quota `machine` went over, and its limit.
+fn exceeded(machine: &mut Machine<NopKernel>) -> (Resource, u64) {
+    match machine.run() {
+        Err(e @ MachineError::QuotaExceeded { .. }) => {
+            assert_eq!(e.code(), 402);
+            let MachineError::QuotaExceeded {
+                resource,
+                limit,
+                backtrace,
+            } = e
+            else {
+                unreachable!()
+            };
+            // Where it was stopped
+            let frame = machine.symbols.describe(machine.hart.pc);
+            assert_eq!(backtrace.0[0], frame);
+            (resource, limit)
+        }
+        res => panic!("expected a quota error, got {res:?}"),
+    }
+}
+
+#[test]
+fn instruction_quotas_are_exact() {
+    let quotas = |n| Quotas {
+        instructions: Some(n),
+        ..Default::default()
+    };
+
+    let mut machine = countdown(quotas(100));
+    assert_eq!(exceeded(&mut machine), (Resource::Instructions, 100));
+    assert_eq!(machine.hart.inst_count, 100);
+    assert_eq!(machine.state, MachineState::Running);
+
+    // The ebreak counts as one past the last instruction
+    let mut machine = countdown(quotas(2001));
+    assert_eq!(exceeded(&mut machine), (Resource::Instructions, 2001));
+    assert_eq!(machine.hart.inst_count, 2001);
+    assert_eq!(machine.hart.pc, CODE + 12);
+
+    let mut machine = countdown(quotas(2002));
+    machine.run().unwrap();
+    assert_eq!(machine.state, MachineState::Halted);
+    assert_eq!(machine.usage().instructions, 2001);
+}
+
+#[test]
+fn memory_quotas_stop_the_guest() {
+    let quotas = |bytes| Quotas {
+        memory_bytes: Some(bytes),
+        ..Default::default()
+    };
+    let touch = |machine: &mut Machine<NopKernel>| {
+        machine.mem.memset(0x10_0000, 1, 4 * 4096).unwrap();
+        machine.usage().memory_bytes
+    };
+
+    let mut machine = countdown(quotas(0));
+    let used = touch(&mut machine);
+    // The program's page and four more
+    assert!(used >= 5 * 4096, "{used}");
+    machine.quotas.memory_bytes = Some(used - 1);
+    assert_eq!(exceeded(&mut machine), (Resource::Memory, used - 1));
+    assert_eq!(machine.hart.inst_count, 0);
+
+    let mut machine = countdown(quotas(used));
+    assert_eq!(touch(&mut machine), used);
+    machine.run().unwrap();
+}
+
+#[test]
+fn quota_errors_name_the_resource() {
+    let error = MachineError::<std::convert::Infallible>::QuotaExceeded {
+        resource: Resource::Instructions,
+        limit: 100,
+        backtrace: GuestBacktrace::default(),
+    };
+    assert_eq!(
+        error.to_string(),
+        "Guest exceeded its instruction quota of 100"
+    );
+}
@@ -0,0 +1,91 @@
+//! Running a program again from an image of the machine right after it was
+//! loaded.
+
+use std::convert::Infallible;
+
+use riscv_vm::{
+    error::{HibernateError, MachineError},
+    hart::Hart32,
+    machine::{Kernel, Machine, MachineConfig, StepResult},
+    memory::Memory,
+};
+
+const LW_T0: u32 = 0x7000_2283; // lw t0, 0x700(zero)
+const ADDI_T0_1: u32 = 0x0012_8293; // addi t0, t0, 1
+const SW_T0: u32 = 0x7050_2023; // sw t0, 0x700(zero)
+const LUI_T1: u32 = 0x0000_4337; // lui t1, 0x4
+const SW_T0_T1: u32 = 0x0053_2023; // sw t0, 0(t1)
+const EBREAK: u32 = 0x0010_0073;
+
+const CODE: u32 = 0x1000;
+const COUNTER: u32 = 0x700;
+const SCRATCH: u32 = 0x4000;
+
+/// A kernel without state, which `resettable` says it can reset.
+struct NopKernel {
+    resettable: bool,
+}
+
+impl Kernel for NopKernel {
+    type Error = Infallible;
+
+    fn syscall(
+        &mut self,
+        _hart: &mut Hart32,
+        _mem: &mut Memory,
+    ) -> Result<StepResult, MachineError<Infallible>> {
+        Ok(StepResult::Ok)
+    }
+
+    fn reset(&mut self, _mem: &mut Memory) -> bool {
+        self.resettable
+    }
+}
+
+/// Increments the counter, copying it to a page nothing was loaded into.
+fn machine(resettable: bool) -> Machine<NopKernel> {
+    let config = MachineConfig {
+        gas_profile: true,
+        ..Default::default()
+    };
+    let mut machine = Machine::with_config(NopKernel { resettable }, config);
+    let program = [LW_T0, ADDI_T0_1, SW_T0, LUI_T1, SW_T0_T1, EBREAK];
+    machine.mem.copy_to(CODE, &program).unwrap();
+    machine.mem.store(COUNTER, 41u32);
+    machine.hart.pc = CODE;
+    machine
+}
+
+#[test]
+fn reset_runs_program_from_the_start() {
+    let mut machine = machine(true);
+    let image = machine.image().unwrap();
+    machine.run().unwrap();
+    let first = (machine.hart.context(), machine.hart.inst_count);
+    assert_eq!(machine.mem.load::<u32>(COUNTER), 42);
+    assert_eq!(machine.mem.load::<u32>(SCRATCH), 42);
+
+    machine.reset_to_image(&image).unwrap();
+    assert_eq!(machine.hart.pc, CODE);
+    assert_eq!(machine.hart.inst_count, 0);
+    assert_eq!(machine.mem.load::<u32>(COUNTER), 41);
+    assert_eq!(machine.mem.load::<u32>(SCRATCH), 0);
+    let profiler = machine.hart.gas_profiler.as_ref().unwrap();
+    assert_eq!(profiler.instructions(), 0);
+
+    machine.run().unwrap();
+    assert_eq!((machine.hart.context(), machine.hart.inst_count), first);
+    assert_eq!(machine.mem.load::<u32>(COUNTER), 42);
+}
+
+#[test]
+fn kernel_that_cannot_reset_is_left_alone() {
+    let mut machine = machine(false);
+    let image = machine.image().unwrap();
+    machine.run().unwrap();
+    assert!(matches!(
+        machine.reset_to_image(&image),
+        Err(HibernateError::KernelUnsupported)
+    ));
+    assert_eq!(machine.mem.load::<u32>(COUNTER), 42);
+}
@@ -0,0 +1,175 @@
+//! Running with periodic host callbacks: they come exactly as often as
+//! asked, and cancelling or breaking leaves the machine ready to resume.
+//! Running as a future yields to the executor on the same terms.
+
+mod common;
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    ops::ControlFlow,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+
+use common::NopKernel;
+use riscv_vm::{
+    error::MachineError,
+    hart::Hart32,
+    machine::{Kernel, Machine, MachineState, StepResult},
+    memory::Memory,
+    riscv_inst::Reg,
+    watchdog::{CancelToken, RunExit, YieldEvery},
+};
+
+/// Counts `t0` down from 1000, then stops: 2002 instructions.
+const COUNTDOWN: [u32; 4] = [
+    0x3e80_0293, // li t0, 1000
+    0xfff2_8293, // loop: addi t0, t0, -1
+    0xfe02_9ee3, // bnez t0, loop
+    common::EBREAK,
+];
+
+fn machine() -> Machine<NopKernel> {
+    common::machine(&COUNTDOWN)
+}
+
+#[test]
+fn callbacks_come_every_n_instructions() {
+    let mut machine = machine();
+    let mut seen = vec![];
+    let exit = machine.run_with(YieldEvery::instructions(500), &CancelToken::new(), |m| {
+        seen.push(m.hart.inst_count);
+        ControlFlow::Continue(())
+    });
+    assert_eq!(exit.unwrap(), RunExit::Halted);
+    assert_eq!(seen, [500, 1000, 1500, 2000]);
+    assert_eq!(machine.hart.get_reg(Reg::T0), 0);
+}
+
+#[test]
+fn breaking_stops_and_resumes() {
+    let mut machine = machine();
+    let cancel = CancelToken::new();
+    let exit = machine.run_with(YieldEvery::instructions(100), &cancel, |m| {
+        if m.hart.inst_count == 300 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert_eq!(exit.unwrap(), RunExit::Stopped);
+    assert_eq!(machine.hart.inst_count, 300);
+    assert_eq!(machine.state, MachineState::Running);
+
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::T0), 0);
+}
+
+#[test]
+fn cancelling_stops_at_the_next_check() {
+    let mut machine = machine();
+    let cancel = CancelToken::new();
+    let token = cancel.clone();
+    let exit = machine.run_with(YieldEvery::instructions(100), &cancel, |m| {
+        if m.hart.inst_count == 200 {
+            token.cancel();
+        }
+        ControlFlow::Continue(())
+    });
+    assert_eq!(exit.unwrap(), RunExit::Cancelled);
+    assert!(cancel.is_cancelled());
+    // The check after the callback that cancelled
+    assert_eq!(machine.hart.inst_count, 300);
+    assert_eq!(machine.state, MachineState::Running);
+
+    // A token stays cancelled, though only checked between strides, which
+    // is long enough here for the guest to finish
+    let exit = machine.run_with(YieldEvery::default(), &cancel, |_| unreachable!());
+    assert_eq!(exit.unwrap(), RunExit::Cancelled);
+    assert_eq!(machine.hart.get_reg(Reg::T0), 0);
+    let exit = machine.run_with(
+        YieldEvery::default(),
+        &CancelToken::new(),
+        |_| unreachable!(),
+    );
+    assert_eq!(exit.unwrap(), RunExit::Halted);
+}
+
+/// Poll `future` until it's ready, returning how many polls that took.
+fn polls<F: Future<Output = Result<(), E>>, E: std::fmt::Debug>(future: F) -> usize {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    for n in 1.. {
+        if let Poll::Ready(res) = future.as_mut().poll(&mut cx) {
+            res.unwrap();
+            return n;
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn futures_yield_every_n_instructions() {
+    let mut machine = machine();
+    // 500, 1000, 1500 and 2000, then the rest
+    assert_eq!(polls(machine.run_async(500)), 5);
+    assert_eq!(machine.state, MachineState::Halted);
+}
+
+#[test]
+fn dropped_futures_leave_the_machine_running() {
+    let mut machine = machine();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(pin!(machine.run_async(100)).poll(&mut cx).is_pending());
+    assert_eq!(machine.hart.inst_count, 100);
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::T0), 0);
+}
+
+#[test]
+fn futures_yield_at_spin_loops() {
+    const PAUSE: u32 = 0x0100_000f;
+    let mut machine = common::machine(&[PAUSE, PAUSE, common::EBREAK]);
+    // After each pause, then the ebreak
+    assert_eq!(polls(machine.run_async(100)), 3);
+}
+
+/// Has I/O in flight until `ready`.
+struct Waiting {
+    ready: bool,
+}
+
+impl Kernel for Waiting {
+    type Error = Infallible;
+
+    fn syscall(
+        &mut self,
+        _hart: &mut Hart32,
+        _mem: &mut Memory,
+    ) -> Result<StepResult, MachineError<Infallible>> {
+        Ok(StepResult::Ok)
+    }
+
+    fn poll_io(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[test]
+fn futures_wait_for_kernel_io() {
+    let machine = Machine::new(Waiting { ready: false });
+    let mut machine = common::load(machine, &COUNTDOWN);
+    let mut cx = Context::from_waker(Waker::noop());
+    for _ in 0..3 {
+        assert!(pin!(machine.run_async(100)).poll(&mut cx).is_pending());
+    }
+    assert_eq!(machine.hart.inst_count, 0);
+
+    machine.kernel.ready = true;
+    assert_eq!(polls(machine.run_async(1000)), 3);
+}
@@ -0,0 +1,70 @@
+//! Spin-loop hints: `pause` and the Zawrs `wrs` instructions end a
+//! [`Machine::run_async`] slice early, so spinning guests give up the thread.
+
+mod common;
+
+use std::{
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+
+use common::{NopKernel, EBREAK};
+use riscv_vm::{
+    error::{HartError, MachineError},
+    machine::Machine,
+    riscv_inst::{Extension, Extensions, Reg},
+};
+
+const PAUSE: u32 = 0x0100_000f; // pause
+const J_BACK: u32 = 0xffdf_f06f; // j -4
+const LR_W: u32 = 0x1002_a52f; // lr.w a0, (t0)
+const WRS_NTO: u32 = 0x00d0_0073; // wrs.nto
+const WRS_STO: u32 = 0x01d0_0073; // wrs.sto
+
+const WORD: u32 = 0x8000;
+
+fn machine(program: &[u32]) -> Machine<NopKernel> {
+    let mut machine = common::machine(program);
+    machine.hart.extensions = Extensions::RV32IMASC.with(Extension::Zawrs);
+    machine.hart.set_reg(Reg::T0, WORD);
+    machine
+}
+
+/// Poll a run of up to 1000 instructions once, returning whether it finished.
+fn poll_slice(machine: &mut Machine<NopKernel>) -> bool {
+    let mut cx = Context::from_waker(Waker::noop());
+    let run = pin!(machine.run_async(1000));
+    match run.poll(&mut cx) {
+        Poll::Ready(res) => {
+            res.unwrap();
+            true
+        }
+        Poll::Pending => false,
+    }
+}
+
+#[test]
+fn pause_yields_each_time_round_a_spin_loop() {
+    let mut machine = machine(&[PAUSE, J_BACK]);
+    assert!(!poll_slice(&mut machine));
+    assert_eq!(machine.hart.inst_count, 1);
+    assert!(!poll_slice(&mut machine));
+    assert_eq!(machine.hart.inst_count, 3);
+}
+
+#[test]
+fn wrs_yields_only_holding_a_reservation() {
+    let mut without = machine(&[WRS_NTO]);
+    without.hart.extensions = Extensions::RV32IMASC;
+    assert!(matches!(
+        without.step(),
+        Err(MachineError::Hart(HartError::IllegalInst { .. }))
+    ));
+
+    let mut machine = machine(&[WRS_NTO, LR_W, WRS_STO, EBREAK]);
+    // With nothing reserved there's nothing to wait for
+    assert!(!poll_slice(&mut machine));
+    assert_eq!(machine.hart.inst_count, 3);
+    assert!(poll_slice(&mut machine));
+}
@@ -0,0 +1,265 @@
+//! Streaming an instruction trace to compressed, indexed files and reading
+//! it back.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::{CODE, EBREAK};
+use riscv_vm::{
+    riscv_inst::{Extension, Extensions, FReg, Reg},
+    symbols::SymbolTable,
+    trace::{trace_file_path, TraceConfig, TraceReader, TraceRecord, TraceWriter},
+    trace_query::{first_write, hot_pcs, reg_history, sequences, CallTree},
+};
+
+const LI_T0_100: u32 = 0x0640_0293; // li t0, 100
+const ADDI_T0_M1: u32 = 0xfff2_8293; // addi t0, t0, -1
+const SW_T0: u32 = 0x7050_2023; // sw t0, 0x700(zero)
+const BNEZ_T0: u32 = 0xfe02_9ce3; // bnez t0, -8
+const COUNTDOWN: [u32; 5] = [LI_T0_100, ADDI_T0_M1, SW_T0, BNEZ_T0, EBREAK];
+
+const JAL_RA_F0: u32 = 0x0100_00ef; // jal ra, f (from CODE)
+const JAL_RA_F4: u32 = 0x00c0_00ef; // jal ra, f (from CODE + 4)
+const SW_A1: u32 = 0x70b0_2023; // sw a1, 0x700(zero)
+const ADDI_A1_1: u32 = 0x0015_8593; // f: addi a1, a1, 1
+const RET: u32 = 0x0000_8067;
+/// Calls `f` twice, then stores what it counted
+const CALLS: [u32; 6] = [JAL_RA_F0, JAL_RA_F4, SW_A1, EBREAK, ADDI_A1_1, RET];
+const F: u32 = CODE + 16;
+
+const COUNTER: u32 = 0x700;
+/// `li`, then 100 iterations of the loop
+const LOOP_RECORDS: u64 = 1 + 100 * 3;
+
+fn trace_path(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("inst-trace");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Runs `program`, tracing it to `path`.
+fn run_program(path: &PathBuf, program: &[u32], config: TraceConfig) -> u64 {
+    let mut machine = common::machine(program);
+    machine.hart.inst_trace = Some(TraceWriter::create(path, config).unwrap());
+    machine.run().unwrap();
+    let mut trace = machine.hart.inst_trace.take().unwrap();
+    trace.finish().unwrap();
+    trace.records()
+}
+
+/// Counts `t0` down from 100, storing it each time, and traces it to `path`.
+fn run_traced(path: &PathBuf, config: TraceConfig) -> u64 {
+    run_program(path, &COUNTDOWN, config)
+}
+
+fn small_chunks() -> TraceConfig {
+    TraceConfig {
+        chunk_records: 16,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn records_round_trip() {
+    let path = trace_path("round-trip.trace");
+    let written = run_traced(&path, small_chunks());
+    assert!(written >= LOOP_RECORDS);
+
+    let reader = TraceReader::open(&path).unwrap();
+    assert_eq!(reader.len(), written);
+    assert_eq!(reader.chunks().len() as u64, written.div_ceil(16));
+    let records: Vec<_> = reader.records().collect::<Result<_, _>>().unwrap();
+    assert_eq!(records.len() as u64, written);
+
+    assert_eq!(
+        records[0],
+        (
+            0,
+            TraceRecord {
+                pc: CODE,
+                inst: LI_T0_100,
+                rd: Some((Reg::T0, 100)),
+                frd: None,
+                store: None,
+            }
+        )
+    );
+    assert_eq!(records[1].1.rd, Some((Reg::T0, 99)));
+    assert_eq!(records[2].1.store, Some((COUNTER, 4)));
+    assert_eq!(
+        records[3].1,
+        TraceRecord {
+            pc: CODE + 12,
+            inst: BNEZ_T0,
+            rd: None,
+            frd: None,
+            store: None,
+        }
+    );
+    for (i, (n, _)) in records.iter().enumerate() {
+        assert_eq!(*n, i as u64);
+    }
+}
+
+#[test]
+fn index_finds_chunks_by_pc() {
+    let path = trace_path("index.trace");
+    run_traced(&path, small_chunks());
+    let reader = TraceReader::open(&path).unwrap();
+
+    // Only the first chunk holds the `li` before the loop.
+    let chunks: Vec<_> = reader
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.may_contain(CODE))
+        .collect();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].first, 0);
+
+    let tail: Vec<_> = reader
+        .records_from(LOOP_RECORDS - 1)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(tail[0].0, LOOP_RECORDS - 1);
+    assert_eq!(tail[0].1.pc, CODE + 12);
+}
+
+#[test]
+fn rotates_files_by_size() {
+    let path = trace_path("rotate.trace");
+    let config = TraceConfig {
+        max_file_bytes: Some(64),
+        ..small_chunks()
+    };
+    let written = run_traced(&path, config);
+
+    let reader = TraceReader::open(&path).unwrap();
+    assert_eq!(reader.len(), written);
+    let files: Vec<_> = reader.chunks().iter().map(|chunk| chunk.file).collect();
+    assert!(files.last().unwrap() > &0, "{files:?}");
+    assert!(trace_file_path(&path, 1).exists());
+    assert_eq!(reader.records().count() as u64, written);
+}
+
+#[test]
+fn reads_trace_without_index() {
+    let path = trace_path("unfinished.trace");
+    run_traced(&path, small_chunks());
+    let indexed = TraceReader::open(&path).unwrap();
+
+    // A trace cut off before the writer finished has no index footer.
+    let bytes = std::fs::read(&path).unwrap();
+    let last = indexed.chunks().last().unwrap();
+    let end = (last.offset + last.compressed_bytes as u64) as usize;
+    std::fs::write(&path, &bytes[..end]).unwrap();
+
+    let reader = TraceReader::open(&path).unwrap();
+    assert_eq!(reader.chunks(), indexed.chunks());
+    assert_eq!(reader.len(), indexed.len());
+}
+
+#[test]
+fn queries_answer_from_the_trace() {
+    let path = trace_path("queries.trace");
+    run_program(&path, &CALLS, small_chunks());
+    let reader = TraceReader::open(&path).unwrap();
+
+    let hot = hot_pcs(&reader).unwrap();
+    assert_eq!(hot[..2], [(F, 2), (F + 4, 2)]);
+
+    let a1: Vec<_> = reg_history(&reader, Reg::A1)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(a1, [(1, F, 1), (4, F, 2)]);
+
+    let (n, record) = first_write(&reader, COUNTER + 2).unwrap().unwrap();
+    assert_eq!((n, record.pc), (6, CODE + 8));
+    assert!(first_write(&reader, COUNTER + 4).unwrap().is_none());
+}
+
+#[test]
+fn call_tree_merges_calls_by_path() {
+    let path = trace_path("calls.trace");
+    let written = run_program(&path, &CALLS, small_chunks());
+    let tree = CallTree::build(&TraceReader::open(&path).unwrap()).unwrap();
+
+    let root = tree.root().unwrap();
+    assert_eq!((root.callee, root.instructions), (CODE, written));
+    assert_eq!(root.children.len(), 1);
+    let f = tree.node(root.children[0]);
+    assert_eq!((f.callee, f.calls, f.instructions), (F, 2, 4));
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert("f", F, 8);
+    let mut report = vec![];
+    tree.write_report(&mut report, &symbols, 4).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.lines().last().unwrap().ends_with("   f"), "{report}");
+}
+
+#[test]
+fn sequences_stay_within_blocks() {
+    let path = trace_path("sequences.trace");
+    run_traced(&path, small_chunks());
+    let reader = TraceReader::open(&path).unwrap();
+
+    let pairs = sequences(&reader, 2).unwrap();
+    assert_eq!(
+        pairs[..2],
+        [(vec!["addi", "sw"], 100), (vec!["sw", "bne"], 100)]
+    );
+    // Nothing pairs `bne` with the `addi` it branches back to
+    assert!(!pairs.iter().any(|(run, _)| run[..] == ["bne", "addi"]));
+
+    let triples = sequences(&reader, 3).unwrap();
+    assert_eq!(triples[0], (vec!["addi", "sw", "bne"], 100));
+}
+
+#[test]
+fn compressed_addis_record_their_writes() {
+    let path = trace_path("compressed.trace");
+    let program = [
+        0x0028_717d, // c.addi16sp sp, -16; c.addi4spn a0, sp, 8
+        0x0001_0505, // c.addi a0, 1; c.nop
+        EBREAK,
+    ];
+    run_program(&path, &program, small_chunks());
+    let reader = TraceReader::open(&path).unwrap();
+
+    let sp: Vec<_> = reg_history(&reader, Reg::Sp)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(sp, [(0, CODE, -16i32 as u32)]);
+    let a0: Vec<_> = reg_history(&reader, Reg::A0)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        a0,
+        [(1, CODE + 2, -8i32 as u32), (2, CODE + 4, -7i32 as u32)]
+    );
+}
+
+#[test]
+fn float_writes_are_recorded() {
+    let path = trace_path("float.trace");
+    let program = [
+        0x7000_3587, // fld fa1, 0x700(zero)
+        0xf005_0553, // fmv.w.x fa0, a0
+        EBREAK,
+    ];
+    let mut machine = common::machine(&program);
+    machine.hart.extensions = Extensions::RV32IMASC.with(Extension::F).with(Extension::D);
+    machine.mem.store::<u64>(COUNTER, 0x0123_4567_89ab_cdef);
+    machine.hart.set_reg(Reg::A0, 0x3f80_0000);
+    machine.hart.inst_trace = Some(TraceWriter::create(&path, small_chunks()).unwrap());
+    machine.run().unwrap();
+    machine.hart.inst_trace.take().unwrap().finish().unwrap();
+
+    let reader = TraceReader::open(&path).unwrap();
+    let records: Vec<_> = reader.records().map(|r| r.unwrap().1).collect();
+    assert_eq!(records[0].frd, Some((FReg::FA1, 0x0123_4567_89ab_cdef)));
+    // NaN-boxed, and no integer register written
+    assert_eq!(records[1].frd, Some((FReg::FA0, 0xffff_ffff_3f80_0000)));
+    assert_eq!(records[1].rd, None);
+}
@@ -0,0 +1,74 @@
+//! The 16550 UART, driven by a guest through the MMIO bus and register by
+//! register from the host.
+
+mod common;
+
+use common::EBREAK;
+use riscv_vm::{
+    mmio::MmioDevice,
+    riscv_inst::Reg,
+    uart::{Uart16550, UART_BASE},
+};
+
+const LCR_DLAB: u32 = 0x80;
+const MCR_LOOPBACK: u32 = 0x10;
+
+/// Waits for a byte and sends back the next one up.
+const ECHO: [u32; 8] = [
+    0x1000_02b7, // lui t0, 0x10000
+    0x0052_c303, // loop: lbu t1, 5(t0)
+    0x0013_7313, // andi t1, t1, 1
+    0xfe03_0ce3, // beqz t1, loop
+    0x0002_c503, // lbu a0, 0(t0)
+    0x0015_0513, // addi a0, a0, 1
+    0x00a2_8023, // sb a0, 0(t0)
+    EBREAK,
+];
+
+#[test]
+fn guest_receives_and_transmits() {
+    let (uart, host) = Uart16550::channel();
+    let mut machine = common::machine(&ECHO);
+    machine.mem.mmio.attach(UART_BASE, Box::new(uart)).unwrap();
+
+    // The guest spins until there's something to read
+    for _ in 0..20 {
+        machine.step().unwrap();
+    }
+    assert!(host.from_guest.try_recv().is_err());
+
+    host.to_guest.send(b'a').unwrap();
+    machine.run().unwrap();
+    assert_eq!(machine.hart.get_reg(Reg::A0), b'b' as u32);
+    assert_eq!(host.from_guest.try_recv(), Ok(b'b'));
+}
+
+#[test]
+fn registers_read_back() {
+    let (mut uart, host) = Uart16550::channel();
+    // Transmitter always empty, nothing received
+    assert_eq!(uart.read(5, 1), 0x60);
+
+    // The divisor latch shadows the data and interrupt enable registers
+    uart.write(3, 1, LCR_DLAB | 3);
+    uart.write(0, 1, 0x0c);
+    uart.write(1, 1, 0x01);
+    assert_eq!((uart.read(0, 1), uart.read(1, 1)), (0x0c, 0x01));
+    uart.write(3, 1, 3);
+    assert_eq!((uart.read(3, 1), uart.read(1, 1)), (3, 0));
+
+    uart.write(7, 1, 0x5a);
+    assert_eq!(uart.read(7, 1), 0x5a);
+
+    // Enabling the transmitter-empty interrupt raises it until it's read
+    uart.write(1, 1, 0x02);
+    assert_eq!(uart.read(2, 1), 0x02);
+    assert_eq!(uart.read(2, 1), 0x01);
+
+    // In loopback, transmitted bytes come straight back
+    uart.write(4, 1, MCR_LOOPBACK);
+    uart.write(0, 1, b'x' as u32);
+    assert_eq!(uart.read(5, 1) & 1, 1);
+    assert_eq!(uart.read(0, 1), b'x' as u32);
+    assert!(host.from_guest.try_recv().is_err());
+}
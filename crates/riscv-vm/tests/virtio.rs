@@ -0,0 +1,157 @@
+//! The virtio block device, driven through its registers and a split
+//! virtqueue in guest memory like a driver would, against an image file.
+
+use std::path::PathBuf;
+
+use riscv_vm::{
+    memory::Memory,
+    mmio::MmioDevice,
+    virtio_blk::{VirtioBlk, SECTOR_SIZE},
+};
+
+const DESC: u32 = 0x10000;
+const AVAIL: u32 = 0x11000;
+const USED: u32 = 0x12000;
+const HEADER: u32 = 0x13000;
+const STATUS: u32 = 0x13100;
+const DATA: u32 = 0x20000;
+
+const QUEUE_NUM: u32 = 8;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// A fresh image of four sectors, the second full of `0xab`, removed when
+/// dropped.
+struct Image(PathBuf);
+
+impl Image {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("derisc-{name}-{}.img", std::process::id()));
+        let mut bytes = vec![0; 4 * SECTOR_SIZE as usize];
+        bytes[SECTOR_SIZE as usize..][..SECTOR_SIZE as usize].fill(0xab);
+        std::fs::write(&path, bytes).unwrap();
+        Self(path)
+    }
+
+    fn sector(&self, sector: usize) -> Vec<u8> {
+        let bytes = std::fs::read(&self.0).unwrap();
+        bytes[sector * SECTOR_SIZE as usize..][..SECTOR_SIZE as usize].to_vec()
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A driver for `blk`, with its queue set up in `mem`.
+struct Driver {
+    blk: VirtioBlk,
+    mem: Memory,
+    requests: u16,
+}
+
+impl Driver {
+    fn new(image: &Image, read_only: bool) -> Self {
+        let mut blk = VirtioBlk::open(&image.0, read_only).unwrap();
+        // ACKNOWLEDGE | DRIVER | FEATURES_OK, then the queue, then DRIVER_OK
+        blk.write(0x070, 4, 0b1011);
+        blk.write(0x038, 4, QUEUE_NUM);
+        for (reg, addr) in [(0x080, DESC), (0x090, AVAIL), (0x0a0, USED)] {
+            blk.write(reg, 4, addr);
+        }
+        blk.write(0x044, 4, 1);
+        blk.write(0x070, 4, 0b1111);
+        Self {
+            blk,
+            mem: Memory::new(),
+            requests: 0,
+        }
+    }
+
+    fn descriptor(&mut self, index: u32, addr: u32, len: u32, flags: u16) {
+        let entry = DESC + 16 * index;
+        self.mem.store::<u64>(entry, addr as u64);
+        self.mem.store::<u32>(entry + 8, len);
+        self.mem.store::<u16>(entry + 12, flags);
+        self.mem.store::<u16>(entry + 14, (index + 1) as u16);
+    }
+
+    /// Make a `kind` request for `sector` with one sector of data at
+    /// `DATA`, returning its status and the bytes the device wrote.
+    fn request(&mut self, kind: u32, sector: u64) -> (u8, u32) {
+        self.mem.store::<u32>(HEADER, kind);
+        self.mem.store::<u64>(HEADER + 8, sector);
+        self.mem.store::<u8>(STATUS, 0xff);
+        let data = if kind == VIRTIO_BLK_T_IN {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+        self.descriptor(0, HEADER, 16, VIRTQ_DESC_F_NEXT);
+        self.descriptor(1, DATA, SECTOR_SIZE as u32, data);
+        self.descriptor(2, STATUS, 1, VIRTQ_DESC_F_WRITE);
+
+        let slot = self.requests as u32 % QUEUE_NUM;
+        self.mem.store::<u16>(AVAIL + 4 + 2 * slot, 0);
+        self.requests += 1;
+        self.mem.store::<u16>(AVAIL + 2, self.requests);
+        self.blk.write(0x050, 4, 0);
+        self.blk.tick(&mut self.mem, 0);
+
+        assert_eq!(self.mem.load::<u16>(USED + 2), self.requests);
+        let elem = USED + 4 + 8 * slot;
+        assert_eq!(self.mem.load::<u32>(elem), 0);
+        // Acknowledge the used buffer notification
+        assert_eq!(self.blk.read(0x060, 4), 1);
+        self.blk.write(0x064, 4, 1);
+        (self.mem.load(STATUS), self.mem.load(elem + 4))
+    }
+}
+
+#[test]
+fn device_identifies_itself() {
+    let image = Image::new("virtio-id");
+    let mut blk = VirtioBlk::open(&image.0, false).unwrap();
+    // "virt", version 2, a block device
+    assert_eq!(blk.read(0x000, 4), 0x7472_6976);
+    assert_eq!(blk.read(0x004, 4), 2);
+    assert_eq!(blk.read(0x008, 4), 2);
+    // Capacity in sectors, then the sector size
+    assert_eq!(blk.read(0x100, 4), 4);
+    assert_eq!(blk.read(0x114, 4), SECTOR_SIZE as u32);
+}
+
+#[test]
+fn sectors_round_trip() {
+    let image = Image::new("virtio-rw");
+    let mut driver = Driver::new(&image, false);
+
+    assert_eq!(driver.request(VIRTIO_BLK_T_IN, 1), (0, 513));
+    let data = driver.mem.slice::<u8>(DATA, SECTOR_SIZE as u32).unwrap();
+    assert!(data.iter().all(|&b| b == 0xab));
+
+    // Written back to another sector, it reaches the image
+    assert_eq!(driver.request(VIRTIO_BLK_T_OUT, 2), (0, 1));
+    assert_eq!(image.sector(2), image.sector(1));
+
+    // Past the end, it fails
+    assert_eq!(driver.request(VIRTIO_BLK_T_IN, 4), (1, 1));
+}
+
+#[test]
+fn read_only_images_refuse_writes() {
+    let image = Image::new("virtio-ro");
+    let mut driver = Driver::new(&image, true);
+    // VIRTIO_BLK_F_RO
+    assert_ne!(driver.blk.read(0x010, 4) & 1 << 5, 0);
+
+    assert_eq!(driver.request(VIRTIO_BLK_T_IN, 1), (0, 513));
+    assert_eq!(driver.request(VIRTIO_BLK_T_OUT, 0), (1, 1));
+    assert!(image.sector(0).iter().all(|&b| b == 0));
+}
@@ -0,0 +1,88 @@
+//! Stopping at memory accesses, by size and value.
+
+mod common;
+
+use common::{NopKernel, CODE};
+use riscv_vm::{
+    error::MemoryAccess,
+    machine::{Machine, MachineState},
+    riscv_inst::Reg,
+    watch::Watchpoint,
+};
+
+const PROGRAM: [u32; 5] = [
+    0x0005_a023, // sw zero, 0(a1)
+    0x00c5_8023, // sb a2, 0(a1)
+    0x0005_a683, // lw a3, 0(a1)
+    0x00c5_a023, // sw a2, 0(a1)
+    0x0010_0073, // ebreak
+];
+const DATA: u32 = 0x8000;
+const VALUE: u32 = 0x1234_5600;
+
+fn machine() -> Machine<NopKernel> {
+    let mut machine = common::machine(&PROGRAM);
+    machine.hart.set_reg(Reg::A1, DATA);
+    machine.hart.set_reg(Reg::A2, VALUE);
+    machine
+}
+
+/// Run to the next stop, returning the pc of the access that stopped it,
+/// if it was a watchpoint.
+fn run(machine: &mut Machine<NopKernel>) -> Option<u32> {
+    machine.resume();
+    machine.run().unwrap();
+    machine.watch_hit().map(|hit| hit.pc)
+}
+
+#[test]
+fn stops_after_matching_stores() {
+    let mut machine = machine();
+    let zero_word = Watchpoint::store(DATA, 4).with_size(4).with_value(0);
+    machine.watch(zero_word.clone());
+
+    assert_eq!(run(&mut machine), Some(CODE));
+    assert_eq!(machine.state, MachineState::Breakpoint);
+    // The store happened, and the hart is past it
+    assert_eq!(machine.hart.pc, CODE + 4);
+    assert_eq!(machine.hart.inst_count, 1);
+    let hit = machine.watch_hit().unwrap();
+    assert_eq!(hit.watchpoint, zero_word);
+    assert_eq!(
+        (hit.access, hit.addr, hit.size, hit.value),
+        (MemoryAccess::Store, DATA, 4, 0)
+    );
+
+    // Neither the byte store of 0 nor the word store of `VALUE` match
+    assert_eq!(run(&mut machine), None);
+    assert_eq!(machine.state, MachineState::Halted);
+    assert_eq!(machine.mem.load::<u32>(DATA), VALUE);
+}
+
+#[test]
+fn partial_overlaps_and_loads_match() {
+    let mut machine = machine();
+    machine.watch(Watchpoint::store(DATA + 2, 1));
+    machine.watch(Watchpoint::load(DATA, 1));
+
+    // Word stores cover the watched byte, the byte store doesn't
+    assert_eq!(run(&mut machine), Some(CODE));
+    assert_eq!(run(&mut machine), Some(CODE + 8));
+    assert_eq!(machine.watch_hit().unwrap().access, MemoryAccess::Load);
+    assert_eq!(run(&mut machine), Some(CODE + 12));
+    assert_eq!(machine.watch_hit().unwrap().value, VALUE);
+    assert_eq!(run(&mut machine), None);
+}
+
+#[test]
+fn removed_watchpoints_dont_stop() {
+    let mut machine = machine();
+    let any = Watchpoint::access(DATA, 4);
+    machine.watch(any.clone());
+    assert!(machine.remove_watchpoint(&any));
+    assert!(!machine.remove_watchpoint(&any));
+    assert!(machine.watchpoints().is_empty());
+
+    assert_eq!(run(&mut machine), None);
+    assert_eq!(machine.hart.inst_count, 4);
+}
@@ -21,6 +21,8 @@ fn main() {
         match test_name {
             s if s.starts_with("rv32ua-p") => {}
             s if s.starts_with("rv32uc-p") => {}
+            s if s.starts_with("rv32ud-p") => {}
+            s if s.starts_with("rv32uf-p") => {}
             s if s.starts_with("rv32ui-p") => {}
             s if s.starts_with("rv32um-p") => {}
             _ => continue,
@@ -31,7 +33,10 @@ fn main() {
     let output = syn::parse_quote! {
         #![cfg(test)]
         use riscv_kernel_linux::MockLinux;
-        use riscv_vm::machine::Machine;
+        use riscv_vm::{
+            machine::{Machine, MachineConfig},
+            riscv_inst::{Extension, Extensions},
+        };
 
         #(#tests)*
     };
@@ -57,8 +62,18 @@ fn generate_test_for_artifact(artifact: &Path) -> TokenStream {
         fn #test_name() {
             let program = include_bytes!(#program_file);
 
-            let mut machine = Machine::new(MockLinux::default());
-            machine.kernel.load_static_elf(&mut machine.hart, &mut machine.mem, program, &[], &[]);
+            // F and D on top of the default extensions, for rv32uf and rv32ud
+            let config = MachineConfig {
+                extensions: Extensions::RV32IMASC
+                    .with(Extension::F)
+                    .with(Extension::D),
+                ..Default::default()
+            };
+            let mut machine = Machine::with_config(MockLinux::default(), config);
+            machine
+                .kernel
+                .load_static_elf(&mut machine.hart, &mut machine.mem, program, &[], &[])
+                .expect("Failed to load ELF");
             let res = machine.run();
             assert!(res.is_ok(), "Test failed: {}", res.unwrap_err());
             assert_eq!(
@@ -0,0 +1,39 @@
+//! Report which extensions the machine passes the `riscv-tests` suite for,
+//! as CSV on stdout.
+//!
+//! Usage: `conformance [--tests] [ARTIFACTS_DIR]`, where the artifacts
+//! default to those `riscv/test-env` builds and `--tests` lists every test
+//! instead of a summary per extension.
+
+use std::path::{Path, PathBuf};
+
+use riscv_tests::conformance::ConformanceReport;
+
+const WORKSPACE_ROOT: &str = env!("CARGO_WORKSPACE_DIR");
+
+fn main() -> std::io::Result<()> {
+    let mut per_test = false;
+    let mut dir = Path::new(WORKSPACE_ROOT).join("riscv/test-env/artifacts");
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--tests" => per_test = true,
+            _ => dir = PathBuf::from(arg),
+        }
+    }
+
+    let report = ConformanceReport::run_dir(&dir)?;
+    if report.results().is_empty() {
+        eprintln!(
+            "No riscv-tests artifacts in {}; build them with riscv/test-env",
+            dir.display()
+        );
+        std::process::exit(1);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    if per_test {
+        report.write_tests_csv(&mut stdout)
+    } else {
+        report.write_csv(&mut stdout)
+    }
+}
@@ -0,0 +1,253 @@
+//! Which parts of the ISA the machine implements, from running the
+//! `riscv-tests` suite built by `riscv/test-env`.
+
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use riscv_kernel_linux::MockLinux;
+use riscv_vm::{
+    error::{HartError, MachineError},
+    machine::{Machine, MachineConfig},
+    quota::Quotas,
+    riscv_inst::{Extension, Extensions},
+};
+
+/// Instructions a test may run before it counts as hung. The longest tests
+/// run well under a million.
+const INSTRUCTION_LIMIT: u64 = 10_000_000;
+
+/// How one test went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    /// The test ran, but got a wrong result or never finished
+    Fail(String),
+    /// The test used an instruction the machine doesn't decode or implement
+    NotImplemented(String),
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail(_) => "fail",
+            Self::NotImplemented(_) => "not-implemented",
+        }
+    }
+
+    /// Why the test didn't pass, if it didn't.
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::Pass => "",
+            Self::Fail(detail) | Self::NotImplemented(detail) => detail,
+        }
+    }
+}
+
+/// The result of one test, e.g. `rv32um-p-mulh`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    /// Test suite, e.g. `rv32um`
+    pub suite: String,
+    /// Test name within the suite, e.g. `mulh`
+    pub test: String,
+    pub outcome: Outcome,
+}
+
+impl TestResult {
+    pub fn extension(&self) -> &'static str {
+        extension(&self.suite)
+    }
+}
+
+/// Outcomes of an extension's tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtensionSummary {
+    pub extension: &'static str,
+    pub passed: usize,
+    pub failed: usize,
+    pub not_implemented: usize,
+}
+
+/// The extension `suite` tests, e.g. `M` for `rv32um`. Suites for the
+/// privileged architecture are named after the mode they test.
+pub fn extension(suite: &str) -> &'static str {
+    match suite.strip_prefix("rv32") {
+        Some("ui") => "I",
+        Some("um") => "M",
+        Some("ua") => "A",
+        Some("uf") => "F",
+        Some("ud") => "D",
+        Some("uc") => "C",
+        Some("uzfh") => "Zfh",
+        Some("uzba") => "Zba",
+        Some("uzbb") => "Zbb",
+        Some("uzbc") => "Zbc",
+        Some("uzbs") => "Zbs",
+        Some("mi") => "machine",
+        Some("si") => "supervisor",
+        _ => "other",
+    }
+}
+
+/// Run a test program to completion and judge it by its exit code, which
+/// the test environment sets to 0 on success. F and D are enabled on top of
+/// the default extensions so the rv32uf and rv32ud suites run.
+pub fn run_test(elf: &[u8]) -> Outcome {
+    let config = MachineConfig {
+        extensions: Extensions::RV32IMASC.with(Extension::F).with(Extension::D),
+        quotas: Quotas {
+            instructions: Some(INSTRUCTION_LIMIT),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut machine = Machine::with_config(MockLinux::default(), config);
+    if let Err(e) =
+        machine
+            .kernel
+            .load_static_elf(&mut machine.hart, &mut machine.mem, elf, &[], &[])
+    {
+        return Outcome::Fail(format!("Failed to load: {e}"));
+    }
+
+    match machine.run() {
+        Ok(()) => match machine.kernel.exit_code() {
+            Some(0) => Outcome::Pass,
+            Some(code) => Outcome::Fail(format!("Exited with code {code}")),
+            None => Outcome::Fail("Halted without exiting".to_string()),
+        },
+        Err(
+            e @ MachineError::Hart(
+                HartError::InvalidInst { .. } | HartError::UnimplementedInst { .. },
+            ),
+        ) => Outcome::NotImplemented(e.to_string()),
+        Err(e) => Outcome::Fail(e.to_string()),
+    }
+}
+
+/// Results of running the suite, in suite and test order.
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceReport {
+    results: Vec<TestResult>,
+}
+
+impl ConformanceReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run every RV32 test in the `riscv-tests` artifacts directory `dir`
+    /// for the physical memory environment (`p`), which is the one the
+    /// machine's test kernel boots.
+    pub fn run_dir(dir: &Path) -> std::io::Result<Self> {
+        let mut report = Self::new();
+        for entry in dir.read_dir()? {
+            let path = entry?.path();
+            if !path.is_file() || path.extension().is_some_and(|ext| ext == "dump") {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if parse_name(name).is_some() {
+                let elf = std::fs::read(&path)?;
+                report.run(name, &elf);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Run the test `elf`, named like `rv32ui-p-add`. Returns `false`, and
+    /// doesn't run it, if the name isn't one of an RV32 `p` test.
+    pub fn run(&mut self, name: &str, elf: &[u8]) -> bool {
+        let Some((suite, test)) = parse_name(name) else {
+            return false;
+        };
+        let result = TestResult {
+            suite: suite.to_string(),
+            test: test.to_string(),
+            outcome: run_test(elf),
+        };
+        let at = self
+            .results
+            .partition_point(|r| (&r.suite, &r.test) < (&result.suite, &result.test));
+        self.results.insert(at, result);
+
+        true
+    }
+
+    pub fn results(&self) -> &[TestResult] {
+        &self.results
+    }
+
+    /// Outcomes by extension, in the order extensions are first tested.
+    pub fn by_extension(&self) -> Vec<ExtensionSummary> {
+        let mut order = Vec::new();
+        let mut summaries = BTreeMap::<&'static str, ExtensionSummary>::new();
+        for result in &self.results {
+            let extension = result.extension();
+            let summary = summaries.entry(extension).or_insert_with(|| {
+                order.push(extension);
+                ExtensionSummary {
+                    extension,
+                    ..Default::default()
+                }
+            });
+            match result.outcome {
+                Outcome::Pass => summary.passed += 1,
+                Outcome::Fail(_) => summary.failed += 1,
+                Outcome::NotImplemented(_) => summary.not_implemented += 1,
+            }
+        }
+
+        order.into_iter().map(|ext| summaries[ext]).collect()
+    }
+
+    /// Write the outcomes by extension as CSV:
+    /// `extension,passed,failed,not_implemented`.
+    pub fn write_csv(&self, w: &mut impl Write) -> std::io::Result<()> {
+        writeln!(w, "extension,passed,failed,not_implemented")?;
+        for s in self.by_extension() {
+            writeln!(
+                w,
+                "{},{},{},{}",
+                s.extension, s.passed, s.failed, s.not_implemented
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write every test's outcome as CSV: `extension,suite,test,outcome,detail`.
+    pub fn write_tests_csv(&self, w: &mut impl Write) -> std::io::Result<()> {
+        writeln!(w, "extension,suite,test,outcome,detail")?;
+        for r in &self.results {
+            writeln!(
+                w,
+                "{},{},{},{},{}",
+                r.extension(),
+                r.suite,
+                r.test,
+                r.outcome.name(),
+                csv_field(r.outcome.detail())
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The suite and test of an RV32 `p` test named like `rv32ui-p-add`.
+fn parse_name(name: &str) -> Option<(&str, &str)> {
+    let (suite, test) = name.split_once("-p-")?;
+    (suite.starts_with("rv32") && !test.is_empty()).then_some((suite, test))
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
@@ -1 +1,2 @@
+pub mod conformance;
 mod isa_tests;
@@ -0,0 +1,88 @@
+//! Summarizing `riscv-tests` outcomes by extension.
+
+use riscv_tests::conformance::{ConformanceReport, ExtensionSummary, Outcome};
+
+const LI_A7_EXIT: u32 = 0x05d0_0893; // li a7, 93
+const ECALL: u32 = 0x0000_0073;
+const UNDECODABLE: u32 = 0xffff_ffff;
+
+const BASE: u32 = 0x10000;
+const CODE: usize = 0x60;
+
+/// `li a0, n`
+fn li_a0(n: u32) -> u32 {
+    n << 20 | 10 << 7 | 0x13
+}
+
+/// A static executable at `BASE` running `code`.
+fn program(code: &[u32]) -> Vec<u8> {
+    let words = |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+    let mut elf = vec![0u8; CODE];
+    // ELF header: 32-bit little-endian ET_EXEC for RISC-V
+    elf[..7].copy_from_slice(b"\x7fELF\x01\x01\x01");
+    let entry = BASE + CODE as u32;
+    elf[16..40].copy_from_slice(&words(&[2 | 243 << 16, 1, entry, 52, 0, 0]));
+    elf[40..52].copy_from_slice(&words(&[52 | 32 << 16, 1 | 40 << 16, 0]));
+    elf.extend(words(code));
+
+    let size = elf.len() as u32;
+    elf[52..84].copy_from_slice(&words(&[1, 0, BASE, BASE, size, size, 5, 0x1000]));
+    elf
+}
+
+fn exit(code: u32) -> Vec<u8> {
+    program(&[li_a0(code), LI_A7_EXIT, ECALL])
+}
+
+#[test]
+fn outcomes_are_summarized_by_extension() {
+    let mut report = ConformanceReport::new();
+    assert!(report.run("rv32um-p-mul", &exit(0)));
+    assert!(report.run("rv32ui-p-add", &exit(0)));
+    assert!(report.run("rv32ui-p-sub", &exit(3)));
+    assert!(report.run("rv32uf-p-fadd", &program(&[UNDECODABLE])));
+    // Only RV32 tests for the physical memory environment are run
+    assert!(!report.run("rv64ui-p-add", &exit(0)));
+    assert!(!report.run("rv32ui-v-add", &exit(0)));
+
+    let tests: Vec<_> = report
+        .results()
+        .iter()
+        .map(|r| (r.suite.as_str(), r.test.as_str(), r.outcome.name()))
+        .collect();
+    assert_eq!(
+        tests,
+        [
+            ("rv32uf", "fadd", "not-implemented"),
+            ("rv32ui", "add", "pass"),
+            ("rv32ui", "sub", "fail"),
+            ("rv32um", "mul", "pass"),
+        ]
+    );
+    assert_eq!(
+        report.results()[2].outcome,
+        Outcome::Fail("Exited with code 3".to_string())
+    );
+
+    let summary = |extension, passed, failed, not_implemented| ExtensionSummary {
+        extension,
+        passed,
+        failed,
+        not_implemented,
+    };
+    assert_eq!(
+        report.by_extension(),
+        [
+            summary("F", 0, 0, 1),
+            summary("I", 1, 1, 0),
+            summary("M", 1, 0, 0)
+        ]
+    );
+
+    let mut csv = Vec::new();
+    report.write_csv(&mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "extension,passed,failed,not_implemented\nF,0,0,1\nI,1,1,0\nM,1,0,0\n"
+    );
+}
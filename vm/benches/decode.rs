@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use goblin::elf::{program_header::PF_X, Elf};
+use riscv_vm::riscv_inst::codegen::rv32imasfdc::Rv32IMASFDC;
+
+/// Every instruction in the executable segments of a guest, in program order.
+fn guest_text(name: &str) -> Vec<u32> {
+    let file = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../riscv/guest_c")
+        .join(name);
+    let bytes = std::fs::read(file).expect("Failed to read ELF file");
+    let elf = Elf::parse(&bytes).expect("Failed to parse ELF file");
+
+    let mut insts = Vec::new();
+    for ph in elf
+        .program_headers
+        .iter()
+        .filter(|ph| ph.p_flags & PF_X != 0)
+    {
+        let text = &bytes[ph.file_range()];
+        let mut i = 0;
+        while i + 2 <= text.len() {
+            let low = u16::from_le_bytes([text[i], text[i + 1]]) as u32;
+            if low & 0b11 != 0b11 {
+                insts.push(low);
+                i += 2;
+            } else if i + 4 <= text.len() {
+                let high = u16::from_le_bytes([text[i + 2], text[i + 3]]) as u32;
+                insts.push(low | high << 16);
+                i += 4;
+            } else {
+                break;
+            }
+        }
+    }
+
+    insts
+}
+
+fn decode_mix(c: &mut Criterion) {
+    let text = guest_text("main");
+    let (compressed, uncompressed): (Vec<u32>, Vec<u32>) =
+        text.iter().partition(|&&inst| inst & 0b11 != 0b11);
+
+    let mut group = c.benchmark_group("decode");
+    for (name, corpus) in [
+        ("guest_text", &text),
+        ("compressed", &compressed),
+        ("uncompressed", &uncompressed),
+    ] {
+        group.throughput(Throughput::Elements(corpus.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for &inst in corpus {
+                    black_box(Rv32IMASFDC::parse(black_box(inst)));
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(decode, decode_mix);
+criterion_main!(decode);
@@ -2,8 +2,11 @@ use std::path::Path;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::prelude::*;
-use riscv_kernel_linux::MockLinux;
-use riscv_vm::machine::Machine;
+use riscv_kernel_linux::{GuestEnv, MockLinux};
+use riscv_vm::{
+    machine::{Machine, MachineConfig},
+    memory::HugePages,
+};
 
 fn decode_setup() -> Vec<u32> {
     let seed = [0; 32];
@@ -18,12 +21,12 @@ fn decode_setup() -> Vec<u32> {
 
 fn decode_bench(c: &mut Criterion) {
     let corpus = decode_setup();
-    c.bench_function("decode_rv32imasc", |b| {
+    c.bench_function("decode_rv32imasfdc", |b| {
         b.iter(|| {
             for &inst in &corpus {
-                black_box(riscv_vm::riscv_inst::codegen::rv32imasc::Rv32IMASC::parse(
-                    black_box(inst),
-                ));
+                black_box(
+                    riscv_vm::riscv_inst::codegen::rv32imasfdc::Rv32IMASFDC::parse(black_box(inst)),
+                );
             }
         })
     });
@@ -33,17 +36,15 @@ fn roundtrip_bench(c: &mut Criterion) {
     let file = Path::new(env!("CARGO_MANIFEST_DIR"))
         .join("../riscv/roundtrip/target/riscv32imac-unknown-linux-musl/release/roundtrip");
     let elf = std::fs::read(file).expect("Failed to read ELF file");
+    let env = GuestEnv::preset("roundtrip");
 
     c.bench_function("roundtrip_e2e", |b| {
         b.iter(|| {
             let mut machine = Machine::new(MockLinux::new(false));
-            machine.kernel.load_static_elf(
-                &mut machine.hart,
-                &mut machine.mem,
-                black_box(&elf),
-                &[],
-                &[],
-            );
+            machine
+                .kernel
+                .load_elf_with_env(&mut machine.hart, &mut machine.mem, black_box(&elf), &env)
+                .expect("Failed to load ELF");
             machine.run().expect("Failed to run");
         })
     });
@@ -53,17 +54,15 @@ fn roundtrip_setup_bench(c: &mut Criterion) {
     let file = Path::new(env!("CARGO_MANIFEST_DIR"))
         .join("../riscv/roundtrip/target/riscv32imac-unknown-linux-musl/release/roundtrip");
     let elf = std::fs::read(file).expect("Failed to read ELF file");
+    let env = GuestEnv::preset("roundtrip");
 
     c.bench_function("roundtrip_load", |b| {
         b.iter(|| {
             let mut machine = Machine::new(MockLinux::new(false));
-            machine.kernel.load_static_elf(
-                &mut machine.hart,
-                &mut machine.mem,
-                black_box(&elf),
-                &[],
-                &[],
-            );
+            machine
+                .kernel
+                .load_elf_with_env(&mut machine.hart, &mut machine.mem, black_box(&elf), &env)
+                .expect("Failed to load ELF");
         })
     });
 }
@@ -72,18 +71,16 @@ fn roundtrip_exec_bench(c: &mut Criterion) {
     let file = Path::new(env!("CARGO_MANIFEST_DIR"))
         .join("../riscv/roundtrip/target/riscv32imac-unknown-linux-musl/release/roundtrip");
     let elf = std::fs::read(file).expect("Failed to read ELF file");
+    let env = GuestEnv::preset("roundtrip");
 
     c.bench_function("roundtrip_exec", |b| {
         b.iter_batched(
             || {
                 let mut machine = Machine::new(MockLinux::new(false));
-                machine.kernel.load_static_elf(
-                    &mut machine.hart,
-                    &mut machine.mem,
-                    black_box(&elf),
-                    &[],
-                    &[],
-                );
+                machine
+                    .kernel
+                    .load_elf_with_env(&mut machine.hart, &mut machine.mem, black_box(&elf), &env)
+                    .expect("Failed to load ELF");
                 machine
             },
             |mut machine| {
@@ -94,11 +91,50 @@ fn roundtrip_exec_bench(c: &mut Criterion) {
     });
 }
 
+/// Copies 16MiB a word at a time, like STREAM's copy kernel.
+const STREAM_COPY: [u32; 10] = [
+    0x1000_0537, // lui a0, 0x10000
+    0x2000_05b7, // lui a1, 0x20000
+    0x0100_0637, // lui a2, 0x1000
+    0x0005_2303, // lw t1, 0(a0)
+    0x0065_a023, // sw t1, 0(a1)
+    0x0045_0513, // addi a0, a0, 4
+    0x0045_8593, // addi a1, a1, 4
+    0xffc6_0613, // addi a2, a2, -4
+    0xfe06_16e3, // bnez a2, -20
+    0x0010_0073, // ebreak
+];
+
+fn stream_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stream_copy");
+    group.sample_size(10);
+    for huge_pages in [HugePages::Off, HugePages::Transparent, HugePages::Explicit] {
+        let config = MachineConfig {
+            huge_pages,
+            ..Default::default()
+        };
+        // Hosts without hugepages, or without a pool for explicit ones
+        if Machine::try_with_config(MockLinux::new(false), config.clone()).is_err() {
+            continue;
+        }
+        group.bench_function(huge_pages.to_string(), |b| {
+            b.iter(|| {
+                let mut machine = Machine::with_config(MockLinux::new(false), config.clone());
+                machine.mem.copy_to(0x1000, &STREAM_COPY).unwrap();
+                machine.hart.pc = 0x1000;
+                machine.run().expect("Failed to run");
+            })
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     microbenches,
     decode_bench,
     roundtrip_bench,
     roundtrip_setup_bench,
     roundtrip_exec_bench,
+    stream_bench,
 );
 criterion_main!(microbenches);
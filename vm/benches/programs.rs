@@ -1,60 +1,66 @@
-use std::path::Path;
+use std::{path::Path, time::Duration};
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use riscv_kernel_linux::MockLinux;
+use riscv_kernel_linux::{GuestEnv, MockLinux};
 use riscv_vm::machine::Machine;
 
-fn primes_setup() -> Machine<MockLinux> {
-    let file = Path::new(env!("CARGO_MANIFEST_DIR"))
-        .join("../riscv/guest_std/target/riscv32imac-unknown-linux-musl/release/guest_std");
-    let elf = std::fs::read(file).expect("Failed to read ELF file");
+fn load(path: &str) -> Machine<MockLinux> {
+    let file = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+    let elf = std::fs::read(&file).expect("Failed to read ELF file");
+
+    let name = file.file_name().unwrap().to_string_lossy();
 
     let mut machine = Machine::new(MockLinux::new(false));
     machine
         .kernel
-        .load_static_elf(&mut machine.hart, &mut machine.mem, &elf, &[], &[]);
+        .load_elf_with_env(
+            &mut machine.hart,
+            &mut machine.mem,
+            &elf,
+            &GuestEnv::preset(name),
+        )
+        .expect("Failed to load ELF");
 
     machine
 }
 
-fn primes_bench(c: &mut Criterion) {
-    let mut group = c.benchmark_group("primes");
+/// Run the program at `path` from the same freshly loaded state each
+/// iteration, timing only the run.
+fn bench_program(c: &mut Criterion, group: &str, name: &str, path: &str) {
+    let mut group = c.benchmark_group(group);
     group.sample_size(200);
-    group.bench_function("primes-guest", |b| {
-        b.iter_batched(
-            primes_setup,
-            |mut machine| {
+    let mut machine = load(path);
+    let image = machine.image().unwrap();
+    group.bench_function(name, |b| {
+        b.iter_custom(|iters| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                machine.reset_to_image(&image).expect("Failed to reset");
+                let start = std::time::Instant::now();
                 machine.run().expect("Failed to run");
-            },
-            criterion::BatchSize::LargeInput,
-        )
+                elapsed += start.elapsed();
+            }
+            elapsed
+        })
     });
 }
 
-fn dhrystone_setup() -> Machine<MockLinux> {
-    let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("../riscv/dhrystone/dhrystone");
-    let elf = std::fs::read(file).expect("Failed to read ELF file");
-
-    let mut machine = Machine::new(MockLinux::new(false));
-    machine
-        .kernel
-        .load_static_elf(&mut machine.hart, &mut machine.mem, &elf, &[], &[]);
-
-    machine
+fn primes_bench(c: &mut Criterion) {
+    bench_program(
+        c,
+        "primes",
+        "primes-guest",
+        "../riscv/guest_std/target/riscv32imac-unknown-linux-musl/release/guest_std",
+    );
 }
 
 fn dhrystone_bench(c: &mut Criterion) {
-    let mut group = c.benchmark_group("dhrystone");
-    group.sample_size(200);
-    group.bench_function("dhrystone-guest", |b| {
-        b.iter_batched(
-            dhrystone_setup,
-            |mut machine| {
-                machine.run().expect("Failed to run");
-            },
-            criterion::BatchSize::LargeInput,
-        )
-    });
+    bench_program(
+        c,
+        "dhrystone",
+        "dhrystone-guest",
+        "../riscv/dhrystone/dhrystone",
+    );
 }
 
 criterion_group!(programs, primes_bench, dhrystone_bench);
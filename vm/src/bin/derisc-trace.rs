@@ -0,0 +1,135 @@
+//! Answers questions about an instruction trace written with
+//! `riscuit --inst-trace`.
+
+use clap::{Parser, Subcommand};
+use goblin::elf::Elf;
+use riscv_kernel_linux::elf_symbols;
+use riscv_vm::{
+    riscv_inst::Reg,
+    symbols::SymbolTable,
+    trace::TraceReader,
+    trace_query::{first_write, hot_pcs, reg_history, sequences, CallTree},
+};
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Trace file, without the `.1`, `.2`, ... of the files it rotated to
+    trace: String,
+    /// The traced ELF, to name functions
+    #[clap(long)]
+    elf: Option<String>,
+    #[clap(subcommand)]
+    query: Query,
+}
+
+#[derive(Debug, Subcommand)]
+enum Query {
+    /// The most executed pcs
+    Hot {
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// The most frequent runs of instructions within a basic block, to
+    /// pick macro-op fusions
+    Sequences {
+        /// Instructions per run
+        #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=8))]
+        len: u8,
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Calls merged by call path, with the instructions each took
+    Calls {
+        #[clap(long, default_value_t = 8)]
+        depth: usize,
+    },
+    /// Every value written to a register, e.g. `a0` or `x10`
+    Reg {
+        #[clap(value_parser = parse_reg)]
+        reg: Reg,
+    },
+    /// The first store to a byte of memory
+    FirstWrite {
+        #[clap(value_parser = maybe_hex)]
+        addr: u32,
+    },
+}
+
+fn maybe_hex(s: &str) -> Result<u32, std::num::ParseIntError> {
+    if s.starts_with("0x") {
+        u32::from_str_radix(s.trim_start_matches("0x"), 16)
+    } else {
+        s.parse::<u32>()
+    }
+}
+
+fn parse_reg(s: &str) -> Result<Reg, String> {
+    let number = match s.strip_prefix('x') {
+        Some(n) => n.parse().ok(),
+        None if s == "fp" => Some(Reg::S0 as u8),
+        None => (0..32).find(|&n| format!("{:?}", Reg::checked_from(n).unwrap()) == s),
+    };
+    number
+        .and_then(Reg::checked_from)
+        .ok_or_else(|| format!("unknown register {s:?}"))
+}
+
+fn main() {
+    let args = Args::parse();
+    let reader = TraceReader::open(&args.trace).expect("Failed to open trace");
+    let symbols = match &args.elf {
+        Some(path) => {
+            let bytes = std::fs::read(path).expect("Failed to read ELF");
+            elf_symbols(&Elf::parse(&bytes).expect("Failed to parse ELF"))
+        }
+        None => SymbolTable::new(),
+    };
+
+    match args.query {
+        Query::Hot { limit } => {
+            let hot = hot_pcs(&reader).expect("Failed to read trace");
+            let total = reader.len().max(1) as f64;
+            println!("{:>12} {:>7}  pc", "count", "");
+            for (pc, count) in hot.into_iter().take(limit) {
+                let percent = count as f64 * 100.0 / total;
+                println!("{count:>12} {percent:>6.2}%  {}", symbols.describe(pc));
+            }
+        }
+        Query::Sequences { len, limit } => {
+            let runs = sequences(&reader, len as usize).expect("Failed to read trace");
+            let total = reader.len().max(1) as f64;
+            println!("{:>12} {:>7}  instructions", "count", "");
+            for (run, count) in runs.into_iter().take(limit) {
+                let percent = count as f64 * 100.0 / total;
+                println!("{count:>12} {percent:>6.2}%  {}", run.join("; "));
+            }
+        }
+        Query::Calls { depth } => {
+            let tree = CallTree::build(&reader).expect("Failed to read trace");
+            tree.write_report(&mut std::io::stdout().lock(), &symbols, depth)
+                .expect("Failed to write call tree");
+        }
+        Query::Reg { reg } => {
+            for write in reg_history(&reader, reg) {
+                let (n, pc, value) = write.expect("Failed to read trace");
+                println!("{n:>12}  {value:#010x}  {}", symbols.describe(pc));
+            }
+        }
+        Query::FirstWrite { addr } => {
+            match first_write(&reader, addr).expect("Failed to read trace") {
+                Some((n, record)) => {
+                    let (start, len) = record.store.unwrap();
+                    println!(
+                        "{n}: {}-byte store to {start:#010x} at {}",
+                        len,
+                        symbols.describe(record.pc)
+                    );
+                }
+                None => {
+                    eprintln!("Nothing stored to {addr:#010x}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
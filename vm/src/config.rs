@@ -0,0 +1,89 @@
+//! Machine configuration files: the runner's options, keyed by their long
+//! names, in TOML or JSON (by a `.json` extension), so a setup can be checked
+//! in and shared.
+//!
+//! ```toml
+//! program = "riscv/guest_c/inputs"
+//! args = ["--verbose"]
+//! isa = "rv32imafdc_zicsr"
+//! deny = ["amoswap.w"]
+//! mount = ["/data=data:ro"]
+//! env = ["LANG=C.UTF-8"]
+//! deterministic = true
+//! uart = true
+//! clint = 10000
+//! ```
+//!
+//! Values are written as on the command line and parsed the same way, and
+//! paths are relative to the working directory. Options given on the command
+//! line override the file's, while lists like `mount` and `env` add to it.
+
+use std::collections::BTreeMap;
+
+use clap::{ArgAction, Command};
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    /// The program to run, unless one is given on the command line
+    pub program: Option<String>,
+    /// Arguments for the guest, unless some are given on the command line
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(flatten)]
+    options: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Flag(bool),
+    Number(u64),
+    Text(String),
+    List(Vec<String>),
+}
+
+impl ConfigFile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        if path.ends_with(".json") {
+            serde_json::from_str(&text).map_err(|e| format!("{path}: {e}"))
+        } else {
+            toml::from_str(&text).map_err(|e| format!("{path}: {e}"))
+        }
+    }
+
+    /// The file's options as arguments for `command`, leaving out those
+    /// `given` on the command line unless they add to a list.
+    pub fn to_args(
+        &self,
+        command: &Command,
+        given: impl Fn(&str) -> bool,
+    ) -> Result<Vec<String>, String> {
+        let mut args = Vec::new();
+        for (name, value) in &self.options {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(name) && name != "config")
+                .ok_or_else(|| format!("unknown option `{name}`"))?;
+            let option = format!("--{name}");
+            match (arg.get_action(), value) {
+                (ArgAction::Append, Value::List(values)) => {
+                    args.extend(values.iter().map(|value| format!("{option}={value}")));
+                }
+                (ArgAction::Append, Value::Text(value)) => args.push(format!("{option}={value}")),
+                _ if given(arg.get_id().as_str()) => {}
+                (ArgAction::SetTrue, Value::Flag(set)) => {
+                    if *set {
+                        args.push(option);
+                    }
+                }
+                (ArgAction::Set, Value::Text(value)) => args.push(format!("{option}={value}")),
+                (ArgAction::Set, Value::Number(value)) => args.push(format!("{option}={value}")),
+                _ => return Err(format!("`{name}` has the wrong type of value")),
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -1,21 +1,267 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::IpAddr};
 
-use clap::Parser;
-use riscv_kernel_linux::MockLinux;
+mod config;
+#[cfg(feature = "window")]
+mod window;
+
+use clap::{error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches, Parser};
+use config::ConfigFile;
+use riscv_kernel_linux::{
+    elf_symbols, ClockMode, CpuTopology, FsQuota, GuestEnv, HostCache, Identity, MockLinux,
+    StdioMode, SystemInfo, UnknownSyscallPolicy,
+};
+use riscv_kernel_sbi::{load_elf, load_linux, SbiFirmware};
 use riscv_vm::{
-    machine::{Machine, MachineState},
-    riscv_inst::Reg,
+    bpred::PredictorConfig,
+    cache::CacheConfig,
+    clint::{Clint, CLINT_BASE},
+    debugcon::{DebugConsole, DEBUG_CONSOLE_BASE},
+    error::{HartError, MachineError},
+    framebuffer::Framebuffer,
+    hart::MisalignedAtomics,
+    machine::{Kernel, Machine, MachineConfig, MachineState},
+    memory::HugePages,
+    pipeline::PipelineTrace,
+    plic::{Plic, PLIC_BASE},
+    policy::{InstPolicy, OpcodeSet},
+    protect::{ExecProtection, Poison},
+    quota::Quotas,
+    riscv_inst::{Extensions, Reg},
+    trace::{TraceConfig, TraceWriter},
+    trap::EbreakMode,
+    uart::{Uart16550, UART_BASE, UART_IRQ},
+    virtio_blk::{VirtioBlk, VIRTIO_BASE, VIRTIO_IRQ},
 };
 
 #[derive(Debug, Parser)]
 struct Args {
-    /// Path to the ELF file to run
-    elf_path: String,
+    /// Path to the ELF file to run, or the kernel `Image` with --dtb
+    #[clap(required_unless_present = "config")]
+    elf_path: Option<String>,
+    /// Read options from a TOML or JSON file, keyed by their long names, e.g.
+    /// `isa = "rv32gc"`, with `program` and `args` for the guest. Options on
+    /// the command line override the file's, and lists like --mount add to it
+    #[clap(long, value_name = "FILE")]
+    config: Option<String>,
+    /// What answers the guest's `ecall`s: `linux` (MockLinux) or `sbi`
+    /// (SBI firmware, for bare-metal and supervisor guests)
+    #[clap(long, default_value_t = KernelKind::Linux)]
+    kernel: KernelKind,
     /// Breakpoint address
     #[clap(short, value_parser = maybe_hex, num_args = 0..)]
     breakpoints: Vec<u32>,
     #[clap(short, long, default_value_t = false)]
     debug: bool,
+    /// What to log, overriding `RUST_LOG`, e.g. `warn,riscv_kernel_linux=debug`.
+    /// Syscalls are logged at debug level in `syscall` spans, and with the
+    /// `trace-instructions` feature, instructions at trace level under
+    /// `riscv_vm::inst`
+    #[clap(long, value_name = "FILTER")]
+    log: Option<String>,
+    /// Restrict the hart to an ISA subset, e.g. `rv32im_zicsr`
+    #[clap(long, default_value_t = Extensions::RV32IMASC)]
+    isa: Extensions,
+    /// Opcodes the guest may not execute, e.g. `amoswap.w,csrrw`
+    #[clap(long, value_delimiter = ',')]
+    deny: Vec<String>,
+    /// Track calls and returns, stopping if a return address was overwritten
+    #[clap(long, default_value_t = false)]
+    shadow_stack: bool,
+    /// Write a CSV table of indirect branch targets to this file on exit
+    #[clap(long)]
+    branch_trace: Option<String>,
+    /// Report indirect branches that don't match the ELF's function boundaries
+    #[clap(long, default_value_t = false, requires = "branch_trace")]
+    validate_branches: bool,
+    /// Run guest clocks on virtual time derived from instruction counts and syscall costs
+    #[clap(long, default_value_t = false)]
+    deterministic: bool,
+    /// Expose a host directory to the guest, as `GUEST_PATH=HOST_DIR[:ro]`
+    #[clap(long)]
+    mount: Vec<String>,
+    /// Cache lookups and up to N MiB of file contents from --mount
+    /// directories, which mustn't change on the host while the guest runs
+    #[clap(long, value_name = "N")]
+    host_cache: Option<u64>,
+    /// Unpack a tar or newc cpio archive as the guest's root filesystem
+    #[clap(long)]
+    initramfs: Option<String>,
+    /// Map a shared object after the program, like a manual `dlopen`. Its
+    /// constructors aren't run
+    #[clap(long = "library", value_name = "PATH")]
+    libraries: Vec<String>,
+    /// Stop the guest after N instructions and print where it was, e.g. to
+    /// find an infinite loop. Add --shadow-stack for a full backtrace.
+    #[clap(long, value_name = "N")]
+    max_instructions: Option<u64>,
+    /// Fail the guest's file writes with EDQUOT once it has written N bytes
+    /// to files
+    #[clap(long, value_name = "N")]
+    max_file_bytes: Option<u64>,
+    /// Fail the guest's file and directory creation with EDQUOT after N
+    #[clap(long, value_name = "N")]
+    max_files: Option<u64>,
+    /// Print the exit code and resource usage to stderr when the guest exits
+    #[clap(long, default_value_t = false)]
+    stats: bool,
+    /// Number of CPUs reported to the guest, which still runs on one hart
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=1024))]
+    cpus: u32,
+    /// Run the guest as `NAME[:UID[:GID]]` rather than root, in `getuid`
+    /// and `/etc/passwd`. UID defaults to 1000 and GID to UID
+    #[clap(long, value_parser = parse_user)]
+    user: Option<Identity>,
+    /// Host name reported by `uname`
+    #[clap(long)]
+    hostname: Option<String>,
+    /// Kernel release reported by `uname`, e.g. `5.15.0`
+    #[clap(long, value_name = "RELEASE")]
+    kernel_release: Option<String>,
+    /// Resolve NAME to IP in the guest, through `/etc/hosts`
+    #[clap(long, value_name = "NAME:IP", value_parser = parse_host)]
+    add_host: Vec<(String, IpAddr)>,
+    /// What unimplemented syscalls do: `enosys`, `log` (ENOSYS and a warning) or `abort`
+    #[clap(long, default_value_t = UnknownSyscallPolicy::Enosys)]
+    unknown_syscalls: UnknownSyscallPolicy,
+    /// What AMOs on misaligned addresses do: `trap`, or `emulate` as with Zam
+    #[clap(long, default_value_t = MisalignedAtomics::Trap)]
+    misaligned_atomics: MisalignedAtomics,
+    /// What `ebreak` does: `kernel` (usually exits), `debugger` (stops for
+    /// --debug) or `trap` (raises a breakpoint exception to the guest)
+    #[clap(long, default_value_t = EbreakMode::Kernel)]
+    ebreak: EbreakMode,
+    /// Print each syscall and its result to stderr
+    #[clap(long, default_value_t = false)]
+    strace: bool,
+    /// Stop at the first panic or abort and report its message and backtrace.
+    /// Add --shadow-stack for a full backtrace.
+    #[clap(long, default_value_t = false)]
+    catch_panics: bool,
+    /// Run the guest's `memcpy`, `memmove` and `memset` as host copies. Their
+    /// instructions aren't counted, profiled or traced.
+    #[clap(long, default_value_t = false)]
+    host_mem_functions: bool,
+    /// Report guest allocations that were never freed, and where they were
+    /// allocated, when the guest exits. Add --shadow-stack for full backtraces.
+    #[clap(long, default_value_t = false)]
+    leak_check: bool,
+    /// Back guest memory with host hugepages: `transparent` (madvise) or
+    /// `explicit` (MAP_HUGETLB, needs a big enough hugepage pool)
+    #[clap(long, default_value_t = HugePages::Off)]
+    huge_pages: HugePages,
+    /// Only run code from pages the ELF or `mmap` marked executable:
+    /// `regions`, or `wx` to also stop guests that ask for writable code.
+    /// Not for `--dtb` kernels
+    #[clap(long, default_value_t = ExecProtection::Off)]
+    exec_protection: ExecProtection,
+    /// Fill memory the guest unmaps with 0x6b bytes: `fill`, or `trap` to
+    /// also stop the guest when it uses the memory after
+    #[clap(long, default_value_t = Poison::Off)]
+    poison: Poison,
+    /// Write the guest's `brk`, `mmap` and `munmap` calls, with the regions
+    /// they changed and who called them, to this file as JSON on exit
+    #[clap(long, value_name = "FILE")]
+    memory_timeline: Option<String>,
+    /// Attach a `WIDTHxHEIGHT` RGBA framebuffer at 0xE0000000, shown in a
+    /// window when built with the `window` feature
+    #[clap(long, value_parser = parse_framebuffer)]
+    framebuffer: Option<Framebuffer>,
+    /// Attach a 16550 UART at 0x10000000, connected to stdin and stdout
+    #[clap(long, default_value_t = false)]
+    uart: bool,
+    /// Attach a debug console at 0x100000 for bare-metal guests: stores to
+    /// +0 print a byte, to +4 print a word in hex, to +8 exit with that code,
+    /// and to +12 fail with the message at that address
+    #[clap(long, default_value_t = false)]
+    debug_console: bool,
+    /// Attach a CLINT at 0x2000000 whose timer ticks every N instructions
+    #[clap(long, value_name = "N")]
+    clint: Option<u64>,
+    /// Attach a PLIC at 0xc000000, with the UART on source 10 and virtio-blk on source 1
+    #[clap(long, default_value_t = false)]
+    plic: bool,
+    /// Attach a virtio-blk device at 0x10001000 backed by a disk image, as
+    /// `IMAGE[:ro]`, with its interrupt on PLIC source 1
+    #[clap(long, value_name = "IMAGE")]
+    virtio_blk: Option<String>,
+    /// Simulate an instruction cache of `SIZE:WAYS:LINE`, e.g. `32k:4:64`,
+    /// and print miss rates per function to stderr on exit
+    #[clap(long, value_name = "SIZE:WAYS:LINE")]
+    icache: Option<CacheConfig>,
+    /// Simulate a data cache of `SIZE:WAYS:LINE`, like --icache
+    #[clap(long, value_name = "SIZE:WAYS:LINE")]
+    dcache: Option<CacheConfig>,
+    /// Simulate a branch predictor, `static`, `bimodal:BITS` or `gshare:BITS`,
+    /// and print the worst predicted branches to stderr on exit
+    #[clap(long, value_name = "MODEL")]
+    branch_predictor: Option<PredictorConfig>,
+    /// Count instructions, the unit of --max-instructions, by function and
+    /// print the most expensive functions to stderr on exit
+    #[clap(long, default_value_t = false)]
+    gas_profile: bool,
+    /// Write a pipeline timing trace of every retired instruction to this
+    /// file, for the Konata viewer. Add --icache, --dcache and
+    /// --branch-predictor to model stalls.
+    #[clap(long, value_name = "FILE")]
+    pipeline_trace: Option<String>,
+    /// Stream a zstd-compressed, indexed trace of every retired instruction
+    /// and its register and memory writes to this file
+    #[clap(long, value_name = "FILE")]
+    inst_trace: Option<String>,
+    /// Start a new instruction trace file (`FILE.1`, `FILE.2`, ...) once the
+    /// current one reaches this many bytes
+    #[clap(long, value_name = "BYTES", requires = "inst_trace")]
+    inst_trace_max_bytes: Option<u64>,
+    /// Boot `elf_path` as a Linux kernel `Image` with this device tree. Implies
+    /// `--kernel sbi`. Attach a CLINT for the SBI timer.
+    #[clap(long, value_name = "DTB")]
+    dtb: Option<String>,
+    /// Initrd to load for the kernel, with --dtb
+    #[clap(long, requires = "dtb")]
+    initrd: Option<String>,
+    /// Kernel command line, with --dtb
+    #[clap(long, value_name = "BOOTARGS", requires = "dtb")]
+    append: Option<String>,
+    /// The guest's `argv[0]`, instead of the ELF's file name
+    #[clap(long, value_name = "NAME")]
+    argv0: Option<String>,
+    /// Set a guest environment variable, as `NAME=VALUE`. Guests start with
+    /// PATH, LANG and TERM set unless --clean-env is given
+    #[clap(long = "env", value_name = "NAME=VALUE", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+    /// Start the guest with only the --env variables
+    #[clap(long, default_value_t = false)]
+    clean_env: bool,
+    /// Arguments for the guest, after `--`
+    #[clap(last = true)]
+    guest_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KernelKind {
+    Linux,
+    Sbi,
+}
+
+impl std::fmt::Display for KernelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Linux => "linux",
+            Self::Sbi => "sbi",
+        })
+    }
+}
+
+impl std::str::FromStr for KernelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linux" => Ok(Self::Linux),
+            "sbi" => Ok(Self::Sbi),
+            _ => Err(format!("unknown kernel {s:?}, expected linux or sbi")),
+        }
+    }
 }
 
 fn maybe_hex(s: &str) -> Result<u32, std::num::ParseIntError> {
@@ -26,29 +272,489 @@ fn maybe_hex(s: &str) -> Result<u32, std::num::ParseIntError> {
     }
 }
 
+fn parse_framebuffer(s: &str) -> Result<Framebuffer, String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or("expected WIDTHxHEIGHT, e.g. 640x480")?;
+    let width = width.parse().map_err(|e| format!("invalid width: {e}"))?;
+    let height = height.parse().map_err(|e| format!("invalid height: {e}"))?;
+    Framebuffer::new(width, height).ok_or_else(|| {
+        format!(
+            "framebuffer must be between 1x1 and {max}x{max}",
+            max = riscv_vm::framebuffer::FRAMEBUFFER_MAX_DIM
+        )
+    })
+}
+
+fn parse_user(s: &str) -> Result<Identity, String> {
+    let mut parts = s.split(':');
+    let name = parts.next().filter(|name| !name.is_empty());
+    let name = name.ok_or("expected NAME[:UID[:GID]]")?;
+    let uid = match parts.next() {
+        Some(uid) => uid.parse().map_err(|e| format!("invalid uid: {e}"))?,
+        None if name == "root" => 0,
+        None => 1000,
+    };
+    let gid = match parts.next() {
+        Some(gid) => gid.parse().map_err(|e| format!("invalid gid: {e}"))?,
+        None => uid,
+    };
+    if parts.next().is_some() {
+        return Err("expected NAME[:UID[:GID]]".to_string());
+    }
+    Ok(match (name, uid) {
+        ("root", 0) => Identity {
+            gid,
+            ..Identity::default()
+        },
+        _ => Identity::user(name, uid, gid),
+    })
+}
+
+fn parse_host(s: &str) -> Result<(String, IpAddr), String> {
+    let (name, addr) = s.split_once(':').ok_or("expected NAME:IP")?;
+    let addr = addr.parse().map_err(|e| format!("invalid address: {e}"))?;
+    Ok((name.to_string(), addr))
+}
+
+fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s.split_once('=').ok_or("expected NAME=VALUE")?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Parse the command line, merging in the --config file if there is one.
+fn parse_args() -> Args {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let Some(path) = &args.config else {
+        return args;
+    };
+    let invalid = |e: String| -> ! { Args::command().error(ErrorKind::InvalidValue, e).exit() };
+
+    let file = ConfigFile::load(path).unwrap_or_else(|e| invalid(e));
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let options = file
+        .to_args(&Args::command(), given)
+        .unwrap_or_else(|e| invalid(format!("{path}: {e}")));
+    let mut argv: Vec<_> = std::env::args_os().take(1).collect();
+    argv.extend(options.into_iter().map(Into::into));
+    if args.elf_path.is_none() {
+        let program = file.program.clone();
+        let program = program.unwrap_or_else(|| {
+            invalid(format!(
+                "{path}: no `program`, and none on the command line"
+            ))
+        });
+        argv.push(program.into());
+    }
+    argv.extend(std::env::args_os().skip(1));
+    if args.guest_args.is_empty() && !file.args.is_empty() {
+        argv.push("--".into());
+        argv.extend(file.args.into_iter().map(Into::into));
+    }
+
+    let matches = Args::command().get_matches_from(argv);
+    Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+/// The guest's `argv` and environment, from the command line.
+fn guest_env(args: &Args, filename: &str) -> GuestEnv {
+    let argv0 = args.argv0.as_deref().unwrap_or(filename);
+    let env = if args.clean_env {
+        GuestEnv::new(argv0)
+    } else {
+        GuestEnv::preset(argv0)
+    };
+    args.env
+        .iter()
+        .fold(env.args(&args.guest_args), |env, (name, value)| {
+            env.var(name, value)
+        })
+}
+
 fn main() {
+    let args = parse_args();
+    let filter = match &args.log {
+        Some(filter) => tracing_subscriber::EnvFilter::new(filter),
+        None => tracing_subscriber::EnvFilter::from_default_env(),
+    };
     tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_env_filter(filter)
         .without_time()
         .init();
+    // Either given or from the --config file
+    let elf_path = args.elf_path.as_deref().unwrap();
+    let elf = std::fs::read(elf_path).expect("Failed to read ELF file");
 
-    let args = Args::parse();
-    let elf = std::fs::read(&args.elf_path).expect("Failed to read ELF file");
+    let filename = elf_path.split('/').next_back().unwrap();
 
-    let filename = args.elf_path.split('/').last().unwrap();
+    let policy = if args.deny.is_empty() {
+        InstPolicy::AllowAll
+    } else {
+        let denied = OpcodeSet::from_mnemonics(args.deny.iter().map(String::as_str));
+        InstPolicy::Deny(denied.expect("Invalid --deny opcode"))
+    };
+    let config = MachineConfig {
+        extensions: args.isa,
+        policy,
+        misaligned_atomics: args.misaligned_atomics,
+        ebreak: args.ebreak,
+        shadow_stack: args.shadow_stack,
+        branch_trace: args.branch_trace.is_some(),
+        catch_panics: args.catch_panics,
+        framebuffer: args.framebuffer,
+        icache: args.icache,
+        dcache: args.dcache,
+        branch_predictor: args.branch_predictor,
+        gas_profile: args.gas_profile,
+        quotas: Quotas {
+            instructions: args.max_instructions,
+            ..Default::default()
+        },
+        leak_check: args.leak_check,
+        huge_pages: args.huge_pages,
+        exec_protection: args.exec_protection,
+        poison: args.poison,
+    };
+    if args.kernel == KernelKind::Sbi || args.dtb.is_some() {
+        run_sbi(&args, config, &elf, filename);
+        return;
+    }
 
-    let mut machine = Machine::new(MockLinux::new(true));
-    let elf =
-        machine
-            .kernel
-            .load_static_elf(&mut machine.hart, &mut machine.mem, &elf, &[filename], &[]);
+    let mut builder = MockLinux::builder()
+        .stdio(StdioMode::Host)
+        .strace(args.strace)
+        .memory_timeline(args.memory_timeline.is_some())
+        .unknown_syscalls(args.unknown_syscalls)
+        .topology(CpuTopology {
+            cpus: args.cpus,
+            ..Default::default()
+        });
+    let mut system = SystemInfo::default();
+    if let Some(hostname) = &args.hostname {
+        system.nodename = hostname.clone();
+    }
+    if let Some(release) = &args.kernel_release {
+        system.release = release.clone();
+    }
+    builder = builder.system(system).fs_quota(FsQuota {
+        bytes_written: args.max_file_bytes,
+        files: args.max_files,
+        ..Default::default()
+    });
+    if args.deterministic {
+        builder = builder.clock_mode(ClockMode::Deterministic);
+    }
+    if let Some(identity) = &args.user {
+        builder = builder.identity(identity.clone());
+    }
+    if !args.add_host.is_empty() {
+        builder = builder.hosts(args.add_host.iter().cloned().collect());
+    }
+    if let Some(path) = &args.initramfs {
+        let archive = std::fs::read(path).expect("Failed to read initramfs");
+        builder = builder.mount_archive(archive);
+    }
+    if let Some(mib) = args.host_cache {
+        builder = builder.host_cache(HostCache::new(mib << 20));
+    }
+    for spec in &args.mount {
+        let (guest, host) = spec
+            .split_once('=')
+            .expect("--mount expects GUEST_PATH=HOST_DIR[:ro]");
+        let (host, read_only) = match host.strip_suffix(":ro") {
+            Some(host) => (host, true),
+            None => (host, false),
+        };
+        builder = builder.mount_host(guest, host, read_only);
+    }
+    let mut kernel = builder.build().expect("Failed to set up the kernel");
+    if args.stats {
+        kernel.on_exit(|exit| {
+            let stats = exit.stats;
+            eprintln!(
+                "exit code {}: {} instructions, {} syscalls, {:.3} ms cpu, {:.3} ms system, \
+                 {:.3} ms elapsed, {} heap bytes, {} mmap bytes, {} KiB max rss, {} open files",
+                exit.code,
+                stats.inst_count,
+                stats.syscalls,
+                stats.cpu_ns as f64 / 1e6,
+                stats.system_ns as f64 / 1e6,
+                stats.elapsed_ns as f64 / 1e6,
+                stats.heap_bytes,
+                stats.mmap_bytes,
+                stats.max_rss_bytes / 1024,
+                stats.open_files,
+            );
+        });
+        if let Some(cache) = kernel.host_cache().cloned() {
+            kernel.on_exit(move |_| {
+                let stats = cache.stats();
+                eprintln!(
+                    "host cache: {} stat hits, {} misses; {} read hits, {} misses; {} invalidations",
+                    stats.stat_hits,
+                    stats.stat_misses,
+                    stats.read_hits,
+                    stats.read_misses,
+                    stats.invalidations,
+                );
+            });
+        }
+    }
+    let mut machine = Machine::with_config(kernel, config);
+    attach_devices(&mut machine, &args);
+    machine.hart.pipeline_trace = pipeline_trace(&args);
+    machine.hart.inst_trace = inst_trace(&args);
+    let elf = machine
+        .kernel
+        .load_elf_with_env(
+            &mut machine.hart,
+            &mut machine.mem,
+            &elf,
+            &guest_env(&args, filename),
+        )
+        .expect("Failed to load ELF");
+    machine.symbols = elf_symbols(&elf);
+    for path in &args.libraries {
+        let library = std::fs::read(path).expect("Failed to read library");
+        let library = machine
+            .load_library(&library)
+            .expect("Failed to load library");
+        tracing::debug!("Loaded {path} at {:#010x}", library.base);
+    }
+    if args.host_mem_functions {
+        let replaced = machine.accelerate_mem_functions();
+        tracing::debug!("Running {replaced:?} on the host");
+    }
 
     if args.debug {
         let mut debugger = Debugger::new(machine, elf, args.breakpoints);
 
         debugger.run();
     } else {
-        machine.run().expect("Failed to run");
+        let res = run(&mut machine, filename);
+        if machine.state == MachineState::Breakpoint {
+            eprintln!("Stopped at ebreak at {:#010x}", machine.hart.pc);
+        }
+        if let Some(trace) = &mut machine.hart.pipeline_trace {
+            trace.finish().expect("Failed to write pipeline trace");
+        }
+        if let Some(trace) = &mut machine.hart.inst_trace {
+            trace.finish().expect("Failed to write instruction trace");
+        }
+        if let Some(path) = &args.branch_trace {
+            write_branch_trace(&machine, path, args.validate_branches);
+        }
+        if let Some(sim) = &machine.hart.cache_sim {
+            sim.write_report(&mut std::io::stderr(), &machine.symbols)
+                .expect("Failed to write cache report");
+        }
+        if let Some(predictor) = &machine.hart.branch_predictor {
+            predictor
+                .write_report(&mut std::io::stderr(), &machine.symbols, 20)
+                .expect("Failed to write branch predictor report");
+        }
+        if let Some(profiler) = &machine.hart.gas_profiler {
+            profiler
+                .write_report(&mut std::io::stderr(), &machine.symbols, 30)
+                .expect("Failed to write gas profile");
+        }
+        if let Some(leaks) = &machine.leaks {
+            leaks
+                .write_report(&mut std::io::stderr(), &machine.mem, &machine.symbols)
+                .expect("Failed to write leak report");
+        }
+        if let (Some(path), Some(timeline)) =
+            (&args.memory_timeline, &machine.kernel.memory_timeline)
+        {
+            let mut file =
+                std::fs::File::create(path).expect("Failed to create memory timeline file");
+            timeline
+                .write_json(&mut file, &machine.symbols)
+                .expect("Failed to write memory timeline");
+        }
+        if let Err(e) = res {
+            if let MachineError::GuestPanic(panic) = &e {
+                eprintln!("{panic}");
+                // Like a Rust process that panicked
+                std::process::exit(101);
+            }
+            if let MachineError::Hart(HartError::ShadowStack(violation)) = &e {
+                if let Some(report) = machine.shadow_stack_report(violation) {
+                    eprint!("{report}");
+                }
+            }
+            if let MachineError::QuotaExceeded { backtrace, .. } = &e {
+                eprintln!("{backtrace}");
+            }
+            panic!("Failed to run: {}", machine.fault(e));
+        }
+        if let Some(code) = machine.exit_code {
+            std::process::exit(code as i32);
+        }
+    }
+}
+
+/// Attach the devices selected on the command line.
+fn attach_devices<K: Kernel>(machine: &mut Machine<K>, args: &Args) {
+    let plic = args.plic.then(Plic::new);
+    if args.uart {
+        let mut uart = Uart16550::stdio();
+        if let Some(plic) = &plic {
+            uart = uart.with_irq(plic.line(UART_IRQ).unwrap());
+        }
+        machine
+            .mem
+            .mmio
+            .attach(UART_BASE, Box::new(uart))
+            .expect("Failed to attach UART");
+    }
+    if let Some(spec) = &args.virtio_blk {
+        let (path, read_only) = match spec.strip_suffix(":ro") {
+            Some(path) => (path, true),
+            None => (spec.as_str(), false),
+        };
+        let mut blk = VirtioBlk::open(path, read_only).expect("Failed to open disk image");
+        if let Some(plic) = &plic {
+            blk = blk.with_irq(plic.line(VIRTIO_IRQ).unwrap());
+        }
+        machine
+            .mem
+            .mmio
+            .attach(VIRTIO_BASE, Box::new(blk))
+            .expect("Failed to attach virtio-blk");
+    }
+    if let Some(plic) = plic {
+        machine
+            .mem
+            .mmio
+            .attach(PLIC_BASE, Box::new(plic))
+            .expect("Failed to attach PLIC");
+    }
+    if let Some(insts_per_tick) = args.clint {
+        machine
+            .mem
+            .mmio
+            .attach(CLINT_BASE, Box::new(Clint::new(insts_per_tick)))
+            .expect("Failed to attach CLINT");
+    }
+    if args.debug_console {
+        machine
+            .mem
+            .mmio
+            .attach(DEBUG_CONSOLE_BASE, Box::new(DebugConsole::stdout()))
+            .expect("Failed to attach debug console");
+    }
+}
+
+fn pipeline_trace(args: &Args) -> Option<PipelineTrace> {
+    let path = args.pipeline_trace.as_ref()?;
+    let file = std::fs::File::create(path).expect("Failed to create pipeline trace file");
+    Some(PipelineTrace::new(std::io::BufWriter::new(file)))
+}
+
+fn inst_trace(args: &Args) -> Option<TraceWriter> {
+    let path = args.inst_trace.as_ref()?;
+    let config = TraceConfig {
+        max_file_bytes: args.inst_trace_max_bytes,
+        ..TraceConfig::default()
+    };
+    Some(TraceWriter::create(path, config).expect("Failed to create instruction trace file"))
+}
+
+/// Run under [`SbiFirmware`] rather than MockLinux: a Linux kernel `Image`
+/// if there's a device tree, otherwise a bare-metal ELF.
+fn run_sbi(args: &Args, config: MachineConfig, image: &[u8], title: &str) {
+    let mut machine = Machine::with_config(SbiFirmware::new(), config);
+    attach_devices(&mut machine, args);
+    machine.hart.pipeline_trace = pipeline_trace(args);
+    machine.hart.inst_trace = inst_trace(args);
+
+    match &args.dtb {
+        Some(dtb) => {
+            let dtb = std::fs::read(dtb).expect("Failed to read device tree");
+            let initrd = args
+                .initrd
+                .as_ref()
+                .map(|path| std::fs::read(path).expect("Failed to read initrd"));
+            let layout = load_linux(
+                &mut machine.hart,
+                &mut machine.mem,
+                image,
+                &dtb,
+                initrd.as_deref(),
+                args.append.as_deref(),
+            )
+            .expect("Failed to load kernel");
+            tracing::debug!("Boot layout: {layout:?}");
+        }
+        None => {
+            let elf =
+                load_elf(&mut machine.hart, &mut machine.mem, image).expect("Failed to load ELF");
+            machine.symbols = elf_symbols(&elf);
+        }
+    }
+
+    let res = run(&mut machine, title);
+    if machine.state == MachineState::Breakpoint {
+        eprintln!("Stopped at ebreak at {:#010x}", machine.hart.pc);
+    }
+    if let Some(trace) = &mut machine.hart.pipeline_trace {
+        trace.finish().expect("Failed to write pipeline trace");
+    }
+    if let Some(trace) = &mut machine.hart.inst_trace {
+        trace.finish().expect("Failed to write instruction trace");
+    }
+    if let Err(e) = res {
+        if let MachineError::GuestPanic(panic) = &e {
+            eprintln!("{panic}");
+            std::process::exit(101);
+        }
+        if let MachineError::QuotaExceeded { backtrace, .. } = &e {
+            eprintln!("{backtrace}");
+        }
+        panic!("Failed to run: {}", machine.fault(e));
+    }
+    if let Some(code) = machine.exit_code {
+        std::process::exit(code as i32);
+    }
+}
+
+#[cfg(feature = "window")]
+fn run<K: Kernel>(machine: &mut Machine<K>, title: &str) -> Result<(), MachineError<K::Error>> {
+    match machine.framebuffer {
+        Some(framebuffer) => window::FramebufferWindow::new(framebuffer, title).run(machine),
+        None => machine.run(),
+    }
+}
+
+#[cfg(not(feature = "window"))]
+fn run<K: Kernel>(machine: &mut Machine<K>, _title: &str) -> Result<(), MachineError<K::Error>> {
+    if machine.framebuffer.is_some() {
+        tracing::warn!("Built without the `window` feature, so the framebuffer isn't shown");
+    }
+    machine.run()
+}
+
+fn write_branch_trace(machine: &Machine<MockLinux>, path: &str, validate: bool) {
+    let Some(trace) = &machine.hart.branch_trace else {
+        return;
+    };
+    let mut file = std::fs::File::create(path).expect("Failed to create branch trace file");
+    trace
+        .write_csv(&mut file, &machine.symbols)
+        .expect("Failed to write branch trace");
+
+    if validate {
+        for edge in trace.validate(&machine.symbols) {
+            tracing::warn!(
+                "Suspicious indirect {} from {} to {} ({} times)",
+                edge.kind.name(),
+                machine.symbols.describe(edge.source),
+                machine.symbols.describe(edge.target),
+                edge.count
+            );
+        }
     }
 }
 
@@ -110,6 +816,11 @@ impl Debugger {
                 }
             }
             self.machine.step().expect("Failed to step");
+            if self.machine.state == MachineState::Breakpoint {
+                tracing::info!("ebreak hit at 0x{:08x}", self.machine.hart.pc);
+                self.machine.resume();
+                self.mode = Mode::Debugging;
+            }
 
             if self.breakpoints.contains(&self.machine.hart.pc) {
                 tracing::info!("Breakpoint hit at 0x{:08x}", self.machine.hart.pc);
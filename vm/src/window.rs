@@ -0,0 +1,76 @@
+use std::{ops::ControlFlow, time::Duration};
+
+use minifb::{Window, WindowOptions};
+use riscv_vm::{
+    error::MachineError,
+    framebuffer::Framebuffer,
+    machine::{Kernel, Machine},
+    watchdog::{CancelToken, RunExit, YieldEvery},
+};
+
+/// How often the window is redrawn while the guest runs.
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// A host window showing the guest framebuffer.
+pub struct FramebufferWindow {
+    window: Window,
+    framebuffer: Framebuffer,
+    buffer: Vec<u32>,
+}
+
+impl FramebufferWindow {
+    pub fn new(framebuffer: Framebuffer, title: &str) -> Self {
+        let window = Window::new(
+            title,
+            framebuffer.width as usize,
+            framebuffer.height as usize,
+            WindowOptions::default(),
+        )
+        .expect("Failed to open framebuffer window");
+
+        Self {
+            window,
+            framebuffer,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Draw the current frame and handle window events. Returns `false` once
+    /// the window has been closed.
+    pub fn present<K: Kernel>(&mut self, machine: &Machine<K>) -> bool {
+        self.framebuffer.to_rgb32(&machine.mem, &mut self.buffer);
+        self.window
+            .update_with_buffer(
+                &self.buffer,
+                self.framebuffer.width as usize,
+                self.framebuffer.height as usize,
+            )
+            .expect("Failed to draw framebuffer");
+        self.window.is_open()
+    }
+
+    /// Run the guest, redrawing the window as it goes. Closing the window stops
+    /// the guest; once the guest exits, the last frame stays up until the
+    /// window is closed.
+    pub fn run<K: Kernel>(
+        &mut self,
+        machine: &mut Machine<K>,
+    ) -> Result<(), MachineError<K::Error>> {
+        let every = YieldEvery::interval(FRAME_INTERVAL);
+        let exit = machine.run_with(every, &CancelToken::new(), |machine| {
+            if self.present(machine) {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        })?;
+        if exit != RunExit::Halted {
+            return Ok(());
+        }
+
+        while self.present(machine) {
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! Running the guest from a `--config` file, in TOML or JSON, with the
+//! command line overriding and adding to it.
+
+use std::{path::PathBuf, process::Command};
+
+const INPUTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../riscv/guest_c/inputs");
+
+/// A config file, removed when dropped.
+struct ConfigFile(PathBuf);
+
+impl ConfigFile {
+    fn new(name: &str, contents: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("derisc-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for ConfigFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Run the runner with `config` and `args`, returning its status and output.
+fn run(config: &ConfigFile, args: &[&str]) -> (bool, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_riscuit"))
+        .arg("--config")
+        .arg(&config.0)
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn toml_config_runs_the_guest() {
+    let config = ConfigFile::new(
+        "run.toml",
+        &format!(
+            "program = \"{INPUTS}\"\n\
+             args = [\"one\", \"two\"]\n\
+             isa = \"rv32imafdc_zicsr\"\n\
+             env = [\"FROM_FILE=1\"]\n\
+             clean-env = true\n\
+             max-instructions = 1000000\n"
+        ),
+    );
+    let (ok, stdout, _) = run(&config, &[]);
+    assert!(ok);
+    assert_eq!(
+        stdout,
+        "argv[0] = inputs\nargv[1] = one\nargv[2] = two\nenvp[0] = FROM_FILE=1\n"
+    );
+
+    // The command line replaces the program's arguments and single options,
+    // and adds to lists
+    let (ok, stdout, _) = run(
+        &config,
+        &["--env", "FROM_CLI=2", "--argv0", "x", "--", "three"],
+    );
+    assert!(ok);
+    assert_eq!(
+        stdout,
+        "argv[0] = x\nargv[1] = three\nenvp[0] = FROM_FILE=1\nenvp[1] = FROM_CLI=2\n"
+    );
+    let (ok, _, stderr) = run(&config, &["--max-instructions", "10"]);
+    assert!(!ok);
+    assert!(stderr.contains("instruction quota of 10"), "{stderr}");
+}
+
+#[test]
+fn json_config_is_checked_against_the_options() {
+    let config = ConfigFile::new("run.json", r#"{"clean-env": true, "argv0": "from-json"}"#);
+    let (ok, stdout, _) = run(&config, &[INPUTS]);
+    assert!(ok);
+    assert_eq!(stdout, "argv[0] = from-json\n");
+
+    for (contents, error) in [
+        (r#"{"bogus": 1}"#, "unknown option `bogus`"),
+        (r#"{"uart": "yes"}"#, "`uart` has the wrong type of value"),
+        (
+            r#"{"isa": "rv99"}"#,
+            "invalid value 'rv99' for '--isa <ISA>'",
+        ),
+        (r#"{"uart": true"#, "EOF while parsing"),
+    ] {
+        let config = ConfigFile::new("bad.json", contents);
+        let (ok, _, stderr) = run(&config, &[INPUTS]);
+        assert!(!ok);
+        assert!(stderr.contains(error), "{contents}: {stderr}");
+    }
+    let config = ConfigFile::new("empty.json", "{}");
+    let (ok, _, stderr) = run(&config, &[]);
+    assert!(!ok);
+    assert!(stderr.contains("no `program`"), "{stderr}");
+}